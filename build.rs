@@ -0,0 +1,40 @@
+//! Hashes the sources that decide what a program parses to, so the on-disk
+//! AST cache (src/parser/cache.rs) misses after any change to them.
+
+use std::path::Path;
+
+const PARSE_SOURCES: &[&str] = &["src/ast", "src/lexer", "src/parser"];
+
+fn main() {
+    let mut files = Vec::new();
+    for dir in PARSE_SOURCES {
+        println!("cargo:rerun-if-changed={}", dir);
+        collect(Path::new(dir), &mut files);
+    }
+    files.sort();
+
+    // FNV-1a, the same hash the cache uses for its keys.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for file in &files {
+        let bytes = std::fs::read(file).unwrap_or_default();
+        for byte in file.to_string_lossy().as_bytes().iter().chain(&bytes) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    println!("cargo:rustc-env=ZEKKEN_AST_SCHEMA={:016x}", hash);
+}
+
+fn collect(dir: &Path, files: &mut Vec<std::path::PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+}
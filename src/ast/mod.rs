@@ -2,14 +2,17 @@
 
 use std::fmt::Debug;
 use crate::lexer::DataType;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+pub mod visit;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Location {
     pub line: usize,
     pub column: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Stmt {
   Program(Program),
   VarDecl(VarDecl),
@@ -17,7 +20,11 @@ pub enum Stmt {
   ObjectDecl(ObjectDecl),
   IfStmt(IfStmt),
   ForStmt(ForStmt),
+  ForIn(ForInStmt),
   WhileStmt(WhileStmt),
+  Loop(LoopStmt),
+  Break(BreakStmt),
+  Continue(ContinueStmt),
   TryCatchStmt(TryCatchStmt),
   BlockStmt(BlockStmt),
   Use(UseStmt),
@@ -25,39 +32,51 @@ pub enum Stmt {
   Export(ExportStmt),
   Return(ReturnStmt),
   Lambda(LambdaDecl),
+  EnumDecl(EnumDecl),
+  Defer(DeferStmt),
+  With(WithStmt),
+  Switch(SwitchStmt),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Expr {
   Assign(AssignExpr),
   Member(MemberExpr),
   Call(CallExpr),
+  MethodCall(MethodCallExpr),
   Unary(UnaryExpr),
   Binary(BinaryExpr),
   Identifier(Identifier),
   Property(Property),
   IntLit(IntLit),
   FloatLit(FloatLit),
+  DecimalLit(DecimalLit),
   StringLit(StringLit),
+  TemplateLit(TemplateLit),
   BoolLit(BoolLit),
+  NullLit(NullLit),
   ArrayLit(ArrayLit),
+  VectorLit(VectorLit),
+  MatrixLit(MatrixLit),
   ObjectLit(ObjectLit),
+  Match(MatchExpr),
+  Lambda(LambdaExpr),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Content {
   Statement(Box<Stmt>),
   Expression(Box<Expr>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Program {
     pub imports: Vec<Content>,
-    pub content: Vec<Box<Content>>,
+    pub content: Vec<Content>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VarDecl {
     pub constant: bool,
     pub ident: String,
@@ -66,7 +85,7 @@ pub struct VarDecl {
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Param {
     pub ident: String,
     pub type_: DataType,
@@ -74,97 +93,201 @@ pub struct Param {
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FuncDecl {
     pub params: Vec<Param>,
     pub ident: String,
     pub return_type: Option<DataType>,
-    pub body: Vec<Box<Content>>,
+    pub body: Vec<Content>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ObjectDecl {
     pub ident: String,
     pub properties: Vec<Property>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IfStmt {
     pub test: Box<Expr>,
-    pub body: Vec<Box<Content>>,
-    pub alt: Option<Vec<Box<Content>>>,
+    pub body: Vec<Content>,
+    pub alt: Option<Vec<Content>>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+/// `for |let i: int = 0; i < n; i += 1| { ... }`; `init` is only in scope
+/// for the duration of the loop.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ForStmt {
     pub init: Option<Box<Stmt>>,
     pub test: Option<Box<Expr>>,
     pub update: Option<Box<Expr>>,
-    pub body: Vec<Box<Content>>,
+    pub body: Vec<Content>,
+    pub else_body: Option<Vec<Content>>,
+    pub label: Option<String>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+/// `for |a, b| in iterable { ... }`: one identifier for array values, or two
+/// for index/value and key/value pairs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForInStmt {
+    pub idents: Vec<String>,
+    pub iterable: Box<Expr>,
+    pub body: Vec<Content>,
+    pub else_body: Option<Vec<Content>>,
+    pub label: Option<String>,
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WhileStmt {
     pub test: Box<Expr>,
-    pub body: Vec<Box<Content>>,
+    pub body: Vec<Content>,
+    /// Runs once the loop finishes without hitting `break`.
+    pub else_body: Option<Vec<Content>>,
+    /// From `name: while ...`; `break name` ends this loop from a nested one.
+    pub label: Option<String>,
+    pub location: Location,
+}
+
+/// `loop { ... }`: repeats until a `break`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoopStmt {
+    pub body: Vec<Content>,
+    pub label: Option<String>,
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakStmt {
+    /// The loop to end; the innermost one when `None`.
+    pub label: Option<String>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContinueStmt {
+    /// The loop to move on to its next iteration; the innermost one when `None`.
+    pub label: Option<String>,
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TryCatchStmt {
-    pub try_block: Vec<Box<Content>>,
+    pub try_block: Vec<Content>,
     pub catch_param: Option<String>,
-    pub catch_block: Option<Vec<Box<Content>>>,
+    pub catch_block: Option<Vec<Content>>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockStmt {
-    pub body: Vec<Box<Content>>,
+    pub body: Vec<Content>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UseStmt {
     pub methods: Option<Vec<String>>,
     pub module: String,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IncludeStmt {
     pub methods: Option<Vec<String>>,
     pub file_path: String,
+    /// `include lazy ...`: only the included file's declarations run.
+    pub lazy: bool,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportStmt {
     pub exports: Vec<String>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReturnStmt {
     pub value: Option<Box<Content>>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LambdaDecl {
     pub constant: bool,
     pub ident: String,
     pub params: Vec<Param>,
     pub return_type: Option<DataType>,
-    pub body: Vec<Box<Content>>,
+    pub body: Vec<Content>,
+    pub location: Location,
+}
+
+/// `fn -> |params| { body }` written where a value is expected, such as a
+/// call argument or an object property.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LambdaExpr {
+    pub params: Vec<Param>,
+    pub body: Vec<Content>,
+    pub location: Location,
+}
+
+/// `defer expr;` runs `expr` when the enclosing function (or file) finishes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeferStmt {
+    pub expr: Box<Expr>,
+    pub location: Location,
+}
+
+/// `with resource as name { ... }` closes `resource` through its `__close__`
+/// method once the body finishes, however it finishes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithStmt {
+    pub resource: Box<Expr>,
+    pub ident: String,
+    pub body: Vec<Content>,
+    pub location: Location,
+}
+
+/// `switch value { case 1, 2 { ... } default { ... } }` runs the body of the
+/// first case holding a value strictly equal to the int or string `value`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwitchStmt {
+    pub subject: Box<Expr>,
+    pub cases: Vec<SwitchCase>,
+    pub default: Option<Vec<Content>>,
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwitchCase {
+    pub values: Vec<Box<Expr>>,
+    pub body: Vec<Content>,
+    /// The body ended with `fallthrough`, so the next case's body (or the
+    /// default) runs after it.
+    pub fallthrough: bool,
+    pub location: Location,
+}
+
+impl SwitchStmt {
+    /// Every case body, then the default's.
+    pub fn bodies(&self) -> impl Iterator<Item = &Vec<Content>> {
+        self.cases.iter().map(|case| &case.body).chain(self.default.iter())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnumDecl {
+    pub ident: String,
+    pub variants: Vec<String>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssignExpr {
     pub left: Box<Expr>,
     pub right: Box<Expr>,
@@ -172,30 +295,47 @@ pub struct AssignExpr {
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MemberExpr {
     pub object: Box<Expr>,
+    /// An `Identifier` naming the property for `obj.key`; the key expression
+    /// for `obj[key]`.
     pub property: Box<Expr>,
-    pub is_method: bool,
+    /// `obj[key]`: the property is evaluated to find the index or key.
+    pub computed: bool,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CallExpr {
     pub callee: Box<Expr>,
     pub args: Vec<Box<Expr>>,
+    /// The parameter each `name: value` argument names, in step with `args`.
+    /// Empty when every argument is positional.
+    pub arg_names: Vec<Option<String>>,
     pub is_native: bool,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+/// `object.method => |args|`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MethodCallExpr {
+    pub object: Box<Expr>,
+    pub method: String,
+    /// `method` resolved when parsed, `None` for names that are not built in.
+    pub method_id: Option<Method>,
+    pub args: Vec<Box<Expr>>,
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UnaryExpr {
     pub operator: String,
     pub operand: Box<Expr>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BinaryExpr {
     pub left: Box<Expr>,
     pub right: Box<Expr>,
@@ -203,88 +343,241 @@ pub struct BinaryExpr {
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Identifier {
     pub name: String,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Property {
     pub key: String,
+    /// The expression of a bracketed `[expr]: value` key, evaluated when the
+    /// object is built; `key` is empty for these.
+    pub computed_key: Option<Box<Expr>>,
     pub value: Box<Expr>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IntLit {
     pub value: i64,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FloatLit {
     pub value: f64,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+/// A `10.50d` literal. The digits are kept as written so the scale survives.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecimalLit {
+    pub value: String,
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StringLit {
     pub value: String,
+    /// Written `r"..."`, so the value is exactly the source text: no escapes
+    /// and no `{expr}` interpolation.
+    #[serde(default)]
+    pub raw: bool,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+/// `"Hello, {name}!"`: a string literal with expressions embedded in braces.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateLit {
+    pub parts: Vec<TemplatePart>,
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TemplatePart {
+    Text(String),
+    /// An interpolated expression, with its source text between the braces.
+    Expr { expr: Box<Expr>, raw: String },
+}
+
+impl TemplateLit {
+    /// The interpolated expressions, in order.
+    pub fn exprs(&self) -> impl Iterator<Item = &Expr> {
+        self.parts.iter().filter_map(|part| match part {
+            TemplatePart::Expr { expr, .. } => Some(expr.as_ref()),
+            TemplatePart::Text(_) => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BoolLit {
     pub value: bool,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+/// `null`, the value that stands for "nothing here".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NullLit {
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ArrayLit {
     pub elements: Vec<Box<Expr>>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ObjectLit {
     pub properties: Vec<Property>,
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchExpr {
+    pub subject: Box<Expr>,
+    pub arms: Vec<MatchArm>,
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Box<Expr>>,
+    pub body: Vec<Content>,
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Pattern {
+    /// `_`, matches anything.
+    Wildcard,
+    /// Matches when the subject equals the value of the expression.
+    Value(Box<Expr>),
+    /// A bare name matches anything and binds it.
+    Binding(String),
+    /// `[a, b]` matches an array of exactly that length.
+    Array(Vec<Pattern>),
+    /// `{ key, other: pattern }` matches an object that has every listed key.
+    Object(Vec<(String, Pattern)>),
+    /// `a | b` matches when any alternative does.
+    Or(Vec<Pattern>),
+    /// A type name such as `int` or `string` matches any value of that kind,
+    /// and binds it when a name follows (`string s`).
+    Kind(DataType, Option<String>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ComplexLit {
     pub real: f64,
     pub imag: f64,
 }
 
-#[derive(Debug, Clone)]
+/// `#[1, 2, 3]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VectorLit {
-    pub elements: Vec<f64>,
+    pub elements: Vec<Box<Expr>>,
+    pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+/// `#[[1, 2], [3, 4]]`, one array literal per row.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MatrixLit {
-    pub rows: Vec<Vec<f64>>,
+    pub rows: Vec<Vec<Box<Expr>>>,
+    pub location: Location,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// A built-in method, resolved from its name once when the call is parsed so
+/// dispatch compares ids rather than strings. Object fields and library
+/// members are not built in and stay looked up by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Method {
+    // Every value
+    Format,
+    Cast,
+    // Arrays
     Length,
+    First,
+    Last,
+    Push,
+    Pop,
+    Shift,
+    Unshift,
+    Resize,
+    Fill,
+    Join,
+    Remove,
+    IndexOf,
+    RemoveValue,
+    Count,
+    SortBy,
+    SortByKeys,
+    // Strings
     ToUpper,
     ToLower,
     Trim,
     Split,
-    Push,
-    Pop, 
-    Join,
-    First,
-    Last,
+    CharCodeAt,
+    // Objects
     Keys,
     Values,
     Entries,
+    HasKey,
+    Get,
+    FindKey,
+    // Numbers
+    IsEven,
+    IsOdd,
     Round,
     Floor,
     Ceil,
-    ToString,
+    Scale,
+    ToFloat,
+}
+
+impl Method {
+    pub fn resolve(name: &str) -> Option<Method> {
+        Some(match name {
+            "format" => Method::Format,
+            "cast" => Method::Cast,
+            "length" => Method::Length,
+            "first" => Method::First,
+            "last" => Method::Last,
+            "push" => Method::Push,
+            "pop" => Method::Pop,
+            "shift" => Method::Shift,
+            "unshift" => Method::Unshift,
+            "resize" => Method::Resize,
+            "fill" => Method::Fill,
+            "join" => Method::Join,
+            "remove" => Method::Remove,
+            "indexOf" => Method::IndexOf,
+            "remove_value" => Method::RemoveValue,
+            "count" => Method::Count,
+            "sort_by" => Method::SortBy,
+            "sort_by_keys" => Method::SortByKeys,
+            "toUpper" => Method::ToUpper,
+            "toLower" => Method::ToLower,
+            "trim" => Method::Trim,
+            "split" => Method::Split,
+            "charCodeAt" => Method::CharCodeAt,
+            "keys" => Method::Keys,
+            "values" => Method::Values,
+            "entries" => Method::Entries,
+            "hasKey" => Method::HasKey,
+            "get" => Method::Get,
+            "find_key" => Method::FindKey,
+            "isEven" => Method::IsEven,
+            "isOdd" => Method::IsOdd,
+            "round" => Method::Round,
+            "floor" => Method::Floor,
+            "ceil" => Method::Ceil,
+            "scale" => Method::Scale,
+            "to_float" => Method::ToFloat,
+            _ => return None,
+        })
+    }
 }
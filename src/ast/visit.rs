@@ -0,0 +1,262 @@
+//! Read-only traversal over the AST.
+//!
+//! Implement [`Visitor`] and override only the hooks you care about; every
+//! default hook forwards to the matching `walk_*` function, which visits all
+//! children of the node. The walkers match every `Stmt`/`Expr` variant
+//! explicitly, so adding a node type fails to compile here until its children
+//! are walked, instead of being silently skipped by lint rules.
+
+use super::*;
+
+pub trait Visitor {
+    fn visit_content(&mut self, content: &Content) {
+        walk_content(self, content);
+    }
+
+    /// A sequence of statements/expressions forming a body (program, function,
+    /// branch or loop body).
+    fn visit_block(&mut self, body: &[Content]) {
+        walk_block(self, body);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_param(&mut self, param: &Param) {
+        walk_param(self, param);
+    }
+
+    fn visit_pattern(&mut self, pattern: &Pattern) {
+        walk_pattern(self, pattern);
+    }
+}
+
+pub fn walk_content<V: Visitor + ?Sized>(visitor: &mut V, content: &Content) {
+    match content {
+        Content::Statement(stmt) => visitor.visit_stmt(stmt),
+        Content::Expression(expr) => visitor.visit_expr(expr),
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, body: &[Content]) {
+    for content in body {
+        visitor.visit_content(content);
+    }
+}
+
+pub fn walk_param<V: Visitor + ?Sized>(visitor: &mut V, param: &Param) {
+    if let Some(default) = &param.default_value {
+        visitor.visit_expr(default);
+    }
+}
+
+pub fn walk_pattern<V: Visitor + ?Sized>(visitor: &mut V, pattern: &Pattern) {
+    match pattern {
+        Pattern::Value(value) => visitor.visit_expr(value),
+        Pattern::Array(items) | Pattern::Or(items) => {
+            for item in items {
+                visitor.visit_pattern(item);
+            }
+        }
+        Pattern::Object(fields) => {
+            for (_, field) in fields {
+                visitor.visit_pattern(field);
+            }
+        }
+        Pattern::Wildcard | Pattern::Binding(_) | Pattern::Kind(..) => {}
+    }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt) {
+    match stmt {
+        Stmt::Program(program) => {
+            for import in &program.imports {
+                visitor.visit_content(import);
+            }
+            visitor.visit_block(&program.content);
+        }
+        Stmt::VarDecl(decl) => {
+            if let Some(value) = &decl.value {
+                visitor.visit_content(value);
+            }
+        }
+        Stmt::FuncDecl(decl) => {
+            for param in &decl.params {
+                visitor.visit_param(param);
+            }
+            visitor.visit_block(&decl.body);
+        }
+        Stmt::Lambda(decl) => {
+            for param in &decl.params {
+                visitor.visit_param(param);
+            }
+            visitor.visit_block(&decl.body);
+        }
+        Stmt::ObjectDecl(decl) => {
+            for property in &decl.properties {
+                if let Some(key) = &property.computed_key {
+                    visitor.visit_expr(key);
+                }
+                visitor.visit_expr(&property.value);
+            }
+        }
+        Stmt::IfStmt(stmt) => {
+            visitor.visit_expr(&stmt.test);
+            visitor.visit_block(&stmt.body);
+            if let Some(alt) = &stmt.alt {
+                visitor.visit_block(alt);
+            }
+        }
+        Stmt::ForStmt(stmt) => {
+            if let Some(init) = &stmt.init {
+                visitor.visit_stmt(init);
+            }
+            if let Some(test) = &stmt.test {
+                visitor.visit_expr(test);
+            }
+            if let Some(update) = &stmt.update {
+                visitor.visit_expr(update);
+            }
+            visitor.visit_block(&stmt.body);
+            if let Some(else_body) = &stmt.else_body {
+                visitor.visit_block(else_body);
+            }
+        }
+        Stmt::ForIn(stmt) => {
+            visitor.visit_expr(&stmt.iterable);
+            visitor.visit_block(&stmt.body);
+            if let Some(else_body) = &stmt.else_body {
+                visitor.visit_block(else_body);
+            }
+        }
+        Stmt::WhileStmt(stmt) => {
+            visitor.visit_expr(&stmt.test);
+            visitor.visit_block(&stmt.body);
+            if let Some(else_body) = &stmt.else_body {
+                visitor.visit_block(else_body);
+            }
+        }
+        Stmt::Loop(stmt) => visitor.visit_block(&stmt.body),
+        Stmt::Break(_) | Stmt::Continue(_) => {}
+        Stmt::TryCatchStmt(stmt) => {
+            visitor.visit_block(&stmt.try_block);
+            if let Some(catch) = &stmt.catch_block {
+                visitor.visit_block(catch);
+            }
+        }
+        Stmt::BlockStmt(stmt) => visitor.visit_block(&stmt.body),
+        Stmt::Return(stmt) => {
+            if let Some(value) = &stmt.value {
+                visitor.visit_content(value);
+            }
+        }
+        Stmt::Defer(stmt) => visitor.visit_expr(&stmt.expr),
+        Stmt::With(stmt) => {
+            visitor.visit_expr(&stmt.resource);
+            visitor.visit_block(&stmt.body);
+        }
+        Stmt::Switch(stmt) => {
+            visitor.visit_expr(&stmt.subject);
+            for case in &stmt.cases {
+                for value in &case.values {
+                    visitor.visit_expr(value);
+                }
+                visitor.visit_block(&case.body);
+            }
+            if let Some(default) = &stmt.default {
+                visitor.visit_block(default);
+            }
+        }
+        Stmt::Use(_) | Stmt::Include(_) | Stmt::Export(_) | Stmt::EnumDecl(_) => {}
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Assign(assign) => {
+            visitor.visit_expr(&assign.left);
+            visitor.visit_expr(&assign.right);
+        }
+        Expr::Member(member) => {
+            visitor.visit_expr(&member.object);
+            // `obj.key` names a property, not a variable.
+            if member.computed {
+                visitor.visit_expr(&member.property);
+            }
+        }
+        Expr::Call(call) => {
+            visitor.visit_expr(&call.callee);
+            for arg in &call.args {
+                visitor.visit_expr(arg);
+            }
+        }
+        Expr::MethodCall(call) => {
+            visitor.visit_expr(&call.object);
+            for arg in &call.args {
+                visitor.visit_expr(arg);
+            }
+        }
+        Expr::Unary(unary) => visitor.visit_expr(&unary.operand),
+        Expr::Binary(binary) => {
+            visitor.visit_expr(&binary.left);
+            visitor.visit_expr(&binary.right);
+        }
+        Expr::Property(property) => visitor.visit_expr(&property.value),
+        Expr::ArrayLit(array) => {
+            for element in &array.elements {
+                visitor.visit_expr(element);
+            }
+        }
+        Expr::VectorLit(vector) => {
+            for element in &vector.elements {
+                visitor.visit_expr(element);
+            }
+        }
+        Expr::MatrixLit(matrix) => {
+            for element in matrix.rows.iter().flatten() {
+                visitor.visit_expr(element);
+            }
+        }
+        Expr::ObjectLit(object) => {
+            for property in &object.properties {
+                if let Some(key) = &property.computed_key {
+                    visitor.visit_expr(key);
+                }
+                visitor.visit_expr(&property.value);
+            }
+        }
+        Expr::TemplateLit(template) => {
+            for expr in template.exprs() {
+                visitor.visit_expr(expr);
+            }
+        }
+        Expr::Match(expr) => {
+            visitor.visit_expr(&expr.subject);
+            for arm in &expr.arms {
+                visitor.visit_pattern(&arm.pattern);
+                if let Some(guard) = &arm.guard {
+                    visitor.visit_expr(guard);
+                }
+                visitor.visit_block(&arm.body);
+            }
+        }
+        Expr::Lambda(lambda) => {
+            for param in &lambda.params {
+                visitor.visit_param(param);
+            }
+            visitor.visit_block(&lambda.body);
+        }
+        Expr::Identifier(_)
+        | Expr::IntLit(_)
+        | Expr::FloatLit(_)
+        | Expr::DecimalLit(_)
+        | Expr::StringLit(_)
+        | Expr::BoolLit(_)
+        | Expr::NullLit(_) => {}
+    }
+}
@@ -0,0 +1,106 @@
+//! Micro-benchmarks for `zekken bench`.
+//!
+//! Each benchmark is a small script that stresses one part of the
+//! interpreter, with `{n}` standing for the iteration count. Every script runs
+//! under both engines and the fastest of the repeated runs is reported, which
+//! is the least noisy number for before/after comparisons of a change.
+
+use crate::diagnostics::{run_program_collecting, ExecutionMode};
+use crate::environment::Environment;
+use crate::parser::Parser;
+use std::time::{Duration, Instant};
+
+const BENCHMARKS: &[(&str, &str)] = &[
+    (
+        "int_arith",
+        "let total: int = 0\nlet i: int = 0\nwhile i < {n} {\n  total = total + i * 3 % 7 - i / 5\n  i += 1\n}",
+    ),
+    (
+        "float_arith",
+        "let x: float = 0.5\nlet acc: float = 0.0\nlet i: int = 0\nwhile i < {n} {\n  acc = acc * 0.5 + x * 1.5 - 0.25\n  i += 1\n}",
+    ),
+    (
+        "compare",
+        "let hits: int = 0\nlet i: int = 0\nwhile i < {n} {\n  if i % 3 == 0 && i >= 10 {\n    hits += 1\n  }\n  i += 1\n}",
+    ),
+    (
+        "calls",
+        "func add |a: int, b: int| -> int {\n  return a + b\n}\nlet total: int = 0\nlet i: int = 0\nwhile i < {n} {\n  total = add => |total, i|\n  i += 1\n}",
+    ),
+    (
+        "strings",
+        "let s: string = \"\"\nlet i: int = 0\nwhile i < {n} / 10 {\n  s = s + \"x\"\n  i += 1\n}",
+    ),
+    (
+        "arrays",
+        "let items: arr = []\nlet i: int = 0\nwhile i < {n} / 10 {\n  items.push => |i|\n  i += 1\n}\nlet total: int = 0\nfor |item| in items {\n  total += item\n}",
+    ),
+    (
+        "methods",
+        "let word: string = \" Zekken \"\nlet f: float = 2.5\nlet hits: int = 0\nlet i: int = 0\nwhile i < {n} / 4 {\n  if i.isEven => || && word.trim => || != \"\" {\n    hits += f.floor => ||\n  }\n  i += 1\n}",
+    ),
+];
+
+/// Names of the benchmarks, in the order they run.
+pub fn names() -> Vec<&'static str> {
+    BENCHMARKS.iter().map(|(name, _)| *name).collect()
+}
+
+fn time_once(source: &str, mode: ExecutionMode) -> Result<Duration, String> {
+    let mut parser = Parser::new();
+    let ast = parser.produce_ast(source.to_string());
+    let mut env = Environment::new();
+    let started = Instant::now();
+    let report = run_program_collecting(&ast, &parser.errors, &mut env, mode);
+    let elapsed = started.elapsed();
+    match report.errors.into_iter().next() {
+        Some(error) => Err(error.message),
+        None => Ok(elapsed),
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    let micros = d.as_secs_f64() * 1e6;
+    if micros >= 1000.0 {
+        format!("{:.2} ms", micros / 1000.0)
+    } else {
+        format!("{:.1} us", micros)
+    }
+}
+
+/// Runs every benchmark whose name contains `filter`, `runs` times per
+/// engine, and prints the best time of each.
+pub fn run(filter: Option<&str>, iterations: u64, runs: u32) -> Result<(), String> {
+    let selected: Vec<_> = BENCHMARKS
+        .iter()
+        .filter(|(name, _)| filter.is_none_or(|f| name.contains(f)))
+        .collect();
+    if selected.is_empty() {
+        return Err(format!(
+            "No benchmark matches '{}' (available: {})",
+            filter.unwrap_or_default(),
+            names().join(", ")
+        ));
+    }
+
+    println!("{:<14}{:>14}{:>14}{:>13}", "benchmark", "tree-walk", "vm", "vm speedup");
+    for (name, template) in selected {
+        let source = template.replace("{n}", &iterations.to_string());
+        let mut best = [Duration::MAX; 2];
+        for (slot, mode) in [ExecutionMode::TreeWalk, ExecutionMode::Bytecode].into_iter().enumerate() {
+            for _ in 0..runs.max(1) {
+                let elapsed = time_once(&source, mode).map_err(|e| format!("Benchmark '{}' failed: {}", name, e))?;
+                best[slot] = best[slot].min(elapsed);
+            }
+        }
+        let speedup = best[0].as_secs_f64() / best[1].as_secs_f64().max(f64::EPSILON);
+        println!(
+            "{:<14}{:>14}{:>14}{:>12.2}x",
+            name,
+            format_duration(best[0]),
+            format_duration(best[1]),
+            speedup
+        );
+    }
+    Ok(())
+}
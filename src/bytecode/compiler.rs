@@ -1,6 +1,8 @@
+use crate::ast::visit::{walk_stmt, Visitor};
 use crate::ast::*;
 use crate::environment::{FunctionValue, Value};
 use crate::lexer::DataType;
+use crate::eval::pattern::{bound_names, value_exprs};
 use std::collections::HashSet;
 use std::sync::Arc;
 
@@ -12,21 +14,29 @@ use super::libraries::http::HttpOpCode;
 use super::libraries::math::MathOpCode;
 use super::libraries::os::OsOpCode;
 
-fn expr_location(expr: &Expr) -> Location {
+pub(super) fn expr_location(expr: &Expr) -> Location {
     match expr {
         Expr::Assign(e) => e.location.clone(),
         Expr::Member(e) => e.location.clone(),
         Expr::Call(e) => e.location.clone(),
+        Expr::MethodCall(e) => e.location.clone(),
         Expr::Unary(e) => e.location.clone(),
         Expr::Binary(e) => e.location.clone(),
         Expr::Identifier(e) => e.location.clone(),
         Expr::Property(e) => e.location.clone(),
         Expr::IntLit(e) => e.location.clone(),
         Expr::FloatLit(e) => e.location.clone(),
+        Expr::DecimalLit(e) => e.location.clone(),
         Expr::StringLit(e) => e.location.clone(),
+        Expr::TemplateLit(e) => e.location.clone(),
         Expr::BoolLit(e) => e.location.clone(),
+        Expr::NullLit(e) => e.location.clone(),
         Expr::ArrayLit(e) => e.location.clone(),
+        Expr::VectorLit(e) => e.location.clone(),
+        Expr::MatrixLit(e) => e.location.clone(),
         Expr::ObjectLit(e) => e.location.clone(),
+        Expr::Match(e) => e.location.clone(),
+        Expr::Lambda(e) => e.location.clone(),
     }
 }
 
@@ -41,7 +51,7 @@ pub(super) struct ParentUsage {
     pub(super) captures: HashSet<String>,
 }
 
-pub(super) fn analyze_function_parent_usage(params: &[Param], body: &[Box<Content>]) -> ParentUsage {
+pub(super) fn analyze_function_parent_usage(params: &[Param], body: &[Content]) -> ParentUsage {
     let mut locals = HashSet::new();
     let mut usage = ParentUsage::default();
     for p in params {
@@ -58,13 +68,34 @@ pub(super) fn analyze_function_parent_usage(params: &[Param], body: &[Box<Conten
     usage
 }
 
+// Compiled loops are plain jumps and never look at `Environment::control_flow`,
+// so a loop whose body can `break` or `continue` runs through the native
+// evaluator instead.
+fn body_has_break(body: &[Content]) -> bool {
+    struct FindBreak(bool);
+
+    impl Visitor for FindBreak {
+        fn visit_stmt(&mut self, stmt: &Stmt) {
+            if matches!(stmt, Stmt::Break(_) | Stmt::Continue(_)) {
+                self.0 = true;
+            } else if !self.0 {
+                walk_stmt(self, stmt);
+            }
+        }
+    }
+
+    let mut finder = FindBreak(false);
+    finder.visit_block(body);
+    finder.0
+}
+
 fn analyze_contents_parent_usage(
-    contents: &[Box<Content>],
+    contents: &[Content],
     locals: &mut HashSet<String>,
     usage: &mut ParentUsage,
 ) {
     for content in contents {
-        match content.as_ref() {
+        match content {
             Content::Statement(stmt) => analyze_stmt_parent_usage(stmt, locals, usage),
             Content::Expression(expr) => analyze_expr_parent_usage(expr, locals, usage),
         }
@@ -90,6 +121,9 @@ fn analyze_stmt_parent_usage(stmt: &Stmt, locals: &mut HashSet<String>, usage: &
         }
         Stmt::ObjectDecl(obj) => {
             for p in &obj.properties {
+                if let Some(key) = &p.computed_key {
+                    analyze_expr_parent_usage(key, locals, usage);
+                }
                 analyze_expr_parent_usage(&p.value, locals, usage);
                 if usage.requires_parent_clone {
                     return;
@@ -112,11 +146,36 @@ fn analyze_stmt_parent_usage(stmt: &Stmt, locals: &mut HashSet<String>, usage: &
             }
         }
         Stmt::ForStmt(f) => {
+            // The loop runs in the current scope, so its counter is a local.
             if let Some(init) = &f.init {
                 analyze_stmt_parent_usage(init, locals, usage);
             }
+            for expr in f.test.iter().chain(f.update.iter()) {
+                analyze_expr_parent_usage(expr, locals, usage);
+            }
+            if usage.requires_parent_clone {
+                return;
+            }
             let mut body_locals = locals.clone();
             analyze_contents_parent_usage(&f.body, &mut body_locals, usage);
+            if let Some(else_body) = &f.else_body {
+                let mut else_locals = locals.clone();
+                analyze_contents_parent_usage(else_body, &mut else_locals, usage);
+            }
+        }
+        Stmt::ForIn(f) => {
+            analyze_expr_parent_usage(&f.iterable, locals, usage);
+            if usage.requires_parent_clone {
+                return;
+            }
+            // Loop variables only exist inside the body.
+            let mut body_locals = locals.clone();
+            body_locals.extend(f.idents.iter().cloned());
+            analyze_contents_parent_usage(&f.body, &mut body_locals, usage);
+            if let Some(else_body) = &f.else_body {
+                let mut else_locals = locals.clone();
+                analyze_contents_parent_usage(else_body, &mut else_locals, usage);
+            }
         }
         Stmt::WhileStmt(w) => {
             analyze_expr_parent_usage(&w.test, locals, usage);
@@ -125,6 +184,14 @@ fn analyze_stmt_parent_usage(stmt: &Stmt, locals: &mut HashSet<String>, usage: &
             }
             let mut body_locals = locals.clone();
             analyze_contents_parent_usage(&w.body, &mut body_locals, usage);
+            if let Some(else_body) = &w.else_body {
+                let mut else_locals = locals.clone();
+                analyze_contents_parent_usage(else_body, &mut else_locals, usage);
+            }
+        }
+        Stmt::Loop(l) => {
+            let mut body_locals = locals.clone();
+            analyze_contents_parent_usage(&l.body, &mut body_locals, usage);
         }
         Stmt::BlockStmt(b) => {
             let mut body_locals = locals.clone();
@@ -138,7 +205,33 @@ fn analyze_stmt_parent_usage(stmt: &Stmt, locals: &mut HashSet<String>, usage: &
                 }
             }
         }
-        Stmt::Export(_) | Stmt::Program(_) => {}
+        Stmt::EnumDecl(e) => {
+            locals.insert(e.ident.clone());
+        }
+        Stmt::Defer(d) => analyze_expr_parent_usage(&d.expr, locals, usage),
+        Stmt::With(w) => {
+            analyze_expr_parent_usage(&w.resource, locals, usage);
+            if usage.requires_parent_clone {
+                return;
+            }
+            let mut body_locals = locals.clone();
+            body_locals.insert(w.ident.clone());
+            analyze_contents_parent_usage(&w.body, &mut body_locals, usage);
+        }
+        Stmt::Switch(s) => {
+            analyze_expr_parent_usage(&s.subject, locals, usage);
+            for value in s.cases.iter().flat_map(|case| &case.values) {
+                analyze_expr_parent_usage(value, locals, usage);
+            }
+            for body in s.bodies() {
+                if usage.requires_parent_clone {
+                    return;
+                }
+                let mut body_locals = locals.clone();
+                analyze_contents_parent_usage(body, &mut body_locals, usage);
+            }
+        }
+        Stmt::Export(_) | Stmt::Program(_) | Stmt::Break(_) | Stmt::Continue(_) => {}
     }
 }
 
@@ -169,14 +262,22 @@ fn analyze_expr_parent_usage(expr: &Expr, locals: &HashSet<String>, usage: &mut
                 }
             }
         }
+        Expr::MethodCall(c) => {
+            analyze_expr_parent_usage(&c.object, locals, usage);
+            for a in &c.args {
+                if usage.requires_parent_clone {
+                    return;
+                }
+                analyze_expr_parent_usage(a, locals, usage);
+            }
+        }
         Expr::Member(m) => {
             analyze_expr_parent_usage(&m.object, locals, usage);
             if usage.requires_parent_clone {
                 return;
             }
-            match m.property.as_ref() {
-                Expr::Identifier(_) | Expr::Property(_) => {}
-                other => analyze_expr_parent_usage(other, locals, usage),
+            if m.computed {
+                analyze_expr_parent_usage(&m.property, locals, usage);
             }
         }
         Expr::Assign(a) => {
@@ -200,15 +301,65 @@ fn analyze_expr_parent_usage(expr: &Expr, locals: &HashSet<String>, usage: &mut
                 }
             }
         }
+        Expr::VectorLit(v) => {
+            for e in &v.elements {
+                analyze_expr_parent_usage(e, locals, usage);
+                if usage.requires_parent_clone {
+                    return;
+                }
+            }
+        }
+        Expr::MatrixLit(m) => {
+            for e in m.rows.iter().flatten() {
+                analyze_expr_parent_usage(e, locals, usage);
+                if usage.requires_parent_clone {
+                    return;
+                }
+            }
+        }
+        Expr::TemplateLit(t) => {
+            for e in t.exprs() {
+                analyze_expr_parent_usage(e, locals, usage);
+                if usage.requires_parent_clone {
+                    return;
+                }
+            }
+        }
         Expr::ObjectLit(o) => {
             for p in &o.properties {
+                if let Some(key) = &p.computed_key {
+                    analyze_expr_parent_usage(key, locals, usage);
+                }
                 analyze_expr_parent_usage(&p.value, locals, usage);
                 if usage.requires_parent_clone {
                     return;
                 }
             }
         }
-        Expr::IntLit(_) | Expr::FloatLit(_) | Expr::StringLit(_) | Expr::BoolLit(_) | Expr::Property(_) => {}
+        Expr::Match(m) => {
+            analyze_expr_parent_usage(&m.subject, locals, usage);
+            for arm in &m.arms {
+                if usage.requires_parent_clone {
+                    return;
+                }
+                let mut values = Vec::new();
+                value_exprs(&arm.pattern, &mut values);
+                for value in values {
+                    analyze_expr_parent_usage(value, locals, usage);
+                }
+                let mut arm_locals = locals.clone();
+                let mut names = Vec::new();
+                bound_names(&arm.pattern, &mut names);
+                arm_locals.extend(names);
+                if let Some(guard) = &arm.guard {
+                    analyze_expr_parent_usage(guard, &arm_locals, usage);
+                }
+                analyze_contents_parent_usage(&arm.body, &mut arm_locals, usage);
+            }
+        }
+        // Like a nested `fn ->` declaration, a lambda keeps its parent scope.
+        Expr::Lambda(_) => usage.requires_parent_clone = true,
+        Expr::IntLit(_) | Expr::FloatLit(_) | Expr::DecimalLit(_) | Expr::StringLit(_) | Expr::BoolLit(_) | Expr::NullLit(_) | Expr::Property(_) => {}
     }
 }
 
@@ -246,7 +397,8 @@ impl Compiler {
                 Expr::IntLit(v) => Some(Value::Int(v.value)),
                 Expr::FloatLit(v) => Some(Value::Float(v.value)),
                 Expr::BoolLit(v) => Some(Value::Boolean(v.value)),
-                Expr::StringLit(v) if !v.value.as_bytes().contains(&b'{') => Some(Value::String(v.value.clone())),
+                Expr::NullLit(_) => Some(Value::Null),
+                Expr::StringLit(v) => Some(Value::String(v.value.clone())),
                 _ => None,
             }
         }
@@ -304,13 +456,18 @@ impl Compiler {
         })
     }
 
-    pub(super) fn compile_contents(&mut self, contents: &[Box<Content>]) {
+    pub(super) fn compile_contents(&mut self, contents: &[Content]) {
         for content in contents {
-            self.compile_content(content.as_ref());
+            self.compile_content(content);
         }
     }
 
     pub(super) fn compile_content(&mut self, content: &Content) {
+        if crate::coverage::is_recording() || crate::trace::is_recording() {
+            if let Some(line) = crate::coverage::line_of(content) {
+                self.emit(Inst::Cover { line });
+            }
+        }
         match content {
             Content::Statement(stmt) => self.compile_stmt(stmt.as_ref()),
             Content::Expression(expr) => {
@@ -340,10 +497,14 @@ impl Compiler {
 
                 self.emit(Inst::DeclareVar {
                     name: decl.ident.clone(),
-                    ty: decl.type_,
+                    ty: decl.type_.clone(),
                     constant: decl.constant,
                     src,
-                    location: decl.location.clone(),
+                    // Type mismatches point at the initializer, like the tree-walk evaluator.
+                    location: match decl.value.as_ref() {
+                        Some(Content::Expression(expr)) => expr_location(expr),
+                        _ => decl.location.clone(),
+                    },
                 });
             }
             Stmt::FuncDecl(func) => {
@@ -372,13 +533,25 @@ impl Compiler {
                     self.patch_jump_target(jump_false, end);
                 }
             }
-            Stmt::WhileStmt(while_stmt) => {
+            Stmt::WhileStmt(while_stmt) if !body_has_break(&while_stmt.body) => {
+                let limit = crate::config::max_iterations();
+                let counter = (limit > 0).then(|| {
+                    let r = self.new_reg();
+                    self.emit(Inst::LoadConst { dst: r, value: Value::Int(0) });
+                    r
+                });
                 let loop_start = self.insts.len();
                 let jump_false = self.emit_test_jump_false(&while_stmt.test, &while_stmt.location);
+                if let Some(counter) = counter {
+                    self.emit(Inst::CountIteration { counter, limit, location: while_stmt.location.clone() });
+                }
                 self.compile_contents(&while_stmt.body);
                 self.emit(Inst::Jump { target: loop_start });
                 let end = self.insts.len();
                 self.patch_jump_target(jump_false, end);
+                if let Some(else_body) = &while_stmt.else_body {
+                    self.compile_contents(else_body);
+                }
             }
             Stmt::BlockStmt(block) => self.compile_contents(&block.body),
             Stmt::Return(ret) => {
@@ -411,16 +584,17 @@ impl Compiler {
             Expr::Assign(assign) => {
                 if let Expr::Identifier(id) = assign.left.as_ref() {
                     if let Expr::IntLit(i) = assign.right.as_ref() {
-                        let delta = match assign.operator.as_str() {
-                            "+=" => Some(i.value),
-                            "-=" => Some(-i.value),
+                        let change = match assign.operator.as_str() {
+                            "+=" => Some((BinaryOpCode::Add, i.value)),
+                            "-=" => Some((BinaryOpCode::Sub, -i.value)),
                             _ => None,
                         };
-                        if let Some(delta) = delta {
+                        if let Some((op, delta)) = change {
                             let dst = self.new_reg();
                             self.emit(Inst::AddIntAssignIdent {
                                 dst,
                                 name: id.name.clone(),
+                                op,
                                 delta,
                                 location: expr_location(assign.right.as_ref()),
                             });
@@ -441,7 +615,7 @@ impl Compiler {
                 }
                 if assign.operator == "=" {
                     if let Expr::Member(member) = assign.left.as_ref() {
-                        if member.is_method {
+                        if member.computed {
                             if let Expr::Identifier(id) = member.object.as_ref() {
                                 let index = self.compile_expr(&member.property);
                                 let src = self.compile_expr(&assign.right);
@@ -477,12 +651,22 @@ impl Compiler {
                 self.emit(Inst::LoadConst { dst, value: Value::Float(v.value) });
                 dst
             }
+            Expr::DecimalLit(v) => {
+                let dst = self.new_reg();
+                self.emit(Inst::LoadConst { dst, value: Value::from(v) });
+                dst
+            }
             Expr::BoolLit(v) => {
                 let dst = self.new_reg();
                 self.emit(Inst::LoadConst { dst, value: Value::Boolean(v.value) });
                 dst
             }
-            Expr::StringLit(v) if !v.value.as_bytes().contains(&b'{') => {
+            Expr::NullLit(_) => {
+                let dst = self.new_reg();
+                self.emit(Inst::LoadConst { dst, value: Value::Null });
+                dst
+            }
+            Expr::StringLit(v) => {
                 let dst = self.new_reg();
                 self.emit(Inst::LoadConst { dst, value: Value::String(v.value.clone()) });
                 dst
@@ -492,7 +676,7 @@ impl Compiler {
                 self.emit(Inst::LoadIdent { dst, name: ident.name.clone(), location: ident.location.clone() });
                 dst
             }
-            Expr::Member(member) if member.is_method => {
+            Expr::Member(member) if member.computed => {
                 let object = self.compile_expr(&member.object);
                 let index = self.compile_expr(&member.property);
                 let dst = self.new_reg();
@@ -527,7 +711,7 @@ impl Compiler {
             }
             Expr::Call(call) => {
                 let argc = call.args.len();
-                if argc <= 3 {
+                if argc <= 3 && call.arg_names.is_empty() {
                     let mut regs = [0usize; 3];
                     for (idx, arg) in call.args.iter().enumerate() {
                         regs[idx] = self.compile_expr(arg);
@@ -542,86 +726,85 @@ impl Compiler {
                             args: regs,
                             is_native: call.is_native,
                             location: call.location.clone(),
+                            arg_locations: call.args.iter().map(|arg| expr_location(arg)).collect(),
                         });
                         return dst;
                     }
+                }
+                let dst = self.new_reg();
+                self.emit(Inst::EvalExprNative { dst, expr: expr.clone() });
+                dst
+            }
+            Expr::MethodCall(call) => {
+                let argc = call.args.len();
+                if argc <= 3 {
+                    let mut regs = [0usize; 3];
+                    for (idx, arg) in call.args.iter().enumerate() {
+                        regs[idx] = self.compile_expr(arg);
+                    }
 
-                    if let Expr::Member(member) = call.callee.as_ref() {
-                        if let Expr::Identifier(object) = member.object.as_ref() {
-                            if let Expr::Identifier(method_ident) = member.property.as_ref() {
-                                let dst = self.new_reg();
-                                if object.name == "math" {
-                                    if let Some(method) = MathOpCode::from_method(method_ident.name.as_str()) {
-                                        self.emit(Inst::CallMath {
-                                            dst,
-                                            method,
-                                            argc: argc as u8,
-                                            args: regs,
-                                            location: call.location.clone(),
-                                        });
-                                        return dst;
-                                    }
-                                } else if object.name == "fs" {
-                                    if let Some(method) = FsOpCode::from_method(method_ident.name.as_str()) {
-                                        self.emit(Inst::CallFs {
-                                            dst,
-                                            method,
-                                            argc: argc as u8,
-                                            args: regs,
-                                            location: call.location.clone(),
-                                        });
-                                        return dst;
-                                    }
-                                } else if object.name == "os" {
-                                    if let Some(method) = OsOpCode::from_method(method_ident.name.as_str()) {
-                                        self.emit(Inst::CallOs {
-                                            dst,
-                                            method,
-                                            argc: argc as u8,
-                                            args: regs,
-                                            location: call.location.clone(),
-                                        });
-                                        return dst;
-                                    }
-                                } else if object.name == "path" {
-                                    if let Some(method) = PathOpCode::from_method(method_ident.name.as_str()) {
-                                        self.emit(Inst::CallPath {
-                                            dst,
-                                            method,
-                                            argc: argc as u8,
-                                            args: regs,
-                                            location: call.location.clone(),
-                                        });
-                                        return dst;
-                                    }
-                                } else if object.name == "encoding" {
-                                    if let Some(method) = EncodingOpCode::from_method(method_ident.name.as_str()) {
-                                        self.emit(Inst::CallEncoding {
-                                            dst,
-                                            method,
-                                            argc: argc as u8,
-                                            args: regs,
-                                            location: call.location.clone(),
-                                        });
-                                        return dst;
-                                    }
-                                } else if object.name == "http" {
-                                    if let Some(method) = HttpOpCode::from_method(method_ident.name.as_str()) {
-                                        self.emit(Inst::CallHttp {
-                                            dst,
-                                            method,
-                                            argc: argc as u8,
-                                            args: regs,
-                                            location: call.location.clone(),
-                                        });
-                                        return dst;
-                                    }
-                                }
-
-                                self.emit(Inst::CallMethodIdent {
+                    if let Expr::Identifier(object) = call.object.as_ref() {
+                        let dst = self.new_reg();
+                        if object.name == "math" {
+                            if let Some(method) = MathOpCode::from_method(call.method.as_str()) {
+                                self.emit(Inst::CallMath {
+                                    dst,
+                                    method,
+                                    argc: argc as u8,
+                                    args: regs,
+                                    location: call.location.clone(),
+                                });
+                                return dst;
+                            }
+                        } else if object.name == "fs" {
+                            if let Some(method) = FsOpCode::from_method(call.method.as_str()) {
+                                self.emit(Inst::CallFs {
                                     dst,
-                                    object_name: object.name.clone(),
-                                    method_name: method_ident.name.clone(),
+                                    method,
+                                    argc: argc as u8,
+                                    args: regs,
+                                    location: call.location.clone(),
+                                });
+                                return dst;
+                            }
+                        } else if object.name == "os" {
+                            if let Some(method) = OsOpCode::from_method(call.method.as_str()) {
+                                self.emit(Inst::CallOs {
+                                    dst,
+                                    method,
+                                    argc: argc as u8,
+                                    args: regs,
+                                    location: call.location.clone(),
+                                });
+                                return dst;
+                            }
+                        } else if object.name == "path" {
+                            if let Some(method) = PathOpCode::from_method(call.method.as_str()) {
+                                self.emit(Inst::CallPath {
+                                    dst,
+                                    method,
+                                    argc: argc as u8,
+                                    args: regs,
+                                    location: call.location.clone(),
+                                });
+                                return dst;
+                            }
+                        } else if object.name == "encoding" {
+                            if let Some(method) = EncodingOpCode::from_method(call.method.as_str()) {
+                                self.emit(Inst::CallEncoding {
+                                    dst,
+                                    method,
+                                    argc: argc as u8,
+                                    args: regs,
+                                    location: call.location.clone(),
+                                });
+                                return dst;
+                            }
+                        } else if object.name == "http" {
+                            if let Some(method) = HttpOpCode::from_method(call.method.as_str()) {
+                                self.emit(Inst::CallHttp {
+                                    dst,
+                                    method,
                                     argc: argc as u8,
                                     args: regs,
                                     location: call.location.clone(),
@@ -629,6 +812,17 @@ impl Compiler {
                                 return dst;
                             }
                         }
+
+                        self.emit(Inst::CallMethodIdent {
+                            dst,
+                            object_name: object.name.clone(),
+                            method_name: call.method.clone(),
+                            method_id: call.method_id,
+                            argc: argc as u8,
+                            args: regs,
+                            location: call.location.clone(),
+                        });
+                        return dst;
                     }
                 }
                 let dst = self.new_reg();
@@ -645,10 +839,11 @@ impl Compiler {
 }
 
 pub(crate) fn make_function_value(
+    name: &str,
     params: &[Param],
-    body: &[Box<Content>],
+    body: &[Content],
     return_type: Option<DataType>,
-    env: &crate::environment::Environment,
+    env: &mut crate::environment::Environment,
 ) -> FunctionValue {
     let usage = analyze_function_parent_usage(params, body);
     let captures = if usage.requires_parent_clone {
@@ -658,9 +853,11 @@ pub(crate) fn make_function_value(
         v.sort_unstable();
         v
     };
+    let closure = env.share_with(name, params, body);
+    let shared = closure.as_ref().map_or(&[][..], |closure| closure.names());
     let mut capture_values = hashbrown::HashMap::new();
     if !usage.requires_parent_clone {
-        for capture in &captures {
+        for capture in captures.iter().filter(|capture| capture.as_str() != name && !shared.contains(capture)) {
             if let Some(value) = env.lookup_ref(capture) {
                 capture_values.insert(capture.clone(), value.clone());
             }
@@ -669,6 +866,7 @@ pub(crate) fn make_function_value(
     let mut compiled = Compiler::new();
     compiled.compile_contents(body);
     FunctionValue {
+        name: Arc::from(name),
         params: Arc::new(params.to_vec()),
         body: Arc::new(body.to_vec()),
         return_type,
@@ -677,5 +875,6 @@ pub(crate) fn make_function_value(
         capture_values: Arc::new(capture_values),
         compiled_insts: Some(Arc::new(compiled.insts)),
         compiled_reg_count: compiled.next_reg,
+        closure,
     }
 }
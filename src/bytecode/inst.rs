@@ -46,6 +46,23 @@ impl BinaryOpCode {
             _ => None,
         }
     }
+
+    pub(super) fn as_str(self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Mod => "%",
+            Self::In => "in",
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Gt => ">",
+            Self::Le => "<=",
+            Self::Ge => ">=",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -60,8 +77,9 @@ pub(crate) enum Inst {
     CallPath { dst: Reg, method: PathOpCode, argc: u8, args: [Reg; 3], location: Location },
     CallEncoding { dst: Reg, method: EncodingOpCode, argc: u8, args: [Reg; 3], location: Location },
     CallHttp { dst: Reg, method: HttpOpCode, argc: u8, args: [Reg; 3], location: Location },
-    CallIdent { dst: Reg, name: String, argc: u8, args: [Reg; 3], is_native: bool, location: Location },
-    CallMethodIdent { dst: Reg, object_name: String, method_name: String, argc: u8, args: [Reg; 3], location: Location },
+    /// `arg_locations` only point argument type errors at the argument.
+    CallIdent { dst: Reg, name: String, argc: u8, args: [Reg; 3], is_native: bool, location: Location, arg_locations: Box<[Location]> },
+    CallMethodIdent { dst: Reg, object_name: String, method_name: String, method_id: Option<Method>, argc: u8, args: [Reg; 3], location: Location },
     EvalExprNative { dst: Reg, expr: Expr },
     ExecStmtNative { stmt: Stmt },
     DeclareVar { name: String, ty: DataType, constant: bool, src: Reg, location: Location },
@@ -77,5 +95,11 @@ pub(crate) enum Inst {
     JumpIfIdentCmpFalse { name: String, value: Value, op: BinaryOpCode, target: usize, location: Location },
     SetLast { src: Reg },
     Return { src: Reg },
-    AddIntAssignIdent { dst: Reg, name: String, delta: i64, location: Location },
+    /// `name += n` or `name -= n` (`op`) for an int literal `n`. Numbers
+    /// change by `delta` in place; anything else takes `op` like `name op n`.
+    AddIntAssignIdent { dst: Reg, name: String, op: BinaryOpCode, delta: i64, location: Location },
+    /// Only emitted while coverage is recording.
+    Cover { line: usize },
+    /// Only emitted under `--max-iterations`; `counter` starts at zero.
+    CountIteration { counter: Reg, limit: u64, location: Location },
 }
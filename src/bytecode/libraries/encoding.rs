@@ -1,6 +1,7 @@
 use crate::ast::Location;
 use crate::environment::{Environment, Value};
 use crate::errors::ZekkenError;
+use crate::libraries::common::{call_native, with_native_code};
 
 #[derive(Debug, Clone, Copy)]
 pub enum EncodingOpCode {
@@ -50,8 +51,8 @@ fn dispatch_library_native(
     env: &mut Environment,
     location: &Location,
 ) -> Result<Value, ZekkenError> {
-    let native = match env.lookup_ref(lib_name) {
-        Some(Value::Object(map)) => match map.get(method_name) {
+    let native = match env.lookup_ref(lib_name).and_then(Value::as_object) {
+        Some(map) => match map.get(method_name) {
             Some(Value::NativeFunction(native)) => Some(native.clone()),
             _ => None,
         },
@@ -66,5 +67,5 @@ fn dispatch_library_native(
         )
     })?;
 
-    native(args).map_err(|msg| ZekkenError::runtime(&msg, location.line, location.column, None))
+    call_native(&*native, args, location.line, location.column).map_err(|msg| with_native_code(ZekkenError::runtime(&msg, location.line, location.column, None)))
 }
@@ -1,6 +1,7 @@
 use crate::ast::Location;
 use crate::environment::{Environment, Value};
 use crate::errors::ZekkenError;
+use crate::libraries::common::{call_native, with_native_code};
 
 #[derive(Debug, Clone, Copy)]
 pub enum FsOpCode {
@@ -18,6 +19,7 @@ pub enum FsOpCode {
     CopyFile,
     Rename,
     Stat,
+    Open,
 }
 
 impl FsOpCode {
@@ -38,6 +40,7 @@ impl FsOpCode {
             "copy_file" => Some(Self::CopyFile),
             "rename" => Some(Self::Rename),
             "stat" => Some(Self::Stat),
+            "open" => Some(Self::Open),
             _ => None,
         }
     }
@@ -59,6 +62,7 @@ impl FsOpCode {
             Self::CopyFile => "copy_file",
             Self::Rename => "rename",
             Self::Stat => "stat",
+            Self::Open => "open",
         }
     }
 
@@ -74,8 +78,8 @@ fn dispatch_library_native(
     env: &mut Environment,
     location: &Location,
 ) -> Result<Value, ZekkenError> {
-    let native = match env.lookup_ref(lib_name) {
-        Some(Value::Object(map)) => match map.get(method_name) {
+    let native = match env.lookup_ref(lib_name).and_then(Value::as_object) {
+        Some(map) => match map.get(method_name) {
             Some(Value::NativeFunction(native)) => Some(native.clone()),
             _ => None,
         },
@@ -90,5 +94,5 @@ fn dispatch_library_native(
         )
     })?;
 
-    native(args).map_err(|msg| ZekkenError::runtime(&msg, location.line, location.column, None))
+    call_native(&*native, args, location.line, location.column).map_err(|msg| with_native_code(ZekkenError::runtime(&msg, location.line, location.column, None)))
 }
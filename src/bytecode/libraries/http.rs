@@ -1,6 +1,7 @@
 use crate::ast::Location;
 use crate::environment::{Environment, Value};
 use crate::errors::ZekkenError;
+use indexmap::IndexMap;
 
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::Duration;
@@ -72,7 +73,7 @@ impl HttpOpCode {
                     }
                 };
 
-                let headers = if args.len() >= 2 && !matches!(args[1], Value::Void) {
+                let headers = if args.len() >= 2 && !matches!(args[1], Value::Void | Value::Null) {
                     obj_string_entries(&args[1], location)?
                 } else {
                     Vec::new()
@@ -81,7 +82,7 @@ impl HttpOpCode {
                 let timeout_ms = if args.len() == 3 {
                     match &args[2] {
                         Value::Int(i) => Some(*i),
-                        Value::Void => None,
+                        Value::Void | Value::Null => None,
                         _ => {
                             return Err(ZekkenError::type_error(
                                 "http.get timeout_ms must be int",
@@ -123,12 +124,18 @@ fn value_type_name(value: &Value) -> &'static str {
         Value::String(_) => "string",
         Value::Boolean(_) => "bool",
         Value::Array(_) => "array",
-        Value::Object(_) => "object",
+        Value::Object(_) | Value::Library(_) | Value::Enum { .. } => "object",
         Value::Function(_) => "function",
         Value::NativeFunction(_) => "native function",
         Value::Complex { .. } => "complex",
+        Value::Decimal(_) => "dec",
         Value::Vector(_) => "vector",
         Value::Matrix(_) => "matrix",
+        Value::Quantity(_) => "quantity",
+        Value::BigFloat(_) => "bigfloat",
+        Value::Expression(_) => "expression",
+        Value::Resource(_) => "resource",
+        Value::Null => "null",
         Value::Void => "void",
     }
 }
@@ -204,36 +211,16 @@ fn url_decode_str(s: &str, location: &Location) -> Result<String, ZekkenError> {
 fn obj_string_entries(v: &Value, location: &Location) -> Result<Vec<(String, String)>, ZekkenError> {
     match v {
         Value::Object(map) => {
-            let mut out: Vec<(String, String)> = Vec::new();
-            if let Some(Value::Array(keys)) = map.get("__keys__") {
-                for kv in keys {
-                    if let Value::String(k) = kv {
-                        if k == "__keys__" {
-                            continue;
-                        }
-                        if let Some(val) = map.get(k) {
-                            let s = match val {
-                                Value::String(s) => s.clone(),
-                                other => other.to_string(),
-                            };
-                            out.push((k.clone(), s));
-                        }
-                    }
-                }
-                return Ok(out);
-            }
-            let mut keys: Vec<&String> = map.keys().filter(|k| k.as_str() != "__keys__").collect();
-            keys.sort_unstable();
-            for k in keys {
-                if let Some(val) = map.get(k) {
+            Ok(map
+                .iter()
+                .map(|(k, val)| {
                     let s = match val {
                         Value::String(s) => s.clone(),
                         other => other.to_string(),
                     };
-                    out.push((k.clone(), s));
-                }
-            }
-            Ok(out)
+                    (k.clone(), s)
+                })
+                .collect())
         }
         _ => Err(ZekkenError::type_error(
             "Expected object",
@@ -246,15 +233,7 @@ fn obj_string_entries(v: &Value, location: &Location) -> Result<Vec<(String, Str
 }
 
 fn obj_from_pairs(pairs: Vec<(String, String)>) -> Value {
-    use hashbrown::HashMap;
-    let mut obj = HashMap::with_capacity(pairs.len() + 1);
-    let mut keys = Vec::with_capacity(pairs.len());
-    for (k, v) in pairs {
-        keys.push(Value::String(k.clone()));
-        obj.insert(k, Value::String(v));
-    }
-    obj.insert("__keys__".to_string(), Value::Array(keys));
-    Value::Object(obj)
+    Value::Object(pairs.into_iter().map(|(k, v)| (k, Value::String(v))).collect())
 }
 
 fn build_query(obj: &Value, location: &Location) -> Result<String, ZekkenError> {
@@ -285,27 +264,13 @@ fn parse_query(qs: &str, location: &Location) -> Result<Value, ZekkenError> {
 }
 
 fn response_obj(url: String, status: i64, headers: Vec<(String, String)>, body: String) -> Value {
-    use hashbrown::HashMap;
     let ok = status >= 200 && status < 300;
-    let mut obj = HashMap::with_capacity(6);
-    let mut keys = Vec::with_capacity(5);
-
-    keys.push(Value::String("url".to_string()));
+    let mut obj = IndexMap::with_capacity(5);
     obj.insert("url".to_string(), Value::String(url));
-
-    keys.push(Value::String("status".to_string()));
     obj.insert("status".to_string(), Value::Int(status));
-
-    keys.push(Value::String("ok".to_string()));
     obj.insert("ok".to_string(), Value::Boolean(ok));
-
-    keys.push(Value::String("headers".to_string()));
     obj.insert("headers".to_string(), obj_from_pairs(headers));
-
-    keys.push(Value::String("body".to_string()));
     obj.insert("body".to_string(), Value::String(body));
-
-    obj.insert("__keys__".to_string(), Value::Array(keys));
     Value::Object(obj)
 }
 
@@ -163,12 +163,18 @@ fn value_type_name(value: &Value) -> &'static str {
         Value::String(_) => "string",
         Value::Boolean(_) => "bool",
         Value::Array(_) => "array",
-        Value::Object(_) => "object",
+        Value::Object(_) | Value::Library(_) | Value::Enum { .. } => "object",
         Value::Function(_) => "function",
         Value::NativeFunction(_) => "native function",
         Value::Complex { .. } => "complex",
+        Value::Decimal(_) => "dec",
         Value::Vector(_) => "vector",
         Value::Matrix(_) => "matrix",
+        Value::Quantity(_) => "quantity",
+        Value::BigFloat(_) => "bigfloat",
+        Value::Expression(_) => "expression",
+        Value::Resource(_) => "resource",
+        Value::Null => "null",
         Value::Void => "void",
     }
 }
@@ -1,9 +1,13 @@
 use crate::ast::*;
-use crate::environment::{Environment, FunctionValue, Value};
+use crate::environment::{sort, decimal_binary, ensure_array_slot, enum_value, int_binary, int_range, string_char_at, switch_case_matches, is_internal_entry, matrix_from, numeric_binary, repeat_string, vector_from, vector_index, Environment, ControlFlow, FunctionValue, Value};
+use crate::config::IterationCap;
 use crate::errors::{push_error, ZekkenError};
-use crate::libraries::load_library;
+use crate::eval::include::{included_bindings, keep_declarations, library_names, trace as trace_include};
+use crate::eval::expression::argument_type_error;
+use crate::eval::pattern::match_pattern;
+use crate::libraries::{arrays, bigfloat, calc, common::{call_native, with_native_code}, linalg, load_library, units};
 use crate::parser::Parser;
-use hashbrown::HashMap;
+use indexmap::IndexMap;
 use std::path::Path;
 
 pub(crate) mod inst;
@@ -31,9 +35,20 @@ fn eval_binary(left: &Value, right: &Value, op: &str, location: &Location) -> Re
         Ok(Value::Boolean(cmp(l, r)))
     }
 
+    if let Some(value) = numeric_binary(op, left, right) {
+        return Ok(value);
+    }
+    if let Some(result) = decimal_binary(op, left, right).or_else(|| units::binary(op, left, right))
+        .or_else(|| bigfloat::binary(op, left, right))
+        .or_else(|| calc::binary(op, left, right))
+        .or_else(|| linalg::binary(op, left, right)) {
+        return result.map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None));
+    }
+
     match op {
         "+" => match (left, right) {
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l + r)),
+            (Value::Int(l), Value::Int(r)) => int_binary("+", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l + r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 + r)),
             (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l + *r as f64)),
@@ -55,17 +70,22 @@ fn eval_binary(left: &Value, right: &Value, op: &str, location: &Location) -> Re
             )),
         },
         "-" => match (left, right) {
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l - r)),
+            (Value::Int(l), Value::Int(r)) => int_binary("-", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l - r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 - r)),
             (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l - *r as f64)),
             _ => Err(ZekkenError::type_error("Invalid operand types for subtraction", "number", "non-number", location.line, location.column)),
         },
         "*" => match (left, right) {
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l * r)),
+            (Value::Int(l), Value::Int(r)) => int_binary("*", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l * r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 * r)),
             (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l * *r as f64)),
+            (Value::String(text), Value::Int(count)) | (Value::Int(count), Value::String(text)) => {
+                repeat_string(text, *count).map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None))
+            }
             _ => Err(ZekkenError::type_error("Invalid operand types for multiplication", "number", "non-number", location.line, location.column)),
         },
         "/" => match (left, right) {
@@ -73,7 +93,8 @@ fn eval_binary(left: &Value, right: &Value, op: &str, location: &Location) -> Re
             (Value::Float(_), Value::Float(r)) if *r == 0.0 => Err(ZekkenError::runtime("Division by zero", location.line, location.column, Some("division by zero"))),
             (Value::Int(_), Value::Float(r)) if *r == 0.0 => Err(ZekkenError::runtime("Division by zero", location.line, location.column, Some("division by zero"))),
             (Value::Float(_), Value::Int(r)) if *r == 0 => Err(ZekkenError::runtime("Division by zero", location.line, location.column, Some("division by zero"))),
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l / r)),
+            (Value::Int(l), Value::Int(r)) => int_binary("/", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l / r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 / r)),
             (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l / *r as f64)),
@@ -81,12 +102,17 @@ fn eval_binary(left: &Value, right: &Value, op: &str, location: &Location) -> Re
         },
         "%" => match (left, right) {
             (Value::Int(_), Value::Int(r)) if *r == 0 => Err(ZekkenError::runtime("Modulo by zero", location.line, location.column, Some("modulo by zero"))),
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l % r)),
+            (Value::Int(l), Value::Int(r)) => int_binary("%", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             _ => Err(ZekkenError::type_error("Invalid operand types for modulo", "int", "non-int", location.line, location.column)),
         },
+        ".." => int_range(left, right)
+            .map(|range| Value::Array(range.map(Value::Int).collect()))
+            .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
         "in" => match (left, right) {
             (_, Value::Array(arr)) => Ok(Value::Boolean(arr.iter().any(|v| compare_values(left, v)))),
             (Value::String(key), Value::Object(obj)) => Ok(Value::Boolean(obj.contains_key(key))),
+            (Value::String(key), Value::Library(lib) | Value::Enum { payload: lib, .. }) => Ok(Value::Boolean(lib.contains_key(key))),
             (Value::String(needle), Value::String(haystack)) => Ok(Value::Boolean(haystack.contains(needle))),
             _ => Err(ZekkenError::type_error(
                 "Invalid 'in' operation",
@@ -152,22 +178,14 @@ fn get_at_path(current: &Value, path: &[MemberKey]) -> Result<Value, String> {
             let next = arr.get(*i).ok_or_else(|| format!("Array index {} out of bounds", i))?;
             get_at_path(next, &path[1..])
         }
-        (MemberKey::Prop(p), Value::Object(map)) => {
-            let next = map.get(p).ok_or_else(|| format!("Property '{}' not found", p))?;
-            get_at_path(next, &path[1..])
-        }
-        (MemberKey::Index(i), Value::Object(map)) => {
-            let key = match map.get("__keys__") {
-                Some(Value::Array(keys)) => match keys.get(*i) {
-                    Some(Value::String(k)) => k,
-                    _ => return Err(format!("Object index {} out of bounds", i)),
-                },
-                _ => return Err("Object does not support numeric indexing".to_string()),
+        (key, value) => {
+            let map = value.as_object().ok_or_else(|| "Invalid member access".to_string())?;
+            let next = match key {
+                MemberKey::Prop(p) => map.get(p).ok_or_else(|| format!("Property '{}' not found", p))?,
+                MemberKey::Index(i) => map.get_index(*i).map(|(_, value)| value).ok_or_else(|| format!("Object index {} out of bounds", i))?,
             };
-            let next = map.get(key).ok_or_else(|| format!("Property '{}' not found", key))?;
             get_at_path(next, &path[1..])
         }
-        _ => Err("Invalid member access".to_string()),
     }
 }
 
@@ -176,16 +194,18 @@ fn assign_at_path(current: &mut Value, path: &[MemberKey], value: Value) -> Resu
         *current = value;
         return Ok(());
     }
+    current.unshare();
 
     match (&path[0], current) {
         (MemberKey::Index(i), Value::Array(arr)) => {
-            if *i >= arr.len() {
-                return Err(format!("Array index {} out of bounds", i));
-            }
             if path.len() == 1 {
+                ensure_array_slot(arr, *i)?;
                 arr[*i] = value;
                 return Ok(());
             }
+            if *i >= arr.len() {
+                return Err(format!("Array index {} out of bounds", i));
+            }
             assign_at_path(&mut arr[*i], &path[1..], value)
         }
         (MemberKey::Prop(p), Value::Object(map)) => {
@@ -197,18 +217,11 @@ fn assign_at_path(current: &mut Value, path: &[MemberKey], value: Value) -> Resu
             assign_at_path(next, &path[1..], value)
         }
         (MemberKey::Index(i), Value::Object(map)) => {
-            let key = match map.get("__keys__") {
-                Some(Value::Array(keys)) => match keys.get(*i) {
-                    Some(Value::String(k)) => k.clone(),
-                    _ => return Err(format!("Object index {} out of bounds", i)),
-                },
-                _ => return Err("Object does not support numeric indexing".to_string()),
-            };
+            let (_, next) = map.get_index_mut(*i).ok_or_else(|| format!("Object index {} out of bounds", i))?;
             if path.len() == 1 {
-                map.insert(key, value);
+                *next = value;
                 return Ok(());
             }
-            let next = map.get_mut(&key).ok_or_else(|| format!("Property '{}' not found", key))?;
             assign_at_path(next, &path[1..], value)
         }
         _ => Err("Invalid member assignment target".to_string()),
@@ -425,7 +438,7 @@ fn eval_assignment_native(assign: &AssignExpr, env: &mut Environment) -> Result<
                             value_type_name(other),
                             assign.location.line,
                             assign.location.column,
-                        ))
+                        ).with_value(other))
                     }
                     None => {
                         return Err(ZekkenError::reference_with_span(
@@ -507,7 +520,7 @@ fn eval_member_native(member: &MemberExpr, env: &mut Environment) -> Result<Valu
         match expr {
             Expr::Member(m) => {
                 let root = collect_chain(m.object.as_ref(), out)?;
-                out.push((m.property.as_ref(), m.is_method));
+                out.push((m.property.as_ref(), m.computed));
                 Some(root)
             }
             Expr::Identifier(id) => Some(id),
@@ -517,7 +530,7 @@ fn eval_member_native(member: &MemberExpr, env: &mut Environment) -> Result<Valu
 
     let mut chain = Vec::new();
     if let Some(root_ident) = collect_chain(member.object.as_ref(), &mut chain) {
-        chain.push((member.property.as_ref(), member.is_method));
+        chain.push((member.property.as_ref(), member.computed));
 
         let supports_fast_chain = chain.iter().all(|prop| {
             matches!(
@@ -526,7 +539,10 @@ fn eval_member_native(member: &MemberExpr, env: &mut Environment) -> Result<Valu
             )
         });
 
-        if supports_fast_chain {
+        'chain: {
+            if !supports_fast_chain {
+                break 'chain;
+            }
             let mut current = env.lookup_ref(&root_ident.name).ok_or_else(|| {
                 ZekkenError::reference_with_span(
                     &format!("Variable '{}' not found", root_ident.name),
@@ -619,7 +635,8 @@ fn eval_member_native(member: &MemberExpr, env: &mut Environment) -> Result<Valu
                             ));
                         }
                     }
-                    Value::Object(map) => {
+                    Value::Object(_) | Value::Library(_) | Value::Enum { .. } => {
+                        let Some(map) = current.as_object() else { unreachable!("objects, libraries and enum variants have fields") };
                         if !computed {
                             // Dot access treats identifiers as literal keys.
                             let key = match prop {
@@ -687,67 +704,23 @@ fn eval_member_native(member: &MemberExpr, env: &mut Environment) -> Result<Valu
                                 })?,
                                 Value::Int(i) if i >= 0 => {
                                     let idx = i as usize;
-                                    let key = match map.get("__keys__") {
-                                        Some(Value::Array(keys)) => match keys.get(idx) {
-                                            Some(Value::String(key)) => key.clone(),
-                                            _ => {
-                                                return Err(ZekkenError::runtime(
-                                                    &format!("Object index {} out of bounds", idx),
-                                                    member.location.line,
-                                                    member.location.column,
-                                                    None,
-                                                ));
-                                            }
-                                        },
-                                        _ => {
-                                            return Err(ZekkenError::runtime(
-                                                "Object does not support numeric indexing",
-                                                member.location.line,
-                                                member.location.column,
-                                                None,
-                                            ));
-                                        }
-                                    };
-                                    map.get(&key).ok_or_else(|| {
-                                        ZekkenError::reference_with_span(
-                                            &format!("Property '{}' not found", key),
-                                            &key,
+                                    map.get_index(idx).map(|(_, value)| value).ok_or_else(|| {
+                                        ZekkenError::runtime(
+                                            &format!("Object index {} out of bounds", idx),
                                             member.location.line,
                                             member.location.column,
-                                            key.len().max(1),
+                                            None,
                                         )
                                     })?
                                 }
                                 Value::Float(f) if f >= 0.0 && f.fract() == 0.0 => {
                                     let idx = f as usize;
-                                    let key = match map.get("__keys__") {
-                                        Some(Value::Array(keys)) => match keys.get(idx) {
-                                            Some(Value::String(key)) => key.clone(),
-                                            _ => {
-                                                return Err(ZekkenError::runtime(
-                                                    &format!("Object index {} out of bounds", idx),
-                                                    member.location.line,
-                                                    member.location.column,
-                                                    None,
-                                                ));
-                                            }
-                                        },
-                                        _ => {
-                                            return Err(ZekkenError::runtime(
-                                                "Object does not support numeric indexing",
-                                                member.location.line,
-                                                member.location.column,
-                                                None,
-                                            ));
-                                        }
-                                    };
-                                    map.get(&key).ok_or_else(|| {
-                                        ZekkenError::reference_with_span(
-                                            &format!("Property '{}' not found", key),
-                                            &key,
+                                    map.get_index(idx).map(|(_, value)| value).ok_or_else(|| {
+                                        ZekkenError::runtime(
+                                            &format!("Object index {} out of bounds", idx),
                                             member.location.line,
                                             member.location.column,
-                                            key.len().max(1),
+                                            None,
                                         )
                                     })?
                                 }
@@ -763,6 +736,10 @@ fn eval_member_native(member: &MemberExpr, env: &mut Environment) -> Result<Valu
                             }
                         }
                     }
+                    // Indexing a string yields a new value, which the slow path builds.
+                    Value::String(_) if computed => break 'chain,
+                    Value::Vector(_) | Value::Matrix(_) | Value::Quantity(_) | Value::BigFloat(_) => break 'chain,
+                    Value::Function(_) | Value::NativeFunction(_) => break 'chain,
                     _ => {
                         return Err(ZekkenError::type_error(
                             "Invalid member access",
@@ -779,8 +756,19 @@ fn eval_member_native(member: &MemberExpr, env: &mut Environment) -> Result<Valu
     }
 
     let object = eval_expr_native(&member.object, env)?;
+    if let (Value::String(s), true) = (&object, member.computed) {
+        let index = eval_expr_native(member.property.as_ref(), env)?;
+        return string_char_at(s, &index)
+            .map(|ch| Value::String(ch.to_string()))
+            .map_err(|e| ZekkenError::runtime(&e, member.location.line, member.location.column, None));
+    }
+    if let (Value::Vector(_) | Value::Matrix(_), true) = (&object, member.computed) {
+        let index = eval_expr_native(member.property.as_ref(), env)?;
+        return vector_index(&object, &index)
+            .map_err(|e| ZekkenError::runtime(&e, member.location.line, member.location.column, None));
+    }
 
-    let key = if member.is_method {
+    let key = if member.computed {
         // Bracket/computed access: evaluate the key expression.
         let v = eval_expr_native(member.property.as_ref(), env)?;
         match v {
@@ -824,7 +812,9 @@ fn eval_member_native(member: &MemberExpr, env: &mut Environment) -> Result<Valu
             "last" => arr.last().cloned().ok_or_else(|| ZekkenError::runtime("Array is empty", member.location.line, member.location.column, None)),
             _ => Err(ZekkenError::type_error("Invalid member access", "array index or known array member", "other", member.location.line, member.location.column)),
         },
-        (Value::Object(map), MemberKey::Prop(prop)) => map.get(&prop).cloned().ok_or_else(|| {
+        (Value::Vector(v), MemberKey::Prop(prop)) if prop == "length" => Ok(Value::Int(v.len() as i64)),
+        (Value::Matrix(m), MemberKey::Prop(prop)) if prop == "length" => Ok(Value::Int(m.len() as i64)),
+        (Value::Function(func), MemberKey::Prop(prop)) => func.property(&prop).ok_or_else(|| {
             ZekkenError::reference_with_span(
                 &format!("Property '{}' not found", prop),
                 &prop,
@@ -833,37 +823,43 @@ fn eval_member_native(member: &MemberExpr, env: &mut Environment) -> Result<Valu
                 prop.len().max(1),
             )
         }),
-        (Value::Object(map), MemberKey::Index(i)) => {
-            let key = match map.get("__keys__") {
-                Some(Value::Array(keys)) => match keys.get(i) {
-                    Some(Value::String(s)) => s.clone(),
-                    _ => {
-                        return Err(ZekkenError::runtime(
-                            &format!("Object index {} out of bounds", i),
-                            member.location.line,
-                            member.location.column,
-                            None,
-                        ))
-                    }
-                },
-                _ => {
-                    return Err(ZekkenError::runtime(
-                        "Object does not support numeric indexing",
+        (object @ (Value::Quantity(_) | Value::BigFloat(_)), MemberKey::Prop(prop)) => object.library_property(&prop).ok_or_else(|| {
+            ZekkenError::reference_with_span(
+                &format!("Property '{}' not found", prop),
+                &prop,
+                member.location.line,
+                member.location.column,
+                prop.len().max(1),
+            )
+        }),
+        (Value::NativeFunction(_), MemberKey::Prop(_)) => Err(ZekkenError::type_error(
+            "Native functions have no name, arity or params",
+            "a function declared in Zekken",
+            "native function",
+            member.location.line,
+            member.location.column,
+        )),
+        (object @ (Value::Object(_) | Value::Library(_) | Value::Enum { .. }), key) => {
+            let Some(map) = object.as_object() else { unreachable!("objects, libraries and enum variants have fields") };
+            match key {
+                MemberKey::Prop(prop) => map.get(&prop).cloned().ok_or_else(|| {
+                    ZekkenError::reference_with_span(
+                        &format!("Property '{}' not found", prop),
+                        &prop,
+                        member.location.line,
+                        member.location.column,
+                        prop.len().max(1),
+                    )
+                }),
+                MemberKey::Index(i) => map.get_index(i).map(|(_, value)| value).cloned().ok_or_else(|| {
+                    ZekkenError::runtime(
+                        &format!("Object index {} out of bounds", i),
                         member.location.line,
                         member.location.column,
                         None,
-                    ))
-                }
-            };
-            map.get(&key).cloned().ok_or_else(|| {
-                ZekkenError::reference_with_span(
-                    &format!("Property '{}' not found", key),
-                    &key,
-                    member.location.line,
-                    member.location.column,
-                    key.len().max(1),
-                )
-            })
+                    )
+                }),
+            }
         }
         (_, _) => Err(ZekkenError::type_error(
             "Invalid member access",
@@ -881,6 +877,7 @@ fn eval_arg_hot_native(expr: &Expr, env: &mut Environment) -> Result<Value, Zekk
         Expr::IntLit(i) => Ok(Value::Int(i.value)),
         Expr::FloatLit(f) => Ok(Value::Float(f.value)),
         Expr::BoolLit(b) => Ok(Value::Boolean(b.value)),
+        Expr::NullLit(_) => Ok(Value::Null),
         Expr::Identifier(id) => {
             if let Some(v) = env.variables.get(&id.name).or_else(|| env.constants.get(&id.name)) {
                 return Ok(match v {
@@ -1015,77 +1012,127 @@ fn try_eval_math_call_native(
     }
 }
 
-fn eval_call_native(call: &CallExpr, env: &mut Environment) -> Result<Value, ZekkenError> {
-    #[inline]
-    fn builtin_requires_at(name: &str) -> bool {
-        matches!(name, "println" | "input" | "parse_json" | "queue")
-    }
-
-    if let Expr::Member(member) = call.callee.as_ref() {
-        let method_name = match member.property.as_ref() {
-            Expr::Identifier(id) => id.name.clone(),
-            Expr::StringLit(s) => s.value.clone(),
-            _ => {
-                return Err(ZekkenError::type_error(
-                    "Invalid method name",
-                    "identifier/string",
-                    "other",
-                    call.location.line,
-                    call.location.column,
-                ))
-            }
-        };
-
-        if let Expr::Identifier(object_ident) = member.object.as_ref() {
-            // Hot path for math library calls.
-            if object_ident.name == "math" {
-                if let Some(result) = try_eval_math_call_native(
-                    method_name.as_str(),
-                    &call.args,
-                    env,
-                    call.location.line,
-                    call.location.column,
-                ) {
-                    return result;
-                }
+fn eval_method_call_native(call: &MethodCallExpr, env: &mut Environment) -> Result<Value, ZekkenError> {
+    if let Expr::Identifier(object_ident) = call.object.as_ref() {
+        // Hot path for math library calls.
+        if object_ident.name == "math" {
+            if let Some(result) = try_eval_math_call_native(
+                call.method.as_str(),
+                &call.args,
+                env,
+                call.location.line,
+                call.location.column,
+            ) {
+                return result;
             }
+        }
 
-            // Hot path for object-backed native methods (queue, fs/os/math objects, etc.)
-            // Avoid cloning the entire object value just to reach a native function.
-            let native_member = match env.lookup_ref(&object_ident.name) {
-                Some(Value::Object(map)) => match map.get(&method_name) {
-                    Some(Value::NativeFunction(native)) => Some(native.clone()),
-                    _ => None,
-                },
+        // Hot path for object-backed native methods (queue, fs/os/math objects, etc.)
+        // Avoid cloning the entire object value just to reach a native function.
+        let native_member = match env.lookup_ref(&object_ident.name).and_then(Value::as_object) {
+            Some(map) => match map.get(&call.method) {
+                Some(Value::NativeFunction(native)) => Some(native.clone()),
                 _ => None,
-            };
-            if let Some(native) = native_member {
-                let args = eval_call_args_native(&call.args, env)?;
-                return native(args).map_err(|msg| {
-                    ZekkenError::runtime(&msg, call.location.line, call.location.column, None)
-                });
+            },
+            _ => None,
+        };
+        if let Some(native) = native_member {
+            let args = eval_call_args_native(&call.args, env)?;
+            if let Some(keyed) = arrays::keyed(&call.method).filter(|_| object_ident.name == "arrays") {
+                return keyed_native(keyed, &args, env, call.location.line, call.location.column);
             }
+            return call_native(&*native, args, call.location.line, call.location.column).map_err(|msg| {
+                with_native_code(ZekkenError::runtime(&msg, call.location.line, call.location.column, None))
+            });
         }
+    }
 
-        let args = eval_call_args_native(&call.args, env)?;
-        let var_name = match member.object.as_ref() {
-            Expr::Identifier(id) => Some(id.name.as_str()),
-            _ => None,
-        };
+    let args = eval_call_args_native(&call.args, env)?;
+    let var_name = match call.object.as_ref() {
+        Expr::Identifier(id) => Some(id.name.as_str()),
+        _ => None,
+    };
 
-        if let Expr::Identifier(id) = member.object.as_ref() {
-            if let Some(obj_owned) = env.lookup_ref(&id.name).cloned() {
-                return obj_owned
-                    .call_method(&method_name, args, Some(env), Some(id.name.as_str()))
-                    .map_err(|msg| ZekkenError::runtime(&msg, call.location.line, call.location.column, None));
-            }
+    let object = match call.object.as_ref() {
+        Expr::Identifier(id) => match env.lookup_ref(&id.name).cloned() {
+            Some(object) => object,
+            None => eval_expr_native(&call.object, env)?,
+        },
+        _ => eval_expr_native(&call.object, env)?,
+    };
+
+    // A function stored in an object property is called like a method.
+    if let Some(map) = object.as_object() {
+        if let Some(Value::Function(func)) = map.get(&call.method) {
+            return call_function_native(func, args, &call.args, env, call.location.line, call.location.column);
         }
+    }
 
-        let object = eval_expr_native(&member.object, env)?;
+    if let (Some(Method::SortBy), Value::Array(items)) = (call.method_id, &object) {
+        return sort_by_native(items, &args, env, call.location.line, call.location.column);
+    }
+
+    object
+        .call_resolved_method(call.method_id, &call.method, args, Some(env), var_name)
+        .map_err(|msg| with_native_code(ZekkenError::runtime(&msg, call.location.line, call.location.column, None)))
+}
+
+// `function`, which a method or library call was passed, applied to each of
+// `items` in turn.
+fn call_for_each_item(function: &Value, items: &[Value], env: &mut Environment, line: usize, column: usize) -> Result<Vec<Value>, ZekkenError> {
+    items
+        .iter()
+        .map(|item| match function {
+            Value::Function(func) => call_function_native(func, vec![item.clone()], &[], env, line, column),
+            Value::NativeFunction(native) => call_native(&**native, vec![item.clone()], line, column)
+                .map_err(|msg| with_native_code(ZekkenError::runtime(&msg, line, column, None))),
+            _ => unreachable!("callers only pass functions"),
+        })
+        .collect()
+}
 
-        return object
-            .call_method(&method_name, args, Some(env), var_name)
-            .map_err(|msg| ZekkenError::runtime(&msg, call.location.line, call.location.column, None));
+// `items.sort_by => |key_fn, descending|`: the key function may be written in
+// Zekken, so it runs here before the keys are sorted.
+pub(super) fn sort_by_native(items: &[Value], args: &[Value], env: &mut Environment, line: usize, column: usize) -> Result<Value, ZekkenError> {
+    let (key_fn, descending) = sort::sort_by_args(args).map_err(|msg| ZekkenError::runtime(&msg, line, column, None))?;
+    let keys = call_for_each_item(key_fn, items, env, line, column)?;
+    sort::by_keys(items, keys, descending).map_err(|msg| ZekkenError::runtime(&msg, line, column, None))
+}
+
+// `arrays.group_by => |items, key_fn|` and the like, whose functions may be
+// written in Zekken.
+pub(super) fn keyed_native(keyed: &arrays::Keyed, args: &[Value], env: &mut Environment, line: usize, column: usize) -> Result<Value, ZekkenError> {
+    let to_error = |msg: String| with_native_code(ZekkenError::runtime(&msg, line, column, None));
+    let (items, functions) = keyed.split(args).map_err(to_error)?;
+    let results = functions
+        .iter()
+        .map(|function| call_for_each_item(function, items, env, line, column))
+        .collect::<Result<Vec<_>, _>>()?;
+    keyed.aggregate(items, results).map_err(to_error)
+}
+
+fn eval_call_native(call: &CallExpr, env: &mut Environment) -> Result<Value, ZekkenError> {
+    #[inline]
+    fn builtin_requires_at(name: &str) -> bool {
+        matches!(name, "println" | "print" | "flush" | "input" | "parse_json" | "queue" | "memstats" | "set_precision" | "format" | "diff")
+    }
+
+    // Named arguments are matched to the callee's parameters by name, so it
+    // has to be a function written in Zekken.
+    if !call.arg_names.is_empty() {
+        let args = eval_call_args_native(&call.args, env)?;
+        let Value::Function(func) = eval_expr_native(&call.callee, env)? else {
+            return Err(ZekkenError::runtime(
+                "Named arguments can only be passed to functions declared in Zekken",
+                call.location.line,
+                call.location.column,
+                None,
+            ));
+        };
+        let func = func
+            .for_named_call(&call.arg_names)
+            .map_err(|msg| ZekkenError::runtime(&msg, call.location.line, call.location.column, Some("argument mismatch")))?;
+        return call_function_native(&func, args, &call.args, env, call.location.line, call.location.column);
     }
 
     if let Expr::Identifier(id) = call.callee.as_ref() {
@@ -1104,25 +1151,25 @@ fn eval_call_native(call: &CallExpr, env: &mut Environment) -> Result<Value, Zek
             Some(Value::Function(f)) => Some(f.clone()),
             _ => None,
         } {
-            return call_function_native(&func, args, env, call.location.line, call.location.column);
+            return call_function_native(&func, args, &call.args, env, call.location.line, call.location.column);
         }
         if let Some(native) = match env.variables.get(&id.name) {
             Some(Value::NativeFunction(n)) => Some(n.clone()),
             _ => None,
         } {
-            return native(args).map_err(|msg| ZekkenError::runtime(&msg, call.location.line, call.location.column, None));
+            return call_native(&*native, args, call.location.line, call.location.column).map_err(|msg| with_native_code(ZekkenError::runtime(&msg, call.location.line, call.location.column, None)));
         }
         if let Some(func) = match env.constants.get(&id.name) {
             Some(Value::Function(f)) => Some(f.clone()),
             _ => None,
         } {
-            return call_function_native(&func, args, env, call.location.line, call.location.column);
+            return call_function_native(&func, args, &call.args, env, call.location.line, call.location.column);
         }
         if let Some(native) = match env.constants.get(&id.name) {
             Some(Value::NativeFunction(n)) => Some(n.clone()),
             _ => None,
         } {
-            return native(args).map_err(|msg| ZekkenError::runtime(&msg, call.location.line, call.location.column, None));
+            return call_native(&*native, args, call.location.line, call.location.column).map_err(|msg| with_native_code(ZekkenError::runtime(&msg, call.location.line, call.location.column, None)));
         }
 
         let callee = env.lookup_ref(&id.name).cloned().ok_or_else(|| {
@@ -1136,51 +1183,59 @@ fn eval_call_native(call: &CallExpr, env: &mut Environment) -> Result<Value, Zek
         })?;
 
         return match callee {
-            Value::Function(func) => call_function_native(&func, args, env, call.location.line, call.location.column),
-            Value::NativeFunction(native) => native(args).map_err(|msg| ZekkenError::runtime(&msg, call.location.line, call.location.column, None)),
+            Value::Function(func) => call_function_native(&func, args, &call.args, env, call.location.line, call.location.column),
+            Value::NativeFunction(native) => call_native(&*native, args, call.location.line, call.location.column).map_err(|msg| with_native_code(ZekkenError::runtime(&msg, call.location.line, call.location.column, None))),
             other => Err(ZekkenError::type_error(
                 "Attempted to call a non-callable value",
                 "function or native function",
                 value_type_name(&other),
                 call.location.line,
                 call.location.column,
-            )),
+            ).with_value(&other)),
         };
     }
 
     let args = eval_call_args_native(&call.args, env)?;
     let callee = eval_expr_native(&call.callee, env)?;
     match callee {
-        Value::Function(func) => call_function_native(&func, args, env, call.location.line, call.location.column),
-        Value::NativeFunction(native) => native(args).map_err(|msg| ZekkenError::runtime(&msg, call.location.line, call.location.column, None)),
+        Value::Function(func) => call_function_native(&func, args, &call.args, env, call.location.line, call.location.column),
+        Value::NativeFunction(native) => call_native(&*native, args, call.location.line, call.location.column).map_err(|msg| with_native_code(ZekkenError::runtime(&msg, call.location.line, call.location.column, None))),
         other => Err(ZekkenError::type_error(
             "Attempted to call a non-callable value",
             "function or native function",
             value_type_name(&other),
             call.location.line,
             call.location.column,
-        )),
+        ).with_value(&other)),
     }
 }
 
 pub(super) fn call_function_native(
     func: &FunctionValue,
     args: Vec<Value>,
+    arg_exprs: &[Box<Expr>],
     env: &mut Environment,
     _line: usize,
     _column: usize,
 ) -> Result<Value, ZekkenError> {
+    let Some(_depth) = crate::config::CallDepth::enter() else {
+        return Err(crate::config::call_depth_error(_line, _column));
+    };
+    let _call = crate::stats::enter_call();
+    let _span = crate::trace::enter(&func.name, _line);
     if args.len() > func.params.len() {
         return Err(ZekkenError::runtime(
-            &format!("Expected {} arguments but got {}", func.params.len(), args.len()),
+            &format!("Expected {} arguments for '{}' but got {}", func.params.len(), func.name, args.len()),
             _line,
             _column,
             Some("argument mismatch"),
         ));
     }
 
+    env.sync_shared();
     if func.needs_parent {
-        let mut function_env = Environment::new_with_parent(env.clone());
+        let mut function_env = Environment::new_call_scope(env, func.params.len());
+        func.bind_closure(&mut function_env);
         for (idx, param) in func.params.iter().enumerate() {
             let value = if let Some(arg) = args.get(idx) {
                 arg.clone()
@@ -1188,38 +1243,39 @@ pub(super) fn call_function_native(
                 eval_expr_native(default_expr, &mut function_env)?
             } else {
                 return Err(ZekkenError::runtime(
-                    &format!("Missing required argument '{}'", param.ident),
+                    &format!("Missing required argument '{}' for '{}'", param.ident, func.name),
                     _line,
                     _column,
                     Some("argument mismatch"),
                 ));
             };
             if !check_value_type(&value, &param.type_) {
-                return Err(ZekkenError::type_error(
-                    &format!("Type mismatch for parameter '{}'", param.ident),
-                    &format!("{:?}", param.type_),
-                    value_type_name(&value),
-                    _line,
-                    _column,
-                ));
+                let at = arg_exprs.get(idx).map_or(Location { line: _line, column: _column }, |arg| compiler::expr_location(arg));
+                return Err(argument_type_error(func, param, &value, at));
             }
-            function_env.declare_ref_typed(param.ident.as_str(), value, param.type_, false);
+            function_env.declare_ref_typed(param.ident.as_str(), value, param.type_.clone(), false);
         }
         let result = if let Some(insts) = func.compiled_insts.as_deref() {
-            run_insts(insts, func.compiled_reg_count, &mut function_env)?
+            run_insts(insts, func.compiled_reg_count, &mut function_env)
         } else {
-            eval_contents_native(func.body.as_ref(), &mut function_env)?
+            eval_contents_native(func.body.as_ref(), &mut function_env).map(|last| function_env.take_return().or(last))
+        };
+        let result = match run_deferred(&mut function_env) {
+            Some(error) if result.is_ok() => Err(error),
+            _ => result,
         };
-        let out = result.unwrap_or(Value::Void);
-        if let Some(ret_ty) = func.return_type {
-            if !check_value_type(&out, &ret_ty) {
+        function_env.sync_shared();
+        env.reload_shared();
+        let out = result?.unwrap_or(Value::Void);
+        if let Some(ret_ty) = &func.return_type {
+            if !check_value_type(&out, ret_ty) {
                 return Err(ZekkenError::type_error(
                     "Type mismatch in function return value",
                     &format!("{:?}", ret_ty),
                     value_type_name(&out),
                     _line,
                     _column,
-                ));
+                ).with_value(&out));
             }
         }
         return Ok(out);
@@ -1228,11 +1284,17 @@ pub(super) fn call_function_native(
     let mut function_env = Environment::take_pooled_scope(func.params.len() + func.captures.len() + 8);
     if !func.captures.is_empty() {
         for capture in func.captures.iter() {
-            if let Some(v) = func.capture_values.get(capture).or_else(|| env.lookup_ref(capture)) {
+            if *capture == *func.name && !func.capture_values.contains_key(capture) {
+                // A function calling itself, even where the caller cannot see it.
+                function_env.declare_ref(capture.as_str(), Value::Function(func.clone()), false);
+            } else if let Some(v) = env.lookup_for_call(capture, &func.capture_values) {
                 function_env.declare_ref(capture.as_str(), clone_value_hot(v), false);
             }
         }
     }
+    if let Some(closure) = &func.closure {
+        function_env.enter_closure(closure);
+    }
 
     let bind_result = (|| -> Result<(), ZekkenError> {
         for (idx, param) in func.params.iter().enumerate() {
@@ -1242,22 +1304,17 @@ pub(super) fn call_function_native(
                 eval_expr_native(default_expr, &mut function_env)?
             } else {
                 return Err(ZekkenError::runtime(
-                    &format!("Missing required argument '{}'", param.ident),
+                    &format!("Missing required argument '{}' for '{}'", param.ident, func.name),
                     _line,
                     _column,
                     Some("argument mismatch"),
                 ));
             };
             if !check_value_type(&value, &param.type_) {
-                return Err(ZekkenError::type_error(
-                    &format!("Type mismatch for parameter '{}'", param.ident),
-                    &format!("{:?}", param.type_),
-                    value_type_name(&value),
-                    _line,
-                    _column,
-                ));
+                let at = arg_exprs.get(idx).map_or(Location { line: _line, column: _column }, |arg| compiler::expr_location(arg));
+                return Err(argument_type_error(func, param, &value, at));
             }
-            function_env.declare_ref_typed(param.ident.as_str(), value, param.type_, false);
+            function_env.declare_ref_typed(param.ident.as_str(), value, param.type_.clone(), false);
         }
         Ok(())
     })();
@@ -1270,21 +1327,27 @@ pub(super) fn call_function_native(
     let result = if let Some(insts) = func.compiled_insts.as_deref() {
         run_insts(insts, func.compiled_reg_count, &mut function_env)
     } else {
-        eval_contents_native(func.body.as_ref(), &mut function_env)
+        eval_contents_native(func.body.as_ref(), &mut function_env).map(|last| function_env.take_return().or(last))
+    };
+    let result = match run_deferred(&mut function_env) {
+        Some(error) if result.is_ok() => Err(error),
+        _ => result,
     };
+    function_env.sync_shared();
+    env.reload_shared();
     let out = match result {
         Ok(v) => Ok(v.unwrap_or(Value::Void)),
         Err(e) => Err(e),
     }.and_then(|v| {
-        if let Some(ret_ty) = func.return_type {
-            if !check_value_type(&v, &ret_ty) {
+        if let Some(ret_ty) = &func.return_type {
+            if !check_value_type(&v, ret_ty) {
                 return Err(ZekkenError::type_error(
                     "Type mismatch in function return value",
                     &format!("{:?}", ret_ty),
                     value_type_name(&v),
                     _line,
                     _column,
-                ));
+                ).with_value(&v));
             }
         }
         Ok(v)
@@ -1299,13 +1362,19 @@ pub(super) fn call_function_native_small(
     arg_regs: &[Reg; 3],
     regs: &[Value],
     env: &mut Environment,
-    line: usize,
-    column: usize,
+    location: &Location,
+    arg_locations: &[Location],
 ) -> Result<Value, ZekkenError> {
+    let Some(_depth) = crate::config::CallDepth::enter() else {
+        return Err(crate::config::call_depth_error(location.line, location.column));
+    };
+    let _call = crate::stats::enter_call();
+    let _span = crate::trace::enter(&func.name, location.line);
+    let (line, column) = (location.line, location.column);
     let argc = argc as usize;
     if argc > func.params.len() {
         return Err(ZekkenError::runtime(
-            &format!("Expected {} arguments but got {}", func.params.len(), argc),
+            &format!("Expected {} arguments for '{}' but got {}", func.params.len(), func.name, argc),
             line,
             column,
             Some("argument mismatch"),
@@ -1314,8 +1383,10 @@ pub(super) fn call_function_native_small(
 
     let bind_value = |idx: usize| clone_value_hot(&regs[arg_regs[idx]]);
 
+    env.sync_shared();
     if func.needs_parent {
-        let mut function_env = Environment::new_with_parent(env.clone());
+        let mut function_env = Environment::new_call_scope(env, func.params.len());
+        func.bind_closure(&mut function_env);
         for (idx, param) in func.params.iter().enumerate() {
             let value = if idx < argc {
                 bind_value(idx)
@@ -1323,38 +1394,39 @@ pub(super) fn call_function_native_small(
                 eval_expr_native(default_expr, &mut function_env)?
             } else {
                 return Err(ZekkenError::runtime(
-                    &format!("Missing required argument '{}'", param.ident),
+                    &format!("Missing required argument '{}' for '{}'", param.ident, func.name),
                     line,
                     column,
                     Some("argument mismatch"),
                 ));
             };
             if !check_value_type(&value, &param.type_) {
-                return Err(ZekkenError::type_error(
-                    &format!("Type mismatch for parameter '{}'", param.ident),
-                    &format!("{:?}", param.type_),
-                    value_type_name(&value),
-                    line,
-                    column,
-                ));
+                let at = arg_locations.get(idx).unwrap_or(location).clone();
+                return Err(argument_type_error(func, param, &value, at));
             }
-            function_env.declare_ref_typed(param.ident.as_str(), value, param.type_, false);
+            function_env.declare_ref_typed(param.ident.as_str(), value, param.type_.clone(), false);
         }
         let result = if let Some(insts) = func.compiled_insts.as_deref() {
-            run_insts(insts, func.compiled_reg_count, &mut function_env)?
+            run_insts(insts, func.compiled_reg_count, &mut function_env)
         } else {
-            eval_contents_native(func.body.as_ref(), &mut function_env)?
+            eval_contents_native(func.body.as_ref(), &mut function_env).map(|last| function_env.take_return().or(last))
+        };
+        let result = match run_deferred(&mut function_env) {
+            Some(error) if result.is_ok() => Err(error),
+            _ => result,
         };
-        let out = result.unwrap_or(Value::Void);
-        if let Some(ret_ty) = func.return_type {
-            if !check_value_type(&out, &ret_ty) {
+        function_env.sync_shared();
+        env.reload_shared();
+        let out = result?.unwrap_or(Value::Void);
+        if let Some(ret_ty) = &func.return_type {
+            if !check_value_type(&out, ret_ty) {
                 return Err(ZekkenError::type_error(
                     "Type mismatch in function return value",
                     &format!("{:?}", ret_ty),
                     value_type_name(&out),
                     line,
                     column,
-                ));
+                ).with_value(&out));
             }
         }
         return Ok(out);
@@ -1363,11 +1435,17 @@ pub(super) fn call_function_native_small(
     let mut function_env = Environment::take_pooled_scope(func.params.len() + func.captures.len() + 8);
     if !func.captures.is_empty() {
         for capture in func.captures.iter() {
-            if let Some(v) = func.capture_values.get(capture).or_else(|| env.lookup_ref(capture)) {
+            if *capture == *func.name && !func.capture_values.contains_key(capture) {
+                // A function calling itself, even where the caller cannot see it.
+                function_env.declare_ref(capture.as_str(), Value::Function(func.clone()), false);
+            } else if let Some(v) = env.lookup_for_call(capture, &func.capture_values) {
                 function_env.declare_ref(capture.as_str(), clone_value_hot(v), false);
             }
         }
     }
+    if let Some(closure) = &func.closure {
+        function_env.enter_closure(closure);
+    }
 
     let bind_result = (|| -> Result<(), ZekkenError> {
         for (idx, param) in func.params.iter().enumerate() {
@@ -1377,22 +1455,17 @@ pub(super) fn call_function_native_small(
                 eval_expr_native(default_expr, &mut function_env)?
             } else {
                 return Err(ZekkenError::runtime(
-                    &format!("Missing required argument '{}'", param.ident),
+                    &format!("Missing required argument '{}' for '{}'", param.ident, func.name),
                     line,
                     column,
                     Some("argument mismatch"),
                 ));
             };
             if !check_value_type(&value, &param.type_) {
-                return Err(ZekkenError::type_error(
-                    &format!("Type mismatch for parameter '{}'", param.ident),
-                    &format!("{:?}", param.type_),
-                    value_type_name(&value),
-                    line,
-                    column,
-                ));
+                let at = arg_locations.get(idx).unwrap_or(location).clone();
+                return Err(argument_type_error(func, param, &value, at));
             }
-            function_env.declare_ref_typed(param.ident.as_str(), value, param.type_, false);
+            function_env.declare_ref_typed(param.ident.as_str(), value, param.type_.clone(), false);
         }
         Ok(())
     })();
@@ -1405,22 +1478,28 @@ pub(super) fn call_function_native_small(
     let result = if let Some(insts) = func.compiled_insts.as_deref() {
         run_insts(insts, func.compiled_reg_count, &mut function_env)
     } else {
-        eval_contents_native(func.body.as_ref(), &mut function_env)
+        eval_contents_native(func.body.as_ref(), &mut function_env).map(|last| function_env.take_return().or(last))
     };
+    let result = match run_deferred(&mut function_env) {
+        Some(error) if result.is_ok() => Err(error),
+        _ => result,
+    };
+    function_env.sync_shared();
+    env.reload_shared();
     let out = match result {
         Ok(v) => Ok(v.unwrap_or(Value::Void)),
         Err(e) => Err(e),
     }
     .and_then(|v| {
-        if let Some(ret_ty) = func.return_type {
-            if !check_value_type(&v, &ret_ty) {
+        if let Some(ret_ty) = &func.return_type {
+            if !check_value_type(&v, ret_ty) {
                 return Err(ZekkenError::type_error(
                     "Type mismatch in function return value",
                     &format!("{:?}", ret_ty),
                     value_type_name(&v),
                     line,
                     column,
-                ));
+                ).with_value(&v));
             }
         }
         Ok(v)
@@ -1431,9 +1510,14 @@ pub(super) fn call_function_native_small(
 
 fn eval_expr_native(expr: &Expr, env: &mut Environment) -> Result<Value, ZekkenError> {
     match expr {
-        Expr::Assign(assign) => eval_assignment_native(assign, env),
+        Expr::Assign(assign) => {
+            let value = eval_assignment_native(assign, env)?;
+            crate::history::record_assignment(&assign.left, &assign.location, env);
+            Ok(value)
+        }
         Expr::Member(member) => eval_member_native(member, env),
         Expr::Call(call) => eval_call_native(call, env),
+        Expr::MethodCall(call) => eval_method_call_native(call, env),
         Expr::Unary(unary) => {
             let operand = eval_expr_native(&unary.operand, env)?;
             match unary.operator.as_str() {
@@ -1445,7 +1529,7 @@ fn eval_expr_native(expr: &Expr, env: &mut Environment) -> Result<Value, ZekkenE
                         value_type_name(&other),
                         unary.location.line,
                         unary.location.column,
-                    )),
+                    ).with_value(&other)),
                 },
                 _ => Err(ZekkenError::internal("Unsupported unary operator")),
             }
@@ -1503,17 +1587,60 @@ fn eval_expr_native(expr: &Expr, env: &mut Environment) -> Result<Value, ZekkenE
                 )
             })
         },
+        Expr::Lambda(lambda) => Ok(Value::Function(make_function_value("<lambda>", &lambda.params, &lambda.body, None, env))),
+        Expr::Match(match_expr) => {
+            let subject = eval_expr_native(&match_expr.subject, env)?;
+            let mut bindings = Vec::new();
+            for arm in &match_expr.arms {
+                let mut failure = None;
+                let matched = match_pattern(
+                    &arm.pattern,
+                    &subject,
+                    &mut |value, candidate| match eval_expr_native(value, env) {
+                        Ok(value) => compare_values(candidate, &value),
+                        Err(error) => {
+                            failure.get_or_insert(error);
+                            false
+                        }
+                    },
+                    &mut bindings,
+                );
+                if let Some(error) = failure {
+                    return Err(error);
+                }
+                if !matched {
+                    continue;
+                }
+                for (name, value) in bindings.drain(..) {
+                    set_or_declare_loop_var(env, &name, value);
+                }
+                if let Some(guard) = &arm.guard {
+                    match eval_expr_native(guard, env)? {
+                        Value::Boolean(true) => {}
+                        Value::Boolean(false) => continue,
+                        other => {
+                            return Err(ZekkenError::type_error(
+                                "Match guard must evaluate to a boolean",
+                                "bool",
+                                value_type_name(&other),
+                                arm.location.line,
+                                arm.location.column,
+                            ).with_value(&other))
+                        }
+                    }
+                }
+                return Ok(eval_contents_native(&arm.body, env)?.unwrap_or(Value::Void));
+            }
+            Ok(Value::Void)
+        }
         Expr::Property(_) => Err(ZekkenError::internal("Property expression not supported in this context")),
         Expr::IntLit(v) => Ok(Value::Int(v.value)),
         Expr::FloatLit(v) => Ok(Value::Float(v.value)),
-        Expr::StringLit(v) => {
-            if v.value.as_bytes().contains(&b'{') {
-                Ok(Value::String(interpolate_string_expressions_native(&v.value, env)))
-            } else {
-                Ok(Value::String(v.value.clone()))
-            }
-        }
+        Expr::DecimalLit(v) => Ok(Value::from(v)),
+        Expr::StringLit(v) => Ok(Value::String(v.value.clone())),
+        Expr::TemplateLit(template) => Ok(Value::String(eval_template_native(template, env))),
         Expr::BoolLit(v) => Ok(Value::Boolean(v.value)),
+        Expr::NullLit(_) => Ok(Value::Null),
         Expr::ArrayLit(arr) => {
             let mut out = Vec::with_capacity(arr.elements.len());
             for e in &arr.elements {
@@ -1521,92 +1648,80 @@ fn eval_expr_native(expr: &Expr, env: &mut Environment) -> Result<Value, ZekkenE
             }
             Ok(Value::Array(out))
         }
+        Expr::VectorLit(vector) => {
+            let mut out = Vec::with_capacity(vector.elements.len());
+            for e in &vector.elements {
+                out.push(eval_expr_native(e.as_ref(), env)?);
+            }
+            vector_from(&out).map_err(|e| ZekkenError::runtime(&e, vector.location.line, vector.location.column, None))
+        }
+        Expr::MatrixLit(matrix) => {
+            let mut rows = Vec::with_capacity(matrix.rows.len());
+            for row in &matrix.rows {
+                let mut out = Vec::with_capacity(row.len());
+                for e in row {
+                    out.push(eval_expr_native(e.as_ref(), env)?);
+                }
+                rows.push(out);
+            }
+            matrix_from(&rows).map_err(|e| ZekkenError::runtime(&e, matrix.location.line, matrix.location.column, None))
+        }
         Expr::ObjectLit(obj) => {
-            let mut map = HashMap::new();
-            let mut keys = Vec::with_capacity(obj.properties.len());
+            let mut map = IndexMap::with_capacity(obj.properties.len());
             for p in &obj.properties {
-                keys.push(Value::String(p.key.clone()));
-                map.insert(p.key.clone(), eval_expr_native(&p.value, env)?);
+                let key = match &p.computed_key {
+                    Some(key_expr) => {
+                        let key_value = eval_expr_native(key_expr, env)?;
+                        key_value.as_object_key().ok_or_else(|| ZekkenError::type_error(
+                            "Computed object key must be a string or int",
+                            "string or int",
+                            value_type_name(&key_value),
+                            p.location.line,
+                            p.location.column,
+                        ).with_value(&key_value))?
+                    }
+                    None => p.key.clone(),
+                };
+                map.insert(key, eval_expr_native(&p.value, env)?);
             }
-            map.insert("__keys__".to_string(), Value::Array(keys));
             Ok(Value::Object(map))
         }
     }
 }
 
-fn interpolate_string_expressions_native(template: &str, env: &mut Environment) -> String {
-    let mut out = String::with_capacity(template.len());
-    let bytes = template.as_bytes();
-    let mut i = 0usize;
-    let mut segment_start = 0usize;
-
-    while i < bytes.len() {
-        if bytes[i] != b'{' {
-            i += 1;
-            continue;
-        }
-
-        let mut j = i + 1;
-        while j < bytes.len() && bytes[j] != b'}' {
-            j += 1;
-        }
-        if j >= bytes.len() {
-            break;
-        }
-
-        let raw_inner = &template[i + 1..j];
-        let inner = raw_inner.trim();
-        out.push_str(&template[segment_start..i]);
-
-        if inner.is_empty() {
-            out.push_str("{}");
-        } else {
-            let mut parser = Parser::new();
-            let program = parser.produce_ast(inner.to_string());
-            let expr = program.content.first().and_then(|c| match c.as_ref() {
-                Content::Expression(e) => Some(e.as_ref().clone()),
-                _ => None,
-            });
-
-            if !parser.errors.is_empty() {
-                out.push('{');
-                out.push_str(raw_inner);
-                out.push('}');
-            } else if let Some(expr) = expr {
-                match eval_expr_native(&expr, env) {
-                    Ok(value) => out.push_str(&value.to_string()),
-                    Err(_) => {
-                        out.push('{');
-                        out.push_str(raw_inner);
-                        out.push('}');
-                    }
+fn eval_template_native(template: &TemplateLit, env: &mut Environment) -> String {
+    let mut out = String::new();
+    for part in &template.parts {
+        match part {
+            TemplatePart::Text(text) => out.push_str(text),
+            TemplatePart::Expr { expr, raw } => match eval_expr_native(expr, env) {
+                Ok(value) => out.push_str(&value.to_string()),
+                Err(_) => {
+                    out.push('{');
+                    out.push_str(raw);
+                    out.push('}');
                 }
-            } else {
-                out.push('{');
-                out.push_str(raw_inner);
-                out.push('}');
-            }
+            },
         }
-
-        i = j + 1;
-        segment_start = i;
     }
-
-    out.push_str(&template[segment_start..]);
     out
 }
 
 fn eval_content_native(content: &Content, env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
+    crate::coverage::content(content);
     match content {
         Content::Statement(stmt) => eval_stmt_native(stmt.as_ref(), env),
         Content::Expression(expr) => Ok(Some(eval_expr_native(expr.as_ref(), env)?)),
     }
 }
 
-fn eval_contents_native(contents: &[Box<Content>], env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
+fn eval_contents_native(contents: &[Content], env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
     let mut last = None;
     for content in contents {
-        last = eval_content_native(content.as_ref(), env)?;
+        last = eval_content_native(content, env)?;
+        if env.control_flow.is_some() {
+            break;
+        }
     }
     Ok(last)
 }
@@ -1618,50 +1733,63 @@ fn content_has_return(content: &Content) -> bool {
     }
 }
 
-fn block_has_return(content: &[Box<Content>]) -> bool {
-    content.iter().any(|c| content_has_return(c))
+fn block_has_return(content: &[Content]) -> bool {
+    content.iter().any(content_has_return)
 }
 
 fn stmt_has_return(stmt: &Stmt) -> bool {
     match stmt {
         Stmt::Return(_) => true,
         Stmt::IfStmt(i) => block_has_return(&i.body) || i.alt.as_ref().map(|b| block_has_return(b)).unwrap_or(false),
-        Stmt::ForStmt(f) => block_has_return(&f.body),
-        Stmt::WhileStmt(w) => block_has_return(&w.body),
+        Stmt::ForStmt(f) => block_has_return(&f.body) || f.else_body.as_ref().map(|b| block_has_return(b)).unwrap_or(false),
+        Stmt::ForIn(f) => block_has_return(&f.body) || f.else_body.as_ref().map(|b| block_has_return(b)).unwrap_or(false),
+        Stmt::WhileStmt(w) => block_has_return(&w.body) || w.else_body.as_ref().map(|b| block_has_return(b)).unwrap_or(false),
+        Stmt::Loop(l) => block_has_return(&l.body),
         Stmt::TryCatchStmt(t) => {
             block_has_return(&t.try_block)
                 || t.catch_block.as_ref().map(|b| block_has_return(b)).unwrap_or(false)
         }
         Stmt::BlockStmt(b) => block_has_return(&b.body),
+        Stmt::With(w) => block_has_return(&w.body),
+        Stmt::Switch(s) => s.bodies().any(|body| block_has_return(body)),
         Stmt::Program(p) => {
-            p.imports.iter().any(|c| content_has_return(c))
-                || p.content.iter().any(|c| content_has_return(c))
+            p.imports.iter().any(content_has_return)
+                || p.content.iter().any(content_has_return)
         }
         Stmt::FuncDecl(_)
         | Stmt::Lambda(_)
+        | Stmt::Break(_)
+        | Stmt::Continue(_)
         | Stmt::VarDecl(_)
         | Stmt::ObjectDecl(_)
+        | Stmt::EnumDecl(_)
+        | Stmt::Defer(_)
         | Stmt::Use(_)
         | Stmt::Include(_)
         | Stmt::Export(_) => false,
     }
 }
 
-fn eval_contents_discard_native(contents: &[Box<Content>], env: &mut Environment) -> Result<(), ZekkenError> {
+fn eval_contents_discard_native(contents: &[Content], env: &mut Environment) -> Result<(), ZekkenError> {
     for content in contents {
-        match content.as_ref() {
+        crate::coverage::content(content);
+        match content {
             Content::Statement(stmt) => {
                 let _ = eval_stmt_native(stmt, env)?;
             }
             Content::Expression(expr) => match expr.as_ref() {
                 Expr::Assign(assign) => {
                     let _ = eval_assignment_native(assign, env)?;
+                    crate::history::record_assignment(&assign.left, &assign.location, env);
                 }
                 _ => {
                     let _ = eval_expr_native(expr, env)?;
                 }
             },
         }
+        if env.control_flow.is_some() {
+            break;
+        }
     }
     Ok(())
 }
@@ -1670,7 +1798,8 @@ fn eval_use_native(use_stmt: &UseStmt, env: &mut Environment) -> Result<Option<V
     match load_library(&use_stmt.module, env) {
         Ok(_) => {
             if let Some(methods) = &use_stmt.methods {
-                if let Some(Value::Object(lib_obj)) = env.lookup(&use_stmt.module) {
+                let library = env.lookup(&use_stmt.module);
+                if let Some(lib_obj) = library.as_ref().and_then(Value::as_object) {
                     for method in methods {
                         if let Some(value) = lib_obj.get(method) {
                             env.declare(method.clone(), value.clone(), false);
@@ -1726,7 +1855,7 @@ fn eval_include_native(include: &IncludeStmt, env: &mut Environment) -> Result<O
     std::env::set_var("ZEKKEN_CURRENT_FILE", &file_path);
 
     let mut parser = Parser::new();
-    let included_ast = parser.produce_ast(file_contents);
+    let mut included_ast = parser.produce_ast_cached(file_contents);
     if !parser.errors.is_empty() {
         for parse_error in parser.errors {
             push_error(parse_error);
@@ -1740,32 +1869,21 @@ fn eval_include_native(include: &IncludeStmt, env: &mut Environment) -> Result<O
         ));
     }
 
+    let libraries = library_names(&included_ast);
+    if include.lazy {
+        keep_declarations(&mut included_ast);
+    }
     let mut child_env = Environment::new_with_parent(env.clone());
     let result = execute_program(&included_ast, &mut child_env);
 
     std::env::set_var("ZEKKEN_CURRENT_FILE", prev_file);
     result?;
 
-    match &include.methods {
-        Some(methods) => {
-            for method in methods {
-                if let Some(value) = child_env.lookup(method) {
-                    env.declare(method.clone(), value, false);
-                } else {
-                    return Err(ZekkenError::runtime(
-                        &format!("Method '{}' not found in included file", method),
-                        include.location.line,
-                        include.location.column,
-                        None,
-                    ));
-                }
-            }
-        }
-        None => {
-            for (name, value) in &child_env.variables {
-                env.declare(name.clone(), value.clone(), false);
-            }
-        }
+    let bindings = included_bindings(include, &libraries, &child_env)
+        .map_err(|msg| ZekkenError::runtime(&msg, include.location.line, include.location.column, None))?;
+    trace_include(&file_path, &bindings);
+    for (name, value) in bindings {
+        env.declare(name, value, false);
     }
 
     Ok(None)
@@ -1795,126 +1913,109 @@ fn set_or_declare_loop_var(env: &mut Environment, name: &str, value: Value) {
     }
 }
 
-fn eval_for_native(for_stmt: &ForStmt, env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
-    let init = for_stmt.init.as_ref().ok_or_else(|| {
-        ZekkenError::runtime("For loop requires an initialization", for_stmt.location.line, for_stmt.location.column, None)
-    })?;
-
-    let var_decl = match init.as_ref() {
-        Stmt::VarDecl(v) => v,
-        _ => {
-            return Err(ZekkenError::runtime(
-                "For loop requires a variable declaration",
-                for_stmt.location.line,
-                for_stmt.location.column,
-                None,
-            ))
+// Same scoping as the tree-walk `evaluate_for_statement`: fresh loop
+// variables per iteration, with whatever they shadowed put back at the end.
+fn eval_for_native(for_stmt: &ForInStmt, env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
+    // `a..b` is walked lazily rather than built into an array first.
+    let range = match for_stmt.iterable.as_ref() {
+        Expr::Binary(range) if range.operator == ".." => {
+            let start = eval_expr_native(&range.left, env)?;
+            let end = eval_expr_native(&range.right, env)?;
+            Some(int_range(&start, &end).map_err(|e| ZekkenError::runtime(&e, range.location.line, range.location.column, None))?)
         }
+        _ => None,
     };
-
-    let collection = match &var_decl.value {
-        Some(Content::Expression(expr)) => eval_expr_native(expr, env)?,
-        _ => {
-            return Err(ZekkenError::runtime(
-                "Expected expression in for loop initialization",
-                for_stmt.location.line,
-                for_stmt.location.column,
-                Some("for |x| in array { ... }"),
-            ))
-        }
+    let mut collection = match range {
+        Some(_) => Value::Void,
+        None => eval_expr_native(&for_stmt.iterable, env)?,
     };
+    collection.unshare();
+    // An enum variant iterates over its fields like an object.
+    if let Value::Enum { payload, .. } = &collection {
+        collection = Value::Object(payload.as_ref().clone());
+    }
+    let ids = &for_stmt.idents;
+    let shadowed: Vec<_> = ids.iter().map(|name| (name, env.take_binding(name))).collect();
+    let body_may_return = block_has_return(&for_stmt.body);
 
     let mut last = None;
-    match collection {
-        Value::Array(arr) => {
-            let ids: Vec<String> = var_decl
-                .ident
-                .split(',')
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty())
-                .collect();
-            if ids.is_empty() || ids.len() > 2 {
-                return Err(ZekkenError::syntax(
+    let result = 'run: {
+        let entries: Box<dyn Iterator<Item = (Value, Value)>> = match (range, collection) {
+            (Some(_), _) | (None, Value::Array(_)) if ids.is_empty() || ids.len() > 2 => {
+                break 'run Err(ZekkenError::syntax(
                     "Array iteration requires one or two identifiers",
-                    var_decl.location.line,
-                    var_decl.location.column,
+                    for_stmt.location.line,
+                    for_stmt.location.column,
                     None,
                     None,
                 ));
             }
-            let body_may_return = block_has_return(&for_stmt.body);
-            if ids.len() == 1 {
-                set_or_declare_loop_var(env, &ids[0], Value::Void);
-            } else {
-                set_or_declare_loop_var(env, &ids[0], Value::Int(0));
-                set_or_declare_loop_var(env, &ids[1], Value::Void);
-            }
-            for (index, value) in arr.into_iter().enumerate() {
-                if ids.len() == 1 {
-                    set_or_declare_loop_var(env, &ids[0], value);
-                } else {
-                    set_or_declare_loop_var(env, &ids[0], Value::Int(index as i64));
-                    set_or_declare_loop_var(env, &ids[1], value);
-                }
-                if body_may_return {
-                    if let Some(v) = eval_contents_native(&for_stmt.body, env)? {
-                        last = Some(v);
-                    }
-                } else {
-                    eval_contents_discard_native(&for_stmt.body, env)?;
+            (Some(range), _) => Box::new(range.enumerate().map(|(index, n)| (Value::Int(index as i64), Value::Int(n)))),
+            (None, Value::Array(arr)) => {
+                Box::new(arr.into_iter().enumerate().map(|(index, value)| (Value::Int(index as i64), value)))
+            }
+            (None, Value::Object(map)) => {
+                if ids.len() != 2 {
+                    break 'run Err(ZekkenError::syntax(
+                        "Object iteration requires two identifiers (key, value)",
+                        for_stmt.location.line,
+                        for_stmt.location.column,
+                        None,
+                        None,
+                    ));
                 }
+                Box::new(
+                    map.into_iter()
+                        .filter(|(key, value)| !is_internal_entry(key, value))
+                        .map(|(key, value)| (Value::String(key), value)),
+                )
             }
-        }
-        Value::Object(map) => {
-            let ids: Vec<String> = var_decl.ident.split(", ").map(|s| s.to_string()).collect();
-            if ids.len() != 2 {
-                return Err(ZekkenError::syntax(
-                    "Object iteration requires two identifiers (key, value)",
-                    var_decl.location.line,
-                    var_decl.location.column,
-                    None,
-                    None,
-                ));
+            (None, other) => {
+                break 'run Err(ZekkenError::type_error(
+                    "For loop must iterate over an object or array",
+                    "object or array",
+                    value_type_name(&other),
+                    for_stmt.location.line,
+                    for_stmt.location.column,
+                ).with_value(&other))
             }
+        };
 
-            let ordered_keys: Vec<String> = match map.get("__keys__") {
-                Some(Value::Array(keys)) => keys
-                    .iter()
-                    .filter_map(|k| if let Value::String(s) = k { Some(s.clone()) } else { None })
-                    .collect(),
-                _ => map.keys().filter(|k| k.as_str() != "__keys__").cloned().collect(),
+        for (key, value) in entries {
+            if ids.len() == 1 {
+                set_or_declare_loop_var(env, &ids[0], value);
+            } else {
+                set_or_declare_loop_var(env, &ids[0], key);
+                set_or_declare_loop_var(env, &ids[1], value);
+            }
+            let ran = if body_may_return {
+                eval_contents_native(&for_stmt.body, env).map(|v| last = v.or(last.take()))
+            } else {
+                eval_contents_discard_native(&for_stmt.body, env)
             };
-
-            let body_may_return = block_has_return(&for_stmt.body);
-            for key in ordered_keys {
-                if key == "__keys__" {
-                    continue;
-                }
-                if let Some(value) = map.get(&key) {
-                    set_or_declare_loop_var(env, &ids[0], Value::String(key));
-                    set_or_declare_loop_var(env, &ids[1], value.clone());
-                    if body_may_return {
-                        if let Some(v) = eval_contents_native(&for_stmt.body, env)? {
-                            last = Some(v);
-                        }
-                    } else {
-                        eval_contents_discard_native(&for_stmt.body, env)?;
-                    }
-                }
+            if let Err(error) = ran {
+                break 'run Err(error);
+            }
+            if env.stop_loop(for_stmt.label.as_deref()) {
+                break 'run Ok(last);
             }
         }
-        other => {
-            return Err(ZekkenError::type_error(
-                "For loop must iterate over an object or array",
-                "object or array",
-                value_type_name(&other),
-                for_stmt.location.line,
-                for_stmt.location.column,
-            ))
+
+        if let Some(else_body) = &for_stmt.else_body {
+            match eval_contents_native(else_body, env) {
+                Ok(Some(v)) => last = Some(v),
+                Ok(None) => {}
+                Err(error) => break 'run Err(error),
+            }
         }
-    }
+        Ok(last)
+    };
 
-    Ok(last)
+    // Reversed, so `|x, x|` puts back the outer `x` last.
+    for (name, saved) in shadowed.into_iter().rev() {
+        env.restore_binding(name, saved);
+    }
+    result
 }
 
 fn eval_try_catch_native(try_catch: &TryCatchStmt, env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
@@ -1922,12 +2023,18 @@ fn eval_try_catch_native(try_catch: &TryCatchStmt, env: &mut Environment) -> Res
         Ok(v) => Ok(v),
         Err(error) => {
             if let Some(catch_block) = &try_catch.catch_block {
-                let mut err_obj = HashMap::new();
+                crate::trace::error(&error, true);
+                let mut err_obj = IndexMap::new();
                 err_obj.insert("message".to_string(), Value::String(error.message.clone()));
                 err_obj.insert("kind".to_string(), Value::String(format!("{:?}", error.kind)));
                 err_obj.insert("line".to_string(), Value::Int(error.context.line as i64));
                 err_obj.insert("column".to_string(), Value::Int(error.context.column as i64));
+                err_obj.insert("code".to_string(), error.code.map_or(Value::Null, |code| Value::String(code.code.to_string())));
+                err_obj.insert("retryable".to_string(), Value::Boolean(error.code.is_some_and(|code| code.retryable)));
                 err_obj.insert("__zekken_error__".to_string(), Value::String(error.to_string()));
+                if let Some(method) = crate::eval::env_eval::catch_method(env) {
+                    err_obj.insert("env_eval".to_string(), method);
+                }
 
                 let prev_var = env.variables.remove("e");
                 let prev_const = env.constants.remove("e");
@@ -1952,6 +2059,7 @@ fn eval_try_catch_native(try_catch: &TryCatchStmt, env: &mut Environment) -> Res
 }
 
 fn eval_stmt_native(stmt: &Stmt, env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
+    crate::stats::statement();
     match stmt {
         Stmt::Program(program) => execute_program(program, env),
         Stmt::VarDecl(decl) => {
@@ -1960,33 +2068,54 @@ fn eval_stmt_native(stmt: &Stmt, env: &mut Environment) -> Result<Option<Value>,
                 Some(Content::Statement(stmt)) => eval_stmt_native(stmt.as_ref(), env)?.unwrap_or(Value::Void),
                 None => Value::Void,
             };
+            // A `return` (or `break`) inside the initializer leaves first.
+            if env.control_flow.is_some() {
+                return Ok(None);
+            }
 
-            if !check_value_type(&value, &decl.type_) {
+            // A variable of any type may hold `null`.
+            if !matches!(value, Value::Null) && !check_value_type(&value, &decl.type_) {
+                // Point at the initializer, like the tree-walk evaluator does.
+                let loc = match decl.value.as_ref() {
+                    Some(Content::Expression(expr)) => compiler::expr_location(expr),
+                    _ => decl.location.clone(),
+                };
                 return Err(ZekkenError::type_error(
                     &format!("Type mismatch in variable declaration '{}'", decl.ident),
                     &format!("{:?}", decl.type_),
                     value_type_name(&value),
-                    decl.location.line,
-                    decl.location.column,
-                ));
+                    loc.line,
+                    loc.column,
+                ).with_value(&value));
             }
 
-            env.declare_ref_typed(&decl.ident, value, decl.type_, decl.constant);
+            crate::history::record(&decl.ident, &value, &decl.location);
+            env.declare_ref_typed(&decl.ident, value, decl.type_.clone(), decl.constant);
             Ok(None)
         }
         Stmt::FuncDecl(func) => {
-            let function_value = make_function_value(&func.params, &func.body, func.return_type, env);
+            let function_value = make_function_value(&func.ident, &func.params, &func.body, func.return_type.clone(), env);
             env.declare(func.ident.clone(), Value::Function(function_value), false);
             Ok(None)
         }
         Stmt::ObjectDecl(obj) => {
-            let mut map = HashMap::new();
-            let mut keys = Vec::with_capacity(obj.properties.len());
+            let mut map = IndexMap::with_capacity(obj.properties.len());
             for p in &obj.properties {
-                keys.push(Value::String(p.key.clone()));
-                map.insert(p.key.clone(), eval_expr_native(&p.value, env)?);
+                let key = match &p.computed_key {
+                    Some(key_expr) => {
+                        let key_value = eval_expr_native(key_expr, env)?;
+                        key_value.as_object_key().ok_or_else(|| ZekkenError::type_error(
+                            "Computed object key must be a string or int",
+                            "string or int",
+                            value_type_name(&key_value),
+                            p.location.line,
+                            p.location.column,
+                        ).with_value(&key_value))?
+                    }
+                    None => p.key.clone(),
+                };
+                map.insert(key, eval_expr_native(&p.value, env)?);
             }
-            map.insert("__keys__".to_string(), Value::Array(keys));
             env.declare(obj.ident.clone(), Value::Object(map), false);
             Ok(None)
         }
@@ -2007,12 +2136,94 @@ fn eval_stmt_native(stmt: &Stmt, env: &mut Environment) -> Result<Option<Value>,
                     value_type_name(&other),
                     if_stmt.location.line,
                     if_stmt.location.column,
-                )),
+                ).with_value(&other)),
+            }
+        }
+        Stmt::Loop(loop_stmt) => {
+            let mut cap = IterationCap::new();
+            loop {
+                if !cap.tick() {
+                    return Err(cap.error(&loop_stmt.location));
+                }
+                let result = eval_contents_native(&loop_stmt.body, env)?;
+                if env.stop_loop(loop_stmt.label.as_deref()) {
+                    return Ok(result);
+                }
             }
         }
-        Stmt::ForStmt(for_stmt) => eval_for_native(for_stmt, env),
+        Stmt::Break(brk) => {
+            env.control_flow = Some(ControlFlow::Break(brk.label.clone()));
+            Ok(None)
+        }
+        Stmt::Continue(cont) => {
+            env.control_flow = Some(ControlFlow::Continue(cont.label.clone()));
+            Ok(None)
+        }
+        Stmt::ForIn(for_stmt) => eval_for_native(for_stmt, env),
+        Stmt::ForStmt(for_stmt) => {
+            // The initializer's binding only lives as long as the loop; put back
+            // whatever it shadowed once the loop ends.
+            let scoped = match for_stmt.init.as_deref() {
+                Some(Stmt::VarDecl(decl)) => Some((decl.ident.as_str(), env.take_binding(&decl.ident))),
+                _ => None,
+            };
+            let result = 'run: {
+                if let Some(init) = &for_stmt.init {
+                    if let Err(error) = eval_stmt_native(init, env) {
+                        break 'run Err(error);
+                    }
+                }
+                let mut last = None;
+                let mut cap = IterationCap::new();
+                loop {
+                    if let Some(test) = &for_stmt.test {
+                        match eval_expr_native(test, env) {
+                            Ok(Value::Boolean(true)) => {}
+                            Ok(Value::Boolean(false)) => break,
+                            Ok(other) => {
+                                break 'run Err(ZekkenError::type_error(
+                                    "For loop condition must evaluate to a boolean",
+                                    "bool",
+                                    value_type_name(&other),
+                                    for_stmt.location.line,
+                                    for_stmt.location.column,
+                                ).with_value(&other))
+                            }
+                            Err(error) => break 'run Err(error),
+                        }
+                    }
+                    if !cap.tick() {
+                        break 'run Err(cap.error(&for_stmt.location));
+                    }
+                    match eval_contents_native(&for_stmt.body, env) {
+                        Ok(value) => last = value,
+                        Err(error) => break 'run Err(error),
+                    }
+                    if env.stop_loop(for_stmt.label.as_deref()) {
+                        break 'run Ok(last);
+                    }
+                    if let Some(update) = &for_stmt.update {
+                        if let Err(error) = eval_expr_native(update, env) {
+                            break 'run Err(error);
+                        }
+                    }
+                }
+                if let Some(else_body) = &for_stmt.else_body {
+                    match eval_contents_native(else_body, env) {
+                        Ok(value) => last = value,
+                        Err(error) => break 'run Err(error),
+                    }
+                }
+                Ok(last)
+            };
+            if let Some((name, saved)) = scoped {
+                env.restore_binding(name, saved);
+            }
+            result
+        }
         Stmt::WhileStmt(while_stmt) => {
             let body_may_return = block_has_return(&while_stmt.body);
+            let mut cap = IterationCap::new();
             #[derive(Clone)]
             enum NumCondOperand {
                 Ident(String),
@@ -2157,6 +2368,9 @@ fn eval_stmt_native(stmt: &Stmt, env: &mut Environment) -> Result<Option<Value>,
                     if !test_true {
                         break;
                     }
+                    if !cap.tick() {
+                        return Err(cap.error(&while_stmt.location));
+                    }
                     if body_may_return {
                         if let Some(v) = eval_contents_native(&while_stmt.body, env)? {
                             result = Some(v);
@@ -2164,6 +2378,14 @@ fn eval_stmt_native(stmt: &Stmt, env: &mut Environment) -> Result<Option<Value>,
                     } else {
                         eval_contents_discard_native(&while_stmt.body, env)?;
                     }
+                    if env.stop_loop(while_stmt.label.as_deref()) {
+                        return Ok(result);
+                    }
+                }
+                if let Some(else_body) = &while_stmt.else_body {
+                    if let Some(v) = eval_contents_native(else_body, env)? {
+                        result = Some(v);
+                    }
                 }
                 return Ok(result);
             }
@@ -2173,6 +2395,9 @@ fn eval_stmt_native(stmt: &Stmt, env: &mut Environment) -> Result<Option<Value>,
                 let test = eval_expr_native(&while_stmt.test, env)?;
                 match test {
                     Value::Boolean(true) => {
+                        if !cap.tick() {
+                            return Err(cap.error(&while_stmt.location));
+                        }
                         if body_may_return {
                             if let Some(v) = eval_contents_native(&while_stmt.body, env)? {
                                 last = Some(v);
@@ -2180,6 +2405,9 @@ fn eval_stmt_native(stmt: &Stmt, env: &mut Environment) -> Result<Option<Value>,
                         } else {
                             eval_contents_discard_native(&while_stmt.body, env)?;
                         }
+                        if env.stop_loop(while_stmt.label.as_deref()) {
+                            return Ok(last);
+                        }
                     }
                     Value::Boolean(false) => break,
                     other => {
@@ -2189,10 +2417,15 @@ fn eval_stmt_native(stmt: &Stmt, env: &mut Environment) -> Result<Option<Value>,
                             value_type_name(&other),
                             while_stmt.location.line,
                             while_stmt.location.column,
-                        ))
+                        ).with_value(&other))
                     }
                 }
             }
+            if let Some(else_body) = &while_stmt.else_body {
+                if let Some(v) = eval_contents_native(else_body, env)? {
+                    last = Some(v);
+                }
+            }
             Ok(last)
         }
         Stmt::TryCatchStmt(try_catch) => eval_try_catch_native(try_catch, env),
@@ -2200,18 +2433,93 @@ fn eval_stmt_native(stmt: &Stmt, env: &mut Environment) -> Result<Option<Value>,
         Stmt::Use(use_stmt) => eval_use_native(use_stmt, env),
         Stmt::Include(include) => eval_include_native(include, env),
         Stmt::Export(exports) => eval_export_native(exports, env),
+        Stmt::EnumDecl(decl) => {
+            env.declare(decl.ident.clone(), enum_value(&decl.ident, &decl.variants), true);
+            Ok(None)
+        }
+        Stmt::Defer(defer) => {
+            env.deferred.push(defer.expr.as_ref().clone());
+            Ok(None)
+        }
+        Stmt::With(with) => {
+            let (line, column) = (with.location.line, with.location.column);
+            let resource = eval_expr_native(&with.resource, env)?;
+            let Some(close) = resource.close_method().cloned() else {
+                return Err(ZekkenError::type_error(
+                    "With resource must have a __close__ method",
+                    "object with __close__",
+                    value_type_name(&resource),
+                    line,
+                    column,
+                ).with_value(&resource));
+            };
+            set_or_declare_loop_var(env, &with.ident, resource);
+            let result = eval_contents_native(&with.body, env);
+            let closed = match close {
+                Value::Function(func) => call_function_native(&func, Vec::new(), &[], env, line, column).map(|_| ()),
+                Value::NativeFunction(native) => native(Vec::new())
+                    .map(|_| ())
+                    .map_err(|msg| ZekkenError::runtime(&msg, line, column, None)),
+                _ => Ok(()),
+            };
+            match closed {
+                Err(error) if result.is_ok() => Err(error),
+                _ => result,
+            }
+        }
+        Stmt::Switch(switch) => {
+            let subject = eval_expr_native(&switch.subject, env)?;
+            if !matches!(subject, Value::Int(_) | Value::String(_)) {
+                let at = compiler::expr_location(&switch.subject);
+                return Err(ZekkenError::type_error(
+                    "Switch value must be an int or a string",
+                    "int or string",
+                    value_type_name(&subject),
+                    at.line,
+                    at.column,
+                ).with_value(&subject));
+            }
+            // Cases are tried in order; with no match only the default runs.
+            let mut start = switch.cases.len();
+            'cases: for (idx, case) in switch.cases.iter().enumerate() {
+                for value in &case.values {
+                    if switch_case_matches(&subject, &eval_expr_native(value, env)?) {
+                        start = idx;
+                        break 'cases;
+                    }
+                }
+            }
+            let mut result = None;
+            for case in &switch.cases[start..] {
+                result = eval_contents_native(&case.body, env)?;
+                if !case.fallthrough || env.control_flow.is_some() {
+                    return Ok(result);
+                }
+            }
+            match &switch.default {
+                Some(default) => eval_contents_native(default, env),
+                None => Ok(result),
+            }
+        }
         Stmt::Return(ret) => {
             let value = match &ret.value {
                 Some(content) => match content.as_ref() {
                     Content::Expression(expr) => eval_expr_native(expr, env)?,
-                    Content::Statement(stmt) => eval_stmt_native(stmt.as_ref(), env)?.unwrap_or(Value::Void),
+                    Content::Statement(stmt) => {
+                        let value = eval_stmt_native(stmt.as_ref(), env)?;
+                        if env.control_flow.is_some() {
+                            return Ok(None);
+                        }
+                        value.unwrap_or(Value::Void)
+                    }
                 },
                 None => Value::Void,
             };
-            Ok(Some(value))
+            env.control_flow = Some(ControlFlow::Return(value));
+            Ok(None)
         }
         Stmt::Lambda(lambda) => {
-            let function_value = make_function_value(&lambda.params, &lambda.body, lambda.return_type, env);
+            let function_value = make_function_value(&lambda.ident, &lambda.params, &lambda.body, lambda.return_type.clone(), env);
             env.declare(lambda.ident.clone(), Value::Function(function_value), lambda.constant);
             Ok(None)
         }
@@ -2225,10 +2533,26 @@ pub fn execute_program(program: &Program, env: &mut Environment) -> Result<Optio
         compiler.compile_content(import);
     }
     compiler.compile_contents(&program.content);
-    run_insts(&compiler.insts, compiler.next_reg, env)
+    let result = run_insts(&compiler.insts, compiler.next_reg, env);
+    match run_deferred(env) {
+        Some(error) if result.is_ok() => Err(error),
+        _ => result,
+    }
+}
+
+/// Runs the expressions `defer`red in `env`'s frame, most recent first. Every
+/// one of them runs even if an earlier one fails; the first error is returned.
+pub fn run_deferred(env: &mut Environment) -> Option<ZekkenError> {
+    let mut first_error = None;
+    while let Some(expr) = env.deferred.pop() {
+        if let Err(error) = eval_expr_native(&expr, env) {
+            first_error.get_or_insert(error);
+        }
+    }
+    first_error
 }
 
-pub fn execute_contents(contents: &[Box<Content>], env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
+pub fn execute_contents(contents: &[Content], env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
     if contents.is_empty() {
         return Ok(None);
     }
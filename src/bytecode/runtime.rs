@@ -1,8 +1,10 @@
 use crate::ast::*;
-use crate::environment::{Environment, Value};
+use crate::environment::decimal::Decimal;
+use crate::libraries::{arrays, bigfloat, calc, common::{call_native, with_native_code}, linalg, units};
+use crate::environment::{decimal_binary, elements_match, ensure_array_slot, int_binary, numeric_binary, repeat_string, string_char_at, vector_index, Environment, Value};
 use crate::errors::ZekkenError;
 use crate::lexer::DataType;
-use hashbrown::HashMap;
+use indexmap::IndexMap;
 use std::cell::RefCell;
 
 use super::compiler::make_function_value;
@@ -20,12 +22,18 @@ pub(super) fn value_type_name(val: &Value) -> &'static str {
         Value::String(_) => "string",
         Value::Boolean(_) => "bool",
         Value::Array(_) => "array",
-        Value::Object(_) => "object",
+        Value::Object(_) | Value::Library(_) | Value::Enum { .. } => "object",
         Value::Function(_) => "function",
         Value::NativeFunction(_) => "native function",
         Value::Complex { .. } => "complex",
+        Value::Decimal(_) => "dec",
         Value::Vector(_) => "vector",
         Value::Matrix(_) => "matrix",
+        Value::Quantity(_) => "quantity",
+        Value::BigFloat(_) => "bigfloat",
+        Value::Expression(_) => "expression",
+        Value::Resource(_) => "resource",
+        Value::Null => "null",
         Value::Void => "void",
     }
 }
@@ -48,9 +56,13 @@ pub(super) fn check_value_type(value: &Value, expected: &DataType) -> bool {
         (Value::Float(_), DataType::Float) => true,
         (Value::String(_), DataType::String) => true,
         (Value::Boolean(_), DataType::Bool) => true,
-        (Value::Array(_), DataType::Array) => true,
-        (Value::Object(_), DataType::Object) => true,
-        (Value::Function(_), DataType::Fn) => true,
+        (Value::Array(_) | Value::Vector(_) | Value::Matrix(_), DataType::Array) => true,
+        (_, DataType::ArrayOf(element)) => elements_match(value, element),
+        (Value::Null, DataType::Nullable(_)) => true,
+        (_, DataType::Nullable(inner)) => check_value_type(value, inner),
+        (Value::Object(_) | Value::Library(_) | Value::Enum { .. } | Value::Quantity(_) | Value::BigFloat(_) | Value::Expression(_), DataType::Object) => true,
+        (Value::Decimal(_), DataType::Decimal) => true,
+        (Value::Function(_) | Value::NativeFunction(_), DataType::Fn) => true,
         _ => false,
     }
 }
@@ -61,18 +73,31 @@ pub(super) fn compare_values(left: &Value, right: &Value) -> bool {
         (Value::Float(l), Value::Float(r)) => l == r,
         (Value::Int(l), Value::Float(r)) => (*l as f64) == *r,
         (Value::Float(l), Value::Int(r)) => *l == (*r as f64),
+        (Value::Decimal(l), Value::Decimal(r)) => l == r,
         (Value::String(l), Value::String(r)) => l == r,
         (Value::Boolean(l), Value::Boolean(r)) => l == r,
-        (Value::Void, Value::Void) => true,
+        (Value::Void, Value::Void) | (Value::Null, Value::Null) => true,
+        (Value::Enum { .. }, Value::Enum { .. }) => left.enum_variant() == right.enum_variant(),
+        (Value::Function(_) | Value::NativeFunction(_), _) => left.same_function(right),
         _ => false,
     }
 }
 
 #[inline]
 pub(super) fn eval_binary_opcode(left: &Value, right: &Value, op: BinaryOpCode, location: &Location) -> Result<Value, ZekkenError> {
+    if let Some(value) = numeric_binary(op.as_str(), left, right) {
+        return Ok(value);
+    }
+    if let Some(result) = decimal_binary(op.as_str(), left, right).or_else(|| units::binary(op.as_str(), left, right))
+        .or_else(|| bigfloat::binary(op.as_str(), left, right))
+        .or_else(|| calc::binary(op.as_str(), left, right))
+        .or_else(|| linalg::binary(op.as_str(), left, right)) {
+        return result.map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None));
+    }
     match op {
         BinaryOpCode::Add => match (left, right) {
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l + r)),
+            (Value::Int(l), Value::Int(r)) => int_binary("+", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l + r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 + r)),
             (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l + *r as f64)),
@@ -94,17 +119,22 @@ pub(super) fn eval_binary_opcode(left: &Value, right: &Value, op: BinaryOpCode,
             )),
         },
         BinaryOpCode::Sub => match (left, right) {
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l - r)),
+            (Value::Int(l), Value::Int(r)) => int_binary("-", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l - r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 - r)),
             (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l - *r as f64)),
             _ => Err(ZekkenError::type_error("Invalid operand types for subtraction", "number", "non-number", location.line, location.column)),
         },
         BinaryOpCode::Mul => match (left, right) {
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l * r)),
+            (Value::Int(l), Value::Int(r)) => int_binary("*", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l * r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 * r)),
             (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l * *r as f64)),
+            (Value::String(text), Value::Int(count)) | (Value::Int(count), Value::String(text)) => {
+                repeat_string(text, *count).map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None))
+            }
             _ => Err(ZekkenError::type_error("Invalid operand types for multiplication", "number", "non-number", location.line, location.column)),
         },
         BinaryOpCode::Div => match (left, right) {
@@ -112,7 +142,8 @@ pub(super) fn eval_binary_opcode(left: &Value, right: &Value, op: BinaryOpCode,
             (Value::Float(_), Value::Float(r)) if *r == 0.0 => Err(ZekkenError::runtime("Division by zero", location.line, location.column, Some("division by zero"))),
             (Value::Int(_), Value::Float(r)) if *r == 0.0 => Err(ZekkenError::runtime("Division by zero", location.line, location.column, Some("division by zero"))),
             (Value::Float(_), Value::Int(r)) if *r == 0 => Err(ZekkenError::runtime("Division by zero", location.line, location.column, Some("division by zero"))),
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l / r)),
+            (Value::Int(l), Value::Int(r)) => int_binary("/", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l / r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 / r)),
             (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l / *r as f64)),
@@ -120,12 +151,14 @@ pub(super) fn eval_binary_opcode(left: &Value, right: &Value, op: BinaryOpCode,
         },
         BinaryOpCode::Mod => match (left, right) {
             (Value::Int(_), Value::Int(r)) if *r == 0 => Err(ZekkenError::runtime("Modulo by zero", location.line, location.column, Some("modulo by zero"))),
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l % r)),
+            (Value::Int(l), Value::Int(r)) => int_binary("%", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             _ => Err(ZekkenError::type_error("Invalid operand types for modulo", "int", "non-int", location.line, location.column)),
         },
         BinaryOpCode::In => match (left, right) {
             (_, Value::Array(arr)) => Ok(Value::Boolean(arr.iter().any(|v| compare_values(left, v)))),
             (Value::String(key), Value::Object(obj)) => Ok(Value::Boolean(obj.contains_key(key))),
+            (Value::String(key), Value::Library(lib) | Value::Enum { payload: lib, .. }) => Ok(Value::Boolean(lib.contains_key(key))),
             (Value::String(needle), Value::String(haystack)) => Ok(Value::Boolean(haystack.contains(needle))),
             _ => Err(ZekkenError::type_error(
                 "Invalid 'in' operation",
@@ -216,11 +249,14 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
     }
 
     let mut regs = take_pooled_regs(reg_count);
+    // Counted locally and reported once, to keep the dispatch loop lean.
+    let mut executed = 0u64;
     let result = (|| -> Result<Option<Value>, ZekkenError> {
         let mut ip = 0usize;
         let mut last_value: Option<Value> = None;
 
         while ip < insts.len() {
+            executed += 1;
             match &insts[ip] {
             Inst::LoadConst { dst, value } => {
                 *get_reg_mut(&mut regs, *dst) = clone_value_hot(value);
@@ -265,7 +301,8 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                             )
                         })?
                     }
-                    Value::Object(map) => {
+                    Value::Object(_) | Value::Library(_) | Value::Enum { .. } => {
+                        let Some(map) = obj.as_object() else { unreachable!("objects, libraries and enum variants have fields") };
                         match idx_val {
                             Value::String(k) => map.get(k).cloned().ok_or_else(|| {
                                 ZekkenError::runtime(
@@ -298,10 +335,15 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                                     value_type_name(idx_val),
                                     location.line,
                                     location.column,
-                                ));
+                                ).with_value(idx_val));
                             }
                         }
                     }
+                    Value::String(s) => string_char_at(s, idx_val)
+                        .map(|ch| Value::String(ch.to_string()))
+                        .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None))?,
+                    Value::Vector(_) | Value::Matrix(_) => vector_index(obj, idx_val)
+                        .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None))?,
                     other => {
                         return Err(ZekkenError::type_error(
                             "Invalid member access",
@@ -309,7 +351,7 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                             value_type_name(other),
                             location.line,
                             location.column,
-                        ));
+                        ).with_value(other));
                     }
                 };
                 *get_reg_mut(&mut regs, *dst) = value;
@@ -318,9 +360,18 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                 let l = get_reg(&regs, *left);
                 let r = get_reg(&regs, *right);
                 let out = match (l, r, op) {
-                    (Value::Int(li), Value::Int(ri), BinaryOpCode::Add) => Value::Int(li + ri),
-                    (Value::Int(li), Value::Int(ri), BinaryOpCode::Sub) => Value::Int(li - ri),
-                    (Value::Int(li), Value::Int(ri), BinaryOpCode::Mul) => Value::Int(li * ri),
+                    (Value::Int(li), Value::Int(ri), BinaryOpCode::Add) => match li.checked_add(*ri) {
+                        Some(v) => Value::Int(v),
+                        None => eval_binary_opcode(l, r, *op, location)?,
+                    },
+                    (Value::Int(li), Value::Int(ri), BinaryOpCode::Sub) => match li.checked_sub(*ri) {
+                        Some(v) => Value::Int(v),
+                        None => eval_binary_opcode(l, r, *op, location)?,
+                    },
+                    (Value::Int(li), Value::Int(ri), BinaryOpCode::Mul) => match li.checked_mul(*ri) {
+                        Some(v) => Value::Int(v),
+                        None => eval_binary_opcode(l, r, *op, location)?,
+                    },
                     (Value::Int(_), Value::Int(0), BinaryOpCode::Div) => {
                         return Err(ZekkenError::runtime(
                             "Division by zero",
@@ -329,7 +380,10 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                             Some("division by zero"),
                         ));
                     }
-                    (Value::Int(li), Value::Int(ri), BinaryOpCode::Div) => Value::Int(li / ri),
+                    (Value::Int(li), Value::Int(ri), BinaryOpCode::Div) => match li.checked_div(*ri) {
+                        Some(v) => Value::Int(v),
+                        None => eval_binary_opcode(l, r, *op, location)?,
+                    },
                     (Value::Int(_), Value::Int(0), BinaryOpCode::Mod) => {
                         return Err(ZekkenError::runtime(
                             "Modulo by zero",
@@ -338,7 +392,10 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                             Some("modulo by zero"),
                         ));
                     }
-                    (Value::Int(li), Value::Int(ri), BinaryOpCode::Mod) => Value::Int(li % ri),
+                    (Value::Int(li), Value::Int(ri), BinaryOpCode::Mod) => match li.checked_rem(*ri) {
+                        Some(v) => Value::Int(v),
+                        None => eval_binary_opcode(l, r, *op, location)?,
+                    },
                     (Value::Int(li), Value::Int(ri), BinaryOpCode::Eq) => Value::Boolean(li == ri),
                     (Value::Int(li), Value::Int(ri), BinaryOpCode::Ne) => Value::Boolean(li != ri),
                     (Value::Int(li), Value::Int(ri), BinaryOpCode::Lt) => Value::Boolean(li < ri),
@@ -437,7 +494,7 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                 let out = method.eval(call_args, env, location)?;
                 *get_reg_mut(&mut regs, *dst) = out;
             }
-            Inst::CallIdent { dst, name, argc, args, is_native, location } => {
+            Inst::CallIdent { dst, name, argc, args, is_native, location, arg_locations } => {
                 let local_callee = match env.variables.get(name).or_else(|| env.constants.get(name)) {
                     Some(Value::Function(func)) => Some(Value::Function(func.clone())),
                     Some(Value::NativeFunction(native)) => Some(Value::NativeFunction(native.clone())),
@@ -448,18 +505,18 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                             value_type_name(other),
                             location.line,
                             location.column,
-                        ))
+                        ).with_value(other))
                     }
                     None => None,
                 };
                 let out = if let Some(callee) = local_callee {
                     match callee {
                         Value::Function(func) => {
-                            super::call_function_native_small(&func, *argc, args, &regs, env, location.line, location.column)?
+                            super::call_function_native_small(&func, *argc, args, &regs, env, location, arg_locations)?
                         }
                         Value::NativeFunction(native) => {
                             let call_args = collect_small_call_args(&regs, *argc, args);
-                            if matches!(name.as_str(), "println" | "input" | "parse_json" | "queue") && !*is_native {
+                            if matches!(name.as_str(), "println" | "print" | "flush" | "input" | "parse_json" | "queue" | "memstats" | "set_precision" | "format" | "diff") && !*is_native {
                                 return Err(ZekkenError::runtime(
                                     &format!("{} is a built-in; call it with '@{} => |...|'", name, name),
                                     location.line,
@@ -467,7 +524,7 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                                     None,
                                 ));
                             }
-                            native(call_args).map_err(|msg| ZekkenError::runtime(&msg, location.line, location.column, None))?
+                            call_native(&*native, call_args, location.line, location.column).map_err(|msg| with_native_code(ZekkenError::runtime(&msg, location.line, location.column, None)))?
                         }
                         _ => unreachable!(),
                     }
@@ -483,11 +540,11 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                     })?;
                     match callee {
                         Value::Function(func) => {
-                            super::call_function_native_small(&func, *argc, args, &regs, env, location.line, location.column)?
+                            super::call_function_native_small(&func, *argc, args, &regs, env, location, arg_locations)?
                         }
                         Value::NativeFunction(native) => {
                             let call_args = collect_small_call_args(&regs, *argc, args);
-                            if matches!(name.as_str(), "println" | "input" | "parse_json" | "queue") && !*is_native {
+                            if matches!(name.as_str(), "println" | "print" | "flush" | "input" | "parse_json" | "queue" | "memstats" | "set_precision" | "format" | "diff") && !*is_native {
                                 return Err(ZekkenError::runtime(
                                     &format!("{} is a built-in; call it with '@{} => |...|'", name, name),
                                     location.line,
@@ -495,7 +552,7 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                                     None,
                                 ));
                             }
-                            native(call_args).map_err(|msg| ZekkenError::runtime(&msg, location.line, location.column, None))?
+                            call_native(&*native, call_args, location.line, location.column).map_err(|msg| with_native_code(ZekkenError::runtime(&msg, location.line, location.column, None)))?
                         }
                         other => {
                             return Err(ZekkenError::type_error(
@@ -504,23 +561,26 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                                 value_type_name(&other),
                                 location.line,
                                 location.column,
-                            ))
+                            ).with_value(&other))
                         }
                     }
                 };
                 *get_reg_mut(&mut regs, *dst) = out;
             }
-            Inst::CallMethodIdent { dst, object_name, method_name, argc, args, location } => {
+            Inst::CallMethodIdent { dst, object_name, method_name, method_id, argc, args, location } => {
                 let call_args = collect_small_call_args(&regs, *argc, args);
-                let native_member = match env.lookup_ref(object_name) {
-                    Some(Value::Object(map)) => match map.get(method_name) {
+                let native_member = match env.lookup_ref(object_name).and_then(Value::as_object) {
+                    Some(map) => match map.get(method_name) {
                         Some(Value::NativeFunction(native)) => Some(native.clone()),
                         _ => None,
                     },
                     _ => None,
                 };
-                let out = if let Some(native) = native_member {
-                    native(call_args).map_err(|msg| ZekkenError::runtime(&msg, location.line, location.column, None))?
+                let keyed = arrays::keyed(method_name).filter(|_| object_name == "arrays");
+                let out = if let (Some(keyed), Some(_)) = (keyed, &native_member) {
+                    super::keyed_native(keyed, &call_args, env, location.line, location.column)?
+                } else if let Some(native) = native_member {
+                    call_native(&*native, call_args, location.line, location.column).map_err(|msg| with_native_code(ZekkenError::runtime(&msg, location.line, location.column, None)))?
                 } else {
                     let object = env.lookup_ref(object_name).cloned().ok_or_else(|| {
                         ZekkenError::reference_with_span(
@@ -531,67 +591,102 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                             object_name.len().max(1),
                         )
                     })?;
-                    object
-                        .call_method(method_name, call_args, Some(env), Some(object_name.as_str()))
-                        .map_err(|msg| ZekkenError::runtime(&msg, location.line, location.column, None))?
+                    // A function stored in an object property is called like a method.
+                    let stored = match object.as_object() {
+                        Some(map) => match map.get(method_name) {
+                            Some(Value::Function(func)) => Some(func.clone()),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    match (stored, &object) {
+                        (Some(func), _) => super::call_function_native(&func, call_args, &[], env, location.line, location.column)?,
+                        (None, Value::Array(items)) if *method_id == Some(Method::SortBy) => {
+                            super::sort_by_native(items, &call_args, env, location.line, location.column)?
+                        }
+                        (None, _) => object
+                            .call_resolved_method(*method_id, method_name, call_args, Some(env), Some(object_name.as_str()))
+                            .map_err(|msg| with_native_code(ZekkenError::runtime(&msg, location.line, location.column, None)))?,
+                    }
                 };
                 *get_reg_mut(&mut regs, *dst) = out;
             }
             Inst::EvalExprNative { dst, expr } => {
                 *get_reg_mut(&mut regs, *dst) = super::eval_expr_native(expr, env)?;
+                // A `return` in a match arm ends the body before anything
+                // uses the match's value.
+                if let Some(v) = env.take_return() {
+                    return Ok(Some(v));
+                }
             }
             Inst::ExecStmtNative { stmt } => {
                 if let Some(v) = super::eval_stmt_native(stmt, env)? {
                     last_value = Some(v);
                 }
+                // A `return` nested in a natively run loop or `try` ends the body.
+                if let Some(v) = env.take_return() {
+                    return Ok(Some(v));
+                }
             }
             Inst::DeclareVar { name, ty, constant, src, location } => {
                 let value = clone_value_hot(get_reg(&regs, *src));
-                if !check_value_type(&value, ty) {
+                if !matches!(value, Value::Null) && !check_value_type(&value, ty) {
                     return Err(ZekkenError::type_error(
                         &format!("Type mismatch in variable declaration '{}'", name),
                         &format!("{:?}", ty),
                         value_type_name(&value),
                         location.line,
                         location.column,
-                    ));
+                    ).with_value(&value));
                 }
-                env.declare_ref_typed(name, value, *ty, *constant);
+                crate::history::record(name, &value, location);
+                env.declare_ref_typed(name, value, ty.clone(), *constant);
             }
             Inst::DeclareFunc { func } => {
-                let function_value = make_function_value(&func.params, &func.body, func.return_type, env);
+                let function_value = make_function_value(&func.ident, &func.params, &func.body, func.return_type.clone(), env);
                 env.declare(func.ident.clone(), Value::Function(function_value), false);
             }
             Inst::DeclareLambda { lambda } => {
-                let function_value = make_function_value(&lambda.params, &lambda.body, lambda.return_type, env);
+                let function_value = make_function_value(&lambda.ident, &lambda.params, &lambda.body, lambda.return_type.clone(), env);
                 env.declare(lambda.ident.clone(), Value::Function(function_value), lambda.constant);
             }
             Inst::DeclareObject { object } => {
-                let mut map = HashMap::new();
-                let mut keys = Vec::with_capacity(object.properties.len());
+                let mut map = IndexMap::with_capacity(object.properties.len());
                 for prop in &object.properties {
+                    let key = match &prop.computed_key {
+                        Some(key_expr) => {
+                            let key_value = super::eval_expr_native(key_expr, env)?;
+                            key_value.as_object_key().ok_or_else(|| ZekkenError::type_error(
+                                "Computed object key must be a string or int",
+                                "string or int",
+                                value_type_name(&key_value),
+                                prop.location.line,
+                                prop.location.column,
+                            ).with_value(&key_value))?
+                        }
+                        None => prop.key.clone(),
+                    };
                     let value = super::eval_expr_native(&prop.value, env)?;
-                    keys.push(Value::String(prop.key.clone()));
-                    map.insert(prop.key.clone(), value);
+                    map.insert(key, value);
                 }
-                map.insert("__keys__".to_string(), Value::Array(keys));
                 env.declare(object.ident.clone(), Value::Object(map), false);
             }
             Inst::AssignIdent { dst, name, src, location } => {
                 let value = clone_value_hot(get_reg(&regs, *src));
                 let expected = env.lookup_type(name).unwrap_or(DataType::Any);
-                if expected != DataType::Any && !check_value_type(&value, &expected) {
+                if expected != DataType::Any && !matches!(value, Value::Null) && !check_value_type(&value, &expected) {
                     return Err(ZekkenError::type_error(
                         &format!("Type mismatch in assignment to '{}'", name),
                         &format!("{:?}", expected),
                         value_type_name(&value),
                         location.line,
                         location.column,
-                    ));
+                    ).with_value(&value));
                 }
                 env.assign(name, value.clone()).map_err(|e| {
                     ZekkenError::runtime(&e, location.line, location.column, None)
                 })?;
+                crate::history::record(name, &value, location);
                 *get_reg_mut(&mut regs, *dst) = value;
             }
             Inst::StoreIndexIdent { dst, name, index, src, location } => {
@@ -600,6 +695,7 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                 let slot = env.lookup_mut_assignable(name).map_err(|e| {
                     ZekkenError::runtime(&e, location.line, location.column, None)
                 })?;
+                slot.unshare();
                 match slot {
                     Value::Array(arr) => {
                         let idx = value_to_non_negative_index(&idx_value).ok_or_else(|| {
@@ -611,14 +707,9 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                                 location.column,
                             )
                         })?;
-                        if idx >= arr.len() {
-                            return Err(ZekkenError::runtime(
-                                &format!("Array index out of bounds: {}", idx),
-                                location.line,
-                                location.column,
-                                None,
-                            ));
-                        }
+                        ensure_array_slot(arr, idx).map_err(|e| {
+                            ZekkenError::runtime(&e, location.line, location.column, None)
+                        })?;
                         arr[idx] = src_value.clone();
                     }
                     Value::Object(map) => {
@@ -633,7 +724,7 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                                     value_type_name(&idx_value),
                                     location.line,
                                     location.column,
-                                ))
+                                ).with_value(&idx_value))
                             }
                         };
                         map.insert(key, src_value.clone());
@@ -645,7 +736,12 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                             value_type_name(other),
                             location.line,
                             location.column,
-                        ));
+                        ).with_value(other));
+                    }
+                }
+                if crate::history::is_recording() || crate::trace::is_recording() {
+                    if let Some(value) = env.lookup_ref(name) {
+                        crate::history::record(name, value, location);
                     }
                 }
                 *get_reg_mut(&mut regs, *dst) = src_value;
@@ -668,7 +764,7 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                             value_type_name(&other),
                             location.line,
                             location.column,
-                        ))
+                        ).with_value(other))
                     }
                 }
             }
@@ -687,7 +783,7 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                                     value_type_name(other),
                                     location.line,
                                     location.column,
-                                ))
+                                ).with_value(other))
                             }
                         };
                         let r = match get_reg(&regs, *right) {
@@ -700,7 +796,7 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                                     value_type_name(other),
                                     location.line,
                                     location.column,
-                                ))
+                                ).with_value(other))
                             }
                         };
                         match op {
@@ -747,7 +843,7 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                             value_type_name(other),
                             location.line,
                             location.column,
-                        ))
+                        ).with_value(other))
                     }
                 }
             }
@@ -780,17 +876,29 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                             value_type_name(&other),
                             location.line,
                             location.column,
-                        ))
+                        ).with_value(&other))
                     }
                 }
             }
             Inst::SetLast { src } => {
                 last_value = Some(clone_value_hot(get_reg(&regs, *src)));
+            }
+            Inst::Cover { line } => crate::coverage::line(*line),
+            Inst::CountIteration { counter, limit, location } => {
+                let slot = get_reg_mut(&mut regs, *counter);
+                let count = match slot {
+                    Value::Int(n) => *n + 1,
+                    _ => 1,
+                };
+                if count as u64 > *limit {
+                    return Err(crate::config::iteration_limit_error(*limit, location));
+                }
+                *slot = Value::Int(count);
             }
                 Inst::Return { src } => {
                     return Ok(Some(clone_value_hot(get_reg(&regs, *src))));
                 }
-            Inst::AddIntAssignIdent { dst, name, delta, location } => {
+            Inst::AddIntAssignIdent { dst, name, op, delta, location } => {
                 let slot = env.lookup_mut_assignable(name).map_err(|e| {
                     ZekkenError::runtime(&e, location.line, location.column, None)
                 })?;
@@ -803,16 +911,22 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
                         *f += *delta as f64;
                         *get_reg_mut(&mut regs, *dst) = Value::Float(*f);
                     }
+                    Value::Decimal(d) => {
+                        *d = d.checked_add(Decimal::from_int(*delta)).map_err(|e| {
+                            ZekkenError::runtime(&e, location.line, location.column, None)
+                        })?;
+                        *get_reg_mut(&mut regs, *dst) = Value::Decimal(*d);
+                    }
                     other => {
-                        return Err(ZekkenError::type_error(
-                            "Invalid assignment target for integer increment",
-                            "int or float",
-                            value_type_name(other),
-                            location.line,
-                            location.column,
-                        ));
+                        let amount = Value::Int(if matches!(op, BinaryOpCode::Sub) { -*delta } else { *delta });
+                        let value = eval_binary_opcode(other, &amount, *op, location)?;
+                        env.assign(name, value.clone()).map_err(|e| {
+                            ZekkenError::runtime(&e, location.line, location.column, None)
+                        })?;
+                        *get_reg_mut(&mut regs, *dst) = value;
                     }
                 }
+                crate::history::record(name, get_reg(&regs, *dst), location);
             }
             }
             ip += 1;
@@ -820,6 +934,7 @@ pub(super) fn run_insts(insts: &[Inst], reg_count: usize, env: &mut Environment)
 
         Ok(last_value)
     })();
+    crate::stats::instructions(executed);
     return_pooled_regs(regs);
     result
 }
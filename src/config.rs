@@ -0,0 +1,475 @@
+//! Project configuration loaded from `Zekken.toml`.
+//!
+//! The manifest is looked up from the directory of the script being run,
+//! walking up towards the filesystem root. Only the tables Zekken currently
+//! understands are read; anything else in the file is ignored.
+
+// The wasm build has no filesystem and never loads a manifest.
+#![allow(dead_code)]
+
+use crate::ast::Location;
+use crate::errors::i18n::Lang;
+use crate::errors::ZekkenError;
+use hashbrown::HashMap;
+use std::path::{Path, PathBuf};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+pub const MANIFEST_NAME: &str = "Zekken.toml";
+
+/// Every lint the checker can report, by the name used in `[lints]`.
+pub const LINTS: &[&str] = &[
+    "undefined-variable",
+    "undefined-function",
+    "not-callable",
+    "builtin-call-syntax",
+    "const-assign",
+    "invalid-assign-target",
+    "missing-include",
+    "include-side-effects",
+    "unknown-library",
+    "type-mismatch",
+    "non-exhaustive-match",
+    "unused-function",
+    "unused-export",
+    "unused-include",
+    "stuck-loop",
+    "unknown-option",
+];
+
+/// Level used when `[lints]` does not mention a lint. Dead-code reports from
+/// `zekken check --unused`, the guesses of `--loop-warn`, includes that run
+/// top-level code and misspelled library options never stop a program, so
+/// they default to `warn`.
+pub fn default_level(lint: &str) -> LintLevel {
+    if lint.starts_with("unused-") || matches!(lint, "stuck-loop" | "include-side-effects" | "unknown-option") {
+        LintLevel::Warn
+    } else {
+        LintLevel::Deny
+    }
+}
+
+/// Language editions, oldest first. A script runs under the oldest one unless
+/// `[language] edition` or `--edition` picks a newer edition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Edition {
+    #[default]
+    E2025,
+    E2026,
+}
+
+impl Edition {
+    pub const NAMES: &'static [&'static str] = &["2025", "2026"];
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "2025" => Some(Self::E2025),
+            "2026" => Some(Self::E2026),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::E2025 => "2025",
+            Self::E2026 => "2026",
+        }
+    }
+}
+
+/// Syntax or behaviour on its way out. Under editions before `error_from` it
+/// is reported as a deprecation warning; from `error_from` on it is an error.
+/// `[deprecations]` can set either level, or `allow`, per feature.
+pub struct Deprecation {
+    pub name: &'static str,
+    pub error_from: Edition,
+    /// What to write instead.
+    pub help: &'static str,
+}
+
+pub const DEPRECATIONS: &[Deprecation] = &[
+    Deprecation {
+        name: "internal-keys",
+        error_from: Edition::E2026,
+        help: "fields like '__zekken_error__' are bookkeeping and will stop being readable; use the value's public fields",
+    },
+];
+
+pub fn deprecation(name: &str) -> Option<&'static Deprecation> {
+    DEPRECATIONS.iter().find(|deprecation| deprecation.name == name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintLevel {
+    /// Drop the diagnostic entirely.
+    Allow,
+    /// Report the diagnostic but keep running.
+    Warn,
+    /// Report the diagnostic as an error.
+    Deny,
+}
+
+impl LintLevel {
+    fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "allow" => Some(Self::Allow),
+            "warn" => Some(Self::Warn),
+            "deny" => Some(Self::Deny),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProjectConfig {
+    pub path: Option<PathBuf>,
+    pub lints: HashMap<String, LintLevel>,
+    /// `[language] aliases = true`: lex the teaching-mode keyword spellings
+    /// in [`crate::lexer::ALIASES`].
+    pub aliases: bool,
+    /// `[language] lang = "es"`: print diagnostics in this language unless
+    /// `ZEKKEN_LANG` picks another.
+    pub lang: Lang,
+    /// `[language] edition = "2026"`: which deprecations are errors.
+    pub edition: Edition,
+    /// `[deprecations]`: levels that override what the edition implies.
+    pub deprecations: HashMap<String, LintLevel>,
+}
+
+impl ProjectConfig {
+    /// Finds the nearest `Zekken.toml` at or above `start` and parses it.
+    /// Returns `Ok(None)` when no manifest exists.
+    pub fn discover(start: &Path) -> Result<Option<Self>, String> {
+        let start = if start.as_os_str().is_empty() { Path::new(".") } else { start };
+        let start = start.canonicalize().unwrap_or_else(|_| start.to_path_buf());
+        for dir in start.ancestors() {
+            let candidate = dir.join(MANIFEST_NAME);
+            if candidate.is_file() {
+                return Self::load(&candidate).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+        let mut config = Self::parse(&source).map_err(|e| format!("{}: {}", path.display(), e))?;
+        config.path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let table = source
+            .parse::<toml::Table>()
+            .map_err(|e| format!("invalid TOML: {}", e.message()))?;
+        let mut config = Self::default();
+
+        if let Some(lints) = table.get("lints") {
+            let lints = lints
+                .as_table()
+                .ok_or_else(|| "[lints] must be a table".to_string())?;
+            for (name, level) in lints {
+                if !LINTS.contains(&name.as_str()) {
+                    return Err(format!(
+                        "unknown lint '{}' in [lints] (known lints: {})",
+                        name,
+                        LINTS.join(", ")
+                    ));
+                }
+                let level = level.as_str().and_then(LintLevel::parse).ok_or_else(|| {
+                    format!("lint '{}' must be set to \"allow\", \"warn\" or \"deny\"", name)
+                })?;
+                config.lints.insert(name.clone(), level);
+            }
+        }
+
+        if let Some(deprecations) = table.get("deprecations") {
+            let deprecations = deprecations
+                .as_table()
+                .ok_or_else(|| "[deprecations] must be a table".to_string())?;
+            for (name, level) in deprecations {
+                if deprecation(name).is_none() {
+                    let known: Vec<&str> = DEPRECATIONS.iter().map(|deprecation| deprecation.name).collect();
+                    return Err(format!(
+                        "unknown deprecation '{}' in [deprecations] (known deprecations: {})",
+                        name,
+                        known.join(", ")
+                    ));
+                }
+                let level = level.as_str().and_then(LintLevel::parse).ok_or_else(|| {
+                    format!("deprecation '{}' must be set to \"allow\", \"warn\" or \"deny\"", name)
+                })?;
+                config.deprecations.insert(name.clone(), level);
+            }
+        }
+
+        if let Some(language) = table.get("language") {
+            let language = language
+                .as_table()
+                .ok_or_else(|| "[language] must be a table".to_string())?;
+            for (name, value) in language {
+                match name.as_str() {
+                    "aliases" => {
+                        config.aliases = value
+                            .as_bool()
+                            .ok_or_else(|| "language.aliases must be true or false".to_string())?;
+                    }
+                    "lang" => {
+                        config.lang = value.as_str().and_then(Lang::from_code).ok_or_else(|| {
+                            format!("language.lang must be one of: {}", Lang::CODES.join(", "))
+                        })?;
+                    }
+                    "edition" => {
+                        let raw = match value {
+                            toml::Value::Integer(year) => year.to_string(),
+                            other => other.as_str().unwrap_or_default().to_string(),
+                        };
+                        config.edition = Edition::parse(&raw).ok_or_else(|| {
+                            format!("language.edition must be one of: {}", Edition::NAMES.join(", "))
+                        })?;
+                    }
+                    _ => {
+                        return Err(format!(
+                            "unknown setting '{}' in [language] (known settings: aliases, edition, lang)",
+                            name
+                        ))
+                    }
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn lint_level(&self, lint: &str) -> LintLevel {
+        self.lints.get(lint).copied().unwrap_or_else(|| default_level(lint))
+    }
+
+    pub fn deprecation_level(&self, deprecation: &Deprecation) -> LintLevel {
+        match self.deprecations.get(deprecation.name) {
+            Some(level) => *level,
+            None if self.edition >= deprecation.error_from => LintLevel::Deny,
+            None => LintLevel::Warn,
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref ACTIVE: Mutex<ProjectConfig> = Mutex::new(ProjectConfig::default());
+}
+
+/// Installs the configuration consulted by the lint pass for this process.
+pub fn set_active(config: ProjectConfig) {
+    *ACTIVE.lock().unwrap() = config;
+}
+
+pub fn lint_level(lint: &str) -> LintLevel {
+    ACTIVE.lock().unwrap().lint_level(lint)
+}
+
+pub fn deprecation_level(deprecation: &Deprecation) -> LintLevel {
+    ACTIVE.lock().unwrap().deprecation_level(deprecation)
+}
+
+/// Applies `--edition`, which wins over the manifest's `[language] edition`.
+pub fn set_edition(edition: Edition) {
+    ACTIVE.lock().unwrap().edition = edition;
+}
+
+pub fn edition() -> Edition {
+    ACTIVE.lock().unwrap().edition
+}
+
+pub fn aliases() -> bool {
+    ACTIVE.lock().unwrap().aliases
+}
+
+pub fn language() -> Lang {
+    ACTIVE.lock().unwrap().lang
+}
+
+/// Path of the active `Zekken.toml`, if one was found.
+pub fn manifest_path() -> Option<PathBuf> {
+    ACTIVE.lock().unwrap().path.clone()
+}
+
+static TYPED: AtomicBool = AtomicBool::new(false);
+
+/// Enables `--typed`: every checked expression must have a statically known
+/// type, not just a compatible one.
+pub fn set_typed(enabled: bool) {
+    TYPED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn typed() -> bool {
+    TYPED.load(Ordering::Relaxed)
+}
+
+static TRACE_IMPORTS: AtomicBool = AtomicBool::new(false);
+
+/// Enables `--trace-imports`: every `include` reports the names it brought
+/// into the including scope.
+pub fn set_trace_imports(enabled: bool) {
+    TRACE_IMPORTS.store(enabled, Ordering::Relaxed);
+}
+
+pub fn trace_imports() -> bool {
+    TRACE_IMPORTS.load(Ordering::Relaxed)
+}
+
+static PERMISSIVE: AtomicBool = AtomicBool::new(false);
+
+/// Enables `--permissive`: assigning past the end of an array grows it
+/// instead of failing.
+pub fn set_permissive(enabled: bool) {
+    PERMISSIVE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn permissive() -> bool {
+    PERMISSIVE.load(Ordering::Relaxed)
+}
+
+thread_local! {
+    // Per thread, like the interpreter state the cap guards.
+    static MAX_ITERATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Sets `--max-iterations`: a `while`, `loop` or C-style `for` that runs
+/// more than `limit` times stops with an error. Zero, the default, is no cap.
+pub fn set_max_iterations(limit: u64) {
+    MAX_ITERATIONS.with(|max| max.set(limit));
+}
+
+pub fn max_iterations() -> u64 {
+    MAX_ITERATIONS.with(Cell::get)
+}
+
+thread_local! {
+    static ENV_EVAL: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Enables `--env-eval`: caught errors get an `env_eval` method, at the cost
+/// of snapshotting the scope every time a `catch` runs.
+pub fn set_env_eval(enabled: bool) {
+    ENV_EVAL.with(|env_eval| env_eval.set(enabled));
+}
+
+pub fn env_eval() -> bool {
+    ENV_EVAL.with(Cell::get)
+}
+
+thread_local! {
+    static PRINT_RESULT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Enables `--print-result`: a script run prints the value of its last
+/// top-level expression, as the REPL and `zekken eval` always do.
+pub fn set_print_result(enabled: bool) {
+    PRINT_RESULT.with(|print_result| print_result.set(enabled));
+}
+
+pub fn print_result() -> bool {
+    PRINT_RESULT.with(Cell::get)
+}
+
+/// Counts the iterations of one running loop against `--max-iterations`.
+pub struct IterationCap {
+    limit: u64,
+    count: u64,
+}
+
+impl IterationCap {
+    pub fn new() -> Self {
+        IterationCap { limit: max_iterations(), count: 0 }
+    }
+
+    /// Counts one more iteration; false once the loop has gone past the cap.
+    #[inline]
+    pub fn tick(&mut self) -> bool {
+        self.count += 1;
+        self.limit == 0 || self.count <= self.limit
+    }
+
+    pub fn error(&self, location: &Location) -> ZekkenError {
+        iteration_limit_error(self.limit, location)
+    }
+}
+
+pub fn iteration_limit_error(limit: u64, location: &Location) -> ZekkenError {
+    ZekkenError::runtime(
+        &format!("Loop stopped after {} iterations", limit),
+        location.line,
+        location.column,
+        Some("raise --max-iterations if the loop is meant to run longer"),
+    )
+}
+
+/// User function calls that may be in progress at once unless
+/// `--max-call-depth` says otherwise.
+pub const DEFAULT_MAX_CALL_DEPTH: u64 = 1000;
+
+/// Native stack given to each allowed call by [`stack_size_for`]. A call
+/// takes under 100 KiB in either engine, plus whatever the expressions
+/// around the call site add.
+const STACK_BYTES_PER_CALL: u64 = 256 * 1024;
+
+/// Native stack a thread needs to run `depth` nested calls without
+/// overflowing, capped at 4 GiB. With no limit (zero) this is the size a
+/// default limit would get.
+pub fn stack_size_for(depth: u64) -> usize {
+    let depth = if depth == 0 { DEFAULT_MAX_CALL_DEPTH } else { depth };
+    depth.saturating_mul(STACK_BYTES_PER_CALL).min(4 << 30) as usize
+}
+
+thread_local! {
+    static MAX_CALL_DEPTH: Cell<u64> = const { Cell::new(DEFAULT_MAX_CALL_DEPTH) };
+    static CALL_DEPTH: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Sets `--max-call-depth`: a call made while `limit` calls are already in
+/// progress fails with a catchable error instead of overflowing the stack.
+/// Zero is no limit.
+pub fn set_max_call_depth(limit: u64) {
+    MAX_CALL_DEPTH.with(|max| max.set(limit));
+}
+
+pub fn max_call_depth() -> u64 {
+    MAX_CALL_DEPTH.with(Cell::get)
+}
+
+/// Marks a user function call as in progress until dropped.
+pub struct CallDepth(());
+
+impl CallDepth {
+    /// Counts one more call in progress, or `None` when that would pass
+    /// `--max-call-depth`.
+    #[inline]
+    pub fn enter() -> Option<CallDepth> {
+        CALL_DEPTH.with(|depth| {
+            let limit = max_call_depth();
+            if limit != 0 && depth.get() >= limit {
+                return None;
+            }
+            depth.set(depth.get() + 1);
+            Some(CallDepth(()))
+        })
+    }
+}
+
+impl Drop for CallDepth {
+    #[inline]
+    fn drop(&mut self) {
+        CALL_DEPTH.with(|depth| depth.set(depth.get().saturating_sub(1)));
+    }
+}
+
+pub fn call_depth_error(line: usize, column: usize) -> ZekkenError {
+    ZekkenError::runtime(
+        "maximum recursion depth exceeded",
+        line,
+        column,
+        Some(&format!("{} calls were already in progress; raise --max-call-depth if the recursion is meant to go deeper", max_call_depth())),
+    )
+}
@@ -0,0 +1,147 @@
+//! Line coverage for `zekken test --coverage`.
+//!
+//! While recording, each statement or expression run as part of a body notes
+//! its line: the evaluators record as they go, and the bytecode compiler emits
+//! a `Cover` instruction in front of every statement it compiles. A [`Report`]
+//! pairs those hits with the line of every statement in the program, so bodies
+//! that never ran (an untaken branch, an uncalled function) show up as missed.
+//!
+//! Lines are the only key, so statements run from included files count
+//! towards the same line numbers of the file under test.
+//!
+//! The same hooks feed [`trace`](crate::trace), which notes every statement
+//! run while it records.
+
+// Only `zekken test` reads the hits back; the wasm build just carries the hooks.
+#![allow(dead_code)]
+
+use crate::ast::visit::{walk_block, Visitor};
+use crate::ast::{Content, Program, Stmt};
+use crate::diagnostics::content_location;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+
+thread_local! {
+    static RECORDING: Cell<bool> = const { Cell::new(false) };
+    static HITS: RefCell<HashMap<usize, u64>> = RefCell::new(HashMap::new());
+}
+
+/// Clears any earlier hits and starts recording.
+pub fn start() {
+    HITS.with(|hits| hits.borrow_mut().clear());
+    RECORDING.with(|r| r.set(true));
+}
+
+/// Stops recording and hands back how often each line ran.
+pub fn stop() -> HashMap<usize, u64> {
+    RECORDING.with(|r| r.set(false));
+    HITS.with(|hits| std::mem::take(&mut *hits.borrow_mut()))
+}
+
+#[inline]
+pub fn is_recording() -> bool {
+    RECORDING.with(Cell::get)
+}
+
+/// Notes one run of the statement starting on `line`.
+#[inline]
+pub fn line(line: usize) {
+    if is_recording() {
+        HITS.with(|hits| *hits.borrow_mut().entry(line).or_insert(0) += 1);
+    }
+    crate::trace::statement(line);
+}
+
+/// The line `content` is counted on. An `else { ... }` block is only a
+/// wrapper (located after its closing brace), so its statements count instead.
+pub fn line_of(content: &Content) -> Option<usize> {
+    match content {
+        Content::Statement(stmt) if matches!(stmt.as_ref(), Stmt::BlockStmt(_)) => None,
+        _ => Some(content_location(content).line),
+    }
+}
+
+/// Notes one run of `content`.
+#[inline]
+pub fn content(content: &Content) {
+    if is_recording() || crate::trace::is_recording() {
+        if let Some(n) = line_of(content) {
+            line(n);
+        }
+    }
+}
+
+/// Every line that starts a statement, each with its hit count.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub lines: BTreeMap<usize, u64>,
+}
+
+struct StatementLines(BTreeMap<usize, u64>);
+
+impl Visitor for StatementLines {
+    fn visit_block(&mut self, body: &[Content]) {
+        self.0.extend(body.iter().filter_map(line_of).map(|line| (line, 0)));
+        walk_block(self, body);
+    }
+}
+
+impl Report {
+    pub fn new(program: &Program, hits: &HashMap<usize, u64>) -> Self {
+        let mut lines = StatementLines(BTreeMap::new());
+        lines.visit_block(&program.imports);
+        lines.visit_block(&program.content);
+        let mut lines = lines.0;
+        for (line, count) in lines.iter_mut() {
+            *count = hits.get(line).copied().unwrap_or(0);
+        }
+        Report { lines }
+    }
+
+    pub fn hit(&self) -> usize {
+        self.lines.values().filter(|count| **count > 0).count()
+    }
+
+    pub fn missed(&self) -> impl Iterator<Item = usize> + '_ {
+        self.lines.iter().filter(|(_, count)| **count == 0).map(|(line, _)| *line)
+    }
+
+    /// The report as an lcov tracefile for `source`.
+    pub fn to_lcov(&self, source: &str) -> String {
+        let mut out = format!("TN:\nSF:{}\n", source);
+        for (line, count) in &self.lines {
+            out.push_str(&format!("DA:{},{}\n", line, count));
+        }
+        out.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", self.lines.len(), self.hit()));
+        out
+    }
+}
+
+/// Collapses sorted line numbers into `4, 9-11` style ranges.
+fn ranges(lines: impl Iterator<Item = usize>) -> String {
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    for line in lines {
+        match spans.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => spans.push((line, line)),
+        }
+    }
+    spans
+        .iter()
+        .map(|(start, end)| if start == end { start.to_string() } else { format!("{}-{}", start, end) })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let total = self.lines.len();
+        let percent = if total == 0 { 100.0 } else { self.hit() as f64 * 100.0 / total as f64 };
+        writeln!(f, "Coverage: {}/{} statement lines ({:.1}%)", self.hit(), total, percent)?;
+        if self.hit() < total {
+            writeln!(f, "  not run: {}", ranges(self.missed()))?;
+        }
+        Ok(())
+    }
+}
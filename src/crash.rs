@@ -0,0 +1,167 @@
+//! Local crash reports for panics inside the interpreter itself.
+//!
+//! A panic is always a bug in Zekken, never in the script, so the hook set up
+//! by [`install`] writes what is needed to reproduce it to a file in the temp
+//! directory: the interpreter version, the panic, the script, and the tokens
+//! and AST of the top-level statement that was running. Nothing is sent
+//! anywhere; the user is asked to attach the file to an issue themselves.
+
+// Only the CLI installs the hook; the wasm build just notes statements.
+#![allow(dead_code)]
+
+use crate::ast::{Content, Program};
+use crate::diagnostics::content_location;
+use crate::lexer::tokenize;
+use crate::parser::Parser;
+use std::cell::Cell;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::sync::{Mutex, Once};
+
+pub const ISSUES_URL: &str = "https://github.com/OzRAGEHarm/Zekken/issues";
+
+/// Most tokens listed when the crash happened before any statement ran.
+const MAX_TOKENS: usize = 500;
+
+thread_local! {
+    // Line of the top-level statement being run; zero before the first one.
+    static STATEMENT_LINE: Cell<usize> = const { Cell::new(0) };
+}
+
+lazy_static::lazy_static! {
+    static ref SCRIPT: Mutex<Option<(String, String)>> = Mutex::new(None);
+}
+
+/// Notes that the top-level statement starting on `line` is about to run.
+#[inline]
+pub fn statement(line: usize) {
+    STATEMENT_LINE.with(|current| current.set(line));
+}
+
+/// Records the script a crash report should include.
+pub fn watch(file: &str, source: &str) {
+    *SCRIPT.lock().unwrap() = Some((file.to_string(), source.to_string()));
+    statement(0);
+}
+
+/// Installs the panic hook. The default hook still prints the panic first.
+pub fn install() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            default_hook(info);
+            let payload = info.payload();
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "(no message)".to_string());
+            let panic = match info.location() {
+                Some(location) => format!("{} at {}", message, location),
+                None => message,
+            };
+            // Never wait on the lock: the panic may have happened while it was held.
+            let script = SCRIPT.try_lock().ok().and_then(|script| script.clone());
+            let (file, source) = script.unwrap_or_default();
+            let line = STATEMENT_LINE.with(Cell::get);
+            eprintln!("{}", instructions(write_report(&file, &source, line, &panic).as_ref()));
+        }));
+    });
+}
+
+fn write_report(file: &str, source: &str, line: usize, panic: &str) -> Result<PathBuf, String> {
+    let stamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    let path = std::env::temp_dir().join(format!("zekken-crash-{}-{}.txt", stamp, std::process::id()));
+    std::fs::write(&path, render(file, source, line, panic))
+        .map(|_| path.clone())
+        .map_err(|err| format!("could not write {}: {}", path.display(), err))
+}
+
+fn instructions(report: Result<&PathBuf, &String>) -> String {
+    let mut out = String::from("\nZekken crashed. This is a bug in the interpreter, not in your script.\n");
+    match report {
+        Ok(path) => {
+            let _ = writeln!(out, "A crash report was written to {}", path.display());
+            let _ = writeln!(
+                out,
+                "Please open an issue at {} and attach it. The report contains your script's source, so remove anything private first.",
+                ISSUES_URL
+            );
+        }
+        Err(err) => {
+            let _ = writeln!(out, "No crash report was written ({}).", err);
+            let _ = writeln!(out, "Please open an issue at {} with the script and the message above.", ISSUES_URL);
+        }
+    }
+    out.push_str("Nothing was sent anywhere.");
+    out
+}
+
+/// The top-level statement starting on `line`, and the line the next one
+/// starts on.
+fn statement_at(program: &Program, line: usize) -> Option<(&Content, Option<usize>)> {
+    let contents: Vec<&Content> = program.imports.iter().chain(&program.content).collect();
+    let index = contents.iter().position(|content| content_location(content).line == line)?;
+    let next = contents.get(index + 1).map(|content| content_location(content).line);
+    Some((contents[index], next))
+}
+
+/// The text of a crash report. `line` is the top-level statement that was
+/// running, or zero if none had started.
+pub fn render(file: &str, source: &str, line: usize, panic: &str) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "Zekken crash report");
+    let _ = writeln!(out, "version: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(out, "platform: {}-{}", std::env::consts::OS, std::env::consts::ARCH);
+    let _ = writeln!(out, "command: {}", std::env::args().collect::<Vec<_>>().join(" "));
+    let _ = writeln!(out, "panic: {}", panic);
+    let _ = writeln!(out, "script: {}", if file.is_empty() { "(none)" } else { file });
+
+    // Parsing again can only be trusted if parsing was not what crashed.
+    let program = (line > 0).then(|| Parser::new().produce_ast(source.to_string()));
+    let found = program.as_ref().and_then(|program| statement_at(program, line));
+    let lines = match found {
+        Some((_, Some(next))) => line..next.max(line + 1),
+        Some((_, None)) => line..usize::MAX,
+        None => 1..usize::MAX,
+    };
+
+    let _ = writeln!(out, "\n== Running statement ==");
+    if line == 0 {
+        let _ = writeln!(out, "(none; the crash happened before the script started running)");
+    } else {
+        let _ = writeln!(out, "line {}", line);
+        for (n, text) in source.lines().enumerate().map(|(i, text)| (i + 1, text)) {
+            if lines.contains(&n) {
+                let _ = writeln!(out, "{:>4} | {}", n, text);
+            }
+        }
+    }
+
+    let _ = writeln!(out, "\n== Tokens ==");
+    let tokens = tokenize(source.to_string());
+    for token in tokens.iter().filter(|token| lines.contains(&token.line)).take(MAX_TOKENS) {
+        let _ = writeln!(out, "{}:{} {:?} {:?}", token.line, token.column, token.kind, token.value);
+    }
+
+    let _ = writeln!(out, "\n== AST ==");
+    match found {
+        Some((content, _)) => {
+            let _ = writeln!(out, "{:#?}", content);
+        }
+        None => {
+            let _ = writeln!(out, "(not available)");
+        }
+    }
+
+    let _ = writeln!(out, "\n== Source ==");
+    out.push_str(source);
+    if !source.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
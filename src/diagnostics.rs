@@ -1,16 +1,22 @@
-use crate::ast::{Content, Expr, Location, Stmt};
+use crate::ast::{ArrayLit, CallExpr, Content, Expr, Identifier, IncludeStmt, Location, Program, Stmt, StringLit};
 use crate::bytecode;
-use crate::environment::{Environment, FunctionValue, Value};
+use crate::environment::snapshot::EnvDiff;
+use crate::environment::{enum_value, Environment, FunctionValue, Value};
 use crate::errors::{
     clear_collected_errors, extract_exit_code, sort_and_dedup_errors, take_collected_errors,
     ZekkenError,
 };
 use crate::eval::expression::evaluate_expression;
-use crate::eval::lint::{collect_lint_expression, collect_lint_statement};
+use crate::eval::include::{included_bindings, library_names};
+use crate::eval::deprecated::collect_deprecations;
+use crate::eval::lint::{collect_include_side_effects, collect_lint_expression, collect_lint_statement};
 use crate::eval::statement::evaluate_statement;
 use crate::lexer::DataType;
+use crate::parser::Parser;
 use hashbrown::HashMap;
+use indexmap::IndexMap;
 use std::collections::HashSet;
+use std::path::Path;
 use std::sync::Arc;
 
 #[allow(dead_code)]
@@ -27,15 +33,17 @@ pub struct RunReport {
     pub exit_code: Option<i32>,
 }
 
-fn dummy_value(ty: DataType) -> Value {
+fn dummy_value(ty: &DataType) -> Value {
     match ty {
         DataType::Int => Value::Int(0),
         DataType::Float => Value::Float(0.0),
         DataType::String => Value::String(String::new()),
         DataType::Bool => Value::Boolean(false),
-        DataType::Array => Value::Array(Vec::new()),
-        DataType::Object => Value::Object(HashMap::new()),
+        DataType::Array | DataType::ArrayOf(_) => Value::Array(Vec::new()),
+        DataType::Nullable(inner) => dummy_value(inner),
+        DataType::Object => Value::Object(IndexMap::new()),
         DataType::Fn => Value::Function(FunctionValue {
+            name: Arc::from(""),
             params: Arc::new(Vec::new()),
             body: Arc::new(Vec::new()),
             return_type: None,
@@ -44,12 +52,14 @@ fn dummy_value(ty: DataType) -> Value {
             capture_values: Arc::new(HashMap::new()),
             compiled_insts: None,
             compiled_reg_count: 0,
+            closure: None,
         }),
+        DataType::Decimal => Value::Decimal(crate::environment::decimal::Decimal::from_int(0)),
         DataType::Any => Value::Void,
     }
 }
 
-fn content_location(content: &Content) -> Location {
+pub(crate) fn content_location(content: &Content) -> Location {
     match content {
         Content::Statement(stmt) => match stmt.as_ref() {
             Stmt::Program(node) => node.location.clone(),
@@ -58,7 +68,11 @@ fn content_location(content: &Content) -> Location {
             Stmt::ObjectDecl(node) => node.location.clone(),
             Stmt::IfStmt(node) => node.location.clone(),
             Stmt::ForStmt(node) => node.location.clone(),
+            Stmt::ForIn(node) => node.location.clone(),
             Stmt::WhileStmt(node) => node.location.clone(),
+            Stmt::Loop(node) => node.location.clone(),
+            Stmt::Break(node) => node.location.clone(),
+            Stmt::Continue(node) => node.location.clone(),
             Stmt::TryCatchStmt(node) => node.location.clone(),
             Stmt::BlockStmt(node) => node.location.clone(),
             Stmt::Use(node) => node.location.clone(),
@@ -66,26 +80,38 @@ fn content_location(content: &Content) -> Location {
             Stmt::Export(node) => node.location.clone(),
             Stmt::Return(node) => node.location.clone(),
             Stmt::Lambda(node) => node.location.clone(),
+            Stmt::EnumDecl(node) => node.location.clone(),
+            Stmt::Defer(node) => node.location.clone(),
+            Stmt::With(node) => node.location.clone(),
+            Stmt::Switch(node) => node.location.clone(),
         },
         Content::Expression(expr) => match expr.as_ref() {
             Expr::Assign(node) => node.location.clone(),
             Expr::Member(node) => node.location.clone(),
             Expr::Call(node) => node.location.clone(),
+            Expr::MethodCall(node) => node.location.clone(),
             Expr::Unary(node) => node.location.clone(),
             Expr::Binary(node) => node.location.clone(),
             Expr::Identifier(node) => node.location.clone(),
             Expr::Property(node) => node.location.clone(),
             Expr::IntLit(node) => node.location.clone(),
             Expr::FloatLit(node) => node.location.clone(),
+            Expr::DecimalLit(node) => node.location.clone(),
             Expr::StringLit(node) => node.location.clone(),
+            Expr::TemplateLit(node) => node.location.clone(),
             Expr::BoolLit(node) => node.location.clone(),
+            Expr::NullLit(node) => node.location.clone(),
             Expr::ArrayLit(node) => node.location.clone(),
+            Expr::VectorLit(node) => node.location.clone(),
+            Expr::MatrixLit(node) => node.location.clone(),
             Expr::ObjectLit(node) => node.location.clone(),
+            Expr::Match(node) => node.location.clone(),
+            Expr::Lambda(node) => node.location.clone(),
         },
     }
 }
 
-fn declare_shape(content: &Content, env: &mut Environment) {
+pub(crate) fn declare_shape(content: &Content, env: &mut Environment) {
     let stmt = match content {
         Content::Statement(stmt) => stmt.as_ref(),
         Content::Expression(_) => return,
@@ -94,21 +120,23 @@ fn declare_shape(content: &Content, env: &mut Environment) {
     match stmt {
         Stmt::VarDecl(decl) => env.declare_ref_typed(
             &decl.ident,
-            dummy_value(decl.type_),
-            decl.type_,
+            dummy_value(&decl.type_),
+            decl.type_.clone(),
             decl.constant,
         ),
         Stmt::FuncDecl(decl) => env.declare_ref_typed(
             &decl.ident,
             Value::Function(FunctionValue {
+                name: Arc::from(decl.ident.as_str()),
                 params: Arc::new(decl.params.clone()),
                 body: Arc::new(decl.body.clone()),
-                return_type: decl.return_type,
+                return_type: decl.return_type.clone(),
                 needs_parent: true,
                 captures: Arc::new(Vec::new()),
                 capture_values: Arc::new(HashMap::new()),
                 compiled_insts: None,
                 compiled_reg_count: 0,
+                closure: None,
             }),
             DataType::Fn,
             false,
@@ -116,24 +144,34 @@ fn declare_shape(content: &Content, env: &mut Environment) {
         Stmt::Lambda(decl) => env.declare_ref_typed(
             &decl.ident,
             Value::Function(FunctionValue {
+                name: Arc::from(decl.ident.as_str()),
                 params: Arc::new(decl.params.clone()),
                 body: Arc::new(decl.body.clone()),
-                return_type: decl.return_type,
+                return_type: decl.return_type.clone(),
                 needs_parent: true,
                 captures: Arc::new(Vec::new()),
                 capture_values: Arc::new(HashMap::new()),
                 compiled_insts: None,
                 compiled_reg_count: 0,
+                closure: None,
             }),
             DataType::Fn,
             decl.constant,
         ),
         Stmt::ObjectDecl(decl) => env.declare_ref_typed(
             &decl.ident,
-            Value::Object(HashMap::new()),
+            Value::Object(IndexMap::new()),
             DataType::Object,
             false,
         ),
+        // Enum declarations have no side effects, so the real value is used
+        // and variant names can be checked.
+        Stmt::EnumDecl(decl) => env.declare_ref_typed(
+            &decl.ident,
+            enum_value(&decl.ident, &decl.variants),
+            DataType::Object,
+            true,
+        ),
         _ => {}
     }
 }
@@ -143,15 +181,16 @@ fn execute_content(
     env: &mut Environment,
     mode: ExecutionMode,
 ) -> Result<Option<Value>, ZekkenError> {
+    crate::crash::statement(content_location(content).line);
     match mode {
-        ExecutionMode::Bytecode => {
-            let boxed = Box::new(content.clone());
-            bytecode::execute_contents(std::slice::from_ref(&boxed), env)
+        ExecutionMode::Bytecode => bytecode::execute_contents(std::slice::from_ref(content), env),
+        ExecutionMode::TreeWalk => {
+            crate::coverage::content(content);
+            match content {
+                Content::Statement(stmt) => evaluate_statement(stmt, env).map(|value| env.take_return().or(value)),
+                Content::Expression(expr) => evaluate_expression(expr, env).map(Some),
+            }
         }
-        ExecutionMode::TreeWalk => match content {
-            Content::Statement(stmt) => evaluate_statement(stmt, env),
-            Content::Expression(expr) => evaluate_expression(expr, env).map(Some),
-        },
     }
 }
 
@@ -167,6 +206,7 @@ fn append_runtime_result(
             if let Some(code) = extract_exit_code(&error.message) {
                 *exit_code = Some(code);
             } else {
+                crate::trace::error(&error, false);
                 errors.push(error);
             }
         }
@@ -174,13 +214,212 @@ fn append_runtime_result(
     errors.extend(take_collected_errors());
 }
 
+// Lint the whole program up front, with every top-level declaration visible
+// so functions can be referenced before the line that defines them.
+fn lint_program(program: &Program, env: &Environment, errors: &mut Vec<ZekkenError>) {
+    let mut analysis_env = env.clone();
+    for content in &program.content {
+        declare_shape(content, &mut analysis_env);
+    }
+    for content in &program.content {
+        match content {
+            Content::Statement(stmt) => collect_lint_statement(stmt, &analysis_env, errors),
+            Content::Expression(expr) => collect_lint_expression(expr, &analysis_env, errors),
+        }
+    }    collect_include_side_effects(program, errors);
+    collect_deprecations(program, errors);
+}
+
+// Declare what an included file would bring into scope without running it:
+// its own imports are followed recursively and only the shape of its
+// top-level declarations is copied into `env`.
+fn declare_include_shape(
+    include: &IncludeStmt,
+    env: &mut Environment,
+    errors: &mut Vec<ZekkenError>,
+    visiting: &mut HashSet<std::path::PathBuf>,
+) {
+    let current_file = std::env::var("ZEKKEN_CURRENT_FILE").unwrap_or_default();
+    let path = Path::new(&current_file)
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(&include.file_path);
+    let source = match std::fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(e) => {
+            errors.push(ZekkenError::runtime(
+                &format!("Failed to include file '{}': {}", path.display(), e),
+                include.location.line,
+                include.location.column,
+                None,
+            ));
+            return;
+        }
+    };
+    let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+    if !visiting.insert(key.clone()) {
+        return;
+    }
+
+    std::env::set_var("ZEKKEN_CURRENT_FILE", &path);
+    let mut parser = Parser::new();
+    let included = parser.produce_ast(source);
+    errors.extend(parser.errors);
+    let libraries = library_names(&included);
+    let mut child_env = Environment::new();
+    for import in &included.imports {
+        if let Content::Statement(stmt) = import {
+            match stmt.as_ref() {
+                Stmt::Include(nested) => declare_include_shape(nested, &mut child_env, errors, visiting),
+                Stmt::Use(_) => {
+                    let _ = evaluate_statement(stmt, &mut child_env);
+                }
+                _ => {}
+            }
+        }
+    }
+    for content in &included.content {
+        declare_shape(content, &mut child_env);
+    }
+    std::env::set_var("ZEKKEN_CURRENT_FILE", &current_file);
+    visiting.remove(&key);
+
+    match included_bindings(include, &libraries, &child_env) {
+        Ok(bindings) => {
+            for (name, value) in bindings {
+                env.declare(name, value, false);
+            }
+        }
+        Err(msg) => errors.push(ZekkenError::runtime(&msg, include.location.line, include.location.column, None)),
+    }
+}
+
+/// Reports every diagnostic `run` would report before execution, without
+/// running the program. Libraries named by `use` are loaded; included files
+/// are parsed but not executed.
+#[allow(dead_code)]
+pub fn check_program(
+    program: &Program,
+    syntax_errors: &[ZekkenError],
+    env: &mut Environment,
+) -> Vec<ZekkenError> {
+    clear_collected_errors();
+    let mut errors = syntax_errors.to_vec();
+    let mut visiting = HashSet::new();
+
+    for import in &program.imports {
+        if let Content::Statement(stmt) = import {
+            match stmt.as_ref() {
+                Stmt::Include(include) => declare_include_shape(include, env, &mut errors, &mut visiting),
+                Stmt::Use(_) => {
+                    if let Err(error) = evaluate_statement(stmt, env) {
+                        errors.push(error);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    lint_program(program, env, &mut errors);
+    errors.extend(take_collected_errors());
+    sort_and_dedup_errors(&mut errors);
+    errors
+}
+
 pub fn run_program_collecting(
     program: &crate::ast::Program,
     syntax_errors: &[ZekkenError],
     env: &mut Environment,
     mode: ExecutionMode,
+) -> RunReport {
+    run_program(program, syntax_errors, env, mode, None)
+}
+
+/// Calls the script's own `func main`, if it declares one, once its top level
+/// has run without errors. `args` are passed as an array of strings when
+/// `main` takes a parameter, and an int it returns becomes the exit code if it is
+/// from 0 to 255, the range a process can exit with.
+#[allow(dead_code)]
+pub fn run_main(program: &Program, env: &mut Environment, mode: ExecutionMode, args: &[String], report: &mut RunReport) {
+    if !report.errors.is_empty() || report.exit_code.is_some() {
+        return;
+    }
+    let main = program.content.iter().find_map(|content| match content {
+        Content::Statement(stmt) => match stmt.as_ref() {
+            Stmt::FuncDecl(decl) if decl.ident == "main" => Some(decl),
+            _ => None,
+        },
+        Content::Expression(_) => None,
+    });
+    let Some(main) = main else { return };
+
+    let location = &main.location;
+    let call_args = if main.params.is_empty() {
+        Vec::new()
+    } else {
+        let elements = args
+            .iter()
+            .map(|arg| Box::new(Expr::StringLit(StringLit { value: arg.clone(), raw: false, location: location.clone() })))
+            .collect();
+        vec![Box::new(Expr::ArrayLit(ArrayLit { elements, location: location.clone() }))]
+    };
+    let call = Content::Expression(Box::new(Expr::Call(CallExpr {
+        callee: Box::new(Expr::Identifier(Identifier { name: "main".to_string(), location: location.clone() })),
+        args: call_args,
+        arg_names: Vec::new(),
+        is_native: false,
+        location: location.clone(),
+    })));
+
+    let mut value = None;
+    append_runtime_result(execute_content(&call, env, mode), &mut report.errors, &mut value, &mut report.exit_code);
+    match value {
+        Some(Value::Int(code @ 0..=255)) => report.exit_code = Some(code as i32),
+        Some(Value::Int(code)) => report.errors.push(ZekkenError::runtime(
+            &format!("'main' returned {}, but an exit code must be between 0 and 255", code),
+            location.line,
+            location.column,
+            None,
+        )),
+        Some(Value::Void | Value::Null) | None => {}
+        Some(other) => report.errors.push(ZekkenError::type_error(
+            "'main' must return an int exit code",
+            "int",
+            other.type_name(),
+            location.line,
+            location.column,
+        )),
+    }
+}
+
+/// Called after each top-level statement by [`run_program_stepping`].
+pub type StepHook<'a> = &'a mut dyn FnMut(&Location, &EnvDiff, &Environment) -> bool;
+
+/// Runs the program like [`run_program_collecting`], calling `after_step` with
+/// the location of each top-level statement once it has run, the bindings it
+/// changed and the scope it ran in. Execution stops early when `after_step`
+/// returns false.
+#[allow(dead_code)]
+pub fn run_program_stepping(
+    program: &crate::ast::Program,
+    syntax_errors: &[ZekkenError],
+    env: &mut Environment,
+    mode: ExecutionMode,
+    after_step: StepHook,
+) -> RunReport {
+    run_program(program, syntax_errors, env, mode, Some(after_step))
+}
+
+fn run_program(
+    program: &crate::ast::Program,
+    syntax_errors: &[ZekkenError],
+    env: &mut Environment,
+    mode: ExecutionMode,
+    mut after_step: Option<StepHook>,
 ) -> RunReport {
     clear_collected_errors();
+    crate::stats::enable_for(program);
     let mut errors = syntax_errors.to_vec();
     let invalid_lines: HashSet<usize> = syntax_errors
         .iter()
@@ -201,24 +440,17 @@ pub fn run_program_collecting(
         }
     }
 
-    let mut analysis_env = env.clone();
-    for content in &program.content {
-        declare_shape(content, &mut analysis_env);
-    }
-    for content in &program.content {
-        match content.as_ref() {
-            Content::Statement(stmt) => collect_lint_statement(stmt, &analysis_env, &mut errors),
-            Content::Expression(expr) => collect_lint_expression(expr, &analysis_env, &mut errors),
-        }
-    }
+    lint_program(program, env, &mut errors);
 
     if !errors.is_empty() {
-        env.declare_ref_typed(
-            "println",
-            Value::NativeFunction(Arc::new(|_| Ok(Value::Void))),
-            DataType::Fn,
-            true,
-        );
+        for name in ["println", "print"] {
+            env.declare_ref_typed(
+                name,
+                Value::NativeFunction(Arc::new(|_| Ok(Value::Void))),
+                DataType::Fn,
+                true,
+            );
+        }
     }
 
     if exit_code.is_none() {
@@ -228,6 +460,7 @@ pub fn run_program_collecting(
                 continue;
             }
 
+            let before = after_step.is_some().then(|| env.snapshot());
             let result = execute_content(content, env, mode);
             let failed = result.is_err();
             append_runtime_result(result, &mut errors, &mut value, &mut exit_code);
@@ -237,6 +470,19 @@ pub fn run_program_collecting(
             if exit_code.is_some() {
                 break;
             }
+            if let (Some(step), Some(before)) = (after_step.as_mut(), before) {
+                if !step(&content_location(content), &before.diff(&env.snapshot()), env) {
+                    break;
+                }
+            }
+        }
+    }
+
+    // Top-level `defer`s run once the whole file has, unless it exited.
+    while exit_code.is_none() {
+        let Some(expr) = env.deferred.pop() else { break };
+        if let Err(error) = execute_content(&Content::Expression(Box::new(expr)), env, mode) {
+            append_runtime_result(Err(error), &mut errors, &mut value, &mut exit_code);
         }
     }
 
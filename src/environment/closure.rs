@@ -0,0 +1,302 @@
+//! Variables shared between a call and the functions created during it.
+//!
+//! A function created inside a call keeps a [`SharedScope`] of that call
+//! rather than copies of its locals. The call publishes the locals the new
+//! function names into that scope. Each call of the function copies them in
+//! and writes them back when it returns, and both sides write theirs out
+//! before calling anything and read them back afterwards, so an assignment on
+//! either side is seen by the other.
+
+use super::{Environment, Value};
+use crate::ast::visit::{walk_expr, walk_stmt, Visitor};
+use crate::ast::*;
+use crate::eval::pattern::bound_names;
+use crate::lexer::DataType;
+use hashbrown::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+#[derive(Debug, Clone)]
+struct Binding {
+    value: Value,
+    ty: DataType,
+    constant: bool,
+}
+
+#[derive(Default)]
+struct Frame {
+    bindings: HashMap<String, Binding>,
+    parent: Option<SharedScope>,
+}
+
+/// The locals of one call that functions created during it can see, and
+/// through `parent` those of the calls around it.
+#[derive(Clone, Default)]
+pub struct SharedScope(Arc<Mutex<Frame>>);
+
+impl std::fmt::Debug for SharedScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SharedScope").field(&self.lock().bindings.keys().collect::<Vec<_>>()).finish()
+    }
+}
+
+impl SharedScope {
+    fn new(parent: Option<SharedScope>) -> Self {
+        SharedScope(Arc::new(Mutex::new(Frame { bindings: HashMap::new(), parent })))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Frame> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The nearest binding of `name`, this scope first.
+    fn get(&self, name: &str) -> Option<Binding> {
+        let mut scope = self.clone();
+        loop {
+            let frame = scope.lock();
+            if let Some(binding) = frame.bindings.get(name) {
+                return Some(binding.clone());
+            }
+            let parent = frame.parent.clone()?;
+            drop(frame);
+            scope = parent;
+        }
+    }
+
+    /// Updates the nearest binding of `name`, if there is one.
+    fn set(&self, name: &str, value: &Value) {
+        let mut scope = self.clone();
+        loop {
+            let mut frame = scope.lock();
+            if let Some(binding) = frame.bindings.get_mut(name) {
+                binding.value = value.clone();
+                return;
+            }
+            let Some(parent) = frame.parent.clone() else { return };
+            drop(frame);
+            scope = parent;
+        }
+    }
+}
+
+/// What a function created inside a call closes over: the scope of that
+/// call, and the names from it or the scopes around it that the body uses.
+#[derive(Debug, Clone)]
+pub struct Closure {
+    scope: SharedScope,
+    names: Arc<Vec<String>>,
+}
+
+impl Closure {
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+}
+
+/// The sharing state of a running call: the closure of the function being
+/// called, and the scope it shares with functions it creates itself.
+#[derive(Debug, Clone, Default)]
+pub struct CallScope {
+    captured: Option<Closure>,
+    own: Option<SharedScope>,
+    /// Set for the scope of a call made without a parent scope, which
+    /// cannot reach the globals.
+    detached: bool,
+}
+
+impl CallScope {
+    pub fn detached() -> Self {
+        CallScope { detached: true, ..CallScope::default() }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.captured.is_none() && self.own.is_none()
+    }
+
+    pub fn is_detached(&self) -> bool {
+        self.detached
+    }
+}
+
+impl Environment {
+    fn own_binding(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name).or_else(|| self.constants.get(name))
+    }
+
+    fn set_own_binding(&mut self, name: &str, value: Value) {
+        if let Some(slot) = self.variables.get_mut(name) {
+            *slot = value;
+        } else if let Some(slot) = self.constants.get_mut(name) {
+            *slot = value;
+        }
+    }
+
+    /// `name` as seen by a function called from this scope without a parent
+    /// scope of its own: the global, else the value it `captured` when it
+    /// was created. From a scope that cannot reach the globals, this scope's
+    /// own binding comes last.
+    pub fn lookup_for_call<'a>(&'a self, name: &str, captured: &'a HashMap<String, Value>) -> Option<&'a Value> {
+        match self.globals() {
+            Some(globals) => globals.lookup_ref(name).or_else(|| captured.get(name)),
+            None => captured.get(name).or_else(|| self.lookup_ref(name)),
+        }
+    }
+
+    /// The closure of a function named `name` created in this scope. The
+    /// locals its body uses are published to this call's shared scope;
+    /// names this call got from its own closure are already shared. `None`
+    /// in the outermost scope, where functions find globals through their
+    /// parent scope, or when the body uses nothing from around it.
+    pub fn share_with(&mut self, name: &str, params: &[Param], body: &[Content]) -> Option<Closure> {
+        self.parent.as_ref()?;
+        let mut names = Vec::new();
+        for free in free_names(params, body) {
+            if free == name {
+                continue;
+            }
+            let inherited = self.shared.captured.as_ref().is_some_and(|closure| closure.names.binary_search(&free).is_ok());
+            if !inherited {
+                let Some(value) = self.own_binding(&free).cloned() else { continue };
+                let binding = Binding {
+                    ty: self.types.get(&free).cloned().unwrap_or(DataType::Any),
+                    constant: self.constants.contains_key(&free),
+                    value,
+                };
+                let parent = self.shared.captured.as_ref().map(|closure| closure.scope.clone());
+                let own = self.shared.own.get_or_insert_with(|| SharedScope::new(parent));
+                own.lock().bindings.insert(free.clone(), binding);
+            }
+            names.push(free);
+        }
+        if names.is_empty() {
+            return None;
+        }
+        names.sort_unstable();
+        let parent = self.shared.captured.as_ref().map(|closure| closure.scope.clone());
+        let scope = self.shared.own.get_or_insert_with(|| SharedScope::new(parent)).clone();
+        Some(Closure { scope, names: Arc::new(names) })
+    }
+
+    /// Declares what `closure` shares in this scope, the scope of a call
+    /// before its parameters are bound.
+    pub fn enter_closure(&mut self, closure: &Closure) {
+        for name in closure.names.iter() {
+            if let Some(binding) = closure.scope.get(name) {
+                self.declare_ref_typed(name, binding.value, binding.ty, binding.constant);
+            }
+        }
+        self.shared.captured = Some(closure.clone());
+    }
+
+    /// Writes this scope's shared locals out, before a call and when the
+    /// call this scope belongs to returns.
+    pub fn sync_shared(&self) {
+        if self.shared.is_empty() {
+            return;
+        }
+        if let Some(closure) = &self.shared.captured {
+            for name in closure.names.iter() {
+                if let Some(value) = self.own_binding(name) {
+                    closure.scope.set(name, value);
+                }
+            }
+        }
+        if let Some(own) = &self.shared.own {
+            for (name, binding) in own.lock().bindings.iter_mut() {
+                if let Some(value) = self.own_binding(name) {
+                    binding.value = value.clone();
+                }
+            }
+        }
+    }
+
+    /// Reads this scope's shared locals back after a call, which may have
+    /// assigned to them.
+    pub fn reload_shared(&mut self) {
+        if self.shared.is_empty() {
+            return;
+        }
+        if let Some(closure) = self.shared.captured.clone() {
+            for name in closure.names.iter() {
+                if let Some(binding) = closure.scope.get(name) {
+                    self.set_own_binding(name, binding.value);
+                }
+            }
+        }
+        if let Some(own) = self.shared.own.clone() {
+            for (name, binding) in own.lock().bindings.iter() {
+                self.set_own_binding(name, binding.value.clone());
+            }
+        }
+    }
+}
+
+/// The names a function body uses without declaring them itself, including
+/// the ones used by functions nested in it.
+fn free_names(params: &[Param], body: &[Content]) -> HashSet<String> {
+    let mut names = Names::default();
+    for param in params {
+        names.declared.insert(param.ident.clone());
+        names.visit_param(param);
+    }
+    names.visit_block(body);
+    names.used.retain(|name| !names.declared.contains(name));
+    names.used
+}
+
+#[derive(Default)]
+struct Names {
+    used: HashSet<String>,
+    declared: HashSet<String>,
+}
+
+impl Visitor for Names {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::FuncDecl(decl) => {
+                self.declared.insert(decl.ident.clone());
+                self.used.extend(free_names(&decl.params, &decl.body));
+                return;
+            }
+            Stmt::Lambda(decl) => {
+                self.declared.insert(decl.ident.clone());
+                self.used.extend(free_names(&decl.params, &decl.body));
+                return;
+            }
+            Stmt::VarDecl(decl) => {
+                self.declared.insert(decl.ident.clone());
+            }
+            Stmt::ObjectDecl(decl) => {
+                self.declared.insert(decl.ident.clone());
+            }
+            Stmt::EnumDecl(decl) => {
+                self.declared.insert(decl.ident.clone());
+            }
+            Stmt::ForIn(stmt) => self.declared.extend(stmt.idents.iter().cloned()),
+            Stmt::TryCatchStmt(stmt) => self.declared.extend(stmt.catch_param.iter().cloned()),
+            Stmt::With(stmt) => {
+                self.declared.insert(stmt.ident.clone());
+            }
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(ident) => {
+                self.used.insert(ident.name.clone());
+            }
+            Expr::Lambda(lambda) => self.used.extend(free_names(&lambda.params, &lambda.body)),
+            Expr::Match(m) => {
+                let mut bound = Vec::new();
+                for arm in &m.arms {
+                    bound_names(&arm.pattern, &mut bound);
+                }
+                self.declared.extend(bound);
+                walk_expr(self, expr);
+            }
+            _ => walk_expr(self, expr),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Exact base-10 numbers for `dec` values.
+//!
+//! A decimal is an integer count of units plus how many of its digits sit
+//! after the decimal point, so `10.50d` is 1050 units at scale 2. Addition,
+//! subtraction and multiplication are exact; division keeps up to
+//! [`DIVISION_SCALE`] digits. The scale a value was written with is kept for
+//! printing (`10.50d` prints as `10.50`) but ignored when comparing.
+
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+
+/// Most digits a decimal keeps after the decimal point.
+pub const MAX_SCALE: u32 = 28;
+
+/// Digits after the decimal point a quotient is worked out to before
+/// trailing zeros are dropped.
+pub const DIVISION_SCALE: u32 = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Decimal {
+    units: i128,
+    scale: u32,
+}
+
+/// How [`Decimal::round`] treats the digits it drops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Halves round away from zero: 2.5 -> 3, -2.5 -> -3.
+    HalfUp,
+    /// Halves round to the even neighbour: 2.5 -> 2, 3.5 -> 4.
+    HalfEven,
+    /// Toward zero.
+    Down,
+    /// Away from zero.
+    Up,
+    /// Toward negative infinity.
+    Floor,
+    /// Toward positive infinity.
+    Ceiling,
+}
+
+impl RoundingMode {
+    pub const NAMES: &'static [&'static str] = &["half_up", "half_even", "down", "up", "floor", "ceiling"];
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "half_up" => Ok(Self::HalfUp),
+            "half_even" => Ok(Self::HalfEven),
+            "down" => Ok(Self::Down),
+            "up" => Ok(Self::Up),
+            "floor" => Ok(Self::Floor),
+            "ceiling" => Ok(Self::Ceiling),
+            _ => Err(format!("Unknown rounding mode '{}' (expected one of: {})", name, Self::NAMES.join(", "))),
+        }
+    }
+}
+
+const OVERFLOW: &str = "Decimal overflow";
+
+fn pow10(exp: u32) -> Option<i128> {
+    10i128.checked_pow(exp)
+}
+
+impl Decimal {
+    pub fn from_int(value: i64) -> Self {
+        Decimal { units: value as i128, scale: 0 }
+    }
+
+    /// Parses `[-]digits[.digits]`, as written in a `10.50d` literal or
+    /// passed to `decimal.parse`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid decimal '{}'", text);
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if whole.is_empty() || !whole.chars().all(|c| c.is_ascii_digit()) || !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        if digits.ends_with('.') {
+            return Err(invalid());
+        }
+        let scale = fraction.len() as u32;
+        if scale > MAX_SCALE {
+            return Err(format!("Decimal '{}' has more than {} digits after the point", text, MAX_SCALE));
+        }
+        let mut units: i128 = 0;
+        for c in whole.chars().chain(fraction.chars()) {
+            units = units
+                .checked_mul(10)
+                .and_then(|u| u.checked_add(c.to_digit(10).unwrap_or(0) as i128))
+                .ok_or_else(|| format!("Decimal '{}' is out of range", text))?;
+        }
+        Ok(Decimal { units: if negative { -units } else { units }, scale })
+    }
+
+    /// The decimal closest to `value` as it prints, so `0.1` becomes exactly
+    /// `0.1` rather than the binary fraction behind it.
+    pub fn from_f64(value: f64) -> Result<Self, String> {
+        if !value.is_finite() {
+            return Err(format!("Cannot convert {} to a decimal", value));
+        }
+        let text = format!("{}", value);
+        if text.contains('e') {
+            return Err(format!("Cannot convert {} to a decimal", value));
+        }
+        let parsed = Decimal::parse(&text)?;
+        Ok(if parsed.scale > MAX_SCALE { parsed.round(MAX_SCALE, RoundingMode::HalfEven) } else { parsed })
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// The whole part, dropping any fraction; `None` outside the int range.
+    pub fn to_i64(self) -> Option<i64> {
+        i64::try_from(self.round(0, RoundingMode::Down).units).ok()
+    }
+
+    /// Digits after the decimal point, as written or produced.
+    pub fn scale(self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.units == 0
+    }
+
+    // The same value with `scale` digits after the point; `scale` must not
+    // be smaller than the current one.
+    fn widen(self, scale: u32) -> Option<Self> {
+        let units = self.units.checked_mul(pow10(scale - self.scale)?)?;
+        Some(Decimal { units, scale })
+    }
+
+    fn aligned(self, other: Self) -> Option<(i128, i128, u32)> {
+        let scale = self.scale.max(other.scale);
+        Some((self.widen(scale)?.units, other.widen(scale)?.units, scale))
+    }
+
+    pub fn checked_add(self, other: Self) -> Result<Self, String> {
+        let (l, r, scale) = self.aligned(other).ok_or(OVERFLOW)?;
+        Ok(Decimal { units: l.checked_add(r).ok_or(OVERFLOW)?, scale })
+    }
+
+    pub fn checked_sub(self, other: Self) -> Result<Self, String> {
+        let (l, r, scale) = self.aligned(other).ok_or(OVERFLOW)?;
+        Ok(Decimal { units: l.checked_sub(r).ok_or(OVERFLOW)?, scale })
+    }
+
+    pub fn checked_mul(self, other: Self) -> Result<Self, String> {
+        let product = Decimal {
+            units: self.units.checked_mul(other.units).ok_or(OVERFLOW)?,
+            scale: self.scale + other.scale,
+        };
+        Ok(if product.scale > MAX_SCALE { product.round(MAX_SCALE, RoundingMode::HalfEven) } else { product })
+    }
+
+    /// `self / other`, worked out to [`DIVISION_SCALE`] digits and rounded
+    /// half-even, then trimmed of trailing zeros down to the operands'
+    /// scale. `10.00d / 4` is `2.50`; `1d / 3` is `0.3333333333333333`.
+    pub fn checked_div(self, other: Self) -> Result<Self, String> {
+        if other.is_zero() {
+            return Err("Division by zero".to_string());
+        }
+        let keep = self.scale.max(other.scale);
+        let target = keep.max(DIVISION_SCALE);
+        // units(self) * 10^(target + other.scale - self.scale) / units(other),
+        // with one extra digit to round from.
+        let shift = (target + other.scale + 1).checked_sub(self.scale).ok_or(OVERFLOW)?;
+        let numerator = self.units.checked_mul(pow10(shift).ok_or(OVERFLOW)?).ok_or(OVERFLOW)?;
+        let mut quotient = Decimal { units: numerator / other.units, scale: target + 1 };
+        if numerator % other.units != 0 {
+            // A sticky digit, so an inexact quotient never rounds as an exact half.
+            let sign = if (numerator < 0) != (other.units < 0) { -1 } else { 1 };
+            let units = quotient.units.checked_mul(10).ok_or(OVERFLOW)? + sign;
+            quotient = Decimal { units, scale: target + 2 };
+        }
+        let mut result = quotient.round(target, RoundingMode::HalfEven);
+        while result.scale > keep && result.units % 10 == 0 {
+            result.units /= 10;
+            result.scale -= 1;
+        }
+        Ok(result)
+    }
+
+    /// The remainder of truncating division, with the sign of `self`.
+    pub fn checked_rem(self, other: Self) -> Result<Self, String> {
+        if other.is_zero() {
+            return Err("Modulo by zero".to_string());
+        }
+        let (l, r, scale) = self.aligned(other).ok_or(OVERFLOW)?;
+        Ok(Decimal { units: l % r, scale })
+    }
+
+    pub fn neg(self) -> Self {
+        Decimal { units: -self.units, scale: self.scale }
+    }
+
+    /// Rounds to exactly `places` digits after the point, padding with zeros
+    /// when the value has fewer.
+    pub fn round(self, places: u32, mode: RoundingMode) -> Self {
+        if places >= self.scale {
+            return self.widen(places).unwrap_or(self);
+        }
+        let Some(divisor) = pow10(self.scale - places) else {
+            // Every kept digit is zero; only the rounding direction is left.
+            return Decimal { units: 0, scale: places };
+        };
+        let truncated = self.units / divisor;
+        let remainder = self.units % divisor;
+        if remainder == 0 {
+            return Decimal { units: truncated, scale: places };
+        }
+        let away = if self.units < 0 { -1 } else { 1 };
+        let twice = remainder.unsigned_abs() * 2;
+        let half = divisor.unsigned_abs();
+        let round_away = match mode {
+            RoundingMode::Down => false,
+            RoundingMode::Up => true,
+            RoundingMode::Floor => self.units < 0,
+            RoundingMode::Ceiling => self.units > 0,
+            RoundingMode::HalfUp => twice >= half,
+            RoundingMode::HalfEven => twice > half || (twice == half && truncated % 2 != 0),
+        };
+        Decimal { units: if round_away { truncated + away } else { truncated }, scale: places }
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.aligned(*other) {
+            Some((l, r, _)) => l.cmp(&r),
+            // Widening only overflows for values far apart in magnitude, so
+            // the signs and the unscaled comparison settle it.
+            None => self.to_f64().total_cmp(&other.to_f64()),
+        }
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let digits = self.units.unsigned_abs().to_string();
+        let sign = if self.units < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, whole, fraction)
+    }
+}
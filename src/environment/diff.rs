@@ -0,0 +1,128 @@
+//! Where two values differ, for `@diff` and `assert.eq`.
+//!
+//! Arrays and objects are walked side by side and only the leaves that
+//! differ are reported, each with its path from the root (`$.user.tags[1]`),
+//! so a failed comparison of two large values points at the part that
+//! changed instead of printing both in full.
+
+use super::{is_internal_entry, Value};
+use indexmap::IndexMap;
+use std::fmt::{self, Display, Formatter};
+
+/// One place where the actual value differs from the expected one. A side
+/// is `None` when the element or key only exists on the other side.
+#[derive(Debug, Clone)]
+pub struct Difference {
+    pub path: String,
+    pub expected: Option<Value>,
+    pub actual: Option<Value>,
+}
+
+/// Every difference between `expected` and `actual`, in the order the walk
+/// meets them. Empty when the two are equal.
+pub fn diff(expected: &Value, actual: &Value) -> Vec<Difference> {
+    let mut out = Vec::new();
+    walk("$".to_string(), expected, actual, &mut out);
+    out
+}
+
+/// Whether `a` and `b` are equal all the way down, as `assert.eq` compares
+/// them.
+pub fn equal(a: &Value, b: &Value) -> bool {
+    diff(a, b).is_empty()
+}
+
+fn walk(path: String, expected: &Value, actual: &Value, out: &mut Vec<Difference>) {
+    match (expected, actual) {
+        (Value::Array(e), Value::Array(a)) => {
+            for i in 0..e.len().max(a.len()) {
+                let at = format!("{}[{}]", path, i);
+                match (e.get(i), a.get(i)) {
+                    (Some(e), Some(a)) => walk(at, e, a, out),
+                    (e, a) => out.push(Difference { path: at, expected: e.cloned(), actual: a.cloned() }),
+                }
+            }
+        }
+        (Value::Object(e), Value::Object(a)) => {
+            for key in e.keys().chain(a.keys().filter(|k| !e.contains_key(*k))) {
+                if e.get(key).or_else(|| a.get(key)).is_some_and(|value| is_internal_entry(key, value)) {
+                    continue;
+                }
+                let at = member_path(&path, key);
+                match (e.get(key), a.get(key)) {
+                    (Some(e), Some(a)) => walk(at, e, a, out),
+                    (e, a) => out.push(Difference { path: at, expected: e.cloned(), actual: a.cloned() }),
+                }
+            }
+        }
+        _ if same(expected, actual) => {}
+        _ => out.push(Difference { path, expected: Some(expected.clone()), actual: Some(actual.clone()) }),
+    }
+}
+
+fn member_path(path: &str, key: &str) -> String {
+    let plain = key.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if plain {
+        format!("{}.{}", path, key)
+    } else {
+        format!("{}[{:?}]", path, key)
+    }
+}
+
+/// Equality of values the walk does not descend into.
+fn same(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::Int(e), Value::Int(a)) => e == a,
+        (Value::Float(e), Value::Float(a)) => e == a,
+        (Value::Int(e), Value::Float(a)) | (Value::Float(a), Value::Int(e)) => *e as f64 == *a,
+        (Value::String(e), Value::String(a)) => e == a,
+        (Value::Boolean(e), Value::Boolean(a)) => e == a,
+        (Value::Decimal(e), Value::Decimal(a)) => e == a,
+        (Value::Vector(e), Value::Vector(a)) => e == a,
+        (Value::Matrix(e), Value::Matrix(a)) => e == a,
+        (Value::Quantity(e), Value::Quantity(a)) => e == a,
+        (Value::BigFloat(e), Value::BigFloat(a)) => e == a,
+        (Value::Expression(e), Value::Expression(a)) => e == a,
+        (Value::Complex { real: er, imag: ei }, Value::Complex { real: ar, imag: ai }) => er == ar && ei == ai,
+        (Value::Enum { .. }, Value::Enum { .. }) => expected.enum_variant() == actual.enum_variant(),
+        (Value::Function(_) | Value::NativeFunction(_), _) => expected.same_function(actual),
+        (Value::Null, Value::Null) | (Value::Void, Value::Void) => true,
+        _ => false,
+    }
+}
+
+/// A value as it appears in a difference: strings quoted, so `"1"` and `1`
+/// read differently.
+fn show(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        other => other.to_string(),
+    }
+}
+
+impl Difference {
+    /// `{ path, expected, actual }`, leaving out the side that is missing.
+    pub fn to_value(&self) -> Value {
+        let mut obj = IndexMap::new();
+        obj.insert("path".to_string(), Value::String(self.path.clone()));
+        if let Some(expected) = &self.expected {
+            obj.insert("expected".to_string(), expected.clone());
+        }
+        if let Some(actual) = &self.actual {
+            obj.insert("actual".to_string(), actual.clone());
+        }
+        Value::Object(obj)
+    }
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (&self.expected, &self.actual) {
+            (Some(e), Some(a)) => write!(f, "{}: expected {}, got {}", self.path, show(e), show(a)),
+            (Some(e), None) => write!(f, "{}: missing, expected {}", self.path, show(e)),
+            (None, Some(a)) => write!(f, "{}: unexpected {}", self.path, show(a)),
+            (None, None) => write!(f, "{}", self.path),
+        }
+    }
+}
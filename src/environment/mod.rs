@@ -1,18 +1,292 @@
 #![allow(dead_code)]
 
 use hashbrown::HashMap;
-use std::cell::RefCell;
+use indexmap::IndexMap;
+use std::cell::{Cell, RefCell};
 use std::collections::VecDeque;
-use std::io::Write;
 use std::fmt::{self, Display, Formatter};
 use std::sync::{Arc, Mutex};
 use std::rc::Rc;
 use crate::ast::*;
 use crate::lexer::DataType;
 use serde_json::Value as JsonValue;
+use smallvec::SmallVec;
+use decimal::{Decimal, RoundingMode};
+
+pub mod closure;
+pub mod decimal;
+pub mod diff;
+pub mod snapshot;
+pub mod sort;
 
 thread_local! {
     static SCOPE_POOL: RefCell<Vec<Environment>> = const { RefCell::new(Vec::new()) };
+    static PRINT_PRECISION: Cell<Option<usize>> = const { Cell::new(None) };
+    static FLOAT_PRECISION: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Most digits `@set_precision` and `@format` accept after the decimal point.
+pub const MAX_FLOAT_PRECISION: usize = 17;
+
+/// Fixes how many digits `print` and `println` write after a float's decimal
+/// point, as `@set_precision` does. `None` restores the default.
+pub fn set_float_precision(digits: Option<usize>) {
+    PRINT_PRECISION.with(|precision| precision.set(digits));
+}
+
+pub fn float_precision() -> Option<usize> {
+    PRINT_PRECISION.with(Cell::get)
+}
+
+/// Runs `f` with floats formatting to `digits` decimals, for printing and
+/// `@format`. Elsewhere, such as `"" + x`, floats keep their default text.
+fn with_float_digits<R>(digits: Option<usize>, f: impl FnOnce() -> R) -> R {
+    let previous = FLOAT_PRECISION.with(|precision| precision.replace(digits));
+    let result = f();
+    FLOAT_PRECISION.with(|precision| precision.set(previous));
+    result
+}
+
+/// How a float prints: the shortest text that reads back as the same
+/// number, unless printing with a fixed precision. Whole numbers keep a `.0`
+/// so they still read as floats.
+pub fn format_float(value: f64) -> String {
+    let text = float_digits(value);
+    if FLOAT_PRECISION.with(Cell::get).is_none() && value.is_finite() && !text.contains(['.', 'e']) {
+        format!("{}.0", text)
+    } else {
+        text
+    }
+}
+
+// `format_float` without the `.0` on whole numbers, for complex parts.
+fn float_digits(value: f64) -> String {
+    if let Some(digits) = FLOAT_PRECISION.with(Cell::get) {
+        return format!("{:.*}", digits, value);
+    }
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    // Exponent form when the number is too small or large to read plainly.
+    let exponent = if value == 0.0 { 0 } else { value.abs().log10().floor() as i32 };
+    if (-4..16).contains(&exponent) {
+        value.to_string()
+    } else {
+        format!("{:e}", value)
+    }
+}
+
+/// `value` printed the way `@format` does: `spec` is
+/// `[<|>|^][width][.precision]`, e.g. `">8.2"`. Precision applies to every
+/// float inside `value`; width pads the whole text, aligning numbers right
+/// and everything else left unless an alignment is given.
+pub fn format_with_spec(value: &Value, spec: &str) -> Result<String, String> {
+    let invalid = || format!("Invalid format spec '{}': expected [<|>|^][width][.precision]", spec);
+    let (align, rest) = match spec.chars().next() {
+        Some(c @ ('<' | '>' | '^')) => (Some(c), &spec[1..]),
+        _ => (None, spec),
+    };
+    let (width, precision) = match rest.split_once('.') {
+        Some((width, precision)) => (width, Some(precision)),
+        None => (rest, None),
+    };
+    let width: usize = if width.is_empty() { 0 } else { width.parse().map_err(|_| invalid())? };
+    let precision = match precision {
+        Some(digits) => match digits.parse::<usize>() {
+            Ok(digits) if digits <= MAX_FLOAT_PRECISION => Some(digits),
+            _ => return Err(invalid()),
+        },
+        None => None,
+    };
+
+    let text = match (value, precision) {
+        (Value::Decimal(d), Some(digits)) => d.round(digits as u32, RoundingMode::HalfUp).to_string(),
+        _ => with_float_digits(precision, || value.to_string()),
+    };
+
+    let pad = width.saturating_sub(text.chars().count());
+    let numeric = matches!(value, Value::Int(_) | Value::Float(_) | Value::Decimal(_));
+    Ok(match align.unwrap_or(if numeric { '>' } else { '<' }) {
+        '>' => format!("{}{}", " ".repeat(pad), text),
+        '^' => format!("{}{}{}", " ".repeat(pad / 2), text, " ".repeat(pad - pad / 2)),
+        _ => format!("{}{}", text, " ".repeat(pad)),
+    })
+}
+
+/// `value` as a decimal: ints exactly, floats as they print, and strings
+/// written like a `dec` literal.
+pub fn to_decimal(value: &Value) -> Result<Decimal, String> {
+    match value {
+        Value::Decimal(d) => Ok(*d),
+        Value::Int(i) => Ok(Decimal::from_int(*i)),
+        Value::Float(f) => Decimal::from_f64(*f),
+        Value::String(s) => Decimal::parse(s.trim()),
+        other => Err(format!("Cannot convert type '{}' to dec", other.type_name())),
+    }
+}
+
+/// The `[places, mode]` arguments of `.round` and `decimal.round`: places
+/// defaults to 0 and the mode to `half_up`.
+pub fn decimal_round(d: Decimal, args: &[Value]) -> Result<Decimal, String> {
+    let places = match args.first() {
+        None => 0,
+        Some(Value::Int(places)) if (0..=decimal::MAX_SCALE as i64).contains(places) => *places as u32,
+        Some(_) => return Err(format!("round expects a number of places between 0 and {}", decimal::MAX_SCALE)),
+    };
+    let mode = match args.get(1) {
+        None => RoundingMode::HalfUp,
+        Some(Value::String(name)) => RoundingMode::parse(name)?,
+        Some(_) => return Err("round expects the rounding mode as a string".to_string()),
+    };
+    if args.len() > 2 {
+        return Err("round takes at most 2 arguments (places, mode)".to_string());
+    }
+    Ok(d.round(places, mode))
+}
+
+/// Int/Int and Float/Float arithmetic and comparisons, tried by both engines
+/// before the general operator dispatch and the library operator hooks.
+/// `None` for every other pairing, and for division or modulo by zero so the
+/// general path can report it.
+#[inline]
+pub fn numeric_binary(op: &str, left: &Value, right: &Value) -> Option<Value> {
+    match (left, right) {
+        (Value::Int(l), Value::Int(r)) => Some(match op {
+            // Overflow and zero divisors fall back to the general path, which
+            // reports them.
+            "+" | "-" | "*" | "/" | "%" => return checked_int(op, *l, *r).map(Value::Int),
+            "<" => Value::Boolean(l < r),
+            ">" => Value::Boolean(l > r),
+            "<=" => Value::Boolean(l <= r),
+            ">=" => Value::Boolean(l >= r),
+            "==" => Value::Boolean(l == r),
+            "!=" => Value::Boolean(l != r),
+            _ => return None,
+        }),
+        (Value::Float(l), Value::Float(r)) => Some(match op {
+            "+" => Value::Float(l + r),
+            "-" => Value::Float(l - r),
+            "*" => Value::Float(l * r),
+            "/" if *r != 0.0 => Value::Float(l / r),
+            "<" => Value::Boolean(l < r),
+            ">" => Value::Boolean(l > r),
+            "<=" => Value::Boolean(l <= r),
+            ">=" => Value::Boolean(l >= r),
+            "==" => Value::Boolean(l == r),
+            "!=" => Value::Boolean(l != r),
+            _ => return None,
+        }),
+        _ => None,
+    }
+}
+
+/// `l op r` for the int operators `+ - * / %`, or `None` when the result does
+/// not fit in an int or the divisor is zero.
+pub fn checked_int(op: &str, l: i64, r: i64) -> Option<i64> {
+    match op {
+        "+" => l.checked_add(r),
+        "-" => l.checked_sub(r),
+        "*" => l.checked_mul(r),
+        "/" => l.checked_div(r),
+        "%" => l.checked_rem(r),
+        _ => None,
+    }
+}
+
+/// Like [`checked_int`], for callers that have already ruled out a zero
+/// divisor: the only failure left is overflow.
+pub fn int_binary(op: &str, l: i64, r: i64) -> Result<Value, String> {
+    checked_int(op, l, r)
+        .map(Value::Int)
+        .ok_or_else(|| format!("Integer overflow: {} {} {} does not fit in an int", l, op, r))
+}
+
+/// The ints `start..end` covers: from `start` up to but not including `end`,
+/// and none when `end <= start`. `for ... in` walks this lazily; anywhere else
+/// the range is built into an array.
+pub fn int_range(start: &Value, end: &Value) -> Result<std::ops::Range<i64>, String> {
+    match (start, end) {
+        (Value::Int(start), Value::Int(end)) => Ok(*start..*end),
+        _ => Err(format!(
+            "Range bounds must be ints, found {} and {}",
+            Environment::value_label(start),
+            Environment::value_label(end)
+        )),
+    }
+}
+
+/// `text * count`: `text` written `count` times in a row, shared by both
+/// engines. Zero times is the empty string.
+pub fn repeat_string(text: &str, count: i64) -> Result<Value, String> {
+    if count < 0 {
+        return Err(format!("Cannot repeat a string {} times", count));
+    }
+    text.len()
+        .checked_mul(count as usize)
+        .filter(|len| *len <= isize::MAX as usize)
+        .map(|_| Value::String(text.repeat(count as usize)))
+        .ok_or_else(|| format!("Repeating a string {} times is too long", count))
+}
+
+/// Whether a `switch` on `subject` takes the case holding `value`: the same
+/// int or the same string, with no conversion between types.
+pub fn switch_case_matches(subject: &Value, value: &Value) -> bool {
+    match (subject, value) {
+        (Value::Int(subject), Value::Int(value)) => subject == value,
+        (Value::String(subject), Value::String(value)) => subject == value,
+        _ => false,
+    }
+}
+
+/// Arithmetic and comparisons with a `dec` operand, shared by both engines.
+/// Ints join decimals exactly; floats are refused, since mixing them would
+/// bring back the rounding decimals exist to avoid. `None` when neither side
+/// is a decimal or `op` is not one decimals take part in.
+pub fn decimal_binary(op: &str, left: &Value, right: &Value) -> Option<Result<Value, String>> {
+    if !matches!(left, Value::Decimal(_)) && !matches!(right, Value::Decimal(_)) {
+        return None;
+    }
+    // `+` with a string still concatenates.
+    if op == "+" && (matches!(left, Value::String(_)) || matches!(right, Value::String(_))) {
+        return None;
+    }
+    let operand = |value: &Value| match value {
+        Value::Decimal(d) => Ok(*d),
+        Value::Int(i) => Ok(Decimal::from_int(*i)),
+        Value::Float(_) => Err(format!(
+            "Cannot mix dec and float in '{}'; cast one side with .cast(\"dec\") or .cast(\"float\")",
+            op
+        )),
+        other => Err(format!("Cannot apply '{}' to dec and {}", op, other.type_name())),
+    };
+    if !matches!(op, "+" | "-" | "*" | "/" | "%" | "==" | "!=" | "<" | ">" | "<=" | ">=") {
+        return None;
+    }
+    let (l, r) = match (operand(left), operand(right)) {
+        (Ok(l), Ok(r)) => (l, r),
+        (Err(e), _) | (_, Err(e)) => {
+            // Equality against other types is simply false, as it is elsewhere.
+            return match op {
+                "==" => Some(Ok(Value::Boolean(false))),
+                "!=" => Some(Ok(Value::Boolean(true))),
+                _ => Some(Err(e)),
+            };
+        }
+    };
+    let result = match op {
+        "+" => l.checked_add(r).map(Value::Decimal),
+        "-" => l.checked_sub(r).map(Value::Decimal),
+        "*" => l.checked_mul(r).map(Value::Decimal),
+        "/" => l.checked_div(r).map(Value::Decimal),
+        "%" => l.checked_rem(r).map(Value::Decimal),
+        "==" => Ok(Value::Boolean(l == r)),
+        "!=" => Ok(Value::Boolean(l != r)),
+        "<" => Ok(Value::Boolean(l < r)),
+        ">" => Ok(Value::Boolean(l > r)),
+        "<=" => Ok(Value::Boolean(l <= r)),
+        _ => Ok(Value::Boolean(l >= r)),
+    };
+    Some(result)
 }
 
 pub enum Value {
@@ -21,15 +295,72 @@ pub enum Value {
   String(String),
   Boolean(bool),
   Array(Vec<Value>),
-  Object(HashMap<String, Value>),
+  Object(IndexMap<String, Value>),
+  /// A library object declared by `use`. Every scope using the library
+  /// shares it; assigning to a member gives that scope an `Object` copy.
+  Library(Arc<IndexMap<String, Value>>),
+  /// A variant of an `enum` declaration: the enum's name, the variant's, and
+  /// the fields scripts read from it (`name` and `ordinal`).
+  Enum { tag: Arc<str>, variant: Arc<str>, payload: Arc<IndexMap<String, Value>> },
   Function(FunctionValue),
   NativeFunction(Arc<dyn Fn(Vec<Value>) -> Result<Value, String> + Send + Sync + 'static>),
   Complex { real: f64, imag: f64 },
+  Decimal(decimal::Decimal),
   Vector(Vec<f64>),
   Matrix(Vec<Vec<f64>>),
+  /// A number with units from the units library.
+  Quantity(crate::libraries::units::Quantity),
+  /// An arbitrary-precision float from `math.bigfloat`.
+  BigFloat(crate::libraries::bigfloat::BigFloat),
+  /// A symbolic expression from the calc library.
+  Expression(crate::libraries::calc::Expression),
+  Resource(NativeResource),
+  /// `null`: written in source to mean "no value", unlike `Void`, which is
+  /// what statements and empty returns produce.
+  Null,
   Void,
 }
 
+/// Host state owned by a library, such as an open file. Scripts only reach it
+/// through the native methods of the object carrying it. Clones share the
+/// state, and closing drops it for all of them.
+#[derive(Clone)]
+pub struct NativeResource {
+    pub kind: &'static str,
+    state: Arc<Mutex<Option<Box<dyn std::any::Any + Send>>>>,
+}
+
+impl NativeResource {
+    pub fn new<T: std::any::Any + Send>(kind: &'static str, state: T) -> Self {
+        NativeResource { kind, state: Arc::new(Mutex::new(Some(Box::new(state)))) }
+    }
+
+    /// Runs `f` on the state, or fails once the resource has been closed.
+    pub fn with<T: std::any::Any, R>(&self, f: impl FnOnce(&mut T) -> Result<R, String>) -> Result<R, String> {
+        let mut state = self.state.lock().map_err(|_| format!("{} is unusable after a panic", self.kind))?;
+        match state.as_mut().and_then(|state| state.downcast_mut::<T>()) {
+            Some(state) => f(state),
+            None => Err(format!("{} is closed", self.kind)),
+        }
+    }
+
+    /// Drops the state. Closing an already closed resource does nothing.
+    pub fn close(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.take();
+        }
+    }
+
+    /// Whether both values refer to the same underlying state.
+    pub fn same_resource(&self, other: &NativeResource) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().map(|state| state.is_none()).unwrap_or(true)
+    }
+}
+
 impl std::fmt::Debug for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -39,11 +370,19 @@ impl std::fmt::Debug for Value {
             Value::Boolean(b) => write!(f, "Boolean({})", b),
             Value::Array(arr) => write!(f, "Array({:?})", arr),
             Value::Object(obj) => write!(f, "Object({:?})", obj),
+            Value::Library(lib) => write!(f, "Library({:?})", lib),
+            Value::Enum { tag, variant, .. } => write!(f, "Enum({}.{})", tag, variant),
             Value::Function(_) => write!(f, "Function(...)"),
             Value::NativeFunction(_) => write!(f, "NativeFunction(...)"),
             Value::Complex { real, imag } => write!(f, "Complex {{ real: {}, imag: {} }}", real, imag),
+            Value::Decimal(d) => write!(f, "Decimal({})", d),
             Value::Vector(v) => write!(f, "Vector({:?})", v),
             Value::Matrix(m) => write!(f, "Matrix({:?})", m),
+            Value::Quantity(q) => write!(f, "Quantity({})", q),
+            Value::BigFloat(b) => write!(f, "BigFloat({})", b),
+            Value::Expression(e) => write!(f, "Expression({})", e),
+            Value::Resource(r) => write!(f, "Resource({})", r.kind),
+            Value::Null => write!(f, "Null"),
             Value::Void => write!(f, "Void"),
         }
     }
@@ -54,15 +393,23 @@ impl Clone for Value {
         match self {
             Value::Int(i) => Value::Int(*i),
             Value::Float(f) => Value::Float(*f),
-            Value::String(s) => Value::String(s.clone()),
+            Value::String(s) => Value::String(crate::stats::copy(|| s.clone())),
             Value::Boolean(b) => Value::Boolean(*b),
-            Value::Array(arr) => Value::Array(arr.clone()),
-            Value::Object(obj) => Value::Object(obj.clone()),
+            Value::Array(arr) => Value::Array(crate::stats::copy(|| arr.clone())),
+            Value::Object(obj) => Value::Object(crate::stats::copy(|| obj.clone())),
+            Value::Library(lib) => Value::Library(lib.clone()),
+            Value::Enum { tag, variant, payload } => Value::Enum { tag: tag.clone(), variant: variant.clone(), payload: payload.clone() },
             Value::Function(func) => Value::Function(func.clone()),
             Value::NativeFunction(f) => Value::NativeFunction(f.clone()),
             Value::Complex { real, imag } => Value::Complex { real: *real, imag: *imag },
-            Value::Vector(v) => Value::Vector(v.clone()),
-            Value::Matrix(m) => Value::Matrix(m.clone()),
+            Value::Decimal(d) => Value::Decimal(*d),
+            Value::Vector(v) => Value::Vector(crate::stats::copy(|| v.clone())),
+            Value::Matrix(m) => Value::Matrix(crate::stats::copy(|| m.clone())),
+            Value::Quantity(q) => Value::Quantity(q.clone()),
+            Value::BigFloat(b) => Value::BigFloat(b.clone()),
+            Value::Expression(e) => Value::Expression(e.clone()),
+            Value::Resource(r) => Value::Resource(r.clone()),
+            Value::Null => Value::Null,
             Value::Void => Value::Void,
         }
     }
@@ -75,7 +422,24 @@ impl Display for Value {
     }
 }
 
+/// Characters of an array or object shown in an error's `value:` line.
+const PREVIEW_WIDTH: usize = 60;
+
 impl Value {
+    /// A one-line rendering of an array or object for error reports, cut
+    /// short after [`PREVIEW_WIDTH`] characters.
+    pub fn preview(&self) -> Option<String> {
+        if !matches!(self, Value::Array(_) | Value::Object(_) | Value::Library(_)) {
+            return None;
+        }
+        let text = self.to_string();
+        if text.chars().count() <= PREVIEW_WIDTH {
+            return Some(text);
+        }
+        let cut: String = text.chars().take(PREVIEW_WIDTH - 3).collect();
+        Some(format!("{}...", cut))
+    }
+
     fn write_escaped_string(f: &mut Formatter, s: &str) -> fmt::Result {
         for ch in s.chars() {
             match ch {
@@ -111,43 +475,11 @@ impl Value {
                 if let Some(Value::String(pretty)) = obj.get("__zekken_error__") {
                     write!(f, "{}", pretty)
                 } else {
-                    write!(f, "{{")?;
-                    let mut first = true;
-
-                    // Prefer insertion-order key list when available.
-                    if let Some(Value::Array(keys)) = obj.get("__keys__") {
-                        for key_val in keys {
-                            if let Value::String(k) = key_val {
-                                if k == "__keys__" || k == "__zekken_error__" {
-                                    continue;
-                                }
-                                if let Some(v) = obj.get(k) {
-                                    if !first { write!(f, ", ")?; }
-                                    write!(f, "{}: ", k)?;
-                                    v.fmt_compact(f, true)?;
-                                    first = false;
-                                }
-                            }
-                        }
-                    } else {
-                        // Deterministic fallback order for objects without __keys__.
-                        let mut keys: Vec<&String> = obj
-                            .keys()
-                            .filter(|k| k.as_str() != "__keys__" && k.as_str() != "__zekken_error__")
-                            .collect();
-                        keys.sort_unstable();
-                        for k in keys {
-                            if let Some(v) = obj.get(k) {
-                                if !first { write!(f, ", ")?; }
-                                write!(f, "{}: ", k)?;
-                                v.fmt_compact(f, true)?;
-                                first = false;
-                            }
-                        }
-                    }
-                    write!(f, "}}")
+                    Self::fmt_fields_compact(f, obj)
                 }
             }
+            Value::Library(lib) => Self::fmt_fields_compact(f, lib),
+            Value::Enum { tag, variant, .. } => write!(f, "{}.{}", tag, variant),
             Value::String(s) => {
                 if in_container {
                     write!(f, "\"")?;
@@ -158,42 +490,47 @@ impl Value {
                 }
             }
             Value::Int(i) => write!(f, "{}", i),
-            Value::Float(fl) => {
-                if fl.fract() == 0.0 {
-                    write!(f, "{:.1}", fl)
-                } else {
-                    write!(f, "{}", fl)
-                }
-            },
+            Value::Float(fl) => write!(f, "{}", format_float(*fl)),
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::Quantity(q) => write!(f, "{}", q),
+            Value::BigFloat(b) => write!(f, "{}", b),
+            Value::Expression(e) => write!(f, "{}", e),
             Value::Boolean(b) => write!(f, "{}", b),
+            Value::Null => write!(f, "null"),
             Value::Function(_) => write!(f, "<function>"),
             Value::NativeFunction(_) => write!(f, "<native function>"),
+            Value::Resource(r) => write!(f, "<{}>", r.kind),
             Value::Complex { real, imag } => {
                 if *imag >= 0.0 {
-                    write!(f, "{} + {}i", real, imag)
+                    write!(f, "{} + {}i", float_digits(*real), float_digits(*imag))
                 } else {
-                    write!(f, "{} - {}i", real, imag.abs())
+                    write!(f, "{} - {}i", float_digits(*real), float_digits(imag.abs()))
                 }
             }
             Value::Vector(v) => {
                 write!(f, "[")?;
                 for (i, val) in v.iter().enumerate() {
                     if i > 0 { write!(f, ", ")? }
-                    write!(f, "{}", val)?;
+                    write!(f, "{}", format_float(*val))?;
                 }
                 write!(f, "]")
             }
             Value::Matrix(m) => {
-                // Keep matrix compact(ish) in default printing.
+                // Each column is as wide as its widest entry, so rows line up.
+                let cells: Vec<Vec<String>> = m.iter().map(|row| row.iter().map(|val| format_float(*val)).collect()).collect();
+                let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+                let widths: Vec<usize> = (0..columns)
+                    .map(|j| cells.iter().filter_map(|row| row.get(j)).map(String::len).max().unwrap_or(0))
+                    .collect();
                 write!(f, "[")?;
-                for (i, row) in m.iter().enumerate() {
+                for (i, row) in cells.iter().enumerate() {
                     if i > 0 {
                         write!(f, ", ")?;
                     }
                     write!(f, "[")?;
-                    for (j, val) in row.iter().enumerate() {
+                    for (j, cell) in row.iter().enumerate() {
                         if j > 0 { write!(f, ", ")? }
-                        write!(f, "{:>8.3}", val)?;
+                        write!(f, "{:>width$}", cell, width = widths[j])?;
                     }
                     write!(f, "]")?;
                 }
@@ -227,46 +564,10 @@ impl Value {
                 if let Some(Value::String(pretty)) = obj.get("__zekken_error__") {
                     write!(f, "{}", pretty)
                 } else {
-                    // Collect keys in deterministic order (prefer __keys__ insertion order).
-                    let mut ordered: Vec<&String> = Vec::new();
-                    if let Some(Value::Array(keys)) = obj.get("__keys__") {
-                        for key_val in keys {
-                            if let Value::String(k) = key_val {
-                                if k == "__keys__" || k == "__zekken_error__" {
-                                    continue;
-                                }
-                                if obj.contains_key(k) {
-                                    ordered.push(k);
-                                }
-                            }
-                        }
-                    } else {
-                        ordered = obj
-                            .keys()
-                            .filter(|k| k.as_str() != "__keys__" && k.as_str() != "__zekken_error__")
-                            .collect();
-                        ordered.sort_unstable();
-                    }
-
-                    if ordered.is_empty() {
-                        return write!(f, "{{}}");
-                    }
-
-                    writeln!(f, "{{")?;
-                    for (i, k) in ordered.iter().enumerate() {
-                        if let Some(v) = obj.get(*k) {
-                            write!(f, "{}{}: ", indent_str(indent + 1), k)?;
-                            v.fmt_pretty(f, indent + 1, true)?;
-                            if i < ordered.len() - 1 {
-                                writeln!(f, ",")?;
-                            } else {
-                                writeln!(f)?;
-                            }
-                        }
-                    }
-                    write!(f, "{}}}", indent_str(indent))
+                    Self::fmt_fields_pretty(f, obj, indent)
                 }
             }
+            Value::Library(lib) => Self::fmt_fields_pretty(f, lib, indent),
             Value::String(s) => {
                 if in_container {
                     write!(f, "\"")?;
@@ -280,6 +581,42 @@ impl Value {
         }
     }
 
+    fn fmt_fields_compact(f: &mut Formatter, obj: &IndexMap<String, Value>) -> fmt::Result {
+        write!(f, "{{")?;
+        let mut first = true;
+
+        for (k, v) in obj.iter().filter(|(k, v)| !is_internal_entry(k, v)) {
+            if !first { write!(f, ", ")?; }
+            write!(f, "{}: ", k)?;
+            v.fmt_compact(f, true)?;
+            first = false;
+        }
+        write!(f, "}}")
+    }
+
+    fn fmt_fields_pretty(f: &mut Formatter, obj: &IndexMap<String, Value>, indent: usize) -> fmt::Result {
+        let indent_str = |n| "  ".repeat(n);
+        let ordered: Vec<&String> = obj.iter().filter(|(k, v)| !is_internal_entry(k, v)).map(|(k, _)| k).collect();
+
+        if ordered.is_empty() {
+            return write!(f, "{{}}");
+        }
+
+        writeln!(f, "{{")?;
+        for (i, k) in ordered.iter().enumerate() {
+            if let Some(v) = obj.get(*k) {
+                write!(f, "{}{}: ", indent_str(indent + 1), k)?;
+                v.fmt_pretty(f, indent + 1, true)?;
+                if i < ordered.len() - 1 {
+                    writeln!(f, ",")?;
+                } else {
+                    writeln!(f)?;
+                }
+            }
+        }
+        write!(f, "{}}}", indent_str(indent))
+    }
+
     pub fn to_pretty_string(&self) -> String {
         struct PrettyValue<'a>(&'a Value);
         impl<'a> Display for PrettyValue<'a> {
@@ -289,6 +626,225 @@ impl Value {
         }
         PrettyValue(self).to_string()
     }
+
+    /// The enum and variant names when this value is a variant created by an
+    /// `enum` declaration. An object that copies a variant's fields is not one.
+    pub fn enum_variant(&self) -> Option<(&str, &str)> {
+        match self {
+            Value::Enum { tag, variant, .. } => Some((tag, variant)),
+            _ => None,
+        }
+    }
+
+    /// The fields of an object, including a library object shared from `use`
+    /// and the read-only fields of an enum variant.
+    pub fn as_object(&self) -> Option<&IndexMap<String, Value>> {
+        match self {
+            Value::Object(obj) => Some(obj),
+            Value::Library(lib) => Some(lib),
+            Value::Enum { payload, .. } => Some(payload),
+            _ => None,
+        }
+    }
+
+    /// Replaces a shared library object with a copy of its own, before
+    /// something assigns to one of its members.
+    pub fn unshare(&mut self) {
+        if let Value::Library(lib) = self {
+            *self = Value::Object(IndexMap::clone(lib));
+        }
+    }
+
+    /// A field of a library value, such as a quantity's `unit`, which
+    /// scripts read with dot access like an object's.
+    pub fn library_property(&self, name: &str) -> Option<Value> {
+        match self {
+            Value::Quantity(q) => q.property(name),
+            Value::BigFloat(b) => b.property(name),
+            _ => None,
+        }
+    }
+
+    /// Whether both values refer to the same function: the same declaration
+    /// or lambda, or the same library function.
+    pub fn same_function(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Function(l), Value::Function(r)) => l.name == r.name && Arc::ptr_eq(&l.body, &r.body),
+            (Value::NativeFunction(l), Value::NativeFunction(r)) => Arc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+
+    /// The `__close__` method of a resource usable with `with`, if this value
+    /// is one.
+    pub fn close_method(&self) -> Option<&Value> {
+        match self {
+            Value::Object(obj) => obj
+                .get("__close__")
+                .filter(|method| matches!(method, Value::Function(_) | Value::NativeFunction(_))),
+            _ => None,
+        }
+    }
+
+    /// The key a computed `[expr]: value` object property stores this value
+    /// under. Only strings and integers can be keys.
+    pub fn as_object_key(&self) -> Option<String> {
+        match self {
+            Value::String(s) => Some(s.clone()),
+            Value::Int(i) => Some(i.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Names of the bookkeeping fields the runtime stores on objects (error
+/// payloads, native resources).
+pub fn is_internal_key(key: &str) -> bool {
+    matches!(key, "__zekken_error__" | "__resource__")
+}
+
+/// Whether `key: value` is bookkeeping the runtime stored rather than a field
+/// a script wrote. Resources are values scripts cannot make, so an object that
+/// merely uses that name keeps it. Printing an object and its
+/// `keys`/`values`/`entries` skip internal entries.
+pub fn is_internal_entry(key: &str, value: &Value) -> bool {
+    match key {
+        "__zekken_error__" => true,
+        "__resource__" => matches!(value, Value::Resource(_)),
+        _ => false,
+    }
+}
+
+/// Builds the namespace object for `enum name { ... }`. Each variant is a
+/// [`Value::Enum`] with `name` and `ordinal` fields, so variants compare equal
+/// only to themselves.
+pub fn enum_value(name: &str, variants: &[String]) -> Value {
+    let tag: Arc<str> = Arc::from(name);
+    let mut namespace = IndexMap::with_capacity(variants.len());
+    for (ordinal, variant) in variants.iter().enumerate() {
+        let mut fields = IndexMap::with_capacity(2);
+        fields.insert("name".to_string(), Value::String(variant.clone()));
+        fields.insert("ordinal".to_string(), Value::Int(ordinal as i64));
+        let payload = Arc::new(fields);
+        namespace.insert(variant.clone(), Value::Enum { tag: tag.clone(), variant: Arc::from(variant.as_str()), payload });
+    }
+    Value::Object(namespace)
+}
+
+/// Longest array that `resize` and growing assignments make, so a huge
+/// length is an error rather than an allocation that aborts the process.
+pub const MAX_ARRAY_LENGTH: usize = 100_000_000;
+
+fn check_array_length(length: usize) -> Result<(), String> {
+    if length > MAX_ARRAY_LENGTH {
+        return Err(format!("An array of {} elements is too long (at most {})", length, MAX_ARRAY_LENGTH));
+    }
+    Ok(())
+}
+
+/// Makes `index` a writable slot of `arr` for `arr[index] = value`. Writing
+/// past the end is an error unless `--permissive` is on, in which case the
+/// array grows and the gap is filled with void.
+pub fn ensure_array_slot(arr: &mut Vec<Value>, index: usize) -> Result<(), String> {
+    if index < arr.len() {
+        return Ok(());
+    }
+    if !crate::config::permissive() {
+        return Err(format!(
+            "Array index {} out of bounds for length {} (use resize, or run with --permissive to grow arrays on assignment)",
+            index,
+            arr.len()
+        ));
+    }
+    check_array_length(index.saturating_add(1))?;
+    arr.resize(index + 1, Value::Void);
+    Ok(())
+}
+
+/// The character of `s` at `index`, counted in code points. Negative
+/// indexes count back from the end, so `s[-1]` is the last character.
+pub fn string_char_at(s: &str, index: &Value) -> Result<char, String> {
+    let index = match index {
+        Value::Int(i) => *i,
+        Value::Float(f) if f.fract() == 0.0 => *f as i64,
+        other => return Err(format!("String index must be an int, found {}", other.type_name())),
+    };
+    let length = s.chars().count();
+    let position = if index < 0 { index + length as i64 } else { index };
+    usize::try_from(position)
+        .ok()
+        .and_then(|position| s.chars().nth(position))
+        .ok_or_else(|| format!("String index {} out of bounds for length {}", index, length))
+}
+
+/// Whether `value` fits `arr<element>`: an array whose every element fits
+/// `element`. Vector entries are floats and matrix rows are vectors of them.
+/// Unlike a variable, an element is only `null` when its type says so.
+pub fn elements_match(value: &Value, element: &DataType) -> bool {
+    match (value, element) {
+        (Value::Array(items), _) => items.iter().all(|item| match item {
+            Value::Null => matches!(element, DataType::Nullable(_) | DataType::Any),
+            _ => Environment::value_matches_datatype(item, element),
+        }),
+        (Value::Vector(_), DataType::Float | DataType::Any) => true,
+        (Value::Matrix(_), DataType::Array | DataType::Any) => true,
+        (Value::Matrix(_), DataType::ArrayOf(entry)) => matches!(**entry, DataType::Float | DataType::Any),
+        _ => false,
+    }
+}
+
+fn vector_entry(value: &Value, literal: &str) -> Result<f64, String> {
+    match value {
+        Value::Int(i) => Ok(*i as f64),
+        Value::Float(f) => Ok(*f),
+        other => Err(format!("{} literal entries must be numbers, found {}", literal, other.type_name())),
+    }
+}
+
+/// The value of a `#[...]` literal: ints and floats become a vector.
+pub fn vector_from(values: &[Value]) -> Result<Value, String> {
+    let entries = values.iter().map(|v| vector_entry(v, "Vector")).collect::<Result<_, _>>()?;
+    Ok(Value::Vector(entries))
+}
+
+/// The value of a `#[[...], ...]` literal. Every row must have as many
+/// entries as the first.
+pub fn matrix_from(rows: &[Vec<Value>]) -> Result<Value, String> {
+    let columns = rows.first().map_or(0, Vec::len);
+    let mut matrix = Vec::with_capacity(rows.len());
+    for (i, row) in rows.iter().enumerate() {
+        if row.len() != columns {
+            return Err(format!(
+                "Matrix row {} has {} entries, expected {} like row 1",
+                i + 1,
+                row.len(),
+                columns
+            ));
+        }
+        matrix.push(row.iter().map(|v| vector_entry(v, "Matrix")).collect::<Result<_, _>>()?);
+    }
+    Ok(Value::Matrix(matrix))
+}
+
+/// `v[index]` on a vector is an entry; `m[index]` on a matrix is a row, as a
+/// vector. Negative indexes count back from the end.
+pub fn vector_index(value: &Value, index: &Value) -> Result<Value, String> {
+    let index = match index {
+        Value::Int(i) => *i,
+        other => return Err(format!("{} index must be an int, found {}", value.type_name(), other.type_name())),
+    };
+    let position = |length: usize| {
+        let position = if index < 0 { index + length as i64 } else { index };
+        usize::try_from(position)
+            .ok()
+            .filter(|p| *p < length)
+            .ok_or_else(|| format!("Index {} out of bounds for {} of length {}", index, value.type_name(), length))
+    };
+    match value {
+        Value::Vector(v) => Ok(Value::Float(v[position(v.len())?])),
+        Value::Matrix(m) => Ok(Value::Vector(m[position(m.len())?].clone())),
+        other => Err(format!("Cannot index into {}", other.type_name())),
+    }
 }
 
 pub fn interpolate_named_placeholders<'a, F>(template: &str, mut lookup: F) -> String
@@ -369,7 +925,13 @@ fn interpolate_positional_placeholders(template: &str, args: &[Value]) -> (Strin
     (out, used)
 }
 
+/// The text `print` and `println` write for `args`, with `@set_precision`
+/// applied to floats.
 pub fn format_print_values(args: &[Value]) -> String {
+    with_float_digits(float_precision(), || print_text(args))
+}
+
+fn print_text(args: &[Value]) -> String {
     if args.is_empty() {
         return String::new();
     }
@@ -395,17 +957,106 @@ pub fn format_print_values(args: &[Value]) -> String {
     }
 }
 
+/// Evaluated arguments of a call. Up to four stay inline, which covers nearly
+/// every call site without a heap allocation.
+pub type CallArgs = SmallVec<[Value; 4]>;
+
 #[derive(Debug, Clone)]
 pub struct FunctionValue {
+  /// The declared name, or empty for the placeholder values the checker uses.
+  pub name: Arc<str>,
   pub params: Arc<Vec<Param>>,
-  pub body: Arc<Vec<Box<Content>>>,
+  pub body: Arc<Vec<Content>>,
   pub return_type: Option<DataType>,
   pub needs_parent: bool,
   pub captures: Arc<Vec<String>>,
   pub capture_values: Arc<HashMap<String, Value>>,
   pub compiled_insts: Option<Arc<Vec<crate::bytecode::inst::Inst>>>,
   pub compiled_reg_count: usize,
-  //pub closure: Environment,
+  /// For functions created inside a call: the locals of that call, and of
+  /// the calls around it, that the body uses. They are shared rather than
+  /// copied, so the function and the call that created it see each other's
+  /// assignments, even after the function is returned.
+  pub closure: Option<closure::Closure>,
+}
+
+/// `params` reordered for a call that names some of its arguments (see
+/// [`CallExpr::arg_names`](crate::ast::CallExpr)): the parameters the
+/// arguments supply come first, in argument order, and the rest keep their
+/// place so their defaults still see the parameters before them.
+pub fn params_for_call(params: &[Param], names: &[Option<String>], function: &str) -> Result<Vec<Param>, String> {
+  let mut order: Vec<usize> = Vec::with_capacity(params.len());
+  for (idx, name) in names.iter().enumerate() {
+    let param = match name {
+      None => idx,
+      Some(name) => params
+        .iter()
+        .position(|param| param.ident == *name)
+        .ok_or_else(|| format!("'{}' has no parameter named '{}'", function, name))?,
+    };
+    if param >= params.len() {
+      continue; // Too many arguments; binding reports it.
+    }
+    if order.contains(&param) {
+      return Err(format!("Argument '{}' of '{}' is given twice", params[param].ident, function));
+    }
+    order.push(param);
+  }
+  order.extend((0..params.len()).filter(|idx| !order.contains(idx)).collect::<Vec<_>>());
+  Ok(order.into_iter().map(|idx| params[idx].clone()).collect())
+}
+
+impl FunctionValue {
+  /// Declares what this function closes over in `scope`, the scope of a call
+  /// before its parameters are bound. A named function also gets itself, so
+  /// a nested function keeps calling itself after it is returned.
+  pub fn bind_closure(&self, scope: &mut Environment) {
+    if let Some(closure) = &self.closure {
+      scope.enter_closure(closure);
+    }
+    if !self.name.is_empty() && &*self.name != "<lambda>" {
+      scope.declare_ref(&self.name, Value::Function(self.clone()), false);
+    }
+  }
+
+  /// This function with its parameters in the order a call with named
+  /// arguments binds them; see [`params_for_call`].
+  pub fn for_named_call(&self, names: &[Option<String>]) -> Result<FunctionValue, String> {
+    let params = params_for_call(&self.params, names, &self.name)?;
+    Ok(FunctionValue { params: Arc::new(params), ..self.clone() })
+  }
+
+  /// The introspection properties of a function: `name`, `arity` (every
+  /// parameter, including ones with defaults) and `params`, one
+  /// `{ name, type, optional }` object per parameter.
+  pub fn property(&self, property: &str) -> Option<Value> {
+    match property {
+      "name" => Some(Value::String(self.name.to_string())),
+      "arity" => Some(Value::Int(self.params.len() as i64)),
+      "params" => Some(Value::Array(
+        self.params
+          .iter()
+          .map(|param| {
+            let mut fields = IndexMap::with_capacity(3);
+            fields.insert("name".to_string(), Value::String(param.ident.clone()));
+            fields.insert("type".to_string(), Value::String(Environment::datatype_label(&param.type_)));
+            fields.insert("optional".to_string(), Value::Boolean(param.default_value.is_some()));
+            Value::Object(fields)
+          })
+          .collect(),
+      )),
+      _ => None,
+    }
+  }
+}
+
+/// A binding taken out of a scope while something shadows it, e.g. the
+/// counter of a C-style `for` loop.
+#[derive(Debug, Default)]
+pub struct SavedBinding {
+  variable: Option<Value>,
+  constant: Option<Value>,
+  ty: Option<DataType>,
 }
 
 #[derive(Debug, Clone)]
@@ -414,11 +1065,29 @@ pub struct Environment {
   pub variables: HashMap<String, Value>,
   pub constants: HashMap<String, Value>,
   pub types: HashMap<String, DataType>,
+  /// Expressions scheduled with `defer` in this frame, in scheduling order.
+  pub deferred: Vec<Expr>,
+  /// Set by `break` and `return` and cleared by the loop or function call
+  /// they end; blocks stop early while it is set.
+  pub control_flow: Option<ControlFlow>,
+  /// The locals this call shares with functions created in or around it.
+  pub shared: closure::CallScope,
+}
+
+#[derive(Debug, Clone)]
+pub enum ControlFlow {
+  /// `break`, or `break label` to end an enclosing labelled loop.
+  Break(Option<String>),
+  /// `continue`, or `continue label` to skip to the next iteration of an
+  /// enclosing labelled loop, ending every loop inside it.
+  Continue(Option<String>),
+  /// `return`, unwinding every enclosing loop up to the function call.
+  Return(Value),
 }
 
 pub fn json_to_zekken(val: &JsonValue) -> Value {
     match val {
-        JsonValue::Null => Value::Void,
+        JsonValue::Null => Value::Null,
         JsonValue::Bool(b) => Value::Boolean(*b),
         JsonValue::Number(n) => {
             if let Some(i) = n.as_i64() {
@@ -432,25 +1101,37 @@ pub fn json_to_zekken(val: &JsonValue) -> Value {
         JsonValue::String(s) => Value::String(s.clone()),
         JsonValue::Array(arr) => Value::Array(arr.iter().map(json_to_zekken).collect()),
         JsonValue::Object(obj) => {
-            let mut map = HashMap::new();
-            let mut keys = Vec::new();
-            for (k, v) in obj.iter() {
-                keys.push(Value::String(k.clone()));
-                map.insert(k.clone(), json_to_zekken(v));
-            }
-            map.insert("__keys__".to_string(), Value::Array(keys));
-            Value::Object(map)
+            Value::Object(obj.iter().map(|(k, v)| (k.clone(), json_to_zekken(v))).collect())
         }
     }
 }
 
+/// Signatures of the globals `Environment::new` declares, in the form of
+/// [`crate::libraries::Signatures`].
+pub const BUILTIN_SIGNATURES: crate::libraries::Signatures = &[
+    ("println", "|...values: any| -> void"),
+    ("print", "|...values: any| -> void"),
+    ("flush", "|| -> void"),
+    ("input", "|prompt: string| -> string"),
+    ("parse_json", "|text: string| -> any"),
+    ("memstats", "|| -> obj"),
+    ("queue", "|| -> obj"),
+    ("set_precision", "|digits?: int| -> void"),
+    ("format", "|value: any, spec?: string| -> string"),
+    ("diff", "|expected: any, actual: any| -> arr"),
+];
+
 impl Environment {
   pub fn new_scope_with_capacity(var_capacity: usize) -> Self {
+      crate::stats::scope(1);
       Environment {
           parent: None,
           variables: HashMap::with_capacity(var_capacity.max(4)),
           constants: HashMap::with_capacity(0),
           types: HashMap::with_capacity(var_capacity.max(4)),
+          deferred: Vec::new(),
+          control_flow: None,
+          shared: closure::CallScope::default(),
       }
   }
 
@@ -465,16 +1146,22 @@ impl Environment {
       });
 
       if let Some(mut env) = reused {
+          crate::stats::scope(1);
           env.parent = None;
           env.variables.clear();
           env.constants.clear();
           env.types.clear();
+          env.deferred.clear();
+          env.control_flow = None;
+          env.shared = closure::CallScope::detached();
           env.variables.reserve(var_capacity.max(4));
           env.types.reserve(var_capacity.max(4));
           return env;
       }
 
-      Self::new_scope_with_capacity(var_capacity)
+      let mut env = Self::new_scope_with_capacity(var_capacity);
+      env.shared = closure::CallScope::detached();
+      env
   }
 
   pub fn return_pooled_scope(mut env: Environment) {
@@ -484,17 +1171,23 @@ impl Environment {
       env.variables.clear();
       env.constants.clear();
       env.types.clear();
+      env.deferred.clear();
+      env.shared = closure::CallScope::default();
       SCOPE_POOL.with(|pool| {
           pool.borrow_mut().push(env);
       });
   }
 
   pub fn new() -> Self {
+      crate::stats::scope(1);
       let mut env = Environment {
           parent: None,
           variables: HashMap::with_capacity(64),
           constants: HashMap::with_capacity(16),
           types: HashMap::with_capacity(64),
+          deferred: Vec::new(),
+          control_flow: None,
+          shared: closure::CallScope::default(),
       };
 
       let disable_print = match std::env::var("ZEKKEN_DISABLE_PRINT") {
@@ -509,16 +1202,36 @@ impl Environment {
                 return Ok(Value::Void);
             }
 
-            let mut stdout = std::io::stdout();
+            let mut line = format_print_values(&args);
+            line.push('\n');
+            crate::output::print(&line).map_err(|e| e.to_string())?;
+
+            Ok(Value::Void)
+        })),
+        DataType::Fn,
+        true,
+      );
 
-            if args.is_empty() {
-                writeln!(stdout).map_err(|e| e.to_string())?;
+      env.declare_ref_typed(
+        "print",
+        Value::NativeFunction(Arc::new(move |args: Vec<Value>| -> Result<Value, String> {
+            if disable_print {
                 return Ok(Value::Void);
             }
+            crate::output::print(&format_print_values(&args)).map_err(|e| e.to_string())?;
+            Ok(Value::Void)
+        })),
+        DataType::Fn,
+        true,
+      );
 
-            let line = format_print_values(&args);
-            writeln!(stdout, "{}", line).map_err(|e| e.to_string())?;
-
+      env.declare_ref_typed(
+        "flush",
+        Value::NativeFunction(Arc::new(|args: Vec<Value>| -> Result<Value, String> {
+            if !args.is_empty() {
+                return Err("flush takes no arguments".to_string());
+            }
+            crate::output::flush().map_err(|e| e.to_string())?;
             Ok(Value::Void)
         })),
         DataType::Fn,
@@ -528,16 +1241,14 @@ impl Environment {
       env.declare(
         "input".to_string(), 
         Value::NativeFunction(Arc::new(|args| {
-          use std::io::{Write, stdin, stdout};
+          use std::io::stdin;
 
           if args.is_empty() {
               return Err("Input requires a prompt string".to_string());
           }
 
-          let mut stdout = stdout();
-
-          write!(stdout, "{}", args[0]).map_err(|e| e.to_string())?;
-          stdout.flush().map_err(|e| e.to_string())?;
+          crate::output::print(&args[0].to_string()).map_err(|e| e.to_string())?;
+          crate::output::flush().map_err(|e| e.to_string())?;
 
           let mut input = String::new();
           stdin().read_line(&mut input).map_err(|e| e.to_string())?;
@@ -560,6 +1271,60 @@ impl Environment {
             }
         })), true);
 
+      env.declare(
+        "memstats".to_string(),
+        Value::NativeFunction(Arc::new(|args: Vec<Value>| -> Result<Value, String> {
+            if !args.is_empty() {
+                return Err("memstats expects no arguments".to_string());
+            }
+            Ok(crate::stats::snapshot().to_value())
+        })),
+        true,
+      );
+
+      env.declare(
+        "set_precision".to_string(),
+        Value::NativeFunction(Arc::new(|args: Vec<Value>| -> Result<Value, String> {
+            match args.as_slice() {
+                [] => set_float_precision(None),
+                [Value::Int(digits)] if (0..=MAX_FLOAT_PRECISION as i64).contains(digits) => {
+                    set_float_precision(Some(*digits as usize))
+                }
+                _ => {
+                    return Err(format!(
+                        "set_precision expects a digit count from 0 to {}, or no arguments to restore the default",
+                        MAX_FLOAT_PRECISION
+                    ))
+                }
+            }
+            Ok(Value::Void)
+        })),
+        true,
+      );
+
+      env.declare(
+        "format".to_string(),
+        Value::NativeFunction(Arc::new(|args: Vec<Value>| -> Result<Value, String> {
+            match args.as_slice() {
+                [value] => Ok(Value::String(value.to_string())),
+                [value, Value::String(spec)] => format_with_spec(value, spec).map(Value::String),
+                _ => Err("format expects a value and an optional spec string".to_string()),
+            }
+        })),
+        true,
+      );
+
+      env.declare(
+        "diff".to_string(),
+        Value::NativeFunction(Arc::new(|args: Vec<Value>| -> Result<Value, String> {
+            match args.as_slice() {
+                [expected, actual] => Ok(Value::Array(diff::diff(expected, actual).iter().map(diff::Difference::to_value).collect())),
+                _ => Err("diff expects two values: the expected one and the actual one".to_string()),
+            }
+        })),
+        true,
+      );
+
       env.declare(
         "queue".to_string(),
         Value::NativeFunction(Arc::new(|args: Vec<Value>| -> Result<Value, String> {
@@ -568,7 +1333,7 @@ impl Environment {
             }
 
             let queue = Arc::new(Mutex::new(VecDeque::<Value>::new()));
-            let mut obj = HashMap::with_capacity(6);
+            let mut obj = IndexMap::with_capacity(6);
 
             {
                 let q = queue.clone();
@@ -665,24 +1430,77 @@ impl Environment {
   }
 
   pub fn new_with_parent(parent: Environment) -> Self {
+      crate::stats::scope(parent.depth() + 1);
       Environment {
           parent: Some(Rc::new(parent)),
           variables: HashMap::with_capacity(16),
           constants: HashMap::with_capacity(8),
           types: HashMap::with_capacity(16),
+          deferred: Vec::new(),
+          control_flow: None,
+          shared: closure::CallScope::default(),
       }
   }
 
   pub fn new_with_parent_capacity(parent: Environment, var_capacity: usize) -> Self {
+      crate::stats::scope(parent.depth() + 1);
       Environment {
           parent: Some(Rc::new(parent)),
           variables: HashMap::with_capacity(var_capacity.max(4)),
           constants: HashMap::with_capacity(0),
           types: HashMap::with_capacity(var_capacity.max(4)),
+          deferred: Vec::new(),
+          control_flow: None,
+          shared: closure::CallScope::default(),
+      }
+  }
+
+  /// The scope of a call made from `caller` to a function that needs a
+  /// parent scope. The parent is the outermost scope around `caller`, so
+  /// the function sees globals but not the caller's locals.
+  pub fn new_call_scope(caller: &Environment, var_capacity: usize) -> Self {
+      let mut outermost = None;
+      let mut scope = caller;
+      while let Some(parent) = scope.parent.as_ref() {
+          outermost = Some(parent);
+          scope = parent;
+      }
+      let parent = outermost.cloned().unwrap_or_else(|| Rc::new(caller.clone()));
+      crate::stats::scope(parent.depth() + 1);
+      Environment {
+          parent: Some(parent),
+          variables: HashMap::with_capacity(var_capacity.max(4)),
+          constants: HashMap::with_capacity(0),
+          types: HashMap::with_capacity(var_capacity.max(4)),
+          deferred: Vec::new(),
+          control_flow: None,
+          shared: closure::CallScope::default(),
+      }
+  }
+
+  /// The root of this scope's chain, which holds the globals, or `None`
+  /// when that is the scope of a call made without a parent scope.
+  pub fn globals(&self) -> Option<&Environment> {
+      let mut scope = self;
+      while let Some(parent) = scope.parent.as_deref() {
+          scope = parent;
+      }
+      (!scope.shared.is_detached()).then_some(scope)
+  }
+
+  /// Number of environments from this one up to the root, inclusive.
+  pub fn depth(&self) -> u64 {
+      let mut depth = 1;
+      let mut scope = self.parent.as_deref();
+      while let Some(env) = scope {
+          depth += 1;
+          scope = env.parent.as_deref();
       }
+      depth
   }
 
   pub fn declare(&mut self, name: String, value: Value, constant: bool) {
+      crate::stats::binding();
       let type_key = name.clone();
       if constant {
           self.constants.insert(name, value);
@@ -692,8 +1510,72 @@ impl Environment {
       self.types.entry(type_key).or_insert(DataType::Any);
   }
 
+  /// Whether the loop labelled `label` has to stop after its current
+  /// iteration. A signal aimed at this loop is consumed; one aimed at an
+  /// outer loop, or a `return`, is left for whatever encloses it. A
+  /// `continue` aimed at this loop lets it go on to its next iteration.
+  pub fn stop_loop(&mut self, label: Option<&str>) -> bool {
+      let (target, stop) = match &self.control_flow {
+          None => return false,
+          Some(ControlFlow::Return(_)) => return true,
+          Some(ControlFlow::Break(target)) => (target, true),
+          Some(ControlFlow::Continue(target)) => (target, false),
+      };
+      if target.is_none() || target.as_deref() == label {
+          self.control_flow = None;
+          return stop;
+      }
+      true
+  }
+
+  /// Consumes a pending `return` and hands back its value; called wherever a
+  /// function body (or a top-level statement) finishes.
+  pub fn take_return(&mut self) -> Option<Value> {
+      match self.control_flow.take() {
+          Some(ControlFlow::Return(value)) => Some(value),
+          other => {
+              self.control_flow = other;
+              None
+          }
+      }
+  }
+
+  /// Removes `name` from this scope so it can be redeclared and later put back
+  /// with `restore_binding`.
+  pub fn take_binding(&mut self, name: &str) -> SavedBinding {
+      SavedBinding {
+          variable: self.variables.remove(name),
+          constant: self.constants.remove(name),
+          ty: self.types.remove(name),
+      }
+  }
+
+  pub fn restore_binding(&mut self, name: &str, saved: SavedBinding) {
+      self.variables.remove(name);
+      self.constants.remove(name);
+      self.types.remove(name);
+      if let Some(value) = saved.variable {
+          self.variables.insert(name.to_string(), value);
+      }
+      if let Some(value) = saved.constant {
+          self.constants.insert(name.to_string(), value);
+      }
+      if let Some(ty) = saved.ty {
+          self.types.insert(name.to_string(), ty);
+      }
+  }
+
+  /// Binds a result the REPL echoed to `_` and to `_n`, where `n` is
+  /// `results` after counting this one.
+  pub fn bind_repl_result(&mut self, results: &mut usize, value: Value) {
+      *results += 1;
+      self.declare(format!("_{}", results), value.clone(), false);
+      self.declare("_".to_string(), value, false);
+  }
+
   #[inline]
   pub fn declare_ref(&mut self, name: &str, value: Value, constant: bool) {
+      crate::stats::binding();
       if constant {
           if let Some(slot) = self.constants.get_mut(name) {
               *slot = value;
@@ -719,7 +1601,7 @@ impl Environment {
       let mut env = self;
       loop {
           if let Some(t) = env.types.get(name) {
-              return Some(*t);
+              return Some(t.clone());
           }
           if let Some(parent) = env.parent.as_ref() {
               env = parent;
@@ -737,37 +1619,46 @@ impl Environment {
           Value::String(_) => "string",
           Value::Boolean(_) => "bool",
           Value::Array(_) => "arr",
-          Value::Object(_) => "obj",
+          Value::Object(_) | Value::Library(_) | Value::Enum { .. } => "obj",
           Value::Function(_) | Value::NativeFunction(_) => "fn",
+          Value::Null => "null",
           _ => "other",
       }
   }
 
   #[inline]
-  fn datatype_label(ty: &DataType) -> &'static str {
+  pub(crate) fn datatype_label(ty: &DataType) -> String {
       match ty {
-          DataType::Int => "int",
-          DataType::Float => "float",
-          DataType::String => "string",
-          DataType::Bool => "bool",
-          DataType::Object => "obj",
-          DataType::Array => "arr",
-          DataType::Fn => "fn",
-          DataType::Any => "any",
+          DataType::Int => "int".to_string(),
+          DataType::Float => "float".to_string(),
+          DataType::String => "string".to_string(),
+          DataType::Bool => "bool".to_string(),
+          DataType::Object => "obj".to_string(),
+          DataType::Array => "arr".to_string(),
+          DataType::ArrayOf(element) => format!("arr<{}>", Self::datatype_label(element)),
+          DataType::Nullable(inner) => format!("{}?", Self::datatype_label(inner)),
+          DataType::Fn => "fn".to_string(),
+          DataType::Decimal => "dec".to_string(),
+          DataType::Any => "any".to_string(),
       }
   }
 
   #[inline]
   fn value_matches_datatype(value: &Value, expected: &DataType) -> bool {
       match expected {
+          // A variable of any type may hold `null`.
+          _ if matches!(value, Value::Null) => true,
           DataType::Any => true,
           DataType::Int => matches!(value, Value::Int(_)),
           DataType::Float => matches!(value, Value::Float(_)),
           DataType::String => matches!(value, Value::String(_)),
           DataType::Bool => matches!(value, Value::Boolean(_)),
-          DataType::Object => matches!(value, Value::Object(_)),
-          DataType::Array => matches!(value, Value::Array(_)),
+          DataType::Object => matches!(value, Value::Object(_) | Value::Library(_) | Value::Enum { .. } | Value::Quantity(_) | Value::BigFloat(_) | Value::Expression(_)),
+          DataType::Array => matches!(value, Value::Array(_) | Value::Vector(_) | Value::Matrix(_)),
+          DataType::ArrayOf(element) => elements_match(value, element),
+          DataType::Nullable(inner) => Self::value_matches_datatype(value, inner),
           DataType::Fn => matches!(value, Value::Function(_) | Value::NativeFunction(_)),
+          DataType::Decimal => matches!(value, Value::Decimal(_)),
       }
   }
 
@@ -907,6 +1798,13 @@ impl From<FloatLit> for Value {
   }
 }
 
+impl From<&DecimalLit> for Value {
+  fn from(lit: &DecimalLit) -> Self {
+    // The lexer already rejected literals that do not parse.
+    Value::Decimal(Decimal::parse(&lit.value).unwrap_or(Decimal::from_int(0)))
+  }
+}
+
 impl From<StringLit> for Value {
   fn from(lit: StringLit) -> Self {
     Value::String(lit.value)
@@ -925,8 +1823,10 @@ impl From<ArrayLit> for Value {
       .map(|e| match *e {
         Expr::IntLit(i) => Value::Int(i.value),
         Expr::FloatLit(f) => Value::Float(f.value),
+        Expr::DecimalLit(d) => Value::from(&d),
         Expr::StringLit(s) => Value::String(s.value),
         Expr::BoolLit(b) => Value::Boolean(b.value),
+        Expr::NullLit(_) => Value::Null,
         _ => Value::Void,
       })
       .collect())
@@ -942,34 +1842,31 @@ impl From<ComplexLit> for Value {
   }
 }
 
-impl From<VectorLit> for Value {
-  fn from(lit: VectorLit) -> Self {
-      Value::Vector(lit.elements)
-  }
-}
-
-impl From<MatrixLit> for Value {
-  fn from(lit: MatrixLit) -> Self {
-      Value::Matrix(lit.rows)
-  }
-}
 
 impl Value {
     pub fn call_method(&self, method_name: &str, args: Vec<Value>, env: Option<&mut Environment>, variable_name: Option<&str>) -> Result<Value, String> {
-        if method_name == "format" {
-            if !args.is_empty() {
-                return Err("format takes no arguments".to_string());
+        self.call_resolved_method(Method::resolve(method_name), method_name, args, env, variable_name)
+    }
+
+    /// `call_method` with the name already resolved, as the parser does for
+    /// every method call it sees.
+    pub fn call_resolved_method(&self, method: Option<Method>, method_name: &str, args: Vec<Value>, env: Option<&mut Environment>, variable_name: Option<&str>) -> Result<Value, String> {
+        match method {
+            Some(Method::Format) => {
+                if !args.is_empty() {
+                    return Err("format takes no arguments".to_string());
+                }
+                return Ok(Value::String(self.to_pretty_string()));
             }
-            return Ok(Value::String(self.to_pretty_string()));
-        }
-        if method_name == "cast" {
-            return self.handle_cast(args);
+            Some(Method::Cast) => return self.handle_cast(args),
+            _ => {}
         }
 
         match self {
-            Value::String(s) => Self::handle_string_method(s, method_name, args),
-            Value::Array(arr) => Self::handle_array_method(arr, method_name, args, env, variable_name),
-            Value::Object(obj) => {
+            Value::String(s) => Self::handle_string_method(s, method, method_name, args),
+            Value::Array(arr) => Self::handle_array_method(arr, method, method_name, args, env, variable_name),
+            Value::Object(_) | Value::Library(_) | Value::Enum { .. } => {
+                let Some(obj) = self.as_object() else { unreachable!("objects, libraries and enum variants have fields") };
                 // First check if the object has the method as a native function
                 if let Some(Value::NativeFunction(func)) = obj.get(method_name) {
                     // Execute the native function directly
@@ -993,10 +1890,13 @@ impl Value {
                 }
                 
                 // If nothing else matched, try standard object methods
-                Self::handle_object_method(obj, method_name, args)
+                Self::handle_object_method(obj, method, method_name, args)
             }
-            Value::Int(n) => Self::handle_int_method(*n, method_name, args),
-            Value::Float(n) => Self::handle_float_method(*n, method_name, args),
+            Value::Int(n) => Self::handle_int_method(*n, method, method_name, args),
+            Value::Float(n) => Self::handle_float_method(*n, method, method_name, args),
+            Value::Decimal(d) => Self::handle_decimal_method(*d, method, method_name, args),
+            Value::BigFloat(b) => crate::libraries::bigfloat::method(b, method_name, &args),
+            Value::Expression(e) => crate::libraries::calc::method(e, method_name, &args),
             _ => Err(format!("Type '{}' does not support methods", self.type_name())),
         }
     }
@@ -1010,7 +1910,7 @@ impl Value {
             (Value::Float(l), Value::Int(r)) => *l == (*r as f64),
             (Value::String(l), Value::String(r)) => l == r,
             (Value::Boolean(l), Value::Boolean(r)) => l == r,
-            (Value::Void, Value::Void) => true,
+            (Value::Void, Value::Void) | (Value::Null, Value::Null) => true,
             _ => false,
         }
     }
@@ -1031,6 +1931,7 @@ impl Value {
             "int" => match self {
                 Value::Int(i) => Ok(Value::Int(*i)),
                 Value::Float(f) => Ok(Value::Int(*f as i64)),
+                Value::Decimal(d) => d.to_i64().map(Value::Int).ok_or_else(|| format!("Decimal {} is out of int range", d)),
                 Value::Boolean(b) => Ok(Value::Int(if *b { 1 } else { 0 })),
                 Value::String(s) => s
                     .trim()
@@ -1042,6 +1943,7 @@ impl Value {
             "float" => match self {
                 Value::Float(f) => Ok(Value::Float(*f)),
                 Value::Int(i) => Ok(Value::Float(*i as f64)),
+                Value::Decimal(d) => Ok(Value::Float(d.to_f64())),
                 Value::Boolean(b) => Ok(Value::Float(if *b { 1.0 } else { 0.0 })),
                 Value::String(s) => s
                     .trim()
@@ -1064,34 +1966,35 @@ impl Value {
                 }
                 _ => Err(format!("Cannot cast type '{}' to bool", self.type_name())),
             },
+            "dec" | "decimal" => to_decimal(self).map(Value::Decimal),
             _ => Err(format!("Unsupported cast target '{}'", target)),
         }
     }
 
-    fn handle_array_method(arr: &Vec<Value>, method_name: &str, mut args: Vec<Value>, env: Option<&mut Environment>, variable_name: Option<&str>) -> Result<Value, String> {
-        match method_name {
-            "length" => Ok(Value::Int(arr.len() as i64)),
-            "first" => {
+    fn handle_array_method(arr: &[Value], method: Option<Method>, method_name: &str, mut args: Vec<Value>, env: Option<&mut Environment>, variable_name: Option<&str>) -> Result<Value, String> {
+        match method {
+            Some(Method::Length) => Ok(Value::Int(arr.len() as i64)),
+            Some(Method::First) => {
                 if let Some(first) = arr.first() {
                     Ok(first.clone())
                 } else {
                     Err("Array is empty".to_string())
                 }
             }
-            "last" => {
+            Some(Method::Last) => {
                 if let Some(last) = arr.last() {
                     Ok(last.clone())
                 } else {
                     Err("Array is empty".to_string())
                 }
             }
-            "push" => {
+            Some(Method::Push) => {
                 if args.len() != 1 {
                     return Err("push requires exactly one argument".to_string());
                 }
                 if let Some(env) = env {
                     if let Some(var_name) = variable_name {
-                        let mut new_arr = arr.clone();
+                        let mut new_arr = arr.to_vec();
                         new_arr.push(args.remove(0));
                         env.assign(var_name, Value::Array(new_arr.clone()))
                             .map_err(|e| format!("Failed to update array: {}", e))?;
@@ -1103,8 +2006,8 @@ impl Value {
                     Err("push requires an environment to update the original array".to_string())
                 }
             }
-            "pop" => {
-                let mut new_arr = arr.clone();
+            Some(Method::Pop) => {
+                let mut new_arr = arr.to_vec();
                 if let Some(popped) = new_arr.pop() {
                     if let Some(env) = env {
                         if let Some(var_name) = variable_name {
@@ -1117,8 +2020,8 @@ impl Value {
                     Err("Array is empty".to_string())
                 }
             }
-            "shift" => {
-                let mut new_arr = arr.clone();
+            Some(Method::Shift) => {
+                let mut new_arr = arr.to_vec();
                 if new_arr.is_empty() {
                     return Err("Array is empty".to_string());
                 }
@@ -1131,13 +2034,13 @@ impl Value {
                 }
                 Ok(shifted)
             }
-            "unshift" => {
+            Some(Method::Unshift) => {
                 if args.len() != 1 {
                     return Err("unshift requires exactly one argument".to_string());
                 }
                 if let Some(env) = env {
                     if let Some(var_name) = variable_name {
-                        let mut new_arr = arr.clone();
+                        let mut new_arr = arr.to_vec();
                         new_arr.insert(0, args.remove(0));
                         env.assign(var_name, Value::Array(new_arr.clone()))
                             .map_err(|e| format!("Failed to update array: {}", e))?;
@@ -1149,7 +2052,38 @@ impl Value {
                     Err("unshift requires an environment to update the original array".to_string())
                 }
             }
-            "join" => {
+            Some(Method::Resize) => {
+                if args.is_empty() || args.len() > 2 {
+                    return Err("resize requires a length and an optional fill value".to_string());
+                }
+                let length = match &args[0] {
+                    Value::Int(n) if *n >= 0 => *n as usize,
+                    _ => return Err("resize length must be a non-negative int".to_string()),
+                };
+                check_array_length(length)?;
+                let fill = args.get(1).cloned().unwrap_or(Value::Void);
+                let (Some(env), Some(var_name)) = (env, variable_name) else {
+                    return Err("resize requires a variable name to update the original array".to_string());
+                };
+                let mut new_arr = arr.to_vec();
+                new_arr.resize(length, fill);
+                env.assign(var_name, Value::Array(new_arr.clone()))
+                    .map_err(|e| format!("Failed to update array: {}", e))?;
+                Ok(Value::Array(new_arr))
+            }
+            Some(Method::Fill) => {
+                if args.len() != 1 {
+                    return Err("fill requires exactly one argument".to_string());
+                }
+                let (Some(env), Some(var_name)) = (env, variable_name) else {
+                    return Err("fill requires a variable name to update the original array".to_string());
+                };
+                let new_arr = vec![args.remove(0); arr.len()];
+                env.assign(var_name, Value::Array(new_arr.clone()))
+                    .map_err(|e| format!("Failed to update array: {}", e))?;
+                Ok(Value::Array(new_arr))
+            }
+            Some(Method::Join) => {
                 if args.len() != 1 {
                     return Err("join requires one string argument".to_string());
                 }
@@ -1163,7 +2097,7 @@ impl Value {
                     .join(delim);
                 Ok(Value::String(joined))
             }
-            "remove" => {
+            Some(Method::Remove) => {
                 if args.len() != 1 {
                     return Err("remove requires exactly one argument (value to remove)".to_string());
                 }
@@ -1171,7 +2105,7 @@ impl Value {
 
                 if let Some(env) = env {
                     if let Some(var_name) = variable_name {
-                        let mut new_arr = arr.clone();
+                        let mut new_arr = arr.to_vec();
                         if let Some(pos) = new_arr
                             .iter()
                             .position(|x| Self::compare_values_simple(x, &value_to_remove))
@@ -1190,17 +2124,46 @@ impl Value {
                     Err("remove requires an environment to update the original array".to_string())
                 }
             }
+            Some(Method::IndexOf) => {
+                if args.len() != 1 {
+                    return Err("indexOf requires exactly one argument".to_string());
+                }
+                let index = arr.iter().position(|x| diff::equal(x, &args[0]));
+                Ok(Value::Int(index.map_or(-1, |i| i as i64)))
+            }
+            Some(Method::Count) => {
+                if args.len() != 1 {
+                    return Err("count requires exactly one argument".to_string());
+                }
+                Ok(Value::Int(arr.iter().filter(|x| diff::equal(x, &args[0])).count() as i64))
+            }
+            Some(Method::RemoveValue) => {
+                if args.len() != 1 {
+                    return Err("remove_value requires exactly one argument".to_string());
+                }
+                let (Some(env), Some(var_name)) = (env, variable_name) else {
+                    return Err("remove_value requires a variable name to update the original array".to_string());
+                };
+                let new_arr: Vec<Value> = arr.iter().filter(|x| !diff::equal(x, &args[0])).cloned().collect();
+                let removed = arr.len() - new_arr.len();
+                env.assign(var_name, Value::Array(new_arr))
+                    .map_err(|e| format!("Failed to update array: {}", e))?;
+                Ok(Value::Int(removed as i64))
+            }
+            Some(Method::SortByKeys) => sort::by_fields(arr, &args),
+            // The engines sort with the key function before a call gets here.
+            Some(Method::SortBy) => Err("sort_by has to be called from a script".to_string()),
             _ => Err(format!("Array method '{}' not supported", method_name)),
         }
     }
 
-    fn handle_string_method(s: &String, method_name: &str, args: Vec<Value>) -> Result<Value, String> {
-        match method_name {
-            "length" => Ok(Value::Int(s.len() as i64)),
-            "toUpper" => Ok(Value::String(s.to_uppercase())),
-            "toLower" => Ok(Value::String(s.to_lowercase())),
-            "trim" => Ok(Value::String(s.trim().to_string())),
-            "split" => {
+    fn handle_string_method(s: &str, method: Option<Method>, method_name: &str, args: Vec<Value>) -> Result<Value, String> {
+        match method {
+            Some(Method::Length) => Ok(Value::Int(s.chars().count() as i64)),
+            Some(Method::ToUpper) => Ok(Value::String(s.to_uppercase())),
+            Some(Method::ToLower) => Ok(Value::String(s.to_lowercase())),
+            Some(Method::Trim) => Ok(Value::String(s.trim().to_string())),
+            Some(Method::Split) => {
                 if args.len() != 1 {
                     return Err("split requires one argument".to_string());
                 }
@@ -1210,90 +2173,37 @@ impl Value {
                 };
                 Ok(Value::Array(s.split(delimiter).map(|part| Value::String(part.to_string())).collect()))
             }
+            Some(Method::CharCodeAt) => {
+                if args.len() != 1 {
+                    return Err("charCodeAt requires one index argument".to_string());
+                }
+                Ok(Value::Int(string_char_at(s, &args[0])? as i64))
+            }
             _ => Err(format!("String method '{}' not supported", method_name)),
         }
     }
 
-    fn handle_object_method(obj: &HashMap<String, Value>, method_name: &str, args: Vec<Value>) -> Result<Value, String> {
+    fn handle_object_method(obj: &IndexMap<String, Value>, method: Option<Method>, method_name: &str, args: Vec<Value>) -> Result<Value, String> {
         // First check if it's a NativeFunction
         if let Some(Value::NativeFunction(func)) = obj.get(method_name) {
             return (func)(args);
         }
 
         // If not a native function, try standard object methods
-        match method_name {
-            "keys" => {
-                let keys_value = obj.get("__keys__").cloned();
-                let keys = if let Some(Value::Array(keys)) = keys_value {
-                    keys
-                } else {
-                    Vec::new()
-                };
-                
-                let ordered_keys: Vec<Value> = keys.into_iter()
-                    .filter_map(|key| {
-                        if let Value::String(s) = key {
-                            if s != "__keys__" {
-                                Some(Value::String(s))
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    })
-                    .collect();
-                Ok(Value::Array(ordered_keys))
-            },
-            "values" => {
-                let keys_value = obj.get("__keys__").cloned();
-                let keys = if let Some(Value::Array(keys)) = keys_value {
-                    keys
-                } else {
-                    Vec::new()
-                };
-
-                let ordered_values: Vec<Value> = keys.into_iter()
-                    .filter_map(|key| {
-                        if let Value::String(s) = key {
-                            if s != "__keys__" {
-                                obj.get(&s).cloned()
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    })
-                    .collect();
-                Ok(Value::Array(ordered_values))
-            },
-            "entries" => {
-                let keys_value = obj.get("__keys__").cloned();
-                let keys = if let Some(Value::Array(keys)) = keys_value {
-                    keys
-                } else {
-                    Vec::new()
-                };
-
-                let entries: Vec<Value> = keys.into_iter()
-                    .filter_map(|key| {
-                        if let Value::String(s) = key {
-                            if s != "__keys__" {
-                                obj.get(&s).map(|value| {
-                                    Value::Array(vec![Value::String(s), value.clone()])
-                                })
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    })
-                    .collect();
-                Ok(Value::Array(entries))
-            },
-            "hasKey" => {
+        match method {
+            Some(Method::Keys) => Ok(Value::Array(
+                obj.iter().filter(|(k, v)| !is_internal_entry(k, v)).map(|(k, _)| Value::String(k.clone())).collect(),
+            )),
+            Some(Method::Values) => Ok(Value::Array(
+                obj.iter().filter(|(k, v)| !is_internal_entry(k, v)).map(|(_, v)| v.clone()).collect(),
+            )),
+            Some(Method::Entries) => Ok(Value::Array(
+                obj.iter()
+                    .filter(|(k, v)| !is_internal_entry(k, v))
+                    .map(|(k, v)| Value::Array(vec![Value::String(k.clone()), v.clone()]))
+                    .collect(),
+            )),
+            Some(Method::HasKey) => {
                 if args.len() != 1 {
                     return Err("hasKey requires one string argument".to_string());
                 }
@@ -1303,7 +2213,7 @@ impl Value {
                     Err("hasKey argument must be a string".to_string())
                 }
             }
-            "get" => {
+            Some(Method::Get) => {
                 if args.len() != 2 {
                     return Err("get requires two arguments: key and default value".to_string());
                 }
@@ -1313,42 +2223,66 @@ impl Value {
                     Err("get first argument must be a string".to_string())
                 }
             }
+            Some(Method::FindKey) => {
+                if args.len() != 1 {
+                    return Err("find_key requires exactly one argument".to_string());
+                }
+                Ok(obj
+                    .iter()
+                    .find(|(k, v)| !is_internal_entry(k, v) && diff::equal(v, &args[0]))
+                    .map_or(Value::Null, |(k, _)| Value::String(k.clone())))
+            }
             _ => Err(format!("Object method '{}' not supported", method_name)),
         }
     }
     
-    fn handle_int_method(n: i64, method_name: &str, _args: Vec<Value>) -> Result<Value, String> {
-        match method_name {
-            "isEven" => Ok(Value::Boolean(n % 2 == 0)),
-            "isOdd" => Ok(Value::Boolean(n % 2 != 0)),
+    fn handle_int_method(n: i64, method: Option<Method>, method_name: &str, _args: Vec<Value>) -> Result<Value, String> {
+        match method {
+            Some(Method::IsEven) => Ok(Value::Boolean(n % 2 == 0)),
+            Some(Method::IsOdd) => Ok(Value::Boolean(n % 2 != 0)),
             _ => Err(format!("Integer method '{}' not supported", method_name)),
         }
     }
 
-    fn handle_float_method(n: f64, method_name: &str, _args: Vec<Value>) -> Result<Value, String> {
-        match method_name {
-            "round" => Ok(Value::Int(n.round() as i64)),
-            "floor" => Ok(Value::Int(n.floor() as i64)),
-            "ceil" => Ok(Value::Int(n.ceil() as i64)),
-            "isEven" => Ok(Value::Boolean(n % 2.0 == 0.0)),
-            "isOdd" => Ok(Value::Boolean(n % 2.0 != 0.0)),
+    fn handle_float_method(n: f64, method: Option<Method>, method_name: &str, _args: Vec<Value>) -> Result<Value, String> {
+        match method {
+            Some(Method::Round) => Ok(Value::Int(n.round() as i64)),
+            Some(Method::Floor) => Ok(Value::Int(n.floor() as i64)),
+            Some(Method::Ceil) => Ok(Value::Int(n.ceil() as i64)),
+            Some(Method::IsEven) => Ok(Value::Boolean(n % 2.0 == 0.0)),
+            Some(Method::IsOdd) => Ok(Value::Boolean(n % 2.0 != 0.0)),
             _ => Err(format!("Float method '{}' not supported", method_name)),
         }
     }
 
-    fn type_name(&self) -> &'static str {
+    fn handle_decimal_method(d: Decimal, method: Option<Method>, method_name: &str, args: Vec<Value>) -> Result<Value, String> {
+        match method {
+            Some(Method::Round) => decimal_round(d, &args).map(Value::Decimal),
+            Some(Method::Scale) => Ok(Value::Int(d.scale() as i64)),
+            Some(Method::ToFloat) => Ok(Value::Float(d.to_f64())),
+            _ => Err(format!("Decimal method '{}' not supported", method_name)),
+        }
+    }
+
+    pub(crate) fn type_name(&self) -> &'static str {
         match self {
             Value::Int(_) => "int",
             Value::Float(_) => "float",
             Value::String(_) => "string",
             Value::Boolean(_) => "boolean",
             Value::Array(_) => "array",
-            Value::Object(_) => "object",
+            Value::Object(_) | Value::Library(_) | Value::Enum { .. } => "object",
             Value::NativeFunction(_) => "native function",
             Value::Function(_) => "function",
             Value::Complex { .. } => "complex",
+            Value::Decimal(_) => "dec",
             Value::Vector(_) => "vector",
             Value::Matrix(_) => "matrix",
+            Value::Quantity(_) => "quantity",
+            Value::BigFloat(_) => "bigfloat",
+            Value::Expression(_) => "expression",
+            Value::Resource(_) => "resource",
+            Value::Null => "null",
             Value::Void => "void",
         }
     }
@@ -0,0 +1,121 @@
+//! Point-in-time copies of the bindings visible from an environment.
+//!
+//! The REPL's `:diff` and `zekken debug step` snapshot the environment before
+//! running a statement and diff it against a snapshot taken afterwards, to
+//! show what the statement actually changed. Under `--env-eval` a caught
+//! error's `env_eval` evaluates against a snapshot of where it was caught.
+
+// Only the CLI takes snapshots; the wasm build never does.
+#![allow(dead_code)]
+
+use super::{Environment, Value};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
+
+/// Every binding visible from a scope, inner scopes shadowing outer ones.
+#[derive(Debug, Clone, Default)]
+pub struct EnvSnapshot {
+    bindings: BTreeMap<String, Value>,
+}
+
+/// Bindings that differ between two snapshots, each list sorted by name.
+#[derive(Debug, Clone, Default)]
+pub struct EnvDiff {
+    pub added: Vec<(String, Value)>,
+    /// Name, old value, new value.
+    pub changed: Vec<(String, Value, Value)>,
+    pub removed: Vec<(String, Value)>,
+}
+
+impl Environment {
+    pub fn snapshot(&self) -> EnvSnapshot {
+        let mut bindings = BTreeMap::new();
+        let mut scope = Some(self);
+        while let Some(env) = scope {
+            for (name, value) in env.variables.iter().chain(env.constants.iter()) {
+                bindings.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+            scope = env.parent.as_deref();
+        }
+        EnvSnapshot { bindings }
+    }
+}
+
+impl EnvSnapshot {
+    /// A fresh scope holding every binding in the snapshot.
+    pub fn to_scope(&self) -> Environment {
+        let mut scope = Environment::new();
+        for (name, value) in &self.bindings {
+            scope.declare_ref(name, value.clone(), false);
+        }
+        scope
+    }
+
+    /// What changed going from `self` to the later snapshot `after`.
+    pub fn diff(&self, after: &EnvSnapshot) -> EnvDiff {
+        let mut diff = EnvDiff::default();
+        for (name, new) in &after.bindings {
+            match self.bindings.get(name) {
+                None => diff.added.push((name.clone(), new.clone())),
+                Some(old) if !same_value(old, new) => diff.changed.push((name.clone(), old.clone(), new.clone())),
+                Some(_) => {}
+            }
+        }
+        for (name, old) in &self.bindings {
+            if !after.bindings.contains_key(name) {
+                diff.removed.push((name.clone(), old.clone()));
+            }
+        }
+        diff
+    }
+}
+
+impl EnvDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl Display for EnvDiff {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (name, value) in &self.added {
+            writeln!(f, "+ {} = {}", name, value)?;
+        }
+        for (name, old, new) in &self.changed {
+            writeln!(f, "~ {}: {} -> {}", name, old, new)?;
+        }
+        for (name, value) in &self.removed {
+            writeln!(f, "- {} = {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+// Structural equality, with functions and resources compared by identity.
+fn same_value(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Int(l), Value::Int(r)) => l == r,
+        (Value::Float(l), Value::Float(r)) => l.to_bits() == r.to_bits(),
+        (Value::String(l), Value::String(r)) => l == r,
+        (Value::Boolean(l), Value::Boolean(r)) => l == r,
+        (Value::Array(l), Value::Array(r)) => l.len() == r.len() && l.iter().zip(r).all(|(l, r)| same_value(l, r)),
+        (Value::Object(l), Value::Object(r)) => {
+            l.len() == r.len() && l.iter().all(|(key, l)| r.get(key).is_some_and(|r| same_value(l, r)))
+        }
+        (Value::Library(l), Value::Library(r)) => Arc::ptr_eq(l, r),
+        (Value::Enum { payload: l, .. }, Value::Enum { payload: r, .. }) => Arc::ptr_eq(l, r),
+        (Value::Function(l), Value::Function(r)) => Arc::ptr_eq(&l.body, &r.body),
+        (Value::NativeFunction(l), Value::NativeFunction(r)) => Arc::ptr_eq(l, r),
+        (Value::Complex { real: lr, imag: li }, Value::Complex { real: rr, imag: ri }) => lr == rr && li == ri,
+        (Value::Decimal(l), Value::Decimal(r)) => l == r && l.scale() == r.scale(),
+        (Value::Vector(l), Value::Vector(r)) => l == r,
+        (Value::Matrix(l), Value::Matrix(r)) => l == r,
+        (Value::Quantity(l), Value::Quantity(r)) => l == r,
+        (Value::BigFloat(l), Value::BigFloat(r)) => l == r && l.precision() == r.precision(),
+        (Value::Expression(l), Value::Expression(r)) => l == r,
+        (Value::Resource(l), Value::Resource(r)) => l.same_resource(r),
+        (Value::Void, Value::Void) | (Value::Null, Value::Null) => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,119 @@
+//! Stable sorting for `sort_by` and `sort_by_keys`.
+//!
+//! Keys order numbers with numbers, decimals with decimals, strings with
+//! strings and bools with bools, and arrays element by element, so a key
+//! function can return `[age, name]` to sort on both. `null` comes before
+//! everything else, which puts records missing a field first. Any other mix
+//! of types is an error rather than an arbitrary order.
+
+use super::Value;
+use std::cmp::Ordering;
+
+/// How `a` and `b` order as sort keys.
+pub fn compare(a: &Value, b: &Value) -> Result<Ordering, String> {
+    Ok(match (a, b) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Less,
+        (_, Value::Null) => Ordering::Greater,
+        (Value::Int(l), Value::Int(r)) => l.cmp(r),
+        (Value::Int(l), Value::Float(r)) => (*l as f64).total_cmp(r),
+        (Value::Float(l), Value::Int(r)) => l.total_cmp(&(*r as f64)),
+        (Value::Float(l), Value::Float(r)) => l.total_cmp(r),
+        (Value::Decimal(l), Value::Decimal(r)) => l.cmp(r),
+        (Value::String(l), Value::String(r)) => l.cmp(r),
+        (Value::Boolean(l), Value::Boolean(r)) => l.cmp(r),
+        (Value::Array(l), Value::Array(r)) => {
+            for (l, r) in l.iter().zip(r) {
+                match compare(l, r)? {
+                    Ordering::Equal => {}
+                    order => return Ok(order),
+                }
+            }
+            l.len().cmp(&r.len())
+        }
+        _ => return Err(format!("Cannot compare {} with {} when sorting", a.type_name(), b.type_name())),
+    })
+}
+
+/// The arguments of `sort_by => |key_fn, descending|`: the function that
+/// picks each element's key, and whether the largest key comes first.
+pub fn sort_by_args(args: &[Value]) -> Result<(&Value, bool), String> {
+    let (key_fn, descending) = match args {
+        [key_fn] => (key_fn, false),
+        [key_fn, Value::Boolean(descending)] => (key_fn, *descending),
+        [_, other] => return Err(format!("sort_by expects descending to be a bool, got {}", other.type_name())),
+        _ => return Err("sort_by requires a key function and an optional descending flag".to_string()),
+    };
+    match key_fn {
+        Value::Function(_) | Value::NativeFunction(_) => Ok((key_fn, descending)),
+        other => Err(format!("sort_by expects a function, got {}", other.type_name())),
+    }
+}
+
+/// `items` sorted by `keys`, the key of each item in order. Items whose
+/// keys are equal keep their order.
+pub fn by_keys(items: &[Value], keys: Vec<Value>, descending: bool) -> Result<Value, String> {
+    let mut pairs: Vec<(Value, &Value)> = keys.into_iter().zip(items).collect();
+    let mut error = None;
+    pairs.sort_by(|(a, _), (b, _)| {
+        let order = compare(a, b).unwrap_or_else(|e| {
+            error.get_or_insert(e);
+            Ordering::Equal
+        });
+        if descending { order.reverse() } else { order }
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(Value::Array(pairs.into_iter().map(|(_, item)| item.clone()).collect())),
+    }
+}
+
+/// `items`, which must all be objects, sorted on the fields named in
+/// `fields`: by the first, then the next among equals, and so on. A name
+/// starting with `-` sorts that field largest first. A missing field
+/// counts as `null`.
+pub fn by_fields(items: &[Value], args: &[Value]) -> Result<Value, String> {
+    let [Value::Array(fields)] = args else {
+        return Err("sort_by_keys requires one array of field names".to_string());
+    };
+    let fields = fields
+        .iter()
+        .map(|field| match field {
+            Value::String(name) => Ok(match name.strip_prefix('-') {
+                Some(name) => (name, true),
+                None => (name.as_str(), false),
+            }),
+            other => Err(format!("sort_by_keys expects field names to be strings, got {}", other.type_name())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let records = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| match item {
+            Value::Object(record) => Ok(record),
+            other => Err(format!("sort_by_keys expects an array of objects, but element {} is {}", i, other.type_name())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut order: Vec<usize> = (0..records.len()).collect();
+    let mut error = None;
+    order.sort_by(|&i, &j| {
+        for &(name, descending) in &fields {
+            let a = records[i].get(name).unwrap_or(&Value::Null);
+            let b = records[j].get(name).unwrap_or(&Value::Null);
+            match compare(a, b) {
+                Ok(Ordering::Equal) => {}
+                Ok(order) => return if descending { order.reverse() } else { order },
+                Err(e) => {
+                    error.get_or_insert(format!("{} (field '{}')", e, name));
+                    return Ordering::Equal;
+                }
+            }
+        }
+        Ordering::Equal
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(Value::Array(order.into_iter().map(|i| items[i].clone()).collect())),
+    }
+}
@@ -0,0 +1,164 @@
+//! Message catalog for showing diagnostics in languages other than English.
+//!
+//! The interpreter builds every error with its English message, and that is
+//! what `catch` blocks, tests and deduplication see. The catalog only changes
+//! how an error is printed: the kind header, the `expected:`/`found:` labels
+//! and any message that matches one of the language's templates. A `{}` in a
+//! template stands for a name or value copied over unchanged. Messages without
+//! an entry stay in English.
+//!
+//! The language comes from `ZEKKEN_LANG` (`es`, `es_ES.UTF-8`, ...) or, when
+//! that is unset, from `[language] lang` in `Zekken.toml`.
+
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    En,
+    Es,
+}
+
+impl Lang {
+    /// Language codes accepted by `ZEKKEN_LANG` and `language.lang`.
+    pub const CODES: &'static [&'static str] = &["en", "es"];
+
+    /// Parses `es`, `es-MX` or a locale like `es_ES.UTF-8`.
+    pub fn from_code(code: &str) -> Option<Self> {
+        let base = code.split(['_', '-', '.']).next().unwrap_or("");
+        match base.to_ascii_lowercase().as_str() {
+            "en" => Some(Self::En),
+            "es" => Some(Self::Es),
+            _ => None,
+        }
+    }
+
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::En => &[],
+            Self::Es => SPANISH,
+        }
+    }
+}
+
+/// The language diagnostics are printed in for this process.
+pub fn active() -> Lang {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(lang) = std::env::var("ZEKKEN_LANG").ok().and_then(|code| Lang::from_code(&code)) {
+        return lang;
+    }
+    crate::config::language()
+}
+
+/// `message` in the active language.
+pub fn message(message: &str) -> Cow<'_, str> {
+    translate(active(), message)
+}
+
+/// `message` in `lang`, or unchanged when the catalog has no entry for it.
+pub fn translate(lang: Lang, message: &str) -> Cow<'_, str> {
+    for (template, translation) in lang.catalog() {
+        if let Some(holes) = holes(template, message) {
+            let mut out = String::new();
+            let mut holes = holes.into_iter();
+            for (i, part) in translation.split("{}").enumerate() {
+                if i > 0 {
+                    out.push_str(holes.next().unwrap_or(""));
+                }
+                out.push_str(part);
+            }
+            return Cow::Owned(out);
+        }
+    }
+    Cow::Borrowed(message)
+}
+
+/// The text `message` puts in each `{}` of `template`, if it matches.
+fn holes<'m>(template: &str, message: &'m str) -> Option<Vec<&'m str>> {
+    let parts: Vec<&str> = template.split("{}").collect();
+    let mut rest = message.strip_prefix(parts[0])?;
+    let mut holes = Vec::new();
+    for (i, part) in parts.iter().enumerate().skip(1) {
+        if i == parts.len() - 1 {
+            holes.push(rest.strip_suffix(part)?);
+            rest = "";
+        } else {
+            let at = rest.find(part)?;
+            holes.push(&rest[..at]);
+            rest = &rest[at + part.len()..];
+        }
+    }
+    rest.is_empty().then_some(holes)
+}
+
+/// The label for a detail line such as `expected:`, padded so the values
+/// after `expected:` and `found:` line up.
+pub fn label(name: &str) -> String {
+    let width = ["expected", "found"]
+        .iter()
+        .map(|name| message(name).chars().count() + 1)
+        .max()
+        .unwrap_or(0);
+    format!("  {:<width$}", format!("{}:", message(name)))
+}
+
+const SPANISH: &[(&str, &str)] = &[
+    ("Syntax Error", "Error de sintaxis"),
+    ("Runtime Error", "Error de ejecución"),
+    ("Type Error", "Error de tipo"),
+    ("Reference Error", "Error de referencia"),
+    ("Library Error", "Error de biblioteca"),
+    ("Internal Error", "Error interno"),
+    ("Warning [{}]", "Advertencia [{}]"),
+    ("Deprecated [{}]", "Obsoleto [{}]"),
+    ("expected", "esperado"),
+    ("found", "encontrado"),
+    ("kind", "tipo"),
+    ("value", "valor"),
+    ("help", "ayuda"),
+    ("fix", "corrección"),
+    ("add '{}' after this", "agrega '{}' después de esto"),
+    ("add '{}' before this", "agrega '{}' antes de esto"),
+    ("remove this ';'", "quita este ';'"),
+    ("declare it with `let {}: {} = ...;`", "declárala con `let {}: {} = ...;`"),
+    ("declare it before use with `let {}: any = ...;`", "declárala antes de usarla con `let {}: any = ...;`"),
+    ("declare it with `func {} |...| { ... }`", "declárala con `func {} |...| { ... }`"),
+    ("declare '{}' with 'let' instead of 'const' so it can change", "declara '{}' con 'let' en lugar de 'const' para poder cambiarla"),
+    ("Variable '{}' not found", "No se encontró la variable '{}'"),
+    ("Function '{}' not found", "No se encontró la función '{}'"),
+    ("Property '{}' not found", "No se encontró la propiedad '{}'"),
+    ("Cannot reassign constant '{}'", "No se puede reasignar la constante '{}'"),
+    ("Cannot call non-function value", "No se puede llamar a un valor que no es una función"),
+    ("Cannot export undefined value '{}'", "No se puede exportar el valor no definido '{}'"),
+    ("Cannot cast type '{}' to {}", "No se puede convertir el tipo '{}' a {}"),
+    ("Cannot cast string '{}' to {}", "No se puede convertir la cadena '{}' a {}"),
+    ("Type mismatch in variable declaration '{}'", "Tipo incorrecto en la declaración de la variable '{}'"),
+    ("Type mismatch in assignment to '{}'", "Tipo incorrecto al asignar a '{}'"),
+    ("Type mismatch in function return value", "Tipo incorrecto en el valor devuelto por la función"),
+    ("Type mismatch for parameter '{}' of '{}'", "Tipo incorrecto para el parámetro '{}' de '{}'"),
+    ("Missing required argument '{}' for '{}'", "Falta el argumento obligatorio '{}' de '{}'"),
+    ("Expected {} arguments for '{}' but got {}", "Se esperaban {} argumentos para '{}' pero se recibieron {}"),
+    ("Division by zero", "División entre cero"),
+    ("Modulo by zero", "Módulo entre cero"),
+    ("Array index {} out of bounds", "El índice de arreglo {} está fuera de rango"),
+    ("Object index {} out of bounds", "El índice de objeto {} está fuera de rango"),
+    ("Array is empty", "El arreglo está vacío"),
+    ("Invalid member access", "Acceso a miembro no válido"),
+    ("Invalid property access", "Acceso a propiedad no válido"),
+    ("Invalid assignment target", "Destino de asignación no válido"),
+    ("Invalid comparison", "Comparación no válida"),
+    ("Invalid operand types for addition", "Tipos de operandos no válidos para la suma"),
+    ("Invalid operand types for subtraction", "Tipos de operandos no válidos para la resta"),
+    ("Invalid operand types for multiplication", "Tipos de operandos no válidos para la multiplicación"),
+    ("Invalid operand types for division", "Tipos de operandos no válidos para la división"),
+    ("Invalid operand types for modulo", "Tipos de operandos no válidos para el módulo"),
+    ("Unknown operator: {}", "Operador desconocido: {}"),
+    ("Unterminated string literal", "Cadena sin cerrar"),
+    ("Unterminated block comment", "Comentario de bloque sin cerrar"),
+    ("Unknown escape sequence", "Secuencia de escape desconocida"),
+    ("Unexpected tokens after expression", "Símbolos inesperados después de la expresión"),
+    ("Unexpected ';' after expression", "';' inesperado después de la expresión"),
+    ("Expected expression", "Se esperaba una expresión"),
+    ("Expected identifier", "Se esperaba un identificador"),
+    ("Unknown loop label '{}'", "Etiqueta de bucle desconocida '{}'"),
+];
@@ -4,6 +4,8 @@ use std::error::Error;
 use std::collections::HashSet;
 use std::sync::Mutex;
 
+pub mod i18n;
+
 #[derive(Debug, Clone)]
 pub struct ErrorContext {
     pub filename: String,
@@ -95,6 +97,19 @@ impl ErrorContext {
         1
     }
 
+    /// Context for `line`/`column` of in-memory `source`, such as the REPL's
+    /// session so far. `span_len` keeps a span already known for the error.
+    #[allow(dead_code)]
+    pub fn from_source(filename: &str, source: &str, line: usize, column: usize, span_len: Option<usize>) -> Self {
+        let raw_line = source
+            .lines()
+            .nth(line.saturating_sub(1))
+            .map(|l| l.trim_end().to_string())
+            .unwrap_or("<line not found>".to_string());
+        let inferred_len = span_len.unwrap_or_else(|| Self::infer_span_len(&raw_line, column));
+        Self::new_with_span(filename.to_string(), line, column, highlight_zekken_line(&raw_line), inferred_len)
+    }
+
     pub fn from_env(line: usize, column: usize) -> Self {
         Self::from_env_with_span(line, column, None)
     }
@@ -145,31 +160,65 @@ pub enum ErrorKind {
     Runtime,
     Type,
     Reference,
+    /// A library function failed for a reason outside the script, such as a
+    /// missing file. These carry an [`ErrorCode`].
+    Library,
     Internal,
 }
 
+/// What a library function says went wrong: a stable `code` such as
+/// `ENOENT` that a `catch` can branch on, and whether the same call might
+/// succeed if tried again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub retryable: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct ZekkenError {
     pub kind: ErrorKind,
     pub message: String,
     pub context: ErrorContext,
     pub extra: Option<String>, // For expected/found, etc.
+    /// Advice printed as a `help:` line under the report.
+    pub help: Option<String>,
+    /// A concrete edit, printed next to the caret (e.g. "add ';' after this").
+    pub fix: Option<String>,
+    /// Set on [`ErrorKind::Library`] errors.
+    pub code: Option<ErrorCode>,
 }
 
 impl ZekkenError {
     pub fn syntax(msg: &str, line: usize, column: usize, expected: Option<&str>, found: Option<&str>) -> Self {
-        let ctx = ErrorContext::from_env(line, column);
+        Self::syntax_in(ErrorContext::from_env(line, column), msg, expected, found)
+    }
+
+    pub fn syntax_with_span(
+        msg: &str,
+        line: usize,
+        column: usize,
+        span_len: usize,
+        expected: Option<&str>,
+        found: Option<&str>,
+    ) -> Self {
+        let ctx = ErrorContext::from_env_with_span(line, column, Some(span_len));
+        Self::syntax_in(ctx, msg, expected, found)
+    }
+
+    fn syntax_in(ctx: ErrorContext, msg: &str, expected: Option<&str>, found: Option<&str>) -> Self {
         let mut extra = String::new();
         // If expected is a type token, try to pretty it up
         let pretty_expected = if let Some(e) = expected {
             // If the string already looks like a full type list or hint, don't pretty-print
-            if e.contains("a type (int, float, string, bool, obj, arr, fn)") {
+            if e.contains(crate::lexer::type_hint()) {
                 e
             } else {
                 match e {
-                    "DataType(Any)" => "a type (int, float, string, bool, obj, arr, fn)",
+                    "DataType(Any)" => crate::lexer::type_hint(),
                     "DataType(Int)" => "Int Type (int)",
                     "DataType(Float)" => "Float Type (float)",
+                    "DataType(Decimal)" => "Decimal Type (dec)",
                     "DataType(String)" => "String Type (string)",
                     "DataType(Bool)" => "Bool Type (bool)",
                     "DataType(Object)" => "Object Type (obj)",
@@ -184,10 +233,11 @@ impl ZekkenError {
 
         
         // Use colorize function for expected/found
+        let (expected_label, found_label) = (i18n::label("expected"), i18n::label("found"));
         if let Some(_e) = expected {
             extra.push_str(&format!(
                 "{} {}{}\n",
-                colorize("  expected:", "\x1b[1;90m"),
+                colorize(&expected_label, "\x1b[1;90m"),
                 colorize(pretty_expected, "\x1b[1;32m"),
                 colorize("", "\x1b[0m")
             ));
@@ -195,7 +245,7 @@ impl ZekkenError {
         if let Some(f) = found {
             extra.push_str(&format!(
                 "{} {}{}\n",
-                colorize("  found:   ", "\x1b[1;90m"), 
+                colorize(&found_label, "\x1b[1;90m"),
                 colorize(f, "\x1b[1;31m"), 
                 colorize("", "\x1b[0m")
             ));
@@ -207,6 +257,9 @@ impl ZekkenError {
             message: msg.to_string(),
             context: ctx,
             extra: if extra.is_empty() { None } else { Some(extra) },
+            help: None,
+            fix: None,
+            code: None,
         }
     }
     pub fn runtime(msg: &str, line: usize, column: usize, details: Option<&str>) -> Self {
@@ -226,6 +279,9 @@ impl ZekkenError {
             message: msg.to_string(),
             context: ctx,
             extra: details.map(|d| d.to_string()),
+            help: None,
+            fix: None,
+            code: None,
         }
     }
 
@@ -238,12 +294,13 @@ impl ZekkenError {
         span_len: usize,
     ) -> Self {
         let ctx = ErrorContext::from_env_with_span(line, column, Some(span_len));
+        let (expected_label, found_label) = (i18n::label("expected"), i18n::label("found"));
         let extra = format!(
             "{} {}{}\n{} {}{}\n",
-            colorize("  expected:", "\x1b[1;90m"),
+            colorize(&expected_label, "\x1b[1;90m"),
             colorize(expected, "\x1b[1;32m"),
             colorize("", "\x1b[0m"),
-            colorize("  found:   ", "\x1b[1;90m"),
+            colorize(&found_label, "\x1b[1;90m"),
             colorize(found, "\x1b[1;31m"),
             colorize("", "\x1b[0m")
         );
@@ -252,6 +309,9 @@ impl ZekkenError {
             message: msg.to_string(),
             context: ctx,
             extra: Some(extra),
+            help: None,
+            fix: None,
+            code: None,
         }
     }
 
@@ -265,7 +325,7 @@ impl ZekkenError {
         let ctx = ErrorContext::from_env_with_span(line, column, Some(span_len));
         let extra = format!(
             "{} {}{}\n",
-            colorize("  kind:", "\x1b[1;90m"),
+            colorize(&format!("  {}:", i18n::message("kind")), "\x1b[1;90m"),
             colorize(kind, "\x1b[1;31m"),
             colorize("", "\x1b[0m")
         );
@@ -274,6 +334,9 @@ impl ZekkenError {
             message: msg.to_string(),
             context: ctx,
             extra: Some(extra),
+            help: None,
+            fix: None,
+            code: None,
         }
     }
 
@@ -291,15 +354,107 @@ impl ZekkenError {
             message: msg.to_string(),
             context: ctx,
             extra: details.map(|d| d.to_string()),
+            help: None,
+            fix: None,
+            code: None,
         }
     }
+    /// This error as the library failure `code`.
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.kind = ErrorKind::Library;
+        self.code = Some(code);
+        self
+    }
+
     pub fn internal(msg: &str) -> Self {
         Self {
             kind: ErrorKind::Internal,
             message: msg.to_string(),
             context: ErrorContext::new("<internal>".to_string(), 0, 0, "".to_string()),
             extra: None,
+            help: None,
+            fix: None,
+            code: None,
+        }
+    }
+
+    /// This error moved into `source`, an in-memory file named `filename`
+    /// whose line `first_line` is where the code that failed starts.
+    #[allow(dead_code)]
+    pub fn relocated(&self, filename: &str, source: &str, first_line: usize) -> Self {
+        let mut error = self.clone();
+        if error.kind != ErrorKind::Internal {
+            let span = self.context.pointer.trim_start().chars().count();
+            error.context = ErrorContext::from_source(
+                filename,
+                source,
+                first_line + self.context.line.saturating_sub(1),
+                self.context.column,
+                (span > 1).then_some(span),
+            );
+        }
+        error
+    }
+
+    /// The full report with location, source line and caret, even in the REPL.
+    #[allow(dead_code)]
+    pub fn full_report(&self) -> String {
+        struct Full<'a>(&'a ZekkenError);
+        impl fmt::Display for Full<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.write_full(f)
+            }
         }
+        Full(self).to_string()
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn with_fix(mut self, fix: impl Into<String>) -> Self {
+        self.fix = Some(fix.into());
+        self
+    }
+
+    /// Adds a `value:` line previewing the array or object the error is
+    /// about; other values are already described by their type.
+    pub fn with_value(mut self, value: &crate::environment::Value) -> Self {
+        if let Some(preview) = value.preview() {
+            let line = format!(
+                "{} {}\n",
+                colorize(&i18n::label("value"), "\x1b[1;90m"),
+                colorize(&preview, "\x1b[1;31m")
+            );
+            self.extra.get_or_insert_with(String::new).push_str(&line);
+        }
+        self
+    }
+
+    /// The diagnostic as a JSON object for `zekken check --json`.
+    #[allow(dead_code)]
+    pub fn to_json(&self) -> serde_json::Value {
+        let kind = match self.kind {
+            ErrorKind::Syntax => "syntax",
+            ErrorKind::Runtime => "runtime",
+            ErrorKind::Type => "type",
+            ErrorKind::Reference => "reference",
+            ErrorKind::Library => "library",
+            ErrorKind::Internal => "internal",
+        };
+        serde_json::json!({
+            "severity": "error",
+            "lint": null,
+            "kind": kind,
+            "message": self.message,
+            "file": self.context.filename,
+            "line": self.context.line,
+            "column": self.context.column,
+            "help": self.help,
+            "fix": self.fix,
+            "code": self.code.map(|code| code.code),
+        })
     }
 
     /// Render a REPL-friendly error string (single-line, no file/line context)
@@ -310,9 +465,10 @@ impl ZekkenError {
             ErrorKind::Runtime => "Runtime Error",
             ErrorKind::Type => "Type Error",
             ErrorKind::Reference => "Reference Error",
+            ErrorKind::Library => "Library Error",
             ErrorKind::Internal => "Internal Error",
         };
-        let mut msg = format!("{}: {}", kind, self.message);
+        let mut msg = format!("{}{}: {}", i18n::message(kind), self.code_suffix(), i18n::message(&self.message));
         if let Some(extra) = &self.extra {
             // Remove ANSI color codes for REPL and trim lines
             let plain = regex::Regex::new(r"\x1b\[[0-9;]*m").unwrap().replace_all(extra, "");
@@ -320,6 +476,12 @@ impl ZekkenError {
                 msg.push_str(&format!("\n  {}", line.trim()));
             }
         }
+        if let Some(fix) = &self.fix {
+            msg.push_str(&format!("\n  {}: {}", i18n::message("fix"), i18n::message(fix)));
+        }
+        if let Some(help) = &self.help {
+            msg.push_str(&format!("\n  {}: {}", i18n::message("help"), i18n::message(help)));
+        }
         msg
     }
 }
@@ -349,31 +511,67 @@ fn colorize(text: &str, color_code: &str) -> String {
     }
 }
 
-impl fmt::Display for ZekkenError {
-    #[cfg(target_arch = "wasm32")]
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let (kind, color) = match self.kind {
+impl ZekkenError {
+    fn kind_label(&self) -> (&'static str, &'static str) {
+        match self.kind {
             ErrorKind::Syntax => ("Syntax Error", "\x1b[1;31m"),
             ErrorKind::Runtime => ("Runtime Error", "\x1b[1;35m"),
             ErrorKind::Type => ("Type Error", "\x1b[1;33m"),
             ErrorKind::Reference => ("Reference Error", "\x1b[1;34m"),
+            ErrorKind::Library => ("Library Error", "\x1b[1;35m"),
             ErrorKind::Internal => ("Internal Error", "\x1b[1;41m"),
-        };
-        let kind_str = colorize(kind, color);
+        }
+    }
+
+    fn write_full(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (kind, color) = self.kind_label();
+        self.write_report(f, &colorize(&format!("{}{}", i18n::message(kind), self.code_suffix()), color))
+    }
+
+    /// ` [ENOENT]` after the kind of a library error.
+    fn code_suffix(&self) -> String {
+        self.code.map(|code| format!(" [{}]", code.code)).unwrap_or_default()
+    }
+
+    // Full report with location, source line and pointer, headed by `label`.
+    fn write_report(&self, f: &mut fmt::Formatter<'_>, label: &str) -> fmt::Result {
         let location = format!("{} -> [Ln: {}, Col: {}]",
             self.context.filename, self.context.line, self.context.column);
         let line_num = format!("{:>4}", self.context.line);
+
+        let fix = self
+            .fix
+            .as_ref()
+            .map(|fix| format!(" {}", colorize(&i18n::message(fix), "\x1b[1;32m")))
+            .unwrap_or_default();
         write!(
             f,
-            "{}: {}\n     | {}\n     |\n{} | {}\n     | {}\n{}",
-            kind_str,
-            self.message,
+            "{}: {}\n     | {}\n     |\n{} | {}\n     | {}{}\n{}",
+            label,
+            i18n::message(&self.message),
             colorize(&location, "\x1b[1;37m"),
             colorize(&line_num, "\x1b[1;90m"),
             self.context.line_content,
             colorize(&self.context.pointer, "\x1b[1;31m"),
+            fix,
             self.extra.clone().unwrap_or_default()
-        )
+        )?;
+        if let Some(help) = &self.help {
+            writeln!(
+                f,
+                "{} {}",
+                colorize(&format!("  {}:", i18n::message("help")), "\x1b[1;36m"),
+                i18n::message(help)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ZekkenError {
+    #[cfg(target_arch = "wasm32")]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_full(f)
     }
 
     #[cfg(not(target_arch = "wasm32"))]
@@ -381,31 +579,54 @@ impl fmt::Display for ZekkenError {
         if *REPL_MODE.lock().unwrap() {
             write!(f, "{}", self.to_repl_string())
         } else {
-            let (kind, color) = match self.kind {
-                ErrorKind::Syntax => ("Syntax Error", "\x1b[1;31m"),
-                ErrorKind::Runtime => ("Runtime Error", "\x1b[1;35m"),
-                ErrorKind::Type => ("Type Error", "\x1b[1;33m"),
-                ErrorKind::Reference => ("Reference Error", "\x1b[1;34m"),
-                ErrorKind::Internal => ("Internal Error", "\x1b[1;41m"),
-            };
+            self.write_full(f)
+        }
+    }
+}
 
-            let kind_str = colorize(kind, color);
-            let location = format!("{} -> [Ln: {}, Col: {}]", 
-                self.context.filename, self.context.line, self.context.column);
-            let line_num = format!("{:>4}", self.context.line);
+/// Which kind of diagnostic a [`ZekkenWarning`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// A lint that the project's `[lints]` table downgraded to `warn`.
+    Warning,
+    /// Deprecated syntax that the edition in use still accepts.
+    Deprecation,
+}
 
-            write!(
-                f,
-                "{}: {}\n     | {}\n     |\n{} | {}\n     | {}\n{}",
-                kind_str,
-                self.message,
-                colorize(&location, "\x1b[1;37m"),
-                colorize(&line_num, "\x1b[1;90m"),
-                self.context.line_content,
-                colorize(&self.context.pointer, "\x1b[1;31m"),
-                self.extra.clone().unwrap_or_default()
-            )
+/// A diagnostic that is reported but does not stop the program from running.
+#[derive(Debug, Clone)]
+pub struct ZekkenWarning {
+    pub severity: Severity,
+    pub lint: &'static str,
+    pub error: ZekkenError,
+}
+
+impl fmt::Display for ZekkenWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (header, color) = match self.severity {
+            Severity::Warning => (format!("Warning [{}]", self.lint), "\x1b[1;33m"),
+            Severity::Deprecation => (format!("Deprecated [{}]", self.lint), "\x1b[1;35m"),
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        if *REPL_MODE.lock().unwrap() {
+            return write!(f, "{}: {}", i18n::message(&header), i18n::message(&self.error.message));
+        }
+        let label = colorize(&i18n::message(&header), color);
+        self.error.write_report(f, &label)
+    }
+}
+
+impl ZekkenWarning {
+    #[allow(dead_code)]
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = self.error.to_json();
+        json["severity"] = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Deprecation => "deprecation",
         }
+        .into();
+        json["lint"] = self.lint.into();
+        json
     }
 }
 
@@ -433,13 +654,60 @@ pub fn push_error(error: ZekkenError) {
     }
 }
 
+lazy_static::lazy_static! {
+    static ref WARNING_LIST: Mutex<Vec<ZekkenWarning>> = Mutex::new(Vec::new());
+}
+
+pub fn push_warning(lint: &'static str, error: ZekkenError) {
+    push_diagnostic(Severity::Warning, lint, error);
+}
+
+pub fn push_deprecation(name: &'static str, error: ZekkenError) {
+    push_diagnostic(Severity::Deprecation, name, error);
+}
+
+fn push_diagnostic(severity: Severity, lint: &'static str, error: ZekkenError) {
+    let mut warnings = WARNING_LIST.lock().unwrap();
+    let duplicate = warnings.iter().any(|w| {
+        w.severity == severity
+            && w.lint == lint
+            && w.error.context.filename == error.context.filename
+            && w.error.context.line == error.context.line
+            && w.error.context.column == error.context.column
+            && w.error.message == error.message
+    });
+    if !duplicate {
+        warnings.push(ZekkenWarning { severity, lint, error });
+    }
+}
+
+pub fn take_warnings() -> Vec<ZekkenWarning> {
+    let mut warnings = std::mem::take(&mut *WARNING_LIST.lock().unwrap());
+    warnings.sort_by(|a, b| {
+        a.error.context.filename.cmp(&b.error.context.filename)
+            .then(a.error.context.line.cmp(&b.error.context.line))
+            .then(a.error.context.column.cmp(&b.error.context.column))
+    });
+    warnings
+}
+
+// Print and clear all collected warnings, returns true if any were printed
+#[allow(dead_code)]
+pub fn print_and_clear_warnings() -> bool {
+    let warnings = take_warnings();
+    for warning in warnings.iter() {
+        crate::output::diagnostic(warning);
+    }
+    !warnings.is_empty()
+}
+
 #[inline]
 fn error_kind_priority(kind: &ErrorKind) -> u8 {
     match kind {
         ErrorKind::Syntax => 0,
         ErrorKind::Reference => 1,
         ErrorKind::Type => 2,
-        ErrorKind::Runtime => 3,
+        ErrorKind::Runtime | ErrorKind::Library => 3,
         ErrorKind::Internal => 4,
     }
 }
@@ -487,7 +755,7 @@ pub fn print_and_clear_errors() -> bool {
     let errors = take_collected_errors();
     if !errors.is_empty() {
         for error in errors.iter() {
-            eprintln!("{}", error);
+            crate::output::diagnostic(error);
         }
         true
     } else {
@@ -535,14 +803,15 @@ fn highlight_zekken_line(line: &str) -> String {
         // String escapes (inside strings)
         (r#"\\[abfnrtv0'"\\]"#, ESCAPE, true, false),
         // Keywords (control)
-        (r"\b(if|else|for|while|try|catch|return)\b", KEYWORD_CONTROL, false, false),
+        (r"\b(if|else|for|while|try|catch|return|match|defer|with|switch)\b", KEYWORD_CONTROL, false, false),
         // Keywords (other)
-        (r"\b(use|include|export|func|let|const|from|in)\b", KEYWORD_OTHER, false, false),
+        (r"\b(use|include|export|func|let|const|from|in|enum|as)\b", KEYWORD_OTHER, false, false),
         // Types
-        (r"\b(int|float|bool|string|arr|obj|fn)\b", TYPE, false, false),
+        (r"\b(int|float|dec|bool|string|arr|obj|fn|any)\b", TYPE, false, false),
         // Boolean
         (r"\b(true|false)\b", BOOL, false, false),
-        // Numbers (float and int)
+        // Numbers (decimal, float and int)
+        (r"\b\d+(\.\d+)?d\b", FLOAT, false, false),
         (r"\b\d+\.\d+\b", FLOAT, false, false),
         (r"\b\d+\b", INT, false, false),
         // Operators
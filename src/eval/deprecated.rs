@@ -0,0 +1,74 @@
+//! Checks for the entries of [`crate::config::DEPRECATIONS`].
+//!
+//! Each finding is reported under its deprecation's name: as a `Deprecated`
+//! warning while the edition in use still accepts it, as an error once the
+//! edition (or `[deprecations]`) says it is gone.
+
+use crate::ast::visit::{walk_expr, Visitor};
+use crate::ast::*;
+use crate::config::{self, LintLevel};
+use crate::environment::is_internal_key;
+use crate::errors::{push_deprecation, ZekkenError};
+
+#[derive(Default)]
+struct Deprecated {
+    found: Vec<(&'static str, ZekkenError)>,
+}
+
+impl Visitor for Deprecated {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Expr::Member(member) = expr {
+            let key = match member.property.as_ref() {
+                Expr::Identifier(ident) if !member.computed => Some(ident.name.as_str()),
+                Expr::StringLit(lit) if member.computed => Some(lit.value.as_str()),
+                _ => None,
+            };
+            if let Some(key) = key.filter(|key| is_internal_key(key)) {
+                self.found.push((
+                    "internal-keys",
+                    ZekkenError::runtime(
+                        &format!("Reading the internal field '{}'", key),
+                        member.location.line,
+                        member.location.column,
+                        None,
+                    ),
+                ));
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Every deprecated construct in `program`, with the deprecation it falls under.
+pub fn deprecations(program: &Program) -> Vec<(&'static str, ZekkenError)> {
+    let mut pass = Deprecated::default();
+    pass.visit_block(&program.content);
+    pass.found
+}
+
+/// Reports [`deprecations`] at the level the active edition and
+/// `[deprecations]` give each one.
+pub fn collect_deprecations(program: &Program, errors: &mut Vec<ZekkenError>) {
+    for (name, error) in deprecations(program) {
+        report_deprecation(name, error, errors);
+    }
+}
+
+pub fn report_deprecation(name: &'static str, error: ZekkenError, errors: &mut Vec<ZekkenError>) {
+    let Some(deprecation) = config::deprecation(name) else {
+        errors.push(error);
+        return;
+    };
+    match config::deprecation_level(deprecation) {
+        LintLevel::Allow => {}
+        LintLevel::Warn => push_deprecation(
+            name,
+            error.with_help(format!(
+                "{}; this is an error from edition {}",
+                deprecation.help,
+                deprecation.error_from.name()
+            )),
+        ),
+        LintLevel::Deny => errors.push(error.with_help(deprecation.help)),
+    }
+}
@@ -0,0 +1,40 @@
+//! Evaluating a source expression against a scope without changing it.
+//!
+//! `zekken debug step` uses this for `eval <expr>` while paused, and under
+//! `--env-eval` every caught error gets an `env_eval` method that evaluates
+//! against a snapshot of the scope it was caught in. Scopes are copied on
+//! write, so anything the expression assigns lands in a copy.
+
+use crate::ast::Content;
+use crate::config;
+use crate::environment::{Environment, Value};
+use crate::eval::expression::evaluate_expression;
+use crate::parser::Parser;
+use std::sync::Arc;
+
+/// Evaluates the single expression in `source` against a copy of `scope`.
+pub fn eval_frozen(source: &str, scope: &Environment) -> Result<Value, String> {
+    let mut parser = Parser::new();
+    let program = parser.produce_ast(source.to_string());
+    if let Some(error) = parser.errors.first() {
+        return Err(error.message.clone());
+    }
+    let expr = match (program.imports.as_slice(), program.content.as_slice()) {
+        ([], [Content::Expression(expr)]) => expr,
+        _ => return Err("env_eval takes a single expression".to_string()),
+    };
+    let mut sandbox = Environment::new_with_parent(scope.clone());
+    evaluate_expression(expr, &mut sandbox).map_err(|error| error.message)
+}
+
+/// The `env_eval` method of a caught error, when `--env-eval` is on.
+pub fn catch_method(scope: &Environment) -> Option<Value> {
+    if !config::env_eval() {
+        return None;
+    }
+    let snapshot = scope.snapshot();
+    Some(Value::NativeFunction(Arc::new(move |args| match args.as_slice() {
+        [Value::String(source)] => eval_frozen(source, &snapshot.to_scope()),
+        _ => Err("env_eval expects the expression as a string".to_string()),
+    })))
+}
@@ -1,11 +1,13 @@
 use crate::ast::*;
 use crate::bytecode;
-use crate::environment::{Environment, FunctionValue, Value};
+use crate::libraries::{arrays, bigfloat, calc, common::{call_native, with_native_code}, linalg, units};
+use crate::environment::{sort, decimal_binary, elements_match, ensure_array_slot, int_binary, int_range, matrix_from, numeric_binary, repeat_string, string_char_at, vector_from, vector_index, CallArgs, Environment, FunctionValue, Value};
 use crate::lexer::DataType;
-use hashbrown::HashMap;
+use indexmap::IndexMap;
 use std::sync::Arc;
 use crate::errors::{ZekkenError};
-use crate::parser::Parser;
+use super::pattern::match_pattern;
+use super::statement::{evaluate_block_content, lambda_value};
 
 fn check_value_type(value: &Value, expected: &DataType) -> bool {
     match (value, expected) {
@@ -14,8 +16,12 @@ fn check_value_type(value: &Value, expected: &DataType) -> bool {
         (Value::Float(_), DataType::Float) => true,
         (Value::String(_), DataType::String) => true,
         (Value::Boolean(_), DataType::Bool) => true,
-        (Value::Array(_), DataType::Array) => true,
-        (Value::Object(_), DataType::Object) => true,
+        (Value::Array(_) | Value::Vector(_) | Value::Matrix(_), DataType::Array) => true,
+        (_, DataType::ArrayOf(element)) => elements_match(value, element),
+        (Value::Null, DataType::Nullable(_)) => true,
+        (_, DataType::Nullable(inner)) => check_value_type(value, inner),
+        (Value::Object(_) | Value::Library(_) | Value::Enum { .. } | Value::Quantity(_) | Value::BigFloat(_) | Value::Expression(_), DataType::Object) => true,
+        (Value::Decimal(_), DataType::Decimal) => true,
         (Value::Function(_), DataType::Fn) => true,
         (Value::NativeFunction(_), DataType::Fn) => true,
         _ => false,
@@ -29,8 +35,10 @@ fn value_type_name(val: &Value) -> &'static str {
         Value::String(_) => "string",
         Value::Boolean(_) => "bool",
         Value::Array(_) => "arr",
-        Value::Object(_) => "obj",
+        Value::Object(_) | Value::Library(_) | Value::Enum { .. } => "obj",
+        Value::Decimal(_) => "dec",
         Value::Function(_) | Value::NativeFunction(_) => "fn",
+        Value::Null => "null",
         _ => "other",
     }
 }
@@ -39,14 +47,11 @@ pub fn evaluate_expression(expr: &Expr, env: &mut Environment) -> Result<Value,
     match expr {
         Expr::IntLit(int) => Ok(Value::Int(int.value)),
         Expr::FloatLit(float) => Ok(Value::Float(float.value)),
-        Expr::StringLit(string) => {
-            if string.value.as_bytes().contains(&b'{') {
-                Ok(Value::String(interpolate_string_expressions(&string.value, env)))
-            } else {
-                Ok(Value::String(string.value.clone()))
-            }
-        },
+        Expr::DecimalLit(decimal) => Ok(Value::from(decimal)),
+        Expr::StringLit(string) => Ok(Value::String(string.value.clone())),
+        Expr::TemplateLit(template) => Ok(Value::String(evaluate_template(template, env))),
         Expr::BoolLit(bool) => Ok(Value::Boolean(bool.value)),
+        Expr::NullLit(_) => Ok(Value::Null),
         Expr::ArrayLit(array) => {
             let mut values = Vec::with_capacity(array.elements.len());
             for element in &array.elements {
@@ -54,11 +59,42 @@ pub fn evaluate_expression(expr: &Expr, env: &mut Environment) -> Result<Value,
             }
             Ok(Value::Array(values))
         },
+        Expr::VectorLit(vector) => {
+            let mut values = Vec::with_capacity(vector.elements.len());
+            for element in &vector.elements {
+                values.push(evaluate_expression(element, env)?);
+            }
+            vector_from(&values).map_err(|e| ZekkenError::runtime(&e, vector.location.line, vector.location.column, None))
+        },
+        Expr::MatrixLit(matrix) => {
+            let mut rows = Vec::with_capacity(matrix.rows.len());
+            for row in &matrix.rows {
+                let mut values = Vec::with_capacity(row.len());
+                for element in row {
+                    values.push(evaluate_expression(element, env)?);
+                }
+                rows.push(values);
+            }
+            matrix_from(&rows).map_err(|e| ZekkenError::runtime(&e, matrix.location.line, matrix.location.column, None))
+        },
         Expr::ObjectLit(object) => {
-            let mut map = HashMap::with_capacity(object.properties.len());
+            let mut map = IndexMap::with_capacity(object.properties.len());
             for prop in &object.properties {
+                let key = match &prop.computed_key {
+                    Some(key_expr) => {
+                        let key_value = evaluate_expression(key_expr, env)?;
+                        key_value.as_object_key().ok_or_else(|| ZekkenError::type_error(
+                            "Computed object key must be a string or int",
+                            "string or int",
+                            value_type_name(&key_value),
+                            prop.location.line,
+                            prop.location.column,
+                        ).with_value(&key_value))?
+                    }
+                    None => prop.key.clone(),
+                };
                 let value = evaluate_expression(&prop.value, env)?;
-                map.insert(prop.key.clone(), value);
+                map.insert(key, value);
             }
             Ok(Value::Object(map))
         },
@@ -89,9 +125,70 @@ pub fn evaluate_expression(expr: &Expr, env: &mut Environment) -> Result<Value,
         },
         Expr::Unary(unary) => evaluate_unary_expression(unary, env),
         Expr::Binary(binary) => evaluate_binary_expression(binary, env),
-        Expr::Call(call) => evaluate_call_expression(call, env),
+        Expr::Call(call) => {
+            let result = evaluate_call_expression(call, env);
+            env.reload_shared();
+            result
+        }
+        Expr::MethodCall(call) => {
+            let result = evaluate_method_call(call, env);
+            env.reload_shared();
+            result
+        }
         Expr::Member(member) => evaluate_member_expression(member, env),
         Expr::Assign(assign) => evaluate_assignment(assign, env),
+        Expr::Lambda(lambda) => Ok(Value::Function(lambda_value("<lambda>", &lambda.params, &lambda.body, None, env))),
+        Expr::Match(match_expr) => {
+            // Arms are tried in order; when none matches the match evaluates to void.
+            let subject = evaluate_expression(&match_expr.subject, env)?;
+            // Names bound by a pattern live in the enclosing scope, like loop variables.
+            let mut bindings = Vec::new();
+            for arm in &match_expr.arms {
+                let mut failure = None;
+                let matched = match_pattern(
+                    &arm.pattern,
+                    &subject,
+                    &mut |value, candidate| match evaluate_expression(value, env) {
+                        Ok(value) => compare_values(candidate, &value),
+                        Err(error) => {
+                            failure.get_or_insert(error);
+                            false
+                        }
+                    },
+                    &mut bindings,
+                );
+                if let Some(error) = failure {
+                    return Err(error);
+                }
+                if !matched {
+                    continue;
+                }
+                for (name, value) in bindings.drain(..) {
+                    if let Some(slot) = env.variables.get_mut(&name) {
+                        *slot = value;
+                    } else {
+                        env.declare_ref(&name, value, false);
+                    }
+                }
+                if let Some(guard) = &arm.guard {
+                    match evaluate_expression(guard, env)? {
+                        Value::Boolean(true) => {}
+                        Value::Boolean(false) => continue,
+                        other => {
+                            return Err(ZekkenError::type_error(
+                                "Match guard must evaluate to a boolean",
+                                "bool",
+                                value_type_name(&other),
+                                arm.location.line,
+                                arm.location.column,
+                            ).with_value(&other))
+                        }
+                    }
+                }
+                return Ok(evaluate_block_content(&arm.body, env)?.unwrap_or(Value::Void));
+            }
+            Ok(Value::Void)
+        }
         Expr::Property(_) => Err(ZekkenError::internal(
             "Property expression not supported in this context",
         ))
@@ -105,76 +202,33 @@ fn evaluate_unary_expression(expr: &UnaryExpr, env: &mut Environment) -> Result<
             Value::Boolean(value) => Ok(Value::Boolean(!value)),
             other => Err(ZekkenError::type_error(
                 "Invalid logical NOT operation",
-                "boolean",
+                "bool",
                 value_type_name(&other),
                 expr.location.line,
                 expr.location.column,
-            )),
+            ).with_value(&other)),
         },
         _ => Err(ZekkenError::internal("Unsupported unary operator")),
     }
 }
 
-fn interpolate_string_expressions(template: &str, env: &mut Environment) -> String {
-    let mut out = String::with_capacity(template.len());
-    let bytes = template.as_bytes();
-    let mut i = 0usize;
-    let mut segment_start = 0usize;
-
-    while i < bytes.len() {
-        if bytes[i] != b'{' {
-            i += 1;
-            continue;
-        }
-
-        let mut j = i + 1;
-        while j < bytes.len() && bytes[j] != b'}' {
-            j += 1;
-        }
-        if j >= bytes.len() {
-            break;
-        }
-
-        let raw_inner = &template[i + 1..j];
-        let inner = raw_inner.trim();
-        out.push_str(&template[segment_start..i]);
-
-        if inner.is_empty() {
-            // Keep positional placeholder for println-style formatting.
-            out.push_str("{}");
-        } else {
-            let mut parser = Parser::new();
-            let program = parser.produce_ast(inner.to_string());
-            let expr = program.content.first().and_then(|c| match c.as_ref() {
-                Content::Expression(e) => Some(e.as_ref().clone()),
-                _ => None,
-            });
-
-            if !parser.errors.is_empty() {
-                out.push('{');
-                out.push_str(raw_inner);
-                out.push('}');
-            } else if let Some(expr) = expr {
-                match evaluate_expression(&expr, env) {
-                    Ok(value) => out.push_str(&value.to_string()),
-                    Err(_) => {
-                        out.push('{');
-                        out.push_str(raw_inner);
-                        out.push('}');
-                    }
+fn evaluate_template(template: &TemplateLit, env: &mut Environment) -> String {
+    let mut out = String::new();
+    for part in &template.parts {
+        match part {
+            TemplatePart::Text(text) => out.push_str(text),
+            TemplatePart::Expr { expr, raw } => match evaluate_expression(expr, env) {
+                Ok(value) => out.push_str(&value.to_string()),
+                // Kept as written, so `{name}` placeholders meant for a later
+                // formatting step survive.
+                Err(_) => {
+                    out.push('{');
+                    out.push_str(raw);
+                    out.push('}');
                 }
-            } else {
-                out.push('{');
-                out.push_str(raw_inner);
-                out.push('}');
-            }
+            },
         }
-
-        i = j + 1;
-        segment_start = i;
     }
-
-    out.push_str(&template[segment_start..]);
     out
 }
 
@@ -233,13 +287,32 @@ fn evaluate_binary_expression(expr: &BinaryExpr, env: &mut Environment) -> Resul
 
     let left = evaluate_expression(&expr.left, env)?;
     let right = evaluate_expression(&expr.right, env)?;
-    
-    match expr.operator.as_str() {
-        "in" => match (&left, &right) {
+    binary_values(&expr.operator, &left, &right, &expr.location)
+}
+
+/// `left op right` for every operator but `&&` and `||`, which short-circuit.
+/// Compound assignments come here too, so `s += x` means `s = s + x`.
+fn binary_values(operator: &str, left: &Value, right: &Value, location: &Location) -> Result<Value, ZekkenError> {
+    if let Some(value) = numeric_binary(operator, left, right) {
+        return Ok(value);
+    }
+    if let Some(result) = decimal_binary(operator, left, right).or_else(|| units::binary(operator, left, right))
+        .or_else(|| bigfloat::binary(operator, left, right))
+        .or_else(|| calc::binary(operator, left, right))
+        .or_else(|| linalg::binary(operator, left, right)) {
+        return result.map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None));
+    }
+
+    match operator {
+        ".." => int_range(left, right)
+            .map(|range| Value::Array(range.map(Value::Int).collect()))
+            .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
+        "in" => match (left, right) {
             (_, Value::Array(arr)) => Ok(Value::Boolean(
-                arr.iter().any(|v| compare_values(&left, v)),
+                arr.iter().any(|v| compare_values(left, v)),
             )),
             (Value::String(key), Value::Object(obj)) => Ok(Value::Boolean(obj.contains_key(key))),
+            (Value::String(key), Value::Library(lib) | Value::Enum { payload: lib, .. }) => Ok(Value::Boolean(lib.contains_key(key))),
             (Value::String(needle), Value::String(haystack)) => {
                 Ok(Value::Boolean(haystack.contains(needle)))
             }
@@ -247,12 +320,13 @@ fn evaluate_binary_expression(expr: &BinaryExpr, env: &mut Environment) -> Resul
                 "Invalid 'in' operation",
                 "value in array, string in object, or string in string",
                 "incompatible operands",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
             )),
         },
-        "+" => match (&left, &right) {
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l + r)),
+        "+" => match (left, right) {
+            (Value::Int(l), Value::Int(r)) => int_binary("+", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l + r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 + r)),
             (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l + *r as f64)),
@@ -269,12 +343,13 @@ fn evaluate_binary_expression(expr: &BinaryExpr, env: &mut Environment) -> Resul
                 "Invalid operand types for addition",
                 "valid types",
                 "invalid types",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
             )),
         },
-        "-" => match (&left, &right) {
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l - r)),
+        "-" => match (left, right) {
+            (Value::Int(l), Value::Int(r)) => int_binary("-", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l - r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 - r)),
             (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l - *r as f64)),
@@ -282,78 +357,84 @@ fn evaluate_binary_expression(expr: &BinaryExpr, env: &mut Environment) -> Resul
                 "Invalid operand types for subtraction",
                 "valid types",
                 "invalid types",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
             )),
         },
-        "*" => match (&left, &right) {
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l * r)),
+        "*" => match (left, right) {
+            (Value::Int(l), Value::Int(r)) => int_binary("*", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l * r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 * r)),
             (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l * *r as f64)),
+            (Value::String(text), Value::Int(count)) | (Value::Int(count), Value::String(text)) => {
+                repeat_string(text, *count).map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None))
+            }
             _ => Err(ZekkenError::type_error(
                 "Invalid operand types for multiplication",
                 "valid types",
                 "invalid types",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
             )),
         },
-        "/" => match (&left, &right) {
+        "/" => match (left, right) {
             (Value::Int(_), Value::Int(r)) if *r == 0 => Err(ZekkenError::runtime(
                 "Division by zero",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
                 Some("division by zero"),
             )),
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l / r)),
+            (Value::Int(l), Value::Int(r)) => int_binary("/", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             (Value::Float(_), Value::Float(r)) if *r == 0.0 => Err(ZekkenError::runtime(
                 "Division by zero",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
                 Some("division by zero"),
             )),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l / r)),
             (Value::Int(_), Value::Float(r)) if *r == 0.0 => Err(ZekkenError::runtime(
                 "Division by zero",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
                 Some("division by zero"),
             )),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 / r)),
             (Value::Float(_), Value::Int(r)) if *r == 0 => Err(ZekkenError::runtime(
                 "Division by zero",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
                 Some("division by zero"),
             )),
             (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l / *r as f64)),
             _ => Err(ZekkenError::runtime(
                 "Invalid operand types for division",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
                 None,
             )),
         },
-        "%" => match (&left, &right) {
+        "%" => match (left, right) {
             (Value::Int(_), Value::Int(r)) if *r == 0 => Err(ZekkenError::runtime(
                 "Modulo by zero",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
                 None,
             )),
-            (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l % r)),
+            (Value::Int(l), Value::Int(r)) => int_binary("%", *l, *r)
+                .map_err(|e| ZekkenError::runtime(&e, location.line, location.column, None)),
             _ => Err(ZekkenError::type_error(
                 "Invalid operand types for modulo",
                 "valid types",
                 "invalid types",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
             )),
         },
-        "==" => Ok(Value::Boolean(compare_values(&left, &right))),
-        "!=" => Ok(Value::Boolean(!compare_values(&left, &right))),
-        "<" => match (&left, &right) {
+        "==" => Ok(Value::Boolean(compare_values(left, right))),
+        "!=" => Ok(Value::Boolean(!compare_values(left, right))),
+        "<" => match (left, right) {
             (Value::Int(l), Value::Int(r)) => Ok(Value::Boolean(l < r)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Boolean(l < r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Boolean((*l as f64) < *r)),
@@ -362,11 +443,11 @@ fn evaluate_binary_expression(expr: &BinaryExpr, env: &mut Environment) -> Resul
                 "Invalid comparison",
                 "valid types",
                 "invalid types",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
             )),
         },
-        ">" => match (&left, &right) {
+        ">" => match (left, right) {
             (Value::Int(l), Value::Int(r)) => Ok(Value::Boolean(l > r)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Boolean(l > r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Boolean((*l as f64) > *r)),
@@ -375,11 +456,11 @@ fn evaluate_binary_expression(expr: &BinaryExpr, env: &mut Environment) -> Resul
                 "Invalid comparison",
                 "valid types",
                 "invalid types",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
             )),
         },
-        "<=" => match (&left, &right) {
+        "<=" => match (left, right) {
             (Value::Int(l), Value::Int(r)) => Ok(Value::Boolean(l <= r)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Boolean(l <= r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Boolean((*l as f64) <= *r)),
@@ -388,11 +469,11 @@ fn evaluate_binary_expression(expr: &BinaryExpr, env: &mut Environment) -> Resul
                 "Invalid comparison",
                 "valid types",
                 "invalid types",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
             )),
         },
-        ">=" => match (&left, &right) {
+        ">=" => match (left, right) {
             (Value::Int(l), Value::Int(r)) => Ok(Value::Boolean(l >= r)),
             (Value::Float(l), Value::Float(r)) => Ok(Value::Boolean(l >= r)),
             (Value::Int(l), Value::Float(r)) => Ok(Value::Boolean((*l as f64) >= *r)),
@@ -401,14 +482,14 @@ fn evaluate_binary_expression(expr: &BinaryExpr, env: &mut Environment) -> Resul
                 "Invalid comparison",
                 "valid types",
                 "invalid types",
-                expr.location.line,
-                expr.location.column,
+                location.line,
+                location.column,
             )),
         },
         operator => Err(ZekkenError::runtime(
             &format!("Unknown operator: {}", operator), 
-            expr.location.line, 
-            expr.location.column,
+            location.line, 
+            location.column,
             None
         ))
     }
@@ -453,20 +534,27 @@ fn try_eval_num_expr(expr: &Expr, env: &Environment) -> Option<NumValue> {
             let r = try_eval_num_expr(&b.right, env)?;
             match b.operator.as_str() {
                 "+" => Some(match (l, r) {
-                    (NumValue::Int(li), NumValue::Int(ri)) => NumValue::Int(li + ri),
+                    (NumValue::Int(li), NumValue::Int(ri)) => NumValue::Int(li.checked_add(ri)?),
                     _ => NumValue::Float(l.as_f64() + r.as_f64()),
                 }),
                 "-" => Some(match (l, r) {
-                    (NumValue::Int(li), NumValue::Int(ri)) => NumValue::Int(li - ri),
+                    (NumValue::Int(li), NumValue::Int(ri)) => NumValue::Int(li.checked_sub(ri)?),
                     _ => NumValue::Float(l.as_f64() - r.as_f64()),
                 }),
                 "*" => Some(match (l, r) {
-                    (NumValue::Int(li), NumValue::Int(ri)) => NumValue::Int(li * ri),
+                    (NumValue::Int(li), NumValue::Int(ri)) => NumValue::Int(li.checked_mul(ri)?),
                     _ => NumValue::Float(l.as_f64() * r.as_f64()),
                 }),
-                "/" => Some(NumValue::Float(l.as_f64() / r.as_f64())),
+                // Zero divisors and int overflow fall back to the general path,
+                // which reports them.
+                "/" => match (l, r) {
+                    (_, NumValue::Int(0)) => None,
+                    (_, NumValue::Float(0.0)) => None,
+                    (NumValue::Int(li), NumValue::Int(ri)) => li.checked_div(ri).map(NumValue::Int),
+                    _ => Some(NumValue::Float(l.as_f64() / r.as_f64())),
+                },
                 "%" => match (l, r) {
-                    (NumValue::Int(li), NumValue::Int(ri)) => Some(NumValue::Int(li % ri)),
+                    (NumValue::Int(li), NumValue::Int(ri)) => li.checked_rem(ri).map(NumValue::Int),
                     _ => None,
                 },
                 _ => None,
@@ -486,19 +574,20 @@ fn try_eval_numeric_binary(expr: &BinaryExpr, env: &Environment) -> Result<Optio
         None => return Ok(None),
     };
 
+    // Int overflow gives `None`, so the general path reports it.
     let out = match expr.operator.as_str() {
-        "+" => Some(match (l, r) {
-            (NumValue::Int(li), NumValue::Int(ri)) => Value::Int(li + ri),
-            _ => Value::Float(l.as_f64() + r.as_f64()),
-        }),
-        "-" => Some(match (l, r) {
-            (NumValue::Int(li), NumValue::Int(ri)) => Value::Int(li - ri),
-            _ => Value::Float(l.as_f64() - r.as_f64()),
-        }),
-        "*" => Some(match (l, r) {
-            (NumValue::Int(li), NumValue::Int(ri)) => Value::Int(li * ri),
-            _ => Value::Float(l.as_f64() * r.as_f64()),
-        }),
+        "+" => match (l, r) {
+            (NumValue::Int(li), NumValue::Int(ri)) => li.checked_add(ri).map(Value::Int),
+            _ => Some(Value::Float(l.as_f64() + r.as_f64())),
+        },
+        "-" => match (l, r) {
+            (NumValue::Int(li), NumValue::Int(ri)) => li.checked_sub(ri).map(Value::Int),
+            _ => Some(Value::Float(l.as_f64() - r.as_f64())),
+        },
+        "*" => match (l, r) {
+            (NumValue::Int(li), NumValue::Int(ri)) => li.checked_mul(ri).map(Value::Int),
+            _ => Some(Value::Float(l.as_f64() * r.as_f64())),
+        },
         "/" => {
             if r.as_f64() == 0.0 {
                 return Err(ZekkenError::runtime(
@@ -509,7 +598,7 @@ fn try_eval_numeric_binary(expr: &BinaryExpr, env: &Environment) -> Result<Optio
                 ));
             }
             match (l, r) {
-                (NumValue::Int(li), NumValue::Int(ri)) => Some(Value::Int(li / ri)),
+                (NumValue::Int(li), NumValue::Int(ri)) => li.checked_div(ri).map(Value::Int),
                 _ => Some(Value::Float(l.as_f64() / r.as_f64())),
             }
         }
@@ -522,7 +611,7 @@ fn try_eval_numeric_binary(expr: &BinaryExpr, env: &Environment) -> Result<Optio
                     None,
                 ));
             }
-            (NumValue::Int(li), NumValue::Int(ri)) => Some(Value::Int(li % ri)),
+            (NumValue::Int(li), NumValue::Int(ri)) => li.checked_rem(ri).map(Value::Int),
             _ => None,
         },
         "<" => Some(Value::Boolean(l.as_f64() < r.as_f64())),
@@ -537,312 +626,344 @@ fn try_eval_numeric_binary(expr: &BinaryExpr, env: &Environment) -> Result<Optio
     Ok(out)
 }
 
-/*
-fn interpolate_string(template: &str, env: &Environment) -> String {
-    let re = Regex::new(r"\{(\w+)\}").unwrap();
-    re.replace_all(template, |caps: &regex::Captures| {
-        let var_name = &caps[1];
-        match env.lookup(var_name) {
-            Some(value) => format!("{}", value),
-            None => format!("{{{}}}", var_name)
+#[inline]
+fn eval_arg_hot(expr: &Expr, env: &mut Environment) -> Result<Value, ZekkenError> {
+    match expr {
+        Expr::IntLit(i) => Ok(Value::Int(i.value)),
+        Expr::FloatLit(f) => Ok(Value::Float(f.value)),
+        Expr::BoolLit(b) => Ok(Value::Boolean(b.value)),
+        Expr::NullLit(_) => Ok(Value::Null),
+        Expr::Identifier(id) => {
+            if let Some(v) = env.variables.get(&id.name).or_else(|| env.constants.get(&id.name)) {
+                return Ok(match v {
+                    Value::Int(i) => Value::Int(*i),
+                    Value::Float(f) => Value::Float(*f),
+                    Value::Boolean(b) => Value::Boolean(*b),
+                    _ => v.clone(),
+                });
+            }
+            evaluate_expression(expr, env)
         }
-    }).to_string()
+        _ => evaluate_expression(expr, env),
+    }
 }
-*/
 
-fn evaluate_call_expression(call: &CallExpr, env: &mut Environment) -> Result<Value, ZekkenError> {
-    #[inline]
-    fn builtin_requires_at(name: &str) -> bool {
-        matches!(name, "println" | "input" | "parse_json" | "queue")
+#[inline]
+fn eval_call_args(args: &[Box<Expr>], env: &mut Environment) -> Result<CallArgs, ZekkenError> {
+    let mut out = CallArgs::with_capacity(args.len());
+    for arg in args {
+        out.push(eval_arg_hot(arg, env)?);
     }
+    Ok(out)
+}
 
-    #[inline]
-    fn eval_arg_hot(expr: &Expr, env: &mut Environment) -> Result<Value, ZekkenError> {
-        match expr {
-            Expr::IntLit(i) => Ok(Value::Int(i.value)),
-            Expr::FloatLit(f) => Ok(Value::Float(f.value)),
-            Expr::BoolLit(b) => Ok(Value::Boolean(b.value)),
-            Expr::Identifier(id) => {
-                if let Some(v) = env.variables.get(&id.name).or_else(|| env.constants.get(&id.name)) {
-                    return Ok(match v {
-                        Value::Int(i) => Value::Int(*i),
-                        Value::Float(f) => Value::Float(*f),
-                        Value::Boolean(b) => Value::Boolean(*b),
-                        _ => v.clone(),
-                    });
-                }
-                evaluate_expression(expr, env)
+fn evaluate_method_call(call: &MethodCallExpr, env: &mut Environment) -> Result<Value, ZekkenError> {
+    let method_name = call.method.as_str();
+    if let Expr::Identifier(ref object_ident) = *call.object {
+        if object_ident.name == "math" {
+            if let Some(result) =
+                try_eval_math_call(method_name, &call.args, env, call.location.line, call.location.column)
+            {
+                return result;
             }
-            _ => evaluate_expression(expr, env),
         }
-    }
 
-    #[inline]
-    fn eval_call_args(args: &[Box<Expr>], env: &mut Environment) -> Result<Vec<Value>, ZekkenError> {
-        match args.len() {
-            0 => Ok(Vec::new()),
-            1 => Ok(vec![eval_arg_hot(&args[0], env)?]),
-            2 => {
-                let mut out = Vec::with_capacity(2);
-                out.push(eval_arg_hot(&args[0], env)?);
-                out.push(eval_arg_hot(&args[1], env)?);
-                Ok(out)
+        let lib_member_native = if let Some(obj) = env.lookup_ref(&object_ident.name).and_then(Value::as_object) {
+            if let Some(Value::NativeFunction(native)) = obj.get(&call.method) {
+                Some(native.clone())
+            } else {
+                None
             }
-            3 => {
-                let mut out = Vec::with_capacity(3);
-                out.push(eval_arg_hot(&args[0], env)?);
-                out.push(eval_arg_hot(&args[1], env)?);
-                out.push(eval_arg_hot(&args[2], env)?);
-                Ok(out)
+        } else {
+            None
+        };
+        if let Some(native) = lib_member_native {
+            let args = eval_call_args(&call.args, env)?;
+            if let Some(keyed) = arrays::keyed(method_name).filter(|_| object_ident.name == "arrays") {
+                return evaluate_keyed(keyed, &args, env, call.location.line, call.location.column);
             }
-            _ => {
-                let mut out = Vec::with_capacity(args.len());
-                for arg in args {
-                    out.push(eval_arg_hot(arg, env)?);
+            return match call_native(&*native, args.into_vec(), call.location.line, call.location.column) {
+                Ok(val) => Ok(val),
+                Err(s) => {
+                    let (line, column, span_len) = call
+                        .args
+                        .first()
+                        .map(|arg| {
+                            let loc = expr_location(arg);
+                            (loc.line, loc.column, expr_span_len(arg))
+                        })
+                        .unwrap_or((call.location.line, call.location.column, 1));
+                    Err(with_native_code(ZekkenError::runtime_with_span(&s, line, column, span_len, None)))
                 }
-                Ok(out)
-            }
+            };
         }
-    }
 
-    // First check for member expressions (method calls)
-    if let Expr::Member(ref member_expr) = *call.callee {
-        if let Expr::Identifier(ref object_ident) = *member_expr.object {
-            if let Expr::Identifier(ref method_ident) = *member_expr.property {
-                if object_ident.name == "math" {
-                    if let Some(result) =
-                        try_eval_math_call(method_ident.name.as_str(), &call.args, env, call.location.line, call.location.column)
-                    {
-                        return result;
+        if let Some(method @ (Method::Push | Method::Pop | Method::Shift | Method::Unshift | Method::Length | Method::First | Method::Last)) = call.method_id {
+            let insert_arg = match method {
+                Method::Push | Method::Unshift => {
+                    if call.args.len() != 1 {
+                        return Err(ZekkenError::runtime(
+                            if method == Method::Push {
+                                "push requires exactly one argument"
+                            } else {
+                                "unshift requires exactly one argument"
+                            },
+                            call.location.line,
+                            call.location.column,
+                            None,
+                        ));
                     }
+                    Some(evaluate_expression(&call.args[0], env)?)
                 }
-
-                let lib_member_native = if let Some(Value::Object(obj)) = env.lookup_ref(&object_ident.name) {
-                    if let Some(Value::NativeFunction(native)) = obj.get(&method_ident.name) {
-                        Some(native.clone())
-                    } else {
-                        None
+                _ => None,
+            };
+            if let Ok(Value::Array(arr)) = env.lookup_mut_assignable(&object_ident.name) {
+                match method {
+                    Method::Push => {
+                        let v = insert_arg.expect("push arg pre-evaluated");
+                        arr.push(v);
+                        return Ok(Value::Array(arr.clone()));
                     }
-                } else {
-                    None
-                };
-                if let Some(native) = lib_member_native {
-                    let args = eval_call_args(&call.args, env)?;
-                    return match (native)(args) {
-                        Ok(val) => Ok(val),
-                        Err(s) => {
-                            let (line, column, span_len) = call
-                                .args
-                                .first()
-                                .map(|arg| {
-                                    let loc = expr_location(arg);
-                                    (loc.line, loc.column, expr_span_len(arg))
-                                })
-                                .unwrap_or((call.location.line, call.location.column, 1));
-                            Err(ZekkenError::runtime_with_span(&s, line, column, span_len, None))
+                    Method::Pop => {
+                        if !call.args.is_empty() {
+                            return Err(ZekkenError::runtime(
+                                "pop requires no arguments",
+                                call.location.line,
+                                call.location.column,
+                                None,
+                            ));
                         }
-                    };
-                }
-
-                match method_ident.name.as_str() {
-                    "push" | "pop" | "shift" | "unshift" | "length" | "first" | "last" => {
-                        let method = method_ident.name.as_str();
-                        let insert_arg = match method {
-                            "push" | "unshift" => {
-                                if call.args.len() != 1 {
-                                    return Err(ZekkenError::runtime(
-                                        if method == "push" {
-                                            "push requires exactly one argument"
-                                        } else {
-                                            "unshift requires exactly one argument"
-                                        },
-                                        call.location.line,
-                                        call.location.column,
-                                        None,
-                                    ));
-                                }
-                                Some(evaluate_expression(&call.args[0], env)?)
-                            }
-                            _ => None,
-                        };
-                        if let Ok(slot) = env.lookup_mut_assignable(&object_ident.name) {
-                            if let Value::Array(arr) = slot {
-                                match method {
-                                    "push" => {
-                                        let v = insert_arg.expect("push arg pre-evaluated");
-                                        arr.push(v);
-                                        return Ok(Value::Array(arr.clone()));
-                                    }
-                                    "pop" => {
-                                        if !call.args.is_empty() {
-                                            return Err(ZekkenError::runtime(
-                                                "pop requires no arguments",
-                                                call.location.line,
-                                                call.location.column,
-                                                None,
-                                            ));
-                                        }
-                                        return arr.pop().ok_or_else(|| {
-                                            ZekkenError::runtime(
-                                                "Array is empty",
-                                                call.location.line,
-                                                call.location.column,
-                                                None,
-                                            )
-                                        });
-                                    }
-                                    "shift" => {
-                                        if !call.args.is_empty() {
-                                            return Err(ZekkenError::runtime(
-                                                "shift requires no arguments",
-                                                call.location.line,
-                                                call.location.column,
-                                                None,
-                                            ));
-                                        }
-                                        if arr.is_empty() {
-                                            return Err(ZekkenError::runtime(
-                                                "Array is empty",
-                                                call.location.line,
-                                                call.location.column,
-                                                None,
-                                            ));
-                                        }
-                                        return Ok(arr.remove(0));
-                                    }
-                                    "unshift" => {
-                                        let v = insert_arg.expect("unshift arg pre-evaluated");
-                                        arr.insert(0, v);
-                                        return Ok(Value::Array(arr.clone()));
-                                    }
-                                    "length" => {
-                                        if !call.args.is_empty() {
-                                            return Err(ZekkenError::runtime(
-                                                "length requires no arguments",
-                                                call.location.line,
-                                                call.location.column,
-                                                None,
-                                            ));
-                                        }
-                                        return Ok(Value::Int(arr.len() as i64));
-                                    }
-                                    "first" => {
-                                        if !call.args.is_empty() {
-                                            return Err(ZekkenError::runtime(
-                                                "first requires no arguments",
-                                                call.location.line,
-                                                call.location.column,
-                                                None,
-                                            ));
-                                        }
-                                        return arr.first().cloned().ok_or_else(|| {
-                                            ZekkenError::runtime(
-                                                "Array is empty",
-                                                call.location.line,
-                                                call.location.column,
-                                                None,
-                                            )
-                                        });
-                                    }
-                                    "last" => {
-                                        if !call.args.is_empty() {
-                                            return Err(ZekkenError::runtime(
-                                                "last requires no arguments",
-                                                call.location.line,
-                                                call.location.column,
-                                                None,
-                                            ));
-                                        }
-                                        return arr.last().cloned().ok_or_else(|| {
-                                            ZekkenError::runtime(
-                                                "Array is empty",
-                                                call.location.line,
-                                                call.location.column,
-                                                None,
-                                            )
-                                        });
-                                    }
-                                    _ => {}
-                                }
-                            }
+                        return arr.pop().ok_or_else(|| {
+                            ZekkenError::runtime(
+                                "Array is empty",
+                                call.location.line,
+                                call.location.column,
+                                None,
+                            )
+                        });
+                    }
+                    Method::Shift => {
+                        if !call.args.is_empty() {
+                            return Err(ZekkenError::runtime(
+                                "shift requires no arguments",
+                                call.location.line,
+                                call.location.column,
+                                None,
+                            ));
+                        }
+                        if arr.is_empty() {
+                            return Err(ZekkenError::runtime(
+                                "Array is empty",
+                                call.location.line,
+                                call.location.column,
+                                None,
+                            ));
                         }
+                        return Ok(arr.remove(0));
+                    }
+                    Method::Unshift => {
+                        let v = insert_arg.expect("unshift arg pre-evaluated");
+                        arr.insert(0, v);
+                        return Ok(Value::Array(arr.clone()));
+                    }
+                    Method::Length => {
+                        if !call.args.is_empty() {
+                            return Err(ZekkenError::runtime(
+                                "length requires no arguments",
+                                call.location.line,
+                                call.location.column,
+                                None,
+                            ));
+                        }
+                        return Ok(Value::Int(arr.len() as i64));
+                    }
+                    Method::First => {
+                        if !call.args.is_empty() {
+                            return Err(ZekkenError::runtime(
+                                "first requires no arguments",
+                                call.location.line,
+                                call.location.column,
+                                None,
+                            ));
+                        }
+                        return arr.first().cloned().ok_or_else(|| {
+                            ZekkenError::runtime(
+                                "Array is empty",
+                                call.location.line,
+                                call.location.column,
+                                None,
+                            )
+                        });
+                    }
+                    Method::Last => {
+                        if !call.args.is_empty() {
+                            return Err(ZekkenError::runtime(
+                                "last requires no arguments",
+                                call.location.line,
+                                call.location.column,
+                                None,
+                            ));
+                        }
+                        return arr.last().cloned().ok_or_else(|| {
+                            ZekkenError::runtime(
+                                "Array is empty",
+                                call.location.line,
+                                call.location.column,
+                                None,
+                            )
+                        });
                     }
                     _ => {}
                 }
             }
         }
+    }
 
-        let object = evaluate_expression(&member_expr.object, env)?;
-        let method_name = match member_expr.property.as_ref() {
-            Expr::Identifier(ident) => ident.name.as_str(),
-            _ => return Err(ZekkenError::type_error(
-                "Invalid method name",
-                "identifier",
-                "other",
+    let object = evaluate_expression(&call.object, env)?;
+
+    if call.method_id == Some(Method::Cast) {
+        if call.args.len() != 1 {
+            return Err(ZekkenError::runtime(
+                "cast requires one string argument (target type)",
                 call.location.line,
                 call.location.column,
-            )),
-        };
+                None,
+            ));
+        }
 
-        if method_name == "cast" {
-            if call.args.len() != 1 {
-                return Err(ZekkenError::runtime(
-                    "cast requires one string argument (target type)",
+        let target_value = evaluate_expression(&call.args[0], env)?;
+        let target = match target_value {
+            Value::String(s) => s.trim().to_ascii_lowercase(),
+            _ => {
+                return Err(ZekkenError::runtime_with_span(
+                    "cast target type must be a string",
                     call.location.line,
                     call.location.column,
+                    1,
                     None,
                 ));
             }
+        };
 
-            let target_value = evaluate_expression(&call.args[0], env)?;
-            let target = match target_value {
-                Value::String(s) => s.trim().to_ascii_lowercase(),
-                _ => {
-                    return Err(ZekkenError::runtime_with_span(
-                        "cast target type must be a string",
-                        call.location.line,
-                        call.location.column,
-                        1,
-                        None,
-                    ));
-                }
-            };
+        return cast_value(&object, &target).map_err(|msg| {
+            let (line, column, span_len) = call
+                .args
+                .first()
+                .map(|arg| {
+                    let loc = expr_location(arg);
+                    (loc.line, loc.column, expr_span_len(arg))
+                })
+                .unwrap_or((call.location.line, call.location.column, 1));
+            ZekkenError::runtime_with_span(&msg, line, column, span_len, None)
+        });
+    }
 
-            return cast_value(&object, &target).map_err(|msg| {
-                let (line, column, span_len) = call
-                    .args
-                    .first()
-                    .map(|arg| {
-                        let loc = expr_location(arg);
-                        (loc.line, loc.column, expr_span_len(arg))
-                    })
-                    .unwrap_or((call.location.line, call.location.column, 1));
-                ZekkenError::runtime_with_span(&msg, line, column, span_len, None)
-            });
+    // A function stored in an object property is called like a method.
+    if let Some(obj) = object.as_object() {
+        if let Some(Value::Function(func_def)) = obj.get(method_name) {
+            let args = eval_call_args(&call.args, env)?;
+            return evaluate_function_value_call_with_args(
+                func_def,
+                args,
+                &call.args,
+                env,
+                call.location.line,
+                call.location.column,
+            );
         }
+    }
 
-        // Call the method on any value type
-        let args = eval_call_args(&call.args, env)?;
+    // Call the method on any value type
+    let args = eval_call_args(&call.args, env)?;
 
-        // Try to call the method on any value type (strings, arrays, objects, etc)
-        match object.call_method(method_name, args, Some(env), if let Expr::Identifier(ref ident) = *member_expr.object {
-            Some(ident.name.as_str())
-        } else {
-            None
-        }) {
-            Ok(result) => return Ok(result),
-            Err(msg) => {
-                let (line, column, span_len) = call
-                    .args
-                    .first()
-                    .map(|arg| {
-                        let loc = expr_location(arg);
-                        (loc.line, loc.column, expr_span_len(arg))
-                    })
-                    .unwrap_or((call.location.line, call.location.column, 1));
-                return Err(ZekkenError::runtime_with_span(&msg, line, column, span_len, None));
+    if let (Some(Method::SortBy), Value::Array(items)) = (call.method_id, &object) {
+        return evaluate_sort_by(items, &args, env, call.location.line, call.location.column);
+    }
+
+    // Try to call the method on any value type (strings, arrays, objects, etc)
+    let var_name = if let Expr::Identifier(ref ident) = *call.object {
+        Some(ident.name.as_str())
+    } else {
+        None
+    };
+    object.call_resolved_method(call.method_id, method_name, args.into_vec(), Some(env), var_name).map_err(|msg| {
+        let (line, column, span_len) = call
+            .args
+            .first()
+            .map(|arg| {
+                let loc = expr_location(arg);
+                (loc.line, loc.column, expr_span_len(arg))
+            })
+            .unwrap_or((call.location.line, call.location.column, 1));
+        ZekkenError::runtime_with_span(&msg, line, column, span_len, None)
+    })
+}
+
+// `function`, which a method or library call was passed, applied to each of
+// `items` in turn.
+fn evaluate_for_each_item(function: &Value, items: &[Value], env: &mut Environment, line: usize, column: usize) -> Result<Vec<Value>, ZekkenError> {
+    items
+        .iter()
+        .map(|item| {
+            let arg: CallArgs = std::iter::once(item.clone()).collect();
+            match function {
+                Value::Function(func) => evaluate_function_value_call_with_args(func, arg, &[], env, line, column),
+                Value::NativeFunction(native) => evaluate_native_function_value_call_with_args(native, arg, line, column),
+                _ => unreachable!("callers only pass functions"),
             }
+        })
+        .collect()
+}
+
+// `items.sort_by => |key_fn, descending|`: the key function may be written in
+// Zekken, so it runs here before the keys are sorted.
+fn evaluate_sort_by(items: &[Value], args: &[Value], env: &mut Environment, line: usize, column: usize) -> Result<Value, ZekkenError> {
+    let (key_fn, descending) = sort::sort_by_args(args).map_err(|msg| ZekkenError::runtime(&msg, line, column, None))?;
+    let keys = evaluate_for_each_item(key_fn, items, env, line, column)?;
+    sort::by_keys(items, keys, descending).map_err(|msg| ZekkenError::runtime(&msg, line, column, None))
+}
+
+// `arrays.group_by => |items, key_fn|` and the like, whose functions may be
+// written in Zekken.
+fn evaluate_keyed(keyed: &arrays::Keyed, args: &[Value], env: &mut Environment, line: usize, column: usize) -> Result<Value, ZekkenError> {
+    let to_error = |msg: String| with_native_code(ZekkenError::runtime(&msg, line, column, None));
+    let (items, functions) = keyed.split(args).map_err(to_error)?;
+    let results = functions
+        .iter()
+        .map(|function| evaluate_for_each_item(function, items, env, line, column))
+        .collect::<Result<Vec<_>, _>>()?;
+    keyed.aggregate(items, results).map_err(to_error)
+}
+
+fn evaluate_call_expression(call: &CallExpr, env: &mut Environment) -> Result<Value, ZekkenError> {
+    #[inline]
+    fn builtin_requires_at(name: &str) -> bool {
+        matches!(name, "println" | "print" | "flush" | "input" | "parse_json" | "queue" | "memstats" | "set_precision" | "format" | "diff")
+    }
+
+    // Named arguments are matched to the callee's parameters by name, so it
+    // has to be a function written in Zekken.
+    if !call.arg_names.is_empty() {
+        let args = eval_call_args(&call.args, env)?;
+        let Value::Function(func_def) = evaluate_expression(&call.callee, env)? else {
+            return Err(ZekkenError::runtime(
+                "Named arguments can only be passed to functions declared in Zekken",
+                call.location.line,
+                call.location.column,
+                None,
+            ));
         };
+        let func_def = func_def
+            .for_named_call(&call.arg_names)
+            .map_err(|msg| ZekkenError::runtime(&msg, call.location.line, call.location.column, Some("argument mismatch")))?;
+        return evaluate_function_value_call_with_args(
+            &func_def,
+            args,
+            &call.args,
+            env,
+            call.location.line,
+            call.location.column,
+        );
     }
 
     // When resolving the callee, try identifier dispatch first.
@@ -852,6 +973,7 @@ fn evaluate_call_expression(call: &CallExpr, env: &mut Environment) -> Result<Va
             return evaluate_function_value_call_with_args(
                 func_def,
                 args,
+                &call.args,
                 env,
                 call.location.line,
                 call.location.column,
@@ -877,6 +999,7 @@ fn evaluate_call_expression(call: &CallExpr, env: &mut Environment) -> Result<Va
             return evaluate_function_value_call_with_args(
                 func_def,
                 args,
+                &call.args,
                 env,
                 call.location.line,
                 call.location.column,
@@ -903,6 +1026,7 @@ fn evaluate_call_expression(call: &CallExpr, env: &mut Environment) -> Result<Va
             Some(Value::Function(func_def)) => evaluate_function_value_call_with_args(
                 func_def,
                 args,
+                &call.args,
                 env,
                 call.location.line,
                 call.location.column,
@@ -941,6 +1065,7 @@ fn evaluate_call_expression(call: &CallExpr, env: &mut Environment) -> Result<Va
         Value::Function(func_def) => evaluate_function_value_call_with_args(
             &func_def,
             args,
+            &call.args,
             env,
             call.location.line,
             call.location.column,
@@ -966,12 +1091,18 @@ fn cast_value(value: &Value, target: &str) -> Result<Value, String> {
             Value::String(_) => "string",
             Value::Boolean(_) => "bool",
             Value::Array(_) => "array",
-            Value::Object(_) => "object",
+            Value::Object(_) | Value::Library(_) | Value::Enum { .. } => "object",
             Value::Function(_) => "function",
             Value::NativeFunction(_) => "native function",
             Value::Complex { .. } => "complex",
+            Value::Decimal(_) => "dec",
             Value::Vector(_) => "vector",
             Value::Matrix(_) => "matrix",
+            Value::Quantity(_) => "quantity",
+            Value::BigFloat(_) => "bigfloat",
+            Value::Expression(_) => "expression",
+            Value::Resource(_) => "resource",
+            Value::Null => "null",
             Value::Void => "void",
         }
     }
@@ -982,6 +1113,7 @@ fn cast_value(value: &Value, target: &str) -> Result<Value, String> {
         "int" => match value {
             Value::Int(i) => Ok(Value::Int(*i)),
             Value::Float(f) => Ok(Value::Int(*f as i64)),
+            Value::Decimal(d) => d.to_i64().map(Value::Int).ok_or_else(|| format!("Decimal {} is out of int range", d)),
             Value::Boolean(b) => Ok(Value::Int(if *b { 1 } else { 0 })),
             Value::String(s) => s
                 .trim()
@@ -993,6 +1125,7 @@ fn cast_value(value: &Value, target: &str) -> Result<Value, String> {
         "float" => match value {
             Value::Float(f) => Ok(Value::Float(*f)),
             Value::Int(i) => Ok(Value::Float(*i as f64)),
+            Value::Decimal(d) => Ok(Value::Float(d.to_f64())),
             Value::Boolean(b) => Ok(Value::Float(if *b { 1.0 } else { 0.0 })),
             Value::String(s) => s
                 .trim()
@@ -1015,6 +1148,7 @@ fn cast_value(value: &Value, target: &str) -> Result<Value, String> {
                 }
             _ => Err(format!("Cannot cast type '{}' to bool", value_type_name_local(value))),
         },
+        "dec" | "decimal" => crate::environment::to_decimal(value).map(Value::Decimal),
         _ => Err(format!("Unsupported cast target '{}'", target)),
     }
 }
@@ -1145,24 +1279,49 @@ fn try_eval_math_call(
     }
 }
 
-fn evaluate_function_value_call_with_args(
+/// The error for an argument that does not fit its parameter's type, reported
+/// at the argument (`at`) rather than at the call.
+pub(crate) fn argument_type_error(func: &FunctionValue, param: &Param, value: &Value, at: Location) -> ZekkenError {
+    ZekkenError::type_error(
+        &format!("Type mismatch for parameter '{}' of '{}'", param.ident, func.name),
+        &Environment::datatype_label(&param.type_),
+        value_type_name(value),
+        at.line,
+        at.column,
+    )
+    .with_value(value)
+}
+
+/// Calls `func_def` with evaluated `args`. `arg_exprs` are the argument
+/// expressions when the call site has them, to point type errors at the
+/// offending argument.
+pub(super) fn evaluate_function_value_call_with_args(
     func_def: &FunctionValue,
-    args: Vec<Value>,
+    args: CallArgs,
+    arg_exprs: &[Box<Expr>],
     env: &Environment,
     line: usize,
     column: usize,
 ) -> Result<Value, ZekkenError> {
+    let Some(_depth) = crate::config::CallDepth::enter() else {
+        return Err(crate::config::call_depth_error(line, column));
+    };
+    let _call = crate::stats::enter_call();
+    let _span = crate::trace::enter(&func_def.name, line);
     if args.len() > func_def.params.len() {
         return Err(ZekkenError::runtime(
-            &format!("Expected {} arguments but got {}", func_def.params.len(), args.len()),
+            &format!("Expected {} arguments for '{}' but got {}", func_def.params.len(), func_def.name, args.len()),
             line,
             column,
             Some("argument mismatch"),
         ));
     }
 
+    env.sync_shared();
     let mut function_env = if func_def.needs_parent {
-        Environment::new_with_parent_capacity(env.clone(), func_def.params.len())
+        let mut scope = Environment::new_call_scope(env, func_def.params.len());
+        func_def.bind_closure(&mut scope);
+        scope
     } else {
         Environment::take_pooled_scope(func_def.params.len())
     };
@@ -1174,55 +1333,61 @@ fn evaluate_function_value_call_with_args(
     // correctness issues where function bodies can't see global names.
     if !func_def.needs_parent && !func_def.captures.is_empty() {
         for name in func_def.captures.iter() {
-            if let Some(val) = func_def.capture_values.get(name).or_else(|| env.lookup_ref(name)) {
+            if *name == *func_def.name && !func_def.capture_values.contains_key(name) {
+                // A function calling itself, even where the caller cannot see it.
+                function_env.declare_ref(name, Value::Function(func_def.clone()), false);
+            } else if let Some(val) = env.lookup_for_call(name, &func_def.capture_values) {
                 function_env.declare_ref(name, val.clone(), false);
             }
         }
     }
+    if let (false, Some(closure)) = (func_def.needs_parent, &func_def.closure) {
+        function_env.enter_closure(closure);
+    }
 
     let bind_and_execute = || -> Result<Value, ZekkenError> {
-        let provided = args;
+        let mut provided = args.into_iter();
 
         // Bind provided args first, then fill missing params from defaults.
         for (idx, param) in func_def.params.iter().enumerate() {
-            let value = if let Some(arg) = provided.get(idx) {
-                arg.clone()
+            let value = if let Some(arg) = provided.next() {
+                arg
             } else if let Some(default_expr) = param.default_value.as_ref() {
                 evaluate_expression(default_expr, &mut function_env)?
             } else {
                 return Err(ZekkenError::runtime(
-                    &format!("Missing required argument '{}'", param.ident),
+                    &format!("Missing required argument '{}' for '{}'", param.ident, func_def.name),
                     line,
                     column,
                     Some("argument mismatch"),
                 ));
             };
             if !check_value_type(&value, &param.type_) {
-                return Err(ZekkenError::type_error(
-                    &format!("Type mismatch for parameter '{}'", param.ident),
-                    &format!("{:?}", param.type_),
-                    value_type_name(&value),
-                    line,
-                    column,
-                ));
+                let at = arg_exprs.get(idx).map_or(Location { line, column }, |arg| expr_location(arg));
+                return Err(argument_type_error(func_def, param, &value, at));
             }
-            function_env.declare_ref_typed(param.ident.as_str(), value, param.type_, false);
+            function_env.declare_ref_typed(param.ident.as_str(), value, param.type_.clone(), false);
         }
 
-        let result = bytecode::execute_contents(func_def.body.as_ref(), &mut function_env)?;
-        Ok(result.unwrap_or(Value::Void))
+        let result = bytecode::execute_contents(func_def.body.as_ref(), &mut function_env);
+        let result = match bytecode::run_deferred(&mut function_env) {
+            Some(error) if result.is_ok() => Err(error),
+            _ => result,
+        };
+        function_env.sync_shared();
+        Ok(result?.unwrap_or(Value::Void))
     };
 
     let out = bind_and_execute().and_then(|v| {
-        if let Some(ret_ty) = func_def.return_type {
-            if !check_value_type(&v, &ret_ty) {
+        if let Some(ret_ty) = &func_def.return_type {
+            if !check_value_type(&v, ret_ty) {
                 return Err(ZekkenError::type_error(
                     "Type mismatch in function return value",
                     &format!("{:?}", ret_ty),
                     value_type_name(&v),
                     line,
                     column,
-                ));
+                ).with_value(&v));
             }
         }
         Ok(v)
@@ -1235,14 +1400,14 @@ fn evaluate_function_value_call_with_args(
 
 fn evaluate_native_function_value_call_with_args(
     native: &Arc<dyn Fn(Vec<Value>) -> Result<Value, String> + Send + Sync + 'static>,
-    args: Vec<Value>,
+    args: CallArgs,
     line: usize,
     column: usize,
 ) -> Result<Value, ZekkenError> {
-    match (native)(args) {
+    match call_native(&**native, args.into_vec(), line, column) {
         Ok(val) => Ok(val),
         Err(s) => {
-            Err(ZekkenError::runtime_with_span(&s, line, column, 1, None))
+            Err(with_native_code(ZekkenError::runtime_with_span(&s, line, column, 1, None)))
         }
     }
 }
@@ -1252,29 +1417,39 @@ fn expr_location(expr: &Expr) -> Location {
         Expr::Assign(e) => e.location.clone(),
         Expr::Member(e) => e.location.clone(),
         Expr::Call(e) => e.location.clone(),
+        Expr::MethodCall(e) => e.location.clone(),
         Expr::Unary(e) => e.location.clone(),
         Expr::Binary(e) => e.location.clone(),
         Expr::Identifier(e) => e.location.clone(),
         Expr::Property(e) => e.location.clone(),
         Expr::IntLit(e) => e.location.clone(),
         Expr::FloatLit(e) => e.location.clone(),
+        Expr::DecimalLit(e) => e.location.clone(),
         Expr::StringLit(e) => e.location.clone(),
+        Expr::TemplateLit(e) => e.location.clone(),
         Expr::BoolLit(e) => e.location.clone(),
+        Expr::NullLit(e) => e.location.clone(),
         Expr::ArrayLit(e) => e.location.clone(),
+        Expr::VectorLit(e) => e.location.clone(),
+        Expr::MatrixLit(e) => e.location.clone(),
         Expr::ObjectLit(e) => e.location.clone(),
+        Expr::Match(e) => e.location.clone(),
+        Expr::Lambda(e) => e.location.clone(),
     }
 }
 
 fn expr_span_len(expr: &Expr) -> usize {
     match expr {
         Expr::Unary(unary) => unary.operator.len() + expr_span_len(&unary.operand),
-        Expr::StringLit(lit) => lit.value.chars().count() + 2, // include quotes
+        Expr::StringLit(lit) => lit.value.chars().count() + 2 + lit.raw as usize, // include quotes and any `r`
         Expr::Identifier(id) => id.name.chars().count().max(1),
         Expr::IntLit(lit) => lit.value.to_string().chars().count().max(1),
         Expr::FloatLit(lit) => lit.value.to_string().chars().count().max(1),
+        Expr::DecimalLit(lit) => lit.value.chars().count() + 1, // include the suffix
         Expr::BoolLit(lit) => {
             if lit.value { 4 } else { 5 }
         }
+        Expr::NullLit(_) => 4,
         _ => 1,
     }
 }
@@ -1285,13 +1460,24 @@ fn evaluate_member_expression(member: &MemberExpr, env: &mut Environment) -> Res
     }
 
     let object = evaluate_expression(&member.object, env)?;
+    if let (Value::String(s), true) = (&object, member.computed) {
+        let index = evaluate_expression(&member.property, env)?;
+        return string_char_at(s, &index)
+            .map(|ch| Value::String(ch.to_string()))
+            .map_err(|e| ZekkenError::runtime(&e, member.location.line, member.location.column, None));
+    }
+    if let (Value::Vector(_) | Value::Matrix(_), true) = (&object, member.computed) {
+        let index = evaluate_expression(&member.property, env)?;
+        return vector_index(&object, &index)
+            .map_err(|e| ZekkenError::runtime(&e, member.location.line, member.location.column, None));
+    }
     let result = match &*member.property {
         Expr::Identifier(ref ident) => {
-            if member.is_method {
+            if member.computed {
                 // Bracket indexing: obj[expr] / arr[expr]
                 //
                 // For identifier keys inside brackets, prefer runtime lookup:
-                // - int/float-as-int => numeric indexing (arrays, and objects via __keys__)
+                // - int/float-as-int => numeric indexing (arrays, and objects by insertion order)
                 // - string => object property lookup by that string
                 // - otherwise fall back to literal property name (obj[foo] -> "foo")
                 if let Some(v) = env.lookup_ref(&ident.name) {
@@ -1318,7 +1504,7 @@ fn evaluate_member_expression(member: &MemberExpr, env: &mut Environment) -> Res
         Expr::StringLit(ref lit) => evaluate_property_access(&object, &lit.value, member.location.line, member.location.column),
         Expr::IntLit(ref lit) => evaluate_index_access(&object, lit.value as usize, member.location.line, member.location.column),
         _ => {
-            if member.is_method {
+            if member.computed {
                 // Bracket indexing supports dynamic expressions.
                 match evaluate_expression(&member.property, env)? {
                     Value::Int(i) if i >= 0 => {
@@ -1357,7 +1543,7 @@ fn evaluate_member_expression_chain(member: &MemberExpr, env: &mut Environment)
         match expr {
             Expr::Member(m) => {
                 let root = collect_chain(m.object.as_ref(), out)?;
-                out.push((m.property.as_ref(), m.is_method));
+                out.push((m.property.as_ref(), m.computed));
                 Some(root)
             }
             Expr::Identifier(id) => Some(id),
@@ -1370,7 +1556,7 @@ fn evaluate_member_expression_chain(member: &MemberExpr, env: &mut Environment)
         Some(id) => id,
         None => return Ok(None),
     };
-    chain.push((member.property.as_ref(), member.is_method));
+    chain.push((member.property.as_ref(), member.computed));
 
     let supports_fast_chain = chain.iter().all(|(prop, _)| {
         matches!(
@@ -1430,92 +1616,62 @@ fn evaluate_member_expression_chain(member: &MemberExpr, env: &mut Environment)
                     ));
                 }
             }
-            Value::Object(map) => match prop {
-                Expr::Identifier(ident) => {
-                    if computed {
-                        // Bracket indexing: allow obj[a] where `a` is an int/float-as-int/string at runtime.
-                        if let Some(v) = env.lookup_ref(&ident.name) {
-                            match v {
-                                Value::String(s) => map.get(s).ok_or_else(|| {
-                                    ZekkenError::reference(
-                                        &format!("Property '{}' not found", s),
-                                        s,
-                                        member.location.line,
-                                        member.location.column,
-                                    )
-                                })?,
-                                Value::Int(i) if *i >= 0 => {
-                                    let idx = *i as usize;
-                                    let key = match map.get("__keys__") {
-                                        Some(Value::Array(keys)) => match keys.get(idx) {
-                                            Some(Value::String(key)) => key,
-                                            _ => {
-                                                return Err(ZekkenError::runtime(
-                                                    &format!("Object index {} out of bounds", idx),
-                                                    member.location.line,
-                                                    member.location.column,
-                                                    None,
-                                                ));
-                                            }
-                                        },
-                                        _ => {
-                                            return Err(ZekkenError::runtime(
-                                                "Object does not support numeric indexing",
-                                                member.location.line,
-                                                member.location.column,
-                                                None,
-                                            ));
-                                        }
-                                    };
-                                    map.get(key).ok_or_else(|| {
+            Value::Object(_) | Value::Library(_) | Value::Enum { .. } => {
+                let Some(map) = current.as_object() else { unreachable!("objects, libraries and enum variants have fields") };
+                match prop {
+                    Expr::Identifier(ident) => {
+                        if computed {
+                            // Bracket indexing: allow obj[a] where `a` is an int/float-as-int/string at runtime.
+                            if let Some(v) = env.lookup_ref(&ident.name) {
+                                match v {
+                                    Value::String(s) => map.get(s).ok_or_else(|| {
                                         ZekkenError::reference(
-                                            &format!("Property '{}' not found", key),
-                                            key,
+                                            &format!("Property '{}' not found", s),
+                                            s,
                                             member.location.line,
                                             member.location.column,
                                         )
-                                    })?
-                                }
-                                Value::Float(f) if *f >= 0.0 && f.fract() == 0.0 => {
-                                    let idx = *f as usize;
-                                    let key = match map.get("__keys__") {
-                                        Some(Value::Array(keys)) => match keys.get(idx) {
-                                            Some(Value::String(key)) => key,
-                                            _ => {
-                                                return Err(ZekkenError::runtime(
-                                                    &format!("Object index {} out of bounds", idx),
-                                                    member.location.line,
-                                                    member.location.column,
-                                                    None,
-                                                ));
-                                            }
-                                        },
-                                        _ => {
-                                            return Err(ZekkenError::runtime(
-                                                "Object does not support numeric indexing",
+                                    })?,
+                                    Value::Int(i) if *i >= 0 => {
+                                        let idx = *i as usize;
+                                        map.get_index(idx).map(|(_, value)| value).ok_or_else(|| {
+                                            ZekkenError::runtime(
+                                                &format!("Object index {} out of bounds", idx),
                                                 member.location.line,
                                                 member.location.column,
                                                 None,
-                                            ));
-                                        }
-                                    };
-                                    map.get(key).ok_or_else(|| {
+                                            )
+                                        })?
+                                    }
+                                    Value::Float(f) if *f >= 0.0 && f.fract() == 0.0 => {
+                                        let idx = *f as usize;
+                                        map.get_index(idx).map(|(_, value)| value).ok_or_else(|| {
+                                            ZekkenError::runtime(
+                                                &format!("Object index {} out of bounds", idx),
+                                                member.location.line,
+                                                member.location.column,
+                                                None,
+                                            )
+                                        })?
+                                    }
+                                    _ => map.get(&ident.name).ok_or_else(|| {
                                         ZekkenError::reference(
-                                            &format!("Property '{}' not found", key),
-                                            key,
+                                            &format!("Property '{}' not found", ident.name),
+                                            &ident.name,
                                             member.location.line,
                                             member.location.column,
                                         )
-                                    })?
+                                    })?,
                                 }
-                                _ => map.get(&ident.name).ok_or_else(|| {
+                            } else {
+                                map.get(&ident.name).ok_or_else(|| {
                                     ZekkenError::reference(
                                         &format!("Property '{}' not found", ident.name),
                                         &ident.name,
                                         member.location.line,
                                         member.location.column,
                                     )
-                                })?,
+                                })?
                             }
                         } else {
                             map.get(&ident.name).ok_or_else(|| {
@@ -1527,67 +1683,41 @@ fn evaluate_member_expression_chain(member: &MemberExpr, env: &mut Environment)
                                 )
                             })?
                         }
-                    } else {
-                        map.get(&ident.name).ok_or_else(|| {
-                            ZekkenError::reference(
-                                &format!("Property '{}' not found", ident.name),
-                                &ident.name,
+                    }
+                    Expr::StringLit(lit) => map.get(&lit.value).ok_or_else(|| {
+                        ZekkenError::reference(
+                            &format!("Property '{}' not found", lit.value),
+                            &lit.value,
+                            member.location.line,
+                            member.location.column,
+                        )
+                    })?,
+                    Expr::IntLit(lit) if lit.value >= 0 => {
+                        let idx = lit.value as usize;
+                        map.get_index(idx).map(|(_, value)| value).ok_or_else(|| {
+                            ZekkenError::runtime(
+                                &format!("Object index {} out of bounds", idx),
                                 member.location.line,
                                 member.location.column,
+                                None,
                             )
                         })?
                     }
-                }
-                Expr::StringLit(lit) => map.get(&lit.value).ok_or_else(|| {
-                    ZekkenError::reference(
-                        &format!("Property '{}' not found", lit.value),
-                        &lit.value,
-                        member.location.line,
-                        member.location.column,
-                    )
-                })?,
-                Expr::IntLit(lit) if lit.value >= 0 => {
-                    let idx = lit.value as usize;
-                    let key = match map.get("__keys__") {
-                        Some(Value::Array(keys)) => match keys.get(idx) {
-                            Some(Value::String(key)) => key,
-                            _ => {
-                                return Err(ZekkenError::runtime(
-                                    &format!("Object index {} out of bounds", idx),
-                                    member.location.line,
-                                    member.location.column,
-                                    None,
-                                ));
-                            }
-                        },
-                        _ => {
-                            return Err(ZekkenError::runtime(
-                                "Object does not support numeric indexing",
-                                member.location.line,
-                                member.location.column,
-                                None,
-                            ));
-                        }
-                    };
-                    map.get(key).ok_or_else(|| {
-                        ZekkenError::reference(
-                            &format!("Property '{}' not found", key),
-                            key,
+                    _ => {
+                        return Err(ZekkenError::type_error(
+                            "Invalid property access",
+                            "string/int/identifier",
+                            "other",
                             member.location.line,
                             member.location.column,
-                        )
-                    })?
-                }
-                _ => {
-                    return Err(ZekkenError::type_error(
-                        "Invalid property access",
-                        "string/int/identifier",
-                        "other",
-                        member.location.line,
-                        member.location.column,
-                    ));
+                        ));
+                    }
                 }
-            },
+            }
+            // Indexing a string yields a new value, which the slow path builds.
+            Value::String(_) if computed => return Ok(None),
+            Value::Vector(_) | Value::Matrix(_) | Value::Quantity(_) | Value::BigFloat(_) => return Ok(None),
+            Value::Function(_) | Value::NativeFunction(_) => return Ok(None),
             _ => {
                 return Err(ZekkenError::type_error(
                     "Invalid member access",
@@ -1605,7 +1735,8 @@ fn evaluate_member_expression_chain(member: &MemberExpr, env: &mut Environment)
 
 fn evaluate_property_access(object: &Value, property: &str, line: usize, column: usize) -> Result<Value, ZekkenError> {
     match object {
-        Value::Object(map) => {
+        Value::Object(_) | Value::Library(_) | Value::Enum { .. } => {
+            let Some(map) = object.as_object() else { unreachable!("objects, libraries and enum variants have fields") };
             map.get(property)
                 .cloned()
                 .ok_or_else(|| ZekkenError::reference(
@@ -1615,6 +1746,27 @@ fn evaluate_property_access(object: &Value, property: &str, line: usize, column:
                     column,
                 ))
         }
+        Value::Vector(v) if property == "length" => Ok(Value::Int(v.len() as i64)),
+        Value::Matrix(m) if property == "length" => Ok(Value::Int(m.len() as i64)),
+        Value::Function(func) => func.property(property).ok_or_else(|| ZekkenError::reference(
+            &format!("Property '{}' not found", property),
+            property,
+            line,
+            column,
+        )),
+        Value::Quantity(_) | Value::BigFloat(_) => object.library_property(property).ok_or_else(|| ZekkenError::reference(
+            &format!("Property '{}' not found", property),
+            property,
+            line,
+            column,
+        )),
+        Value::NativeFunction(_) => Err(ZekkenError::type_error(
+            "Native functions have no name, arity or params",
+            "a function declared in Zekken",
+            "native function",
+            line,
+            column,
+        )),
         _ => Err(ZekkenError::type_error(
             "Invalid member access",
             "object",
@@ -1643,34 +1795,14 @@ fn evaluate_index_access(object: &Value, idx: usize, line: usize, column: usize)
                 None,
             ))
         }
-        Value::Object(map) => {
-            // Support numeric indexing for objects with __keys__
-            if let Some(Value::Array(keys)) = map.get("__keys__") {
-                if let Some(Value::String(key)) = keys.get(idx) {
-                    map.get(key)
-                        .cloned()
-                        .ok_or_else(|| ZekkenError::reference(
-                            &format!("Property '{}' not found", key),
-                            key,
-                            line,
-                            column,
-                        ))
-                } else {
-                    Err(ZekkenError::runtime(
-                        &format!("Object index {} out of bounds", idx),
-                        line,
-                        column,
-                        None,
-                    ))
-                }
-            } else {
-                Err(ZekkenError::runtime(
-                    "Object does not support numeric indexing",
-                    line,
-                    column,
-                    None,
-                ))
-            }
+        Value::Object(_) | Value::Library(_) | Value::Enum { .. } => {
+            let Some(map) = object.as_object() else { unreachable!("objects, libraries and enum variants have fields") };
+            map.get_index(idx).map(|(_, value)| value.clone()).ok_or_else(|| ZekkenError::runtime(
+                &format!("Object index {} out of bounds", idx),
+                line,
+                column,
+                None,
+            ))
         }
         _ => Err(ZekkenError::type_error(
             "Invalid member access",
@@ -1684,11 +1816,14 @@ fn evaluate_index_access(object: &Value, idx: usize, line: usize, column: usize)
 
 pub fn evaluate_assignment_discard(assign: &AssignExpr, env: &mut Environment) -> Result<(), ZekkenError> {
     let _ = evaluate_assignment_internal(assign, env, false)?;
+    crate::history::record_assignment(&assign.left, &assign.location, env);
     Ok(())
 }
 
 fn evaluate_assignment(assign: &AssignExpr, env: &mut Environment) -> Result<Value, ZekkenError> {
-    evaluate_assignment_internal(assign, env, true)
+    let value = evaluate_assignment_internal(assign, env, true)?;
+    crate::history::record_assignment(&assign.left, &assign.location, env);
+    Ok(value)
 }
 
 fn evaluate_assignment_internal(assign: &AssignExpr, env: &mut Environment, want_result: bool) -> Result<Value, ZekkenError> {
@@ -1716,7 +1851,7 @@ fn evaluate_assignment_internal(assign: &AssignExpr, env: &mut Environment, want
         if assign.operator == "=" {
             let right_val = evaluate_expression(&assign.right, env)?;
             let expected = env.lookup_type(name).unwrap_or(DataType::Any);
-            if expected != DataType::Any && !check_value_type(&right_val, &expected) {
+            if expected != DataType::Any && !matches!(right_val, Value::Null) && !check_value_type(&right_val, &expected) {
                 let loc = expr_location(&assign.right);
                 return Err(ZekkenError::type_error(
                     &format!("Type mismatch in assignment to '{}'", name),
@@ -1724,7 +1859,7 @@ fn evaluate_assignment_internal(assign: &AssignExpr, env: &mut Environment, want
                     value_type_name(&right_val),
                     loc.line,
                     loc.column,
-                ));
+                ).with_value(&right_val));
             }
             if let Ok(slot) = env.lookup_mut_assignable(name) {
                 return match right_val {
@@ -1944,7 +2079,7 @@ fn evaluate_assignment_internal(assign: &AssignExpr, env: &mut Environment, want
                     value_type_name(&other),
                     assign.location.line,
                     assign.location.column,
-                ))
+                ).with_value(&other))
             }
         }
     } else if assign.operator != "=" {
@@ -1960,22 +2095,7 @@ fn evaluate_assignment_internal(assign: &AssignExpr, env: &mut Environment, want
             AssignTarget::Member(expr) => evaluate_expression(expr, env)?,
         };
         let right_val = evaluate_expression(&assign.right, env)?;
-        match assign.operator.as_str() {
-            "+=" => add_values(&left_val, &right_val),
-            "-=" => subtract_values(&left_val, &right_val),
-            "*=" => multiply_values(&left_val, &right_val),
-            "/=" => divide_values(&left_val, &right_val),
-            "%=" => modulo_values(left_val, right_val),
-            _ => {
-                return Err(ZekkenError::runtime(
-                    &format!("Unknown operator: {}", assign.operator),
-                    assign.location.line,
-                    assign.location.column,
-                    None,
-                ))
-            }
-        }
-        .map_err(|e| ZekkenError::runtime(&e, assign.location.line, assign.location.column, None))?
+        binary_values(assign.operator.trim_end_matches('='), &left_val, &right_val, &assign.location)?
     } else {
         evaluate_expression(&assign.right, env)?
     };
@@ -2089,31 +2209,22 @@ fn assign_at_path(current: &mut Value, path: &[MemberKey], value: Value) -> Resu
         *current = value;
         return Ok(());
     }
+    current.unshare();
 
     match &path[0] {
         MemberKey::Index(idx) => match current {
             Value::Array(arr) => {
-                if *idx >= arr.len() {
+                if path.len() == 1 {
+                    ensure_array_slot(arr, *idx)?;
+                } else if *idx >= arr.len() {
                     return Err(format!("Array index {} out of bounds", idx));
                 }
                 assign_at_path(&mut arr[*idx], &path[1..], value)
             }
             Value::Object(map) => {
-                let key_for_index = match map.get("__keys__") {
-                    Some(Value::Array(keys)) => match keys.get(*idx) {
-                        Some(Value::String(key)) => Some(key.clone()),
-                        _ => None,
-                    },
-                    _ => None,
-                };
-                if let Some(key) = key_for_index {
-                    if let Some(next) = map.get_mut(&key) {
-                        assign_at_path(next, &path[1..], value)
-                    } else {
-                        Err(format!("Property '{}' not found", key))
-                    }
-                } else {
-                    Err("Object does not support numeric indexing".to_string())
+                match map.get_index_mut(*idx) {
+                    Some((_, next)) => assign_at_path(next, &path[1..], value),
+                    None => Err(format!("Object index {} out of bounds", idx)),
                 }
             }
             _ => Err("Invalid member assignment target (indexing non-array/object)".to_string()),
@@ -2140,97 +2251,16 @@ fn assign_to_member(member_expr: &Expr, value: Value, env: &mut Environment) ->
     assign_at_path(root_value, &path, value)
 }
 
-fn add_values(left: &Value, right: &Value) -> Result<Value, String> {
-    match (left, right) {
-        (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l + r)),
-        (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l + r)),
-        (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 + r)),
-        (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l + *r as f64)),
-        (Value::String(l), Value::String(r)) => Ok(Value::String(l.clone() + r)),
-        (Value::String(l), other) => Ok(Value::String(l.clone() + &other.to_string())),
-        (other, Value::String(r)) => Ok(Value::String(other.to_string() + r)),
-        (Value::Array(l), Value::Array(r)) => {
-            let mut result = l.clone();
-            result.extend(r.clone());
-            Ok(Value::Array(result))
-        },
-        _ => Err("Invalid operand types for addition".to_string())
-    }
-}
-
-fn subtract_values(left: &Value, right: &Value) -> Result<Value, String> {
-    match (left, right) {
-        (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l - r)),
-        (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l - r)),
-        (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 - r)),
-        (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l - *r as f64)),
-        _ => Err("Invalid operand types for subtraction".to_string())
-    }
-}
-
-fn multiply_values(left: &Value, right: &Value) -> Result<Value, String> {
-    match (left, right) {
-        (Value::Int(l), Value::Int(r)) => Ok(Value::Int(l * r)),
-        (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l * r)),
-        (Value::Int(l), Value::Float(r)) => Ok(Value::Float(*l as f64 * r)),
-        (Value::Float(l), Value::Int(r)) => Ok(Value::Float(l * *r as f64)),
-        _ => Err("Invalid operand types for multiplication".to_string())
-    }
-}
-
-fn divide_values(left: &Value, right: &Value) -> Result<Value, String> {
-    match (left, right) {
-        (Value::Int(l), Value::Int(r)) => {
-            if *r == 0 {
-                Err("Division by zero".to_string())
-            } else {
-                Ok(Value::Int(l / r))
-            }
-        },
-        (Value::Float(l), Value::Float(r)) => {
-            if *r == 0.0 {
-                Err("Division by zero".to_string())
-            } else {
-                Ok(Value::Float(l / r))
-            }
-        },
-        (Value::Int(l), Value::Float(r)) => {
-            if *r == 0.0 {
-                Err("Division by zero".to_string())
-            } else {
-                Ok(Value::Float(*l as f64 / r))
-            }
-        }
-        (Value::Float(l), Value::Int(r)) => {
-            if *r == 0 {
-                Err("Division by zero".to_string())
-            } else {
-                Ok(Value::Float(l / *r as f64))
-            }
-        }
-        _ => Err("Invalid operand types for division".to_string())
-    }
-}
-
-fn modulo_values(left: Value, right: Value) -> Result<Value, String> {
-    match (left, right) {
-        (Value::Int(l), Value::Int(r)) => {
-            if r == 0 {
-                Err("Modulo by zero".to_string())
-            } else {
-                Ok(Value::Int(l % r))
-            }
-        },
-        _ => Err("Invalid operand types for modulo".to_string())
-    }
-}
-
 fn compare_values(left: &Value, right: &Value) -> bool {
     match (left, right) {
         (Value::Int(l), Value::Int(r)) => l == r,
         (Value::Float(l), Value::Float(r)) => l == r,
+        (Value::Decimal(l), Value::Decimal(r)) => l == r,
         (Value::String(l), Value::String(r)) => l == r,
         (Value::Boolean(l), Value::Boolean(r)) => l == r,
+        (Value::Null, Value::Null) => true,
+        (Value::Enum { .. }, Value::Enum { .. }) => left.enum_variant() == right.enum_variant(),
+        (Value::Function(_) | Value::NativeFunction(_), _) => left.same_function(right),
         _ => false
     }
 }
@@ -0,0 +1,94 @@
+//! What an `include` brings into the including scope.
+//!
+//! Both evaluators and the checker copy an included file's bindings through
+//! here. Names starting with `_` and the interpreter's own `ZEKKEN_*`
+//! bindings stay private to the included file, and libraries the file loaded
+//! with `use` are left for the includer to `use` itself. An `include lazy`
+//! runs only the file's declarations, leaving demo code and other top-level
+//! statements unrun.
+
+use crate::ast::{Content, IncludeStmt, Program, Stmt};
+use crate::environment::{Environment, Value};
+
+/// Whether `name` is internal to the file that declares it.
+pub fn is_private(name: &str) -> bool {
+    name.starts_with('_') || name.starts_with("ZEKKEN_")
+}
+
+/// Whether `content` only declares something: a variable, function, object,
+/// enum or export. These are all an `include lazy` runs.
+pub fn is_declaration(content: &Content) -> bool {
+    matches!(
+        content,
+        Content::Statement(stmt) if matches!(
+            stmt.as_ref(),
+            Stmt::VarDecl(_) | Stmt::FuncDecl(_) | Stmt::Lambda(_) | Stmt::ObjectDecl(_) | Stmt::EnumDecl(_) | Stmt::Export(_)
+        )
+    )
+}
+
+/// Drops the top-level statements an `include lazy` leaves unrun.
+pub fn keep_declarations(program: &mut Program) {
+    program.content.retain(is_declaration);
+}
+
+/// Names bound by the `use` statements of an included file.
+pub fn library_names(program: &Program) -> Vec<String> {
+    let mut names = Vec::new();
+    for import in &program.imports {
+        if let Content::Statement(stmt) = import {
+            if let Stmt::Use(use_stmt) = stmt.as_ref() {
+                names.push(use_stmt.module.clone());
+                names.extend(use_stmt.methods.iter().flatten().cloned());
+            }
+        }
+    }
+    names
+}
+
+/// The bindings `include` copies out of `child`, the scope the included file
+/// ran in, sorted by name. `libraries` are the included file's own
+/// [`library_names`].
+pub fn included_bindings(
+    include: &IncludeStmt,
+    libraries: &[String],
+    child: &Environment,
+) -> Result<Vec<(String, Value)>, String> {
+    match &include.methods {
+        Some(methods) => methods
+            .iter()
+            .map(|method| {
+                if is_private(method) {
+                    return Err(format!("'{}' is private to the included file", method));
+                }
+                child
+                    .lookup(method)
+                    .map(|value| (method.clone(), value))
+                    .ok_or_else(|| format!("Method '{}' not found in included file", method))
+            })
+            .collect(),
+        None => {
+            let mut bindings: Vec<(String, Value)> = child
+                .variables
+                .iter()
+                .filter(|(name, _)| !is_private(name) && !libraries.contains(name))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            bindings.sort_by(|a, b| a.0.cmp(&b.0));
+            Ok(bindings)
+        }
+    }
+}
+
+/// Reports what an include contributed when `--trace-imports` is on.
+pub fn trace(path: &str, bindings: &[(String, Value)]) {
+    if !crate::config::trace_imports() {
+        return;
+    }
+    let names: Vec<&str> = bindings.iter().map(|(name, _)| name.as_str()).collect();
+    if names.is_empty() {
+        crate::output::diagnostic(format_args!("include {}: (nothing)", path));
+    } else {
+        crate::output::diagnostic(format_args!("include {}: {}", path, names.join(", ")));
+    }
+}
@@ -0,0 +1,276 @@
+//! Static type inference used by the lint pass.
+//!
+//! Types are synthesized bottom-up from literals, declared variable and
+//! parameter types, and function return types. A function without a declared
+//! return type gets one inferred from its `return` statements when they all
+//! agree. Anything that cannot be known before running (member access,
+//! native calls, `any` bindings) infers as `None` and is never reported.
+
+use crate::ast::*;
+use crate::environment::{Environment, Value};
+use crate::lexer::DataType;
+
+// Bounds how many nested function bodies are inferred through, so recursive
+// functions terminate.
+const MAX_CALL_DEPTH: usize = 4;
+
+/// Name of a type as runtime type errors print it.
+pub fn type_name(ty: DataType) -> &'static str {
+    match ty {
+        DataType::Nullable(inner) => type_name(*inner),
+        DataType::Int => "int",
+        DataType::Float => "float",
+        DataType::String => "string",
+        DataType::Bool => "bool",
+        DataType::Object => "object",
+        DataType::Array | DataType::ArrayOf(_) => "array",
+        DataType::Fn => "function",
+        DataType::Decimal => "dec",
+        DataType::Any => "any",
+    }
+}
+
+/// Whether a value inferred as `found` can be stored where `expected` is
+/// declared. An `arr` fits an `arr<T>` and the other way round, leaving the
+/// elements to the check when the value is stored. A `T?` counts as its `T`,
+/// as whether it holds `null` is only known then too.
+pub fn fits(found: &DataType, expected: &DataType) -> bool {
+    match (found, expected) {
+        (DataType::Nullable(found), _) => fits(found, expected),
+        (_, DataType::Nullable(expected)) => fits(found, expected),
+        (DataType::ArrayOf(found), DataType::ArrayOf(expected)) => fits(found, expected),
+        (DataType::Array | DataType::ArrayOf(_), DataType::Array | DataType::ArrayOf(_)) => true,
+        _ => found == expected,
+    }
+}
+
+fn value_type(value: &Value) -> Option<DataType> {
+    match value {
+        Value::Int(_) => Some(DataType::Int),
+        Value::Float(_) => Some(DataType::Float),
+        Value::String(_) => Some(DataType::String),
+        Value::Boolean(_) => Some(DataType::Bool),
+        Value::Array(_) => Some(DataType::Array),
+        Value::Object(_) | Value::Library(_) | Value::Enum { .. } | Value::Quantity(_) | Value::BigFloat(_) | Value::Expression(_) => Some(DataType::Object),
+        Value::Function(_) | Value::NativeFunction(_) => Some(DataType::Fn),
+        Value::Decimal(_) => Some(DataType::Decimal),
+        _ => None,
+    }
+}
+
+fn known(ty: DataType) -> Option<DataType> {
+    (ty != DataType::Any).then_some(ty)
+}
+
+/// Infers the static type of `expr`, or `None` when it is not known.
+pub fn infer_expr(expr: &Expr, env: &Environment) -> Option<DataType> {
+    infer_at_depth(expr, env, 0)
+}
+
+fn infer_at_depth(expr: &Expr, env: &Environment, depth: usize) -> Option<DataType> {
+    match expr {
+        Expr::IntLit(_) => Some(DataType::Int),
+        Expr::FloatLit(_) => Some(DataType::Float),
+        Expr::DecimalLit(_) => Some(DataType::Decimal),
+        Expr::StringLit(_) | Expr::TemplateLit(_) => Some(DataType::String),
+        Expr::Lambda(_) => Some(DataType::Fn),
+        Expr::BoolLit(_) => Some(DataType::Bool),
+        Expr::NullLit(_) => None,
+        Expr::ArrayLit(_) | Expr::VectorLit(_) | Expr::MatrixLit(_) => Some(DataType::Array),
+        Expr::ObjectLit(_) => Some(DataType::Object),
+        Expr::Identifier(ident) => match env.lookup_type(&ident.name).and_then(known) {
+            Some(ty) => Some(ty),
+            // Untyped bindings (loop variables, host values) only have a type
+            // when they hold a real value rather than a placeholder.
+            None => env.lookup_ref(&ident.name).and_then(value_type),
+        },
+        Expr::Unary(unary) if unary.operator == "!" => Some(DataType::Bool),
+        Expr::Binary(binary) => {
+            let left = || infer_at_depth(&binary.left, env, depth);
+            let right = || infer_at_depth(&binary.right, env, depth);
+            match binary.operator.as_str() {
+                "==" | "!=" | "<" | ">" | "<=" | ">=" | "&&" | "||" | "in" => Some(DataType::Bool),
+                ".." => Some(DataType::Array),
+                "+" => match (left(), right()) {
+                    (Some(DataType::String), _) | (_, Some(DataType::String)) => Some(DataType::String),
+                    (Some(DataType::Array), Some(DataType::Array)) => Some(DataType::Array),
+                    (Some(l), Some(r)) => numeric_result(&l, &r),
+                    _ => None,
+                },
+                "*" => match (left()?, right()?) {
+                    (DataType::String, DataType::Int) | (DataType::Int, DataType::String) => Some(DataType::String),
+                    (l, r) => numeric_result(&l, &r).or_else(|| linear_result(&l, &r)),
+                },
+                "-" | "/" => {
+                    let (l, r) = (left()?, right()?);
+                    numeric_result(&l, &r).or_else(|| linear_result(&l, &r))
+                }
+                "%" => match (left()?, right()?) {
+                    (DataType::Int, DataType::Int) => Some(DataType::Int),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+        Expr::Call(call) => {
+            let ident = match call.callee.as_ref() {
+                Expr::Identifier(ident) => ident,
+                _ => return None,
+            };
+            match env.lookup_ref(&ident.name)? {
+                Value::Function(func) => match &func.return_type {
+                    Some(ty) => known(ty.clone()),
+                    None if depth < MAX_CALL_DEPTH => {
+                        infer_return_type(&func.params, &func.body, env, depth + 1)
+                    }
+                    None => None,
+                },
+                _ => None,
+            }
+        }
+        Expr::MethodCall(call) => infer_cast(call),
+        Expr::Assign(_) | Expr::Member(_) | Expr::Property(_) | Expr::Unary(_) | Expr::Match(_) => None,
+    }
+}
+
+// `value.cast => |"int"|` with a literal target has the target's type.
+fn infer_cast(call: &MethodCallExpr) -> Option<DataType> {
+    match call.args.as_slice() {
+        [target] if call.method == "cast" => match target.as_ref() {
+            Expr::StringLit(target) => match target.value.trim().to_ascii_lowercase().as_str() {
+                "int" => Some(DataType::Int),
+                "float" => Some(DataType::Float),
+                "bool" => Some(DataType::Bool),
+                "string" => Some(DataType::String),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Vector and matrix arithmetic, which has an `arr` on at least one side.
+fn linear_result(left: &DataType, right: &DataType) -> Option<DataType> {
+    match (left, right) {
+        (DataType::Array, DataType::Array | DataType::Int | DataType::Float)
+        | (DataType::Int | DataType::Float, DataType::Array) => Some(DataType::Array),
+        _ => None,
+    }
+}
+
+fn numeric_result(left: &DataType, right: &DataType) -> Option<DataType> {
+    match (left, right) {
+        (DataType::Int, DataType::Int) => Some(DataType::Int),
+        (DataType::Int | DataType::Float, DataType::Int | DataType::Float) => Some(DataType::Float),
+        _ => None,
+    }
+}
+
+// Collects the `return` statements of one function body, without descending
+// into nested function declarations.
+struct Returns<'a> {
+    values: Vec<Option<&'a Expr>>,
+    locals: Vec<(&'a str, DataType)>,
+}
+
+impl<'a> Returns<'a> {
+    fn collect(&mut self, body: &'a [Content]) {
+        for content in body {
+            if let Content::Statement(stmt) = content {
+                self.stmt(stmt);
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) {
+        match stmt {
+            Stmt::Return(ret) => self.values.push(match ret.value.as_deref() {
+                Some(Content::Expression(expr)) => Some(expr),
+                _ => None,
+            }),
+            Stmt::VarDecl(decl) => self.locals.push((&decl.ident, decl.type_.clone())),
+            Stmt::IfStmt(stmt) => {
+                self.collect(&stmt.body);
+                if let Some(alt) = &stmt.alt {
+                    self.collect(alt);
+                }
+            }
+            Stmt::ForStmt(stmt) => {
+                self.collect(&stmt.body);
+                if let Some(else_body) = &stmt.else_body {
+                    self.collect(else_body);
+                }
+            }
+            Stmt::ForIn(stmt) => {
+                self.collect(&stmt.body);
+                if let Some(else_body) = &stmt.else_body {
+                    self.collect(else_body);
+                }
+            }
+            Stmt::WhileStmt(stmt) => {
+                self.collect(&stmt.body);
+                if let Some(else_body) = &stmt.else_body {
+                    self.collect(else_body);
+                }
+            }
+            Stmt::Loop(stmt) => self.collect(&stmt.body),
+            Stmt::BlockStmt(stmt) => self.collect(&stmt.body),
+            Stmt::With(stmt) => self.collect(&stmt.body),
+            Stmt::Switch(stmt) => {
+                for body in stmt.bodies() {
+                    self.collect(body);
+                }
+            }
+            Stmt::TryCatchStmt(stmt) => {
+                self.collect(&stmt.try_block);
+                if let Some(catch) = &stmt.catch_block {
+                    self.collect(catch);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether any `return` in the body (outside nested functions) returns a value.
+pub fn returns_value(body: &[Content]) -> bool {
+    let mut returns = Returns { values: Vec::new(), locals: Vec::new() };
+    returns.collect(body);
+    returns.values.iter().any(Option::is_some)
+}
+
+/// Infers the return type of a function without a declared one: the type all
+/// of its `return` statements agree on, if any.
+pub fn infer_return_type(
+    params: &[Param],
+    body: &[Content],
+    env: &Environment,
+    depth: usize,
+) -> Option<DataType> {
+    let mut returns = Returns { values: Vec::new(), locals: Vec::new() };
+    returns.collect(body);
+
+    let mut scope = Environment::new_with_parent_capacity(env.clone(), params.len() + returns.locals.len());
+    for param in params {
+        scope.declare_ref_typed(&param.ident, Value::Void, param.type_.clone(), false);
+    }
+    // Locals from every block share one scope here; a name declared twice with
+    // different types is treated as unknown.
+    for (name, ty) in &returns.locals {
+        let conflicting = scope.variables.contains_key(*name) && scope.lookup_type(name).as_ref() != Some(ty);
+        let ty = if conflicting { DataType::Any } else { ty.clone() };
+        scope.declare_ref_typed(name, Value::Void, ty, false);
+    }
+
+    let mut inferred = None;
+    for value in &returns.values {
+        let ty = infer_at_depth((*value)?, &scope, depth)?;
+        match inferred {
+            None => inferred = Some(ty),
+            Some(ref prev) if *prev == ty => {}
+            Some(_) => return None,
+        }
+    }
+    inferred
+}
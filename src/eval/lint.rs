@@ -1,15 +1,24 @@
+use crate::ast::visit::{walk_expr, walk_stmt, Visitor};
 use crate::ast::*;
-use crate::environment::{Environment, FunctionValue, Value};
-use crate::errors::ZekkenError;
+use crate::environment::{enum_value, params_for_call, Environment, FunctionValue, Value};
+use crate::config::{self, LintLevel};
+use crate::diagnostics::{content_location, declare_shape};
+use crate::errors::{push_warning, ZekkenError};
+use crate::eval::include::is_declaration;
+use crate::eval::infer::{fits, infer_expr, returns_value, type_name};
+use crate::eval::pattern::{bound_names, is_irrefutable};
+use crate::eval::statement::expr_location;
 use crate::lexer::DataType;
 use crate::libraries::load_library;
+use crate::parser::Parser;
 use hashbrown::HashMap;
+use indexmap::IndexMap;
 use std::path::Path;
 use std::sync::Arc;
 
 #[inline]
 fn builtin_requires_at(name: &str) -> bool {
-    matches!(name, "println" | "input" | "parse_json" | "queue")
+    matches!(name, "println" | "print" | "flush" | "input" | "parse_json" | "queue" | "memstats" | "set_precision" | "format" | "diff")
 }
 
 fn dummy_value_for_type(ty: &DataType) -> Value {
@@ -18,9 +27,11 @@ fn dummy_value_for_type(ty: &DataType) -> Value {
         DataType::Float => Value::Float(0.0),
         DataType::String => Value::String(String::new()),
         DataType::Bool => Value::Boolean(false),
-        DataType::Object => Value::Object(HashMap::new()),
-        DataType::Array => Value::Array(Vec::new()),
+        DataType::Object => Value::Object(IndexMap::new()),
+        DataType::Array | DataType::ArrayOf(_) => Value::Array(Vec::new()),
+        DataType::Nullable(inner) => dummy_value_for_type(inner),
         DataType::Fn => Value::Function(FunctionValue {
+            name: Arc::from(""),
             params: Arc::new(Vec::new()),
             body: Arc::new(Vec::new()),
             return_type: None,
@@ -29,522 +40,575 @@ fn dummy_value_for_type(ty: &DataType) -> Value {
             capture_values: Arc::new(HashMap::new()),
             compiled_insts: None,
             compiled_reg_count: 0,
+            closure: None,
         }),
+        DataType::Decimal => Value::Decimal(crate::environment::decimal::Decimal::from_int(0)),
         DataType::Any => Value::Void,
     }
 }
 
-pub fn lint_expression(expr: &Expr, env: &Environment) -> Result<(), ZekkenError> {
-    match expr {
-        Expr::Identifier(ident) => {
-            // Look up the identifier in the environment
-            if env.lookup(&ident.name).is_none() {
-                return Err(ZekkenError::reference(
-                    &format!("Variable '{}' not found", &ident.name),
-                    "variable",
-                    ident.location.line,
-                    ident.location.column,
-                ));
-            }
-        },
-        Expr::Binary(binary) => {
-            lint_expression(&binary.left, env)?;
-            lint_expression(&binary.right, env)?;
+/// Routes a diagnostic through the `[lints]` level configured for `lint`:
+/// denied lints become errors, warned ones are collected as warnings.
+pub fn report_lint(lint: &'static str, error: ZekkenError, errors: &mut Vec<ZekkenError>) {
+    match config::lint_level(lint) {
+        LintLevel::Allow => {}
+        LintLevel::Warn => push_warning(lint, error),
+        LintLevel::Deny => errors.push(error),
+    }
+}
+
+/// Scope-aware lint pass. Statements that open a scope (function bodies,
+/// branches, loops, try/catch) push a child environment holding dummy values
+/// for the names they bind; everything else is handled by the generic walker.
+struct Linter<'a> {
+    root: &'a Environment,
+    scopes: Vec<Environment>,
+    // Declared return type of each enclosing function, innermost last.
+    return_types: Vec<Option<DataType>>,
+    // Number of enclosing `try` blocks in the current function body.
+    try_depth: usize,
+    errors: &'a mut Vec<ZekkenError>,
+}
+
+impl<'a> Linter<'a> {
+    fn new(root: &'a Environment, errors: &'a mut Vec<ZekkenError>) -> Self {
+        Self { root, scopes: Vec::new(), return_types: Vec::new(), try_depth: 0, errors }
+    }
+
+    fn env(&self) -> &Environment {
+        self.scopes.last().unwrap_or(self.root)
+    }
+
+    fn push_scope(&mut self, capacity: usize) {
+        let scope = Environment::new_with_parent_capacity(self.env().clone(), capacity);
+        self.scopes.push(scope);
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &str, value: Value, ty: DataType, constant: bool) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.declare_ref_typed(name, value, ty, constant);
         }
-        Expr::Call(call) => {
-            // Lint callee.
-            if let Expr::Identifier(ident) = call.callee.as_ref() {
-                let (val, kind) = env.lookup_with_kind(&ident.name);
-                let is_callable = matches!(
-                    val.as_ref(),
-                    Some(Value::Function(_)) | Some(Value::NativeFunction(_))
-                );
-
-                if !is_callable {
-                    if val.is_some() {
-                        return Err(ZekkenError::type_error(
-                            "Cannot call non-function value",
-                            "function",
-                            "non-function",
-                            call.location.line,
-                            call.location.column,
-                        ));
-                    } else {
-                        return Err(ZekkenError::reference(
-                            &format!("Function '{}' not found", &ident.name),
-                            "function",
-                            call.location.line,
-                            call.location.column,
-                        ));
-                    }
-                }
+    }
 
-                // Enforce built-ins requiring '@' prefix.
-                if builtin_requires_at(&ident.name) && !call.is_native {
-                    return Err(ZekkenError::runtime(
-                        &format!("{} is a built-in; call it with '@{} => |...|'", ident.name, ident.name),
-                        call.location.line,
-                        call.location.column,
-                        None,
-                    ));
-                }
-                let _ = kind; // reserved for future, more precise diagnostics
-            } else {
-                // Calling a member (e.g. `"x".cast => |"int"|`, `math.sqrt => |9|`):
-                // lint the object, but treat the member name as a literal (not a variable).
-                if let Expr::Member(member) = call.callee.as_ref() {
-                    lint_expression(&member.object, env)?;
-                } else {
-                    lint_expression(&call.callee, env)?;
-                }
-            }
+    fn report(&mut self, lint: &'static str, error: ZekkenError) {
+        report_lint(lint, error, self.errors);
+    }
 
-            // Always lint arguments.
-            for arg in &call.args {
-                lint_expression(arg, env)?;
-            }
-        },
-        Expr::Assign(assign) => {
-            // Check if target is assignable
-            match *assign.left {
-                Expr::Identifier(ref ident) => {
-                    let (_, kind) = env.lookup_with_kind(&ident.name);
-                    if kind == Some("constant") {
-                        return Err(ZekkenError::runtime(
-                            &format!("Cannot assign to constant '{}'", &ident.name),
-                            assign.location.line,
-                            assign.location.column,
-                            None,
-                        ));
-                    }
-                },
-                Expr::Member(ref member) => {
-                    lint_expression(&member.object, env)?;
-                }
-                _ => {
-                    return Err(ZekkenError::type_error(
-                        "Invalid assignment target",
-                        "identifier or member access",
-                        "other",
-                        assign.location.line,
-                        assign.location.column,
-                    ));
-                }
-            }
-            lint_expression(&assign.right, env)?;
-        },
-        Expr::Member(member) => {
-            lint_expression(&member.object, env)?;
-            // Only bracket/computed member access should lint the property expression.
-            // Dot member access (`obj.key`) treats the identifier as a literal key.
-            if member.is_method {
-                lint_expression(&member.property, env)?;
-            }
-        },
-        Expr::ArrayLit(array) => {
-            for el in &array.elements {
-                lint_expression(el, env)?;
+    fn scoped_block(&mut self, body: &[Content]) {
+        self.push_scope(8);
+        self.visit_block(body);
+        self.pop_scope();
+    }
+
+    fn function_body(&mut self, params: &[Param], body: &[Content], return_type: Option<DataType>) {
+        self.push_scope(params.len() + 8);
+        for param in params {
+            self.declare(&param.ident, dummy_value_for_type(&param.type_), param.type_.clone(), false);
+        }
+        self.return_types.push(return_type);
+        // A `try` around the declaration does not guard the body once called.
+        let try_depth = std::mem::take(&mut self.try_depth);
+        self.visit_block(body);
+        self.try_depth = try_depth;
+        self.return_types.pop();
+        self.pop_scope();
+    }
+
+    // Report `expr` when its inferred type is known and differs from `expected`,
+    // unless a `try` block would catch the resulting runtime error.
+    // Under `--typed` an expression whose type cannot be inferred is an error too.
+    // `context` completes "Type mismatch ...", e.g. "for parameter 'x'".
+    fn check_type(&mut self, expr: &Expr, expected: DataType, context: &str) {
+        if expected == DataType::Any {
+            return;
+        }
+        if let DataType::Nullable(inner) = &expected {
+            if !matches!(expr, Expr::NullLit(_)) {
+                self.check_type(expr, (**inner).clone(), context);
             }
+            return;
         }
-        Expr::ObjectLit(object) => {
-            for prop in &object.properties {
-                lint_expression(&prop.value, env)?;
+        // The elements of a literal are each checked against `arr<T>`'s `T`.
+        if let (DataType::ArrayOf(element), Expr::ArrayLit(array)) = (&expected, expr) {
+            for (i, item) in array.elements.iter().enumerate() {
+                self.check_type(item, (**element).clone(), &format!("{} at element {}", context, i));
             }
+            return;
+        }
+        let loc = expr_location(expr);
+        match infer_expr(expr, self.env()) {
+            Some(found) if !fits(&found, &expected) && self.try_depth == 0 => self.report("type-mismatch", ZekkenError::type_error(
+                &format!("Type mismatch {}", context),
+                &format!("{:?}", expected),
+                type_name(found),
+                loc.line,
+                loc.column,
+            )),
+            None if config::typed() => self.errors.push(ZekkenError::type_error(
+                &format!("Cannot infer a type {} (required by --typed)", context),
+                &format!("{:?}", expected),
+                "unknown",
+                loc.line,
+                loc.column,
+            )),
+            _ => {}
         }
-        // Other expression types like literals don't need linting
-        _ => {}
     }
-    Ok(())
-}
 
-pub fn lint_statement(stmt: &Stmt, env: &Environment) -> Result<(), ZekkenError> {
-    fn lint_contents_seq(contents: &[Box<Content>], env: &mut Environment) -> Result<(), ZekkenError> {
-        for content in contents {
-            match content.as_ref() {
-                Content::Expression(expr) => lint_expression(expr, env)?,
-                Content::Statement(stmt) => {
-                    // Inside a function/lambda body, model sequential local bindings so
-                    // later expressions can reference earlier `let` declarations.
-                    if let Stmt::VarDecl(var_decl) = stmt.as_ref() {
-                        if let Some(content) = &var_decl.value {
-                            match content {
-                                Content::Expression(expr) => lint_expression(expr, env)?,
-                                Content::Statement(stmt) => lint_statement(stmt, env)?,
+    fn lint_call(&mut self, call: &CallExpr) {
+        match call.callee.as_ref() {
+            Expr::Identifier(ident) => match self.env().lookup_ref(&ident.name) {
+                // A `fn` parameter or variable: which function it holds, and
+                // so what it takes, is only known when the call runs.
+                Some(Value::Function(func)) if func.name.is_empty() => {}
+                Some(Value::Function(func)) => {
+                    let params = if call.arg_names.is_empty() {
+                        Arc::clone(&func.params)
+                    } else {
+                        match params_for_call(&func.params, &call.arg_names, &ident.name) {
+                            Ok(params) => Arc::new(params),
+                            Err(msg) => {
+                                self.errors.push(ZekkenError::runtime(
+                                    &msg,
+                                    call.location.line,
+                                    call.location.column,
+                                    Some("argument mismatch"),
+                                ));
+                                for arg in &call.args {
+                                    self.visit_expr(arg);
+                                }
+                                return;
                             }
                         }
-                        env.declare_ref_typed(
-                            var_decl.ident.as_str(),
-                            dummy_value_for_type(&var_decl.type_),
-                            var_decl.type_,
-                            var_decl.constant,
-                        );
-                    } else {
-                        lint_statement(stmt, env)?;
+                    };
+                    if config::typed() {
+                        self.check_arity(call, &ident.name, &params);
+                    }
+                    for (arg, param) in call.args.iter().zip(params.iter()) {
+                        self.check_type(arg, param.type_.clone(), &format!("for parameter '{}' of '{}'", param.ident, ident.name));
                     }
                 }
-            }
+                Some(Value::NativeFunction(_)) => {
+                    // Enforce built-ins requiring '@' prefix.
+                    if builtin_requires_at(&ident.name) && !call.is_native {
+                        self.report("builtin-call-syntax", ZekkenError::runtime(
+                            &format!(
+                                "{} is a built-in; call it with '@{} => |...|'",
+                                ident.name, ident.name
+                            ),
+                            call.location.line,
+                            call.location.column,
+                            None,
+                        ).with_fix("add '@' before this"));
+                    }
+                }
+                // An `any` binding may well hold a function.
+                Some(_) if self.env().lookup_type(&ident.name) == Some(DataType::Any) => {}
+                Some(_) => self.report("not-callable", ZekkenError::type_error(
+                    "Cannot call non-function value",
+                    "function",
+                    "non-function",
+                    call.location.line,
+                    call.location.column,
+                )),
+                None => self.report("undefined-function", ZekkenError::reference(
+                    &format!("Function '{}' not found", ident.name),
+                    "function",
+                    call.location.line,
+                    call.location.column,
+                ).with_help(format!("declare it with `func {} |...| {{ ... }}`", ident.name))),
+            },
+            callee => self.visit_expr(callee),
+        }
+
+        for arg in &call.args {
+            self.visit_expr(arg);
         }
-        Ok(())
     }
 
-    match stmt {
-        Stmt::VarDecl(var_decl) => {
-            if let Some(content) = &var_decl.value {
-                match content {
-                    Content::Expression(expr) => lint_expression(expr, env)?,
-                    Content::Statement(stmt) => lint_statement(stmt, env)?,
-                }
-            }
-        },
-        Stmt::FuncDecl(func_decl) => {
-            // Lint function body in a dedicated scope that includes parameters.
-            let mut fn_env = Environment::new_with_parent_capacity(env.clone(), func_decl.params.len() + 8);
-            for param in func_decl.params.iter() {
-                fn_env.declare_ref_typed(param.ident.as_str(), dummy_value_for_type(&param.type_), param.type_, false);
+    fn check_arity(&mut self, call: &CallExpr, name: &str, params: &[Param]) {
+        if call.args.len() > params.len() {
+            self.errors.push(ZekkenError::runtime(
+                &format!("Too many arguments for '{}': expected at most {}, found {}", name, params.len(), call.args.len()),
+                call.location.line,
+                call.location.column,
+                Some("argument mismatch"),
+            ));
+        }
+        for param in params.iter().skip(call.args.len()) {
+            if param.default_value.is_none() {
+                self.errors.push(ZekkenError::runtime(
+                    &format!("Missing required argument '{}' for '{}'", param.ident, name),
+                    call.location.line,
+                    call.location.column,
+                    Some("argument mismatch"),
+                ));
             }
+        }
+    }
 
-            lint_contents_seq(&func_decl.body, &mut fn_env)?;
-        },
-        Stmt::Lambda(lambda) => {
-            // Same parameter scoping rules as functions.
-            let mut fn_env = Environment::new_with_parent_capacity(env.clone(), lambda.params.len() + 8);
-            for param in lambda.params.iter() {
-                fn_env.declare_ref_typed(param.ident.as_str(), dummy_value_for_type(&param.type_), param.type_, false);
-            }
-            lint_contents_seq(&lambda.body, &mut fn_env)?;
+    // Under `--typed`, a function that returns a value must say what type.
+    fn require_return_type(&mut self, name: &str, return_type: Option<DataType>, body: &[Content], location: &Location) {
+        if config::typed() && return_type.is_none() && returns_value(body) {
+            self.errors.push(ZekkenError::type_error(
+                &format!("Function '{}' returns a value but declares no return type (required by --typed)", name),
+                "declared return type",
+                "none",
+                location.line,
+                location.column,
+            ));
         }
-        Stmt::IfStmt(if_stmt) => {
-            lint_expression(&if_stmt.test, env)?;
-            let mut body_env = Environment::new_with_parent_capacity(env.clone(), 8);
-            lint_contents_seq(&if_stmt.body, &mut body_env)?;
-            if let Some(alt) = &if_stmt.alt {
-                let mut alt_env = Environment::new_with_parent_capacity(env.clone(), 8);
-                lint_contents_seq(alt, &mut alt_env)?;
-            }
-        },
-        Stmt::ForStmt(for_stmt) => {
-            if let Some(init) = &for_stmt.init {
-                // `for |...| in <collection> { ... }` is represented as a `ForStmt` whose `init`
-                // is a VarDecl with `ident` containing one/two loop identifiers and `value`
-                // containing the collection expression.
-                if for_stmt.test.is_none() && for_stmt.update.is_none() {
-                    if let Stmt::VarDecl(var_decl) = init.as_ref() {
-                        if let Some(Content::Expression(expr)) = var_decl.value.as_ref() {
-                            // Lint the collection in the outer env.
-                            lint_expression(expr, env)?;
-
-                            // Lint the body in a scope that contains the loop vars.
-                            let idents: Vec<String> = var_decl
-                                .ident
-                                .split(',')
-                                .map(|s| s.trim().to_string())
-                                .filter(|s| !s.is_empty())
-                                .collect();
-
-                            let mut loop_env = Environment::new_with_parent_capacity(env.clone(), idents.len().max(1) + 8);
-                            for ident in idents.iter() {
-                                loop_env.declare_ref(ident.as_str(), Value::Void, false);
-                            }
-                            lint_contents_seq(&for_stmt.body, &mut loop_env)?;
-                            return Ok(());
-                        }
-                    }
-                }
+    }
 
-                lint_statement(init, env)?;
-            }
-            if let Some(test) = &for_stmt.test {
-                lint_expression(test, env)?;
-            }
-            if let Some(update) = &for_stmt.update {
-                lint_expression(update, env)?;
-            }
-            let mut body_env = Environment::new_with_parent_capacity(env.clone(), 8);
-            lint_contents_seq(&for_stmt.body, &mut body_env)?;
-        },
-        Stmt::WhileStmt(while_stmt) => {
-            lint_expression(&while_stmt.test, env)?;
-            let mut body_env = Environment::new_with_parent_capacity(env.clone(), 8);
-            lint_contents_seq(&while_stmt.body, &mut body_env)?;
-        },
-        Stmt::TryCatchStmt(try_catch) => {
-            let mut try_env = Environment::new_with_parent_capacity(env.clone(), 8);
-            lint_contents_seq(&try_catch.try_block, &mut try_env)?;
-            if let Some(catch_block) = &try_catch.catch_block {
-                let mut catch_env = Environment::new_with_parent_capacity(env.clone(), 8);
-                if let Some(name) = try_catch.catch_param.as_deref().filter(|name| !name.is_empty() && *name != "_") {
-                    catch_env.declare(name.to_string(), crate::environment::Value::Void, false);
+    fn lint_assign(&mut self, assign: &AssignExpr) {
+        match assign.left.as_ref() {
+            Expr::Identifier(ident) => {
+                let (value, kind) = self.env().lookup_with_kind(&ident.name);
+                if value.is_none() {
+                    let ty = infer_expr(&assign.right, self.env()).map_or("any", type_name);
+                    self.report("undefined-variable", ZekkenError::reference(
+                        &format!("Variable '{}' not found", ident.name),
+                        "variable",
+                        ident.location.line,
+                        ident.location.column,
+                    ).with_help(format!("declare it with `let {}: {} = ...;`", ident.name, ty)));
+                } else if kind == Some("constant") {
+                    self.report("const-assign", ZekkenError::runtime(
+                        &format!("Cannot assign to constant '{}'", ident.name),
+                        assign.location.line,
+                        assign.location.column,
+                        None,
+                    ).with_help(format!("declare '{}' with 'let' instead of 'const' so it can change", ident.name)));
+                } else if assign.operator == "=" {
+                    let expected = self.env().lookup_type(&ident.name).unwrap_or(DataType::Any);
+                    self.check_type(&assign.right, expected, &format!("in assignment to '{}'", ident.name));
                 }
-                lint_contents_seq(catch_block, &mut catch_env)?;
             }
-        },
-        _ => {}
+            Expr::Member(member) => self.visit_expr(&member.object),
+            _ => self.report("invalid-assign-target", ZekkenError::type_error(
+                "Invalid assignment target",
+                "identifier or member access",
+                "other",
+                assign.location.line,
+                assign.location.column,
+            )),
+        }
+        self.visit_expr(&assign.right);
     }
-    Ok(())
-}
 
-pub fn collect_lint_expression(expr: &Expr, env: &Environment, errors: &mut Vec<ZekkenError>) {
-    match expr {
-        Expr::Identifier(ident) => {
-            if env.lookup_ref(&ident.name).is_none() {
-                errors.push(ZekkenError::reference(
-                    &format!("Variable '{}' not found", ident.name),
-                    "variable",
-                    ident.location.line,
-                    ident.location.column,
-                ));
-            }
-        }
-        Expr::Unary(unary) => collect_lint_expression(&unary.operand, env, errors),
-        Expr::Binary(binary) => {
-            collect_lint_expression(&binary.left, env, errors);
-            collect_lint_expression(&binary.right, env, errors);
+    // A match whose arms name variants of an enum must cover every variant
+    // unless it has a `_` arm.
+    fn check_exhaustive(&mut self, expr: &MatchExpr) {
+        // Guarded arms may fall through, so they cover nothing.
+        let unguarded = || expr.arms.iter().filter(|arm| arm.guard.is_none());
+        if unguarded().any(|arm| is_irrefutable(&arm.pattern)) {
+            return;
         }
-        Expr::Call(call) => {
-            if let Expr::Identifier(ident) = call.callee.as_ref() {
-                match env.lookup_ref(&ident.name) {
-                    Some(Value::Function(_)) | Some(Value::NativeFunction(_)) => {
-                        if builtin_requires_at(&ident.name) && !call.is_native {
-                            errors.push(ZekkenError::runtime(
-                                &format!(
-                                    "{} is a built-in; call it with '@{} => |...|'",
-                                    ident.name, ident.name
-                                ),
-                                call.location.line,
-                                call.location.column,
-                                None,
-                            ));
-                        }
-                    }
-                    Some(_) => errors.push(ZekkenError::type_error(
-                        "Cannot call non-function value",
-                        "function",
-                        "non-function",
-                        call.location.line,
-                        call.location.column,
-                    )),
-                    None => errors.push(ZekkenError::reference(
-                        &format!("Function '{}' not found", ident.name),
-                        "function",
-                        call.location.line,
-                        call.location.column,
-                    )),
-                }
-            } else if let Expr::Member(member) = call.callee.as_ref() {
-                collect_lint_expression(&member.object, env, errors);
-            } else {
-                collect_lint_expression(&call.callee, env, errors);
+        let mut namespace = None;
+        let mut covered = Vec::new();
+        let alternatives = unguarded().flat_map(|arm| match &arm.pattern {
+            Pattern::Or(alternatives) => alternatives.iter().collect(),
+            pattern => vec![pattern],
+        });
+        for pattern in alternatives {
+            let Pattern::Value(value) = pattern else { continue };
+            let Expr::Member(member) = value.as_ref() else { continue };
+            let (Expr::Identifier(owner), Expr::Identifier(variant)) = (member.object.as_ref(), member.property.as_ref()) else {
+                continue;
+            };
+            let Some(Value::Object(variants)) = self.env().lookup_ref(&owner.name) else { continue };
+            if variants.get(&variant.name).and_then(Value::enum_variant).is_some() {
+                covered.push(variant.name.clone());
+                namespace = Some((owner.name.clone(), variants.clone()));
             }
+        }
+        let Some((name, variants)) = namespace else { return };
 
-            for arg in &call.args {
-                collect_lint_expression(arg, env, errors);
-            }
+        let missing: Vec<String> = variants
+            .keys()
+            .filter(|key| !covered.contains(key))
+            .map(|key| format!("{}.{}", name, key))
+            .collect();
+        if !missing.is_empty() {
+            self.report("non-exhaustive-match", ZekkenError::type_error(
+                &format!("Non-exhaustive match on enum '{}'", name),
+                "every variant or a '_' arm",
+                &format!("missing {}", missing.join(", ")),
+                expr.location.line,
+                expr.location.column,
+            ));
         }
-        Expr::Assign(assign) => {
-            match assign.left.as_ref() {
-                Expr::Identifier(ident) => {
-                    let (value, kind) = env.lookup_with_kind(&ident.name);
-                    if value.is_none() {
-                        errors.push(ZekkenError::reference(
-                            &format!("Variable '{}' not found", ident.name),
-                            "variable",
-                            ident.location.line,
-                            ident.location.column,
-                        ));
-                    } else if kind == Some("constant") {
-                        errors.push(ZekkenError::runtime(
-                            &format!("Cannot assign to constant '{}'", ident.name),
-                            assign.location.line,
-                            assign.location.column,
-                            None,
-                        ));
-                    }
-                }
-                Expr::Member(member) => collect_lint_expression(&member.object, env, errors),
-                _ => errors.push(ZekkenError::type_error(
-                    "Invalid assignment target",
-                    "identifier or member access",
-                    "other",
-                    assign.location.line,
-                    assign.location.column,
-                )),
+    }
+
+    fn lint_c_for(&mut self, stmt: &ForStmt) {
+        self.push_scope(8);
+        if let Some(init) = &stmt.init {
+            self.visit_stmt(init);
+            if let Stmt::VarDecl(decl) = init.as_ref() {
+                self.declare(&decl.ident, dummy_value_for_type(&decl.type_), decl.type_.clone(), decl.constant);
             }
-            collect_lint_expression(&assign.right, env, errors);
         }
-        Expr::Member(member) => {
-            collect_lint_expression(&member.object, env, errors);
-            if member.is_method {
-                collect_lint_expression(&member.property, env, errors);
-            }
+        if let Some(test) = &stmt.test {
+            self.visit_expr(test);
         }
-        Expr::ArrayLit(array) => {
-            for element in &array.elements {
-                collect_lint_expression(element, env, errors);
-            }
+        if let Some(update) = &stmt.update {
+            self.visit_expr(update);
         }
-        Expr::ObjectLit(object) => {
-            for property in &object.properties {
-                collect_lint_expression(&property.value, env, errors);
-            }
+        self.visit_block(&stmt.body);
+        self.pop_scope();
+    }
+
+    fn lint_for(&mut self, stmt: &ForInStmt) {
+        self.push_scope(8);
+        self.visit_expr(&stmt.iterable);
+        // Every variable over a range holds an int: the index or the number.
+        let over_range = matches!(stmt.iterable.as_ref(), Expr::Binary(range) if range.operator == "..");
+        for ident in &stmt.idents {
+            let value = if over_range { Value::Int(0) } else { Value::Void };
+            self.declare(ident, value, DataType::Any, false);
         }
-        Expr::IntLit(_)
-        | Expr::FloatLit(_)
-        | Expr::StringLit(_)
-        | Expr::BoolLit(_)
-        | Expr::Property(_) => {}
+        self.visit_block(&stmt.body);
+        self.pop_scope();
     }
 }
 
-fn collect_lint_contents(
-    contents: &[Box<Content>],
-    env: &mut Environment,
-    errors: &mut Vec<ZekkenError>,
-) {
-    for content in contents {
-        match content.as_ref() {
-            Content::Expression(expr) => collect_lint_expression(expr, env, errors),
-            Content::Statement(stmt) => {
-                if let Stmt::VarDecl(decl) = stmt.as_ref() {
-                    if let Some(value) = &decl.value {
-                        match value {
-                            Content::Expression(expr) => collect_lint_expression(expr, env, errors),
-                            Content::Statement(stmt) => collect_lint_statement(stmt, env, errors),
-                        }
+impl Visitor for Linter<'_> {
+    // Inside a scope, model sequential local bindings so later expressions can
+    // reference earlier `let` declarations.
+    fn visit_block(&mut self, body: &[Content]) {
+        // Functions declared in the block can be called from anywhere in it,
+        // including their own bodies.
+        if let Some(scope) = self.scopes.last_mut() {
+            for content in body {
+                if let Content::Statement(stmt) = content {
+                    if matches!(stmt.as_ref(), Stmt::FuncDecl(_) | Stmt::Lambda(_)) {
+                        declare_shape(content, scope);
                     }
-                    env.declare_ref_typed(
-                        &decl.ident,
-                        dummy_value_for_type(&decl.type_),
-                        decl.type_,
-                        decl.constant,
-                    );
-                } else {
-                    collect_lint_statement(stmt, env, errors);
                 }
             }
         }
-    }
-}
-
-pub fn collect_lint_statement(
-    stmt: &Stmt,
-    env: &Environment,
-    errors: &mut Vec<ZekkenError>,
-) {
-    match stmt {
-        Stmt::Program(program) => {
-            let mut program_env = env.clone();
-            collect_lint_contents(&program.content, &mut program_env, errors);
-        }
-        Stmt::VarDecl(decl) => {
-            if let Some(value) = &decl.value {
-                match value {
-                    Content::Expression(expr) => collect_lint_expression(expr, env, errors),
-                    Content::Statement(stmt) => collect_lint_statement(stmt, env, errors),
+        for content in body {
+            self.visit_content(content);
+            if let Content::Statement(stmt) = content {
+                match stmt.as_ref() {
+                    Stmt::VarDecl(decl) => {
+                        self.declare(&decl.ident, dummy_value_for_type(&decl.type_), decl.type_.clone(), decl.constant);
+                    }
+                    Stmt::EnumDecl(decl) => {
+                        self.declare(&decl.ident, enum_value(&decl.ident, &decl.variants), DataType::Object, true);
+                    }
+                    _ => {}
                 }
             }
         }
-        Stmt::FuncDecl(decl) => {
-            let mut fn_env = Environment::new_with_parent_capacity(env.clone(), decl.params.len() + 8);
-            for param in &decl.params {
-                fn_env.declare_ref_typed(
-                    &param.ident,
-                    dummy_value_for_type(&param.type_),
-                    param.type_,
-                    false,
-                );
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Program(program) => self.scoped_block(&program.content),
+            Stmt::FuncDecl(decl) => {
+                self.require_return_type(&decl.ident, decl.return_type.clone(), &decl.body, &decl.location);
+                self.function_body(&decl.params, &decl.body, decl.return_type.clone());
+            }
+            Stmt::Lambda(decl) => {
+                self.require_return_type(&decl.ident, decl.return_type.clone(), &decl.body, &decl.location);
+                self.function_body(&decl.params, &decl.body, decl.return_type.clone());
+            }
+            Stmt::VarDecl(decl) => {
+                if let Some(Content::Expression(value)) = &decl.value {
+                    self.visit_expr(value);
+                    // `for |a, b| in ...` bindings reuse VarDecl; they carry no declared type.
+                    if !decl.ident.contains(',') {
+                        self.check_type(value, decl.type_.clone(), &format!("in variable declaration '{}'", decl.ident));
+                    }
+                } else {
+                    walk_stmt(self, stmt);
+                }
             }
-            collect_lint_contents(&decl.body, &mut fn_env, errors);
-        }
-        Stmt::Lambda(decl) => {
-            let mut fn_env = Environment::new_with_parent_capacity(env.clone(), decl.params.len() + 8);
-            for param in &decl.params {
-                fn_env.declare_ref_typed(
-                    &param.ident,
-                    dummy_value_for_type(&param.type_),
-                    param.type_,
-                    false,
-                );
+            Stmt::Return(ret) => {
+                if let Some(Content::Expression(value)) = ret.value.as_deref() {
+                    self.visit_expr(value);
+                    if let Some(Some(expected)) = self.return_types.last().cloned() {
+                        self.check_type(value, expected, "in function return value");
+                    }
+                } else {
+                    walk_stmt(self, stmt);
+                }
             }
-            collect_lint_contents(&decl.body, &mut fn_env, errors);
-        }
-        Stmt::ObjectDecl(decl) => {
-            for property in &decl.properties {
-                collect_lint_expression(&property.value, env, errors);
+            Stmt::IfStmt(stmt) => {
+                self.visit_expr(&stmt.test);
+                self.scoped_block(&stmt.body);
+                if let Some(alt) = &stmt.alt {
+                    self.scoped_block(alt);
+                }
             }
-        }
-        Stmt::IfStmt(stmt) => {
-            collect_lint_expression(&stmt.test, env, errors);
-            let mut body_env = Environment::new_with_parent_capacity(env.clone(), 8);
-            collect_lint_contents(&stmt.body, &mut body_env, errors);
-            if let Some(alt) = &stmt.alt {
-                let mut alt_env = Environment::new_with_parent_capacity(env.clone(), 8);
-                collect_lint_contents(alt, &mut alt_env, errors);
+            Stmt::ForStmt(stmt) => {
+                self.lint_c_for(stmt);
+                if let Some(else_body) = &stmt.else_body {
+                    self.scoped_block(else_body);
+                }
             }
-        }
-        Stmt::ForStmt(stmt) => {
-            let mut body_env = Environment::new_with_parent_capacity(env.clone(), 8);
-            if let Some(init) = &stmt.init {
-                if stmt.test.is_none() && stmt.update.is_none() {
-                    if let Stmt::VarDecl(decl) = init.as_ref() {
-                        if let Some(Content::Expression(collection)) = &decl.value {
-                            collect_lint_expression(collection, env, errors);
-                        }
-                        for ident in decl.ident.split(',').map(str::trim).filter(|name| !name.is_empty()) {
-                            body_env.declare_ref(ident, Value::Void, false);
-                        }
-                    }
-                } else {
-                    collect_lint_statement(init, env, errors);
+            Stmt::ForIn(stmt) => {
+                self.lint_for(stmt);
+                if let Some(else_body) = &stmt.else_body {
+                    self.scoped_block(else_body);
                 }
             }
-            if let Some(test) = &stmt.test {
-                collect_lint_expression(test, &body_env, errors);
+            Stmt::WhileStmt(stmt) => {
+                self.visit_expr(&stmt.test);
+                self.scoped_block(&stmt.body);
+                if let Some(else_body) = &stmt.else_body {
+                    self.scoped_block(else_body);
+                }
             }
-            if let Some(update) = &stmt.update {
-                collect_lint_expression(update, &body_env, errors);
+            Stmt::Loop(stmt) => self.scoped_block(&stmt.body),
+            Stmt::Switch(stmt) => {
+                self.visit_expr(&stmt.subject);
+                let subject = infer_expr(&stmt.subject, self.env());
+                if let Some(found) = subject.clone().filter(|ty| !matches!(ty, DataType::Int | DataType::String)) {
+                    let loc = expr_location(&stmt.subject);
+                    self.report("type-mismatch", ZekkenError::type_error(
+                        "Switch value must be an int or a string",
+                        "int or string",
+                        type_name(found),
+                        loc.line,
+                        loc.column,
+                    ));
+                }
+                for value in stmt.cases.iter().flat_map(|case| &case.values) {
+                    self.visit_expr(value);
+                    // A case of another type than the value can never be taken.
+                    if let Some(expected @ (DataType::Int | DataType::String)) = &subject {
+                        self.check_type(value, expected.clone(), "in switch case");
+                    }
+                }
+                for body in stmt.bodies() {
+                    self.scoped_block(body);
+                }
             }
-            collect_lint_contents(&stmt.body, &mut body_env, errors);
-        }
-        Stmt::WhileStmt(stmt) => {
-            collect_lint_expression(&stmt.test, env, errors);
-            let mut body_env = Environment::new_with_parent_capacity(env.clone(), 8);
-            collect_lint_contents(&stmt.body, &mut body_env, errors);
-        }
-        Stmt::TryCatchStmt(stmt) => {
-            let mut try_env = Environment::new_with_parent_capacity(env.clone(), 8);
-            collect_lint_contents(&stmt.try_block, &mut try_env, errors);
-            if let Some(catch) = &stmt.catch_block {
-                let mut catch_env = Environment::new_with_parent_capacity(env.clone(), 8);
-                if let Some(name) = stmt.catch_param.as_deref().filter(|name| !name.is_empty() && *name != "_") {
-                    catch_env.declare_ref(name, Value::Object(HashMap::new()), false);
+            Stmt::TryCatchStmt(stmt) => {
+                self.try_depth += 1;
+                self.scoped_block(&stmt.try_block);
+                self.try_depth -= 1;
+                if let Some(catch) = &stmt.catch_block {
+                    self.push_scope(8);
+                    if let Some(name) = stmt.catch_param.as_deref().filter(|name| !name.is_empty() && *name != "_") {
+                        self.declare(name, Value::Object(IndexMap::new()), DataType::Object, false);
+                    }
+                    self.visit_block(catch);
+                    self.pop_scope();
                 }
-                collect_lint_contents(catch, &mut catch_env, errors);
             }
-        }
-        Stmt::BlockStmt(stmt) => {
-            let mut body_env = Environment::new_with_parent_capacity(env.clone(), 8);
-            collect_lint_contents(&stmt.body, &mut body_env, errors);
-        }
-        Stmt::Return(stmt) => {
-            if let Some(value) = &stmt.value {
-                match value.as_ref() {
-                    Content::Expression(expr) => collect_lint_expression(expr, env, errors),
-                    Content::Statement(stmt) => collect_lint_statement(stmt, env, errors),
+            Stmt::BlockStmt(stmt) => self.scoped_block(&stmt.body),
+            Stmt::With(stmt) => {
+                self.visit_expr(&stmt.resource);
+                self.push_scope(8);
+                self.declare(&stmt.ident, Value::Void, DataType::Any, false);
+                self.visit_block(&stmt.body);
+                self.pop_scope();
+            }
+            Stmt::Use(stmt) => {
+                if let Err(error) = lint_use(stmt) {
+                    self.report("unknown-library", error);
                 }
             }
-        }
-        Stmt::Use(stmt) => {
-            if let Err(error) = lint_use(stmt) {
-                errors.push(error);
+            Stmt::Include(stmt) => {
+                if let Err(error) = lint_include(stmt) {
+                    self.report("missing-include", error);
+                }
             }
+            _ => walk_stmt(self, stmt),
         }
-        Stmt::Include(stmt) => {
-            if let Err(error) = lint_include(stmt) {
-                errors.push(error);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(ident) => {
+                if self.env().lookup_ref(&ident.name).is_none() {
+                    self.report("undefined-variable", ZekkenError::reference(
+                        &format!("Variable '{}' not found", ident.name),
+                        "variable",
+                        ident.location.line,
+                        ident.location.column,
+                    ).with_help(format!("declare it before use with `let {}: any = ...;`", ident.name)));
+                }
             }
+            Expr::Call(call) => self.lint_call(call),
+            Expr::Match(expr) => {
+                self.visit_expr(&expr.subject);
+                for arm in &expr.arms {
+                    self.visit_pattern(&arm.pattern);
+                    let mut names = Vec::new();
+                    bound_names(&arm.pattern, &mut names);
+                    self.push_scope(names.len() + 8);
+                    for name in &names {
+                        self.declare(name, Value::Void, DataType::Any, false);
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.visit_expr(guard);
+                    }
+                    self.visit_block(&arm.body);
+                    self.pop_scope();
+                }
+                self.check_exhaustive(expr);
+            }
+            Expr::Assign(assign) => self.lint_assign(assign),
+            Expr::Lambda(lambda) => self.function_body(&lambda.params, &lambda.body, None),
+            Expr::Unary(unary) if unary.operator == "!" => {
+                self.check_type(&unary.operand, DataType::Bool, "for the operand of '!'");
+                walk_expr(self, expr);
+            }
+            Expr::Binary(range) if range.operator == ".." => {
+                self.check_type(&range.left, DataType::Int, "for the start of a range");
+                self.check_type(&range.right, DataType::Int, "for the end of a range");
+                walk_expr(self, expr);
+            }
+            // An interpolation that fails at run time is printed as written,
+            // which `{name}`-style placeholders rely on, so names in it may be
+            // undefined.
+            Expr::TemplateLit(_) => {}
+            _ => walk_expr(self, expr),
         }
-        Stmt::Export(_) => {}
     }
+
+    // Default values are evaluated in the function scope at call time, which
+    // the lint pass does not model.
+    fn visit_param(&mut self, _param: &Param) {}
+}
+
+fn first_error(errors: Vec<ZekkenError>) -> Result<(), ZekkenError> {
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+pub fn lint_expression(expr: &Expr, env: &Environment) -> Result<(), ZekkenError> {
+    let mut errors = Vec::new();
+    collect_lint_expression(expr, env, &mut errors);
+    first_error(errors)
+}
+
+pub fn lint_statement(stmt: &Stmt, env: &Environment) -> Result<(), ZekkenError> {
+    let mut errors = Vec::new();
+    collect_lint_statement(stmt, env, &mut errors);
+    first_error(errors)
+}
+
+pub fn collect_lint_expression(expr: &Expr, env: &Environment, errors: &mut Vec<ZekkenError>) {
+    Linter::new(env, errors).visit_expr(expr);
+}
+
+pub fn collect_lint_statement(
+    stmt: &Stmt,
+    env: &Environment,
+    errors: &mut Vec<ZekkenError>,
+) {
+    Linter::new(env, errors).visit_stmt(stmt);
 }
 
 pub fn lint_include(include: &IncludeStmt) -> Result<(), ZekkenError> {
@@ -570,10 +634,43 @@ pub fn lint_include(include: &IncludeStmt) -> Result<(), ZekkenError> {
     Ok(())
 }
 
+/// Reports plain includes of files whose top level does more than declare
+/// things, as the `include-side-effects` lint. Those statements run on every
+/// include; `include lazy` skips them.
+pub fn collect_include_side_effects(program: &Program, errors: &mut Vec<ZekkenError>) {
+    let current_file = std::env::var("ZEKKEN_CURRENT_FILE").unwrap_or_default();
+    let current_dir = Path::new(&current_file).parent().unwrap_or_else(|| Path::new(""));
+    for import in &program.imports {
+        let Content::Statement(stmt) = import else { continue };
+        let Stmt::Include(include) = stmt.as_ref() else { continue };
+        if include.lazy {
+            continue;
+        }
+        let Ok(source) = std::fs::read_to_string(current_dir.join(&include.file_path)) else { continue };
+        let mut parser = Parser::new();
+        let included = parser.produce_ast_cached(source);
+        if !parser.errors.is_empty() {
+            continue;
+        }
+        if let Some(code) = included.content.iter().find(|content| !is_declaration(content)) {
+            report_lint("include-side-effects", ZekkenError::runtime(
+                &format!(
+                    "Included file '{}' runs top-level code on line {}; use 'include lazy' to only load its declarations",
+                    include.file_path,
+                    content_location(code).line
+                ),
+                include.location.line,
+                include.location.column,
+                None,
+            ), errors);
+        }
+    }
+}
+
 pub fn lint_use(use_stmt: &UseStmt) -> Result<(), ZekkenError> {
     // First check if library exists
     match use_stmt.module.as_str() {
-        "math" | "fs" | "os" | "path" | "encoding" | "http" => {
+        "math" | "fs" | "os" | "path" | "encoding" | "http" | "semver" | "strings" | "decimal" | "units" | "calc" | "random" | "assert" | "arrays" => {
             // If specific methods are requested, validate they exist in the library
             if let Some(methods) = &use_stmt.methods {
                 // Create a temporary environment to load the library
@@ -581,7 +678,7 @@ pub fn lint_use(use_stmt: &UseStmt) -> Result<(), ZekkenError> {
                 load_library(&use_stmt.module, &mut temp_env)?;
                 
                 // Look up the library object and check each method exists in it
-                if let Some(Value::Object(lib_obj)) = temp_env.lookup(&use_stmt.module) {
+                if let Some(lib_obj) = temp_env.lookup_ref(&use_stmt.module).and_then(Value::as_object) {
                     for method in methods {
                         if !lib_obj.contains_key(method) {
                             return Err(ZekkenError::reference(
@@ -0,0 +1,142 @@
+//! Likely-infinite loop report for `--loop-warn`.
+//!
+//! A `while` (or C-style `for`) whose condition only reads variables that
+//! nothing in the loop writes cannot change its answer once it is true. The
+//! check is deliberately conservative: a condition that calls anything, or a
+//! body that calls a user function, breaks, returns or exits, is assumed to
+//! know what it is doing.
+
+// Only the CLI runs this pass; the wasm build never reaches it.
+#![allow(dead_code)]
+
+use crate::ast::visit::{walk_expr, walk_stmt, Visitor};
+use crate::ast::*;
+use crate::errors::ZekkenError;
+use crate::eval::lint::report_lint;
+use std::collections::BTreeSet;
+
+/// The variables an expression reads.
+#[derive(Default)]
+struct Reads {
+    names: BTreeSet<String>,
+    calls: bool,
+}
+
+impl Visitor for Reads {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(ident) => {
+                self.names.insert(ident.name.clone());
+            }
+            Expr::Call(_) | Expr::MethodCall(_) => self.calls = true,
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// The variables a loop body may change, and whether it can leave early.
+#[derive(Default)]
+struct Writes {
+    names: BTreeSet<String>,
+    leaves: bool,
+}
+
+fn root_name(mut target: &Expr) -> Option<&str> {
+    while let Expr::Member(member) = target {
+        target = &member.object;
+    }
+    match target {
+        Expr::Identifier(ident) => Some(&ident.name),
+        _ => None,
+    }
+}
+
+impl Visitor for Writes {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Break(_) | Stmt::Return(_) | Stmt::Include(_) | Stmt::Use(_) => self.leaves = true,
+            Stmt::VarDecl(decl) => {
+                self.names.insert(decl.ident.clone());
+            }
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign(assign) => self.names.extend(root_name(&assign.left).map(str::to_string)),
+            // Methods like `push` change the value they are called on.
+            Expr::MethodCall(call) => self.names.extend(root_name(&call.object).map(str::to_string)),
+            // A user function may assign to anything it can see.
+            Expr::Call(call) if !call.is_native || matches!(call.callee.as_ref(), Expr::Identifier(ident) if ident.name == "exit") => {
+                self.leaves = true
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[derive(Default)]
+struct StuckLoops {
+    found: Vec<ZekkenError>,
+}
+
+impl StuckLoops {
+    fn check(&mut self, test: &Expr, body: &[Content], update: Option<&Expr>, location: &Location) {
+        let mut reads = Reads::default();
+        reads.visit_expr(test);
+        if reads.calls || reads.names.is_empty() {
+            return;
+        }
+        let mut writes = Writes::default();
+        writes.visit_block(body);
+        if let Some(update) = update {
+            writes.visit_expr(update);
+        }
+        if writes.leaves || reads.names.iter().any(|name| writes.names.contains(name)) {
+            return;
+        }
+        let names: Vec<String> = reads.names.iter().map(|name| format!("'{}'", name)).collect();
+        self.found.push(ZekkenError::runtime(
+            &format!(
+                "Loop condition only reads {}, which the loop never changes, so it may never end",
+                names.join(", ")
+            ),
+            location.line,
+            location.column,
+            None,
+        ));
+    }
+}
+
+impl Visitor for StuckLoops {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::WhileStmt(stmt) => self.check(&stmt.test, &stmt.body, None, &stmt.location),
+            Stmt::ForStmt(stmt) => {
+                if let Some(test) = &stmt.test {
+                    self.check(test, &stmt.body, stmt.update.as_deref(), &stmt.location);
+                }
+            }
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+}
+
+/// Loops in `program` that look like they can never end.
+pub fn stuck_loops(program: &Program) -> Vec<ZekkenError> {
+    let mut pass = StuckLoops::default();
+    pass.visit_block(&program.content);
+    pass.found
+}
+
+/// Reports [`stuck_loops`] as the `stuck-loop` lint.
+pub fn collect_stuck_loops(program: &Program, errors: &mut Vec<ZekkenError>) {
+    for error in stuck_loops(program) {
+        report_lint("stuck-loop", error, errors);
+    }
+}
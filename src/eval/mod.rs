@@ -1,3 +1,10 @@
 pub mod expression;
 pub mod statement;
 pub mod lint;
+pub mod include;
+pub mod infer;
+pub mod pattern;
+pub mod unused;
+pub mod loops;
+pub mod deprecated;
+pub mod env_eval;
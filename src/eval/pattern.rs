@@ -0,0 +1,131 @@
+//! Destructuring of values against `match` patterns.
+//!
+//! Both the tree-walk evaluator and the bytecode VM match through here; each
+//! passes a callback that evaluates a value pattern with its own evaluator and
+//! compares it against the matched value. A callback that fails to evaluate
+//! keeps the error itself and rejects the value.
+
+use crate::ast::{Expr, Pattern};
+use crate::environment::Value;
+use crate::lexer::DataType;
+
+/// Matches `value` against `pattern`, appending every name the pattern binds
+/// to `bindings`. `accepts` decides whether a value pattern equals a value.
+/// Bindings collected by a failed match are discarded.
+pub fn match_pattern<F>(pattern: &Pattern, value: &Value, accepts: &mut F, bindings: &mut Vec<(String, Value)>) -> bool
+where
+    F: FnMut(&Expr, &Value) -> bool,
+{
+    let start = bindings.len();
+    let matched = match_inner(pattern, value, accepts, bindings);
+    if !matched {
+        bindings.truncate(start);
+    }
+    matched
+}
+
+fn match_inner<F>(pattern: &Pattern, value: &Value, accepts: &mut F, bindings: &mut Vec<(String, Value)>) -> bool
+where
+    F: FnMut(&Expr, &Value) -> bool,
+{
+    match pattern {
+        Pattern::Wildcard => true,
+        Pattern::Binding(name) => {
+            bindings.push((name.clone(), value.clone()));
+            true
+        }
+        Pattern::Value(expected) => accepts(expected, value),
+        Pattern::Array(items) => match value {
+            Value::Array(elements) if elements.len() == items.len() => items
+                .iter()
+                .zip(elements)
+                .all(|(item, element)| match_inner(item, element, accepts, bindings)),
+            _ => false,
+        },
+        Pattern::Object(fields) => match value.as_object() {
+            Some(map) => fields.iter().all(|(key, field)| {
+                map.get(key).is_some_and(|field_value| match_inner(field, field_value, accepts, bindings))
+            }),
+            _ => false,
+        },
+        Pattern::Or(alternatives) => alternatives
+            .iter()
+            .any(|alternative| match_pattern(alternative, value, accepts, bindings)),
+        Pattern::Kind(kind, binding) => {
+            if !has_kind(value, kind) {
+                return false;
+            }
+            if let Some(name) = binding {
+                bindings.push((name.clone(), value.clone()));
+            }
+            true
+        }
+    }
+}
+
+/// Whether `value` is of the kind a type name in a pattern stands for.
+fn has_kind(value: &Value, kind: &DataType) -> bool {
+    matches!(
+        (value, kind),
+        (_, DataType::Any)
+            | (Value::Int(_), DataType::Int)
+            | (Value::Float(_), DataType::Float)
+            | (Value::Decimal(_), DataType::Decimal)
+            | (Value::String(_), DataType::String)
+            | (Value::Boolean(_), DataType::Bool)
+            | (Value::Array(_) | Value::Vector(_) | Value::Matrix(_), DataType::Array)
+            | (Value::Object(_) | Value::Library(_) | Value::Enum { .. } | Value::Quantity(_) | Value::BigFloat(_) | Value::Expression(_), DataType::Object)
+            | (Value::Function(_) | Value::NativeFunction(_), DataType::Fn)
+    )
+}
+
+/// Names bound by `pattern`, in source order. Alternatives of an or-pattern
+/// may bind different names; all of them are listed.
+pub fn bound_names(pattern: &Pattern, out: &mut Vec<String>) {
+    match pattern {
+        Pattern::Binding(name) | Pattern::Kind(_, Some(name)) => {
+            if !out.contains(name) {
+                out.push(name.clone());
+            }
+        }
+        Pattern::Array(items) | Pattern::Or(items) => {
+            for item in items {
+                bound_names(item, out);
+            }
+        }
+        Pattern::Object(fields) => {
+            for (_, field) in fields {
+                bound_names(field, out);
+            }
+        }
+        Pattern::Wildcard | Pattern::Value(_) | Pattern::Kind(..) => {}
+    }
+}
+
+/// Every value expression `pattern` compares against, in source order.
+pub fn value_exprs<'a>(pattern: &'a Pattern, out: &mut Vec<&'a Expr>) {
+    match pattern {
+        Pattern::Value(value) => out.push(value),
+        Pattern::Array(items) | Pattern::Or(items) => {
+            for item in items {
+                value_exprs(item, out);
+            }
+        }
+        Pattern::Object(fields) => {
+            for (_, field) in fields {
+                value_exprs(field, out);
+            }
+        }
+        Pattern::Wildcard | Pattern::Binding(_) | Pattern::Kind(..) => {}
+    }
+}
+
+/// Whether `pattern` matches every value.
+pub fn is_irrefutable(pattern: &Pattern) -> bool {
+    match pattern {
+        Pattern::Wildcard | Pattern::Binding(_) => true,
+        Pattern::Or(alternatives) => alternatives.iter().any(is_irrefutable),
+        Pattern::Kind(kind, _) => *kind == DataType::Any,
+        Pattern::Value(_) | Pattern::Array(_) | Pattern::Object(_) => false,
+    }
+}
@@ -1,16 +1,21 @@
 use crate::ast::*;
-use crate::environment::{Environment, Value, FunctionValue};
+use crate::environment::{elements_match, enum_value, int_range, is_internal_entry, switch_case_matches, CallArgs, Environment, ControlFlow, Value, FunctionValue};
+use crate::environment::decimal::Decimal;
 use crate::parser::Parser;
-use super::expression::{evaluate_assignment_discard, evaluate_expression};
+use super::expression::{evaluate_assignment_discard, evaluate_expression, evaluate_function_value_call_with_args};
+use crate::config::IterationCap;
 use crate::errors::{ZekkenError, ErrorKind, push_error};
 use crate::libraries::load_library;
 use crate::lexer::DataType;
 use hashbrown::HashMap;
+use indexmap::IndexMap;
 use std::collections::HashSet;
 use std::path::Path;
 use std::sync::Arc;
 // use std::process;
 use super::lint::{lint_statement, lint_expression, lint_include, lint_use};
+use super::include::{included_bindings, keep_declarations, library_names, trace as trace_include};
+use super::pattern::{bound_names, value_exprs};
 
 // Check if the value type matches the expected type
 fn check_value_type(value: &Value, expected: &DataType) -> bool {
@@ -20,9 +25,13 @@ fn check_value_type(value: &Value, expected: &DataType) -> bool {
         (Value::Float(_), DataType::Float) => true,
         (Value::String(_), DataType::String) => true,
         (Value::Boolean(_), DataType::Bool) => true,
-        (Value::Array(_), DataType::Array) => true,
-        (Value::Object(_), DataType::Object) => true,
-        (Value::Function(_), DataType::Fn) => true,
+        (Value::Array(_) | Value::Vector(_) | Value::Matrix(_), DataType::Array) => true,
+        (_, DataType::ArrayOf(element)) => elements_match(value, element),
+        (Value::Null, DataType::Nullable(_)) => true,
+        (_, DataType::Nullable(inner)) => check_value_type(value, inner),
+        (Value::Object(_) | Value::Library(_) | Value::Enum { .. } | Value::Quantity(_) | Value::BigFloat(_) | Value::Expression(_), DataType::Object) => true,
+        (Value::Decimal(_), DataType::Decimal) => true,
+        (Value::Function(_) | Value::NativeFunction(_), DataType::Fn) => true,
         _ => false,
     }
 }
@@ -33,10 +42,13 @@ fn create_dummy_value(data_type: &DataType) -> Value {
         DataType::String => Value::String(String::new()),
         DataType::Int => Value::Int(0),
         DataType::Float => Value::Float(0.0),
+        DataType::Decimal => Value::Decimal(Decimal::from_int(0)),
         DataType::Bool => Value::Boolean(false),
-        DataType::Array => Value::Array(vec![]),
-        DataType::Object => Value::Object(HashMap::new()),
+        DataType::Array | DataType::ArrayOf(_) => Value::Array(vec![]),
+        DataType::Nullable(inner) => create_dummy_value(inner),
+        DataType::Object => Value::Object(IndexMap::new()),
         DataType::Fn => Value::Function(FunctionValue { 
+            name: Arc::from(""),
             params: Arc::new(vec![]), 
             body: Arc::new(vec![]),
             return_type: None,
@@ -45,6 +57,7 @@ fn create_dummy_value(data_type: &DataType) -> Value {
             capture_values: Arc::new(HashMap::new()),
             compiled_insts: None,
             compiled_reg_count: 0,
+            closure: None,
         }),
         _ => Value::Void,
     }
@@ -56,7 +69,7 @@ struct ParentUsage {
     captures: HashSet<String>,
 }
 
-fn analyze_function_parent_usage(params: &[Param], body: &[Box<Content>]) -> ParentUsage {
+fn analyze_function_parent_usage(params: &[Param], body: &[Content]) -> ParentUsage {
     let mut locals = HashSet::new();
     let mut usage = ParentUsage::default();
     for p in params {
@@ -74,12 +87,12 @@ fn analyze_function_parent_usage(params: &[Param], body: &[Box<Content>]) -> Par
 }
 
 fn analyze_contents_parent_usage(
-    contents: &[Box<Content>],
+    contents: &[Content],
     locals: &mut HashSet<String>,
     usage: &mut ParentUsage,
 ) {
     for content in contents {
-        match content.as_ref() {
+        match content {
             Content::Statement(stmt) => analyze_stmt_parent_usage(stmt, locals, usage),
             Content::Expression(expr) => analyze_expr_parent_usage(expr, locals, usage),
         }
@@ -111,6 +124,9 @@ fn analyze_stmt_parent_usage(stmt: &Stmt, locals: &mut HashSet<String>, usage: &
         }
         Stmt::ObjectDecl(obj) => {
             for p in &obj.properties {
+                if let Some(key) = &p.computed_key {
+                    analyze_expr_parent_usage(key, locals, usage);
+                }
                 analyze_expr_parent_usage(&p.value, locals, usage);
                 if usage.requires_parent_clone {
                     return;
@@ -138,6 +154,10 @@ fn analyze_stmt_parent_usage(stmt: &Stmt, locals: &mut HashSet<String>, usage: &
                 analyze_stmt_parent_usage(init, locals, usage);
             }
         }
+        Stmt::ForIn(f) => {
+            usage.requires_parent_clone = true;
+            analyze_expr_parent_usage(&f.iterable, locals, usage);
+        }
         Stmt::WhileStmt(w) => {
             analyze_expr_parent_usage(&w.test, locals, usage);
             if usage.requires_parent_clone {
@@ -145,7 +165,16 @@ fn analyze_stmt_parent_usage(stmt: &Stmt, locals: &mut HashSet<String>, usage: &
             }
             let mut body_locals = locals.clone();
             analyze_contents_parent_usage(&w.body, &mut body_locals, usage);
+            if let Some(else_body) = &w.else_body {
+                let mut else_locals = locals.clone();
+                analyze_contents_parent_usage(else_body, &mut else_locals, usage);
+            }
         }
+        Stmt::Loop(l) => {
+            let mut body_locals = locals.clone();
+            analyze_contents_parent_usage(&l.body, &mut body_locals, usage);
+        }
+        Stmt::Break(_) | Stmt::Continue(_) => {}
         Stmt::TryCatchStmt(_) => {
             usage.requires_parent_clone = true;
         }
@@ -164,6 +193,32 @@ fn analyze_stmt_parent_usage(stmt: &Stmt, locals: &mut HashSet<String>, usage: &
         Stmt::Use(_) | Stmt::Include(_) | Stmt::Export(_) => {
             usage.requires_parent_clone = true;
         }
+        Stmt::EnumDecl(e) => {
+            locals.insert(e.ident.clone());
+        }
+        Stmt::Defer(d) => analyze_expr_parent_usage(&d.expr, locals, usage),
+        Stmt::With(w) => {
+            analyze_expr_parent_usage(&w.resource, locals, usage);
+            if usage.requires_parent_clone {
+                return;
+            }
+            let mut body_locals = locals.clone();
+            body_locals.insert(w.ident.clone());
+            analyze_contents_parent_usage(&w.body, &mut body_locals, usage);
+        }
+        Stmt::Switch(s) => {
+            analyze_expr_parent_usage(&s.subject, locals, usage);
+            for value in s.cases.iter().flat_map(|case| &case.values) {
+                analyze_expr_parent_usage(value, locals, usage);
+            }
+            for body in s.bodies() {
+                if usage.requires_parent_clone {
+                    return;
+                }
+                let mut body_locals = locals.clone();
+                analyze_contents_parent_usage(body, &mut body_locals, usage);
+            }
+        }
         Stmt::Program(_) => {}
     }
 }
@@ -195,14 +250,22 @@ fn analyze_expr_parent_usage(expr: &Expr, locals: &HashSet<String>, usage: &mut
                 }
             }
         }
+        Expr::MethodCall(c) => {
+            analyze_expr_parent_usage(&c.object, locals, usage);
+            for a in &c.args {
+                if usage.requires_parent_clone {
+                    return;
+                }
+                analyze_expr_parent_usage(a, locals, usage);
+            }
+        }
         Expr::Member(m) => {
             analyze_expr_parent_usage(&m.object, locals, usage);
             if usage.requires_parent_clone {
                 return;
             }
-            match m.property.as_ref() {
-                Expr::Identifier(_) | Expr::Property(_) => {}
-                other => analyze_expr_parent_usage(other, locals, usage),
+            if m.computed {
+                analyze_expr_parent_usage(&m.property, locals, usage);
             }
         }
         Expr::Assign(a) => {
@@ -226,18 +289,70 @@ fn analyze_expr_parent_usage(expr: &Expr, locals: &HashSet<String>, usage: &mut
                 }
             }
         }
+        Expr::VectorLit(v) => {
+            for e in &v.elements {
+                analyze_expr_parent_usage(e, locals, usage);
+                if usage.requires_parent_clone {
+                    return;
+                }
+            }
+        }
+        Expr::MatrixLit(m) => {
+            for e in m.rows.iter().flatten() {
+                analyze_expr_parent_usage(e, locals, usage);
+                if usage.requires_parent_clone {
+                    return;
+                }
+            }
+        }
+        Expr::TemplateLit(t) => {
+            for e in t.exprs() {
+                analyze_expr_parent_usage(e, locals, usage);
+                if usage.requires_parent_clone {
+                    return;
+                }
+            }
+        }
         Expr::ObjectLit(o) => {
             for p in &o.properties {
+                if let Some(key) = &p.computed_key {
+                    analyze_expr_parent_usage(key, locals, usage);
+                }
                 analyze_expr_parent_usage(&p.value, locals, usage);
                 if usage.requires_parent_clone {
                     return;
                 }
             }
         }
+        Expr::Match(m) => {
+            analyze_expr_parent_usage(&m.subject, locals, usage);
+            for arm in &m.arms {
+                if usage.requires_parent_clone {
+                    return;
+                }
+                let mut values = Vec::new();
+                value_exprs(&arm.pattern, &mut values);
+                for value in values {
+                    analyze_expr_parent_usage(value, locals, usage);
+                }
+                let mut arm_locals = locals.clone();
+                let mut names = Vec::new();
+                bound_names(&arm.pattern, &mut names);
+                arm_locals.extend(names);
+                if let Some(guard) = &arm.guard {
+                    analyze_expr_parent_usage(guard, &arm_locals, usage);
+                }
+                analyze_contents_parent_usage(&arm.body, &mut arm_locals, usage);
+            }
+        }
+        // Like a nested `fn ->` declaration, a lambda keeps its parent scope.
+        Expr::Lambda(_) => usage.requires_parent_clone = true,
         Expr::IntLit(_)
         | Expr::FloatLit(_)
+        | Expr::DecimalLit(_)
         | Expr::StringLit(_)
         | Expr::BoolLit(_)
+        | Expr::NullLit(_)
         | Expr::Property(_) => {}
     }
 }
@@ -248,25 +363,20 @@ fn process_statement_scope(stmt: &Stmt, env: &mut Environment) {
         Stmt::Lambda(lambda) => {
             // Register the lambda function in the environment during the first pass
             let function_value = FunctionValue {
+                name: Arc::from(lambda.ident.as_str()),
                 params: Arc::new(lambda.params.clone()),
                 body: Arc::new(lambda.body.clone()),
-                return_type: lambda.return_type,
+                return_type: lambda.return_type.clone(),
                 needs_parent: true,
                 captures: Arc::new(vec![]),
                 capture_values: Arc::new(HashMap::new()),
                 compiled_insts: None,
                 compiled_reg_count: 0,
+                closure: None,
             };
             env.declare(lambda.ident.clone(), Value::Function(function_value), lambda.constant);
         },
         Stmt::VarDecl(var_decl) => {
-            // Skip type checking for object iteration patterns in for loops
-            if var_decl.ident.contains(", ") {
-                // This is likely a for-loop pattern, we'll validate types during evaluation
-                env.declare(var_decl.ident.clone(), Value::Void, false);
-                return;
-            }
-
             // First-pass scope processing should only register declaration shapes.
             // Avoid evaluating expressions here to keep lint preprocessing O(AST size)
             // instead of re-executing value logic.
@@ -280,68 +390,94 @@ fn process_statement_scope(stmt: &Stmt, env: &mut Environment) {
         Stmt::FuncDecl(func_decl) => {
             // First, register the function itself in the environment
             let function_value = FunctionValue {
+                name: Arc::from(func_decl.ident.as_str()),
                 params: Arc::new(func_decl.params.clone()),
                 body: Arc::new(func_decl.body.clone()),
-                return_type: func_decl.return_type,
+                return_type: func_decl.return_type.clone(),
                 needs_parent: true,
                 captures: Arc::new(vec![]),
                 capture_values: Arc::new(HashMap::new()),
                 compiled_insts: None,
                 compiled_reg_count: 0,
+                closure: None,
             };
             env.declare(func_decl.ident.clone(), Value::Function(function_value), false);
         },
+        Stmt::EnumDecl(decl) => {
+            // Enum values are plain data, so the real namespace is declared.
+            env.declare(decl.ident.clone(), enum_value(&decl.ident, &decl.variants), true);
+        },
         Stmt::BlockStmt(block) => {
             // Process block contents in the current environment
             for content in &block.body {
-                if let Content::Statement(stmt) = &**content {
+                if let Content::Statement(stmt) = content {
                     process_statement_scope(stmt, env);
                 }
             }
         },
         Stmt::ForStmt(for_stmt) => {
-            // Process initializer if it exists
             if let Some(init) = &for_stmt.init {
                 process_statement_scope(init, env);
             }
-            
-            // Process the loop body
             for content in &for_stmt.body {
-                if let Content::Statement(stmt) = &**content {
+                if let Content::Statement(stmt) = content {
+                    process_statement_scope(stmt, env);
+                }
+            }
+        },
+        Stmt::ForIn(for_stmt) => {
+            // Loop variables only exist while the loop runs, so just the body
+            // can declare anything here.
+            for content in &for_stmt.body {
+                if let Content::Statement(stmt) = content {
                     process_statement_scope(stmt, env);
                 }
             }
         },
         Stmt::IfStmt(if_stmt) => {
             for content in &if_stmt.body {
-                if let Content::Statement(stmt) = &**content {
+                if let Content::Statement(stmt) = content {
                     process_statement_scope(stmt, env);
                 }
             }
             if let Some(alt) = &if_stmt.alt {
                 for content in alt {
-                    if let Content::Statement(stmt) = &**content {
+                    if let Content::Statement(stmt) = content {
                         process_statement_scope(stmt, env);
                     }
                 }
             }
         },
         Stmt::WhileStmt(while_stmt) => {
-            for content in &while_stmt.body {
-                if let Content::Statement(stmt) = &**content {
+            for content in while_stmt.body.iter().chain(while_stmt.else_body.iter().flatten()) {
+                if let Content::Statement(stmt) = content {
+                    process_statement_scope(stmt, env);
+                }
+            }
+        },
+        Stmt::Loop(loop_stmt) => {
+            for content in &loop_stmt.body {
+                if let Content::Statement(stmt) = content {
+                    process_statement_scope(stmt, env);
+                }
+            }
+        },
+        Stmt::Switch(switch) => {
+            for content in switch.bodies().flatten() {
+                if let Content::Statement(stmt) = content {
                     process_statement_scope(stmt, env);
                 }
             }
         },
         Stmt::TryCatchStmt(try_catch) => {
             for content in &try_catch.try_block {
-                if let Content::Statement(stmt) = &**content {
+                if let Content::Statement(stmt) = content {
                     process_statement_scope(stmt, env);
                 }
             }
             if let Some(catch_block) = &try_catch.catch_block {
                 for content in catch_block {
-                    if let Content::Statement(stmt) = &**content {
+                    if let Content::Statement(stmt) = content {
                         process_statement_scope(stmt, env);
                     }
                 }
@@ -359,41 +495,133 @@ fn value_type_name(val: &Value) -> &'static str {
         Value::String(_) => "string",
         Value::Boolean(_) => "bool",
         Value::Array(_) => "array",
-        Value::Object(_) => "object",
+        Value::Object(_) | Value::Library(_) | Value::Enum { .. } => "object",
+        Value::Decimal(_) => "dec",
         Value::Function(_) => "",
         Value::NativeFunction(_) => "",
+        Value::Null => "null",
         Value::Void => "void",
         _ => "unknown",
     }
 }
 
-fn expr_location(expr: &Expr) -> Location {
+pub(crate) fn expr_location(expr: &Expr) -> Location {
     match expr {
         Expr::Assign(e) => e.location.clone(),
         Expr::Member(e) => e.location.clone(),
         Expr::Call(e) => e.location.clone(),
+        Expr::MethodCall(e) => e.location.clone(),
         Expr::Unary(e) => e.location.clone(),
         Expr::Binary(e) => e.location.clone(),
         Expr::Identifier(e) => e.location.clone(),
         Expr::Property(e) => e.location.clone(),
         Expr::IntLit(e) => e.location.clone(),
         Expr::FloatLit(e) => e.location.clone(),
+        Expr::DecimalLit(e) => e.location.clone(),
         Expr::StringLit(e) => e.location.clone(),
+        Expr::TemplateLit(e) => e.location.clone(),
         Expr::BoolLit(e) => e.location.clone(),
+        Expr::NullLit(e) => e.location.clone(),
         Expr::ArrayLit(e) => e.location.clone(),
+        Expr::VectorLit(e) => e.location.clone(),
+        Expr::MatrixLit(e) => e.location.clone(),
         Expr::ObjectLit(e) => e.location.clone(),
+        Expr::Match(e) => e.location.clone(),
+        Expr::Lambda(e) => e.location.clone(),
     }
 }
 
 // Evaluate a statement and return the result
 pub fn evaluate_statement(stmt: &Stmt, env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
+    crate::stats::statement();
     match stmt {
         Stmt::Program(program) => evaluate_program(program, env),
         Stmt::VarDecl(var_decl) => evaluate_var_declaration(var_decl, env),
         Stmt::FuncDecl(func_decl) => evaluate_function_declaration(func_decl, env),
         Stmt::ObjectDecl(obj_decl) => evaluate_object_declaration(obj_decl, env),
         Stmt::IfStmt(if_stmt) => evaluate_if_statement(if_stmt, env),
-        Stmt::ForStmt(for_stmt) => evaluate_for_statement(for_stmt, env),
+        Stmt::Loop(loop_stmt) => {
+            let mut cap = IterationCap::new();
+            loop {
+                if !cap.tick() {
+                    return Err(cap.error(&loop_stmt.location));
+                }
+                let result = evaluate_block_content(&loop_stmt.body, env)?;
+                if env.stop_loop(loop_stmt.label.as_deref()) {
+                    return Ok(result);
+                }
+            }
+        }
+        Stmt::Break(brk) => {
+            env.control_flow = Some(ControlFlow::Break(brk.label.clone()));
+            Ok(None)
+        }
+        Stmt::Continue(cont) => {
+            env.control_flow = Some(ControlFlow::Continue(cont.label.clone()));
+            Ok(None)
+        }
+        Stmt::ForIn(for_stmt) => evaluate_for_statement(for_stmt, env),
+        Stmt::ForStmt(for_stmt) => {
+            // The initializer's binding only lives as long as the loop; put back
+            // whatever it shadowed once the loop ends.
+            let scoped = match for_stmt.init.as_deref() {
+                Some(Stmt::VarDecl(decl)) => Some((decl.ident.as_str(), env.take_binding(&decl.ident))),
+                _ => None,
+            };
+            let result = 'run: {
+                if let Some(init) = &for_stmt.init {
+                    if let Err(error) = evaluate_statement(init, env) {
+                        break 'run Err(error);
+                    }
+                }
+                let mut last = None;
+                let mut cap = IterationCap::new();
+                loop {
+                    if let Some(test) = &for_stmt.test {
+                        match evaluate_expression(test, env) {
+                            Ok(Value::Boolean(true)) => {}
+                            Ok(Value::Boolean(false)) => break,
+                            Ok(other) => {
+                                break 'run Err(ZekkenError::type_error(
+                                    "For loop condition must evaluate to a boolean",
+                                    "bool",
+                                    value_type_name(&other),
+                                    for_stmt.location.line,
+                                    for_stmt.location.column,
+                                ).with_value(&other))
+                            }
+                            Err(error) => break 'run Err(error),
+                        }
+                    }
+                    if !cap.tick() {
+                        break 'run Err(cap.error(&for_stmt.location));
+                    }
+                    match evaluate_block_content(&for_stmt.body, env) {
+                        Ok(value) => last = value,
+                        Err(error) => break 'run Err(error),
+                    }
+                    if env.stop_loop(for_stmt.label.as_deref()) {
+                        break 'run Ok(last);
+                    }
+                    if let Some(update) = &for_stmt.update {
+                        if let Err(error) = evaluate_expression(update, env) {
+                            break 'run Err(error);
+                        }
+                    }
+                }
+                if let Some(else_body) = &for_stmt.else_body {
+                    match evaluate_block_content(else_body, env) {
+                        Ok(value) => last = value,
+                        Err(error) => break 'run Err(error),
+                    }
+                }
+                Ok(last)
+            };
+            if let Some((name, saved)) = scoped {
+                env.restore_binding(name, saved);
+            }
+            result
+        }
         Stmt::WhileStmt(while_stmt) => evaluate_while_statement(while_stmt, env),
         Stmt::TryCatchStmt(try_catch) => evaluate_try_catch(try_catch, env),
         Stmt::BlockStmt(block) => evaluate_block(block, env),
@@ -402,6 +630,74 @@ pub fn evaluate_statement(stmt: &Stmt, env: &mut Environment) -> Result<Option<V
         Stmt::Use(use_stmt) => evaluate_use(use_stmt, env),
         Stmt::Include(include) => evaluate_include(include, env),
         Stmt::Export(exports) => evaluate_export(exports, env),
+        Stmt::EnumDecl(decl) => {
+            env.declare(decl.ident.clone(), enum_value(&decl.ident, &decl.variants), true);
+            Ok(None)
+        }
+        Stmt::Defer(defer) => {
+            env.deferred.push(defer.expr.as_ref().clone());
+            Ok(None)
+        }
+        Stmt::With(with) => {
+            let (line, column) = (with.location.line, with.location.column);
+            let resource = evaluate_expression(&with.resource, env)?;
+            let Some(close) = resource.close_method().cloned() else {
+                return Err(ZekkenError::type_error(
+                    "With resource must have a __close__ method",
+                    "object with __close__",
+                    value_type_name(&resource),
+                    line,
+                    column,
+                ).with_value(&resource));
+            };
+            set_or_declare_loop_var(env, &with.ident, resource);
+            let result = evaluate_block_content(&with.body, env);
+            let closed = match close {
+                Value::Function(func) => evaluate_function_value_call_with_args(&func, CallArgs::new(), &[], env, line, column).map(|_| ()),
+                Value::NativeFunction(native) => native(Vec::new())
+                    .map(|_| ())
+                    .map_err(|msg| ZekkenError::runtime(&msg, line, column, None)),
+                _ => Ok(()),
+            };
+            match closed {
+                Err(error) if result.is_ok() => Err(error),
+                _ => result,
+            }
+        }
+        Stmt::Switch(switch) => {
+            let subject = evaluate_expression(&switch.subject, env)?;
+            if !matches!(subject, Value::Int(_) | Value::String(_)) {
+                let at = expr_location(&switch.subject);
+                return Err(ZekkenError::type_error(
+                    "Switch value must be an int or a string",
+                    "int or string",
+                    value_type_name(&subject),
+                    at.line,
+                    at.column,
+                ).with_value(&subject));
+            }
+            // Cases are tried in order; with no match only the default runs.
+            let mut start = switch.cases.len();
+            'cases: for (idx, case) in switch.cases.iter().enumerate() {
+                for value in &case.values {
+                    if switch_case_matches(&subject, &evaluate_expression(value, env)?) {
+                        start = idx;
+                        break 'cases;
+                    }
+                }
+            }
+            let mut result = None;
+            for case in &switch.cases[start..] {
+                result = evaluate_block_content(&case.body, env)?;
+                if !case.fallthrough || env.control_flow.is_some() {
+                    return Ok(result);
+                }
+            }
+            match &switch.default {
+                Some(default) => evaluate_block_content(default, env),
+                None => Ok(result),
+            }
+        }
     }
 }
 
@@ -463,14 +759,14 @@ fn evaluate_program(program: &Program, env: &mut Environment) -> Result<Option<V
 
     // Process all top-level statements in the same environment
     for content in &program.content {
-        if let Content::Statement(stmt) = &**content {
+        if let Content::Statement(stmt) = content {
             process_statement_scope(stmt, env);
         }
     }
 
     // Second pass: Now lint everything with the complete environment
     for content in &program.content {
-        match &**content {
+        match content {
             Content::Statement(stmt) => {
                 if let Err(e) = lint_statement(stmt, env) {
                     lint_errors.push(e);
@@ -484,6 +780,8 @@ fn evaluate_program(program: &Program, env: &mut Environment) -> Result<Option<V
         }
     }
 
+    super::deprecated::collect_deprecations(program, &mut lint_errors);
+
     // Report lint errors and stop before execution.
     if !lint_errors.is_empty() {
         for error in lint_errors {
@@ -497,7 +795,17 @@ fn evaluate_program(program: &Program, env: &mut Environment) -> Result<Option<V
     
     // Imports/declarations were already applied directly to env during lint setup.
     // Execute only top-level content to avoid duplicate import work and side effects.
-    evaluate_block_content(&program.content, env)
+    let result = evaluate_block_content(&program.content, env).map(|value| env.take_return().or(value));
+    let mut deferred_error = None;
+    while let Some(expr) = env.deferred.pop() {
+        if let Err(error) = evaluate_expression(&expr, env) {
+            deferred_error.get_or_insert(error);
+        }
+    }
+    match deferred_error {
+        Some(error) if result.is_ok() => Err(error),
+        _ => result,
+    }
 }
 
 // Handle variable declarations
@@ -506,7 +814,13 @@ fn evaluate_var_declaration(decl: &VarDecl, env: &mut Environment) -> Result<Opt
         Some(content) => match content {
             Content::Expression(expr) => {
                 let val = evaluate_expression(expr, env)?;
-                if !check_value_type(&val, &decl.type_) {
+                // A `return` (or `break`) inside the initializer, such as in a
+                // match arm, leaves before there is a value to declare.
+                if env.control_flow.is_some() {
+                    return Ok(None);
+                }
+                // A variable of any type may hold `null`.
+                if !matches!(val, Value::Null) && !check_value_type(&val, &decl.type_) {
                     let loc = expr_location(expr);
                     return Err(ZekkenError::type_error(
                         &format!("Type mismatch in variable declaration '{}'", decl.ident),
@@ -514,7 +828,7 @@ fn evaluate_var_declaration(decl: &VarDecl, env: &mut Environment) -> Result<Opt
                         value_type_name(&val),
                         loc.line,
                         loc.column
-                    ));
+                    ).with_value(&val));
                 }
                 val
             },
@@ -529,57 +843,45 @@ fn evaluate_var_declaration(decl: &VarDecl, env: &mut Environment) -> Result<Opt
         None => Value::Void,
     };
 
-    env.declare_ref_typed(&decl.ident, value, decl.type_, decl.constant);
+    crate::history::record(&decl.ident, &value, &decl.location);
+    env.declare_ref_typed(&decl.ident, value, decl.type_.clone(), decl.constant);
     Ok(None)
 }
 
 // Handle function declarations
 fn evaluate_function_declaration(func: &FuncDecl, env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
-    let usage = analyze_function_parent_usage(&func.params, &func.body);
-    let captures = if usage.requires_parent_clone {
-        vec![]
-    } else {
-        let mut v: Vec<String> = usage.captures.into_iter().collect();
-        v.sort_unstable();
-        v
-    };
-    let capture_values = captures
-        .iter()
-        .filter(|name| name.as_str() != func.ident)
-        .filter_map(|name| env.lookup_ref(name).map(|value| (name.clone(), value.clone())))
-        .collect();
-    let function_value = FunctionValue {
-        params: Arc::new(func.params.clone()),
-        body: Arc::new(func.body.clone()),
-        return_type: func.return_type,
-        needs_parent: usage.requires_parent_clone,
-        captures: Arc::new(captures),
-        capture_values: Arc::new(capture_values),
-        compiled_insts: None,
-        compiled_reg_count: 0,
-    };
-
+    let function_value = lambda_value(&func.ident, &func.params, &func.body, func.return_type.clone(), env);
     env.declare(func.ident.clone(), Value::Function(function_value), false);
     Ok(None)
 }
 
 // Handle object declarations
 fn evaluate_object_declaration(obj: &ObjectDecl, env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
-    let mut object_map = HashMap::new();
-    let mut keys = Vec::new();
+    let mut object_map = IndexMap::new();
     for property in &obj.properties {
+        let key = match &property.computed_key {
+            Some(key_expr) => {
+                let key_value = evaluate_expression(key_expr, env)?;
+                key_value.as_object_key().ok_or_else(|| ZekkenError::type_error(
+                    "Computed object key must be a string or int",
+                    "string or int",
+                    value_type_name(&key_value),
+                    property.location.line,
+                    property.location.column,
+                ).with_value(&key_value))?
+            }
+            None => property.key.clone(),
+        };
         let value = evaluate_expression(&property.value, env)
             .map_err(|e| ZekkenError::type_error(
-                &format!("Failed to evaluate property '{}': {}", property.key, e),
+                &format!("Failed to evaluate property '{}': {}", key, e),
                 "object",
                 "property evaluation failed",
                 obj.location.line,
                 obj.location.column
             ))?;
-        keys.push(property.key.clone());
-        object_map.insert(property.key.clone(), value);
+        object_map.insert(key, value);
     }
-    object_map.insert("__keys__".to_string(), Value::Array(keys.iter().map(|k| Value::String(k.clone())).collect()));
     env.declare(obj.ident.clone(), Value::Object(object_map), false);
     Ok(None)
 }
@@ -588,11 +890,12 @@ fn evaluate_object_declaration(obj: &ObjectDecl, env: &mut Environment) -> Resul
 fn evaluate_if_statement(if_stmt: &IfStmt, env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
     #[inline]
     fn eval_single_content_or_block(
-        content: &[Box<Content>],
+        content: &[Content],
         env: &mut Environment,
     ) -> Result<Option<Value>, ZekkenError> {
         if content.len() == 1 {
-            match content[0].as_ref() {
+            crate::coverage::content(&content[0]);
+            match &content[0] {
                 Content::Statement(stmt) => evaluate_statement(stmt, env),
                 Content::Expression(expr) => Ok(Some(evaluate_expression(expr, env)?)),
             }
@@ -741,7 +1044,7 @@ fn evaluate_if_statement(if_stmt: &IfStmt, env: &mut Environment) -> Result<Opti
                         value_type_name(other),
                         line,
                         column,
-                    )),
+                    ).with_value(other)),
                     None => Err(ZekkenError::reference(
                         &format!("Variable '{}' not found", id.name),
                         "variable",
@@ -847,12 +1150,12 @@ fn evaluate_if_statement(if_stmt: &IfStmt, env: &mut Environment) -> Result<Opti
         Char(u8),
     }
 
-    fn nested_else_if<'a>(alt: &'a Option<Vec<Box<Content>>>) -> Option<&'a IfStmt> {
+    fn nested_else_if(alt: &Option<Vec<Content>>) -> Option<&IfStmt> {
         let block = alt.as_ref()?;
         if block.len() != 1 {
             return None;
         }
-        match block[0].as_ref() {
+        match &block[0] {
             Content::Statement(stmt) => match stmt.as_ref() {
                 Stmt::IfStmt(i) => Some(i),
                 _ => None,
@@ -861,7 +1164,7 @@ fn evaluate_if_statement(if_stmt: &IfStmt, env: &mut Environment) -> Result<Opti
         }
     }
 
-    fn parse_mod_eq<'a>(expr: &'a Expr) -> Option<(&'a str, i64, i64)> {
+    fn parse_mod_eq(expr: &Expr) -> Option<(&str, i64, i64)> {
         let bin = match expr {
             Expr::Binary(b) if b.operator == "==" => b,
             _ => return None,
@@ -886,7 +1189,7 @@ fn evaluate_if_statement(if_stmt: &IfStmt, env: &mut Environment) -> Result<Opti
         Some((ident, divisor, expected))
     }
 
-    fn parse_str_eq<'a>(expr: &'a Expr) -> Option<(&'a str, u8)> {
+    fn parse_str_eq(expr: &Expr) -> Option<(&str, u8)> {
         let bin = match expr {
             Expr::Binary(b) if b.operator == "==" => b,
             _ => return None,
@@ -1036,58 +1339,48 @@ fn evaluate_if_statement(if_stmt: &IfStmt, env: &mut Environment) -> Result<Opti
             value_type_name(&test_result),
             if_stmt.location.line,
             if_stmt.location.column
-        ))
+        ).with_value(&test_result))
     }
 }
 
 // Handle for statements
-fn evaluate_for_statement(for_stmt: &ForStmt, env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
-    if let Some(ref init) = for_stmt.init {
-        if let Stmt::VarDecl(var_decl) = &**init {
-            let collection_value = match &var_decl.value {
-                Some(content) => match content {
-                    Content::Expression(expr) => evaluate_expression(expr, env)?,
-                    _ => return Err(ZekkenError::runtime(
-                        "Expected expression in for loop initialization",
-                        for_stmt.location.line,
-                        for_stmt.location.column,
-                        Some("for |x| in array { ... }"),
-                    )),
-                },
-                None => return Err(ZekkenError::runtime(
-                    "For loop initialization requires a value",
-                    for_stmt.location.line,
-                    for_stmt.location.column,
-                    None,
-                )),
-            };
-            match collection_value {
-                Value::Object(ref map) => evaluate_for_object(map, var_decl, &for_stmt.body, env),
-                Value::Array(arr) => evaluate_for_array(arr, var_decl, &for_stmt.body, env),
-                _ => Err(ZekkenError::type_error(
-                    "For loop must iterate over an object or array",
-                    "object or array",
-                    value_type_name(&collection_value),
-                    for_stmt.location.line,
-                    for_stmt.location.column
-                ))
-            }
-        } else {
-            Err(ZekkenError::runtime(
-                "For loop requires a variable declaration",
-                for_stmt.location.line,
-                for_stmt.location.column,
-                None
-            ))
-        }
-    } else {
-        Err(ZekkenError::runtime(
-            "For loop requires an initialization",
+// Loop variables get a fresh binding every iteration and only live for the
+// loop: whatever they shadowed is put back afterwards, like the counter of a
+// C-style `for`. The body still runs in the enclosing scope, so writes to
+// outer variables stick.
+fn evaluate_for_statement(for_stmt: &ForInStmt, env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
+    // `a..b` is walked lazily rather than built into an array first.
+    let range = match for_stmt.iterable.as_ref() {
+        Expr::Binary(range) if range.operator == ".." => {
+            let start = evaluate_expression(&range.left, env)?;
+            let end = evaluate_expression(&range.right, env)?;
+            Some(int_range(&start, &end).map_err(|e| ZekkenError::runtime(&e, range.location.line, range.location.column, None))?)
+        }
+        _ => None,
+    };
+    let collection_value = match range {
+        Some(_) => Value::Void,
+        None => evaluate_expression(&for_stmt.iterable, env)?,
+    };
+    let shadowed: Vec<_> = for_stmt.idents.iter().map(|name| (name, env.take_binding(name))).collect();
+    let result = match (range, collection_value) {
+        (Some(range), _) => evaluate_for_array(range.map(Value::Int), for_stmt, env),
+        (None, Value::Object(ref map)) => evaluate_for_object(map, for_stmt, env),
+        (None, Value::Library(ref lib) | Value::Enum { payload: ref lib, .. }) => evaluate_for_object(lib, for_stmt, env),
+        (None, Value::Array(arr)) => evaluate_for_array(arr, for_stmt, env),
+        (None, other) => Err(ZekkenError::type_error(
+            "For loop must iterate over an object or array",
+            "object or array",
+            value_type_name(&other),
             for_stmt.location.line,
-            for_stmt.location.column,
-            None
-        ))
+            for_stmt.location.column
+        ).with_value(&other))
+    };
+    // Reversed, so `|x, x|` puts back the outer `x` last.
+    for (name, saved) in shadowed.into_iter().rev() {
+        env.restore_binding(name, saved);
     }
+    result
 }
 
 // Handle while statements
@@ -1188,6 +1481,7 @@ fn evaluate_while_statement(while_stmt: &WhileStmt, env: &mut Environment) -> Re
     }
 
     let body_may_return = block_has_return(&while_stmt.body);
+    let mut cap = IterationCap::new();
 
     if let Some(cond) = build_numeric_cond(&while_stmt.test) {
         let mut result = None;
@@ -1201,25 +1495,7 @@ fn evaluate_while_statement(while_stmt: &WhileStmt, env: &mut Environment) -> Re
                     NumCondOp::Eq => l == r,
                     NumCondOp::Neq => l != r,
                 }
-            } else {
-                let l = as_num_value(&cond.left, env).ok_or_else(|| {
-                    ZekkenError::type_error(
-                        "While loop condition must evaluate to a boolean",
-                        "bool",
-                        "non-boolean",
-                        while_stmt.location.line,
-                        while_stmt.location.column,
-                    )
-                })?;
-                let r = as_num_value(&cond.right, env).ok_or_else(|| {
-                    ZekkenError::type_error(
-                        "While loop condition must evaluate to a boolean",
-                        "bool",
-                        "non-boolean",
-                        while_stmt.location.line,
-                        while_stmt.location.column,
-                    )
-                })?;
+            } else if let (Some(l), Some(r)) = (as_num_value(&cond.left, env), as_num_value(&cond.right, env)) {
                 match cond.op {
                     NumCondOp::Lt => l < r,
                     NumCondOp::Lte => l <= r,
@@ -1228,16 +1504,39 @@ fn evaluate_while_statement(while_stmt: &WhileStmt, env: &mut Environment) -> Re
                     NumCondOp::Eq => l == r,
                     NumCondOp::Neq => l != r,
                 }
+            } else {
+                // Not plain ints and floats (a `dec`, say): evaluate the test as written.
+                match evaluate_expression(&while_stmt.test, env)? {
+                    Value::Boolean(b) => b,
+                    _ => {
+                        return Err(ZekkenError::type_error(
+                            "While loop condition must evaluate to a boolean",
+                            "bool",
+                            "non-boolean",
+                            while_stmt.location.line,
+                            while_stmt.location.column,
+                        ))
+                    }
+                }
             };
 
             if !test_true {
                 break;
             }
+            if !cap.tick() {
+                return Err(cap.error(&while_stmt.location));
+            }
             if body_may_return {
                 result = evaluate_block_content(&while_stmt.body, env)?;
             } else {
                 evaluate_block_discard(&while_stmt.body, env)?;
             }
+            if env.stop_loop(while_stmt.label.as_deref()) {
+                return Ok(result);
+            }
+        }
+        if let Some(else_body) = &while_stmt.else_body {
+            result = evaluate_block_content(else_body, env)?;
         }
         return Ok(result);
     }
@@ -1247,11 +1546,17 @@ fn evaluate_while_statement(while_stmt: &WhileStmt, env: &mut Environment) -> Re
         let test_result = evaluate_expression(&while_stmt.test, env)?;
         match test_result {
             Value::Boolean(true) => {
+                if !cap.tick() {
+                    return Err(cap.error(&while_stmt.location));
+                }
                 if body_may_return {
                     result = evaluate_block_content(&while_stmt.body, env)?;
                 } else {
                     evaluate_block_discard(&while_stmt.body, env)?;
                 }
+                if env.stop_loop(while_stmt.label.as_deref()) {
+                    return Ok(result);
+                }
             }
             Value::Boolean(false) => break,
             _ => {
@@ -1261,10 +1566,13 @@ fn evaluate_while_statement(while_stmt: &WhileStmt, env: &mut Environment) -> Re
                     value_type_name(&test_result),
                     while_stmt.location.line,
                     while_stmt.location.column
-                ))
+                ).with_value(&test_result))
             }
         }
     }
+    if let Some(else_body) = &while_stmt.else_body {
+        result = evaluate_block_content(else_body, env)?;
+    }
     Ok(result)
 }
 
@@ -1274,13 +1582,19 @@ fn evaluate_try_catch(try_catch: &TryCatchStmt, env: &mut Environment) -> Result
         Ok(value) => Ok(value),
         Err(error) => {
             if let Some(catch_block) = &try_catch.catch_block {
-                let mut err_obj = HashMap::new();
+                crate::trace::error(&error, true);
+                let mut err_obj = IndexMap::new();
                 err_obj.insert("message".to_string(), Value::String(error.message.clone()));
                 err_obj.insert("kind".to_string(), Value::String(format!("{:?}", error.kind)));
                 err_obj.insert("line".to_string(), Value::Int(error.context.line as i64));
                 err_obj.insert("column".to_string(), Value::Int(error.context.column as i64));
+                err_obj.insert("code".to_string(), error.code.map_or(Value::Null, |code| Value::String(code.code.to_string())));
+                err_obj.insert("retryable".to_string(), Value::Boolean(error.code.is_some_and(|code| code.retryable)));
                 // Add the pretty error string for display
                 err_obj.insert("__zekken_error__".to_string(), Value::String(error.to_string()));
+                if let Some(method) = crate::eval::env_eval::catch_method(env) {
+                    err_obj.insert("env_eval".to_string(), method);
+                }
 
                 let prev_var = env.variables.remove("e");
                 let prev_const = env.constants.remove("e");
@@ -1310,8 +1624,10 @@ fn stmt_has_return(stmt: &Stmt) -> bool {
         Stmt::IfStmt(i) => {
             block_has_return(&i.body) || i.alt.as_ref().map(|b| block_has_return(b)).unwrap_or(false)
         }
-        Stmt::ForStmt(f) => block_has_return(&f.body),
-        Stmt::WhileStmt(w) => block_has_return(&w.body),
+        Stmt::ForStmt(f) => block_has_return(&f.body) || f.else_body.as_ref().map(|b| block_has_return(b)).unwrap_or(false),
+        Stmt::ForIn(f) => block_has_return(&f.body) || f.else_body.as_ref().map(|b| block_has_return(b)).unwrap_or(false),
+        Stmt::WhileStmt(w) => block_has_return(&w.body) || w.else_body.as_ref().map(|b| block_has_return(b)).unwrap_or(false),
+        Stmt::Loop(l) => block_has_return(&l.body),
         Stmt::TryCatchStmt(t) => {
             block_has_return(&t.try_block)
                 || t.catch_block
@@ -1320,15 +1636,21 @@ fn stmt_has_return(stmt: &Stmt) -> bool {
                     .unwrap_or(false)
         }
         Stmt::BlockStmt(b) => block_has_return(&b.body),
+        Stmt::With(w) => block_has_return(&w.body),
+        Stmt::Switch(s) => s.bodies().any(|body| block_has_return(body)),
         Stmt::Program(p) => {
-            p.imports.iter().any(|c| content_has_return(c))
-                || p.content.iter().any(|c| content_has_return(c))
+            p.imports.iter().any(content_has_return)
+                || p.content.iter().any(content_has_return)
         }
         // Nested function/lambda returns do not affect outer control flow.
         Stmt::FuncDecl(_)
         | Stmt::Lambda(_)
+        | Stmt::Break(_)
+        | Stmt::Continue(_)
         | Stmt::VarDecl(_)
         | Stmt::ObjectDecl(_)
+        | Stmt::EnumDecl(_)
+        | Stmt::Defer(_)
         | Stmt::Use(_)
         | Stmt::Include(_)
         | Stmt::Export(_) => false,
@@ -1342,13 +1664,14 @@ fn content_has_return(content: &Content) -> bool {
     }
 }
 
-fn block_has_return(content: &[Box<Content>]) -> bool {
-    content.iter().any(|c| content_has_return(c))
+fn block_has_return(content: &[Content]) -> bool {
+    content.iter().any(content_has_return)
 }
 
-fn evaluate_block_discard(content: &[Box<Content>], env: &mut Environment) -> Result<(), ZekkenError> {
+fn evaluate_block_discard(content: &[Content], env: &mut Environment) -> Result<(), ZekkenError> {
     for item in content {
-        match item.as_ref() {
+        crate::coverage::content(item);
+        match item {
             Content::Statement(stmt) => {
                 let _ = evaluate_statement(stmt, env)?;
             }
@@ -1359,6 +1682,9 @@ fn evaluate_block_discard(content: &[Box<Content>], env: &mut Environment) -> Re
                 }
             },
         }
+        if env.control_flow.is_some() {
+            break;
+        }
     }
     Ok(())
 }
@@ -1369,13 +1695,14 @@ fn evaluate_block(block: &BlockStmt, env: &mut Environment) -> Result<Option<Val
 }
 
 // Handle code block content
-fn evaluate_block_content(content: &[Box<Content>], env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
+pub(super) fn evaluate_block_content(content: &[Content], env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
     if content.is_empty() {
         return Ok(None);
     }
 
     if content.len() == 1 {
-        return match content[0].as_ref() {
+        crate::coverage::content(&content[0]);
+        return match &content[0] {
             Content::Statement(stmt) => evaluate_statement(stmt, env),
             Content::Expression(expr) => Ok(Some(evaluate_expression(expr, env)?)),
         };
@@ -1384,7 +1711,8 @@ fn evaluate_block_content(content: &[Box<Content>], env: &mut Environment) -> Re
     let (last, rest) = content.split_last().unwrap();
 
     for item in rest {
-        match item.as_ref() {
+        crate::coverage::content(item);
+        match item {
             Content::Statement(stmt) => {
                 let _ = evaluate_statement(stmt, env)?;
             }
@@ -1397,9 +1725,13 @@ fn evaluate_block_content(content: &[Box<Content>], env: &mut Environment) -> Re
                 }
             },
         }
+        if env.control_flow.is_some() {
+            return Ok(None);
+        }
     }
 
-    match last.as_ref() {
+    crate::coverage::content(last);
+    match last {
         Content::Statement(stmt) => {
             evaluate_statement(stmt, env)
         }
@@ -1409,23 +1741,44 @@ fn evaluate_block_content(content: &[Box<Content>], env: &mut Environment) -> Re
     }
 }
 
-// Handle return values in functions
+// Handle return values in functions. The value travels in `env.control_flow`
+// so every enclosing block and loop stops until the call takes it.
 fn evaluate_return(ret: &ReturnStmt, env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
-    match &ret.value {
+    let value = match &ret.value {
         Some(content) => match &**content {
-            Content::Expression(expr) => {
-                let value = evaluate_expression(expr, env)?;
-                Ok(Some(value))
-            },
-            Content::Statement(stmt) => evaluate_statement(stmt, env),
+            Content::Expression(expr) => evaluate_expression(expr, env)?,
+            Content::Statement(stmt) => {
+                let value = evaluate_statement(stmt, env)?;
+                if env.control_flow.is_some() {
+                    return Ok(None);
+                }
+                value.unwrap_or(Value::Void)
+            }
         },
-        None => Ok(Some(Value::Void)),
-    }
+        None => Value::Void,
+    };
+    env.control_flow = Some(ControlFlow::Return(value));
+    Ok(None)
 }
 
 // Handle lambda expressions
 fn evaluate_lambda(lambda: &LambdaDecl, env: &mut Environment) -> Result<Option<Value>, ZekkenError> {
-    let usage = analyze_function_parent_usage(&lambda.params, &lambda.body);
+    let function_value = lambda_value(&lambda.ident, &lambda.params, &lambda.body, lambda.return_type.clone(), env);
+    env.declare(lambda.ident.clone(), Value::Function(function_value), lambda.constant);
+    Ok(None)
+}
+
+/// The function value of a lambda or function named `name`, capturing what
+/// its body reads from `env`. Locals of the call it is created in are
+/// shared with it rather than copied; see [`Environment::share_with`].
+pub(crate) fn lambda_value(
+    name: &str,
+    params: &[Param],
+    body: &[Content],
+    return_type: Option<DataType>,
+    env: &mut Environment,
+) -> FunctionValue {
+    let usage = analyze_function_parent_usage(params, body);
     let captures = if usage.requires_parent_clone {
         vec![]
     } else {
@@ -1433,24 +1786,25 @@ fn evaluate_lambda(lambda: &LambdaDecl, env: &mut Environment) -> Result<Option<
         v.sort_unstable();
         v
     };
+    let closure = env.share_with(name, params, body);
     let capture_values = captures
         .iter()
-        .filter(|name| name.as_str() != lambda.ident)
-        .filter_map(|name| env.lookup_ref(name).map(|value| (name.clone(), value.clone())))
+        .filter(|capture| capture.as_str() != name)
+        .filter(|capture| !closure.as_ref().is_some_and(|closure| closure.names().contains(capture)))
+        .filter_map(|capture| env.lookup_ref(capture).map(|value| (capture.clone(), value.clone())))
         .collect();
-    let function_value = FunctionValue {
-        params: Arc::new(lambda.params.clone()),
-        body: Arc::new(lambda.body.clone()),
-        return_type: lambda.return_type,
+    FunctionValue {
+        name: Arc::from(name),
+        params: Arc::new(params.to_vec()),
+        body: Arc::new(body.to_vec()),
+        return_type,
         needs_parent: usage.requires_parent_clone,
         captures: Arc::new(captures),
         capture_values: Arc::new(capture_values),
         compiled_insts: None,
         compiled_reg_count: 0,
-    };
-
-    env.declare(lambda.ident.clone(), Value::Function(function_value), lambda.constant);
-    Ok(None)
+        closure,
+    }
 }
 
 // Handle use statements for importing libraries
@@ -1460,7 +1814,8 @@ fn evaluate_use(use_stmt: &UseStmt, env: &mut Environment) -> Result<Option<Valu
             // If specific methods are requested, extract them from the library object
             if let Some(methods) = &use_stmt.methods {
                 // Get the library object
-                if let Some(Value::Object(lib_obj)) = env.lookup(&use_stmt.module) {
+                let library = env.lookup(&use_stmt.module);
+                if let Some(lib_obj) = library.as_ref().and_then(Value::as_object) {
                     // Import each requested method directly into the target environment
                     for method in methods {
                         if let Some(value) = lib_obj.get(method) {
@@ -1523,7 +1878,7 @@ fn evaluate_include(include: &IncludeStmt, env: &mut Environment) -> Result<Opti
     std::env::set_var("ZEKKEN_CURRENT_FILE", &file_path);
 
     let mut parser = Parser::new();
-    let included_ast = parser.produce_ast(file_contents);
+    let mut included_ast = parser.produce_ast_cached(file_contents);
     if !parser.errors.is_empty() {
         for parse_error in parser.errors {
             push_error(parse_error);
@@ -1537,6 +1892,11 @@ fn evaluate_include(include: &IncludeStmt, env: &mut Environment) -> Result<Opti
         ));
     }
 
+    let libraries = library_names(&included_ast);
+    if include.lazy {
+        keep_declarations(&mut included_ast);
+    }
+
     // Create a new child environment with current env as parent
     let mut child_env = Environment::new_with_parent(env.clone());
 
@@ -1548,27 +1908,12 @@ fn evaluate_include(include: &IncludeStmt, env: &mut Environment) -> Result<Opti
 
     result?;
 
-    // Copy exported methods or all variables from child_env to current env
-    match &include.methods {
-        Some(methods) => {
-            for method in methods {
-                if let Some(value) = child_env.lookup(method) {
-                    env.declare(method.clone(), value, false);
-                } else {
-                    return Err(ZekkenError::runtime(
-                        &format!("Method '{}' not found in included file", method),
-                        include.location.line,
-                        include.location.column,
-                        None,
-                    ));
-                }
-            }
-        }
-        None => {
-            for (name, value) in &child_env.variables {
-                env.declare(name.clone(), value.clone(), false);
-            }
-        }
+    // Copy the requested or public bindings from child_env to current env
+    let bindings = included_bindings(include, &libraries, &child_env)
+        .map_err(|msg| ZekkenError::runtime(&msg, include.location.line, include.location.column, None))?;
+    trace_include(&file_path, &bindings);
+    for (name, value) in bindings {
+        env.declare(name, value, false);
     }
 
     Ok(None)
@@ -1601,85 +1946,52 @@ fn set_or_declare_loop_var(env: &mut Environment, name: &str, value: Value) {
 
 // Handle for loop iterations over objects
 fn evaluate_for_object(
-    map: &HashMap<String, Value>,
-    var_decl: &VarDecl,
-    body: &[Box<Content>],
+    map: &IndexMap<String, Value>,
+    for_stmt: &ForInStmt,
     env: &mut Environment
 ) -> Result<Option<Value>, ZekkenError> {
-    // Extract key and value identifiers
-    let idents: Vec<String> = var_decl.ident.split(", ").map(|s| s.to_string()).collect();
+    let idents = &for_stmt.idents;
     if idents.len() != 2 {
         return Err(ZekkenError::syntax(
             "Object iteration requires two identifiers (key, value)",
-            var_decl.location.line,
-            var_decl.location.column,
+            for_stmt.location.line,
+            for_stmt.location.column,
             None,
             None,
         ));
     }
     
-    // Get the keys array from the object
-    let keys = if let Some(Value::Array(keys)) = map.get("__keys__") {
-        keys
-    } else {
-        return Err(ZekkenError::type_error(
-            "Object missing key order",
-            "array",
-            "missing",
-            var_decl.location.line,
-            var_decl.location.column,
-        ));
-    };
-
-    // Match array iteration semantics: bind/update loop vars in the *current* env.
-    //
-    // This avoids cloning environments per iteration (performance) and makes the value
-    // identifier usable in nested statements (e.g. `for ... in value { ... }`, `let x = value.first`).
+    // Loop vars live in the *current* env, like array iteration, rather than a
+    // cloned child env per iteration.
     set_or_declare_loop_var(env, &idents[0], Value::String(String::new()));
     set_or_declare_loop_var(env, &idents[1], Value::Void);
 
-    for key_val in keys {
-        if let Value::String(ref key) = key_val {
-            if let Some(value) = map.get(key) {
-                // Check if the value matches the declared type
-                if var_decl.type_ != DataType::Any && !check_value_type(value, &var_decl.type_) {
-                    return Err(ZekkenError::type_error(
-                        &format!("Type mismatch in for loop value: expected {:?}, found {}", var_decl.type_, value_type_name(value)),
-                        &format!("{:?}", var_decl.type_),
-                        value_type_name(value),
-                        var_decl.location.line,
-                        var_decl.location.column
-                    ));
-                }
-
-                set_or_declare_loop_var(env, &idents[0], Value::String(key.clone()));
-                set_or_declare_loop_var(env, &idents[1], value.clone());
-                evaluate_block_content(body, env)?;
-            }
+    for (key, value) in map.iter().filter(|(key, value)| !is_internal_entry(key, value)) {
+        set_or_declare_loop_var(env, &idents[0], Value::String(key.clone()));
+        set_or_declare_loop_var(env, &idents[1], value.clone());
+        evaluate_block_content(&for_stmt.body, env)?;
+        if env.stop_loop(for_stmt.label.as_deref()) {
+            return Ok(None);
         }
     }
-    Ok(None)
+    match &for_stmt.else_body {
+        Some(else_body) => evaluate_block_content(else_body, env),
+        None => Ok(None),
+    }
 }
 
 // Handle for loop iterations over arrays
 fn evaluate_for_array(
-    arr: Vec<Value>,
-    var_decl: &VarDecl,
-    body: &[Box<Content>],
+    arr: impl IntoIterator<Item = Value>,
+    for_stmt: &ForInStmt,
     env: &mut Environment
 ) -> Result<Option<Value>, ZekkenError> {
-    let idents: Vec<String> = var_decl
-        .ident
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .collect();
-
+    let idents = &for_stmt.idents;
     if idents.is_empty() || idents.len() > 2 {
         return Err(ZekkenError::syntax(
             "Array iteration requires one or two identifiers",
-            var_decl.location.line,
-            var_decl.location.column,
+            for_stmt.location.line,
+            for_stmt.location.column,
             None,
             None,
         ));
@@ -1692,14 +2004,20 @@ fn evaluate_for_array(
         set_or_declare_loop_var(env, &idents[1], Value::Void);
     }
 
-    for (index, value) in arr.iter().enumerate() {
+    for (index, value) in arr.into_iter().enumerate() {
         if idents.len() == 1 {
-            set_or_declare_loop_var(env, &idents[0], value.clone());
+            set_or_declare_loop_var(env, &idents[0], value);
         } else {
             set_or_declare_loop_var(env, &idents[0], Value::Int(index as i64));
-            set_or_declare_loop_var(env, &idents[1], value.clone());
+            set_or_declare_loop_var(env, &idents[1], value);
+        }
+        evaluate_block_content(&for_stmt.body, env)?;
+        if env.stop_loop(for_stmt.label.as_deref()) {
+            return Ok(None);
         }
-        evaluate_block_content(body, env)?;
     }
-    Ok(None)
+    match &for_stmt.else_body {
+        Some(else_body) => evaluate_block_content(else_body, env),
+        None => Ok(None),
+    }
 }
@@ -0,0 +1,270 @@
+//! Whole-program dead-code report for `zekken check --unused`.
+//!
+//! Starting from the entry script, every included file is loaded and the
+//! top-level code of each file is treated as a root. Functions become
+//! reachable when a reachable body names them; names are resolved globally,
+//! since `include` copies declarations into the including scope. Shadowing is
+//! not modelled, so a name that is used anywhere keeps every definition with
+//! that name alive.
+
+// Only the CLI runs this pass; the wasm build never reaches it.
+#![allow(dead_code)]
+
+use crate::ast::visit::{walk_expr, Visitor};
+use crate::ast::*;
+use crate::errors::ZekkenError;
+use crate::eval::include::is_private;
+use crate::eval::lint::report_lint;
+use crate::parser::Parser;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+struct SourceFile {
+    path: PathBuf,
+    program: Program,
+}
+
+struct IncludeEdge {
+    from: usize,
+    to: usize,
+    methods: Option<Vec<String>>,
+    location: Location,
+}
+
+struct FunctionDef<'a> {
+    file: usize,
+    name: &'a str,
+    params: &'a [Param],
+    body: &'a [Content],
+    location: &'a Location,
+}
+
+#[derive(Default)]
+struct References(HashSet<String>);
+
+impl Visitor for References {
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(ident) => {
+                self.0.insert(ident.name.clone());
+            }
+            _ => walk_expr(self, expr),
+        }
+    }
+}
+
+fn load_files(entry: &Path) -> Result<(Vec<SourceFile>, Vec<IncludeEdge>), String> {
+    let mut files: Vec<SourceFile> = Vec::new();
+    let mut edges = Vec::new();
+    let mut index: HashMap<PathBuf, usize> = HashMap::new();
+
+    let source = std::fs::read_to_string(entry)
+        .map_err(|e| format!("Error reading file {}: {}", entry.display(), e))?;
+    index.insert(entry.canonicalize().unwrap_or_else(|_| entry.to_path_buf()), 0);
+    files.push(SourceFile { path: entry.to_path_buf(), program: Parser::new().produce_ast(source) });
+
+    let mut next = 0;
+    while next < files.len() {
+        let dir = files[next].path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let includes: Vec<IncludeStmt> = files[next]
+            .program
+            .imports
+            .iter()
+            .filter_map(|import| match import {
+                Content::Statement(stmt) => match stmt.as_ref() {
+                    Stmt::Include(include) => Some(include.clone()),
+                    _ => None,
+                },
+                Content::Expression(_) => None,
+            })
+            .collect();
+
+        for include in includes {
+            let path = dir.join(&include.file_path);
+            let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+            let to = match index.get(&key) {
+                Some(&to) => to,
+                None => {
+                    // Missing files are reported by the regular check.
+                    let Ok(source) = std::fs::read_to_string(&path) else { continue };
+                    files.push(SourceFile { path, program: Parser::new().produce_ast(source) });
+                    index.insert(key, files.len() - 1);
+                    files.len() - 1
+                }
+            };
+            edges.push(IncludeEdge { from: next, to, methods: include.methods, location: include.location });
+        }
+        next += 1;
+    }
+
+    Ok((files, edges))
+}
+
+fn declared_name(content: &Content) -> Option<&str> {
+    match content {
+        Content::Statement(stmt) => match stmt.as_ref() {
+            Stmt::VarDecl(decl) => Some(&decl.ident),
+            Stmt::FuncDecl(decl) => Some(&decl.ident),
+            Stmt::Lambda(decl) => Some(&decl.ident),
+            Stmt::ObjectDecl(decl) => Some(&decl.ident),
+            Stmt::EnumDecl(decl) => Some(&decl.ident),
+            _ => None,
+        },
+        Content::Expression(_) => None,
+    }
+}
+
+// Names a file brings into scope for whoever includes it, including the ones
+// it re-exposes from its own includes.
+fn exposed_names(file: usize, files: &[SourceFile], edges: &[IncludeEdge], seen: &mut HashSet<usize>) -> HashSet<String> {
+    let mut names = HashSet::new();
+    if !seen.insert(file) {
+        return names;
+    }
+    for content in &files[file].program.content {
+        if let Some(name) = declared_name(content).filter(|name| !is_private(name)) {
+            names.insert(name.to_string());
+        }
+    }
+    for edge in edges.iter().filter(|edge| edge.from == file) {
+        let nested = exposed_names(edge.to, files, edges, seen);
+        match &edge.methods {
+            Some(methods) => names.extend(methods.iter().filter(|m| nested.contains(*m)).cloned()),
+            None => names.extend(nested),
+        }
+    }
+    names
+}
+
+fn report(file: &Path, lint: &'static str, message: &str, kind: &str, location: &Location, errors: &mut Vec<ZekkenError>) {
+    // Error context reads the source line from the current file.
+    let previous = std::env::var("ZEKKEN_CURRENT_FILE").ok();
+    std::env::set_var("ZEKKEN_CURRENT_FILE", file);
+    report_lint(lint, ZekkenError::reference(message, kind, location.line, location.column), errors);
+    match previous {
+        Some(previous) => std::env::set_var("ZEKKEN_CURRENT_FILE", previous),
+        None => std::env::remove_var("ZEKKEN_CURRENT_FILE"),
+    }
+}
+
+/// Reports top-level functions, exports and includes that nothing reachable
+/// from `entry` refers to, as the `unused-function`, `unused-export` and
+/// `unused-include` lints.
+pub fn collect_unused(entry: &Path, errors: &mut Vec<ZekkenError>) -> Result<(), String> {
+    let (files, edges) = load_files(entry)?;
+
+    let mut functions = Vec::new();
+    let mut refs: Vec<References> = files.iter().map(|_| References::default()).collect();
+    for (idx, file) in files.iter().enumerate() {
+        for content in &file.program.content {
+            match content {
+                Content::Statement(stmt) => match stmt.as_ref() {
+                    Stmt::FuncDecl(decl) => functions.push(FunctionDef {
+                        file: idx,
+                        name: &decl.ident,
+                        params: &decl.params,
+                        body: &decl.body,
+                        location: &decl.location,
+                    }),
+                    Stmt::Lambda(decl) => functions.push(FunctionDef {
+                        file: idx,
+                        name: &decl.ident,
+                        params: &decl.params,
+                        body: &decl.body,
+                        location: &decl.location,
+                    }),
+                    _ => refs[idx].visit_content(content),
+                },
+                Content::Expression(_) => refs[idx].visit_content(content),
+            }
+        }
+    }
+
+    // Walk function bodies until no new names show up.
+    let mut reachable = vec![false; functions.len()];
+    loop {
+        let used: HashSet<String> = refs.iter().flat_map(|r| r.0.iter().cloned()).collect();
+        let mut changed = false;
+        for (idx, function) in functions.iter().enumerate() {
+            if reachable[idx] || !used.contains(function.name) {
+                continue;
+            }
+            reachable[idx] = true;
+            changed = true;
+            for param in function.params {
+                refs[function.file].visit_param(param);
+            }
+            refs[function.file].visit_block(function.body);
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    // Names referenced from outside each file.
+    let used_outside = |file: usize| -> HashSet<&str> {
+        refs.iter()
+            .enumerate()
+            .filter(|(idx, _)| *idx != file)
+            .flat_map(|(_, r)| r.0.iter().map(String::as_str))
+            .collect()
+    };
+
+    for (idx, function) in functions.iter().enumerate() {
+        if !reachable[idx] {
+            report(
+                &files[function.file].path,
+                "unused-function",
+                &format!("Function '{}' is never used", function.name),
+                "function",
+                function.location,
+                errors,
+            );
+        }
+    }
+
+    for (idx, file) in files.iter().enumerate() {
+        // Exports only matter to files that include this one.
+        if !edges.iter().any(|edge| edge.to == idx) {
+            continue;
+        }
+        let outside = used_outside(idx);
+        for content in &file.program.content {
+            if let Content::Statement(stmt) = content {
+                if let Stmt::Export(export) = stmt.as_ref() {
+                    for name in export.exports.iter().filter(|name| !outside.contains(name.as_str())) {
+                        report(
+                            &file.path,
+                            "unused-export",
+                            &format!("Export '{}' is never used by an including file", name),
+                            "export",
+                            &export.location,
+                            errors,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    for edge in &edges {
+        let outside = used_outside(edge.to);
+        let exposed = exposed_names(edge.to, &files, &edges, &mut HashSet::new());
+        let requested: Vec<&String> = match &edge.methods {
+            Some(methods) => methods.iter().collect(),
+            None => exposed.iter().collect(),
+        };
+        if !requested.iter().any(|name| outside.contains(name.as_str())) {
+            report(
+                &files[edge.from].path,
+                "unused-include",
+                &format!("Nothing from included file '{}' is used", files[edge.to].path.display()),
+                "include",
+                &edge.location,
+                errors,
+            );
+        }
+    }
+
+    Ok(())
+}
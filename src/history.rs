@@ -0,0 +1,106 @@
+//! Per-variable assignment history for `zekken debug step`.
+//!
+//! While recording, every declaration and assignment appends the variable's
+//! new value and the source location that wrote it, so the stepper's
+//! `history x` can show how `x` changed over a whole loop rather than just
+//! its value after the statement. Recording is off unless the stepper turns
+//! it on, and then costs a thread-local check per write.
+
+// Only the CLI's stepper records; the wasm build never does.
+#![allow(dead_code)]
+
+use crate::ast::{Expr, Location};
+use crate::environment::{Environment, Value};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display, Formatter};
+
+/// Changes kept per variable unless the stepper asks for another cap.
+pub const DEFAULT_CAP: usize = 100;
+
+/// One write to a variable.
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub value: Value,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// The most recent changes to one variable, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    pub changes: VecDeque<Change>,
+    /// Older changes dropped to stay within the cap.
+    pub dropped: usize,
+}
+
+thread_local! {
+    static CAP: Cell<usize> = const { Cell::new(0) };
+    static HISTORIES: RefCell<HashMap<String, History>> = RefCell::new(HashMap::new());
+}
+
+/// Starts recording, keeping at most `cap` changes per variable. A cap of
+/// zero stops recording.
+pub fn record_with_cap(cap: usize) {
+    CAP.with(|c| c.set(cap));
+    HISTORIES.with(|histories| histories.borrow_mut().clear());
+}
+
+#[inline]
+pub fn is_recording() -> bool {
+    CAP.with(|c| c.get() > 0)
+}
+
+/// Notes that `name` now holds `value`, written at `location`.
+#[inline]
+pub fn record(name: &str, value: &Value, location: &Location) {
+    crate::trace::assign(name, value, location);
+    let cap = CAP.with(Cell::get);
+    if cap == 0 {
+        return;
+    }
+    HISTORIES.with(|histories| {
+        let mut histories = histories.borrow_mut();
+        let history = histories.entry(name.to_string()).or_default();
+        if history.changes.len() == cap {
+            history.changes.pop_front();
+            history.dropped += 1;
+        }
+        history.changes.push_back(Change { value: value.clone(), line: location.line, column: location.column });
+    });
+}
+
+/// Records the variable an assignment to `target` changed: the identifier
+/// itself, or the variable at the root of `a.b[0] = ...`.
+#[inline]
+pub fn record_assignment(target: &Expr, location: &Location, env: &Environment) {
+    if !is_recording() && !crate::trace::is_recording() {
+        return;
+    }
+    let mut root = target;
+    while let Expr::Member(member) = root {
+        root = &member.object;
+    }
+    if let Expr::Identifier(ident) = root {
+        if let Some(value) = env.lookup_ref(&ident.name) {
+            record(&ident.name, value, location);
+        }
+    }
+}
+
+/// The recorded history of `name`, if it was ever written while recording.
+pub fn of(name: &str) -> Option<History> {
+    HISTORIES.with(|histories| histories.borrow().get(name).cloned())
+}
+
+impl Display for History {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.dropped > 0 {
+            writeln!(f, "  ... {} earlier change(s) not kept", self.dropped)?;
+        }
+        for change in &self.changes {
+            writeln!(f, "  line {:>4}, col {:>3}: {}", change.line, change.column, change.value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+//! Syntax-highlight classes for source text, straight from the lexer.
+//!
+//! The playground colours its editor with these spans instead of
+//! approximating the grammar with regexes. Class names match the editor's
+//! CodeMirror mode (`keyword`, `keyword-control`, `string`, ...).
+
+use super::{lex_with_comments, Token, TokenType};
+use serde::Serialize;
+
+/// One classified token. Lines and columns are 1-based and count
+/// characters; the end is exclusive, so a token on one line covers
+/// `column..end_column`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HighlightSpan {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    pub class: &'static str,
+}
+
+fn class_of(tokens: &[Token], index: usize) -> Option<&'static str> {
+    let previous = index.checked_sub(1).map(|i| &tokens[i].kind);
+    let next = tokens.get(index + 1).map(|token| &token.kind);
+    let class = match tokens[index].kind {
+        TokenType::SingleLineComment | TokenType::MultiLineComment | TokenType::DocComment => "comment",
+        TokenType::String | TokenType::RawString => "string",
+        TokenType::Int | TokenType::Float | TokenType::Decimal => "number",
+        TokenType::Boolean(_) | TokenType::Null => "boolean",
+        TokenType::DataType(_) | TokenType::Question => "type",
+        TokenType::If
+        | TokenType::Else
+        | TokenType::For
+        | TokenType::While
+        | TokenType::Loop
+        | TokenType::Break
+        | TokenType::Continue
+        | TokenType::Return
+        | TokenType::Try
+        | TokenType::Catch
+        | TokenType::Match
+        | TokenType::Defer
+        | TokenType::With
+        | TokenType::Switch => "keyword-control",
+        TokenType::Let
+        | TokenType::Const
+        | TokenType::Func
+        | TokenType::Use
+        | TokenType::Include
+        | TokenType::Export
+        | TokenType::In
+        | TokenType::From
+        | TokenType::Enum
+        | TokenType::As => "keyword",
+        TokenType::Identifier => match (previous, next) {
+            (Some(TokenType::At), _) => "builtin",
+            (Some(TokenType::Func), _) | (_, Some(TokenType::FatArrow)) => "function",
+            (Some(TokenType::Dot), _) => "property",
+            _ => "variable",
+        },
+        TokenType::ArithOp(_)
+        | TokenType::BinOp(_)
+        | TokenType::AssignOp(_)
+        | TokenType::ThinArrow
+        | TokenType::FatArrow
+        | TokenType::Pipe
+        | TokenType::Ampersand
+        | TokenType::Dot
+        | TokenType::DotDot => "operator",
+        TokenType::Comma
+        | TokenType::Colon
+        | TokenType::Semicolon
+        | TokenType::OpenParen
+        | TokenType::CloseParen
+        | TokenType::OpenBrace
+        | TokenType::CloseBrace
+        | TokenType::OpenBracket
+        | TokenType::CloseBracket
+        | TokenType::Hash => "punctuation",
+        // `@` stays unstyled; the builtin name after it carries the colour.
+        TokenType::At
+        | TokenType::SingleQuote
+        | TokenType::DoubleQuote
+        | TokenType::Undefined
+        | TokenType::EOF => return None,
+    };
+    Some(class)
+}
+
+/// Highlight spans for every token in `source`, comments included, in source
+/// order. Text the lexer rejects is still classified as far as it got, so
+/// half-typed code keeps its colours.
+pub fn highlight(source: &str) -> Vec<HighlightSpan> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut line_starts = vec![0];
+    line_starts.extend(chars.iter().enumerate().filter(|(_, c)| **c == '\n').map(|(i, _)| i + 1));
+
+    let (tokens, _) = lex_with_comments(source.to_string());
+    let mut spans = Vec::with_capacity(tokens.len());
+    for (index, token) in tokens.iter().enumerate() {
+        let Some(class) = class_of(&tokens, index) else {
+            continue;
+        };
+        let start = line_starts[token.line - 1] + token.column - 1;
+        let (mut end_line, mut end_column) = (token.line, token.column);
+        for ch in &chars[start..(start + token.length).min(chars.len())] {
+            if *ch == '\n' {
+                end_line += 1;
+                end_column = 1;
+            } else {
+                end_column += 1;
+            }
+        }
+        spans.push(HighlightSpan { line: token.line, column: token.column, end_line, end_column, class });
+    }
+    spans
+}
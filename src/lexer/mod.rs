@@ -1,6 +1,13 @@
 #![allow(dead_code)]
 
 use crate::ast::*;
+use crate::errors::ZekkenError;
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::OnceLock;
+
+pub mod highlight;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ArithOp {
@@ -34,7 +41,7 @@ pub enum AssignOp {
     ModAssign,      // %=
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DataType {
     Int,
     Float,
@@ -42,18 +49,45 @@ pub enum DataType {
     Bool,
     Object,
     Array,
+    /// `arr<T>`: an array whose elements are all `T`.
+    ArrayOf(Box<DataType>),
+    /// `T?`: a `T` or `null`.
+    Nullable(Box<DataType>),
     Fn,
+    Decimal,
     Any,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+// Type errors print the expected type this way, e.g. `Int` or `Array<Int>`.
+impl fmt::Debug for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataType::Int => write!(f, "Int"),
+            DataType::Float => write!(f, "Float"),
+            DataType::String => write!(f, "String"),
+            DataType::Bool => write!(f, "Bool"),
+            DataType::Object => write!(f, "Object"),
+            DataType::Array => write!(f, "Array"),
+            DataType::ArrayOf(element) => write!(f, "Array<{:?}>", element),
+            DataType::Nullable(inner) => write!(f, "{:?}?", inner),
+            DataType::Fn => write!(f, "Fn"),
+            DataType::Decimal => write!(f, "Decimal"),
+            DataType::Any => write!(f, "Any"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TokenType {
     // Literals
     Int,
     Float,
+    Decimal,
     Identifier,
     String,
+    RawString, // r"..."
     Boolean(bool),
+    Null,
 
     // Data Types
     DataType(DataType),
@@ -66,6 +100,9 @@ pub enum TokenType {
     Else,
     For,
     While,
+    Loop,
+    Break,
+    Continue,
     Use,
     Include,
     Export,
@@ -74,6 +111,12 @@ pub enum TokenType {
     Return,
     Try,
     Catch,
+    Enum,
+    Match,
+    Defer,
+    With,
+    Switch,
+    As,
     
     // Grouping
     At,
@@ -81,12 +124,18 @@ pub enum TokenType {
     Colon,
     Semicolon,
     Dot,
+    /// `..` between the bounds of a range.
+    DotDot,
     OpenParen,
     CloseParen,
     OpenBrace,
     CloseBrace,
     OpenBracket,
     CloseBracket,
+    /// Starts a `#[...]` vector or matrix literal.
+    Hash,
+    /// Marks a type as nullable, as in `int?`.
+    Question,
     SingleQuote,
     DoubleQuote,
 
@@ -102,6 +151,8 @@ pub enum TokenType {
     // Comments
     SingleLineComment,
     MultiLineComment,
+    /// `/// text`: documentation for the declaration that follows.
+    DocComment,
 
     Undefined,
 
@@ -118,6 +169,9 @@ pub static KEYWORDS: &[(&str, TokenType)] = &[
     ("else", TokenType::Else),
     ("for", TokenType::For),
     ("while", TokenType::While),
+    ("loop", TokenType::Loop),
+    ("break", TokenType::Break),
+    ("continue", TokenType::Continue),
     ("use", TokenType::Use),
     ("include", TokenType::Include),
     ("export", TokenType::Export),
@@ -126,17 +180,38 @@ pub static KEYWORDS: &[(&str, TokenType)] = &[
     ("return", TokenType::Return),
     ("try", TokenType::Try),
     ("catch", TokenType::Catch),
+    ("enum", TokenType::Enum),
+    ("match", TokenType::Match),
+    ("defer", TokenType::Defer),
+    ("with", TokenType::With),
+    ("switch", TokenType::Switch),
+    ("as", TokenType::As),
     ("int", TokenType::DataType(DataType::Int)),
     ("float", TokenType::DataType(DataType::Float)),
+    ("dec", TokenType::DataType(DataType::Decimal)),
     ("string", TokenType::DataType(DataType::String)),
     ("bool", TokenType::DataType(DataType::Bool)),
     ("obj", TokenType::DataType(DataType::Object)),
     ("arr", TokenType::DataType(DataType::Array)),
     ("fn", TokenType::DataType(DataType::Fn)),
+    ("any", TokenType::DataType(DataType::Any)),
     ("true", TokenType::Boolean(true)),
     ("false", TokenType::Boolean(false)),
+    ("null", TokenType::Null),
+];
+
+// Teaching-mode spellings for classrooms coming from Python-like pseudocode.
+// They are plain identifiers unless the file starts using them with an
+// `ALIAS_PRAGMA` comment or `Zekken.toml` sets `[language] aliases = true`.
+pub static ALIASES: &[(&str, TokenType)] = &[
+    ("function", TokenType::Func),
+    ("and", TokenType::BinOp(BinOp::And)),
+    ("or", TokenType::BinOp(BinOp::Or)),
+    ("not", TokenType::BinOp(BinOp::Not)),
 ];
 
+pub const ALIAS_PRAGMA: &str = "zekken: aliases";
+
 pub static TOKEN_CHAR: &[(&str, TokenType)] = &[
     ("@", TokenType::At),
     ("(", TokenType::OpenParen),
@@ -146,6 +221,7 @@ pub static TOKEN_CHAR: &[(&str, TokenType)] = &[
     ("[", TokenType::OpenBracket),
     ("]", TokenType::CloseBracket),
     (".", TokenType::Dot),
+    ("..", TokenType::DotDot),
     (";", TokenType::Semicolon),
     (":", TokenType::Colon),
     (",", TokenType::Comma),
@@ -177,6 +253,12 @@ pub static TOKEN_CHAR: &[(&str, TokenType)] = &[
     ("<", TokenType::BinOp(BinOp::Less)),
 ];
 
+impl TokenType {
+    pub fn is_comment(&self) -> bool {
+        matches!(self, TokenType::SingleLineComment | TokenType::MultiLineComment | TokenType::DocComment)
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct Token {
@@ -217,12 +299,28 @@ fn is_skippable(input: &str) -> bool {
 }
 
 pub fn tokenize(source: String) -> Vec<Token> {
+    lex(source).0
+}
+
+/// Tokens for `source` without comments, plus the syntax errors found while
+/// lexing it. The parser only ever sees these, so a comment can sit between
+/// any two tokens, such as between the arguments of a call.
+pub fn lex(source: String) -> (Vec<Token>, Vec<ZekkenError>) {
+    let (mut tokens, errors) = lex_with_comments(source);
+    tokens.retain(|token| !token.kind.is_comment());
+    (tokens, errors)
+}
+
+/// Like [`lex`], but keeps comment tokens for tools that show them.
+pub fn lex_with_comments(source: String) -> (Vec<Token>, Vec<ZekkenError>) {
     let src: Vec<char> = source.chars().collect();
     let mut tokens = Vec::new();
+    let mut errors = Vec::new();
     let mut index: usize = 0;
     let len = src.len();
     let mut line = 1;
     let mut column = 1;
+    let mut aliases = crate::config::aliases();
 
     while index < len {
         let c = src[index];
@@ -243,7 +341,9 @@ pub fn tokenize(source: String) -> Vec<Token> {
         }
 
         // Get token
-        if let Some((token, consumed)) = tokenize_char(&src, index, line, column) {
+        if let Some((token, consumed)) = tokenize_char(&src, index, line, column, &mut errors) {
+            // Lengths count source characters, escapes and quotes included.
+            let token = token.with_length(consumed);
             // Track position using consumed source chars, not token value formatting.
             for ch in &src[index..index + consumed] {
                 if *ch == '\n' {
@@ -253,9 +353,19 @@ pub fn tokenize(source: String) -> Vec<Token> {
                     column += 1;
                 }
             }
-            // Comments are not part of the language grammar; skip them entirely.
-            if !matches!(token.kind, TokenType::SingleLineComment | TokenType::MultiLineComment) {
-                tokens.push(token);
+            match token.kind {
+                TokenType::SingleLineComment => {
+                    aliases |= token.value.trim() == ALIAS_PRAGMA;
+                    tokens.push(token);
+                }
+                TokenType::Identifier if aliases => {
+                    let mut token = token;
+                    if let Some((_, kind)) = ALIASES.iter().find(|(alias, _)| *alias == token.value) {
+                        token.kind = kind.clone();
+                    }
+                    tokens.push(token);
+                }
+                _ => tokens.push(token),
             }
             index += consumed;
         } else {
@@ -265,10 +375,16 @@ pub fn tokenize(source: String) -> Vec<Token> {
     }
 
     tokens.push(Token::new("".to_string(), TokenType::EOF, line, column));
-    tokens
+    (tokens, errors)
 }
 
-fn tokenize_char(src: &[char], start: usize, line: usize, column: usize) -> Option<(Token, usize)> {
+fn tokenize_char(
+    src: &[char],
+    start: usize,
+    line: usize,
+    column: usize,
+    errors: &mut Vec<ZekkenError>,
+) -> Option<(Token, usize)> {
     let len = src.len();
     if start >= len {
         return None;
@@ -280,27 +396,54 @@ fn tokenize_char(src: &[char], start: usize, line: usize, column: usize) -> Opti
     if cur == '/' && start + 1 < len {
         let next_char = src[start + 1];
         if next_char == '/' {
-            let mut idx = start + 2;
+            // `///` starts a doc comment; `////` and longer runs are plain comments.
+            let is_doc = src.get(start + 2) == Some(&'/') && src.get(start + 3) != Some(&'/');
+            let mut idx = if is_doc { start + 3 } else { start + 2 };
             let mut content = String::new();
             while idx < len && src[idx] != '\n' {
                 content.push(src[idx]);
                 idx += 1;
             }
+            let kind = if is_doc { TokenType::DocComment } else { TokenType::SingleLineComment };
             return Some((
-                Token::new(content, TokenType::SingleLineComment, line, column)
-                    .with_length(idx - start),
+                Token::new(content, kind, line, column).with_length(idx - start),
                 idx - start
             ));
         } else if next_char == '*' {
+            // Block comments nest, so commenting out code that already
+            // contains a block comment works.
             let mut idx = start + 2;
+            let mut depth = 1;
             let mut content = String::new();
-            while idx < len - 1 {
-                if src[idx] == '*' && src[idx + 1] == '/' {
-                    idx += 2;
-                    break;
+            while idx < len {
+                match (src[idx], src.get(idx + 1)) {
+                    ('/', Some('*')) => {
+                        depth += 1;
+                        content.push_str("/*");
+                        idx += 2;
+                    }
+                    ('*', Some('/')) => {
+                        depth -= 1;
+                        idx += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                        content.push_str("*/");
+                    }
+                    (c, _) => {
+                        content.push(c);
+                        idx += 1;
+                    }
                 }
-                content.push(src[idx]);
-                idx += 1;
+            }
+            if depth > 0 {
+                errors.push(ZekkenError::syntax(
+                    "Unterminated block comment",
+                    line,
+                    column,
+                    Some("'*/'"),
+                    Some("end of file"),
+                ));
             }
             return Some((
                 Token::new(content, TokenType::MultiLineComment, line, column)
@@ -315,6 +458,7 @@ fn tokenize_char(src: &[char], start: usize, line: usize, column: usize) -> Opti
         let next = src[start + 1];
         let tk = match (cur, next) {
             ('-', '>') => Some(TokenType::ThinArrow),
+            ('.', '.') => Some(TokenType::DotDot),
             ('=', '>') => Some(TokenType::FatArrow),
             ('+', '=') => Some(TokenType::AssignOp(AssignOp::AddAssign)),
             ('-', '=') => Some(TokenType::AssignOp(AssignOp::SubAssign)),
@@ -334,6 +478,12 @@ fn tokenize_char(src: &[char], start: usize, line: usize, column: usize) -> Opti
         }
     }
 
+    // Raw strings, before `r` is taken for an identifier
+    if cur == 'r' && matches!(src.get(start + 1), Some('"' | '\'')) {
+        let token = parse_raw_string(src, start, line, column, errors);
+        return Some((token.clone(), token.length));
+    }
+
     // Check for identifiers
     if cur.is_alphabetic() || cur == '_' {
         let token = parse_identifier(src, start, line, column);
@@ -343,14 +493,14 @@ fn tokenize_char(src: &[char], start: usize, line: usize, column: usize) -> Opti
 
     // Check for numbers
     if cur.is_ascii_digit() {
-        let token = parse_number(src, start, line, column);
-        let consumed = token.value.len();
+        let token = parse_number(src, start, line, column, errors);
+        let consumed = token.length;
         return Some((token, consumed));
     }
 
     // Check for strings
     if cur == '"' || cur == '\'' {
-        let token = parse_string(src, start, line, column);
+        let token = parse_string(src, start, line, column, errors);
         return Some((token.clone(), token.length));
     }
 
@@ -363,6 +513,8 @@ fn tokenize_char(src: &[char], start: usize, line: usize, column: usize) -> Opti
         '}' => Some(TokenType::CloseBrace),
         '[' => Some(TokenType::OpenBracket),
         ']' => Some(TokenType::CloseBracket),
+        '#' => Some(TokenType::Hash),
+        '?' => Some(TokenType::Question),
         '.' => Some(TokenType::Dot),
         ';' => Some(TokenType::Semicolon),
         ':' => Some(TokenType::Colon),
@@ -389,7 +541,7 @@ fn tokenize_char(src: &[char], start: usize, line: usize, column: usize) -> Opti
     None
 }
 
-fn parse_number(src: &[char], start: usize, line: usize, column: usize) -> Token {
+fn parse_number(src: &[char], start: usize, line: usize, column: usize, errors: &mut Vec<ZekkenError>) -> Token {
     let mut num = String::new();
     let mut idx = start;
     let len = src.len();
@@ -399,10 +551,10 @@ fn parse_number(src: &[char], start: usize, line: usize, column: usize) -> Token
         if c.is_ascii_digit() {
             num.push(c);
             idx += 1;
-        } else if c == '.' && is_integer {
+        } else if c == '.' && idx + 1 < len && src[idx + 1].is_ascii_digit() {
             // Only treat '.' as part of a float literal if it is followed by a digit.
-            // This allows member access on integer literals: `99.cast => |...|`
-            if idx + 1 >= len || !src[idx + 1].is_ascii_digit() {
+            // This allows member access on number literals: `99.cast => |...|`
+            if !is_integer {
                 break;
             }
             num.push(c);
@@ -412,91 +564,247 @@ fn parse_number(src: &[char], start: usize, line: usize, column: usize) -> Token
             break;
         }
     }
+
+    // `1.2.3`: keep `1.2` as the value but consume the whole run, so the
+    // parser does not see a stray member access.
+    let mut end = idx;
+    while end + 1 < len && src[end] == '.' && src[end + 1].is_ascii_digit() {
+        end += 1;
+        while end < len && src[end].is_ascii_digit() {
+            end += 1;
+        }
+    }
+    // `10.50d`: an exact decimal, unless the `d` starts a longer name.
+    if end == idx && end < len && src[end] == 'd' && !src.get(end + 1).is_some_and(|c| c.is_alphanumeric() || *c == '_') {
+        if let Err(message) = crate::environment::decimal::Decimal::parse(&num) {
+            errors.push(ZekkenError::syntax(&message, line, column, Some("a decimal literal"), Some(&num)));
+        }
+        return Token::new(num, TokenType::Decimal, line, column).with_length(end + 1 - start);
+    }
+
+    if end > idx {
+        let written: String = src[start..end].iter().collect();
+        errors.push(ZekkenError::syntax(
+            &format!("Malformed number literal '{}'", written),
+            line,
+            column,
+            Some("a number with at most one decimal point"),
+            Some(&written),
+        ));
+    } else if is_integer && num.parse::<i64>().is_err() {
+        errors.push(ZekkenError::syntax(
+            &format!("Integer literal '{}' is out of range", num),
+            line,
+            column,
+            Some(&format!("an int no larger than {}", i64::MAX)),
+            Some(&num),
+        ));
+    } else if !is_integer && !num.parse::<f64>().is_ok_and(f64::is_finite) {
+        errors.push(ZekkenError::syntax(
+            &format!("Float literal '{}' is out of range", num),
+            line,
+            column,
+            Some(&format!("a float no larger than {:e}", f64::MAX)),
+            Some(&num),
+        ));
+    }
+
     let token_type = if is_integer { TokenType::Int } else { TokenType::Float };
-    Token::new(num, token_type, line, column)
+    Token::new(num, token_type, line, column).with_length(end - start)
 }
 
-fn parse_string(src: &[char], start: usize, line: usize, column: usize) -> Token {
+fn parse_string(src: &[char], start: usize, line: usize, column: usize, errors: &mut Vec<ZekkenError>) -> Token {
     let quote = src[start];
+    // `"""` opens a block string, closed by the next `"""`.
+    let delimiter = if quote == '"' && src[start..].starts_with(&['"'; 3]) { 3 } else { 1 };
     let mut content = String::new();
-    let mut escaped = false;
-    let mut idx = start + 1;
+    // Where the line breaks written in the source landed in `content`.
+    let mut breaks = Vec::new();
+    let mut idx = start + delimiter;
     let len = src.len();
+    // Position of `src[idx]`, for diagnostics inside multi-line strings.
+    let (mut at_line, mut at_column) = (line, column + delimiter);
+    let mut terminated = false;
 
     while idx < len {
         let c = src[idx];
-        if escaped {
-            match c {
-                'n' => content.push('\n'),
-                't' => content.push('\t'),
-                'r' => content.push('\r'),
-                '0' => content.push('\0'),
-                'e' => content.push('\x1b'),
-                '\\' => content.push('\\'),
-                '"' => content.push('"'),
-                '\'' => content.push('\''),
-                'x' => {
-                    // Hex escape: \xNN
-                    if idx + 2 < len {
-                        let h1 = src[idx + 1];
-                        let h2 = src[idx + 2];
-                        let hex = [h1, h2].iter().collect::<String>();
-                        if let Ok(v) = u8::from_str_radix(&hex, 16) {
-                            content.push(v as char);
-                            idx += 2; // Consume both hex digits.
-                        } else {
-                            // Keep behavior resilient for malformed escapes.
-                            content.push('x');
-                        }
-                    } else {
-                        content.push('x');
-                    }
-                }
-                _ => content.push(c),
+        if c == quote && src.get(idx..idx + delimiter).is_some_and(|end| end.iter().all(|c| *c == quote)) {
+            idx += delimiter; // Consume the closing quote
+            terminated = true;
+            break;
+        }
+        if c != '\\' {
+            if c == '\n' {
+                breaks.push(content.len());
+            }
+            content.push(c);
+            if c == '\n' {
+                at_line += 1;
+                at_column = 1;
+            } else {
+                at_column += 1;
             }
-            escaped = false;
-        } else if c == '\\' {
-            escaped = true;
-        } else if c == quote {
-            idx += 1; // Consume the closing quote
+            idx += 1;
+            continue;
+        }
+
+        let escape_column = at_column;
+        let Some(&kind) = src.get(idx + 1) else {
+            idx += 1;
             break;
+        };
+        // Characters the escape spans after the backslash.
+        let mut used = 1;
+        let decoded = match kind {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            'e' => Ok('\x1b'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            '\'' => Ok('\''),
+            'x' => {
+                let digits: String = src[idx + 2..len.min(idx + 4)].iter().collect();
+                if digits.len() == 2 && digits.chars().all(|d| d.is_ascii_hexdigit()) {
+                    used += 2;
+                    Ok(u8::from_str_radix(&digits, 16).unwrap_or_default() as char)
+                } else {
+                    Err(("Invalid hex escape", "'\\x' followed by two hex digits"))
+                }
+            }
+            'u' => match unicode_escape(&src[idx + 2..]) {
+                Some((ch, consumed)) => {
+                    used += consumed;
+                    ch.ok_or(("Invalid unicode escape", "a Unicode scalar value (at most 10FFFF, not a surrogate)"))
+                }
+                None => Err(("Invalid unicode escape", "'\\u{...}' with 1 to 6 hex digits")),
+            },
+            _ => Err(("Unknown escape sequence", "one of \\n \\t \\r \\0 \\e \\\\ \\\" \\' \\xNN \\u{...}")),
+        };
+        match decoded {
+            Ok(ch) => content.push(ch),
+            Err((message, expected)) => {
+                let found: String = src[idx..idx + 1 + used].iter().collect();
+                errors.push(ZekkenError::syntax(message, at_line, escape_column, Some(expected), Some(&found)));
+                // Keep the text as written so the rest of the string survives.
+                content.push_str(&found);
+            }
+        }
+        if kind == '\n' {
+            at_line += 1;
+            at_column = 1;
         } else {
-            content.push(c);
+            at_column += 1 + used;
         }
-        idx += 1;
+        idx += 1 + used;
+    }
+
+    if !terminated {
+        errors.push(ZekkenError::syntax(
+            "Unterminated string literal",
+            line,
+            column,
+            Some(&if delimiter == 3 { "closing \"\"\"".to_string() } else { format!("closing {}", quote) }),
+            Some("end of file"),
+        ));
+    }
+    if delimiter == 3 && !breaks.is_empty() {
+        content = trim_block(&content, &breaks);
     }
     let length = idx - start;
     Token::new(content, TokenType::String, line, column).with_length(length)
 }
 
-fn keyword_token(ident: &str) -> TokenType {
-    match ident {
-        "let" => TokenType::Let,
-        "const" => TokenType::Const,
-        "func" => TokenType::Func,
-        "if" => TokenType::If,
-        "else" => TokenType::Else,
-        "for" => TokenType::For,
-        "while" => TokenType::While,
-        "use" => TokenType::Use,
-        "include" => TokenType::Include,
-        "export" => TokenType::Export,
-        "in" => TokenType::In,
-        "from" => TokenType::From,
-        "return" => TokenType::Return,
-        "try" => TokenType::Try,
-        "catch" => TokenType::Catch,
-        "int" => TokenType::DataType(DataType::Int),
-        "float" => TokenType::DataType(DataType::Float),
-        "string" => TokenType::DataType(DataType::String),
-        "bool" => TokenType::DataType(DataType::Bool),
-        "obj" => TokenType::DataType(DataType::Object),
-        "arr" => TokenType::DataType(DataType::Array),
-        "fn" => TokenType::DataType(DataType::Fn),
-        "true" => TokenType::Boolean(true),
-        "false" => TokenType::Boolean(false),
-        _ => TokenType::Identifier,
+/// The text of a `"""` block that spans lines, without the line break after
+/// the opening quotes, the blank line before the closing ones, or the
+/// indentation its lines share. `breaks` are the line breaks written in the
+/// source; a `\n` escape does not start a new line here.
+fn trim_block(content: &str, breaks: &[usize]) -> String {
+    let mut lines = Vec::with_capacity(breaks.len() + 1);
+    let mut from = 0;
+    for &at in breaks {
+        lines.push(&content[from..at]);
+        from = at + 1;
+    }
+    lines.push(&content[from..]);
+
+    let blank = |line: &&str| line.chars().all(|c| c == ' ' || c == '\t');
+    if lines.first().is_some_and(blank) {
+        lines.remove(0);
+    }
+    if lines.last().is_some_and(blank) {
+        lines.pop();
     }
+    let indent = lines
+        .iter()
+        .filter(|line| !blank(line))
+        .map(|line| line.len() - line.trim_start_matches([' ', '\t']).len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| line.get(indent..).unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `r"..."`: everything up to the closing quote, backslashes included.
+fn parse_raw_string(src: &[char], start: usize, line: usize, column: usize, errors: &mut Vec<ZekkenError>) -> Token {
+    let quote = src[start + 1];
+    let body = &src[start + 2..];
+    let (content, length) = match body.iter().position(|c| *c == quote) {
+        Some(end) => (body[..end].iter().collect(), end + 3),
+        None => {
+            errors.push(ZekkenError::syntax(
+                "Unterminated raw string literal",
+                line,
+                column,
+                Some(&format!("closing {}", quote)),
+                Some("end of file"),
+            ));
+            (body.iter().collect(), body.len() + 2)
+        }
+    };
+    Token::new(content, TokenType::RawString, line, column).with_length(length)
+}
+
+/// Decodes the `{1F600}` part of a `\u{1F600}` escape. Returns the character
+/// (`None` for values that are not Unicode scalar values) and the number of
+/// source characters used, or `None` when the braces or digits are malformed.
+fn unicode_escape(src: &[char]) -> Option<(Option<char>, usize)> {
+    if src.first() != Some(&'{') {
+        return None;
+    }
+    let close = src.iter().take(8).position(|c| *c == '}')?;
+    let digits: String = src[1..close].iter().collect();
+    if digits.is_empty() || !digits.chars().all(|d| d.is_ascii_hexdigit()) {
+        return None;
+    }
+    let value = u32::from_str_radix(&digits, 16).ok()?;
+    Some((char::from_u32(value), close + 1))
+}
+
+fn keyword_token(ident: &str) -> TokenType {
+    static TOKENS: OnceLock<HashMap<&'static str, TokenType>> = OnceLock::new();
+    let tokens = TOKENS.get_or_init(|| KEYWORDS.iter().cloned().collect());
+    tokens.get(ident).cloned().unwrap_or(TokenType::Identifier)
+}
+
+/// The type keywords, for messages that list them: `int, float, ...`.
+pub fn type_keywords() -> String {
+    KEYWORDS
+        .iter()
+        .filter(|(_, token)| matches!(token, TokenType::DataType(_)))
+        .map(|(keyword, _)| *keyword)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `a type (int, float, ...)`, the expected label for a missing annotation.
+pub fn type_hint() -> &'static str {
+    static HINT: OnceLock<String> = OnceLock::new();
+    HINT.get_or_init(|| format!("a type ({})", type_keywords()))
 }
 
 fn parse_identifier(src: &[char], start: usize, line: usize, column: usize) -> Token {
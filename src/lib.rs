@@ -1,4 +1,4 @@
-mod ast;
+pub mod ast;
 mod parser;
 mod environment;
 mod bytecode;
@@ -7,6 +7,14 @@ mod lexer;
 mod libraries;
 mod eval;
 mod diagnostics;
+mod config;
+mod stats;
+mod history;
+mod coverage;
+mod symbols;
+mod crash;
+mod trace;
+mod output;
 
 use wasm_bindgen::prelude::*;
 
@@ -31,6 +39,8 @@ pub fn run_zekken(input: &str) -> String {
 
     let mut parser = parser::Parser::new();
     let ast = parser.produce_ast(input.to_string());
+    // Each playground run starts from the default float formatting.
+    environment::set_float_precision(None);
     let mut env = environment::Environment::new();
 
     #[cfg(target_arch = "wasm32")]
@@ -51,6 +61,15 @@ pub fn run_zekken(input: &str) -> String {
                 crate::lexer::DataType::Fn,
                 true,
             );
+            env.declare_ref_typed(
+                "print",
+                Value::NativeFunction(Arc::new(|args: Vec<Value>| -> Result<Value, String> {
+                    WASM_OUTPUT.lock().unwrap().push_str(&environment::format_print_values(&args));
+                    Ok(Value::Void)
+                })),
+                crate::lexer::DataType::Fn,
+                true,
+            );
         }
     }
 
@@ -61,22 +80,24 @@ pub fn run_zekken(input: &str) -> String {
         diagnostics::ExecutionMode::Bytecode,
     );
     let mut output = String::new();
+    for warning in errors::take_warnings() {
+        output.push_str(&format!("{}\n", warning));
+    }
     for error in &report.errors {
         output.push_str(&format!("{}\n", error));
     }
-    if report.errors.is_empty() {
-        if let Some(val) = report.value {
-            if !matches!(val, environment::Value::Void) {
-                output.push_str(&format!("{}\n", val));
-            }
-        }
-    }
-
     #[cfg(target_arch = "wasm32")]
     {
         output.push_str(&WASM_OUTPUT.lock().unwrap());
     }
 
+    // Like `zekken run`, the playground only echoes the result when asked to.
+    if report.errors.is_empty() && config::print_result() {
+        if let Some(val) = report.value.filter(|val| !matches!(val, environment::Value::Void)) {
+            output.push_str(&format!("{}\n", val));
+        }
+    }
+
     output
 }
 
@@ -86,12 +107,63 @@ pub fn clear_errors() {
     crate::errors::clear_collected_errors();
 }
 
+/// How many calls a playground script may nest before a call fails with
+/// "maximum recursion depth exceeded". Zero is no limit.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn set_max_call_depth(limit: u32) {
+    config::set_max_call_depth(limit as u64);
+}
+
+/// Whether a playground run ends by printing the value of its last
+/// top-level expression.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn set_print_result(enabled: bool) {
+    config::set_print_result(enabled);
+}
+
+/// Adds or replaces a file in the playground's virtual file system, which
+/// `fs` reads and writes in place of a disk.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn write_virtual_file(path: &str, contents: &str) {
+    let _ = libraries::vfs::page_files().with_file(path, contents);
+}
+
+/// Every file in the playground's virtual file system, as a `{ path:
+/// contents }` object.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn virtual_files() -> JsValue {
+    let files = serde_json::to_string(&libraries::vfs::page_files().files()).unwrap_or_default();
+    js_sys::JSON::parse(&files).unwrap_or(JsValue::NULL)
+}
+
+/// Global builtins and library members with their signatures, for the
+/// playground editor's autocomplete. See `symbols::builtin_symbols`.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn get_builtin_symbols() -> JsValue {
+    js_sys::JSON::parse(&symbols::builtin_symbols().to_string()).unwrap_or(JsValue::NULL)
+}
+
+/// Highlight spans for `input`, classified by the real lexer. See
+/// `lexer::highlight::HighlightSpan` for the shape of each span.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn highlight_source(input: &str) -> JsValue {
+    let spans = serde_json::to_string(&lexer::highlight::highlight(input)).unwrap_or_default();
+    js_sys::JSON::parse(&spans).unwrap_or(JsValue::NULL)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ast::Stmt;
+    use crate::ast::{Content, Expr, MemberExpr, Method, Stmt, TemplatePart, VarDecl};
     use crate::environment::{Environment, Value};
     use crate::lexer::DataType;
+    use std::collections::BTreeMap;
     use std::sync::{Arc, Mutex};
 
     fn parse(source: &str) -> ast::Program {
@@ -178,7 +250,7 @@ let result: arr = collect => |3|;
     #[test]
     fn diagnostics_collect_and_order_all_error_categories() {
         let source = r#"
-let broken: int = 1
+let broken: int = 1 2
 let missing: int = absent => ||;
 let wrong: string = 2;
 @fail => ||
@@ -241,6 +313,34 @@ let wrong: string = 2;
         assert_eq!(program.content.len(), 2, "parser did not recover after semicolon");
     }
 
+    #[test]
+    fn reports_leftover_tokens_after_expression_statement_once() {
+        let source = "@println => |1| stray tokens here\nx = 1 2 3\n@println => |2|\n";
+        let mut parser = parser::Parser::new();
+        let program = parser.produce_ast(source.to_string());
+
+        assert_eq!(parser.errors.len(), 2, "parser errors: {:#?}", parser.errors);
+        for (error, line, column) in [(&parser.errors[0], 1, 17), (&parser.errors[1], 2, 7)] {
+            assert_eq!(error.kind, errors::ErrorKind::Syntax);
+            assert_eq!(error.message, "Unexpected tokens after expression");
+            assert_eq!((error.context.line, error.context.column), (line, column));
+        }
+        assert!(parser.errors[0].context.pointer.ends_with(&"~".repeat(16)));
+        assert_eq!(program.content.len(), 3, "parser did not recover after leftover tokens");
+    }
+
+    #[test]
+    fn missing_type_errors_list_every_type_keyword() {
+        let mut parser = parser::Parser::new();
+        parser.produce_ast("let x: 5 = 1\n".to_string());
+        assert!(!parser.errors.is_empty());
+        assert_eq!(parser.errors[0].message, "Expected type (int, float, dec, string, bool, obj, arr, fn, any) after ':'");
+
+        let mut parser = parser::Parser::new();
+        parser.produce_ast("let y: = pick => ||\n".to_string());
+        assert_eq!(parser.errors[0].message, "Expected a type (int, float, dec, string, bool, obj, arr, fn, any) Type after ':'");
+    }
+
     #[test]
     fn distinguishes_empty_call_pipes_from_logical_or() {
         let source = r#"
@@ -270,16 +370,2960 @@ let length: int = data.length => ||;
     }
 
     #[test]
-    fn diagnostics_deduplicate_only_exact_errors() {
-        let duplicate = errors::ZekkenError::internal("duplicate");
-        let mut different_details = duplicate.clone();
-        different_details.extra = Some("different details".to_string());
-        let mut collected = vec![duplicate.clone(), different_details, duplicate];
+    fn operators_group_by_the_precedence_table() {
+        fn tree(expr: &Expr) -> String {
+            match expr {
+                Expr::Identifier(ident) => ident.name.clone(),
+                Expr::IntLit(lit) => lit.value.to_string(),
+                Expr::Unary(unary) => format!("({} {})", unary.operator, tree(&unary.operand)),
+                Expr::Binary(binary) => format!("({} {} {})", binary.operator, tree(&binary.left), tree(&binary.right)),
+                Expr::Assign(assign) => format!("({} {} {})", assign.operator, tree(&assign.left), tree(&assign.right)),
+                Expr::Member(member) => format!("(. {} {})", tree(&member.object), tree(&member.property)),
+                Expr::MethodCall(call) => format!("({}=> {})", tree(&call.object), call.method),
+                other => panic!("unexpected expression {other:?}"),
+            }
+        }
 
-        errors::sort_and_dedup_errors(&mut collected);
+        let cases = [
+            ("a || b && c", "(|| a (&& b c))"),
+            ("a && b || c && d", "(|| (&& a b) (&& c d))"),
+            ("a == b && c != d", "(&& (== a b) (!= c d))"),
+            ("a < b == c > d", "(== (< a b) (> c d))"),
+            ("a in b == c", "(== (in a b) c)"),
+            ("a + b < c * d", "(< (+ a b) (* c d))"),
+            ("a - b - c", "(- (- a b) c)"),
+            ("a / b * c % d", "(% (* (/ a b) c) d)"),
+            ("!a && b", "(&& (! a) b)"),
+            ("!a == b", "(== (! a) b)"),
+            ("!!a || b", "(|| (! (! a)) b)"),
+            ("-a * b", "(* (- 0 a) b)"),
+            ("!a.b", "(! (. a b))"),
+            ("!a.b => ||", "(! (a=> b))"),
+            ("a = b || c", "(= a (|| b c))"),
+            ("a = b = c + 1", "(= a (= b (+ c 1)))"),
+            ("a += b * c", "(+= a (* b c))"),
+            ("a.b -= c && d", "(-= (. a b) (&& c d))"),
+            ("a + b = c", "(= (+ a b) c)"),
+        ];
+        for (source, expected) in cases {
+            let program = parse(source);
+            let [content] = program.content.as_slice() else {
+                panic!("{source:?} did not parse to one expression: {:#?}", program.content);
+            };
+            let Content::Expression(expr) = content else {
+                panic!("{source:?} did not parse to one expression: {:#?}", program.content);
+            };
+            assert_eq!(tree(expr), expected, "{source}");
+        }
+    }
 
-        assert_eq!(collected.len(), 2);
-        assert!(collected.iter().any(|error| error.extra.is_none()));
-        assert!(collected.iter().any(|error| error.extra.is_some()));
+    #[test]
+    fn builtin_symbols_match_what_the_interpreter_registers() {
+        let symbols = symbols::builtin_symbols();
+        let names = |list: &serde_json::Value| -> Vec<String> {
+            list.as_array().unwrap().iter().map(|symbol| symbol["name"].as_str().unwrap().to_string()).collect()
+        };
+
+        assert_eq!(names(&symbols["builtins"]), ["diff", "flush", "format", "input", "memstats", "parse_json", "print", "println", "queue", "set_precision"]);
+        assert_eq!(names(&symbols["libraries"]), libraries::library_names());
+
+        let math = &symbols["libraries"].as_array().unwrap().iter().find(|lib| lib["name"] == "math").unwrap()["members"];
+        let pi = math.as_array().unwrap().iter().find(|member| member["name"] == "PI").unwrap();
+        assert_eq!(pi["kind"], "constant");
+        let sqrt = math.as_array().unwrap().iter().find(|member| member["name"] == "sqrt").unwrap();
+        assert_eq!(sqrt["kind"], "function");
+        assert_eq!(sqrt["signature"], "|x: numeric| -> float");
+
+        // Every registered symbol is documented, and every signature names a
+        // symbol that exists.
+        let mut documented = environment::BUILTIN_SIGNATURES.len();
+        for symbol in symbols["builtins"].as_array().unwrap() {
+            assert!(symbol["signature"].is_string(), "no signature for builtin {}", symbol["name"]);
+        }
+        for library in symbols["libraries"].as_array().unwrap() {
+            let name = library["name"].as_str().unwrap();
+            documented += libraries::signatures(name).len();
+            for member in library["members"].as_array().unwrap() {
+                assert!(member["signature"].is_string(), "no signature for {}.{}", name, member["name"]);
+            }
+        }
+        let registered = symbols["builtins"].as_array().unwrap().len()
+            + symbols["libraries"].as_array().unwrap().iter().map(|lib| lib["members"].as_array().unwrap().len()).sum::<usize>();
+        assert_eq!(documented, registered, "a signature names a symbol that is not registered");
+    }
+
+    #[test]
+    fn highlight_classifies_tokens_with_the_lexer() {
+        let source = "use math\n/* a\n   b */ let s: string = \"\\u{e9}\" // hi\n@println => |math.sqrt => |2.0||\nfunc twice |x: int| -> int { return x * 2 }\n";
+        let spans = lexer::highlight::highlight(source);
+        let classes: Vec<&str> = spans.iter().map(|span| span.class).collect();
+        assert_eq!(
+            classes,
+            [
+                "keyword", "variable",
+                "comment", "keyword", "variable", "punctuation", "type", "operator", "string", "comment",
+                "builtin", "operator", "operator", "variable", "operator", "function", "operator", "operator", "number", "operator",
+                "keyword", "function", "operator", "variable", "punctuation", "type", "operator", "operator", "type", "punctuation",
+                "keyword-control", "variable", "operator", "number", "punctuation",
+            ]
+        );
+
+        // The block comment spans two lines; the string's span covers its
+        // quotes and escape, not the decoded text.
+        let comment = &spans[2];
+        assert_eq!((comment.line, comment.column, comment.end_line, comment.end_column), (2, 1, 3, 8));
+        let string = &spans[8];
+        assert_eq!((string.line, string.column, string.end_line, string.end_column), (3, 25, 3, 33));
+    }
+
+    #[test]
+    fn floats_print_without_artifacts_and_honour_precision() {
+        let source = r#"
+let sum: string = "" + (0.1 + 0.2)
+let near: string = "" + 2.9999999999999996
+let whole: string = "" + 2.0
+let fixed: string = @format => |3.14159, ".2"|
+let padded: string = @format => |[1.5, 2.25], ">14.1"|
+@set_precision => |3|
+let third: string = "" + 1.0 / 3.0
+let unpadded: string = @format => |0.25, ">6"|
+let tiny: string = "" + 0.00001 / 3.0
+let huge: string = "" + 123456789.0 * 100000000000000.0
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            let text = |name: &str| match env.lookup(name) {
+                Some(Value::String(s)) => s,
+                other => panic!("{name} should be a string, got {other:?}"),
+            };
+            assert_eq!(text("sum"), "0.30000000000000004");
+            assert_eq!(text("near"), "2.9999999999999996");
+            assert_eq!(text("whole"), "2.0");
+            assert_eq!(text("fixed"), "3.14");
+            assert_eq!(text("padded"), "    [1.5, 2.2]");
+            // `@set_precision` only changes what print and println write.
+            assert_eq!(text("third"), "0.3333333333333333");
+            assert_eq!(text("unpadded"), "  0.25");
+            assert_eq!(environment::format_print_values(&[Value::Float(1.0 / 3.0)]), "0.333");
+            assert_eq!(environment::format_print_values(&[Value::String("x = {}".into()), Value::Float(0.5)]), "x = 0.500");
+            assert_eq!(text("tiny"), "3.3333333333333337e-6");
+            assert_eq!(text("huge"), "1.23456789e22");
+            environment::set_float_precision(None);
+            assert_eq!(environment::format_print_values(&[Value::Float(1.0 / 4.0)]), "0.25");
+        }
+
+        let matrix = Value::Matrix(vec![vec![1.0, 22.5], vec![333.25, 4.0]]);
+        assert_eq!(matrix.to_string(), "[[   1.0, 22.5], [333.25,  4.0]]");
+        let rounding_noise = Value::Matrix(vec![vec![2.0, 1e-17], vec![-3e-17, 1.0]]);
+        assert_eq!(rounding_noise.to_string(), "[[   2.0, 1e-17], [-3e-17,   1.0]]");
+    }
+
+    #[test]
+    fn decimals_are_exact_and_round_by_mode() {
+        let source = r#"
+use decimal
+let sum: string = "" + (0.10d + 0.20d)
+let quarter: string = "" + 10.00d / 4
+let third: string = "" + 1d / 3
+let total: dec = 19.99d
+total *= 3
+let even: string = "" + 2.5d.round => |0, "half_even"|
+let up: string = "" + 2.675d.round => |2|
+let floor: string = "" + (-2.5d).round => |0, "floor"|
+let parsed: string = "" + decimal.parse => |"-12.3400"|
+let same: bool = 1.5d == 1.50d
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            let text = |name: &str| match env.lookup(name) {
+                Some(Value::String(s)) => s,
+                other => panic!("{name} should be a string, got {other:?}"),
+            };
+            assert_eq!(text("sum"), "0.30");
+            assert_eq!(text("quarter"), "2.50");
+            assert_eq!(text("third"), "0.3333333333333333");
+            assert_eq!(env.lookup("total").map(|v| v.to_string()).as_deref(), Some("59.97"));
+            assert_eq!(text("even"), "2");
+            assert_eq!(text("up"), "2.68");
+            assert_eq!(text("floor"), "-3");
+            assert_eq!(text("parsed"), "-12.3400");
+            assert!(matches!(env.lookup("same"), Some(Value::Boolean(true))));
+        }
+
+        let program = parse("let mixed: dec = 1.5d + 0.5");
+        let error = eval::statement::evaluate_statement(&Stmt::Program(program), &mut Environment::new()).unwrap_err();
+        assert!(error.message.contains("Cannot mix dec and float"), "{}", error.message);
+    }
+
+    #[test]
+    fn units_check_dimensions_and_convert() {
+        let source = r#"
+use units
+let speed: string = "" + units.km => |5| / units.h => |2|
+let sum: string = "" + (units.m => |1| + units.cm => |50|)
+let ratio: float = units.km => |1| / units.m => |1|
+let miles: float = units.convert => |5, "km", "mi"|
+let newtons: string = "" + units.convert => |units.of => |2, "kg*m/s^2"|, "N"|
+let longer: bool = units.km => |5| > units.mi => |3|
+let q: obj = units.km => |5|
+let value: int = q.value
+let unit: string = q.unit
+let forged: obj = { __unit__: "lol", value: 1 }
+let shown: string = "" + forged
+let forged_keys: arr = forged.keys => ||
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            let text = |name: &str| match env.lookup(name) {
+                Some(Value::String(s)) => s,
+                other => panic!("{name} should be a string, got {other:?}"),
+            };
+            assert_eq!(text("speed"), "2.5 km/h");
+            assert_eq!(text("sum"), "1.5 m");
+            assert!(matches!(env.lookup("ratio"), Some(Value::Float(r)) if r == 1000.0));
+            assert!(matches!(env.lookup("miles"), Some(Value::Float(m)) if (m - 3.106855961).abs() < 1e-9));
+            assert_eq!(text("newtons"), "2.0 N");
+            assert!(matches!(env.lookup("longer"), Some(Value::Boolean(true))));
+            assert!(matches!(env.lookup("value"), Some(Value::Int(5))));
+            assert_eq!(text("unit"), "km");
+            // An object that merely has a `__unit__` key is a plain object.
+            assert_eq!(text("shown"), "{__unit__: \"lol\", value: 1}");
+            assert!(matches!(env.lookup("forged_keys"), Some(Value::Array(keys)) if keys.len() == 2));
+        }
+
+        let program = parse("use units\nlet bad: obj = units.km => |1| + units.s => |1|");
+        let error = eval::statement::evaluate_statement(&Stmt::Program(program), &mut Environment::new()).unwrap_err();
+        assert!(error.message.contains("Cannot add km and s"), "{}", error.message);
+    }
+
+    #[test]
+    fn vector_and_matrix_literals_build_linear_algebra_values() {
+        let source = r#"
+use math
+let v: arr = #[1, 2.5, -3]
+let m: arr = #[[1, 2], [3, 4]]
+let entry: float = v[-1]
+let row: arr = m[1]
+let dot: float = math.dot => |v, #[2, 2, 2]|
+let product: arr = math.matmul => |m, m|
+let size: int = m.length
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("v"), Some(Value::Vector(v)) if v == vec![1.0, 2.5, -3.0]));
+            assert!(matches!(env.lookup("entry"), Some(Value::Float(f)) if f == -3.0));
+            assert!(matches!(env.lookup("row"), Some(Value::Vector(r)) if r == vec![3.0, 4.0]));
+            assert!(matches!(env.lookup("dot"), Some(Value::Float(d)) if d == 1.0));
+            assert!(matches!(env.lookup("product"), Some(Value::Matrix(p)) if p == vec![vec![7.0, 10.0], vec![15.0, 22.0]]));
+            assert!(matches!(env.lookup("size"), Some(Value::Int(2))));
+        }
+
+        let source = r#"
+let v: arr = #[1, 2, 3]
+let m: arr = #[[1, 2], [3, 4]]
+let sum: arr = v + #[1, 1, 1]
+let scaled: arr = 2 * v - 1
+let negated: arr = -v
+let halved: arr = m / 2
+let mv: arr = m * #[1, 1]
+let vm: arr = #[1, 1] * m
+let mm: arr = m * m
+let same: bool = v == #[1, 2, 3]
+let acc: arr = #[0, 0, 0]
+acc += v
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            let vector = |name: &str| match env.lookup(name) {
+                Some(Value::Vector(v)) => v,
+                other => panic!("{name} should be a vector, got {other:?}"),
+            };
+            assert_eq!(vector("sum"), vec![2.0, 3.0, 4.0]);
+            assert_eq!(vector("scaled"), vec![1.0, 3.0, 5.0]);
+            assert_eq!(vector("negated"), vec![-1.0, -2.0, -3.0]);
+            assert_eq!(vector("mv"), vec![3.0, 7.0]);
+            assert_eq!(vector("vm"), vec![4.0, 6.0]);
+            assert_eq!(vector("acc"), vec![1.0, 2.0, 3.0]);
+            assert!(matches!(env.lookup("halved"), Some(Value::Matrix(h)) if h == vec![vec![0.5, 1.0], vec![1.5, 2.0]]));
+            assert!(matches!(env.lookup("mm"), Some(Value::Matrix(p)) if p == vec![vec![7.0, 10.0], vec![15.0, 22.0]]));
+            assert!(matches!(env.lookup("same"), Some(Value::Boolean(true))));
+        }
+        for (source, message) in [
+            ("let bad: arr = #[1, 2] + #[1, 2, 3]", "Cannot add a vector of length 2 and a vector of length 3"),
+            ("let bad: arr = #[[1, 2]] * #[[1, 2]]", "Cannot multiply a 1x2 matrix by a 1x2 matrix"),
+            ("let bad: arr = #[1, 2] * #[1, 2]", "use math.dot"),
+        ] {
+            let error = eval::statement::evaluate_statement(&Stmt::Program(parse(source)), &mut Environment::new()).unwrap_err();
+            assert!(error.message.contains(message), "{}", error.message);
+        }
+
+        let program = parse("let ragged: arr = #[[1, 2], [3]]");
+        let error = eval::statement::evaluate_statement(&Stmt::Program(program), &mut Environment::new()).unwrap_err();
+        assert!(error.message.contains("Matrix row 2 has 1 entries, expected 2"), "{}", error.message);
+    }
+
+    #[test]
+    fn collection_helpers_compare_elements_deeply() {
+        let source = r#"
+let items: arr = [1, [1, 2], { id: 3 }, [1, 2], 1.0]
+let nested: int = items.indexOf => |[1, 2]|
+let object: int = items.indexOf => |{ id: 3 }|
+let absent: int = items.indexOf => |"1"|
+let ones: int = items.count => |1|
+let removed: int = items.remove_value => |[1, 2]|
+let left: int = items.length => ||
+let user: obj = { name: "RAGE", tags: ["a"], score: 10 }
+let tagged: string = user.find_key => |["a"]|
+let missing: string = user.find_key => |11|
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("nested"), Some(Value::Int(1))));
+            assert!(matches!(env.lookup("object"), Some(Value::Int(2))));
+            assert!(matches!(env.lookup("absent"), Some(Value::Int(-1))));
+            assert!(matches!(env.lookup("ones"), Some(Value::Int(2))));
+            assert!(matches!(env.lookup("removed"), Some(Value::Int(2))));
+            assert!(matches!(env.lookup("left"), Some(Value::Int(3))));
+            assert!(matches!(env.lookup("tagged"), Some(Value::String(k)) if k == "tags"));
+            assert!(matches!(env.lookup("missing"), Some(Value::Null)));
+        }
+    }
+
+    #[test]
+    fn sorting_by_key_functions_and_fields_is_stable() {
+        let source = r#"
+let people: arr = [
+  { name: "cy", age: 30 },
+  { name: "al", age: 25 },
+  { name: "bo", age: 30 },
+  { name: "di" }
+]
+let words: arr = ["pear", "fig", "apple", "kiwi"]
+let shortest: arr = words.sort_by => |fn -> |w: string| { return w.length => || }|
+let longest: arr = words.sort_by => |fn -> |w: string| { return w.length => || }, true|
+let ranked: arr = people.sort_by_keys => |["-age", "name"]|
+let youngest: arr = people.sort_by => |fn -> |p: obj| { return [p.get => |"age", null|, p.name] }|
+let left: string = words.join => |","|
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            // Records are listed by their name.
+            let strings = |name: &str| match env.lookup(name) {
+                Some(Value::Array(items)) => items
+                    .iter()
+                    .map(|item| match item {
+                        Value::Object(record) => record["name"].to_string(),
+                        item => item.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(","),
+                other => panic!("{name} is {other:?}"),
+            };
+            assert_eq!(strings("shortest"), "fig,pear,kiwi,apple");
+            assert_eq!(strings("longest"), "apple,pear,kiwi,fig");
+            assert_eq!(strings("ranked"), "bo,cy,al,di");
+            assert_eq!(strings("youngest"), "di,al,bo,cy");
+            assert!(matches!(env.lookup("left"), Some(Value::String(words)) if words == "pear,fig,apple,kiwi"));
+        }
+
+        for (source, message) in [
+            ("let xs: arr = [1, \"a\"]\nlet s: arr = xs.sort_by => |fn -> |x: any| { return x }|\n", "Cannot compare"),
+            ("let xs: arr = [1]\nlet s: arr = xs.sort_by => |1|\n", "sort_by expects a function"),
+            ("let xs: arr = [1]\nlet s: arr = xs.sort_by_keys => |[\"age\"]|\n", "expects an array of objects"),
+        ] {
+            for mode in [diagnostics::ExecutionMode::TreeWalk, diagnostics::ExecutionMode::Bytecode] {
+                let report = diagnostics::run_program_collecting(&parse(source), &[], &mut Environment::new(), mode);
+                let error = report.errors.first().unwrap_or_else(|| panic!("{source:?} should fail"));
+                assert!(error.message.contains(message), "{}", error.message);
+            }
+        }
+    }
+
+    #[test]
+    fn linear_algebra_solvers_check_shapes() {
+        let source = r#"
+use math
+let a: arr = #[[0, 2, 1], [1, 1, 0], [3, 0, 1]]
+let x: arr = math.solve => |a, #[5, 3, 4]|
+let factors: obj = math.lu => |a|
+let pa: arr = math.matmul => |factors.p, a|
+let lu: arr = math.matmul => |factors.l, factors.u|
+let line: arr = math.lstsq => |#[[1, 0], [1, 1], [1, 2]], #[1, 3, 5]|
+let spectrum: obj = math.eig => |#[[2, 1], [1, 2]]|
+let values: arr = spectrum.values
+"#;
+        let close = |actual: &[f64], expected: &[f64]| {
+            actual.len() == expected.len() && actual.iter().zip(expected).all(|(a, e)| (a - e).abs() < 1e-9)
+        };
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("x"), Some(Value::Vector(x)) if close(&x, &[1.0, 2.0, 1.0])));
+            match (env.lookup("pa"), env.lookup("lu")) {
+                (Some(Value::Matrix(pa)), Some(Value::Matrix(lu))) => {
+                    assert!(pa.iter().zip(&lu).all(|(l, r)| close(l, r)), "{pa:?} != {lu:?}");
+                }
+                other => panic!("lu should give matrices, got {other:?}"),
+            }
+            assert!(matches!(env.lookup("line"), Some(Value::Vector(x)) if close(&x, &[1.0, 2.0])));
+            assert!(matches!(env.lookup("values"), Some(Value::Vector(v)) if close(&v, &[1.0, 3.0])));
+        }
+
+        let program = parse("use math\nlet bad: arr = math.solve => |#[[1, 2, 3], [4, 5, 6]], #[1, 2]|");
+        let error = eval::statement::evaluate_statement(&Stmt::Program(program), &mut Environment::new()).unwrap_err();
+        assert!(error.message.contains("A is 2x3 but must be square"), "{}", error.message);
+    }
+
+    #[test]
+    fn bigfloats_compute_to_the_requested_precision() {
+        let source = r#"
+use math
+let two: obj = math.bigfloat => |"2", 40|
+let root: string = "" + two.sqrt => ||
+let one: obj = math.bigfloat => |1, 30|
+let e: string = "" + one.exp => ||
+let ten: obj = math.bigfloat => |10, 30|
+let ln10: string = "" + ten.ln => ||
+let third: string = "" + one / 3
+let a: obj = math.bigfloat => |"0.1", 30|
+let exact: bool = a + 0.2 == math.bigfloat => |"0.3"|
+let digits: int = two.precision
+let forged: obj = { __bigfloat__: "3e0", precision: -1 }
+let shown: string = "" + forged
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            let text = |name: &str| match env.lookup(name) {
+                Some(Value::String(s)) => s,
+                other => panic!("{name} should be a string, got {other:?}"),
+            };
+            assert_eq!(text("root"), "1.41421356237309504880168872420969807857");
+            assert_eq!(text("e"), "2.71828182845904523536028747135");
+            assert_eq!(text("ln10"), "2.30258509299404568401799145468");
+            assert_eq!(text("third"), "0.333333333333333333333333333333");
+            assert!(matches!(env.lookup("exact"), Some(Value::Boolean(true))));
+            assert!(matches!(env.lookup("digits"), Some(Value::Int(40))));
+            assert_eq!(text("shown"), "{__bigfloat__: \"3e0\", precision: -1}");
+        }
+
+        // Forged fields make a plain object, and exponents stay in range.
+        for (source, expected) in [
+            ("let forged: obj = { __bigfloat__: \"3e0\", precision: -1 }\nlet bad: any = forged / 7", "Invalid operand types"),
+            ("use math\nlet huge: obj = math.bigfloat => |\"1e999999999999999\"|\nlet bad: any = huge * huge", "bigfloat out of range"),
+            ("use math\nlet bad: obj = math.bigfloat => |\"1.5e-9223372036854775808\"|", "bigfloat out of range"),
+        ] {
+            let error = eval::statement::evaluate_statement(&Stmt::Program(parse(source)), &mut Environment::new()).unwrap_err();
+            assert!(error.message.contains(expected), "{}", error.message);
+        }
+    }
+
+    #[test]
+    fn semver_ranges_with_nothing_above_them_are_errors() {
+        for range in ["^18446744073709551615", "~1.18446744073709551615", ">1.18446744073709551615"] {
+            let source = format!("use semver\nlet ok: bool = semver.satisfies => |\"1.0.0\", \"{}\"|", range);
+            let error = eval::statement::evaluate_statement(&Stmt::Program(parse(&source)), &mut Environment::new()).unwrap_err();
+            assert!(error.message.contains("has no version above it"), "{}", error.message);
+        }
+    }
+
+    #[test]
+    fn semver_rejects_malformed_pre_release_and_build_identifiers() {
+        for version in ["1.0.0-01", "1.0.0-", "1.0.0+", "1.0.0-rc..1", "1.0.0+build.", "1.0.0-rc_1"] {
+            let source = format!("use semver\nlet v: obj = semver.parse => |\"{}\"|", version);
+            let error = eval::statement::evaluate_statement(&Stmt::Program(parse(&source)), &mut Environment::new()).unwrap_err();
+            assert!(error.message.contains("Invalid version"), "{}: {}", version, error.message);
+        }
+        let source = "use semver\nlet v: obj = semver.parse => |\"1.0.0-rc.0.x-1+build.001\"|";
+        execute(source, false, &mut Environment::new());
+    }
+
+    #[test]
+    fn numeric_fast_paths_agree_with_the_general_operators() {
+        let source = r#"
+let a: int = 7
+let b: int = 2
+let nested: int = (a / b) + 1
+let half: float = (7.0 / b) + 1
+let rem: int = (a % b) * 10
+let x: float = 1.5
+let scaled: float = x * 2.0 - 0.5
+let less: bool = x < 2.0
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("nested"), Some(Value::Int(4))));
+            assert!(matches!(env.lookup("half"), Some(Value::Float(h)) if h == 4.5));
+            assert!(matches!(env.lookup("rem"), Some(Value::Int(10))));
+            assert!(matches!(env.lookup("scaled"), Some(Value::Float(s)) if s == 2.5));
+            assert!(matches!(env.lookup("less"), Some(Value::Boolean(true))));
+        }
+
+        for source in ["let a: int = 1\nlet b: int = 0\nlet c: int = (a / b) + 1", "let a: int = 1\nlet b: int = 0\nlet c: int = (a % b) + 1"] {
+            let error = eval::statement::evaluate_statement(&Stmt::Program(parse(source)), &mut Environment::new()).unwrap_err();
+            assert!(error.message.contains("by zero"), "{}", error.message);
+        }
+
+        let prelude = "let max: int = 9223372036854775807\nlet min: int = -max - 1\nlet neg: int = -1\n";
+        for op in ["max + 1", "min - 1", "max * 2", "(max + 1) * 2", "min / neg", "min % neg", "9223372036854775807 + 1"] {
+            let source = format!("{}let c: int = {}", prelude, op);
+            for use_vm in [false, true] {
+                let program = parse(&source);
+                let mut env = Environment::new();
+                let result = if use_vm {
+                    bytecode::execute_program(&program, &mut env)
+                } else {
+                    eval::statement::evaluate_statement(&Stmt::Program(program), &mut env)
+                };
+                assert!(matches!(&result, Err(e) if e.message.starts_with("Integer overflow")), "{op}: {result:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn compound_assignment_matches_its_operator() {
+        let source = r#"
+let s: string = "n="
+s += 42
+let held: any = "n="
+held += 1.5
+let o: obj = { label: "x" }
+o.label += true
+let rule: string = "-" * 3
+let twice: string = 2 * "ab"
+let none: string = "ab" * 0
+let t: string = "ab"
+t *= 2
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            let text = |name: &str| env.lookup(name).map(|value| value.to_string());
+            assert_eq!(text("s").as_deref(), Some("n=42"));
+            assert_eq!(text("held").as_deref(), Some("n=1.5"));
+            assert_eq!(text("o").as_deref(), Some("{label: \"xtrue\"}"));
+            assert_eq!(text("rule").as_deref(), Some("---"));
+            assert_eq!(text("twice").as_deref(), Some("abab"));
+            assert_eq!(text("none").as_deref(), Some(""));
+            assert_eq!(text("t").as_deref(), Some("abab"));
+
+            for failing in ["let s: string = \"a\"\ns -= 1", "let r: string = \"a\" * -1"] {
+                let program = parse(failing);
+                let mut env = Environment::new();
+                let result = if use_vm {
+                    bytecode::execute_program(&program, &mut env)
+                } else {
+                    eval::statement::evaluate_statement(&Stmt::Program(program), &mut env)
+                };
+                assert!(result.is_err(), "{failing} should fail");
+            }
+        }
+        let program = parse("let rule: string = \"-\" * 3");
+        assert!(diagnostics::check_program(&program, &[], &mut Environment::new()).is_empty());
+    }
+
+    #[test]
+    fn template_strings_parse_their_interpolations() {
+        let program = parse(r#"let s: string = "a {x + 1} b {} c {nope nope} d {{k: 2}.k}""#);
+        let Some(Content::Statement(stmt)) = program.content.first() else {
+            panic!("expected a declaration");
+        };
+        let Stmt::VarDecl(decl) = stmt.as_ref() else {
+            panic!("expected a declaration, got {stmt:?}");
+        };
+        let Some(Content::Expression(expr)) = &decl.value else {
+            panic!("expected an initializer");
+        };
+        let Expr::TemplateLit(template) = expr.as_ref() else {
+            panic!("expected a template, got {expr:?}");
+        };
+        let texts: Vec<_> = template
+            .parts
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Text(text) => text.clone(),
+                TemplatePart::Expr { raw, .. } => format!("<{raw}>"),
+            })
+            .collect();
+        assert_eq!(texts, ["a ", "<x + 1>", " b {} c {nope nope} d ", "<{k: 2}.k>"]);
+
+        let plain = parse(r#"let s: string = '{} and {"k": 1}'"#);
+        let Some(Content::Statement(stmt)) = plain.content.first() else {
+            panic!("expected a declaration");
+        };
+        assert!(matches!(stmt.as_ref(), Stmt::VarDecl(VarDecl { value: Some(Content::Expression(e)), .. }) if matches!(e.as_ref(), Expr::StringLit(_))));
+    }
+
+    #[test]
+    fn template_strings_evaluate_in_both_engines() {
+        let source = r#"
+let name: string = "Zed"
+func greet || -> string {
+  return "hi {name}"
+}
+let items: arr = [1, 2, 3]
+let greeting: string = greet => ||
+let summary: string = "{items.length => ||} items, first {items[0]}, {missing} kept"
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("greeting"), Some(Value::String(s)) if s == "hi Zed"));
+            assert!(matches!(env.lookup("summary"), Some(Value::String(s)) if s == "3 items, first 1, {missing} kept"));
+        }
+    }
+
+    #[test]
+    fn output_waits_for_the_end_of_the_line() {
+        assert_eq!(output::ready_len("", false), 0);
+        assert_eq!(output::ready_len("progress", false), 0);
+        assert_eq!(output::ready_len("done\nnext", false), 5);
+        assert_eq!(output::ready_len("a\nb\n", false), 4);
+        assert_eq!(output::ready_len("progress", true), 8);
+
+        let source = r#"
+@print => |""|
+@flush => ||
+let result: any = @flush => ||
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("result"), Some(Value::Void)));
+        }
+    }
+
+    #[test]
+    fn results_are_only_echoed_when_asked_for() {
+        let source = "func answer || -> int { return 42 }\nanswer => ||";
+        assert_eq!(run_zekken(source), "");
+        config::set_print_result(true);
+        let echoed = run_zekken(source);
+        let void = run_zekken("let x: int = 1");
+        config::set_print_result(false);
+        assert_eq!(echoed, "42\n");
+        assert_eq!(void, "");
+    }
+
+    #[test]
+    fn repl_results_are_bound_to_underscore_names() {
+        let mut env = Environment::new();
+        let mut results = 0;
+        for input in ["6 * 7", "\"answer\""] {
+            match eval::statement::evaluate_statement(&Stmt::Program(parse(input)), &mut env) {
+                Ok(Some(value)) => env.bind_repl_result(&mut results, value),
+                other => panic!("{input} should produce a value, got {other:?}"),
+            }
+        }
+        assert_eq!(results, 2);
+        assert!(matches!(env.lookup("_1"), Some(Value::Int(42))));
+        assert!(matches!(env.lookup("_2"), Some(Value::String(s)) if s == "answer"));
+        assert!(matches!(env.lookup("_"), Some(Value::String(s)) if s == "answer"));
+
+        let sum = eval::statement::evaluate_statement(&Stmt::Program(parse("_1 + 1")), &mut env);
+        assert!(matches!(sum, Ok(Some(Value::Int(43)))), "{sum:?}");
+    }
+
+    #[test]
+    fn block_strings_keep_lines_and_drop_shared_indentation() {
+        let source = "let table: string = \"users\"
+let sql: string = \"\"\"
+    SELECT id
+      FROM {table}\\n
+    WHERE note = \"a\"
+    \"\"\"
+let inline: string = \"\"\"say \"hi\" {table}\"\"\"
+";
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("sql"), Some(Value::String(s)) if s == "SELECT id\n  FROM users\n\nWHERE note = \"a\""));
+            assert!(matches!(env.lookup("inline"), Some(Value::String(s)) if s == "say \"hi\" users"));
+        }
+
+        let (tokens, errors) = lexer::lex("let s: string = \"\"\"\n  a\n  \"\"\"\nlet t: int = 1".to_string());
+        assert!(errors.is_empty());
+        assert_eq!(tokens.iter().find(|t| t.value == "t").map(|t| (t.line, t.column)), Some((4, 5)));
+        let (_, errors) = lexer::lex("let s: string = \"\"\"never closed\"".to_string());
+        assert_eq!(errors[0].message, "Unterminated string literal");
+    }
+
+    #[test]
+    fn raw_strings_keep_backslashes_and_braces() {
+        let source = r#"
+let x: int = 1
+let path: string = r"C:\new\{x}"
+let quoted: string = r'say "\d+" {x}'
+let keyed: obj = { r"a\b": 2 }
+let r: string = "plain"
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("path"), Some(Value::String(s)) if s == r"C:\new\{x}"));
+            assert!(matches!(env.lookup("quoted"), Some(Value::String(s)) if s == r#"say "\d+" {x}"#));
+            assert!(matches!(env.lookup("keyed"), Some(Value::Object(o)) if matches!(o.get(r"a\b"), Some(Value::Int(2)))));
+            assert!(matches!(env.lookup("r"), Some(Value::String(s)) if s == "plain"));
+        }
+
+        let program = parse(r#"let s: string = r"\n""#);
+        let Some(Content::Statement(stmt)) = program.content.first() else {
+            panic!("expected a declaration");
+        };
+        assert!(matches!(stmt.as_ref(), Stmt::VarDecl(VarDecl { value: Some(Content::Expression(e)), .. })
+            if matches!(e.as_ref(), Expr::StringLit(lit) if lit.raw && lit.value == r"\n")));
+        let (_, errors) = lexer::lex(r#"let s: string = r"open"#.to_string());
+        assert!(errors.iter().any(|e| e.message == "Unterminated raw string literal"));
+    }
+
+    #[test]
+    fn return_unwinds_nested_loops_and_try_blocks() {
+        let source = r#"
+func find |grid: arr| -> int {
+  for |row| in grid {
+    for |cell| in row {
+      if cell > 4 {
+        return cell
+      }
+    }
+  }
+  return -1
+}
+func guarded |x: int| -> string {
+  try {
+    if x > 0 {
+      return "positive"
+    }
+  } catch |e| {
+    return "error"
+  }
+  return "other"
+}
+func spin |limit: int| -> int {
+  let n: int = 0
+  loop {
+    n += 1
+    if n == limit {
+      return n
+    }
+  }
+  return 0
+}
+let found: int = find => |[[1, 2], [3, 5], [9]]|
+let missing: int = find => |[[1]]|
+let pos: string = guarded => |3|
+let neg: string = guarded => |-3|
+func pick |i: int| -> int {
+  let r: int = match i { 2 -> { return 20; }, _ -> 0 }
+  return r + 1
+}
+let spun: int = spin => |7|
+let picked: int = pick => |2|
+let unpicked: int = pick => |3|
+let total: int = 0
+for |n| in [1, 2, 3] {
+  total += find => |[[n, 6]]|
+}
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("found"), Some(Value::Int(5))));
+            assert!(matches!(env.lookup("missing"), Some(Value::Int(-1))));
+            assert!(matches!(env.lookup("pos"), Some(Value::String(s)) if s == "positive"));
+            assert!(matches!(env.lookup("neg"), Some(Value::String(s)) if s == "other"));
+            assert!(matches!(env.lookup("spun"), Some(Value::Int(7))));
+            assert!(matches!(env.lookup("picked"), Some(Value::Int(20))));
+            assert!(matches!(env.lookup("unpicked"), Some(Value::Int(1))));
+            assert!(matches!(env.lookup("total"), Some(Value::Int(18))));
+            assert!(env.control_flow.is_none());
+        }
+    }
+
+    #[test]
+    fn repeated_use_shares_the_cached_library_object() {
+        let math = |env: &Environment| match env.lookup("math") {
+            Some(Value::Library(math)) => math,
+            other => panic!("math should be a shared library object, got {other:?}"),
+        };
+        let (mut first, mut second) = (Environment::new(), Environment::new());
+        crate::libraries::load_library("math", &mut first).unwrap();
+        crate::libraries::load_library("math", &mut second).unwrap();
+        assert!(Arc::ptr_eq(&math(&first), &math(&second)));
+
+        execute("use math\nuse math\nlet r: float = math.sqrt => |16|", true, &mut first);
+        assert!(matches!(first.lookup("r"), Some(Value::Float(r)) if r == 4.0));
+
+        // Assigning to a member copies the object for that variable alone.
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute("use math\nlet m: obj = math\nm.PI = 3\nlet pi: float = math.PI", use_vm, &mut env);
+            assert!(matches!(env.lookup("m"), Some(Value::Object(m)) if matches!(m.get("PI"), Some(Value::Int(3)))));
+            assert!(matches!(env.lookup("pi"), Some(Value::Float(pi)) if pi == std::f64::consts::PI));
+            assert!(Arc::ptr_eq(&math(&env), &math(&second)));
+        }
+    }
+
+    #[test]
+    fn distributions_sample_and_report_cdfs() {
+        let source = r#"
+use math
+use random
+let erf: float = math.erf => |0.5|
+let tail: float = math.erfc => |5|
+let fact: float = math.gamma => |6|
+let half: float = math.gamma => |0.5|
+let z: float = math.normal_cdf => |1.96|
+let iq: float = math.normal_cdf => |100, 100, 15|
+let coins: float = math.binomial_cdf => |5, 10, 0.5|
+let calls: float = math.poisson_cdf => |2, 3|
+let heads: int = random.binomial => |50, 0.5|
+let none: int = random.poisson => |0|
+let sample: float = random.normal => |10, 0|
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            let float = |name: &str| match env.lookup(name) {
+                Some(Value::Float(f)) => f,
+                other => panic!("{name} should be a float, got {other:?}"),
+            };
+            assert!((float("erf") - 0.520_499_877_813_046_5).abs() < 1e-13);
+            assert!((float("tail") - 1.537_459_794_428_035e-12).abs() < 1e-24);
+            assert_eq!(float("fact"), 120.0);
+            assert!((float("half") - std::f64::consts::PI.sqrt()).abs() < 1e-14);
+            assert!((float("z") - 0.975_002_104_851_779_5).abs() < 1e-13);
+            assert_eq!(float("iq"), 0.5);
+            assert!((float("coins") - 0.623_046_875).abs() < 1e-15);
+            assert!((float("calls") - 8.5 * (-3.0f64).exp()).abs() < 1e-15);
+            assert!(matches!(env.lookup("heads"), Some(Value::Int(h)) if (0..=50).contains(&h)));
+            assert!(matches!(env.lookup("none"), Some(Value::Int(0))));
+            assert_eq!(float("sample"), 10.0);
+        }
+
+        let program = parse("use math\nlet g: float = math.gamma => |-3|");
+        let error = eval::statement::evaluate_statement(&Stmt::Program(program), &mut Environment::new()).unwrap_err();
+        assert!(error.message.contains("gamma is undefined at -3"), "{}", error.message);
+    }
+
+    #[test]
+    fn calc_expressions_differentiate_and_evaluate() {
+        let source = r#"
+use calc
+let f: obj = calc.parse => |"x^2 + 3x"|
+let d: obj = f.diff => |"x"|
+let slope: float = d.eval => |{x: 2}|
+let derivative: string = "" + d
+let x: obj = calc.var => |"x"|
+let built: obj = x * x + 1
+let square: string = "" + built.simplify => ||
+let chain: string = "" + calc.diff => |"ln(x^2 + 1)"|
+let folded: string = "" + calc.simplify => |"0*y + 1*x - x + 2^3"|
+let same: bool = d == calc.diff => |f, "x"|
+let forged: obj = { __calc__: "x" }
+let shown: string = "" + forged
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            let text = |name: &str| match env.lookup(name) {
+                Some(Value::String(s)) => s,
+                other => panic!("{name} should be a string, got {other:?}"),
+            };
+            assert_eq!(text("derivative"), "2*x + 3");
+            assert!(matches!(env.lookup("slope"), Some(Value::Float(s)) if s == 7.0));
+            assert_eq!(text("square"), "x^2 + 1");
+            assert_eq!(text("chain"), "2*x/(x^2 + 1)");
+            assert_eq!(text("folded"), "8");
+            assert!(matches!(env.lookup("same"), Some(Value::Boolean(true))));
+            assert_eq!(text("shown"), "{__calc__: \"x\"}");
+        }
+
+        let program = parse("use calc\nlet v: float = calc.eval => |\"x + y\", {x: 1}|");
+        let error = eval::statement::evaluate_statement(&Stmt::Program(program), &mut Environment::new()).unwrap_err();
+        assert!(error.message.contains("no value given for 'y'"), "{}", error.message);
+    }
+
+    #[test]
+    fn diff_points_at_the_parts_of_values_that_differ() {
+        let source = r#"
+use assert
+let want: obj = { name: "ada", tags: ["a", "b"], age: 36 }
+let got: obj = { name: "ada", tags: ["a", "c", "d"], extra: true }
+let changes: arr = @diff => |want, got|
+let none: arr = @diff => |[1, { x: 2.0 }], [1.0, { x: 2 }]|
+let failure: string = ""
+try {
+  assert.eq => |got, want, "user"|
+} catch |e| {
+  failure = e.message
+}
+assert.eq => |none, []|
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            let Some(Value::Array(changes)) = env.lookup("changes") else { panic!("changes should be an array") };
+            let described: Vec<String> = changes.iter().map(ToString::to_string).collect();
+            assert_eq!(described, [
+                r#"{path: "$.tags[1]", expected: "b", actual: "c"}"#,
+                r#"{path: "$.tags[2]", actual: "d"}"#,
+                r#"{path: "$.age", expected: 36}"#,
+                r#"{path: "$.extra", actual: true}"#,
+            ]);
+            assert!(matches!(env.lookup("none"), Some(Value::Array(none)) if none.is_empty()));
+            assert!(matches!(env.lookup("failure"), Some(Value::String(message)) if message
+                == r#"user: assert.eq failed: $.tags[1]: expected "b", got "c"; $.tags[2]: unexpected "d"; $.age: missing, expected 36; $.extra: unexpected true"#));
+        }
+    }
+
+    #[test]
+    fn ast_visitor_reaches_nested_expressions() {
+        use crate::ast::visit::{walk_expr, Visitor};
+        use crate::ast::Expr;
+
+        #[derive(Default)]
+        struct Identifiers(Vec<String>);
+
+        impl Visitor for Identifiers {
+            fn visit_expr(&mut self, expr: &Expr) {
+                if let Expr::Identifier(ident) = expr {
+                    self.0.push(ident.name.clone());
+                }
+                walk_expr(self, expr);
+            }
+        }
+
+        let source = r#"
+func scale |x: int, factor: int = base| {
+    if x > limit {
+        return [x * factor, { total: !done }];
+    }
+    return x;
+}
+while running {
+    running = check => |count|
+}
+"#;
+        let program = parse(source);
+        let mut visitor = Identifiers::default();
+        visitor.visit_stmt(&Stmt::Program(program));
+        assert_eq!(
+            visitor.0,
+            vec!["base", "x", "limit", "x", "factor", "done", "x", "running", "running", "check", "count"]
+        );
+    }
+
+    #[test]
+    fn diagnostics_deduplicate_only_exact_errors() {
+        let duplicate = errors::ZekkenError::internal("duplicate");
+        let mut different_details = duplicate.clone();
+        different_details.extra = Some("different details".to_string());
+        let mut collected = vec![duplicate.clone(), different_details, duplicate];
+
+        errors::sort_and_dedup_errors(&mut collected);
+
+        assert_eq!(collected.len(), 2);
+        assert!(collected.iter().any(|error| error.extra.is_none()));
+        assert!(collected.iter().any(|error| error.extra.is_some()));
+    }
+
+    #[test]
+    fn manifest_lints_table_sets_levels() {
+        use crate::config::{LintLevel, ProjectConfig};
+
+        let config = ProjectConfig::parse(
+            r#"
+name = "demo"
+
+[lints]
+undefined-variable = "warn"
+builtin-call-syntax = "allow"
+"#,
+        )
+        .expect("valid manifest");
+        assert_eq!(config.lint_level("undefined-variable"), LintLevel::Warn);
+        assert_eq!(config.lint_level("builtin-call-syntax"), LintLevel::Allow);
+        assert_eq!(config.lint_level("const-assign"), LintLevel::Deny);
+
+        assert!(ProjectConfig::parse("[lints]\nno-such-lint = \"warn\"").is_err());
+        assert!(ProjectConfig::parse("[lints]\nconst-assign = \"loud\"").is_err());
+    }
+
+    #[test]
+    fn diagnostics_carry_help_and_fix_suggestions() {
+        let mut parser = parser::Parser::new();
+        parser.produce_ast("let xs: arr = [1, 2\n@println => |xs|\n".to_string());
+        let fixes: Vec<Option<&str>> = parser.errors.iter().map(|error| error.fix.as_deref()).collect();
+        assert_eq!(fixes, vec![Some("add ']' after this")]);
+
+        let program = parse("total = 4 + 1\nconst limit: int = 3\nlimit = 4\n");
+        let errors = diagnostics::check_program(&program, &[], &mut Environment::new());
+        let helps: Vec<Option<&str>> = errors.iter().map(|error| error.help.as_deref()).collect();
+        assert_eq!(
+            helps,
+            vec![
+                Some("declare it with `let total: int = ...;`"),
+                Some("declare 'limit' with 'let' instead of 'const' so it can change"),
+            ]
+        );
+        let json = errors[0].to_json();
+        assert_eq!((json["kind"].as_str(), json["line"].as_u64()), (Some("reference"), Some(1)));
+        assert_eq!(json["help"], "declare it with `let total: int = ...;`");
+    }
+
+    #[test]
+    fn type_errors_preview_the_array_or_object_they_found() {
+        let source = "let parsed: any = [1, \"two\", 3]\nlet point: obj = parsed\n";
+        for mode in [diagnostics::ExecutionMode::TreeWalk, diagnostics::ExecutionMode::Bytecode] {
+            let report = diagnostics::run_program_collecting(&parse(source), &[], &mut Environment::new(), mode);
+            let extra = report.errors[0].extra.clone().unwrap_or_default();
+            assert!(extra.contains("[1, \"two\", 3]"), "{extra}");
+        }
+
+        let long = Value::Array((0..100).map(Value::Int).collect());
+        let preview = long.preview().expect("arrays have a preview");
+        assert_eq!((preview.chars().count(), preview.ends_with("...")), (60, true));
+        assert_eq!(Value::Int(3).preview(), None);
+    }
+
+    #[test]
+    fn crash_reports_narrow_to_the_running_statement() {
+        let source = "let a: int = 1\nfunc f |x: int| -> int {\n  return x + 1\n}\n@println => |a|\n";
+        let report = crate::crash::render("main.zk", source, 2, "boom at src/eval/statement.rs:1:1");
+        assert!(report.contains(&format!("version: {}", env!("CARGO_PKG_VERSION"))), "{report}");
+        assert!(report.contains("panic: boom at src/eval/statement.rs:1:1"), "{report}");
+
+        let (snippet, _) = report.split_once("== Source ==").expect("source section");
+        assert!(snippet.contains("   3 |   return x + 1"), "{report}");
+        assert!(snippet.contains("4:1 CloseBrace"), "{report}");
+        assert!(snippet.contains("FuncDecl"), "{report}");
+        assert!(!snippet.contains("println"), "{report}");
+
+        let early = crate::crash::render("main.zk", source, 0, "boom");
+        assert!(early.contains("before the script started running"), "{early}");
+        assert!(early.contains("5:1 At"), "{early}");
+    }
+
+    #[test]
+    fn repl_errors_are_reported_against_the_session_source() {
+        let error = crate::errors::ZekkenError::reference("Variable 'missing' not found", "variable", 1, 14);
+        let session = "let xs: arr = [1, 2]\n@println => |missing|";
+        let relocated = error.relocated("<repl>", session, 2);
+        assert_eq!((relocated.context.line, relocated.context.column), (2, 14));
+
+        let report = relocated.full_report();
+        assert!(report.contains("<repl> -> [Ln: 2, Col: 14]"), "{report}");
+        assert!(report.contains("^~~~~~~"), "{report}");
+    }
+
+    #[test]
+    fn error_messages_follow_the_catalog_of_the_chosen_language() {
+        use crate::errors::i18n::{translate, Lang};
+
+        assert_eq!(Lang::from_code("es_ES.UTF-8"), Some(Lang::Es));
+        assert_eq!(Lang::from_code("fr"), None);
+        assert_eq!(
+            translate(Lang::Es, "Type mismatch for parameter 'width' of 'pad'"),
+            "Tipo incorrecto para el parámetro 'width' de 'pad'"
+        );
+        assert_eq!(translate(Lang::Es, "Warning [stuck-loop]"), "Advertencia [stuck-loop]");
+        assert_eq!(translate(Lang::Es, "Division by zero in 'avg'"), "Division by zero in 'avg'");
+        assert_eq!(translate(Lang::En, "Variable 'x' not found"), "Variable 'x' not found");
+
+        let config = crate::config::ProjectConfig::parse("[language]\nlang = \"es\"").expect("valid manifest");
+        assert_eq!(config.lang, Lang::Es);
+        assert!(crate::config::ProjectConfig::parse("[language]\nlang = \"tlh\"").is_err());
+    }
+
+    #[test]
+    fn keyword_aliases_need_the_pragma_or_manifest() {
+        use crate::lexer::{tokenize, BinOp, TokenType};
+
+        let kinds = |source: &str| -> Vec<TokenType> {
+            tokenize(source.to_string()).into_iter().map(|token| token.kind).collect()
+        };
+        assert_eq!(
+            kinds("// zekken: aliases\nfunction a and not b or c"),
+            [
+                TokenType::Func,
+                TokenType::Identifier,
+                TokenType::BinOp(BinOp::And),
+                TokenType::BinOp(BinOp::Not),
+                TokenType::Identifier,
+                TokenType::BinOp(BinOp::Or),
+                TokenType::Identifier,
+                TokenType::EOF,
+            ]
+        );
+        assert!(kinds("and or not function").iter().take(4).all(|kind| *kind == TokenType::Identifier));
+
+        let config = crate::config::ProjectConfig::parse("[language]\naliases = true").expect("valid manifest");
+        assert!(config.aliases);
+        assert!(crate::config::ProjectConfig::parse("[language]\nspelling = true").is_err());
+    }
+
+    #[test]
+    fn deprecations_warn_until_the_edition_that_removes_them() {
+        use crate::config::{deprecation, Edition, LintLevel, ProjectConfig};
+
+        let program = parse("let x: int = 1\nlet x: int = 3\nlet e: obj = {}\nlet tag: string = e.__resource__\n");
+        let found: Vec<(&str, usize)> = eval::deprecated::deprecations(&program)
+            .into_iter()
+            .map(|(name, error)| (name, error.context.line))
+            .collect();
+        assert_eq!(found, [("internal-keys", 4)]);
+
+        let internal_keys = deprecation("internal-keys").expect("registered deprecation");
+        assert_eq!(ProjectConfig::default().deprecation_level(internal_keys), LintLevel::Warn);
+        let config = ProjectConfig::parse("[language]\nedition = 2026").expect("valid manifest");
+        assert_eq!(config.edition, Edition::E2026);
+        assert_eq!(config.deprecation_level(internal_keys), LintLevel::Deny);
+        let config = ProjectConfig::parse("[language]\nedition = \"2026\"\n[deprecations]\ninternal-keys = \"warn\"")
+            .expect("valid manifest");
+        assert_eq!(config.deprecation_level(internal_keys), LintLevel::Warn);
+        assert!(ProjectConfig::parse("[deprecations]\nscoping = \"deny\"").is_err());
+        assert!(ProjectConfig::parse("[language]\nedition = 1999").is_err());
+    }
+
+    #[test]
+    fn unused_report_follows_includes() {
+        let dir = std::env::temp_dir().join(format!("zekken-unused-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("lib.zk"),
+            "func used || { return 1; }\nfunc stale || { return 2; }\nexport { used, stale };\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("extra.zk"), "let nobody: int = 0;\n").unwrap();
+        std::fs::write(
+            dir.join("main.zk"),
+            "include \"lib.zk\";\ninclude \"extra.zk\";\nlet x: int = used => ||;\n",
+        )
+        .unwrap();
+
+        let mut errors = Vec::new();
+        eval::unused::collect_unused(&dir.join("main.zk"), &mut errors).unwrap();
+        let mut reported: Vec<(&str, String)> = errors::take_warnings()
+            .into_iter()
+            .map(|warning| (warning.lint, warning.error.message))
+            .collect();
+        reported.sort();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(errors.is_empty());
+        assert_eq!(
+            reported,
+            vec![
+                ("unused-export", "Export 'stale' is never used by an including file".to_string()),
+                ("unused-function", "Function 'stale' is never used".to_string()),
+                ("unused-include", format!("Nothing from included file '{}' is used", dir.join("extra.zk").display())),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn which_only_resolves_files_that_can_run() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("zekken-which-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (tool, notes) = (dir.join("tool"), dir.join("notes"));
+        std::fs::write(&tool, "#!/bin/sh\n").unwrap();
+        std::fs::write(&notes, "not a program\n").unwrap();
+        std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::set_permissions(&notes, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let source = format!(
+            "use os\nlet tool: string = os.which => |\"{}\"|\nlet notes: string = os.which => |\"{}\"|\nlet missing: string = os.which => |\"zekken-no-such-tool\"|\nlet sep: string = os.path_separator\nlet nl: string = os.line_ending\n",
+            tool.display(),
+            notes.display()
+        );
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(&source, use_vm, &mut env);
+            let text = |name: &str| match env.lookup(name) {
+                Some(Value::String(s)) => s,
+                other => panic!("{name} should be a string, got {other:?}"),
+            };
+            assert_eq!(text("tool"), tool.display().to_string());
+            assert_eq!(text("notes"), "");
+            assert_eq!(text("missing"), "");
+            assert_eq!((text("sep"), text("nl")), (":".to_string(), "\n".to_string()));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exec_takes_a_directory_environment_input_and_timeout() {
+        let dir = std::env::temp_dir().canonicalize().unwrap();
+        let source = format!(
+            r#"
+use os
+let r: obj = os.exec => |"sh", ["-c", "pwd; echo $ZK_GREETING; cat"], {{ cwd: "{}", env: {{ ZK_GREETING: "hi" }}, stdin: "fed" }}|
+let slow: obj = os.exec => |"sleep", ["5"], {{ timeout: 50 }}|
+let plain: obj = os.exec => |"echo", {{ cwd: null }}|
+let failure: string = ""
+try {{
+  os.exec => |"echo", [], {{ timeout: -1 }}|
+}} catch |e| {{
+  failure = e.message
+}}
+"#,
+            dir.display()
+        );
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(&source, use_vm, &mut env);
+            let Some(Value::Object(r)) = env.lookup("r") else { panic!("exec should return an object") };
+            assert!(matches!(r.get("stdout"), Some(Value::String(out)) if *out == format!("{}\nhi\nfed", dir.display())));
+            assert!(matches!(r.get("ok"), Some(Value::Boolean(true))));
+            let Some(Value::Object(slow)) = env.lookup("slow") else { panic!("exec should return an object") };
+            assert!(matches!(slow.get("timed_out"), Some(Value::Boolean(true))));
+            assert!(matches!(slow.get("ok"), Some(Value::Boolean(false))));
+            assert!(matches!(slow.get("duration_ms"), Some(Value::Int(ms)) if *ms < 4000));
+            let Some(Value::Object(plain)) = env.lookup("plain") else { panic!("exec should return an object") };
+            assert!(matches!(plain.get("status"), Some(Value::Int(0))));
+            assert!(matches!(env.lookup("failure"), Some(Value::String(m)) if m == "os.exec option 'timeout' must be a non-negative int"));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn library_options_warn_about_unknown_keys() {
+        let source = r#"use os;
+let r: obj = os.exec => |"echo", ["hi"], { inherit_stdoi: false }|
+let failure: string = ""
+try {
+  os.exec => |"echo", [], { inherit_stdio: "yes" }|
+} catch |e| {
+  failure = e.message
+}
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            let Some(Value::Object(r)) = env.lookup("r") else { panic!("exec should return an object") };
+            assert!(matches!(r.get("stdout"), Some(Value::String(out)) if out == "hi\n"));
+            assert!(matches!(env.lookup("failure"), Some(Value::String(m)) if m == "os.exec option 'inherit_stdio' must be a bool"));
+            let warnings: Vec<(usize, String)> = errors::take_warnings()
+                .into_iter()
+                .filter(|warning| warning.lint == "unknown-option")
+                .map(|warning| (warning.error.context.line, warning.error.message))
+                .collect();
+            assert_eq!(
+                warnings,
+                vec![(2, "os.exec has no option 'inherit_stdoi' (it takes cwd, env, stdin, timeout, inherit_stdio)".to_string())]
+            );
+        }
+    }
+
+    #[test]
+    fn lazy_includes_only_run_declarations() {
+        let dir = std::env::temp_dir().join(format!("zekken-lazy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("util.zk"),
+            "func twice |x: int| -> int { return x * 2; }\nlet base: int = 10;\nexplode => ||\n",
+        )
+        .unwrap();
+
+        let util = dir.join("util.zk").to_string_lossy().replace('\\', "/");
+
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(&format!("include lazy \"{util}\";\nlet result: int = twice => |base|;\n"), use_vm, &mut env);
+            assert!(matches!(env.lookup("result"), Some(Value::Int(20))));
+        }
+        let eager = parse(&format!("include \"{util}\";\n"));
+        let eager = eval::statement::evaluate_statement(&Stmt::Program(eager), &mut Environment::new());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(eager.is_err());
+
+        // `lazy` is still an importable name.
+        let program = parse("include lazy from \"util.zk\";\n");
+        match program.imports[0] {
+            ast::Content::Statement(ref stmt) => match stmt.as_ref() {
+                Stmt::Include(include) => {
+                    assert!(!include.lazy);
+                    assert_eq!(include.methods.as_deref(), Some(&["lazy".to_string()][..]));
+                }
+                other => panic!("expected include, got {other:?}"),
+            },
+            ref other => panic!("expected include, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn lint_infers_types_before_running() {
+        let source = r#"
+func greet || { return "hi"; }
+func double |x: int| -> int { return x + x; }
+func broken |x: int| -> int { return "x" + x; }
+let count: int = greet => ||;
+let label: string = greet => ||;
+let twice: int = double => |label|;
+let ok: float = 1.5 * 2;
+for |item| in [1, "two"] {
+    let copy: int = 0;
+}
+try {
+    let guarded: int = "caught at runtime";
+} catch |e| {
+    @println => |e.message|
+}
+"#;
+        let program = parse(source);
+        let errors = diagnostics::check_program(&program, &[], &mut Environment::new());
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Type mismatch in function return value",
+                "Type mismatch in variable declaration 'count'",
+                "Type mismatch for parameter 'x' of 'double'",
+            ]
+        );
+    }
+
+    #[test]
+    fn switch_runs_the_first_equal_case_and_falls_through_on_request() {
+        let source = r#"
+func describe |code: int| -> string {
+  let label: string = ""
+  switch code {
+    case 200, 201 { label = "ok" }
+    case 301 {
+      label = "moved "
+      fallthrough
+    }
+    case 302 { label = label + "redirect" }
+    default { label = "other" }
+  }
+  return label
+}
+let ok: string = describe => |201|
+let moved: string = describe => |301|
+let found: string = describe => |302|
+let other: string = describe => |500|
+let word: string = "none"
+switch "b" {
+  case "a" { word = "a" }
+  case "b" { word = "b" }
+}
+let visited: int = 0
+for |n| in 0..6 {
+  switch n {
+    case 1 { continue }
+    case 4 { break }
+  }
+  visited += 1
+}
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            for (name, expected) in [("ok", "ok"), ("moved", "moved redirect"), ("found", "redirect"), ("other", "other"), ("word", "b")] {
+                assert!(matches!(env.lookup(name), Some(Value::String(s)) if s == expected), "{name}");
+            }
+            assert!(matches!(env.lookup("visited"), Some(Value::Int(3))));
+        }
+
+        let program = parse("let x: float = 1.0\nswitch x { default { } }\nswitch 1 {\n  case \"1\" { }\n}\n");
+        let errors = diagnostics::check_program(&program, &[], &mut Environment::new());
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, vec!["Switch value must be an int or a string", "Type mismatch in switch case"]);
+
+        for (source, message) in [
+            ("switch 1 {\n  default { }\n  case 1 { }\n}", "'default' must come after every case"),
+            ("switch 1 {\n  case 1 { fallthrough }\n}", "'fallthrough' needs a case or 'default' after it"),
+        ] {
+            let mut parser = parser::Parser::new();
+            parser.produce_ast(source.to_string());
+            let messages: Vec<&str> = parser.errors.iter().map(|error| error.message.as_str()).collect();
+            assert_eq!(messages, vec![message]);
+        }
+    }
+
+    #[test]
+    fn enum_match_runs_in_both_engines_and_checks_exhaustiveness() {
+        let source = r#"
+enum Color { Red, Green, Blue }
+let c: obj = Color.Blue;
+let label: string = match c {
+    Color.Red -> "warm",
+    Color.Green -> { "natural" }
+    Color.Blue -> "cool"
+};
+let name: string = Color.Green.name;
+let shown: string = "" + c;
+let forged: obj = { __enum__: "Color", name: "Blue" };
+let fooled: bool = forged == Color.Blue;
+let forged_text: string = "" + forged;
+let fields: arr = c.keys => ||;
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("label"), Some(Value::String(s)) if s == "cool"));
+            assert!(matches!(env.lookup("name"), Some(Value::String(s)) if s == "Green"));
+            assert!(matches!(env.lookup("shown"), Some(Value::String(s)) if s == "Color.Blue"));
+            // Copying a variant's visible fields does not make a variant.
+            assert!(matches!(env.lookup("fooled"), Some(Value::Boolean(false))));
+            assert!(matches!(env.lookup("forged_text"), Some(Value::String(s)) if s == "{__enum__: \"Color\", name: \"Blue\"}"));
+            assert!(matches!(env.lookup("c"), Some(Value::Enum { tag, variant, .. }) if &*tag == "Color" && &*variant == "Blue"));
+            assert_eq!(env.lookup("fields").map(|v| v.to_string()).as_deref(), Some("[\"name\", \"ordinal\"]"));
+        }
+
+        let program = parse(
+            r#"
+enum Color { Red, Green, Blue }
+let c: obj = Color.Red;
+match c {
+    Color.Red -> 1
+}
+match c {
+    Color.Red -> 1,
+    _ -> 2
+}
+"#,
+        );
+        let errors = diagnostics::check_program(&program, &[], &mut Environment::new());
+        assert_eq!(errors.len(), 1, "{errors:#?}");
+        assert_eq!(errors[0].message, "Non-exhaustive match on enum 'Color'");
+        assert!(format!("{}", errors[0]).contains("missing Color.Green, Color.Blue"));
+    }
+
+    #[test]
+    fn match_patterns_bind_destructure_and_guard() {
+        let source = r#"
+let point: arr = [2, 2];
+let shape: string = match point {
+    [0, 0] -> "origin",
+    [x, y] if x == y -> "diagonal",
+    _ -> "elsewhere"
+};
+let user: obj = { name: "ada", role: "owner" };
+let greeting: string = match user {
+    { name, role: "admin" | "owner" } -> "hello, " + name,
+    _ -> "hello"
+};
+let size: string = match 2 {
+    1 | 2 | 3 -> "small",
+    _ -> "large"
+};
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("shape"), Some(Value::String(s)) if s == "diagonal"));
+            assert!(matches!(env.lookup("greeting"), Some(Value::String(s)) if s == "hello, ada"));
+            assert!(matches!(env.lookup("size"), Some(Value::String(s)) if s == "small"));
+        }
+
+        // A guarded arm does not count towards exhaustiveness.
+        let program = parse(
+            r#"
+enum Color { Red, Green, Blue }
+let c: obj = Color.Red;
+match c {
+    Color.Red | Color.Green -> 1,
+    other if other == Color.Blue -> 2
+}
+"#,
+        );
+        let errors = diagnostics::check_program(&program, &[], &mut Environment::new());
+        assert_eq!(errors.len(), 1, "{errors:#?}");
+        assert!(format!("{}", errors[0]).contains("missing Color.Blue"));
+    }
+
+    #[test]
+    fn match_on_type_names_branches_on_any_values() {
+        let source = r#"
+func describe |x: any| -> string {
+    return match x {
+        int n if n < 0 -> "negative",
+        int | float -> "number",
+        string s -> "text " + s,
+        arr items -> "list of " + items.length,
+        any -> "other"
+    }
+}
+let data: any = @parse_json => |'[-3, 2.5, "a", [1, 2], {"k": true}]'|
+let labels: arr = []
+for |item| in data {
+    labels.push => |@describe => |item||
+}
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            let Some(Value::Array(labels)) = env.lookup("labels") else { panic!("labels missing") };
+            let labels: Vec<String> = labels.iter().map(|label| label.to_string()).collect();
+            assert_eq!(labels, ["negative", "number", "text a", "list of 2", "other"]);
+        }
+        let errors = diagnostics::check_program(&parse(source), &[], &mut Environment::new());
+        assert!(errors.is_empty(), "{errors:#?}");
+    }
+
+    #[test]
+    fn function_values_report_their_name_arity_and_params() {
+        let source = r#"
+func greet |who: string, punct: string = "!"| -> string { return "hi " + who + punct }
+let shout: fn -> |text: string| { return text + "!!" }
+let handlers: obj = { g: greet, s: shout }
+let names: string = handlers.g.name + "/" + handlers.s.name
+let arity: int = greet.arity
+let optional: bool = greet.params[1].optional
+let first_type: string = greet.params[0].type
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("names"), Some(Value::String(s)) if s == "greet/shout"));
+            assert!(matches!(env.lookup("arity"), Some(Value::Int(2))));
+            assert!(matches!(env.lookup("optional"), Some(Value::Boolean(true))));
+            assert!(matches!(env.lookup("first_type"), Some(Value::String(s)) if s == "string"));
+        }
+
+        // Argument errors name the function they are about.
+        let program = parse("func greet |who: string| { }\n@greet => |\"a\", \"b\"|\n");
+        for mode in [diagnostics::ExecutionMode::TreeWalk, diagnostics::ExecutionMode::Bytecode] {
+            let report = diagnostics::run_program_collecting(&program, &[], &mut Environment::new(), mode);
+            assert_eq!(report.errors[0].message, "Expected 1 arguments for 'greet' but got 2");
+        }
+    }
+
+    #[test]
+    fn function_typed_parameters_can_be_called() {
+        let source = r#"
+func apply |f: fn, x: int| -> int { return f => |x| }
+func apply_any |f: any, x: int| -> int { return f => |x| }
+func with_default |f: fn = fn -> |n: int| { return n }, x: int = 1| -> int { return f => |x| }
+let double: fn -> |n: int| { return n * 2 }
+let held: fn = double
+let applied: int = apply => |double, 21|
+let loose: int = apply_any => |held, 4|
+let defaulted: int = with_default => ||
+let indirect: int = held => |5|
+"#;
+        let errors = diagnostics::check_program(&parse(source), &[], &mut Environment::new());
+        assert!(errors.is_empty(), "{errors:#?}");
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("applied"), Some(Value::Int(42))));
+            assert!(matches!(env.lookup("loose"), Some(Value::Int(8))));
+            assert!(matches!(env.lookup("defaulted"), Some(Value::Int(1))));
+            assert!(matches!(env.lookup("indirect"), Some(Value::Int(10))));
+        }
+    }
+
+    #[test]
+    fn functions_can_be_referenced_without_calling_them() {
+        let source = r#"
+use math
+func double |n: int| -> int { return n * 2 }
+let steps: arr = [double, fn -> |n: int| { return n + 1 }]
+let table: obj = { twice: double, nested: { root: math.sqrt } }
+let root: fn = table.nested.root
+let first: int = steps[0] => |4|
+let second: int = steps[1] => |4|
+let twice: int = table.twice => |5|
+let rooted: float = root => |9.0|
+let same: bool = table.twice == double
+let different: bool = steps[1] == double
+"#;
+        let errors = diagnostics::check_program(&parse(source), &[], &mut Environment::new());
+        assert!(errors.is_empty(), "{errors:#?}");
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("first"), Some(Value::Int(8))));
+            assert!(matches!(env.lookup("second"), Some(Value::Int(5))));
+            assert!(matches!(env.lookup("twice"), Some(Value::Int(10))));
+            assert!(matches!(env.lookup("rooted"), Some(Value::Float(r)) if r == 3.0));
+            assert!(matches!(env.lookup("same"), Some(Value::Boolean(true))));
+            assert!(matches!(env.lookup("different"), Some(Value::Boolean(false))));
+        }
+    }
+
+    #[test]
+    fn lambda_expressions_are_values() {
+        let source = r#"
+func apply |f: fn, x: int| -> int { return f => |x| }
+let offset: int = 10
+let passed: int = apply => |fn -> |n: int| { return n * 2 }, 21|
+let ops: obj = { add: fn -> |a: int, b: int| { return a + b + offset } }
+let method: int = ops.add => |1, 2|
+let stored: fn = fn -> || { return "hi" }
+let greeting: string = stored => ||
+let name: string = stored.name
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("passed"), Some(Value::Int(42))));
+            assert!(matches!(env.lookup("method"), Some(Value::Int(13))));
+            assert!(matches!(env.lookup("greeting"), Some(Value::String(s)) if s == "hi"));
+            assert!(matches!(env.lookup("name"), Some(Value::String(s)) if s == "<lambda>"));
+        }
+    }
+
+    #[test]
+    fn returned_functions_keep_the_outer_functions_locals() {
+        let source = r#"
+func make_adder |n: int| -> fn {
+  return fn -> |x: int| { return n + x }
+}
+func make_nested |n: int| -> fn {
+  return fn -> |x: int| {
+    let g: fn -> |y: int| { return n + x + y }
+    return g => |100|
+  }
+}
+func make_fact |base: int| -> fn {
+  func fact |n: int| -> int {
+    if n <= 1 { return base }
+    return n * (fact => |n - 1|)
+  }
+  return fact
+}
+let add5: fn = make_adder => |5|
+let added: int = add5 => |1|
+let nested: fn = make_nested => |1|
+let deep: int = nested => |10|
+let fact: fn = make_fact => |1|
+let product: int = fact => |5|
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("added"), Some(Value::Int(6))));
+            assert!(matches!(env.lookup("deep"), Some(Value::Int(111))));
+            assert!(matches!(env.lookup("product"), Some(Value::Int(120))));
+        }
+    }
+
+    #[test]
+    fn closures_share_the_locals_they_capture() {
+        let source = r#"
+func counter || -> fn {
+  let n: int = 0
+  return fn -> || {
+    n = n + 1
+    return n
+  }
+}
+func later || -> int {
+  let v: int = 1
+  let read: fn = fn -> || { return v }
+  v = 7
+  return read => ||
+}
+func writer || -> int {
+  let v: int = 1
+  let bump: fn = fn -> || { v = v * 10 }
+  bump => ||
+  bump => ||
+  return v
+}
+let secret: int = 99
+func reads_secret || -> int { return secret }
+func shadows || -> int {
+  let secret: int = 5
+  return reads_secret => ||
+}
+let next: fn = counter => ||
+let first: int = next => ||
+let second: int = next => ||
+let seen: int = later => ||
+let written: int = writer => ||
+let lexical: int = shadows => ||
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("first"), Some(Value::Int(1))));
+            assert!(matches!(env.lookup("second"), Some(Value::Int(2))));
+            assert!(matches!(env.lookup("seen"), Some(Value::Int(7))));
+            assert!(matches!(env.lookup("written"), Some(Value::Int(100))));
+            assert!(matches!(env.lookup("lexical"), Some(Value::Int(99))));
+        }
+    }
+
+    #[test]
+    fn named_arguments_bind_by_parameter_name() {
+        let source = r#"
+func area |width: int, height: int = width * 2, scale: int = 1| -> int { return width * height * scale }
+let sub: fn -> |a: int, b: int| { return a - b }
+let named: int = area => |width: 3, height: 4|
+let reordered: int = area => |scale: 10, width: 2|
+let mixed: int = area => |5, scale: 3|
+let lambda: int = sub => |b: 1, a: 10|
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("named"), Some(Value::Int(12))));
+            assert!(matches!(env.lookup("reordered"), Some(Value::Int(80))));
+            assert!(matches!(env.lookup("mixed"), Some(Value::Int(150))));
+            assert!(matches!(env.lookup("lambda"), Some(Value::Int(9))));
+        }
+
+        let program = parse("func g |a: int| { }\ng => |b: 1|\ng => |1, a: 2|\n");
+        let errors = diagnostics::check_program(&program, &[], &mut Environment::new());
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert!(messages.contains(&"'g' has no parameter named 'b'"), "{messages:?}");
+        assert!(messages.contains(&"Argument 'a' of 'g' is given twice"), "{messages:?}");
+
+        let mut parser = parser::Parser::new();
+        parser.produce_ast("g => |a: 1, 2|".to_string());
+        assert_eq!(parser.errors[0].message, "Positional arguments must come before named ones");
+    }
+
+    #[test]
+    fn argument_type_errors_point_at_the_offending_argument() {
+        let program = parse("func pad |text: string, width: int| { }\npad => |\"x\", \"wide\"|\n");
+        for mode in [diagnostics::ExecutionMode::TreeWalk, diagnostics::ExecutionMode::Bytecode] {
+            let report = diagnostics::run_program_collecting(&program, &[], &mut Environment::new(), mode);
+            let error = &report.errors[0];
+            assert_eq!((error.message.as_str(), error.context.line, error.context.column), ("Type mismatch for parameter 'width' of 'pad'", 2, 14));
+        }
+        // Calls compiled inside a function body keep their argument locations too.
+        let program = parse("func pad |text: string| { }\nfunc outer || { pad => |1| }\nouter => ||\n");
+        for mode in [diagnostics::ExecutionMode::TreeWalk, diagnostics::ExecutionMode::Bytecode] {
+            let report = diagnostics::run_program_collecting(&program, &[], &mut Environment::new(), mode);
+            let error = &report.errors[0];
+            assert_eq!((error.message.as_str(), error.context.line, error.context.column), ("Type mismatch for parameter 'text' of 'pad'", 2, 25));
+        }
+    }
+
+    #[test]
+    fn array_annotations_can_name_their_element_type() {
+        let source = r#"
+func total |xs: arr<int>| -> int {
+  let sum: int = 0
+  for |x| in xs { sum += x }
+  return sum
+}
+let xs: arr<int> = [1, 2, 3]
+let grid: arr<arr<int>> = [[1], [2, 3]]
+let v: arr<float> = #[1, 2]
+let m: arr<arr<float>> = #[[1, 2], [3, 4]]
+xs.push => |4|
+let sum: int = total => |xs|
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("sum"), Some(Value::Int(10))));
+        }
+
+        let program = parse("let xs: arr<int> = [1, \"two\"];\nlet ok: arr<string> = [\"a\"];\nlet rows: arr<arr<int>> = [[1], [true]];\n");
+        let errors = diagnostics::check_program(&program, &[], &mut Environment::new());
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Type mismatch in variable declaration 'xs' at element 1",
+                "Type mismatch in variable declaration 'rows' at element 1 at element 0",
+            ]
+        );
+
+        // Arrays built at runtime are checked element by element when stored.
+        for source in [
+            "let raw: arr = [1, \"two\"]\nlet xs: arr<int> = raw\n",
+            "let xs: arr<int> = [1]\nxs = [\"two\"]\n",
+            "func first |xs: arr<string>| -> string { return xs[0] }\nfirst => |[1]|\n",
+        ] {
+            for mode in [diagnostics::ExecutionMode::TreeWalk, diagnostics::ExecutionMode::Bytecode] {
+                let report = diagnostics::run_program_collecting(&parse(source), &[], &mut Environment::new(), mode);
+                let error = report.errors.first().unwrap_or_else(|| panic!("{source:?} should fail"));
+                assert!(error.message.contains("Type mismatch"), "{}", error.message);
+            }
+        }
+    }
+
+    #[test]
+    fn nullable_annotations_admit_null_where_plain_types_do_not() {
+        let source = r#"
+func find |xs: arr<int>, want: int| -> int? {
+  for |x| in xs { if x == want { return x } }
+  return null
+}
+func describe |x: int?| -> string {
+  if x == null { return "none" }
+  return "some"
+}
+let hit: int? = find => |[1, 2], 2|
+let miss: int? = find => |[1, 2], 5|
+let tags: arr<string?> = ["a", null]
+let text: string = describe => |miss|
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("hit"), Some(Value::Int(2))));
+            assert!(matches!(env.lookup("miss"), Some(Value::Null)));
+            assert!(matches!(env.lookup("text"), Some(Value::String(text)) if text == "none"));
+        }
+
+        let program = parse("let n: int? = \"one\";\nlet ok: int? = null;\n");
+        let errors = diagnostics::check_program(&program, &[], &mut Environment::new());
+        let messages: Vec<&str> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, vec!["Type mismatch in variable declaration 'n'"]);
+
+        for source in [
+            "func f |x: int| -> int { return x }\nf => |null|\n",
+            "func f || -> int { return null }\nf => ||\n",
+            "let xs: arr<int> = [1, null]\n",
+        ] {
+            for mode in [diagnostics::ExecutionMode::TreeWalk, diagnostics::ExecutionMode::Bytecode] {
+                let report = diagnostics::run_program_collecting(&parse(source), &[], &mut Environment::new(), mode);
+                let error = report.errors.first().unwrap_or_else(|| panic!("{source:?} should fail"));
+                assert!(error.message.contains("Type mismatch"), "{}", error.message);
+            }
+        }
+    }
+
+    #[test]
+    fn ranges_count_up_to_their_end_and_stay_lazy_in_loops() {
+        let source = r#"
+let total: int = 0
+for |i| in 1..5 { total += i }
+let pairs: arr = []
+for |index, n| in 10..12 { pairs.push => |index * 100 + n| }
+let empty: int = 0
+for |i| in 3..3 { empty += 1 }
+let digits: arr = 0..3
+let seen: int = 0
+for |i| in 0..1000000000000 {
+    if i == 3 { break }
+    seen += 1
+}
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("total"), Some(Value::Int(10))));
+            assert!(matches!(env.lookup("pairs"), Some(Value::Array(pairs)) if matches!(pairs[..], [Value::Int(10), Value::Int(111)])));
+            assert!(matches!(env.lookup("empty"), Some(Value::Int(0))));
+            assert!(matches!(env.lookup("digits"), Some(Value::Array(digits)) if digits.len() == 3));
+            assert!(matches!(env.lookup("seen"), Some(Value::Int(3))));
+        }
+
+        let errors = diagnostics::check_program(&parse("let f: float = 1.5\nlet xs: arr = 0..f\n"), &[], &mut Environment::new());
+        assert_eq!(errors.len(), 1, "{errors:#?}");
+        assert_eq!(errors[0].message, "Type mismatch for the end of a range");
+    }
+
+    #[test]
+    fn null_fills_variables_of_any_type() {
+        let source = r#"
+let found: int = null;
+let was_null: bool = found == null;
+found = 3
+let now_null: bool = found == null;
+let data: obj = @parse_json => |'{"missing": null}'|
+let from_json: bool = data.missing == null;
+let label: string = match data.missing {
+    null -> "none",
+    _ -> "some"
+};
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("was_null"), Some(Value::Boolean(true))));
+            assert!(matches!(env.lookup("now_null"), Some(Value::Boolean(false))));
+            assert!(matches!(env.lookup("from_json"), Some(Value::Boolean(true))));
+            assert!(matches!(env.lookup("label"), Some(Value::String(s)) if s == "none"));
+        }
+
+        // Parameters keep their declared type.
+        let program = parse("func f |n: int| { return n; }\nf => |null|\n");
+        let report = diagnostics::run_program_collecting(&program, &[], &mut Environment::new(), diagnostics::ExecutionMode::TreeWalk);
+        assert_eq!(report.errors.len(), 1, "{:#?}", report.errors);
+        assert_eq!(report.errors[0].message, "Type mismatch for parameter 'n' of 'f'");
+    }
+
+    #[test]
+    fn history_keeps_the_latest_changes_per_variable() {
+        let source = "let total: int = 0\nlet i: int = 0\nwhile i < 5 {\n  total += i\n  i = i + 1\n}\nlet items: arr = [1]\nitems[0] = 9\n";
+        for use_vm in [false, true] {
+            history::record_with_cap(3);
+            execute(source, use_vm, &mut Environment::new());
+            let total = history::of("total").expect("total was recorded");
+            assert_eq!(total.dropped, 3);
+            let values: Vec<String> = total.changes.iter().map(|c| c.value.to_string()).collect();
+            assert_eq!(values, ["3", "6", "10"]);
+            assert!(total.changes.iter().all(|c| c.line == 4));
+            let items = history::of("items").expect("items was recorded");
+            assert_eq!(items.changes.back().map(|c| c.value.to_string()), Some("[9]".to_string()));
+            assert!(history::of("missing").is_none());
+        }
+        history::record_with_cap(0);
+        execute("let quiet: int = 1\n", false, &mut Environment::new());
+        assert!(history::of("quiet").is_none());
+    }
+
+    #[test]
+    fn coverage_reports_statements_that_never_ran() {
+        let source = "func sign |n: int| -> string {\n  if n < 0 {\n    return \"negative\"\n  }\n  return \"positive\"\n}\nfunc unused |n: int| -> int {\n  return n\n}\nlet total: int = 0\nlet i: int = 0\nwhile i < 3 {\n  total += i\n  i += 1\n}\nlet label: string = sign => |total|\nif total > 100 {\n  total = 0\n} else {\n  total = 1\n}\n";
+        for use_vm in [false, true] {
+            coverage::start();
+            execute(source, use_vm, &mut Environment::new());
+            let report = coverage::Report::new(&parse(source), &coverage::stop());
+            assert_eq!(report.missed().collect::<Vec<_>>(), [3, 8, 18]);
+            assert_eq!(report.lines.get(&13), Some(&3));
+            assert_eq!(report.lines.get(&20), Some(&1));
+            let lcov = report.to_lcov("t.zk");
+            assert!(lcov.starts_with("TN:\nSF:t.zk\n"));
+            assert!(lcov.ends_with(&format!("LF:{}\nLH:{}\nend_of_record\n", report.lines.len(), report.hit())));
+        }
+        assert!(coverage::stop().is_empty());
+    }
+
+    #[test]
+    fn traces_record_calls_statements_assignments_and_errors() {
+        let source = "func square |n: int| -> int {\n  return n * n\n}\nlet total: int = 0\ntotal = square => |3|\ntry {\n  let bad: int = total / 0\n} catch |e| {\n  total = 1\n}\n";
+        for use_vm in [false, true] {
+            trace::start();
+            execute(source, use_vm, &mut Environment::new());
+            let document = trace::finish();
+            let events = document["traceEvents"].as_array().unwrap();
+            let of = |category: &str| events.iter().filter(|e| e["cat"] == category).collect::<Vec<_>>();
+            let calls = of("function");
+            assert_eq!(calls.len(), 2, "{calls:#?}");
+            assert_eq!((calls[0]["ph"].as_str(), calls[0]["name"].as_str()), (Some("B"), Some("square")));
+            assert_eq!(calls[1]["ph"], "E");
+            let totals: Vec<_> = of("assign").iter().filter(|e| e["name"] == "total").map(|e| e["args"]["value"].clone()).collect();
+            assert_eq!(totals, ["0", "9", "1"]);
+            assert!(of("statement").iter().any(|e| e["args"]["line"] == 2));
+            let errors = of("error");
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0]["args"]["message"], "Division by zero");
+            assert_eq!(errors[0]["args"]["caught"], true);
+            assert!(events.windows(2).all(|pair| pair[0]["ts"].as_u64() <= pair[1]["ts"].as_u64()));
+        }
+        assert!(!trace::is_recording());
+    }
+
+    #[test]
+    fn expressions_evaluate_against_a_frozen_scope() {
+        let mut env = Environment::new();
+        execute("let x: int = 20\n", false, &mut env);
+        let frozen = |source: &str| eval::env_eval::eval_frozen(source, &env);
+        assert!(matches!(frozen("x + 1"), Ok(Value::Int(21))));
+        frozen("x = 5").unwrap();
+        assert!(matches!(env.lookup("x"), Some(Value::Int(20))));
+        assert!(frozen("let y: int = 1").is_err());
+
+        let source = "let x: int = 4\nlet seen: int = 0\ntry {\n  let bad: int = x / 0\n} catch |e| {\n  seen = e.env_eval => |\"x * 2\"|\n}\n";
+        config::set_env_eval(true);
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("seen"), Some(Value::Int(8))));
+        }
+        config::set_env_eval(false);
+    }
+
+    #[test]
+    fn library_failures_carry_a_code() {
+        let missing = std::env::temp_dir().join(format!("zekken-missing-{}", std::process::id()));
+        let source = format!(
+            r#"use fs;
+use assert;
+let code: any = ""
+let kind: string = ""
+let retryable: bool = true
+try {{
+  fs.read_file => |"{}"|
+}} catch |e| {{
+  code = e.code
+  kind = e.kind
+  retryable = e.retryable
+}}
+let user_code: any = ""
+try {{
+  assert.ok => |false|
+}} catch |e| {{
+  user_code = e.code
+}}
+"#,
+            missing.to_string_lossy().replace('\\', "/")
+        );
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(&source, use_vm, &mut env);
+            assert!(matches!(env.lookup("code"), Some(Value::String(c)) if c == "ENOENT"));
+            assert!(matches!(env.lookup("kind"), Some(Value::String(k)) if k == "Library"));
+            assert!(matches!(env.lookup("retryable"), Some(Value::Boolean(false))));
+            assert!(matches!(env.lookup("user_code"), Some(Value::Null)));
+        }
+
+        let error = errors::ZekkenError::runtime("Failed to read file 'x': gone", 1, 1, None)
+            .with_code(errors::ErrorCode { code: "ENOENT", retryable: false });
+        assert!(error.to_repl_string().starts_with("Library Error [ENOENT]: Failed to read file 'x'"));
+    }
+
+    #[test]
+    fn runaway_recursion_is_a_catchable_error() {
+        let source = "func down |n: int| -> int {\n  return 1 + down => |n + 1|\n}\nlet message: string = \"\"\ntry {\n  let never: int = down => |0|\n} catch |e| {\n  message = e.message\n}\nfunc count |n: int| -> int {\n  if n == 0 {\n    return 0\n  }\n  return 1 + count => |n - 1|\n}\nlet deep: int = count => |900|\n";
+        let runner = std::thread::Builder::new()
+            .stack_size(config::stack_size_for(config::DEFAULT_MAX_CALL_DEPTH))
+            .spawn(move || {
+                for use_vm in [false, true] {
+                    let mut env = Environment::new();
+                    execute(source, use_vm, &mut env);
+                    assert!(matches!(env.lookup("message"), Some(Value::String(m)) if m == "maximum recursion depth exceeded"));
+                    assert!(matches!(env.lookup("deep"), Some(Value::Int(900))));
+                }
+                config::set_max_call_depth(100);
+                let program = parse("func count |n: int| -> int {\n  if n == 0 {\n    return 0\n  }\n  return 1 + count => |n - 1|\n}\nlet deep: int = count => |200|\n");
+                let error = bytecode::execute_program(&program, &mut Environment::new()).expect_err("the limit should stop the recursion");
+                assert_eq!(error.message, "maximum recursion depth exceeded");
+            })
+            .unwrap();
+        runner.join().unwrap();
+    }
+
+    #[test]
+    fn loops_can_be_capped_and_flagged_when_stuck() {
+        let spin = "let n: int = 0\nwhile n >= 0 {\n  n += 1\n}\n";
+        let forever = "let k: int = 0\nloop {\n  k += 1\n}\n";
+        let counted = "let t: int = 0\nfor |let i: int = 0; i < 20; i += 1| {\n  t += i\n}\n";
+        config::set_max_iterations(10);
+        for use_vm in [false, true] {
+            for source in [spin, forever, counted] {
+                let program = parse(source);
+                let mut env = Environment::new();
+                let result = if use_vm {
+                    bytecode::execute_program(&program, &mut env)
+                } else {
+                    eval::statement::evaluate_statement(&Stmt::Program(program), &mut env)
+                };
+                let error = result.expect_err("the cap should stop the loop");
+                assert_eq!(error.message, "Loop stopped after 10 iterations");
+            }
+        }
+        config::set_max_iterations(0);
+        let mut env = Environment::new();
+        execute(counted, true, &mut env);
+        assert!(matches!(env.lookup("t"), Some(Value::Int(190))));
+
+        let stuck = parse(
+            "let i: int = 0\nlet n: int = 3\nwhile i < n {\n  @println => |i|\n}\nwhile i < n {\n  i += 1\n}\nlet q: arr = [1]\nwhile q.length => || > 0 {\n  q.pop => ||\n}\nwhile n > 0 {\n  if i == 2 {\n    break\n  }\n}\nfor |let j: int = 0; j < n; | {\n  n = 0\n}\nfor |let j: int = 0; j < n; | {\n}\n",
+        );
+        let errors = eval::loops::stuck_loops(&stuck);
+        let lines: Vec<usize> = errors.iter().map(|e| e.context.line).collect();
+        assert_eq!(lines, [3, 21]);
+        assert!(errors[0].message.starts_with("Loop condition only reads 'i', 'n'"));
+    }
+
+    #[test]
+    fn match_arms_can_test_value_kinds() {
+        let source = r#"
+let kinds: string = "";
+for |v| in [-2, 7, 1.5, "hi", true, [1, 2, 3], { a: 1 }] {
+    kinds = kinds + match v {
+        int n if n < 0 -> "neg ",
+        int | float -> "num ",
+        string s -> s + " ",
+        bool -> "bool ",
+        arr -> "arr ",
+        _ -> "other"
+    }
+}
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("kinds"), Some(Value::String(s)) if s == "neg num num hi bool arr other"));
+        }
+    }
+
+    #[test]
+    fn deferred_expressions_run_last_in_first_out_and_report_errors() {
+        let source = r#"
+let log: string = "";
+defer log = log + "b";
+defer log = log + "a";
+log = log + "-"
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("log"), Some(Value::String(s)) if s == "-ab"));
+        }
+
+        let source = r#"
+func close || -> int {
+    let zero: int = 0;
+    defer 1 / zero;
+    return 1;
+}
+let closed: int = close => ||;
+"#;
+        for use_vm in [false, true] {
+            let program = parse(source);
+            let mut env = Environment::new();
+            let result = if use_vm {
+                bytecode::execute_program(&program, &mut env)
+            } else {
+                eval::statement::evaluate_statement(&Stmt::Program(program), &mut env)
+            };
+            assert!(matches!(result, Err(e) if e.message == "Division by zero"));
+        }
+    }
+
+    #[test]
+    fn with_blocks_close_resources_even_when_the_body_fails() {
+        let source = r#"
+try {
+    with resource as r {
+        let n: int = r.missing_count => ||;
+    }
+} catch |e| {
+    let caught: bool = true;
+}
+"#;
+        for use_vm in [false, true] {
+            let closes = Arc::new(Mutex::new(0));
+            let counter = closes.clone();
+            let mut resource = indexmap::IndexMap::new();
+            resource.insert(
+                "__close__".to_string(),
+                Value::NativeFunction(Arc::new(move |_| {
+                    *counter.lock().unwrap() += 1;
+                    Ok(Value::Void)
+                })),
+            );
+            let mut env = Environment::new();
+            env.declare_ref_typed("resource", Value::Object(resource), DataType::Object, false);
+            execute(source, use_vm, &mut env);
+            assert_eq!(*closes.lock().unwrap(), 1);
+        }
+    }
+
+    /// Runs `source` with `files` in an in-memory file system on top of the
+    /// disk, returning the files the script left in it.
+    fn execute_with_files(source: &str, use_vm: bool, files: &[(&str, &str)]) -> (Environment, BTreeMap<String, String>) {
+        use crate::libraries::vfs::{set_backend, Disk, Overlay};
+        let overlay = files
+            .iter()
+            .fold(Overlay::over(Arc::new(Disk)), |overlay, (path, contents)| overlay.with_file(path, contents));
+        let previous = set_backend(Arc::new(overlay.clone()));
+        let mut env = Environment::new();
+        execute(source, use_vm, &mut env);
+        set_backend(previous);
+        (env, overlay.files())
+    }
+
+    #[test]
+    fn scripts_can_use_files_that_only_exist_in_memory() {
+        let source = r#"
+use fs;
+let config: string = fs.read_file => |"zk_overlay/config.txt"|
+fs.create_dir => |"zk_overlay/out"|
+fs.write_file => |"zk_overlay/out/report.txt", config + "done"|
+let h: obj = fs.open => |"zk_overlay/out/report.txt", "a"|
+h.write => |"!"|
+h.close => ||
+fs.remove_file => |"zk_overlay/config.txt"|
+let still_there: bool = fs.exists => |"zk_overlay/config.txt"|
+let listed: arr = fs.read_dir => |"zk_overlay"|
+let on_disk: bool = fs.exists => |"Cargo.toml"|
+"#;
+        for use_vm in [false, true] {
+            let (env, files) = execute_with_files(source, use_vm, &[("zk_overlay/config.txt", "ok ")]);
+            assert_eq!(files.get("zk_overlay/out/report.txt").map(String::as_str), Some("ok done!"));
+            assert!(!files.contains_key("zk_overlay/config.txt"));
+            assert!(matches!(env.lookup("still_there"), Some(Value::Boolean(false))));
+            assert!(matches!(env.lookup("listed"), Some(Value::Array(items)) if items.len() == 1));
+            assert!(matches!(env.lookup("on_disk"), Some(Value::Boolean(true))));
+            assert!(!std::path::Path::new("zk_overlay").exists());
+        }
+    }
+
+    #[test]
+    fn file_handles_read_write_and_seek_incrementally() {
+        for use_vm in [false, true] {
+            let path = std::env::temp_dir().join(format!("zekken_handle_{}_{}.txt", std::process::id(), use_vm));
+            let source = format!(
+                r#"
+use fs;
+let path: string = "{}";
+with fs.open => |path, "w+"| as f {{
+    f.write => |"alpha\nbeta\n"|
+    f.seek => |0|
+}}
+let h: obj = fs.open => |path, "r+"|;
+let first: string = h.read_line => ||;
+let next: string = h.read => |2|;
+let end: int = h.seek => |0, "end"|;
+h.write => |"gamma"|
+h.close => ||
+let closed: bool = h.is_closed => ||;
+"#,
+                path.display()
+            );
+            let mut env = Environment::new();
+            execute(&source, use_vm, &mut env);
+            assert!(matches!(env.lookup("first"), Some(Value::String(s)) if s == "alpha\n"));
+            assert!(matches!(env.lookup("next"), Some(Value::String(s)) if s == "be"));
+            assert!(matches!(env.lookup("end"), Some(Value::Int(11))));
+            assert!(matches!(env.lookup("closed"), Some(Value::Boolean(true))));
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "alpha\nbeta\ngamma");
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn environment_diffs_report_added_changed_and_removed_bindings() {
+        let mut env = Environment::new();
+        execute("let kept: int = 1;\nlet bumped: int = 1;", false, &mut env);
+        let before = env.snapshot();
+        let mut child = Environment::new_with_parent(env.clone());
+        execute("bumped = 2\nlet fresh: string = \"new\";", false, &mut child);
+        let diff = before.diff(&child.snapshot());
+        assert!(matches!(diff.added.as_slice(), [(name, Value::String(v))] if name == "fresh" && v == "new"));
+        assert!(matches!(diff.changed.as_slice(), [(name, Value::Int(1), Value::Int(2))] if name == "bumped"));
+        assert!(diff.removed.is_empty());
+
+        let reverse = child.snapshot().diff(&before);
+        assert!(matches!(reverse.removed.as_slice(), [(name, _)] if name == "fresh"));
+        assert!(before.diff(&env.snapshot()).added.is_empty());
+    }
+
+    #[test]
+    fn objects_keep_insertion_order_without_hidden_keys() {
+        let source = r#"
+let o: obj = { zeta: 1, alpha: 2, mid: 3 };
+o.beta = 4
+let keys: arr = o.keys => ||;
+let order: string = "";
+for |k, v| in o { order += k }
+let hidden: bool = o.hasKey => |"__keys__"|;
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            let Some(Value::Array(keys)) = env.lookup("keys") else { panic!("keys missing") };
+            let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+            assert_eq!(keys, ["zeta", "alpha", "mid", "beta"]);
+            assert!(matches!(env.lookup("order"), Some(Value::String(s)) if s == "zetaalphamidbeta"));
+            assert!(matches!(env.lookup("hidden"), Some(Value::Boolean(false))));
+        }
+    }
+
+    #[test]
+    fn object_literals_accept_string_and_computed_keys() {
+        let source = r#"
+let field: string = "score";
+let o: obj = { "content-type": "json", [field]: 10, [field + "_max"]: 100, [2]: "two" };
+let keys: arr = o.keys => ||;
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            let Some(Value::Array(keys)) = env.lookup("keys") else { panic!("keys missing") };
+            let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+            assert_eq!(keys, ["content-type", "score", "score_max", "2"]);
+        }
+    }
+
+    #[test]
+    fn semicolons_are_optional_at_line_ends() {
+        let source = r#"
+let a: int = 1
+const b: int = 2 // trailing comment
+func twice |n: int| -> int { return n * 2 }
+let c: int = twice => |a + b|
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("c"), Some(Value::Int(6))));
+        }
+
+        let mut parser = parser::Parser::new();
+        parser.produce_ast("let a: int = 1 let b: int = 2".to_string());
+        assert!(parser.errors.iter().any(|e| e.message.contains("Expected ';' after variable declaration")));
+    }
+
+    #[test]
+    fn for_in_loops_keep_their_identifiers_separate() {
+        let program = parse("for |k, v| in { a: 1 } { }");
+        match program.content.first() {
+            Some(ast::Content::Statement(stmt)) => match stmt.as_ref() {
+                Stmt::ForIn(for_in) => assert_eq!(for_in.idents, ["k", "v"]),
+                other => panic!("expected a for-in loop, got {:?}", other),
+            },
+            other => panic!("expected a statement, got {:?}", other),
+        }
+
+        let source = r#"
+let total: int = 0
+for |i, n| in [10, 20] { total = total + i + n }
+for |k, v| in { a: 1, b: 2 } { total = total + v }
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("total"), Some(Value::Int(34))));
+            assert!(env.lookup("k").is_none());
+            assert!(env.lookup("k, v").is_none());
+        }
+    }
+
+    #[test]
+    fn for_in_loop_variables_are_fresh_each_iteration() {
+        let source = r#"
+let item: string = "outer"
+let k: int = 7
+let seen: arr = []
+let sum: int = 0
+for |item| in [1, 2, 3] {
+    seen.push => |item|
+    item = item * 10
+    sum += item
+}
+let keys: string = ""
+for |k, v| in { a: 1, b: 2 } {
+    keys = keys + k
+    v = 0
+}
+for |i, row| in [[1], [2]] {
+    for |i, cell| in row {
+        sum += cell
+    }
+    sum += i
+}
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("item"), Some(Value::String(s)) if s == "outer"));
+            assert!(matches!(env.lookup("k"), Some(Value::Int(7))));
+            assert_eq!(env.lookup("seen").map(|v| v.to_string()), Some("[1, 2, 3]".to_string()));
+            assert!(matches!(env.lookup("sum"), Some(Value::Int(64))));
+            assert!(matches!(env.lookup("keys"), Some(Value::String(s)) if s == "ab"));
+            assert!(env.lookup("v").is_none());
+            assert!(env.lookup("i").is_none());
+        }
+    }
+
+    #[test]
+    fn c_style_for_loops_scope_their_counter() {
+        let source = r#"
+let i: string = "outer"
+let total: int = 0
+for |let i: int = 0; i < 5; i += 1| { total += i }
+let n: int = 0
+for |; n < 3; n += 1| { }
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("total"), Some(Value::Int(10))));
+            assert!(matches!(env.lookup("i"), Some(Value::String(i)) if i == "outer"));
+            assert!(matches!(env.lookup("n"), Some(Value::Int(3))));
+        }
+    }
+
+    #[test]
+    fn break_ends_the_innermost_loop_and_skips_loop_else() {
+        let source = r#"
+let n: int = 0
+loop {
+  n += 1
+  if n == 4 { break }
+}
+let found: string = "none"
+for |x| in [1, 3, 8, 9] {
+  if x % 2 == 0 {
+    found = "even"
+    break
+  }
+} else {
+  found = "all odd"
+}
+let finished: bool = false
+let i: int = 0
+while i < 3 {
+  i += 1
+  loop { break }
+} else {
+  finished = true
+}
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("n"), Some(Value::Int(4))));
+            assert!(matches!(env.lookup("found"), Some(Value::String(found)) if found == "even"));
+            assert!(matches!(env.lookup("i"), Some(Value::Int(3))));
+            assert!(matches!(env.lookup("finished"), Some(Value::Boolean(true))));
+        }
+
+        let mut parser = parser::Parser::new();
+        parser.produce_ast("while true { func f || { break } }".to_string());
+        assert!(parser.errors.iter().any(|e| e.message.contains("'break' outside of a loop")));
+    }
+
+    #[test]
+    fn labelled_break_ends_the_named_outer_loop() {
+        let source = r#"
+let visited: int = 0
+rows: for |row| in [[1, 2, 3], [4, 5, 6], [7, 8, 9]] {
+  for |cell| in row {
+    visited += 1
+    if cell == 5 { break rows }
+  }
+}
+let count: int = 0
+outer: loop {
+  let i: int = 0
+  while i < 10 {
+    i += 1
+    count += 1
+    if count == 7 { break outer }
+    if i == 3 { break }
+  }
+}
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("visited"), Some(Value::Int(5))));
+            assert!(matches!(env.lookup("count"), Some(Value::Int(7))));
+        }
+
+        let mut parser = parser::Parser::new();
+        parser.produce_ast("a: while true { break b }".to_string());
+        assert!(parser.errors.iter().any(|e| e.message.contains("Unknown loop label 'b'")));
+    }
+
+    #[test]
+    fn main_is_called_with_the_script_args_and_returns_the_exit_code() {
+        let program = parse("let base: int = 10\nfunc main |args: arr| -> int {\n  return base + args.length\n}\n");
+        for mode in [diagnostics::ExecutionMode::TreeWalk, diagnostics::ExecutionMode::Bytecode] {
+            let mut env = Environment::new();
+            let mut report = diagnostics::run_program_collecting(&program, &[], &mut env, mode);
+            diagnostics::run_main(&program, &mut env, mode, &["a".to_string(), "b".to_string()], &mut report);
+            assert!(report.errors.is_empty(), "{:#?}", report.errors);
+            assert_eq!(report.exit_code, Some(12));
+        }
+
+        let program = parse("func main || { return \"done\" }\n");
+        let mut env = Environment::new();
+        let mut report = diagnostics::run_program_collecting(&program, &[], &mut env, diagnostics::ExecutionMode::TreeWalk);
+        diagnostics::run_main(&program, &mut env, diagnostics::ExecutionMode::TreeWalk, &[], &mut report);
+        assert_eq!(report.errors[0].message, "'main' must return an int exit code");
+
+        let program = parse("func main || -> int { return 300 }\n");
+        let mut env = Environment::new();
+        let mut report = diagnostics::run_program_collecting(&program, &[], &mut env, diagnostics::ExecutionMode::TreeWalk);
+        diagnostics::run_main(&program, &mut env, diagnostics::ExecutionMode::TreeWalk, &[], &mut report);
+        assert_eq!(report.exit_code, None);
+        assert_eq!(report.errors[0].message, "'main' returned 300, but an exit code must be between 0 and 255");
+    }
+
+    #[test]
+    fn continue_skips_to_the_next_iteration_of_the_named_loop() {
+        let source = r#"
+let odd_sum: int = 0
+for |n| in 0..8 {
+  if n % 2 == 0 { continue }
+  odd_sum += n
+}
+let pairs: arr = []
+rows: for |r| in [1, 2, 3] {
+  for |c| in [1, 2, 3] {
+    if c > r { continue rows }
+    pairs.push => |r * 10 + c|
+  }
+}
+let i: int = 0
+let seen: int = 0
+let finished: bool = false
+while i < 6 {
+  i += 1
+  if i == 3 { continue }
+  seen += i
+} else {
+  finished = true
+}
+let total: int = 0
+for |let j: int = 0; j < 5; j = j + 1| {
+  if j == 1 { continue }
+  total += j
+}
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("odd_sum"), Some(Value::Int(16))));
+            assert!(matches!(env.lookup("pairs"), Some(Value::Array(pairs)) if pairs.len() == 6));
+            assert!(matches!(env.lookup("seen"), Some(Value::Int(18))));
+            assert!(matches!(env.lookup("finished"), Some(Value::Boolean(true))));
+            assert!(matches!(env.lookup("total"), Some(Value::Int(9))));
+        }
+
+        let mut parser = parser::Parser::new();
+        parser.produce_ast("func f || { continue }".to_string());
+        assert!(parser.errors.iter().any(|e| e.message.contains("'continue' outside of a loop")));
+    }
+
+    #[test]
+    fn boolean_initializers_accept_any_bool_expression() {
+        let source = r#"
+let x: int = 5
+let flag: bool = false
+let literal: bool = true
+let compared: bool = x > 3
+let negated: bool = !flag
+let combined: bool = true && x < 10
+let either: bool = false || flag
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            for (name, expected) in [("literal", true), ("compared", true), ("negated", true), ("combined", true), ("either", false)] {
+                assert!(matches!(env.lookup(name), Some(Value::Boolean(b)) if b == expected), "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn logical_not_negates_booleans_and_rejects_other_values() {
+        let source = r#"
+let a: int = 1
+let b: int = 2
+let ready: bool = false
+let unequal: bool = !(a == b)
+let twice: bool = !!ready
+let guarded: int = 0
+if !ready && !(a > b) {
+    guarded = 1
+}
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("unequal"), Some(Value::Boolean(true))));
+            assert!(matches!(env.lookup("twice"), Some(Value::Boolean(false))));
+            assert!(matches!(env.lookup("guarded"), Some(Value::Int(1))));
+        }
+
+        // Caught before running when the operand's type is known...
+        let program = parse("let n: int = 3\nlet bad: bool = !n\n");
+        let errors = diagnostics::check_program(&program, &[], &mut Environment::new());
+        assert_eq!(errors.len(), 1, "{:#?}", errors);
+        assert_eq!(errors[0].message, "Type mismatch for the operand of '!'");
+
+        // ...and at run time otherwise, with the same wording in both engines.
+        for use_vm in [false, true] {
+            let program = parse("let data: obj = @parse_json => |'{\"n\": 1}'|\nlet bad: bool = !data.n\n");
+            let mut env = Environment::new();
+            let result = if use_vm {
+                bytecode::execute_program(&program, &mut env)
+            } else {
+                eval::statement::evaluate_statement(&Stmt::Program(program), &mut env)
+            };
+            let error = result.expect_err("`!` on an int should fail");
+            assert_eq!(error.message, "Invalid logical NOT operation");
+            let details = error.extra.unwrap_or_default();
+            assert!(details.contains("bool") && !details.contains("boolean"), "{details}");
+        }
+    }
+
+    #[test]
+    fn ast_round_trips_through_json() {
+        let source = r#"
+func total |items: arr| -> int {
+  let sum: int = 0
+  for |item| in items { sum += item }
+  return sum
+}
+let result: int = total => |[1, 2, 3]|
+"#;
+        let json = parser::Parser::to_json(&parse(source));
+        let program = parser::Parser::from_json(&json).expect("AST JSON should parse back");
+        assert_eq!(parser::Parser::to_json(&program), json);
+
+        let mut env = Environment::new();
+        let result = bytecode::execute_program(&program, &mut env);
+        assert!(result.is_ok(), "execution failed: {result:#?}");
+        assert!(matches!(env.lookup("result"), Some(Value::Int(6))));
+
+        assert!(parser::Parser::from_json("{\"imports\": 3}").is_err());
+    }
+
+    #[test]
+    fn ast_cache_is_keyed_by_source_text() {
+        let dir = std::env::temp_dir().join(format!("zekken-ast-cache-{}", std::process::id()));
+        let source = "let cached: int = 41 + 1";
+        assert!(parser::cache::load(&dir, source).is_none());
+
+        let program = parse(source);
+        parser::cache::store(&dir, source, &program);
+        let cached = parser::cache::load(&dir, source).expect("stored AST should load");
+        assert_eq!(parser::Parser::to_json(&cached), parser::Parser::to_json(&program));
+        assert!(parser::cache::load(&dir, "let cached: int = 41 + 2").is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn memstats_counts_function_calls() {
+        let source = r#"
+func add |a: int, b: int| -> int { return a + b }
+let items: arr = [1, 2, 3]
+let before: obj = @memstats => ||
+let total: int = 0
+for |i| in items { total = add => |total, i| }
+let after: obj = @memstats => ||
+let calls: int = after.calls - before.calls
+let copies: int = after.values_allocated - before.values_allocated
+"#;
+        crate::stats::enable();
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("calls"), Some(Value::Int(3))));
+            assert!(matches!(env.lookup("copies"), Some(Value::Int(copies)) if copies > 0));
+            let Some(Value::Object(after)) = env.lookup("after") else { panic!("memstats should return an object") };
+            assert!(matches!(after.get("peak_call_depth"), Some(Value::Int(depth)) if *depth >= 1));
+            assert!(matches!(after.get("clone_micros"), Some(Value::Int(_))));
+        }
+    }
+
+    #[test]
+    fn includes_keep_private_names_and_libraries_to_themselves() {
+        let dir = std::env::temp_dir().join(format!("zekken-include-hygiene-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("lib.zk");
+        std::fs::write(
+            &lib,
+            "use math\nlet shared: int = 1\nlet _hidden: int = 2\nfunc helper || -> int { return _hidden + shared }\n",
+        )
+        .unwrap();
+
+        let source = format!("include \"{}\"\nlet got: int = helper => ||\n", lib.display());
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(&source, use_vm, &mut env);
+            assert!(matches!(env.lookup("got"), Some(Value::Int(3))));
+            assert!(matches!(env.lookup("shared"), Some(Value::Int(1))));
+            assert!(env.lookup("_hidden").is_none());
+            assert!(env.lookup("math").is_none());
+
+            let program = parse(&format!("include {{ _hidden }} from \"{}\"\n", lib.display()));
+            let result = if use_vm {
+                bytecode::execute_program(&program, &mut env)
+            } else {
+                eval::statement::evaluate_statement(&Stmt::Program(program), &mut env)
+            };
+            assert!(result.is_err(), "including a private name should fail");
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn member_access_and_method_calls_have_distinct_shapes() {
+        let program = parse("let v: int = nums[i]\nlet w: int = o.key\nnums.push => |1|\nq.enqueue => |1|\n");
+        let expr_of = |content: &Content| -> Expr {
+            match content {
+                Content::Statement(stmt) => match stmt.as_ref() {
+                    Stmt::VarDecl(decl) => match decl.value.as_ref() {
+                        Some(Content::Expression(expr)) => expr.as_ref().clone(),
+                        other => panic!("unexpected initializer {other:?}"),
+                    },
+                    other => panic!("unexpected statement {other:?}"),
+                },
+                Content::Expression(expr) => expr.as_ref().clone(),
+            }
+        };
+        assert!(matches!(expr_of(&program.content[0]), Expr::Member(MemberExpr { computed: true, .. })));
+        assert!(matches!(expr_of(&program.content[1]), Expr::Member(MemberExpr { computed: false, .. })));
+        assert!(matches!(expr_of(&program.content[2]), Expr::MethodCall(ref call) if call.method == "push" && call.method_id == Some(Method::Push)));
+        // Names that are not built in stay unresolved and are looked up by name.
+        assert!(matches!(expr_of(&program.content[3]), Expr::MethodCall(ref call) if call.method_id.is_none()));
+
+        let source = r#"
+let nums: arr = [10, 20, 30]
+let i: int = 1
+let key: string = "i"
+let o: obj = { i: 5 }
+let indexed: int = nums[i]
+let dotted: int = o.i
+let bracketed: int = o[key]
+nums.push => |40|
+let pushed: int = nums[3]
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            for (name, expected) in [("indexed", 20), ("dotted", 5), ("bracketed", 5), ("pushed", 40)] {
+                assert!(matches!(env.lookup(name), Some(Value::Int(n)) if n == expected), "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn array_index_assignment_grows_only_when_permissive() {
+        let sized = r#"
+let nums: arr = [1, 2, 3]
+nums.resize => |5, 0|
+let padded: int = nums[4]
+nums.fill => |7|
+let filled: int = nums[0]
+nums.resize => |2|
+"#;
+        let grow = "let nums: arr = [1, 2]\nnums[4] = 9\n";
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(sized, use_vm, &mut env);
+            assert!(matches!(env.lookup("padded"), Some(Value::Int(0))));
+            assert!(matches!(env.lookup("filled"), Some(Value::Int(7))));
+            assert!(matches!(env.lookup("nums"), Some(Value::Array(items)) if items.len() == 2));
+
+            let program = parse(grow);
+            let mut env = Environment::new();
+            let result = if use_vm {
+                bytecode::execute_program(&program, &mut env)
+            } else {
+                eval::statement::evaluate_statement(&Stmt::Program(program), &mut env)
+            };
+            assert!(result.is_err(), "assigning past the end should fail by default");
+        }
+
+        config::set_permissive(true);
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(grow, use_vm, &mut env);
+            let Some(Value::Array(items)) = env.lookup("nums") else { panic!("nums should stay an array") };
+            assert_eq!(items.len(), 5);
+            assert!(matches!(items[2], Value::Void));
+            assert!(matches!(items[4], Value::Int(9)));
+
+            // Huge lengths are errors, not aborted allocations.
+            for source in [
+                "let nums: arr = []\nnums.resize => |9223372036854775807, 0|\n",
+                "let nums: arr = []\nnums[9223372036854775806] = 1\n",
+            ] {
+                let program = parse(source);
+                let mut env = Environment::new();
+                let result = if use_vm {
+                    bytecode::execute_program(&program, &mut env)
+                } else {
+                    eval::statement::evaluate_statement(&Stmt::Program(program), &mut env)
+                };
+                let error = result.unwrap_err();
+                assert!(error.message.contains("is too long"), "{}", error.message);
+            }
+        }
+        config::set_permissive(false);
+    }
+
+    #[test]
+    fn arrays_library_reshapes_arrays() {
+        let source = r#"
+use arrays
+let pairs: arr = arrays.zip => |["a", "b", "c"], [1, 2]|
+let columns: arr = arrays.unzip => |pairs|
+let chunks: arr = arrays.chunk => |[1, 2, 3, 4, 5], 2|
+let windows: arr = arrays.windows => |[1, 2, 3], 2|
+let wide: arr = arrays.windows => |[1, 2], 3|
+let columns_of: arr = arrays.transpose => |[[1, 2, 3], [4, 5, 6]]|
+let nothing: arr = arrays.transpose => |[]|
+let sales: arr = [{ region: "north", amount: 3 }, { region: "south", amount: 5 }, { region: "north", amount: 2.5 }]
+let region: fn = fn -> |s: obj| { return s.region }
+let by_region: obj = arrays.group_by => |sales, region|
+let orders: obj = arrays.count_by => |sales, region|
+let totals: obj = arrays.sum_by => |sales, region, fn -> |s: obj| { return s.amount }|
+let lengths: obj = arrays.count_by => |[1, 22, 333, 4], fn -> |n: int| { return ((n.cast => |"string"|).length => ||) }|
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            for (name, expected) in [
+                ("pairs", r#"[["a", 1], ["b", 2]]"#),
+                ("columns", r#"[["a", "b"], [1, 2]]"#),
+                ("chunks", "[[1, 2], [3, 4], [5]]"),
+                ("windows", "[[1, 2], [2, 3]]"),
+                ("wide", "[]"),
+                ("columns_of", "[[1, 4], [2, 5], [3, 6]]"),
+                ("nothing", "[]"),
+                ("by_region", r#"{north: [{region: "north", amount: 3}, {region: "north", amount: 2.5}], south: [{region: "south", amount: 5}]}"#),
+                ("orders", "{north: 2, south: 1}"),
+                ("totals", "{north: 5.5, south: 5}"),
+                ("lengths", "{1: 2, 2: 1, 3: 1}"),
+            ] {
+                assert_eq!(env.lookup(name).map(|v| v.to_string()).as_deref(), Some(expected), "{name}");
+            }
+        }
+
+        for (source, message) in [
+            ("arrays.chunk => |[1, 2], 0|", "arrays.chunk expects a positive int size"),
+            ("arrays.unzip => |[[1, 2], [3]]|", "but pairs[1] is [3]"),
+            ("arrays.transpose => |[[1, 2], [3]]|", "but rows[1] has 1 elements and rows[0] has 2"),
+            ("arrays.zip => |[1], \"ab\"|", "arrays.zip expects b to be an array, got string"),
+            ("arrays.group_by => |[1], 2|", "arrays.group_by expects a function, got int"),
+            ("arrays.sum_by => |[1], fn -> |x: int| { return \"k\" }|", "arrays.sum_by expects an array and 2 functions"),
+            ("arrays.count_by => |[1], fn -> |x: int| { return x > 0 }|", "but the key of items[0] is boolean"),
+        ] {
+            let program = parse(&format!("use arrays\n{}\n", source));
+            let error = eval::statement::evaluate_statement(&Stmt::Program(program), &mut Environment::new()).unwrap_err();
+            assert!(error.message.contains(message), "{}", error.message);
+        }
+    }
+
+    #[test]
+    fn strings_index_by_character_from_either_end() {
+        let source = r#"
+use strings
+let word: string = "héllo"
+let second: string = word[1]
+let last: string = word[-1]
+let literal: string = "hello"[1]
+let words: arr = ["ab", "cd"]
+let nested: string = words[1][0]
+let code: int = word.charCodeAt => |1|
+let back: string = strings.from_char_code => |code|
+let length: int = word.length => ||
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            for (name, expected) in [("second", "é"), ("last", "o"), ("literal", "e"), ("nested", "c"), ("back", "é")] {
+                assert!(matches!(env.lookup(name), Some(Value::String(s)) if s == expected), "{name}");
+            }
+            assert!(matches!(env.lookup("code"), Some(Value::Int(233))));
+            assert!(matches!(env.lookup("length"), Some(Value::Int(5))));
+
+            let program = parse("let out: string = \"hi\"[2]\n");
+            let result = if use_vm {
+                bytecode::execute_program(&program, &mut env)
+            } else {
+                eval::statement::evaluate_statement(&Stmt::Program(program), &mut env)
+            };
+            assert!(result.is_err(), "indexing past the end of a string should fail");
+        }
+    }
+
+    #[test]
+    fn block_comments_nest_and_doc_comments_have_their_own_kind() {
+        use crate::lexer::{lex, lex_with_comments, TokenType};
+
+        let (tokens, errors) = lex_with_comments("/// Doc.\n/* a /* b */ c */ x //// rule\n".to_string());
+        assert!(errors.is_empty());
+        let kinds: Vec<TokenType> = tokens.iter().map(|token| token.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            [TokenType::DocComment, TokenType::MultiLineComment, TokenType::Identifier, TokenType::SingleLineComment, TokenType::EOF]
+        );
+        assert_eq!(tokens[0].value, " Doc.");
+        assert_eq!(tokens[1].value, " a /* b */ c ");
+
+        let (tokens, errors) = lex("let a: int = 1\n/* open /* nested */\n".to_string());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Unterminated block comment");
+        assert_eq!((errors[0].context.line, errors[0].context.column), (2, 1));
+        assert!(tokens.iter().all(|token| !token.kind.is_comment()));
+
+        let mut parser = parser::Parser::new();
+        parser.produce_ast("let b: int = 2 /* never closed".to_string());
+        assert!(parser.errors.iter().any(|error| error.message == "Unterminated block comment"));
+    }
+
+    #[test]
+    fn calls_take_arguments_across_lines_and_comments() {
+        let source = r#"
+func add |a: int, b: int| -> int { return a + b }
+let spread: int = add => |
+  1, // first
+  // nothing here
+  2  /* second */
+|
+let inline: int = add => | /* a | b */ add => |1, /* x
+  */ 2|, 3 |
+let xs: arr = [1]
+xs.push => | // appended
+  4 |
+let text: string = @format => |
+  3.14159, // pi
+  ".2" /* two places */
+|
+"#;
+        for use_vm in [false, true] {
+            let mut env = Environment::new();
+            execute(source, use_vm, &mut env);
+            assert!(matches!(env.lookup("spread"), Some(Value::Int(3))));
+            assert!(matches!(env.lookup("inline"), Some(Value::Int(6))));
+            assert_eq!(env.lookup("xs").map(|xs| xs.to_string()).as_deref(), Some("[1, 4]"));
+            assert!(matches!(env.lookup("text"), Some(Value::String(s)) if s == "3.14"));
+        }
+    }
+
+    #[test]
+    fn string_escapes_decode_and_report_bad_sequences() {
+        use crate::lexer::lex;
+
+        let (tokens, errors) = lex(r#""\u{1F600} \x41 \0 \u{e9}""#.to_string());
+        assert!(errors.is_empty(), "{errors:#?}");
+        assert_eq!(tokens[0].value, "\u{1F600} A \0 é");
+
+        let (_, errors) = lex(r#"let s: string = "a\qb\u{110000}\xZ1""#.to_string());
+        let reported: Vec<(&str, usize)> = errors.iter().map(|e| (e.message.as_str(), e.context.column)).collect();
+        assert_eq!(
+            reported,
+            [("Unknown escape sequence", 19), ("Invalid unicode escape", 22), ("Invalid hex escape", 32)]
+        );
+
+        let (_, errors) = lex("let s: string = \"never closed\n".to_string());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Unterminated string literal");
+        assert_eq!((errors[0].context.line, errors[0].context.column), (1, 17));
+    }
+
+    #[test]
+    fn malformed_and_out_of_range_numbers_are_syntax_errors() {
+        use crate::lexer::{lex, TokenType};
+
+        let messages = |source: &str| -> Vec<String> {
+            let (_, errors) = lex(source.to_string());
+            errors.into_iter().map(|error| error.message).collect()
+        };
+        assert_eq!(messages("1.2.3"), ["Malformed number literal '1.2.3'"]);
+        assert_eq!(messages("9999999999999999999999"), ["Integer literal '9999999999999999999999' is out of range"]);
+        assert!(messages("9223372036854775807 0.5").is_empty());
+
+        // A dot that is not followed by a digit is member access.
+        let (tokens, errors) = lex("99.cast 1.5.cast".to_string());
+        assert!(errors.is_empty());
+        let kinds: Vec<TokenType> = tokens.iter().map(|token| token.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            [TokenType::Int, TokenType::Dot, TokenType::Identifier, TokenType::Float, TokenType::Dot, TokenType::Identifier, TokenType::EOF]
+        );
+
+        let mut parser = parser::Parser::new();
+        parser.produce_ast("let big: int = 9999999999999999999999".to_string());
+        assert_eq!(parser.errors.len(), 1);
     }
 }
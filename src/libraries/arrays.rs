@@ -0,0 +1,235 @@
+//! Reshaping arrays: pairing two up, splitting one into runs, and turning
+//! rows into columns. Also grouping and totalling elements by a key.
+
+use crate::environment::{decimal_binary, numeric_binary, Environment, Value};
+use indexmap::IndexMap;
+use std::sync::Arc;
+
+pub const SIGNATURES: super::Signatures = &[
+    ("zip", "|a: arr, b: arr| -> arr"),
+    ("unzip", "|pairs: arr| -> arr"),
+    ("chunk", "|items: arr, size: int| -> arr"),
+    ("windows", "|items: arr, size: int| -> arr"),
+    ("transpose", "|rows: arr| -> arr"),
+    ("group_by", "|items: arr, key: fn| -> obj"),
+    ("count_by", "|items: arr, key: fn| -> obj"),
+    ("sum_by", "|items: arr, key: fn, value: fn| -> obj"),
+];
+
+/// Builds a `Keyed` member's result from the array and what its functions
+/// returned for each element.
+type Aggregate = fn(&[Value], Vec<Vec<Value>>) -> Result<Value, String>;
+
+/// A member that calls functions on each element and builds its result
+/// from what they return. A library native cannot run a function written in
+/// Zekken, so the engines call the functions themselves when they see
+/// `arrays.<function> => |...|` and pass the results to `aggregate`.
+pub struct Keyed {
+    pub function: &'static str,
+    /// How many functions follow the array.
+    functions: usize,
+    aggregate: Aggregate,
+}
+
+const KEYED: &[Keyed] = &[
+    Keyed { function: "group_by", functions: 1, aggregate: group_by },
+    Keyed { function: "count_by", functions: 1, aggregate: count_by },
+    Keyed { function: "sum_by", functions: 2, aggregate: sum_by },
+];
+
+/// The member named `name` if it is one of the `Keyed` ones.
+pub fn keyed(name: &str) -> Option<&'static Keyed> {
+    KEYED.iter().find(|keyed| keyed.function == name)
+}
+
+impl Keyed {
+    /// The array a call passes, and the functions to call on each element.
+    pub fn split<'a>(&self, args: &'a [Value]) -> Result<(&'a [Value], &'a [Value]), String> {
+        let Some((items, functions)) = args.split_first().filter(|(_, functions)| functions.len() == self.functions) else {
+            return Err(format!(
+                "arrays.{} expects an array and {} function{}",
+                self.function,
+                self.functions,
+                if self.functions == 1 { "" } else { "s" }
+            ));
+        };
+        if let Some(other) = functions.iter().find(|f| !matches!(f, Value::Function(_) | Value::NativeFunction(_))) {
+            return Err(format!("arrays.{} expects a function, got {}", self.function, other.type_name()));
+        }
+        Ok((array_arg(items, self.function, "items")?, functions))
+    }
+
+    /// The result for `items`, given `results[f][i]`: what the `f`th
+    /// function returned for `items[i]`.
+    pub fn aggregate(&self, items: &[Value], results: Vec<Vec<Value>>) -> Result<Value, String> {
+        (self.aggregate)(items, results)
+    }
+
+    /// The native stored on the library object. It can only call library
+    /// functions; the engines handle Zekken ones before a call gets here.
+    fn native(&'static self) -> Value {
+        Value::NativeFunction(Arc::new(move |args| {
+            let (items, functions) = self.split(&args)?;
+            let results = functions
+                .iter()
+                .map(|function| match function {
+                    Value::NativeFunction(native) => items.iter().map(|item| native(vec![item.clone()])).collect(),
+                    _ => Err(format!("arrays.{} can only call a Zekken function when called as arrays.{} => |...|", self.function, self.function)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            self.aggregate(items, results)
+        }))
+    }
+}
+
+/// The object key for `key`, which the key function returned for `items[i]`.
+fn group_key(key: &Value, function: &str, i: usize) -> Result<String, String> {
+    key.as_object_key().ok_or_else(|| {
+        format!("arrays.{} expects keys to be strings or ints, but the key of items[{}] is {}", function, i, key.type_name())
+    })
+}
+
+fn group_by(items: &[Value], results: Vec<Vec<Value>>) -> Result<Value, String> {
+    let mut groups: IndexMap<String, Vec<Value>> = IndexMap::new();
+    for (i, (item, key)) in items.iter().zip(&results[0]).enumerate() {
+        groups.entry(group_key(key, "group_by", i)?).or_default().push(item.clone());
+    }
+    Ok(Value::Object(groups.into_iter().map(|(key, group)| (key, Value::Array(group))).collect()))
+}
+
+fn count_by(_items: &[Value], results: Vec<Vec<Value>>) -> Result<Value, String> {
+    let mut counts: IndexMap<String, i64> = IndexMap::new();
+    for (i, key) in results[0].iter().enumerate() {
+        *counts.entry(group_key(key, "count_by", i)?).or_default() += 1;
+    }
+    Ok(Value::Object(counts.into_iter().map(|(key, count)| (key, Value::Int(count))).collect()))
+}
+
+fn sum_by(_items: &[Value], results: Vec<Vec<Value>>) -> Result<Value, String> {
+    let mut sums: IndexMap<String, Value> = IndexMap::new();
+    for (i, (key, value)) in results[0].iter().zip(&results[1]).enumerate() {
+        if !matches!(value, Value::Int(_) | Value::Float(_) | Value::Decimal(_)) {
+            return Err(format!("arrays.sum_by expects numeric values, but the value of items[{}] is {}", i, value.type_name()));
+        }
+        let key = group_key(key, "sum_by", i)?;
+        let sum = match (sums.get(&key), value) {
+            (None, _) => value.clone(),
+            // Ints and floats add up to a float, as `+` does.
+            (Some(Value::Int(l)), Value::Float(r)) => Value::Float(*l as f64 + r),
+            (Some(Value::Float(l)), Value::Int(r)) => Value::Float(l + *r as f64),
+            (Some(sum), _) => numeric_binary("+", sum, value)
+                .map(Ok)
+                .or_else(|| decimal_binary("+", sum, value))
+                .unwrap_or_else(|| Err(format!("arrays.sum_by cannot add {} to {}", value.type_name(), sum.type_name())))?,
+        };
+        sums.insert(key, sum);
+    }
+    Ok(Value::Object(sums))
+}
+
+fn array_arg<'a>(value: &'a Value, function: &str, name: &str) -> Result<&'a [Value], String> {
+    match value {
+        Value::Array(items) => Ok(items),
+        other => Err(format!("arrays.{} expects {} to be an array, got {}", function, name, other.type_name())),
+    }
+}
+
+fn size_arg(value: &Value, function: &str) -> Result<usize, String> {
+    match value {
+        Value::Int(n) if *n > 0 => Ok(*n as usize),
+        _ => Err(format!("arrays.{} expects a positive int size", function)),
+    }
+}
+
+/// `items` split into arrays of `size`, by `split`: `chunks` or `windows`.
+fn runs(args: &[Value], function: &str, split: fn(&[Value], usize) -> Vec<&[Value]>) -> Result<Value, String> {
+    let [items, size] = args else {
+        return Err(format!("arrays.{} expects an array and a size", function));
+    };
+    let items = array_arg(items, function, "items")?;
+    let size = size_arg(size, function)?;
+    Ok(Value::Array(split(items, size).into_iter().map(|run| Value::Array(run.to_vec())).collect()))
+}
+
+fn transpose(rows: &[Value]) -> Result<Value, String> {
+    let rows = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| array_arg(row, "transpose", &format!("rows[{}]", i)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let width = rows.first().map_or(0, |row| row.len());
+    if let Some((i, row)) = rows.iter().enumerate().find(|(_, row)| row.len() != width) {
+        return Err(format!(
+            "arrays.transpose expects rows of one length, but rows[{}] has {} elements and rows[0] has {}",
+            i,
+            row.len(),
+            width
+        ));
+    }
+    Ok(Value::Array(
+        (0..width).map(|column| Value::Array(rows.iter().map(|row| row[column].clone()).collect())).collect(),
+    ))
+}
+
+pub fn register(env: &mut Environment) -> Result<(), String> {
+    let mut arrays_obj = IndexMap::new();
+
+    arrays_obj.insert(
+        "zip".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            let [a, b] = args.as_slice() else {
+                return Err("arrays.zip expects two arrays".to_string());
+            };
+            let (a, b) = (array_arg(a, "zip", "a")?, array_arg(b, "zip", "b")?);
+            Ok(Value::Array(a.iter().zip(b).map(|(x, y)| Value::Array(vec![x.clone(), y.clone()])).collect()))
+        })),
+    );
+
+    arrays_obj.insert(
+        "unzip".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            let [pairs] = args.as_slice() else {
+                return Err("arrays.unzip expects one array of pairs".to_string());
+            };
+            let pairs = array_arg(pairs, "unzip", "pairs")?;
+            let (mut firsts, mut seconds) = (Vec::with_capacity(pairs.len()), Vec::with_capacity(pairs.len()));
+            for (i, pair) in pairs.iter().enumerate() {
+                match pair {
+                    Value::Array(pair) if pair.len() == 2 => {
+                        firsts.push(pair[0].clone());
+                        seconds.push(pair[1].clone());
+                    }
+                    other => return Err(format!("arrays.unzip expects [a, b] pairs, but pairs[{}] is {}", i, other)),
+                }
+            }
+            Ok(Value::Array(vec![Value::Array(firsts), Value::Array(seconds)]))
+        })),
+    );
+
+    arrays_obj.insert(
+        "chunk".to_string(),
+        Value::NativeFunction(Arc::new(|args| runs(&args, "chunk", |items, size| items.chunks(size).collect()))),
+    );
+
+    arrays_obj.insert(
+        "windows".to_string(),
+        Value::NativeFunction(Arc::new(|args| runs(&args, "windows", |items, size| items.windows(size).collect()))),
+    );
+
+    arrays_obj.insert(
+        "transpose".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            let [rows] = args.as_slice() else {
+                return Err("arrays.transpose expects one array of rows".to_string());
+            };
+            transpose(array_arg(rows, "transpose", "rows")?)
+        })),
+    );
+
+    for keyed in KEYED {
+        arrays_obj.insert(keyed.function.to_string(), keyed.native());
+    }
+
+    env.declare("arrays".to_string(), Value::Object(arrays_obj), true);
+    Ok(())
+}
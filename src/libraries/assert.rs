@@ -0,0 +1,82 @@
+//! Checks for test scripts. A failed check is a runtime error, so
+//! `zekken test` reports it against the line that made it.
+
+use crate::environment::diff::diff;
+use crate::environment::{Environment, Value};
+use indexmap::IndexMap;
+use std::sync::Arc;
+
+pub const SIGNATURES: super::Signatures = &[
+    ("ok", "|condition: bool, message?: string| -> void"),
+    ("eq", "|actual: any, expected: any, message?: string| -> void"),
+    ("ne", "|actual: any, expected: any, message?: string| -> void"),
+];
+
+/// Differences listed in an `assert.eq` failure before the rest are counted.
+const SHOWN_DIFFERENCES: usize = 5;
+
+/// `check`'s failure, after the caller's own message when there is one.
+fn failure(check: &str, message: Option<&str>, detail: &str) -> String {
+    match message {
+        Some(message) => format!("{}: {} failed: {}", message, check, detail),
+        None => format!("{} failed: {}", check, detail),
+    }
+}
+
+/// The optional message after `required` arguments.
+fn message_arg<'a>(name: &str, args: &'a [Value], required: usize) -> Result<Option<&'a str>, String> {
+    if args.len() < required {
+        return Err(format!("assert.{} expects {} arguments and an optional message", name, required));
+    }
+    match &args[required..] {
+        [] => Ok(None),
+        [Value::String(message)] => Ok(Some(message)),
+        _ => Err(format!("assert.{} takes an optional message string after its {} arguments", name, required)),
+    }
+}
+
+pub fn register(env: &mut Environment) -> Result<(), String> {
+    let mut assert_obj = IndexMap::new();
+
+    assert_obj.insert(
+        "ok".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            let message = message_arg("ok", &args, 1)?;
+            match &args[0] {
+                Value::Boolean(true) => Ok(Value::Void),
+                Value::Boolean(false) => Err(failure("assert.ok", message, "condition was false")),
+                _ => Err("assert.ok expects a bool condition".to_string()),
+            }
+        })),
+    );
+
+    assert_obj.insert(
+        "eq".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            let message = message_arg("eq", &args, 2)?;
+            let differences = diff(&args[1], &args[0]);
+            if differences.is_empty() {
+                return Ok(Value::Void);
+            }
+            let mut detail = differences.iter().take(SHOWN_DIFFERENCES).map(ToString::to_string).collect::<Vec<_>>().join("; ");
+            if differences.len() > SHOWN_DIFFERENCES {
+                detail.push_str(&format!(" (and {} more)", differences.len() - SHOWN_DIFFERENCES));
+            }
+            Err(failure("assert.eq", message, &detail))
+        })),
+    );
+
+    assert_obj.insert(
+        "ne".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            let message = message_arg("ne", &args, 2)?;
+            if diff(&args[1], &args[0]).is_empty() {
+                return Err(failure("assert.ne", message, &format!("both values are {}", args[0])));
+            }
+            Ok(Value::Void)
+        })),
+    );
+
+    env.declare("assert".to_string(), Value::Object(assert_obj), true);
+    Ok(())
+}
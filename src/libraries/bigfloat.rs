@@ -0,0 +1,466 @@
+//! Arbitrary-precision floats for `math.bigfloat`.
+//!
+//! A bigfloat is `mantissa * 10^exponent`, rounded half-even to a number of
+//! significant decimal digits chosen when it is made. Working in base 10
+//! keeps parsing and printing exact, so `math.bigfloat => |"0.1", 40|` is
+//! exactly one tenth. Intermediate steps carry [`GUARD_DIGITS`] extra digits.
+//!
+//! In scripts a bigfloat is a [`Value::BigFloat`] with a readable
+//! `precision` field. Arithmetic and comparison operators work on it through
+//! [`binary`], and `sqrt`, `exp`, `ln`, `to_float` and `with_precision` are
+//! methods (see [`method`]). Exponents are kept within [`MAX_EXPONENT`], so
+//! results too large or too small to represent are errors.
+
+use crate::environment::Value;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Signed, Zero};
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+
+/// Significant digits when `math.bigfloat` is not given a precision.
+pub const DEFAULT_PRECISION: usize = 50;
+
+pub const MAX_PRECISION: usize = 10_000;
+
+/// The largest power of ten a bigfloat may be scaled by, either way.
+pub const MAX_EXPONENT: i64 = 1_000_000_000_000_000;
+
+const GUARD_DIGITS: usize = 10;
+
+// `exp` refuses arguments beyond this, whose results would have exponents
+// too large to be useful.
+const MAX_EXP_ARGUMENT: f64 = 1e9;
+
+#[derive(Debug, Clone)]
+pub struct BigFloat {
+    mantissa: BigInt,
+    exponent: i64,
+    precision: usize,
+}
+
+fn pow10(n: usize) -> BigInt {
+    BigInt::from(10u32).pow(n as u32)
+}
+
+fn digit_count(m: &BigInt) -> usize {
+    if m.is_zero() {
+        1
+    } else {
+        m.abs().to_string().len()
+    }
+}
+
+fn out_of_range() -> String {
+    format!("bigfloat out of range: exponents are limited to {}", MAX_EXPONENT)
+}
+
+impl BigFloat {
+    fn new(mantissa: BigInt, exponent: i64, precision: usize) -> Result<Self, String> {
+        if !(-MAX_EXPONENT..=MAX_EXPONENT).contains(&exponent) {
+            return Err(out_of_range());
+        }
+        Ok(BigFloat { mantissa, exponent, precision }.rounded(precision))
+    }
+
+    pub fn from_int(value: i64, precision: usize) -> Self {
+        BigFloat { mantissa: BigInt::from(value), exponent: 0, precision }.rounded(precision)
+    }
+
+    pub fn precision(&self) -> usize {
+        self.precision
+    }
+
+    /// The float as it prints, so `0.1` becomes exactly one tenth.
+    pub fn from_f64(value: f64, precision: usize) -> Result<Self, String> {
+        if !value.is_finite() {
+            return Err(format!("Cannot make a bigfloat from {}", value));
+        }
+        BigFloat::parse(&format!("{:e}", value), precision)
+    }
+
+    /// Parses `[-]digits[.digits][e[-]digits]`.
+    pub fn parse(text: &str, precision: usize) -> Result<Self, String> {
+        let invalid = || format!("Invalid bigfloat '{}'", text);
+        let (number, exponent) = match text.split_once(['e', 'E']) {
+            Some((number, exponent)) => (number, exponent.parse::<i64>().map_err(|_| invalid())?),
+            None => (text, 0),
+        };
+        let (negative, digits) = match number.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, number.strip_prefix('+').unwrap_or(number)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let all_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !all_digits(whole) || !all_digits(fraction) {
+            return Err(invalid());
+        }
+        let mut mantissa: BigInt = format!("{}{}", whole, fraction).parse().map_err(|_| invalid())?;
+        if negative {
+            mantissa = -mantissa;
+        }
+        let exponent = exponent.checked_sub(fraction.len() as i64).ok_or_else(out_of_range)?;
+        BigFloat::new(mantissa, exponent, precision)
+    }
+
+    pub fn with_precision(&self, precision: usize) -> Self {
+        BigFloat { precision, ..self.clone() }.rounded(precision)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa.is_zero()
+    }
+
+    fn is_negative(&self) -> bool {
+        self.mantissa.is_negative()
+    }
+
+    // Position of the decimal point relative to the first digit: the value
+    // lies in [10^(top - 1), 10^top).
+    fn top(&self) -> i64 {
+        digit_count(&self.mantissa) as i64 + self.exponent
+    }
+
+    // Rounds half-even to `digits` significant digits and drops trailing
+    // zeros, so equal values have equal representations.
+    fn rounded(mut self, digits: usize) -> Self {
+        let count = digit_count(&self.mantissa);
+        if count > digits {
+            let drop = count - digits;
+            let divisor = pow10(drop);
+            let (mut quotient, remainder) = self.mantissa.div_rem(&divisor);
+            let twice = remainder.abs() * 2u32;
+            if twice > divisor || (twice == divisor && quotient.is_odd()) {
+                if self.mantissa.is_negative() {
+                    quotient -= 1u32;
+                } else {
+                    quotient += 1u32;
+                }
+            }
+            self.mantissa = quotient;
+            self.exponent += drop as i64;
+        }
+        if self.mantissa.is_zero() {
+            self.exponent = 0;
+            return self;
+        }
+        let ten = BigInt::from(10u32);
+        while (&self.mantissa % &ten).is_zero() {
+            self.mantissa /= &ten;
+            self.exponent += 1;
+        }
+        self
+    }
+
+    fn neg(&self) -> Self {
+        BigFloat { mantissa: -self.mantissa.clone(), ..self.clone() }
+    }
+
+    pub fn add(&self, other: &Self) -> Result<Self, String> {
+        let precision = self.precision.max(other.precision);
+        if self.is_zero() {
+            return Ok(other.with_precision(precision));
+        }
+        if other.is_zero() {
+            return Ok(self.with_precision(precision));
+        }
+        // An operand entirely below the other's last kept digit cannot
+        // change the rounded sum.
+        let reach = (precision + GUARD_DIGITS) as i64 + 2;
+        if other.top() < self.top() - reach {
+            return Ok(self.with_precision(precision));
+        }
+        if self.top() < other.top() - reach {
+            return Ok(other.with_precision(precision));
+        }
+        let exponent = self.exponent.min(other.exponent);
+        let align = |x: &Self| &x.mantissa * pow10((x.exponent - exponent) as usize);
+        BigFloat::new(align(self) + align(other), exponent, precision)
+    }
+
+    pub fn sub(&self, other: &Self) -> Result<Self, String> {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Self) -> Result<Self, String> {
+        let precision = self.precision.max(other.precision);
+        let exponent = self.exponent.checked_add(other.exponent).ok_or_else(out_of_range)?;
+        BigFloat::new(&self.mantissa * &other.mantissa, exponent, precision)
+    }
+
+    pub fn div(&self, other: &Self) -> Result<Self, String> {
+        if other.is_zero() {
+            return Err("Division by zero".to_string());
+        }
+        let precision = self.precision.max(other.precision);
+        let wanted = precision + GUARD_DIGITS + digit_count(&other.mantissa);
+        let shift = wanted.saturating_sub(digit_count(&self.mantissa));
+        let quotient = &self.mantissa * pow10(shift) / &other.mantissa;
+        let exponent = self
+            .exponent
+            .checked_sub(shift as i64)
+            .and_then(|e| e.checked_sub(other.exponent))
+            .ok_or_else(out_of_range)?;
+        BigFloat::new(quotient, exponent, precision)
+    }
+
+    pub fn sqrt(&self) -> Result<Self, String> {
+        if self.is_negative() {
+            return Err(format!("Cannot take the square root of {}", self));
+        }
+        if self.is_zero() {
+            return Ok(self.clone());
+        }
+        let wanted = 2 * (self.precision + GUARD_DIGITS);
+        let mut shift = wanted.saturating_sub(digit_count(&self.mantissa));
+        if (self.exponent - shift as i64) % 2 != 0 {
+            shift += 1;
+        }
+        let root = (&self.mantissa * pow10(shift)).sqrt();
+        BigFloat::new(root, (self.exponent - shift as i64) / 2, self.precision)
+    }
+
+    pub fn exp(&self) -> Result<Self, String> {
+        let estimate = self.to_f64();
+        if estimate.abs() > MAX_EXP_ARGUMENT {
+            return Err(format!("bigfloat exp argument {} is too large", self));
+        }
+        if self.is_negative() {
+            let one = BigFloat::from_int(1, self.precision);
+            return one.div(&self.neg().exp()?);
+        }
+        // exp(x) = exp(x / 2^k)^(2^k), with x / 2^k small enough for the
+        // Taylor series to converge quickly. Squaring k times loses about
+        // k * log10(2) digits, which the working precision makes up for.
+        let halvings = if estimate > 0.01 { (estimate / 0.01).log2().ceil() as usize } else { 0 };
+        let working = self.precision + GUARD_DIGITS + halvings / 3 + 1;
+        let two_k = BigFloat { mantissa: BigInt::one() << halvings, exponent: 0, precision: working }.rounded(working);
+        let reduced = self.with_precision(working).div(&two_k)?;
+        let mut sum = BigFloat::from_int(1, working);
+        let mut term = BigFloat::from_int(1, working);
+        for n in 1.. {
+            term = term.mul(&reduced)?.div(&BigFloat::from_int(n, working))?;
+            if term.is_zero() || term.top() < sum.top() - working as i64 - 1 {
+                break;
+            }
+            sum = sum.add(&term)?;
+        }
+        for _ in 0..halvings {
+            sum = sum.mul(&sum)?;
+        }
+        Ok(sum.with_precision(self.precision))
+    }
+
+    pub fn ln(&self) -> Result<Self, String> {
+        if self.is_negative() || self.is_zero() {
+            return Err(format!("Cannot take the logarithm of {}", self));
+        }
+        let working = self.precision + GUARD_DIGITS;
+        // x = f * 10^q with f in [0.1, 1), so ln(x) = ln(f) + q * ln(10).
+        let q = self.top();
+        let f = BigFloat { mantissa: self.mantissa.clone(), exponent: self.exponent - q, precision: working };
+        let mut result = ln_newton(&f)?;
+        if q != 0 {
+            let ten = BigFloat::from_int(10, working);
+            result = result.add(&ln_newton(&ten)?.mul(&BigFloat::from_int(q, working))?)?;
+        }
+        Ok(result.with_precision(self.precision))
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// The fields scripts can read: `precision`.
+    pub fn property(&self, name: &str) -> Option<Value> {
+        match name {
+            "precision" => Some(Value::Int(self.precision as i64)),
+            _ => None,
+        }
+    }
+}
+
+// ln(x) for a moderate x by Newton's method on exp: each step
+// y += 2 * (x - e^y) / (x + e^y) roughly triples the correct digits, so a
+// float estimate converges in a few rounds.
+fn ln_newton(x: &BigFloat) -> Result<BigFloat, String> {
+    let working = x.precision;
+    let mut y = BigFloat::from_f64(x.to_f64().ln(), working)?;
+    let two = BigFloat::from_int(2, working);
+    for _ in 0..64 {
+        let e = y.exp()?;
+        let step = two.mul(&x.sub(&e)?)?.div(&x.add(&e)?)?;
+        y = y.add(&step)?;
+        if step.is_zero() || step.top() < y.top().min(0) - working as i64 {
+            break;
+        }
+    }
+    Ok(y)
+}
+
+impl PartialEq for BigFloat {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for BigFloat {}
+
+impl PartialOrd for BigFloat {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// By sign, then by magnitude: the position of the point, then the digits
+// lined up at the same exponent. Both are in lowest terms, so equal values
+// have equal representations.
+impl Ord for BigFloat {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let sign = |x: &Self| if x.is_zero() { 0 } else if x.is_negative() { -1 } else { 1 };
+        let by_sign = sign(self).cmp(&sign(other));
+        if by_sign != Ordering::Equal || self.is_zero() {
+            return by_sign;
+        }
+        let magnitude = match self.top().cmp(&other.top()) {
+            Ordering::Equal => {
+                let exponent = self.exponent.min(other.exponent);
+                let align = |x: &Self| x.mantissa.abs() * pow10((x.exponent - exponent) as usize);
+                align(self).cmp(&align(other))
+            }
+            order => order,
+        };
+        if self.is_negative() { magnitude.reverse() } else { magnitude }
+    }
+}
+
+// Plain notation while the point sits near the digits, scientific beyond:
+// `123.45`, `0.00012`, `1.5e-30`, `2.5e120`.
+impl Display for BigFloat {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let digits = self.mantissa.abs().to_string();
+        let sign = if self.is_negative() { "-" } else { "" };
+        let point = self.top();
+        let plain_limit = self.precision.max(21) as i64;
+        if self.is_zero() {
+            write!(f, "0")
+        } else if point > plain_limit || point < -6 {
+            let (first, rest) = digits.split_at(1);
+            let rest = if rest.is_empty() { String::new() } else { format!(".{}", rest) };
+            write!(f, "{}{}{}e{}", sign, first, rest, point - 1)
+        } else if self.exponent >= 0 {
+            write!(f, "{}{}{}", sign, digits, "0".repeat(self.exponent as usize))
+        } else if point > 0 {
+            let (whole, fraction) = digits.split_at(point as usize);
+            write!(f, "{}{}.{}", sign, whole, fraction)
+        } else {
+            write!(f, "{}0.{}{}", sign, "0".repeat((-point) as usize), digits)
+        }
+    }
+}
+
+fn precision_arg(value: Option<&Value>) -> Result<usize, String> {
+    match value {
+        None => Ok(DEFAULT_PRECISION),
+        Some(Value::Int(p)) if *p >= 1 && *p as usize <= MAX_PRECISION => Ok(*p as usize),
+        Some(Value::Int(p)) => Err(format!("bigfloat precision must be between 1 and {}, got {}", MAX_PRECISION, p)),
+        Some(other) => Err(format!("bigfloat precision must be an int, found {}", other.type_name())),
+    }
+}
+
+/// `math.bigfloat => |value, precision?|`: a string, int, float or bigfloat
+/// at `precision` significant digits (default [`DEFAULT_PRECISION`]).
+pub fn construct(args: &[Value]) -> Result<Value, String> {
+    let Some((value, rest)) = args.split_first() else {
+        return Err("bigfloat expects a value and an optional precision".to_string());
+    };
+    if rest.len() > 1 {
+        return Err("bigfloat expects a value and an optional precision".to_string());
+    }
+    let precision = precision_arg(rest.first())?;
+    let number = match value {
+        Value::String(text) => BigFloat::parse(text.trim(), precision)?,
+        other => operand(other, precision).ok_or_else(|| {
+            format!("bigfloat expects a string, int, float or bigfloat, found {}", other.type_name())
+        })??,
+    }
+    .with_precision(precision);
+    Ok(Value::BigFloat(number))
+}
+
+fn from_value(value: &Value) -> Option<BigFloat> {
+    match value {
+        Value::BigFloat(number) => Some(number.clone()),
+        _ => None,
+    }
+}
+
+// A bigfloat, or a number converted to one at `precision` digits.
+fn operand(value: &Value, precision: usize) -> Option<Result<BigFloat, String>> {
+    match value {
+        Value::Int(i) => Some(Ok(BigFloat::from_int(*i, precision))),
+        Value::Float(f) => Some(BigFloat::from_f64(*f, precision)),
+        other => from_value(other).map(Ok),
+    }
+}
+
+/// Operators with a bigfloat operand. Ints and floats join at the
+/// bigfloat's precision. `None` when neither side is a bigfloat, or for `+`
+/// with a string, which still concatenates.
+pub fn binary(op: &str, left: &Value, right: &Value) -> Option<Result<Value, String>> {
+    let (l, r) = (from_value(left), from_value(right));
+    let precision = match (&l, &r) {
+        (None, None) => return None,
+        (Some(x), None) | (None, Some(x)) => x.precision,
+        (Some(a), Some(b)) => a.precision.max(b.precision),
+    };
+    if op == "in" || (op == "+" && (matches!(left, Value::String(_)) || matches!(right, Value::String(_)))) {
+        return None;
+    }
+    let (Some(l), Some(r)) = (operand(left, precision), operand(right, precision)) else {
+        return Some(match op {
+            "==" => Ok(Value::Boolean(false)),
+            "!=" => Ok(Value::Boolean(true)),
+            _ => Err(format!("Cannot apply '{}' to a bigfloat and a non-number", op)),
+        });
+    };
+    let (l, r) = match (l, r) {
+        (Ok(l), Ok(r)) => (l, r),
+        (Err(e), _) | (_, Err(e)) => return Some(Err(e)),
+    };
+    Some(match op {
+        "+" => l.add(&r).map(Value::BigFloat),
+        "-" => l.sub(&r).map(Value::BigFloat),
+        "*" => l.mul(&r).map(Value::BigFloat),
+        "/" => l.div(&r).map(Value::BigFloat),
+        "==" => Ok(Value::Boolean(l == r)),
+        "!=" => Ok(Value::Boolean(l != r)),
+        "<" => Ok(Value::Boolean(l < r)),
+        "<=" => Ok(Value::Boolean(l <= r)),
+        ">" => Ok(Value::Boolean(l > r)),
+        ">=" => Ok(Value::Boolean(l >= r)),
+        _ => Err(format!("Operator '{}' is not supported for bigfloats", op)),
+    })
+}
+
+/// Methods on a bigfloat.
+pub fn method(number: &BigFloat, name: &str, args: &[Value]) -> Result<Value, String> {
+    let no_args = |result: Result<BigFloat, String>| {
+        if args.is_empty() {
+            result.map(Value::BigFloat)
+        } else {
+            Err(format!("bigfloat {} takes no arguments", name))
+        }
+    };
+    match name {
+        "sqrt" => no_args(number.sqrt()),
+        "exp" => no_args(number.exp()),
+        "ln" => no_args(number.ln()),
+        "to_float" if args.is_empty() => Ok(Value::Float(number.to_f64())),
+        "with_precision" => match args {
+            [p] => precision_arg(Some(p)).map(|p| Value::BigFloat(number.with_precision(p))),
+            _ => Err("bigfloat with_precision expects one int".to_string()),
+        },
+        _ => Err(format!("Bigfloat method '{}' not supported", name)),
+    }
+}
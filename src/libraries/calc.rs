@@ -0,0 +1,649 @@
+//! Symbolic expressions: parse, differentiate, simplify and evaluate.
+//!
+//! An expression is a [`Value::Expression`] holding a [`Node`] tree, which
+//! prints as its canonical text. Operations work on the tree and wrap the
+//! result back up. Both evaluators route operators with an expression
+//! operand through [`binary`], so `x * x + 1` builds a new expression from
+//! `let x: obj = calc.var => |"x"|`.
+
+use crate::environment::{Environment, Value};
+use indexmap::IndexMap;
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
+
+pub const SIGNATURES: crate::libraries::Signatures = &[
+    ("parse", "|text: string| -> obj"),
+    ("var", "|name: string| -> obj"),
+    ("diff", "|expr: obj, var: string| -> obj"),
+    ("simplify", "|expr: obj| -> obj"),
+    ("eval", "|expr: obj, at: obj| -> float"),
+    ("vars", "|expr: obj| -> arr"),
+];
+
+/// Functions an expression may call, each taking one argument.
+const FUNCTIONS: &[&str] = &["sin", "cos", "tan", "exp", "ln", "sqrt"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Num(f64),
+    Var(String),
+    Neg(Box<Node>),
+    Add(Box<Node>, Box<Node>),
+    Sub(Box<Node>, Box<Node>),
+    Mul(Box<Node>, Box<Node>),
+    Div(Box<Node>, Box<Node>),
+    Pow(Box<Node>, Box<Node>),
+    Call(&'static str, Box<Node>),
+}
+
+use Node::*;
+
+fn num(n: f64) -> Node {
+    Num(n)
+}
+
+fn add(a: Node, b: Node) -> Node {
+    Add(Box::new(a), Box::new(b))
+}
+
+fn sub(a: Node, b: Node) -> Node {
+    Sub(Box::new(a), Box::new(b))
+}
+
+fn mul(a: Node, b: Node) -> Node {
+    Mul(Box::new(a), Box::new(b))
+}
+
+fn div(a: Node, b: Node) -> Node {
+    Div(Box::new(a), Box::new(b))
+}
+
+fn pow(a: Node, b: Node) -> Node {
+    Pow(Box::new(a), Box::new(b))
+}
+
+fn call(f: &'static str, a: Node) -> Node {
+    Call(f, Box::new(a))
+}
+
+// ---------------------------------------------------------------------------
+// Parsing
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(char),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            let value = literal.parse().map_err(|_| format!("'{}' is not a number", literal))?;
+            tokens.push(Token::Num(value));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if "+-*/^()".contains(c) {
+            tokens.push(Token::Op(c));
+            i += 1;
+        } else {
+            return Err(format!("unexpected '{}' at column {}", c, i + 1));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the usual precedence: `+ -`, then `* /` and
+/// juxtaposition (`3x`, `2(x + 1)`), then unary minus, then right
+/// associative `^`.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, op: char) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Node, String> {
+        let mut left = self.term()?;
+        loop {
+            if self.eat('+') {
+                left = add(left, self.term()?);
+            } else if self.eat('-') {
+                left = sub(left, self.term()?);
+            } else {
+                return Ok(left);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Node, String> {
+        let mut left = self.unary()?;
+        loop {
+            if self.eat('*') {
+                left = mul(left, self.unary()?);
+            } else if self.eat('/') {
+                left = div(left, self.unary()?);
+            } else if matches!(self.peek(), Some(Token::Ident(_)) | Some(Token::Op('('))) {
+                left = mul(left, self.power()?);
+            } else {
+                return Ok(left);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.eat('-') {
+            return Ok(Neg(Box::new(self.unary()?)));
+        }
+        if self.eat('+') {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Node, String> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            return Ok(pow(base, self.unary()?));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.tokens.get(self.pos).cloned() {
+            Some(Token::Num(n)) => {
+                self.pos += 1;
+                Ok(Num(n))
+            }
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                if let Some(f) = FUNCTIONS.iter().find(|f| **f == name) {
+                    if !self.eat('(') {
+                        return Err(format!("{} needs its argument in parentheses", name));
+                    }
+                    let arg = self.expr()?;
+                    if !self.eat(')') {
+                        return Err(format!("missing ')' after the argument to {}", name));
+                    }
+                    return Ok(call(f, arg));
+                }
+                Ok(match name.as_str() {
+                    "pi" => Num(std::f64::consts::PI),
+                    "e" => Num(std::f64::consts::E),
+                    _ => Var(name),
+                })
+            }
+            Some(Token::Op('(')) => {
+                self.pos += 1;
+                let inner = self.expr()?;
+                if !self.eat(')') {
+                    return Err("missing ')'".to_string());
+                }
+                Ok(inner)
+            }
+            Some(Token::Op(c)) => Err(format!("unexpected '{}'", c)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn parse(text: &str) -> Result<Node, String> {
+    let mut parser = Parser { tokens: tokenize(text)?, pos: 0 };
+    let node = parser.expr()?;
+    match parser.peek() {
+        None => Ok(node),
+        Some(Token::Op(c)) => Err(format!("unexpected '{}'", c)),
+        Some(Token::Num(n)) => Err(format!("unexpected number {}", n)),
+        Some(Token::Ident(name)) => Err(format!("unexpected '{}'", name)),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Printing
+// ---------------------------------------------------------------------------
+
+impl Node {
+    /// Binding strength, used to decide where parentheses are needed.
+    fn precedence(&self) -> u8 {
+        match self {
+            Add(..) | Sub(..) => 1,
+            Mul(..) | Div(..) => 2,
+            Neg(_) => 3,
+            Num(n) if *n < 0.0 => 3,
+            Pow(..) => 4,
+            _ => 5,
+        }
+    }
+
+    fn write_operand(&self, f: &mut Formatter, min: u8) -> fmt::Result {
+        if self.precedence() < min {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Num(n) if *n == std::f64::consts::PI => write!(f, "pi"),
+            Num(n) if *n == std::f64::consts::E => write!(f, "e"),
+            Num(n) => write!(f, "{}", n),
+            Var(name) => write!(f, "{}", name),
+            Neg(a) => {
+                write!(f, "-")?;
+                a.write_operand(f, 3)
+            }
+            Add(a, b) => {
+                a.write_operand(f, 1)?;
+                write!(f, " + ")?;
+                b.write_operand(f, 2)
+            }
+            Sub(a, b) => {
+                a.write_operand(f, 1)?;
+                write!(f, " - ")?;
+                b.write_operand(f, 2)
+            }
+            Mul(a, b) => {
+                a.write_operand(f, 2)?;
+                write!(f, "*")?;
+                b.write_operand(f, 3)
+            }
+            Div(a, b) => {
+                a.write_operand(f, 2)?;
+                write!(f, "/")?;
+                b.write_operand(f, 3)
+            }
+            Pow(a, b) => {
+                a.write_operand(f, 5)?;
+                write!(f, "^")?;
+                b.write_operand(f, 3)
+            }
+            Call(name, a) => write!(f, "{}({})", name, a),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Calculus
+// ---------------------------------------------------------------------------
+
+impl Node {
+    fn contains(&self, var: &str) -> bool {
+        match self {
+            Num(_) => false,
+            Var(name) => name == var,
+            Neg(a) | Call(_, a) => a.contains(var),
+            Add(a, b) | Sub(a, b) | Mul(a, b) | Div(a, b) | Pow(a, b) => a.contains(var) || b.contains(var),
+        }
+    }
+
+    fn collect_vars(&self, out: &mut BTreeSet<String>) {
+        match self {
+            Num(_) => {}
+            Var(name) => {
+                out.insert(name.clone());
+            }
+            Neg(a) | Call(_, a) => a.collect_vars(out),
+            Add(a, b) | Sub(a, b) | Mul(a, b) | Div(a, b) | Pow(a, b) => {
+                a.collect_vars(out);
+                b.collect_vars(out);
+            }
+        }
+    }
+
+    /// The derivative with respect to `var`, unsimplified.
+    fn derivative(&self, var: &str) -> Node {
+        if !self.contains(var) {
+            return num(0.0);
+        }
+        match self {
+            Num(_) => num(0.0),
+            Var(_) => num(1.0),
+            Neg(a) => Neg(Box::new(a.derivative(var))),
+            Add(a, b) => add(a.derivative(var), b.derivative(var)),
+            Sub(a, b) => sub(a.derivative(var), b.derivative(var)),
+            Mul(a, b) => add(mul(a.derivative(var), (**b).clone()), mul((**a).clone(), b.derivative(var))),
+            Div(a, b) => div(
+                sub(mul(a.derivative(var), (**b).clone()), mul((**a).clone(), b.derivative(var))),
+                pow((**b).clone(), num(2.0)),
+            ),
+            Pow(a, b) if !b.contains(var) => mul(
+                mul((**b).clone(), pow((**a).clone(), sub((**b).clone(), num(1.0)))),
+                a.derivative(var),
+            ),
+            Pow(a, b) if !a.contains(var) => mul(mul(self.clone(), call("ln", (**a).clone())), b.derivative(var)),
+            // d(u^v) = u^v * (v' ln u + v u' / u)
+            Pow(a, b) => mul(
+                self.clone(),
+                add(
+                    mul(b.derivative(var), call("ln", (**a).clone())),
+                    div(mul((**b).clone(), a.derivative(var)), (**a).clone()),
+                ),
+            ),
+            Call(name, a) => {
+                let u = (**a).clone();
+                let outer = match *name {
+                    "sin" => call("cos", u),
+                    "cos" => Neg(Box::new(call("sin", u))),
+                    "tan" => div(num(1.0), pow(call("cos", u), num(2.0))),
+                    "exp" => self.clone(),
+                    "ln" => div(num(1.0), u),
+                    _ => div(num(1.0), mul(num(2.0), self.clone())),
+                };
+                mul(outer, a.derivative(var))
+            }
+        }
+    }
+
+    fn eval(&self, at: &dyn Fn(&str) -> Option<f64>) -> Result<f64, String> {
+        Ok(match self {
+            Num(n) => *n,
+            Var(name) => at(name).ok_or_else(|| format!("no value given for '{}'", name))?,
+            Neg(a) => -a.eval(at)?,
+            Add(a, b) => a.eval(at)? + b.eval(at)?,
+            Sub(a, b) => a.eval(at)? - b.eval(at)?,
+            Mul(a, b) => a.eval(at)? * b.eval(at)?,
+            Div(a, b) => a.eval(at)? / b.eval(at)?,
+            Pow(a, b) => a.eval(at)?.powf(b.eval(at)?),
+            Call(name, a) => apply(name, a.eval(at)?),
+        })
+    }
+
+    /// Constant folding plus the identities that clean up a derivative:
+    /// `0 + x`, `1 * x`, `x^1`, `x - x`, `x * x` and friends.
+    fn simplify(&self) -> Node {
+        match self {
+            Num(_) | Var(_) => self.clone(),
+            Neg(a) => match a.simplify() {
+                Num(n) => num(-n),
+                Neg(inner) => *inner,
+                a => Neg(Box::new(a)),
+            },
+            Add(a, b) => match (a.simplify(), b.simplify()) {
+                (Num(x), Num(y)) => num(x + y),
+                (Num(0.0), e) | (e, Num(0.0)) => e,
+                (e, Num(y)) if y < 0.0 => sub(e, num(-y)),
+                (e, Neg(n)) => sub(e, *n),
+                (x, y) if x == y => mul(num(2.0), x).simplify(),
+                (x, y) => add(x, y),
+            },
+            Sub(a, b) => match (a.simplify(), b.simplify()) {
+                (Num(x), Num(y)) => num(x - y),
+                (e, Num(0.0)) => e,
+                (Num(0.0), e) => Neg(Box::new(e)).simplify(),
+                (e, Neg(n)) => add(e, *n),
+                (x, y) if x == y => num(0.0),
+                (x, y) => sub(x, y),
+            },
+            Mul(a, b) => match (a.simplify(), b.simplify()) {
+                (Num(x), Num(y)) => num(x * y),
+                (Num(0.0), _) | (_, Num(0.0)) => num(0.0),
+                (Num(1.0), e) | (e, Num(1.0)) => e,
+                (Num(-1.0), e) | (e, Num(-1.0)) => Neg(Box::new(e)).simplify(),
+                (Num(x), Mul(c, e)) | (Mul(c, e), Num(x)) if matches!(*c, Num(_)) => match *c {
+                    Num(y) => mul(num(x * y), *e).simplify(),
+                    _ => unreachable!(),
+                },
+                (e, Num(x)) => mul(num(x), e),
+                (Div(n, d), e) | (e, Div(n, d)) if *n == num(1.0) => div(e, *d).simplify(),
+                (e, Mul(c, f)) if matches!(*c, Num(_)) => mul(mul(*c, e), *f).simplify(),
+                (Neg(x), Neg(y)) => mul(*x, *y).simplify(),
+                (Neg(x), y) | (y, Neg(x)) => Neg(Box::new(mul(*x, y))).simplify(),
+                (x, y) if x == y => pow(x, num(2.0)),
+                (Pow(x, p), y) | (y, Pow(x, p)) if *x == y => pow(y, add(*p, num(1.0))).simplify(),
+                (x, y) => mul(x, y),
+            },
+            Div(a, b) => match (a.simplify(), b.simplify()) {
+                (Num(x), Num(y)) if y != 0.0 => num(x / y),
+                (Num(0.0), _) => num(0.0),
+                (e, Num(1.0)) => e,
+                (x, y) if x == y && x != num(0.0) => num(1.0),
+                (x, y) => div(x, y),
+            },
+            Pow(a, b) => match (a.simplify(), b.simplify()) {
+                (Num(x), Num(y)) if x.powf(y).is_finite() => num(x.powf(y)),
+                (_, Num(0.0)) => num(1.0),
+                (e, Num(1.0)) => e,
+                (Num(1.0), _) => num(1.0),
+                (x, y) => pow(x, y),
+            },
+            Call(name, a) => match a.simplify() {
+                Num(x) if apply(name, x).is_finite() && apply(name, x).fract() == 0.0 => num(apply(name, x)),
+                Call("exp", inner) if *name == "ln" => *inner,
+                Call("ln", inner) if *name == "exp" => *inner,
+                a => call(name, a),
+            },
+        }
+    }
+}
+
+fn apply(name: &str, x: f64) -> f64 {
+    match name {
+        "sin" => x.sin(),
+        "cos" => x.cos(),
+        "tan" => x.tan(),
+        "exp" => x.exp(),
+        "ln" => x.ln(),
+        _ => x.sqrt(),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Script values
+// ---------------------------------------------------------------------------
+
+/// A symbolic expression as scripts hold it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression(Node);
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+fn to_value(node: &Node) -> Value {
+    Value::Expression(Expression(node.clone()))
+}
+
+fn from_value(value: &Value) -> Option<Node> {
+    match value {
+        Value::Expression(Expression(node)) => Some(node.clone()),
+        _ => None,
+    }
+}
+
+/// An expression argument: an expression object, or text to parse.
+fn expr_arg(value: Option<&Value>, function: &str) -> Result<Node, String> {
+    match value {
+        Some(Value::String(text)) => parse(text).map_err(|e| format!("{}: {}", function, e)),
+        Some(value) => from_value(value).ok_or_else(|| format!("{} expects an expression or a string", function)),
+        None => Err(format!("{} expects an expression", function)),
+    }
+}
+
+fn var_arg(node: &Node, value: Option<&Value>, function: &str) -> Result<String, String> {
+    match value {
+        Some(Value::String(name)) => Ok(name.clone()),
+        Some(_) => Err(format!("{} expects the variable name as a string", function)),
+        // With no name, a single-variable expression differentiates by its
+        // only variable.
+        None => {
+            let mut vars = BTreeSet::new();
+            node.collect_vars(&mut vars);
+            match vars.len() {
+                0 => Ok("x".to_string()),
+                1 => Ok(vars.into_iter().next().unwrap_or_default()),
+                _ => Err(format!("{} needs a variable name when the expression has several", function)),
+            }
+        }
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Int(i) => Some(*i as f64),
+        Value::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+/// Evaluates at an object of variable values, or at a bare number when the
+/// expression has at most one variable.
+fn eval_at(node: &Node, at: Option<&Value>, function: &str) -> Result<Value, String> {
+    let result = match at {
+        Some(Value::Object(values)) => node.eval(&|name| values.get(name).and_then(number)),
+        Some(value) => {
+            let x = number(value).ok_or_else(|| format!("{} expects an object of variable values or a number", function))?;
+            let var = var_arg(node, None, function)?;
+            node.eval(&|name| (name == var).then_some(x))
+        }
+        None => node.eval(&|_| None),
+    };
+    result.map(Value::Float).map_err(|e| format!("{}: {}", function, e))
+}
+
+fn vars(node: &Node) -> Value {
+    let mut names = BTreeSet::new();
+    node.collect_vars(&mut names);
+    Value::Array(names.into_iter().map(Value::String).collect())
+}
+
+/// `+ - * /`, and `==` and `!=` by structure, with an expression operand
+/// and an expression or number on the other side. `None` otherwise, so
+/// strings still concatenate.
+pub fn binary(op: &str, left: &Value, right: &Value) -> Option<Result<Value, String>> {
+    let (l, r) = (from_value(left), from_value(right));
+    if l.is_none() && r.is_none() {
+        return None;
+    }
+    let l = l.or_else(|| number(left).map(num))?;
+    let r = r.or_else(|| number(right).map(num))?;
+    Some(match op {
+        "+" => Ok(to_value(&add(l, r))),
+        "-" => Ok(to_value(&sub(l, r))),
+        "*" => Ok(to_value(&mul(l, r))),
+        "/" => Ok(to_value(&div(l, r))),
+        "==" => Ok(Value::Boolean(l == r)),
+        "!=" => Ok(Value::Boolean(l != r)),
+        _ => Err(format!("Operator '{}' is not supported for expressions", op)),
+    })
+}
+
+/// Methods on an expression.
+pub fn method(expression: &Expression, name: &str, args: &[Value]) -> Result<Value, String> {
+    let node = expression.0.clone();
+    match name {
+        "diff" if args.len() <= 1 => {
+            var_arg(&node, args.first(), "expression diff").map(|var| to_value(&node.derivative(&var).simplify()))
+        }
+        "simplify" if args.is_empty() => Ok(to_value(&node.simplify())),
+        "eval" if args.len() <= 1 => eval_at(&node, args.first(), "expression eval"),
+        "vars" if args.is_empty() => Ok(vars(&node)),
+        "pow" => match args {
+            [exponent] => match from_value(exponent).or_else(|| number(exponent).map(num)) {
+                Some(exponent) => Ok(to_value(&pow(node, exponent))),
+                None => Err("pow expects a number or an expression".to_string()),
+            },
+            _ => Err("pow expects one exponent".to_string()),
+        },
+        "diff" | "simplify" | "eval" | "vars" => Err(format!("wrong number of arguments to expression {}", name)),
+        _ => Err(format!("Expression method '{}' not supported", name)),
+    }
+}
+
+pub fn register(env: &mut Environment) -> Result<(), String> {
+    let mut calc_obj = IndexMap::new();
+
+    calc_obj.insert(
+        "parse".to_string(),
+        Value::NativeFunction(Arc::new(|args| match args.as_slice() {
+            [Value::String(text)] => parse(text).map(|node| to_value(&node)).map_err(|e| format!("calc.parse: {}", e)),
+            _ => Err("calc.parse expects one string".to_string()),
+        })),
+    );
+
+    calc_obj.insert(
+        "var".to_string(),
+        Value::NativeFunction(Arc::new(|args| match args.as_slice() {
+            [Value::String(name)] if matches!(parse(name), Ok(Var(_))) => Ok(to_value(&Var(name.clone()))),
+            _ => Err("calc.var expects one variable name".to_string()),
+        })),
+    );
+
+    calc_obj.insert(
+        "diff".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            if args.len() > 2 {
+                return Err("calc.diff expects an expression and a variable name".to_string());
+            }
+            let node = expr_arg(args.first(), "calc.diff")?;
+            let var = var_arg(&node, args.get(1), "calc.diff")?;
+            Ok(to_value(&node.derivative(&var).simplify()))
+        })),
+    );
+
+    calc_obj.insert(
+        "simplify".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            if args.len() != 1 {
+                return Err("calc.simplify expects one expression".to_string());
+            }
+            Ok(to_value(&expr_arg(args.first(), "calc.simplify")?.simplify()))
+        })),
+    );
+
+    calc_obj.insert(
+        "eval".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            if args.len() > 2 {
+                return Err("calc.eval expects an expression and its variable values".to_string());
+            }
+            eval_at(&expr_arg(args.first(), "calc.eval")?, args.get(1), "calc.eval")
+        })),
+    );
+
+    calc_obj.insert(
+        "vars".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            if args.len() != 1 {
+                return Err("calc.vars expects one expression".to_string());
+            }
+            Ok(vars(&expr_arg(args.first(), "calc.vars")?))
+        })),
+    );
+
+    env.declare("calc".to_string(), Value::Object(calc_obj), true);
+    Ok(())
+}
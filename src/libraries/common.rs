@@ -0,0 +1,208 @@
+//! Helpers shared by the library natives.
+//!
+//! [`Options`] reads the options object a native takes as its last argument,
+//! so every native checks its fields the same way and fails with the same
+//! wording. Keys a native does not know are reported as `unknown-option`
+//! warnings. A native has no location to report them at, so they wait until
+//! [`call_native`] hands back its result at the call site.
+//!
+//! Natives fail with a plain message string, which becomes a runtime error.
+//! A failure that is not the script's fault, like a missing file, is a
+//! [`NativeError`] instead: converting it to the message string also leaves
+//! its code behind, and [`with_native_code`] turns the runtime error built
+//! from that message into a library error carrying the code.
+
+use crate::config::{self, LintLevel};
+use crate::environment::Value;
+use crate::errors::{push_warning, ErrorCode, ZekkenError};
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::io;
+
+/// The lint unknown option keys are reported under.
+pub const UNKNOWN_OPTION: &str = "unknown-option";
+
+thread_local! {
+    static PENDING: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    static RAISED: RefCell<Option<NativeError>> = const { RefCell::new(None) };
+}
+
+/// Calls `native`, then reports any warnings it raised at `line`/`column`.
+#[inline]
+pub fn call_native(
+    native: &dyn Fn(Vec<Value>) -> Result<Value, String>,
+    args: Vec<Value>,
+    line: usize,
+    column: usize,
+) -> Result<Value, String> {
+    RAISED.with(|raised| raised.take());
+    let result = native(args);
+    if PENDING.with(|pending| !pending.borrow().is_empty()) {
+        for message in PENDING.with(|pending| std::mem::take(&mut *pending.borrow_mut())) {
+            push_warning(UNKNOWN_OPTION, ZekkenError::runtime(&message, line, column, None));
+        }
+    }
+    result
+}
+
+/// A library failure with a code a script can branch on.
+#[derive(Debug, Clone)]
+pub struct NativeError {
+    pub code: &'static str,
+    pub message: String,
+    pub retryable: bool,
+}
+
+impl NativeError {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        NativeError { code, message: message.into(), retryable: false }
+    }
+
+    /// Marks a failure that trying again may fix, like a timeout.
+    pub fn retryable(mut self) -> Self {
+        self.retryable = true;
+        self
+    }
+
+    /// `error` while doing `what`, coded after the errno it usually comes from.
+    pub fn io(error: &io::Error, what: impl Display) -> Self {
+        use io::ErrorKind::*;
+        let (code, retryable) = match error.kind() {
+            NotFound => ("ENOENT", false),
+            PermissionDenied => ("EACCES", false),
+            AlreadyExists => ("EEXIST", false),
+            IsADirectory => ("EISDIR", false),
+            NotADirectory => ("ENOTDIR", false),
+            DirectoryNotEmpty => ("ENOTEMPTY", false),
+            InvalidInput | InvalidData => ("EINVAL", false),
+            Unsupported => ("ENOTSUP", false),
+            UnexpectedEof => ("EOF", false),
+            BrokenPipe => ("EPIPE", false),
+            AddrInUse => ("EADDRINUSE", false),
+            ConnectionRefused => ("ECONNREFUSED", true),
+            ConnectionReset | ConnectionAborted => ("ECONNRESET", true),
+            TimedOut => ("ETIMEDOUT", true),
+            Interrupted => ("EINTR", true),
+            WouldBlock => ("EAGAIN", true),
+            _ => ("EIO", false),
+        };
+        NativeError { code, message: format!("{}: {}", what, error), retryable }
+    }
+}
+
+impl From<NativeError> for String {
+    fn from(error: NativeError) -> String {
+        let message = error.message.clone();
+        RAISED.with(|raised| *raised.borrow_mut() = Some(error));
+        message
+    }
+}
+
+/// `error`, built from the message a native failed with, as the library
+/// error that native raised, if it raised one with that message.
+pub fn with_native_code(error: ZekkenError) -> ZekkenError {
+    match RAISED.with(|raised| raised.take()) {
+        Some(native) if native.message == error.message => {
+            error.with_code(ErrorCode { code: native.code, retryable: native.retryable })
+        }
+        _ => error,
+    }
+}
+
+/// Reads the fields of an options object passed to a native function,
+/// naming the function and the field in its errors. A missing or `null`
+/// object reads like an empty one, and so does a `null` field.
+pub struct Options<'a> {
+    function: &'static str,
+    fields: Option<&'a IndexMap<String, Value>>,
+}
+
+impl<'a> Options<'a> {
+    /// Checks `value` is an options object for `function` whose keys are all
+    /// in `known`. Other keys are ignored, with an `unknown-option` warning,
+    /// or fail the call when that lint is denied.
+    pub fn new(function: &'static str, value: Option<&'a Value>, known: &[&str]) -> Result<Self, String> {
+        let fields = match value {
+            None | Some(Value::Null) => None,
+            Some(Value::Object(fields)) => Some(fields),
+            Some(_) => return Err(format!("{} expects its options as an object", function)),
+        };
+        for key in fields.into_iter().flat_map(IndexMap::keys) {
+            if known.contains(&key.as_str()) {
+                continue;
+            }
+            let message = format!("{} has no option '{}' (it takes {})", function, key, known.join(", "));
+            match config::lint_level(UNKNOWN_OPTION) {
+                LintLevel::Allow => {}
+                LintLevel::Warn => PENDING.with(|pending| pending.borrow_mut().push(message)),
+                LintLevel::Deny => return Err(message),
+            }
+        }
+        Ok(Options { function, fields })
+    }
+
+    fn field(&self, key: &str) -> Option<&'a Value> {
+        self.fields.and_then(|fields| fields.get(key)).filter(|value| !matches!(value, Value::Null))
+    }
+
+    fn invalid(&self, key: &str, expected: &str) -> String {
+        format!("{} option '{}' must be {}", self.function, key, expected)
+    }
+
+    pub fn opt_string(&self, key: &str) -> Result<Option<&'a str>, String> {
+        match self.field(key) {
+            None => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(_) => Err(self.invalid(key, "a string")),
+        }
+    }
+
+    pub fn get_string(&self, key: &str, default: &'a str) -> Result<&'a str, String> {
+        Ok(self.opt_string(key)?.unwrap_or(default))
+    }
+
+    pub fn get_bool(&self, key: &str, default: bool) -> Result<bool, String> {
+        match self.field(key) {
+            None => Ok(default),
+            Some(Value::Boolean(b)) => Ok(*b),
+            Some(_) => Err(self.invalid(key, "a bool")),
+        }
+    }
+
+    /// An int within `range`.
+    pub fn opt_int(&self, key: &str, range: std::ops::RangeInclusive<i64>) -> Result<Option<i64>, String> {
+        match self.field(key) {
+            None => Ok(None),
+            Some(Value::Int(n)) if range.contains(n) => Ok(Some(*n)),
+            Some(_) if range == (0..=i64::MAX) => Err(self.invalid(key, "a non-negative int")),
+            Some(_) if *range.end() == i64::MAX => Err(self.invalid(key, &format!("an int of at least {}", range.start()))),
+            Some(_) => Err(self.invalid(key, &format!("an int from {} to {}", range.start(), range.end()))),
+        }
+    }
+
+    pub fn get_int(&self, key: &str, default: i64, range: std::ops::RangeInclusive<i64>) -> Result<i64, String> {
+        Ok(self.opt_int(key, range)?.unwrap_or(default))
+    }
+
+    /// An object whose values are strings, or `null` to mean "unset".
+    pub fn string_map(&self, key: &str) -> Result<Vec<(&'a str, Option<&'a str>)>, String> {
+        match self.field(key) {
+            None => Ok(Vec::new()),
+            Some(Value::Object(map)) => map
+                .iter()
+                .map(|(k, v)| match v {
+                    Value::String(s) => Ok((k.as_str(), Some(s.as_str()))),
+                    Value::Null => Ok((k.as_str(), None)),
+                    _ => Err(self.invalid(key, "an object of strings")),
+                })
+                .collect(),
+            Some(_) => Err(self.invalid(key, "an object of strings")),
+        }
+    }
+
+    /// The field's value, whatever its type.
+    pub fn get_any(&self, key: &str) -> Option<&'a Value> {
+        self.field(key)
+    }
+}
@@ -0,0 +1,39 @@
+use crate::environment::decimal::Decimal;
+use crate::environment::{decimal_round, Environment, Value};
+use indexmap::IndexMap;
+use std::sync::Arc;
+
+pub const SIGNATURES: super::Signatures = &[
+    ("parse", "|text: string| -> dec"),
+    ("round", "|value: dec, places?: int, mode?: string| -> dec"),
+];
+
+pub fn register(env: &mut Environment) -> Result<(), String> {
+    let mut decimal_obj = IndexMap::new();
+
+    decimal_obj.insert(
+        "parse".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            match args.as_slice() {
+                [Value::String(text)] => Decimal::parse(text.trim()).map(Value::Decimal),
+                _ => Err("decimal.parse expects exactly one string".to_string()),
+            }
+        })),
+    );
+
+    decimal_obj.insert(
+        "round".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            let Some((value, rest)) = args.split_first() else {
+                return Err("decimal.round expects a decimal, then optional places and mode".to_string());
+            };
+            match value {
+                Value::Decimal(d) => decimal_round(*d, rest).map(Value::Decimal),
+                _ => Err("decimal.round expects a dec as its first argument".to_string()),
+            }
+        })),
+    );
+
+    env.declare("decimal".to_string(), Value::Object(decimal_obj), true);
+    Ok(())
+}
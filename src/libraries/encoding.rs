@@ -1,5 +1,5 @@
 use crate::environment::{Environment, Value};
-use hashbrown::HashMap;
+use indexmap::IndexMap;
 use std::sync::Arc;
 
 const BASE64_TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
@@ -121,8 +121,17 @@ fn parse_hex_nibble(c: u8) -> Option<u8> {
     }
 }
 
+pub const SIGNATURES: super::Signatures = &[
+    ("base64_encode", "|input: string| -> string"),
+    ("base64_decode", "|input: string| -> string"),
+    ("hex_encode", "|input: string| -> string"),
+    ("hex_decode", "|input: string| -> string"),
+    ("url_encode", "|input: string| -> string"),
+    ("url_decode", "|input: string| -> string"),
+];
+
 pub fn register(env: &mut Environment) -> Result<(), String> {
-    let mut encoding_obj = HashMap::new();
+    let mut encoding_obj = IndexMap::new();
 
     encoding_obj.insert(
         "base64_encode".to_string(),
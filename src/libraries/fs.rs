@@ -1,18 +1,39 @@
-use crate::environment::{Environment, Value}; 
-use hashbrown::HashMap;
-use std::fs;
-use std::fs::OpenOptions;
-use std::io::{BufRead, BufReader, Write};
-use std::path::Path;
+use crate::environment::{Environment, NativeResource, Value}; 
+use indexmap::IndexMap;
+use super::common::NativeError;
+use super::vfs::{backend, FileLike, OpenMode};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
 use std::sync::Arc;
 
+// Open files are read through a buffer; writes and seeks go to the file
+// after re-syncing its position with what has actually been consumed.
+type OpenFile = BufReader<Box<dyn FileLike>>;
+
+pub const SIGNATURES: super::Signatures = &[
+    ("read_file", "|path: string| -> string"),
+    ("write_file", "|path: string, content: string| -> void"),
+    ("append_file", "|path: string, content: string| -> void"),
+    ("read_dir", "|path: string| -> arr"),
+    ("read_lines", "|path: string| -> arr"),
+    ("create_dir", "|path: string| -> void"),
+    ("remove_dir", "|path: string| -> void"),
+    ("exists", "|path: string| -> bool"),
+    ("is_file", "|path: string| -> bool"),
+    ("is_dir", "|path: string| -> bool"),
+    ("remove_file", "|path: string| -> void"),
+    ("copy_file", "|src: string, dst: string| -> int"),
+    ("rename", "|src: string, dst: string| -> void"),
+    ("stat", "|path: string| -> obj"),
+    ("open", "|path: string, mode?: string| -> obj"),
+];
+
 pub fn register(env: &mut Environment) -> Result<(), String> {
     // Create reusable function values
     let read_file_fn = Value::NativeFunction(Arc::new(|args| {
         if let [Value::String(path)] = args.as_slice() {
-            match fs::read_to_string(Path::new(path.as_str())) {
+            match backend().read_to_string(path) {
                 Ok(content) => Ok(Value::String(content)),
-                Err(e) => Err(format!("Failed to read file '{}': {}", path, e))
+                Err(e) => Err(NativeError::io(&e, format!("Failed to read file '{}'", path)).into())
             }
         } else {
             Err("read_file expects a string path argument".to_string())
@@ -20,16 +41,16 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
     }));
 
     // For object-style access, we maintain an fs object
-    let mut fs_obj = HashMap::new();
+    let mut fs_obj = IndexMap::new();
 
     // Add functions to the fs object
     fs_obj.insert("read_file".to_string(), read_file_fn.clone());
 
     fs_obj.insert("write_file".to_string(), Value::NativeFunction(Arc::new(|args| {
         if let [Value::String(path), Value::String(content)] = args.as_slice() {
-            match fs::write(Path::new(path.as_str()), content) {
+            match backend().write(path, content.as_bytes(), false) {
                 Ok(_) => Ok(Value::Void),
-                Err(e) => Err(format!("Failed to write file '{}': {}", path, e))
+                Err(e) => Err(NativeError::io(&e, format!("Failed to write file '{}'", path)).into())
             }
         } else {
             Err("write_file expects path and content string arguments".to_string())
@@ -38,12 +59,9 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
 
     fs_obj.insert("append_file".to_string(), Value::NativeFunction(Arc::new(|args| {
         if let [Value::String(path), Value::String(content)] = args.as_slice() {
-            match OpenOptions::new().create(true).append(true).open(Path::new(path.as_str())) {
-                Ok(mut file) => match file.write_all(content.as_bytes()) {
-                    Ok(_) => Ok(Value::Void),
-                    Err(e) => Err(format!("Failed to append to file '{}': {}", path, e)),
-                },
-                Err(e) => Err(format!("Failed to open file '{}' for append: {}", path, e)),
+            match backend().write(path, content.as_bytes(), true) {
+                Ok(_) => Ok(Value::Void),
+                Err(e) => Err(NativeError::io(&e, format!("Failed to append to file '{}'", path)).into()),
             }
         } else {
             Err("append_file expects path and content string arguments".to_string())
@@ -53,15 +71,9 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
     // Directory Operations
     fs_obj.insert("read_dir".to_string(), Value::NativeFunction(Arc::new(|args| {
         if let [Value::String(path)] = args.as_slice() {
-            match fs::read_dir(Path::new(path.as_str())) {
-                Ok(entries) => {
-                    let files: Vec<Value> = entries
-                        .filter_map(|entry| entry.ok())
-                        .map(|entry| Value::String(entry.path().display().to_string()))
-                        .collect();
-                    Ok(Value::Array(files))
-                },
-                Err(e) => Err(format!("Failed to read directory '{}': {}", path, e))
+            match backend().list(path) {
+                Ok(entries) => Ok(Value::Array(entries.into_iter().map(Value::String).collect())),
+                Err(e) => Err(NativeError::io(&e, format!("Failed to read directory '{}'", path)).into())
             }
         } else {
             Err("read_dir expects a string path argument".to_string())
@@ -70,19 +82,9 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
 
     fs_obj.insert("read_lines".to_string(), Value::NativeFunction(Arc::new(|args| {
         if let [Value::String(path)] = args.as_slice() {
-            match fs::File::open(Path::new(path.as_str())) {
-                Ok(file) => {
-                    let reader = BufReader::new(file);
-                    let mut lines: Vec<Value> = Vec::new();
-                    for line in reader.lines() {
-                        match line {
-                            Ok(text) => lines.push(Value::String(text)),
-                            Err(e) => return Err(format!("Failed reading line from '{}': {}", path, e)),
-                        }
-                    }
-                    Ok(Value::Array(lines))
-                }
-                Err(e) => Err(format!("Failed to open file '{}': {}", path, e)),
+            match backend().read_to_string(path) {
+                Ok(content) => Ok(Value::Array(content.lines().map(|line| Value::String(line.to_string())).collect())),
+                Err(e) => Err(NativeError::io(&e, format!("Failed to read file '{}'", path)).into()),
             }
         } else {
             Err("read_lines expects a string path argument".to_string())
@@ -91,9 +93,9 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
 
     fs_obj.insert("create_dir".to_string(), Value::NativeFunction(Arc::new(|args| {
         if let [Value::String(path)] = args.as_slice() {
-            match fs::create_dir_all(Path::new(path.as_str())) {
+            match backend().create_dir(path) {
                 Ok(_) => Ok(Value::Boolean(true)),
-                Err(e) => Err(format!("Failed to create directory '{}': {}", path, e))
+                Err(e) => Err(NativeError::io(&e, format!("Failed to create directory '{}'", path)).into())
             }
         } else {
             Err("create_dir expects a string path argument".to_string())
@@ -102,9 +104,9 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
 
     fs_obj.insert("remove_dir".to_string(), Value::NativeFunction(Arc::new(|args| {
         if let [Value::String(path)] = args.as_slice() {
-            match fs::remove_dir_all(Path::new(path.as_str())) {
+            match backend().remove_dir(path) {
                 Ok(_) => Ok(Value::Boolean(true)),
-                Err(e) => Err(format!("Failed to remove directory '{}': {}", path, e))
+                Err(e) => Err(NativeError::io(&e, format!("Failed to remove directory '{}'", path)).into())
             }
         } else {
             Err("remove_dir expects a string path argument".to_string())
@@ -114,7 +116,7 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
     // Path Operations
     fs_obj.insert("exists".to_string(), Value::NativeFunction(Arc::new(|args| {
         if let [Value::String(path)] = args.as_slice() {
-            Ok(Value::Boolean(Path::new(path.as_str()).exists()))
+            Ok(Value::Boolean(backend().exists(path)))
         } else {
             Err("exists expects a string path argument".to_string())
         }
@@ -122,7 +124,7 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
 
     fs_obj.insert("is_file".to_string(), Value::NativeFunction(Arc::new(|args| {
         if let [Value::String(path)] = args.as_slice() {
-            Ok(Value::Boolean(Path::new(path.as_str()).is_file()))
+            Ok(Value::Boolean(backend().metadata(path).is_ok_and(|meta| meta.is_file)))
         } else {
             Err("is_file expects a string path argument".to_string())
         }
@@ -130,7 +132,7 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
 
     fs_obj.insert("is_dir".to_string(), Value::NativeFunction(Arc::new(|args| {
         if let [Value::String(path)] = args.as_slice() {
-            Ok(Value::Boolean(Path::new(path.as_str()).is_dir()))
+            Ok(Value::Boolean(backend().metadata(path).is_ok_and(|meta| meta.is_dir)))
         } else {
             Err("is_dir expects a string path argument".to_string())
         }
@@ -138,9 +140,9 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
 
     fs_obj.insert("remove_file".to_string(), Value::NativeFunction(Arc::new(|args| {
         if let [Value::String(path)] = args.as_slice() {
-            match fs::remove_file(Path::new(path.as_str())) {
+            match backend().remove_file(path) {
                 Ok(_) => Ok(Value::Boolean(true)),
-                Err(e) => Err(format!("Failed to remove file '{}': {}", path, e))
+                Err(e) => Err(NativeError::io(&e, format!("Failed to remove file '{}'", path)).into())
             }
         } else {
             Err("remove_file expects a string path argument".to_string())
@@ -149,9 +151,9 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
 
     fs_obj.insert("copy_file".to_string(), Value::NativeFunction(Arc::new(|args| {
         if let [Value::String(from), Value::String(to)] = args.as_slice() {
-            match fs::copy(Path::new(from.as_str()), Path::new(to.as_str())) {
+            match backend().copy(from, to) {
                 Ok(bytes) => Ok(Value::Int(bytes as i64)),
-                Err(e) => Err(format!("Failed to copy file '{}' -> '{}': {}", from, to, e)),
+                Err(e) => Err(NativeError::io(&e, format!("Failed to copy file '{}' -> '{}'", from, to)).into()),
             }
         } else {
             Err("copy_file expects source and destination string paths".to_string())
@@ -160,9 +162,9 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
 
     fs_obj.insert("rename".to_string(), Value::NativeFunction(Arc::new(|args| {
         if let [Value::String(from), Value::String(to)] = args.as_slice() {
-            match fs::rename(Path::new(from.as_str()), Path::new(to.as_str())) {
+            match backend().rename(from, to) {
                 Ok(_) => Ok(Value::Void),
-                Err(e) => Err(format!("Failed to rename '{}' -> '{}': {}", from, to, e)),
+                Err(e) => Err(NativeError::io(&e, format!("Failed to rename '{}' -> '{}'", from, to)).into()),
             }
         } else {
             Err("rename expects source and destination string paths".to_string())
@@ -171,31 +173,139 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
 
     fs_obj.insert("stat".to_string(), Value::NativeFunction(Arc::new(|args| {
         if let [Value::String(path)] = args.as_slice() {
-            match fs::metadata(Path::new(path.as_str())) {
+            match backend().metadata(path) {
                 Ok(meta) => {
-                    let mut out = HashMap::new();
+                    let mut out = IndexMap::new();
                     out.insert("path".to_string(), Value::String(path.clone()));
-                    out.insert("size".to_string(), Value::Int(meta.len() as i64));
-                    out.insert("is_file".to_string(), Value::Boolean(meta.is_file()));
-                    out.insert("is_dir".to_string(), Value::Boolean(meta.is_dir()));
-                    out.insert("readonly".to_string(), Value::Boolean(meta.permissions().readonly()));
-                    let modified_epoch = meta
-                        .modified()
-                        .ok()
-                        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                        .map(|d| d.as_secs() as i64)
-                        .unwrap_or(0);
-                    out.insert("modified_unix".to_string(), Value::Int(modified_epoch));
+                    out.insert("size".to_string(), Value::Int(meta.len as i64));
+                    out.insert("is_file".to_string(), Value::Boolean(meta.is_file));
+                    out.insert("is_dir".to_string(), Value::Boolean(meta.is_dir));
+                    out.insert("readonly".to_string(), Value::Boolean(meta.readonly));
+                    out.insert("modified_unix".to_string(), Value::Int(meta.modified_unix));
                     Ok(Value::Object(out))
                 }
-                Err(e) => Err(format!("Failed to stat '{}': {}", path, e)),
+                Err(e) => Err(NativeError::io(&e, format!("Failed to stat '{}'", path)).into()),
             }
         } else {
             Err("stat expects a string path argument".to_string())
         }
     })));
 
+    fs_obj.insert("open".to_string(), Value::NativeFunction(Arc::new(|args| {
+        let (path, mode) = match args.as_slice() {
+            [Value::String(path)] => (path.clone(), "r".to_string()),
+            [Value::String(path), Value::String(mode)] => (path.clone(), mode.clone()),
+            _ => return Err("open expects a string path and an optional mode string".to_string()),
+        };
+        let Some(options) = OpenMode::parse(&mode) else {
+            return Err(format!("Unknown file mode '{}' (expected r, w, a, r+, w+ or a+)", mode));
+        };
+        match backend().open(&path, options) {
+            Ok(file) => Ok(file_handle(path, mode, file)),
+            Err(e) => Err(NativeError::io(&e, format!("Failed to open file '{}'", path)).into()),
+        }
+    })));
+
     env.declare("fs".to_string(), Value::Object(fs_obj), true);
 
     Ok(())
 }
+
+// Builds the handle object returned by `fs.open`. Its methods share the open
+// file through a `Value::Resource`; `close` (also exposed as `__close__` for
+// `with` blocks) releases it.
+fn file_handle(path: String, mode: String, file: Box<dyn FileLike>) -> Value {
+    let resource = NativeResource::new("file", BufReader::new(file));
+    let mut handle = IndexMap::new();
+    handle.insert("path".to_string(), Value::String(path));
+    handle.insert("mode".to_string(), Value::String(mode));
+
+    let file = resource.clone();
+    handle.insert("read".to_string(), Value::NativeFunction(Arc::new(move |args| {
+        let limit = match args.as_slice() {
+            [] => None,
+            [Value::Int(n)] if *n >= 0 => Some(*n as u64),
+            _ => return Err("read expects an optional non-negative byte count".to_string()),
+        };
+        file.with(|reader: &mut OpenFile| {
+            let mut bytes = Vec::new();
+            let read = match limit {
+                Some(n) => reader.by_ref().take(n).read_to_end(&mut bytes),
+                None => reader.read_to_end(&mut bytes),
+            };
+            read.map_err(|e| NativeError::io(&e, "Failed to read file"))?;
+            Ok(Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+        })
+    })));
+
+    let file = resource.clone();
+    handle.insert("read_line".to_string(), Value::NativeFunction(Arc::new(move |args| {
+        if !args.is_empty() {
+            return Err("read_line takes no arguments".to_string());
+        }
+        file.with(|reader: &mut OpenFile| {
+            let mut line = String::new();
+            reader
+                .read_line(&mut line)
+                .map_err(|e| NativeError::io(&e, "Failed to read line"))?;
+            Ok(Value::String(line))
+        })
+    })));
+
+    let file = resource.clone();
+    handle.insert("write".to_string(), Value::NativeFunction(Arc::new(move |args| {
+        let [Value::String(text)] = args.as_slice() else {
+            return Err("write expects a string argument".to_string());
+        };
+        file.with(|reader: &mut OpenFile| {
+            reader
+                .stream_position()
+                .and_then(|position| reader.seek(SeekFrom::Start(position)))
+                .and_then(|_| reader.get_mut().write_all(text.as_bytes()))
+                .map_err(|e| NativeError::io(&e, "Failed to write file"))?;
+            Ok(Value::Int(text.len() as i64))
+        })
+    })));
+
+    let file = resource.clone();
+    handle.insert("seek".to_string(), Value::NativeFunction(Arc::new(move |args| {
+        let target = match args.as_slice() {
+            [Value::Int(offset)] if *offset >= 0 => SeekFrom::Start(*offset as u64),
+            [Value::Int(offset), Value::String(whence)] => match whence.as_str() {
+                "start" if *offset >= 0 => SeekFrom::Start(*offset as u64),
+                "current" => SeekFrom::Current(*offset),
+                "end" => SeekFrom::End(*offset),
+                _ => return Err(format!("Invalid seek '{}' from '{}' (expected start, current or end)", offset, whence)),
+            },
+            _ => return Err("seek expects an int offset and an optional 'start', 'current' or 'end'".to_string()),
+        };
+        file.with(|reader: &mut OpenFile| match reader.seek(target) {
+            Ok(position) => Ok(Value::Int(position as i64)),
+            Err(e) => Err(NativeError::io(&e, "Failed to seek file").into()),
+        })
+    })));
+
+    let file = resource.clone();
+    handle.insert("flush".to_string(), Value::NativeFunction(Arc::new(move |_| {
+        file.with(|reader: &mut OpenFile| match reader.get_mut().flush() {
+            Ok(_) => Ok(Value::Void),
+            Err(e) => Err(NativeError::io(&e, "Failed to flush file").into()),
+        })
+    })));
+
+    let file = resource.clone();
+    let close = Value::NativeFunction(Arc::new(move |_| {
+        file.close();
+        Ok(Value::Void)
+    }));
+    handle.insert("close".to_string(), close.clone());
+    handle.insert("__close__".to_string(), close);
+
+    let file = resource.clone();
+    handle.insert("is_closed".to_string(), Value::NativeFunction(Arc::new(move |_| {
+        Ok(Value::Boolean(file.is_closed()))
+    })));
+
+    handle.insert("__resource__".to_string(), Value::Resource(resource));
+    Value::Object(handle)
+}
@@ -1,7 +1,11 @@
 use crate::environment::{Environment, Value};
+use super::common::Options;
 use hashbrown::HashMap;
+use indexmap::IndexMap;
 use std::sync::Arc;
 
+#[cfg(not(target_arch = "wasm32"))]
+use super::common::NativeError;
 #[cfg(not(target_arch = "wasm32"))]
 use std::io::Read;
 #[cfg(not(target_arch = "wasm32"))]
@@ -26,52 +30,22 @@ fn http_allowed() -> bool {
 }
 
 fn obj_from_pairs(pairs: Vec<(String, String)>) -> Value {
-    let mut obj = HashMap::with_capacity(pairs.len() + 1);
-    let mut keys = Vec::with_capacity(pairs.len());
-    for (k, v) in pairs {
-        keys.push(Value::String(k.clone()));
-        obj.insert(k, Value::String(v));
-    }
-    obj.insert("__keys__".to_string(), Value::Array(keys));
-    Value::Object(obj)
+    Value::Object(pairs.into_iter().map(|(k, v)| (k, Value::String(v))).collect())
 }
 
 fn obj_string_entries(v: &Value, name: &str) -> Result<Vec<(String, String)>, String> {
     match v {
         Value::Object(map) => {
-            let mut out: Vec<(String, String)> = Vec::new();
-            // Prefer stable ordering.
-            if let Some(Value::Array(keys)) = map.get("__keys__") {
-                for kv in keys {
-                    if let Value::String(k) = kv {
-                        if k == "__keys__" {
-                            continue;
-                        }
-                        if let Some(val) = map.get(k) {
-                            let s = match val {
-                                Value::String(s) => s.clone(),
-                                other => other.to_string(),
-                            };
-                            out.push((k.clone(), s));
-                        }
-                    }
-                }
-                return Ok(out);
-            }
-
-            // Fallback deterministic order.
-            let mut keys: Vec<&String> = map.keys().filter(|k| k.as_str() != "__keys__").collect();
-            keys.sort_unstable();
-            for k in keys {
-                if let Some(val) = map.get(k) {
+            Ok(map
+                .iter()
+                .map(|(k, val)| {
                     let s = match val {
                         Value::String(s) => s.clone(),
                         other => other.to_string(),
                     };
-                    out.push((k.clone(), s));
-                }
-            }
-            Ok(out)
+                    (k.clone(), s)
+                })
+                .collect())
         }
         _ => Err(format!("{name} expects an object of string values")),
     }
@@ -79,25 +53,12 @@ fn obj_string_entries(v: &Value, name: &str) -> Result<Vec<(String, String)>, St
 
 fn response_obj(url: String, status: i64, headers: Vec<(String, String)>, body: String) -> Value {
     let ok = status >= 200 && status < 300;
-    let mut obj = HashMap::with_capacity(6);
-    let mut keys = Vec::with_capacity(5);
-
-    keys.push(Value::String("url".to_string()));
+    let mut obj = IndexMap::with_capacity(5);
     obj.insert("url".to_string(), Value::String(url));
-
-    keys.push(Value::String("status".to_string()));
     obj.insert("status".to_string(), Value::Int(status));
-
-    keys.push(Value::String("ok".to_string()));
     obj.insert("ok".to_string(), Value::Boolean(ok));
-
-    keys.push(Value::String("headers".to_string()));
     obj.insert("headers".to_string(), obj_from_pairs(headers));
-
-    keys.push(Value::String("body".to_string()));
     obj.insert("body".to_string(), Value::String(body));
-
-    obj.insert("__keys__".to_string(), Value::Array(keys));
     Value::Object(obj)
 }
 
@@ -205,7 +166,8 @@ fn do_request(
     let response = match res {
         Ok(r) => r,
         Err(ureq::Error::Status(_code, r)) => r,
-        Err(e) => return Err(format!("http request failed: {}", e)),
+        // Nothing came back, so another attempt may get through.
+        Err(e) => return Err(NativeError::new("ENETWORK", format!("http request failed: {}", e)).retryable().into()),
     };
 
     let status = response.status() as i64;
@@ -300,22 +262,17 @@ fn parse_routes(v: &Value) -> Result<HashMap<String, Route>, String> {
 
     let mut out: HashMap<String, Route> = HashMap::new();
     for (k, val) in map {
-        if k == "__keys__" {
-            continue;
-        }
         let route = match val {
             Value::String(s) => Route::Plain(s.clone()),
-            Value::Object(obj) => {
-                let status = match obj.get("status") {
-                    Some(Value::Int(i)) => (*i).clamp(100, 599) as u16,
-                    _ => 200u16,
-                };
-                let body = match obj.get("body") {
+            Value::Object(_) => {
+                let route = Options::new("http.serve route", Some(val), &["status", "body", "headers"])?;
+                let status = route.get_int("status", 200, 100..=599)? as u16;
+                let body = match route.get_any("body") {
                     Some(Value::String(s)) => s.clone(),
                     Some(other) => other.to_string(),
                     None => String::new(),
                 };
-                let headers = match obj.get("headers") {
+                let headers = match route.get_any("headers") {
                     Some(h) => obj_string_entries(h, "http.serve routes.headers")?,
                     None => Vec::new(),
                 };
@@ -336,34 +293,18 @@ fn request_obj(
     headers: Vec<(String, String)>,
     body: String,
 ) -> Value {
-    let mut obj = HashMap::with_capacity(8);
-    let mut keys = Vec::with_capacity(7);
+    let mut obj = IndexMap::with_capacity(7);
 
     let path = url.split('?').next().unwrap_or(&url).to_string();
     let query = url.splitn(2, '?').nth(1).unwrap_or("").to_string();
 
-    keys.push(Value::String("id".to_string()));
     obj.insert("id".to_string(), Value::Int(id));
-
-    keys.push(Value::String("method".to_string()));
     obj.insert("method".to_string(), Value::String(method));
-
-    keys.push(Value::String("url".to_string()));
     obj.insert("url".to_string(), Value::String(url));
-
-    keys.push(Value::String("path".to_string()));
     obj.insert("path".to_string(), Value::String(path));
-
-    keys.push(Value::String("query".to_string()));
     obj.insert("query".to_string(), Value::String(query));
-
-    keys.push(Value::String("headers".to_string()));
     obj.insert("headers".to_string(), obj_from_pairs(headers));
-
-    keys.push(Value::String("body".to_string()));
     obj.insert("body".to_string(), Value::String(body));
-
-    obj.insert("__keys__".to_string(), Value::Array(keys));
     Value::Object(obj)
 }
 
@@ -412,8 +353,19 @@ fn response_from_value(v: &Value) -> (u16, Vec<(String, String)>, String) {
     }
 }
 
+pub const SIGNATURES: super::Signatures = &[
+    ("build_query", "|params: obj| -> string"),
+    ("parse_query", "|qs: string| -> obj"),
+    ("request", "|method: string, url: string, headers?: obj, body?: any, timeout_ms?: int| -> obj"),
+    ("get_json", "|url: string, headers?: obj, timeout_ms?: int| -> any"),
+    ("get", "|url: string, headers?: obj, timeout_ms?: int| -> obj"),
+    ("post", "|url: string, body: any, headers?: obj, timeout_ms?: int| -> obj"),
+    ("serve", "|addr: string, routes: obj| -> void"),
+    ("listen", "|addr: string| -> obj"),
+];
+
 pub fn register(env: &mut Environment) -> Result<(), String> {
-    let mut http_obj: HashMap<String, Value> = HashMap::new();
+    let mut http_obj: IndexMap<String, Value> = IndexMap::new();
 
     http_obj.insert("build_query".to_string(), Value::NativeFunction(Arc::new(|args| {
         let entries = match args.get(0) {
@@ -459,12 +411,12 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
         let mut timeout_ms: Option<i64> = None;
 
         if let Some(v) = args.get(2) {
-            if !matches!(v, Value::Void) {
+            if !matches!(v, Value::Void | Value::Null) {
                 headers = obj_string_entries(v, "http.request headers")?;
             }
         }
         if let Some(v) = args.get(3) {
-            if !matches!(v, Value::Void) {
+            if !matches!(v, Value::Void | Value::Null) {
                 body = Some(match v {
                     Value::String(s) => s.clone(),
                     other => other.to_string(),
@@ -530,7 +482,7 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
         };
         let body = match args.get(1) {
             Some(Value::String(s)) => Some(s.clone()),
-            Some(Value::Void) | None => None,
+            Some(Value::Void | Value::Null) | None => None,
             Some(other) => Some(other.to_string()),
         };
         let headers = match args.get(2) {
@@ -579,14 +531,14 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
                 pending: HashMap::new(),
             }));
 
-            let mut obj: HashMap<String, Value> = HashMap::with_capacity(6);
+            let mut obj: IndexMap<String, Value> = IndexMap::with_capacity(6);
 
             {
                 let st = state.clone();
                 obj.insert("accept".to_string(), Value::NativeFunction(Arc::new(move |args| {
                     let timeout_ms = match args.get(0) {
                         Some(Value::Int(ms)) => Some(*ms),
-                        Some(Value::Void) | None => None,
+                        Some(Value::Void | Value::Null) | None => None,
                         Some(_) => return Err("http_server.accept timeout_ms must be int".to_string()),
                     };
 
@@ -687,16 +639,12 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
                 Ok(Value::String(addr_str.clone()))
             })));
 
-            let mut keys: Vec<Value> = obj.keys().cloned().map(Value::String).collect();
-            keys.sort_by(|a, b| a.to_string().cmp(&b.to_string()));
-            obj.insert("__keys__".to_string(), Value::Array(keys));
+            obj.sort_keys();
             Ok(Value::Object(obj))
         }
     })));
 
-    let mut keys: Vec<Value> = http_obj.keys().cloned().map(Value::String).collect();
-    keys.sort_by(|a, b| a.to_string().cmp(&b.to_string()));
-    http_obj.insert("__keys__".to_string(), Value::Array(keys));
+    http_obj.sort_keys();
 
     env.declare("http".to_string(), Value::Object(http_obj), true);
     Ok(())
@@ -0,0 +1,479 @@
+//! Dense linear algebra behind `math.solve`, `math.lu`, `math.qr`,
+//! `math.eig` and `math.lstsq`, and the arithmetic operators on vectors and
+//! matrices.
+//!
+//! Matrices are rows of `f64`, as in `Value::Matrix`. Every function checks
+//! shapes first and names them in its errors, so a bad call reads like
+//! "math.solve: A is 2x3 but must be square".
+
+use crate::environment::Value;
+
+/// Row-major dense matrix, as stored in `Value::Matrix`.
+pub type Matrix = Vec<Vec<f64>>;
+
+/// Largest matrix `math.eig` accepts; the QR iteration it uses is only
+/// meant for small systems.
+pub const MAX_EIG_SIZE: usize = 16;
+
+// Pivots and off-diagonal entries smaller than this, relative to the
+// matrix's largest entry, count as zero.
+const EPSILON: f64 = 1e-12;
+
+const MAX_ITERATIONS: usize = 500;
+
+/// `rows x cols`, as printed in shape errors.
+pub fn shape(m: &Matrix) -> String {
+    format!("{}x{}", m.len(), m.first().map_or(0, Vec::len))
+}
+
+fn identity(n: usize) -> Matrix {
+    (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect()
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    let cols = m.first().map_or(0, Vec::len);
+    (0..cols).map(|j| m.iter().map(|row| row[j]).collect()).collect()
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let cols = b.first().map_or(0, Vec::len);
+    a.iter()
+        .map(|row| (0..cols).map(|j| row.iter().zip(b).map(|(x, b_row)| x * b_row[j]).sum()).collect())
+        .collect()
+}
+
+fn largest_entry(m: &Matrix) -> f64 {
+    m.iter().flatten().fold(0.0, |acc: f64, x| acc.max(x.abs()))
+}
+
+fn require_square(m: &Matrix, what: &str) -> Result<usize, String> {
+    if m.is_empty() || m.len() != m[0].len() {
+        return Err(format!("{} is {} but must be square", what, shape(m)));
+    }
+    Ok(m.len())
+}
+
+/// LU decomposition with partial pivoting: `p * a == l * u`, with `l` unit
+/// lower triangular and `p` a permutation matrix. Fails when `a` is
+/// singular.
+pub struct Lu {
+    pub l: Matrix,
+    pub u: Matrix,
+    pub p: Matrix,
+    // Row `i` of `p * a` is row `perm[i]` of `a`.
+    perm: Vec<usize>,
+}
+
+pub fn lu(a: &Matrix) -> Result<Lu, String> {
+    let n = require_square(a, "A")?;
+    let tolerance = largest_entry(a) * EPSILON;
+    let mut u = a.clone();
+    let mut l = identity(n);
+    let mut perm: Vec<usize> = (0..n).collect();
+    for k in 0..n {
+        let pivot = (k..n).max_by(|&i, &j| u[i][k].abs().total_cmp(&u[j][k].abs())).unwrap_or(k);
+        if u[pivot][k].abs() <= tolerance {
+            return Err("A is singular".to_string());
+        }
+        if pivot != k {
+            u.swap(pivot, k);
+            perm.swap(pivot, k);
+            let (upper, lower) = l.split_at_mut(pivot);
+            upper[k][..k].swap_with_slice(&mut lower[0][..k]);
+        }
+        for i in k + 1..n {
+            let factor = u[i][k] / u[k][k];
+            l[i][k] = factor;
+            let (upper, lower) = u.split_at_mut(i);
+            for (x, above) in lower[0][k..].iter_mut().zip(&upper[k][k..]) {
+                *x -= factor * above;
+            }
+        }
+    }
+    let p = perm.iter().map(|&from| (0..n).map(|j| if j == from { 1.0 } else { 0.0 }).collect()).collect();
+    Ok(Lu { l, u, p, perm })
+}
+
+impl Lu {
+    /// Solves `a * x == b` for each column of `b`.
+    fn solve(&self, b: &Matrix) -> Matrix {
+        let n = self.u.len();
+        let cols = b.first().map_or(0, Vec::len);
+        let mut x = vec![vec![0.0; cols]; n];
+        for c in 0..cols {
+            // Forward substitution: l * y == p * b.
+            let mut y = vec![0.0; n];
+            for i in 0..n {
+                y[i] = b[self.perm[i]][c] - (0..i).map(|j| self.l[i][j] * y[j]).sum::<f64>();
+            }
+            // Back substitution: u * x == y.
+            for i in (0..n).rev() {
+                let rest: f64 = (i + 1..n).map(|j| self.u[i][j] * x[j][c]).sum();
+                x[i][c] = (y[i] - rest) / self.u[i][i];
+            }
+        }
+        x
+    }
+}
+
+/// Solves `a * x == b`, where `b` has one column per right-hand side.
+pub fn solve(a: &Matrix, b: &Matrix) -> Result<Matrix, String> {
+    let n = require_square(a, "A")?;
+    if b.len() != n {
+        return Err(format!("A is {} but b has {} rows", shape(a), b.len()));
+    }
+    Ok(lu(a)?.solve(b))
+}
+
+/// Householder QR: `a == q * r` with `q` having orthonormal columns and `r`
+/// upper triangular. For an `m x n` matrix, `q` is `m x k` and `r` is
+/// `k x n`, where `k = min(m, n)`.
+pub fn qr(a: &Matrix) -> Result<(Matrix, Matrix), String> {
+    let m = a.len();
+    let n = a.first().map_or(0, Vec::len);
+    if m == 0 || n == 0 {
+        return Err(format!("A is {} and has no entries", shape(a)));
+    }
+    let mut r = a.clone();
+    let mut q = identity(m);
+    for k in 0..n.min(m.saturating_sub(1)) {
+        let norm = (k..m).map(|i| r[i][k] * r[i][k]).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            continue;
+        }
+        let alpha = if r[k][k] > 0.0 { -norm } else { norm };
+        let mut v: Vec<f64> = (k..m).map(|i| r[i][k]).collect();
+        v[0] -= alpha;
+        let v_norm = v.iter().map(|x| x * x).sum::<f64>();
+        if v_norm == 0.0 {
+            continue;
+        }
+        // r = (I - 2vv'/v'v) r and q = q (I - 2vv'/v'v), on rows/cols k.. only.
+        let dots: Vec<f64> = (0..n).map(|j| (k..m).map(|i| v[i - k] * r[i][j]).sum()).collect();
+        for (row, vi) in r[k..].iter_mut().zip(&v) {
+            for (x, dot) in row.iter_mut().zip(&dots) {
+                *x -= 2.0 * dot / v_norm * vi;
+            }
+        }
+        for row in q.iter_mut() {
+            let dot: f64 = (k..m).map(|i| row[i] * v[i - k]).sum();
+            let scale = 2.0 * dot / v_norm;
+            for i in k..m {
+                row[i] -= scale * v[i - k];
+            }
+        }
+    }
+    let k = m.min(n);
+    let q = q.into_iter().map(|row| row[..k].to_vec()).collect();
+    let mut r: Matrix = r.into_iter().take(k).collect();
+    for (i, row) in r.iter_mut().enumerate() {
+        for x in row.iter_mut().take(i) {
+            *x = 0.0;
+        }
+    }
+    Ok((q, r))
+}
+
+/// The `x` minimising `|a * x - b|`, for an `m x n` matrix `a` with
+/// `m >= n` and independent columns.
+pub fn lstsq(a: &Matrix, b: &Matrix) -> Result<Matrix, String> {
+    let m = a.len();
+    let n = a.first().map_or(0, Vec::len);
+    if m < n {
+        return Err(format!("A is {} but needs at least as many rows as columns", shape(a)));
+    }
+    if b.len() != m {
+        return Err(format!("A is {} but b has {} rows", shape(a), b.len()));
+    }
+    let (q, r) = qr(a)?;
+    let tolerance = largest_entry(a) * EPSILON;
+    if r.iter().enumerate().any(|(i, row)| row[i].abs() <= tolerance) {
+        return Err("the columns of A are not independent".to_string());
+    }
+    let qtb = multiply(&transpose(&q), b);
+    let cols = b.first().map_or(0, Vec::len);
+    let mut x = vec![vec![0.0; cols]; n];
+    for c in 0..cols {
+        for i in (0..n).rev() {
+            let rest: f64 = (i + 1..n).map(|j| r[i][j] * x[j][c]).sum();
+            x[i][c] = (qtb[i][c] - rest) / r[i][i];
+        }
+    }
+    Ok(x)
+}
+
+/// Eigenvalues in ascending order, with unit eigenvectors as the columns of
+/// the second matrix. Only real spectra are supported.
+pub fn eig(a: &Matrix) -> Result<(Vec<f64>, Matrix), String> {
+    let n = require_square(a, "A")?;
+    if n > MAX_EIG_SIZE {
+        return Err(format!("A is {} but eig supports at most {}x{}", shape(a), MAX_EIG_SIZE, MAX_EIG_SIZE));
+    }
+    let scale = largest_entry(a).max(1.0);
+    let symmetric = (0..n).all(|i| (0..i).all(|j| (a[i][j] - a[j][i]).abs() <= scale * EPSILON));
+    let (values, vectors) = if symmetric {
+        jacobi(a)
+    } else {
+        let values = qr_eigenvalues(a)?;
+        let vectors: Vec<Vec<f64>> = values.iter().map(|&value| inverse_iteration(a, value)).collect();
+        (values, transpose(&vectors))
+    };
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&i, &j| values[i].total_cmp(&values[j]));
+    let sorted_values = order.iter().map(|&i| values[i]).collect();
+    let sorted_vectors = vectors.iter().map(|row| order.iter().map(|&i| row[i]).collect()).collect();
+    Ok((sorted_values, sorted_vectors))
+}
+
+// Cyclic Jacobi rotations for a symmetric matrix: the diagonal converges to
+// the eigenvalues and the accumulated rotations to their eigenvectors.
+fn jacobi(a: &Matrix) -> (Vec<f64>, Matrix) {
+    let n = a.len();
+    let mut m = a.clone();
+    let mut vectors = identity(n);
+    let tolerance = largest_entry(a) * EPSILON;
+    for _ in 0..MAX_ITERATIONS {
+        let off: f64 = (0..n).flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j))).map(|(i, j)| m[i][j].abs()).sum();
+        if off <= tolerance {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                if m[p][q].abs() <= tolerance * EPSILON {
+                    continue;
+                }
+                let theta = (m[q][q] - m[p][p]) / (2.0 * m[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in m.iter_mut() {
+                    let (mkp, mkq) = (row[p], row[q]);
+                    row[p] = c * mkp - s * mkq;
+                    row[q] = s * mkp + c * mkq;
+                }
+                let (upper, lower) = m.split_at_mut(q);
+                for (mpk, mqk) in upper[p].iter_mut().zip(lower[0].iter_mut()) {
+                    let (x, y) = (*mpk, *mqk);
+                    *mpk = c * x - s * y;
+                    *mqk = s * x + c * y;
+                }
+                for row in vectors.iter_mut() {
+                    let (vp, vq) = (row[p], row[q]);
+                    row[p] = c * vp - s * vq;
+                    row[q] = s * vp + c * vq;
+                }
+            }
+        }
+    }
+    let values = (0..n).map(|i| m[i][i]).collect();
+    let columns: Vec<Vec<f64>> = transpose(&vectors).into_iter().map(normalized).collect();
+    (values, transpose(&columns))
+}
+
+// Shifted QR iteration with deflation. A 2x2 block that will not split is
+// solved directly, and refused if its eigenvalues are complex.
+fn qr_eigenvalues(a: &Matrix) -> Result<Vec<f64>, String> {
+    let mut h = a.clone();
+    let mut size = h.len();
+    let mut values = Vec::with_capacity(size);
+    let tolerance = largest_entry(a).max(1.0) * EPSILON;
+    let mut iterations = 0;
+    while size > 0 {
+        if size == 1 {
+            values.push(h[0][0]);
+            break;
+        }
+        let last = size - 1;
+        if h[last][last - 1].abs() <= tolerance {
+            values.push(h[last][last]);
+            size -= 1;
+            iterations = 0;
+            continue;
+        }
+        let (p, q, r, s) = (h[last - 1][last - 1], h[last - 1][last], h[last][last - 1], h[last][last]);
+        let half_trace = (p + s) / 2.0;
+        let discriminant = ((p - s) / 2.0).powi(2) + q * r;
+        let block_isolated = size == 2 || h[last - 1][last - 2].abs() <= tolerance;
+        if block_isolated || iterations >= MAX_ITERATIONS {
+            if discriminant < 0.0 {
+                return Err("A has complex eigenvalues, which eig does not support".to_string());
+            }
+            if !block_isolated {
+                return Err("eig did not converge".to_string());
+            }
+            let root = discriminant.sqrt();
+            values.push(half_trace - root);
+            values.push(half_trace + root);
+            size -= 2;
+            iterations = 0;
+            continue;
+        }
+        // Wilkinson shift: the eigenvalue of the trailing 2x2 block nearest
+        // its last entry, or the block's real part when the pair is complex.
+        let shift = if discriminant >= 0.0 {
+            let root = discriminant.sqrt();
+            if (half_trace + root - s).abs() < (half_trace - root - s).abs() { half_trace + root } else { half_trace - root }
+        } else {
+            half_trace
+        };
+        let mut active: Matrix = h[..size].iter().map(|row| row[..size].to_vec()).collect();
+        for (i, row) in active.iter_mut().enumerate() {
+            row[i] -= shift;
+        }
+        let (q, r) = qr(&active)?;
+        let next = multiply(&r, &q);
+        for i in 0..size {
+            for j in 0..size {
+                h[i][j] = next[i][j] + if i == j { shift } else { 0.0 };
+            }
+        }
+        iterations += 1;
+    }
+    Ok(values)
+}
+
+// The eigenvector for a known eigenvalue, by a few rounds of inverse
+// iteration on a slightly shifted matrix.
+fn inverse_iteration(a: &Matrix, value: f64) -> Vec<f64> {
+    let n = a.len();
+    let mut shift = value + value.abs().max(1.0) * 1e-10;
+    let factors = loop {
+        let mut shifted = a.clone();
+        for (i, row) in shifted.iter_mut().enumerate() {
+            row[i] -= shift;
+        }
+        match lu(&shifted) {
+            Ok(factors) => break factors,
+            Err(_) => shift += value.abs().max(1.0) * 1e-8,
+        }
+    };
+    let mut x = vec![vec![1.0]; n];
+    for _ in 0..4 {
+        let next = factors.solve(&x);
+        x = normalized(next.into_iter().map(|row| row[0]).collect()).into_iter().map(|v| vec![v]).collect();
+    }
+    x.into_iter().map(|row| row[0]).collect()
+}
+
+// Scales to unit length, with the largest entry made positive so results
+// do not flip sign between runs.
+fn normalized(v: Vec<f64>) -> Vec<f64> {
+    let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let largest = v.iter().copied().fold(0.0, |acc: f64, x| if x.abs() > acc.abs() { x } else { acc });
+    let sign = if largest < 0.0 { -1.0 } else { 1.0 };
+    if norm == 0.0 {
+        return v;
+    }
+    v.into_iter().map(|x| sign * x / norm).collect()
+}
+
+/// One side of a vector or matrix operator.
+#[derive(Clone, Copy)]
+enum Operand<'a> {
+    Scalar(f64),
+    Vector(&'a [f64]),
+    Matrix(&'a Matrix),
+}
+
+fn operand(value: &Value) -> Option<Operand<'_>> {
+    match value {
+        Value::Int(n) => Some(Operand::Scalar(*n as f64)),
+        Value::Float(x) => Some(Operand::Scalar(*x)),
+        Value::Vector(v) => Some(Operand::Vector(v)),
+        Value::Matrix(m) => Some(Operand::Matrix(m)),
+        _ => None,
+    }
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Vector(v) => format!("a vector of length {}", v.len()),
+        Value::Matrix(m) => format!("a {} matrix", shape(m)),
+        Value::Int(_) | Value::Float(_) => "a number".to_string(),
+        _ => "a non-number".to_string(),
+    }
+}
+
+/// Applies `f` to every entry, keeping the shape.
+fn map(value: Operand, f: impl Fn(f64) -> f64) -> Value {
+    match value {
+        Operand::Scalar(x) => Value::Float(f(x)),
+        Operand::Vector(v) => Value::Vector(v.iter().map(|x| f(*x)).collect()),
+        Operand::Matrix(m) => Value::Matrix(m.iter().map(|row| row.iter().map(|x| f(*x)).collect()).collect()),
+    }
+}
+
+/// `+ - * /` with a vector or matrix operand, and `==`/`!=` between two of
+/// the same kind. `+` and `-` go entry by entry and need equal shapes, or a
+/// number on one side that applies to every entry; so does `*` and `/` with
+/// a number. `*` between a matrix and a matrix or vector is the matrix
+/// product, with a vector on the right read as a column and on the left as a
+/// row. `None` when neither side is a vector or matrix, for `in`, and for `+`
+/// with a string, which still concatenates.
+pub fn binary(op: &str, left: &Value, right: &Value) -> Option<Result<Value, String>> {
+    let is_linear = |value: &Value| matches!(value, Value::Vector(_) | Value::Matrix(_));
+    if !is_linear(left) && !is_linear(right) {
+        return None;
+    }
+    if op == "in" || (op == "+" && (matches!(left, Value::String(_)) || matches!(right, Value::String(_)))) {
+        return None;
+    }
+    if op == "==" || op == "!=" {
+        let equal = match (left, right) {
+            (Value::Vector(a), Value::Vector(b)) => a == b,
+            (Value::Matrix(a), Value::Matrix(b)) => a == b,
+            _ => return None,
+        };
+        return Some(Ok(Value::Boolean(equal == (op == "=="))));
+    }
+    let verb = match op {
+        "+" => "add",
+        "-" => "subtract",
+        "*" => "multiply",
+        "/" => "divide",
+        _ => return Some(Err(format!("Operator '{}' is not supported for vectors and matrices", op))),
+    };
+    let mismatch = || Err(format!("Cannot {} {} and {}", verb, describe(left), describe(right)));
+    let (Some(l), Some(r)) = (operand(left), operand(right)) else {
+        return Some(mismatch());
+    };
+    Some(match (op, l, r) {
+        ("+" | "-", Operand::Vector(a), Operand::Vector(b)) if a.len() == b.len() => {
+            let sign = if op == "+" { 1.0 } else { -1.0 };
+            Ok(Value::Vector(a.iter().zip(b).map(|(x, y)| x + sign * y).collect()))
+        }
+        ("+" | "-", Operand::Matrix(a), Operand::Matrix(b)) if shape(a) == shape(b) => {
+            let sign = if op == "+" { 1.0 } else { -1.0 };
+            Ok(Value::Matrix(
+                a.iter().zip(b).map(|(x, y)| x.iter().zip(y).map(|(x, y)| x + sign * y).collect()).collect(),
+            ))
+        }
+        ("+", Operand::Scalar(n), other) | ("+", other, Operand::Scalar(n)) => Ok(map(other, |x| x + n)),
+        ("-", other, Operand::Scalar(n)) => Ok(map(other, |x| x - n)),
+        ("-", Operand::Scalar(n), other) => Ok(map(other, |x| n - x)),
+        ("*", Operand::Scalar(n), other) | ("*", other, Operand::Scalar(n)) => Ok(map(other, |x| x * n)),
+        ("*", Operand::Matrix(a), Operand::Matrix(b)) if columns(a) == b.len() => Ok(Value::Matrix(multiply(a, b))),
+        ("*", Operand::Matrix(a), Operand::Vector(v)) if columns(a) == v.len() => {
+            let column: Matrix = v.iter().map(|x| vec![*x]).collect();
+            Ok(Value::Vector(multiply(a, &column).into_iter().map(|row| row[0]).collect()))
+        }
+        ("*", Operand::Vector(v), Operand::Matrix(b)) if v.len() == b.len() => {
+            Ok(Value::Vector(multiply(&vec![v.to_vec()], b).into_iter().next().unwrap_or_default()))
+        }
+        ("*", Operand::Vector(_), Operand::Vector(_)) => {
+            Err("Cannot multiply two vectors; use math.dot for their dot product".to_string())
+        }
+        ("*", Operand::Matrix(_) | Operand::Vector(_), Operand::Matrix(_) | Operand::Vector(_)) => Err(format!(
+            "Cannot multiply {} by {}; the left side needs as many columns as the right has rows",
+            describe(left),
+            describe(right)
+        )),
+        ("/", _, Operand::Scalar(0.0)) => Err("Division by zero".to_string()),
+        ("/", other, Operand::Scalar(n)) => Ok(map(other, |x| x / n)),
+        _ => mismatch(),
+    })
+}
+
+fn columns(m: &Matrix) -> usize {
+    m.first().map_or(0, Vec::len)
+}
@@ -1,29 +1,89 @@
+use super::bigfloat;
+use super::linalg::{self, Matrix};
+use super::special;
 use crate::environment::{Environment, Value};
 use crate::ast::{*};
 use crate::lexer::{*};
-use hashbrown::HashMap;
+use indexmap::IndexMap;
 use std::f64::consts::{PI, E};
 #[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[cfg(target_arch = "wasm32")]
-fn random_unit() -> Result<f64, String> {
+pub(super) fn random_unit() -> Result<f64, String> {
     // WASM does not reliably support SystemTime/OS entropy in all environments.
     // Use the host-provided JS RNG for the demo/runtime.
     Ok(js_sys::Math::random())
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn random_unit() -> Result<f64, String> {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|e| format!("random time error: {}", e))?;
-    let nanos = now.subsec_nanos() as f64;
-    Ok(nanos / 1_000_000_000.0)
+static RANDOM_STATE: AtomicU64 = AtomicU64::new(0);
+
+// splitmix64, seeded from the clock on first use. Reading the clock on every
+// call made back-to-back samples nearly equal, which skews anything that
+// combines several draws, like a Box-Muller normal.
+#[cfg(not(target_arch = "wasm32"))]
+pub(super) fn random_unit() -> Result<f64, String> {
+    const GOLDEN: u64 = 0x9E37_79B9_7F4A_7C15;
+    if RANDOM_STATE.load(Ordering::Relaxed) == 0 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| format!("random time error: {}", e))?;
+        let seed = (now.as_nanos() as u64) | 1;
+        let _ = RANDOM_STATE.compare_exchange(0, seed, Ordering::Relaxed, Ordering::Relaxed);
+    }
+    let mut z = RANDOM_STATE.fetch_add(GOLDEN, Ordering::Relaxed).wrapping_add(GOLDEN);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    Ok((z >> 11) as f64 / (1u64 << 53) as f64)
 }
 
+pub const SIGNATURES: super::Signatures = &[
+    ("PI", "float"),
+    ("E", "float"),
+    ("I", "complex"),
+    ("sqrt", "|x: numeric| -> float"),
+    ("pow", "|base: numeric, exp: numeric| -> float"),
+    ("abs", "|x: numeric| -> numeric"),
+    ("sin", "|x: numeric| -> float"),
+    ("cos", "|x: numeric| -> float"),
+    ("tan", "|x: numeric| -> float"),
+    ("log", "|x: numeric, base?: numeric| -> float"),
+    ("exp", "|x: numeric| -> float"),
+    ("floor", "|x: numeric| -> float"),
+    ("ceil", "|x: numeric| -> float"),
+    ("round", "|x: numeric| -> float"),
+    ("min", "|a: numeric, b: numeric| -> numeric"),
+    ("max", "|a: numeric, b: numeric| -> numeric"),
+    ("clamp", "|x: numeric, min: numeric, max: numeric| -> numeric"),
+    ("random", "|| -> float"),
+    ("rand_int", "|min: int, max: int| -> int"),
+    ("rand_choice", "|arr: arr| -> any"),
+    ("shuffle", "|arr: arr| -> arr"),
+    ("atan2", "|y: numeric, x: numeric| -> float"),
+    ("vector", "|arr: arr| -> arr"),
+    ("dot", "|a: arr, b: arr| -> float"),
+    ("matrix", "|arr: arr| -> arr"),
+    ("matmul", "|a: arr, b: arr| -> arr"),
+    ("solve", "|a: arr, b: arr| -> arr"),
+    ("lu", "|a: arr| -> obj"),
+    ("qr", "|a: arr| -> obj"),
+    ("eig", "|a: arr| -> obj"),
+    ("lstsq", "|a: arr, b: arr| -> arr"),
+    ("bigfloat", "|value: string, precision?: int| -> obj"),
+    ("erf", "|x: numeric| -> float"),
+    ("erfc", "|x: numeric| -> float"),
+    ("gamma", "|x: numeric| -> float"),
+    ("normal_cdf", "|x: numeric, mu?: numeric, sigma?: numeric| -> float"),
+    ("binomial_cdf", "|k: int, n: int, p: numeric| -> float"),
+    ("poisson_cdf", "|k: int, lambda: numeric| -> float"),
+];
+
 pub fn register(env: &mut Environment) -> Result<(), String> {
-    let mut math_obj = HashMap::new();
+    let mut math_obj = IndexMap::new();
 
     fn create_param(name: &str, type_: DataType) -> Param {
         Param {
@@ -415,86 +475,261 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
         }
     })));
     
-    // Matrix multiplication: matmul(a, b)
+    // Matrix multiplication: matmul(a, b). Two `#[[...]]` matrices give a
+    // matrix; anything else gives an array of arrays.
     math_obj.insert("matmul".to_string(), Value::NativeFunction(Arc::new(|args| {
         if args.len() != 2 {
             return Err("matmul expects exactly two arguments".to_string());
         }
-        let a = match &args[0] {
-            Value::Array(rows) => rows,
-            _ => return Err("matmul expects both arguments to be matrices (array of arrays)".to_string()),
+        let a = matmul_operand(&args[0], "first")?;
+        let b = matmul_operand(&args[1], "second")?;
+
+        // Check dimensions
+        let a_cols = a[0].len();
+        let b_cols = b[0].len();
+        if a_cols != b.len() {
+            return Err(format!(
+                "matmul: cannot multiply {} by {}; the first matrix needs as many columns as the second has rows",
+                linalg::shape(&a),
+                linalg::shape(&b)
+            ));
+        }
+
+        // Perform multiplication
+        let result: Vec<Vec<f64>> = a
+            .iter()
+            .map(|a_row| (0..b_cols).map(|j| (0..a_cols).map(|k| a_row[k] * b[k][j]).sum()).collect())
+            .collect();
+        if let (Value::Matrix(_), Value::Matrix(_)) = (&args[0], &args[1]) {
+            Ok(Value::Matrix(result))
+        } else {
+            Ok(Value::Array(
+                result.into_iter().map(|row| Value::Array(row.into_iter().map(Value::Float).collect())).collect(),
+            ))
+        }
+    })));
+
+    // Linear algebra on matrices (`#[[...]]` or arrays of arrays). A vector
+    // right-hand side gives a vector back; a matrix one gives a matrix.
+    math_obj.insert("solve".to_string(), Value::NativeFunction(Arc::new(|args| {
+        let [a, b] = args.as_slice() else {
+            return Err("solve expects a matrix A and a right-hand side b".to_string());
         };
-        let b = match &args[1] {
-            Value::Array(rows) => rows,
-            _ => return Err("matmul expects both arguments to be matrices (array of arrays)".to_string()),
+        let (b, column) = rhs_arg(b, "solve")?;
+        let x = linalg::solve(&matrix_arg(a, "solve", "A")?, &b).map_err(|e| format!("math.solve: {}", e))?;
+        Ok(rhs_result(x, column))
+    })));
+
+    math_obj.insert("lstsq".to_string(), Value::NativeFunction(Arc::new(|args| {
+        let [a, b] = args.as_slice() else {
+            return Err("lstsq expects a matrix A and a right-hand side b".to_string());
         };
-    
-        // Check dimensions
-        let a_rows = a.len();
-        let a_cols = match a.get(0) {
-            Some(Value::Array(cols)) => cols.len(),
-            _ => return Err("matmul: first matrix is empty or not a matrix".to_string()),
+        let (b, column) = rhs_arg(b, "lstsq")?;
+        let x = linalg::lstsq(&matrix_arg(a, "lstsq", "A")?, &b).map_err(|e| format!("math.lstsq: {}", e))?;
+        Ok(rhs_result(x, column))
+    })));
+
+    math_obj.insert("lu".to_string(), Value::NativeFunction(Arc::new(|args| {
+        let [a] = args.as_slice() else {
+            return Err("lu expects exactly one matrix".to_string());
         };
-        let b_rows = b.len();
-        let b_cols = match b.get(0) {
-            Some(Value::Array(cols)) => cols.len(),
-            _ => return Err("matmul: second matrix is empty or not a matrix".to_string()),
+        let factors = linalg::lu(&matrix_arg(a, "lu", "A")?).map_err(|e| format!("math.lu: {}", e))?;
+        let mut out = IndexMap::with_capacity(3);
+        out.insert("l".to_string(), Value::Matrix(factors.l.clone()));
+        out.insert("u".to_string(), Value::Matrix(factors.u.clone()));
+        out.insert("p".to_string(), Value::Matrix(factors.p.clone()));
+        Ok(Value::Object(out))
+    })));
+
+    math_obj.insert("qr".to_string(), Value::NativeFunction(Arc::new(|args| {
+        let [a] = args.as_slice() else {
+            return Err("qr expects exactly one matrix".to_string());
         };
-    
-        if a_cols != b_rows {
-            return Err("matmul: number of columns in first matrix must equal number of rows in second matrix".to_string());
+        let (q, r) = linalg::qr(&matrix_arg(a, "qr", "A")?).map_err(|e| format!("math.qr: {}", e))?;
+        let mut out = IndexMap::with_capacity(2);
+        out.insert("q".to_string(), Value::Matrix(q));
+        out.insert("r".to_string(), Value::Matrix(r));
+        Ok(Value::Object(out))
+    })));
+
+    math_obj.insert("eig".to_string(), Value::NativeFunction(Arc::new(|args| {
+        let [a] = args.as_slice() else {
+            return Err("eig expects exactly one matrix".to_string());
+        };
+        let (values, vectors) = linalg::eig(&matrix_arg(a, "eig", "A")?).map_err(|e| format!("math.eig: {}", e))?;
+        let mut out = IndexMap::with_capacity(2);
+        out.insert("values".to_string(), Value::Vector(values));
+        out.insert("vectors".to_string(), Value::Matrix(vectors));
+        Ok(Value::Object(out))
+    })));
+
+    // Arbitrary precision: math.bigfloat(value, precision?)
+    math_obj.insert("bigfloat".to_string(), Value::NativeFunction(Arc::new(|args| bigfloat::construct(&args))));
+
+    // Special functions and distribution CDFs
+    math_obj.insert("erf".to_string(), Value::NativeFunction(Arc::new(|args: Vec<Value>| match args.as_slice() {
+        [x] => Ok(Value::Float(special::erf(number_arg(x, "erf", "x")?))),
+        _ => Err("erf expects exactly one argument".to_string()),
+    })));
+
+    math_obj.insert("erfc".to_string(), Value::NativeFunction(Arc::new(|args: Vec<Value>| match args.as_slice() {
+        [x] => Ok(Value::Float(special::erfc(number_arg(x, "erfc", "x")?))),
+        _ => Err("erfc expects exactly one argument".to_string()),
+    })));
+
+    math_obj.insert("gamma".to_string(), Value::NativeFunction(Arc::new(|args: Vec<Value>| match args.as_slice() {
+        [x] => {
+            let x = number_arg(x, "gamma", "x")?;
+            special::gamma(x)
+                .map(Value::Float)
+                .ok_or_else(|| format!("gamma is undefined at {}", x))
+        }
+        _ => Err("gamma expects exactly one argument".to_string()),
+    })));
+
+    math_obj.insert("normal_cdf".to_string(), Value::NativeFunction(Arc::new(|args: Vec<Value>| {
+        let (x, mu, sigma) = match args.as_slice() {
+            [x] => (x, 0.0, 1.0),
+            [x, mu, sigma] => (x, number_arg(mu, "normal_cdf", "mu")?, number_arg(sigma, "normal_cdf", "sigma")?),
+            _ => return Err("normal_cdf expects x, or x, mu and sigma".to_string()),
+        };
+        if sigma <= 0.0 {
+            return Err("normal_cdf expects sigma > 0".to_string());
         }
-    
-        // Perform multiplication
-        let mut result = Vec::with_capacity(a_rows);
-        for i in 0..a_rows {
-            let mut row = Vec::with_capacity(b_cols);
-            let a_row = match &a[i] {
-                Value::Array(cols) => cols,
-                _ => return Err("matmul: first matrix is not well-formed".to_string()),
-            };
-            for j in 0..b_cols {
-                let mut sum = 0.0;
-                for k in 0..a_cols {
-                    let a_val = match &a_row[k] {
-                        Value::Int(x) => *x as f64,
-                        Value::Float(x) => *x,
-                        _ => return Err("matmul: matrix elements must be numbers".to_string()),
-                    };
-                    let b_col = match &b[k] {
-                        Value::Array(cols) => cols,
-                        _ => return Err("matmul: second matrix is not well-formed".to_string()),
-                    };
-                    let b_val = match &b_col[j] {
-                        Value::Int(x) => *x as f64,
-                        Value::Float(x) => *x,
-                        _ => return Err("matmul: matrix elements must be numbers".to_string()),
-                    };
-                    sum += a_val * b_val;
-                }
-                row.push(Value::Float(sum));
-            }
-            result.push(Value::Array(row));
+        Ok(Value::Float(special::normal_cdf(number_arg(x, "normal_cdf", "x")?, mu, sigma)))
+    })));
+
+    math_obj.insert("binomial_cdf".to_string(), Value::NativeFunction(Arc::new(|args: Vec<Value>| {
+        let [Value::Int(k), Value::Int(n), p] = args.as_slice() else {
+            return Err("binomial_cdf expects an int k, an int n and a probability p".to_string());
+        };
+        let p = probability_arg(p, "binomial_cdf")?;
+        if *n < 0 {
+            return Err("binomial_cdf expects n >= 0".to_string());
         }
-        Ok(Value::Array(result))
+        Ok(Value::Float(special::binomial_cdf(*k, *n, p)))
     })));
 
-    // Register either full module or specific imports
-    if let Some(Value::Array(methods)) = env.lookup("__IMPORT_METHODS__") {
-        // Specific imports
-        for method in methods {
-            if let Value::String(name) = method {
-                if let Some(value) = math_obj.get(&name) {
-                    env.declare(name, value.clone(), true);
-                } else {
-                    return Err(format!("Math module error: '{}' not found", name));
-                }
-            }
+    math_obj.insert("poisson_cdf".to_string(), Value::NativeFunction(Arc::new(|args: Vec<Value>| {
+        let [Value::Int(k), lambda] = args.as_slice() else {
+            return Err("poisson_cdf expects an int k and a rate lambda".to_string());
+        };
+        let lambda = number_arg(lambda, "poisson_cdf", "lambda")?;
+        if lambda < 0.0 {
+            return Err("poisson_cdf expects lambda >= 0".to_string());
         }
+        Ok(Value::Float(special::poisson_cdf(*k, lambda)))
+    })));
+
+    env.declare("math".to_string(), Value::Object(math_obj), true);
+
+    Ok(())
+}
+
+/// A numeric argument as a float, or an error naming `func` and `name`.
+pub(super) fn number_arg(value: &Value, func: &str, name: &str) -> Result<f64, String> {
+    match value {
+        Value::Int(x) => Ok(*x as f64),
+        Value::Float(x) => Ok(*x),
+        _ => Err(format!("{} expects {} to be a number", func, name)),
+    }
+}
+
+/// A number in `[0, 1]`.
+pub(super) fn probability_arg(value: &Value, func: &str) -> Result<f64, String> {
+    let p = number_arg(value, func, "p")?;
+    if (0.0..=1.0).contains(&p) {
+        Ok(p)
     } else {
-        // Full module import
-        env.declare("math".to_string(), Value::Object(math_obj), true);
+        Err(format!("{} expects p between 0 and 1, got {}", func, p))
     }
+}
 
-    Ok(())
+// The rows of a matmul argument: a matrix, or an array of equally long
+// arrays of numbers.
+fn matmul_operand(value: &Value, which: &str) -> Result<Vec<Vec<f64>>, String> {
+    let rows = match value {
+        Value::Matrix(rows) => rows.clone(),
+        Value::Array(rows) => rows
+            .iter()
+            .map(|row| match row {
+                Value::Array(cols) => cols
+                    .iter()
+                    .map(|v| match v {
+                        Value::Int(x) => Ok(*x as f64),
+                        Value::Float(x) => Ok(*x),
+                        _ => Err("matmul: matrix elements must be numbers".to_string()),
+                    })
+                    .collect(),
+                _ => Err(format!("matmul: {} matrix is not well-formed", which)),
+            })
+            .collect::<Result<_, _>>()?,
+        _ => return Err("matmul expects both arguments to be matrices (array of arrays)".to_string()),
+    };
+    match rows.first() {
+        Some(first) if !first.is_empty() && rows.iter().all(|row| row.len() == first.len()) => Ok(rows),
+        Some(first) if !first.is_empty() => Err(format!("matmul: {} matrix is not well-formed", which)),
+        _ => Err(format!("matmul: {} matrix is empty or not a matrix", which)),
+    }
+}
+
+fn number_row(values: &[Value]) -> Option<Vec<f64>> {
+    values
+        .iter()
+        .map(|v| match v {
+            Value::Int(x) => Some(*x as f64),
+            Value::Float(x) => Some(*x),
+            _ => None,
+        })
+        .collect()
+}
+
+// A matrix argument: a `#[[...]]` matrix, or an array of equally long
+// arrays of numbers.
+fn matrix_arg(value: &Value, func: &str, name: &str) -> Result<Matrix, String> {
+    let invalid = || format!("math.{}: {} must be a matrix of numbers", func, name);
+    let rows = match value {
+        Value::Matrix(rows) => rows.clone(),
+        Value::Array(rows) => rows
+            .iter()
+            .map(|row| match row {
+                Value::Array(cols) => number_row(cols).ok_or_else(invalid),
+                Value::Vector(cols) => Ok(cols.clone()),
+                _ => Err(invalid()),
+            })
+            .collect::<Result<_, _>>()?,
+        _ => return Err(invalid()),
+    };
+    if let Some(i) = rows.iter().position(|row| row.len() != rows[0].len()) {
+        return Err(format!(
+            "math.{}: row {} of {} has {} entries, expected {}",
+            func,
+            i + 1,
+            name,
+            rows[i].len(),
+            rows[0].len()
+        ));
+    }
+    Ok(rows)
+}
+
+// The right-hand side of a solve, as columns, and whether it was a single
+// vector rather than a matrix.
+fn rhs_arg(value: &Value, func: &str) -> Result<(Matrix, bool), String> {
+    let column = |v: &[f64]| v.iter().map(|x| vec![*x]).collect();
+    match value {
+        Value::Vector(v) => Ok((column(v), true)),
+        Value::Array(items) if items.iter().all(|item| matches!(item, Value::Int(_) | Value::Float(_))) => {
+            Ok((column(&number_row(items).unwrap_or_default()), true))
+        }
+        other => Ok((matrix_arg(other, func, "b")?, false)),
+    }
+}
+
+fn rhs_result(x: Matrix, column: bool) -> Value {
+    if column {
+        Value::Vector(x.into_iter().map(|row| row[0]).collect())
+    } else {
+        Value::Matrix(x)
+    }
 }
@@ -1,44 +1,111 @@
 #![allow(dead_code)]
 
+pub mod arrays;
+pub mod assert;
+pub mod common;
 pub mod math;
 pub mod fs;
 pub mod os;
 pub mod path;
 pub mod encoding;
 pub mod http;
+pub mod semver;
+pub mod strings;
+pub mod decimal;
+pub mod units;
+pub mod linalg;
+pub mod bigfloat;
+pub mod calc;
+pub mod special;
+pub mod random;
+pub mod vfs;
 
 use hashbrown::HashMap;
-use std::sync::OnceLock;
-use crate::environment::Environment;
+use indexmap::IndexMap;
+use std::sync::{Arc, OnceLock};
+use crate::environment::{Environment, Value};
 use crate::errors::ZekkenError;
 
 // Type alias for library registration functions
 type LibraryFunction = fn(&mut Environment) -> Result<(), String>;
 
+/// `(member, signature)` pairs describing what a library registers, for
+/// editor tooling. Functions read like `|x: numeric| -> float`, constants
+/// give just their type.
+pub type Signatures = &'static [(&'static str, &'static str)];
+
+struct Library {
+    register: LibraryFunction,
+    signatures: Signatures,
+    /// The library object, built by the first `use` and shared by the rest.
+    loaded: OnceLock<Arc<IndexMap<String, Value>>>,
+}
+
+impl Library {
+    fn new(register: LibraryFunction, signatures: Signatures) -> Self {
+        Library { register, signatures, loaded: OnceLock::new() }
+    }
+}
+
 // Global registry of available libraries
-static LIBRARIES: OnceLock<HashMap<&'static str, LibraryFunction>> = OnceLock::new();
+static LIBRARIES: OnceLock<HashMap<&'static str, Library>> = OnceLock::new();
 
 // Initialize the standard library registry
-fn init_libraries() -> HashMap<&'static str, LibraryFunction> {
-    let mut map: HashMap<&'static str, LibraryFunction> = HashMap::new();
+fn init_libraries() -> HashMap<&'static str, Library> {
+    let mut map: HashMap<&'static str, Library> = HashMap::new();
     
     // Register standard libraries
-    map.insert("math", math::register);
-    map.insert("fs", fs::register);
-    map.insert("os", os::register);
-    map.insert("path", path::register);
-    map.insert("encoding", encoding::register);
-    map.insert("http", http::register);
+    map.insert("math", Library::new(math::register, math::SIGNATURES));
+    map.insert("fs", Library::new(fs::register, fs::SIGNATURES));
+    map.insert("os", Library::new(os::register, os::SIGNATURES));
+    map.insert("path", Library::new(path::register, path::SIGNATURES));
+    map.insert("encoding", Library::new(encoding::register, encoding::SIGNATURES));
+    map.insert("http", Library::new(http::register, http::SIGNATURES));
+    map.insert("semver", Library::new(semver::register, semver::SIGNATURES));
+    map.insert("strings", Library::new(strings::register, strings::SIGNATURES));
+    map.insert("decimal", Library::new(decimal::register, decimal::SIGNATURES));
+    map.insert("units", Library::new(units::register, units::SIGNATURES));
+    map.insert("calc", Library::new(calc::register, calc::SIGNATURES));
+    map.insert("random", Library::new(random::register, random::SIGNATURES));
+    map.insert("assert", Library::new(assert::register, assert::SIGNATURES));
+    map.insert("arrays", Library::new(arrays::register, arrays::SIGNATURES));
     
     map
 }
 
-// Load and initialize a library by name
+/// Every library `use` can load, sorted by name.
+pub fn library_names() -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = LIBRARIES.get_or_init(init_libraries).keys().copied().collect();
+    names.sort_unstable();
+    names
+}
+
+/// The documented signatures of `library`'s members.
+pub fn signatures(library: &str) -> Signatures {
+    LIBRARIES.get_or_init(init_libraries).get(library).map_or(&[], |lib| lib.signatures)
+}
+
+// Load and initialize a library by name. Each library object is built once
+// per process and shared by every scope that uses it; a scope only copies
+// it when it assigns to one of its members.
 pub fn load_library(library: &str, env: &mut Environment) -> Result<(), ZekkenError> {
     let registry = LIBRARIES.get_or_init(init_libraries);
-    if let Some(register_fn) = registry.get(library) {
-        register_fn(env).map_err(|e| ZekkenError::internal(&format!("Failed to load library '{}': {}", library, e)))
-    } else {
-        Err(ZekkenError::internal(&format!("Library '{}' not found", library)))
-    }
+    let Some(lib) = registry.get(library) else {
+        return Err(ZekkenError::internal(&format!("Library '{}' not found", library)));
+    };
+    let members = match lib.loaded.get() {
+        Some(members) => members.clone(),
+        None => {
+            let mut scratch = Environment::new_scope();
+            (lib.register)(&mut scratch)
+                .map_err(|e| ZekkenError::internal(&format!("Failed to load library '{}': {}", library, e)))?;
+            let members = match scratch.lookup(library) {
+                Some(Value::Object(members)) => Arc::new(members),
+                _ => return Err(ZekkenError::internal(&format!("Library '{}' did not declare itself", library))),
+            };
+            lib.loaded.get_or_init(|| members).clone()
+        }
+    };
+    env.declare(library.to_string(), Value::Library(members), true);
+    Ok(())
 }
@@ -1,8 +1,9 @@
 use crate::environment::{Environment, Value};
-use hashbrown::HashMap;
+use super::common::{NativeError, Options};
+use indexmap::IndexMap;
 use std::env as std_env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 #[cfg(not(target_arch = "wasm32"))]
 use std::process::Command;
@@ -22,14 +23,181 @@ fn shell_execution_allowed() -> bool {
     }
 }
 
+/// Whether `path` is a file this process could run.
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+/// Where `cmd` resolves to the way a shell would find it: as given when it
+/// names a path, otherwise the first match in a `PATH` directory. On
+/// Windows a name without an extension also tries each `PATHEXT` one.
+fn find_executable(cmd: &str) -> Option<PathBuf> {
+    let extensions: Vec<String> = if cfg!(windows) && Path::new(cmd).extension().is_none() {
+        std_env::var("PATHEXT")
+            .unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string())
+            .split(';')
+            .filter(|ext| !ext.is_empty())
+            .map(|ext| format!("{}{}", cmd, ext))
+            .collect()
+    } else {
+        vec![cmd.to_string()]
+    };
+
+    if cmd.contains('/') || cmd.contains('\\') {
+        return extensions.iter().map(PathBuf::from).find(|p| is_executable(p));
+    }
+    let path_env = std_env::var_os("PATH").unwrap_or_default();
+    std_env::split_paths(&path_env)
+        .flat_map(|dir| extensions.iter().map(move |name| dir.join(name)))
+        .find(|candidate| is_executable(candidate))
+}
+
+/// What `os.exec` accepts after the command and its arguments.
+#[derive(Default)]
+struct ExecOptions<'a> {
+    cwd: Option<&'a str>,
+    /// Variables to set, or to remove when `None`, on top of ours.
+    env: Vec<(&'a str, Option<&'a str>)>,
+    stdin: Option<&'a str>,
+    timeout_ms: Option<u64>,
+    inherit_stdio: bool,
+}
+
+impl<'a> ExecOptions<'a> {
+    const KEYS: &'static [&'static str] = &["cwd", "env", "stdin", "timeout", "inherit_stdio"];
+
+    fn parse(options: &Options<'a>) -> Result<Self, String> {
+        Ok(ExecOptions {
+            cwd: options.opt_string("cwd")?,
+            env: options.string_map("env")?,
+            stdin: options.opt_string("stdin")?,
+            timeout_ms: options.opt_int("timeout", 0..=i64::MAX)?.map(|ms| ms as u64),
+            inherit_stdio: options.get_bool("inherit_stdio", false)?,
+        })
+    }
+}
+
+/// Runs `command` to completion, or until `timeout_ms` passes and it is
+/// killed, and describes how it went.
+#[cfg(not(target_arch = "wasm32"))]
+fn exec(command: &str, args: &[String], options: &ExecOptions) -> std::io::Result<Value> {
+    use std::io::{Read, Write};
+    use std::process::Stdio;
+    use std::time::{Duration, Instant};
+
+    let mut cmd = Command::new(command);
+    cmd.args(args);
+    if let Some(cwd) = options.cwd {
+        cmd.current_dir(cwd);
+    }
+    for (key, value) in &options.env {
+        match value {
+            Some(value) => cmd.env(key, value),
+            None => cmd.env_remove(key),
+        };
+    }
+    let output = || if options.inherit_stdio { Stdio::inherit() } else { Stdio::piped() };
+    cmd.stdin(if options.stdin.is_some() { Stdio::piped() } else if options.inherit_stdio { Stdio::inherit() } else { Stdio::null() })
+        .stdout(output())
+        .stderr(output());
+
+    let started = Instant::now();
+    let mut child = cmd.spawn()?;
+    // Feed stdin and drain the pipes on their own threads, so a child that
+    // fills one pipe while we wait on another cannot deadlock.
+    let feeder = options.stdin.map(|input| {
+        let mut pipe = child.stdin.take();
+        let input = input.as_bytes().to_vec();
+        std::thread::spawn(move || {
+            if let Some(pipe) = pipe.as_mut() {
+                let _ = pipe.write_all(&input);
+            }
+        })
+    });
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        std::thread::spawn(move || {
+            let mut out = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut out);
+            }
+            out
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+
+    let mut timed_out = false;
+    let status = match options.timeout_ms {
+        None => child.wait()?,
+        Some(limit) => loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if started.elapsed() >= Duration::from_millis(limit) {
+                timed_out = true;
+                let _ = child.kill();
+                break child.wait()?;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        },
+    };
+    if let Some(feeder) = feeder {
+        let _ = feeder.join();
+    }
+    let text = |reader: std::thread::JoinHandle<Vec<u8>>| Value::String(String::from_utf8_lossy(&reader.join().unwrap_or_default()).to_string());
+
+    let mut result = IndexMap::new();
+    result.insert("status".to_string(), Value::Int(status.code().unwrap_or(-1) as i64));
+    result.insert("stdout".to_string(), text(stdout));
+    result.insert("stderr".to_string(), text(stderr));
+    result.insert("ok".to_string(), Value::Boolean(status.success() && !timed_out));
+    result.insert("timed_out".to_string(), Value::Boolean(timed_out));
+    result.insert("duration_ms".to_string(), Value::Int(started.elapsed().as_millis() as i64));
+    Ok(Value::Object(result))
+}
+
+pub const SIGNATURES: super::Signatures = &[
+    ("platform", "|| -> string"),
+    ("arch", "|| -> string"),
+    ("cwd", "|| -> string"),
+    ("args", "|| -> arr"),
+    ("env", "|key: string| -> string"),
+    ("set_env", "|key: string, value: string| -> void"),
+    ("remove_env", "|key: string| -> void"),
+    ("home_dir", "|| -> string"),
+    ("temp_dir", "|| -> string"),
+    ("hostname", "|| -> string"),
+    ("username", "|| -> string"),
+    ("pid", "|| -> int"),
+    ("cpu_count", "|| -> int"),
+    ("uptime_ms", "|| -> int"),
+    ("sleep", "|ms: int| -> void"),
+    ("ls", "|path?: string| -> arr"),
+    ("which", "|cmd: string| -> string"),
+    ("path_separator", "string"),
+    ("line_ending", "string"),
+    ("exec", "|cmd: string, args?: arr, options?: obj| -> obj"),
+    ("system", "|cmd: string, args?: arr| -> int"),
+    ("spawn", "|cmd: string, args?: arr| -> int"),
+    ("exit", "|code?: int| -> void"),
+];
+
 pub fn register(env: &mut Environment) -> Result<(), String> {
-    let mut os_obj = HashMap::new();
+    let mut os_obj = IndexMap::new();
 
     // Get current working directory
     os_obj.insert("cwd".to_string(), Value::NativeFunction(Arc::new(|_args| {
         std_env::current_dir()
             .map(|p| Value::String(p.display().to_string()))
-            .map_err(|e| format!("Failed to get current directory: {}", e))
+            .map_err(|e| NativeError::io(&e, "Failed to get current directory").into())
     })));
 
     // List files in a directory
@@ -39,15 +207,16 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
         } else {
             "."
         };
-        match fs::read_dir(path) {
+        match super::vfs::backend().list(path) {
             Ok(entries) => {
                 let files: Vec<Value> = entries
-                    .filter_map(|entry| entry.ok())
-                    .map(|entry| Value::String(entry.file_name().to_string_lossy().to_string()))
+                    .iter()
+                    .filter_map(|entry| std::path::Path::new(entry).file_name())
+                    .map(|name| Value::String(name.to_string_lossy().to_string()))
                     .collect();
                 Ok(Value::Array(files))
             }
-            Err(e) => Err(format!("Failed to list directory '{}': {}", path, e)),
+            Err(e) => Err(NativeError::io(&e, format!("Failed to list directory '{}'", path)).into()),
         }
     })));
 
@@ -156,26 +325,14 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
             Some(Value::String(s)) => s.as_str(),
             _ => return Err("which expects a command string".to_string()),
         };
+        Ok(Value::String(find_executable(cmd).map(|p| p.to_string_lossy().to_string()).unwrap_or_default()))
+    })));
 
-        let contains_sep = cmd.contains('/') || cmd.contains('\\');
-        if contains_sep {
-            let p = std::path::Path::new(cmd);
-            if p.exists() && p.is_file() {
-                return Ok(Value::String(p.to_string_lossy().to_string()));
-            }
-            return Ok(Value::String(String::new()));
-        }
-
-        let path_env = std_env::var_os("PATH").unwrap_or_default();
-        for dir in std_env::split_paths(&path_env) {
-            let candidate: PathBuf = dir.join(cmd);
-            if candidate.exists() && candidate.is_file() {
-                return Ok(Value::String(candidate.to_string_lossy().to_string()));
-            }
-        }
+    // Separator between the directories in PATH
+    os_obj.insert("path_separator".to_string(), Value::String(if cfg!(windows) { ";" } else { ":" }.to_string()));
 
-        Ok(Value::String(String::new()))
-    })));
+    // Line ending native text files use
+    os_obj.insert("line_ending".to_string(), Value::String(if cfg!(windows) { "\r\n" } else { "\n" }.to_string()));
 
     // Exit process
     os_obj.insert("exit".to_string(), Value::NativeFunction(Arc::new(|args| {
@@ -227,7 +384,12 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
             _ => return Err("exec expects first argument to be a command string".to_string()),
         };
 
-        let cmd_args: Vec<String> = if let Some(Value::Array(values)) = args.get(1) {
+        // The options object may stand in for the args array.
+        let (cmd_args, options) = match (args.get(1), args.get(2)) {
+            (Some(Value::Object(_)), None) => (None, args.get(1)),
+            (cmd_args, options) => (cmd_args, options),
+        };
+        let cmd_args: Vec<String> = if let Some(Value::Array(values)) = cmd_args {
             let mut out = Vec::with_capacity(values.len());
             for v in values {
                 match v {
@@ -240,17 +402,8 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
             Vec::new()
         };
 
-        let output = Command::new(&command).args(&cmd_args).output();
-        match output {
-            Ok(o) => {
-                let mut result = HashMap::new();
-                result.insert("status".to_string(), Value::Int(o.status.code().unwrap_or(-1) as i64));
-                result.insert("stdout".to_string(), Value::String(String::from_utf8_lossy(&o.stdout).to_string()));
-                result.insert("stderr".to_string(), Value::String(String::from_utf8_lossy(&o.stderr).to_string()));
-                Ok(Value::Object(result))
-            }
-            Err(e) => Err(format!("exec failed for '{}': {}", command, e)),
-        }
+        let options = ExecOptions::parse(&Options::new("os.exec", options, ExecOptions::KEYS)?)?;
+        exec(&command, &cmd_args, &options).map_err(|e| NativeError::io(&e, format!("exec failed for '{}'", command)).into())
         }
     })));
 
@@ -292,7 +445,7 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
 
             match Command::new(&command).args(&cmd_args).status() {
                 Ok(status) => Ok(Value::Int(status.code().unwrap_or(-1) as i64)),
-                Err(e) => Err(format!("system failed for '{}': {}", command, e)),
+                Err(e) => Err(NativeError::io(&e, format!("system failed for '{}'", command)).into()),
             }
         }
     })));
@@ -335,7 +488,7 @@ pub fn register(env: &mut Environment) -> Result<(), String> {
 
         match Command::new(&command).args(&cmd_args).spawn() {
             Ok(child) => Ok(Value::Int(child.id() as i64)),
-            Err(e) => Err(format!("spawn failed for '{}': {}", command, e)),
+            Err(e) => Err(NativeError::io(&e, format!("spawn failed for '{}'", command)).into()),
         }
         }
     })));
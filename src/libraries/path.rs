@@ -1,5 +1,5 @@
 use crate::environment::{Environment, Value};
-use hashbrown::HashMap;
+use indexmap::IndexMap;
 use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 
@@ -95,8 +95,20 @@ fn relative_between(from: &Path, to: &Path) -> Result<PathBuf, String> {
     }
 }
 
+pub const SIGNATURES: super::Signatures = &[
+    ("join", "|...parts: string| -> string"),
+    ("normalize", "|path: string| -> string"),
+    ("resolve", "|...parts: string| -> string"),
+    ("basename", "|path: string| -> string"),
+    ("dirname", "|path: string| -> string"),
+    ("extname", "|path: string| -> string"),
+    ("stem", "|path: string| -> string"),
+    ("is_abs", "|path: string| -> bool"),
+    ("relative", "|from: string, to: string| -> string"),
+];
+
 pub fn register(env: &mut Environment) -> Result<(), String> {
-    let mut path_obj = HashMap::new();
+    let mut path_obj = IndexMap::new();
 
     path_obj.insert(
         "join".to_string(),
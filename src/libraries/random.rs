@@ -0,0 +1,120 @@
+//! Samples from the common probability distributions.
+//!
+//! Every sampler draws from the same generator as `math.random`, and the
+//! matching CDFs live in the math library (`math.normal_cdf` and friends).
+
+use super::math::{number_arg, probability_arg, random_unit};
+use crate::environment::{Environment, Value};
+use indexmap::IndexMap;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+pub const SIGNATURES: crate::libraries::Signatures = &[
+    ("normal", "|mu?: numeric, sigma?: numeric| -> float"),
+    ("binomial", "|n: int, p: numeric| -> int"),
+    ("poisson", "|lambda: numeric| -> int"),
+];
+
+/// A uniform sample in `(0, 1]`, safe to take the log of.
+fn open_unit() -> Result<f64, String> {
+    Ok(1.0 - random_unit()?)
+}
+
+/// Box-Muller.
+fn normal(mu: f64, sigma: f64) -> Result<f64, String> {
+    let (u1, u2) = (open_unit()?, random_unit()?);
+    Ok(mu + sigma * (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos())
+}
+
+/// Counts successes by jumping over the geometric gaps between them, so the
+/// cost grows with the number of successes rather than `n`.
+fn binomial(n: i64, p: f64) -> Result<i64, String> {
+    if p == 0.0 || n == 0 {
+        return Ok(0);
+    }
+    if p == 1.0 {
+        return Ok(n);
+    }
+    let q = p.min(1.0 - p);
+    let log_miss = (1.0 - q).ln();
+    let (mut trial, mut successes) = (0i64, 0i64);
+    loop {
+        let gap = (open_unit()?.ln() / log_miss).floor() as i64 + 1;
+        trial = trial.saturating_add(gap);
+        if trial > n {
+            break;
+        }
+        successes += 1;
+    }
+    Ok(if q < p { n - successes } else { successes })
+}
+
+/// Rates above this are split into chunks, since Knuth's product of
+/// uniforms underflows against `e^-lambda` for large rates.
+const POISSON_CHUNK: f64 = 30.0;
+
+/// Knuth's method, summed over chunks of the rate.
+fn poisson(lambda: f64) -> Result<i64, String> {
+    let mut remaining = lambda;
+    let mut count = 0;
+    while remaining > 0.0 {
+        let rate = remaining.min(POISSON_CHUNK);
+        remaining -= rate;
+        let limit = (-rate).exp();
+        let mut product = random_unit()?;
+        while product > limit {
+            count += 1;
+            product *= random_unit()?;
+        }
+    }
+    Ok(count)
+}
+
+pub fn register(env: &mut Environment) -> Result<(), String> {
+    let mut random_obj = IndexMap::new();
+
+    random_obj.insert(
+        "normal".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            let (mu, sigma) = match args.as_slice() {
+                [] => (0.0, 1.0),
+                [mu, sigma] => (number_arg(mu, "random.normal", "mu")?, number_arg(sigma, "random.normal", "sigma")?),
+                _ => return Err("random.normal expects no arguments, or mu and sigma".to_string()),
+            };
+            if sigma < 0.0 {
+                return Err("random.normal expects sigma >= 0".to_string());
+            }
+            Ok(Value::Float(normal(mu, sigma)?))
+        })),
+    );
+
+    random_obj.insert(
+        "binomial".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            let [Value::Int(n), p] = args.as_slice() else {
+                return Err("random.binomial expects an int n and a probability p".to_string());
+            };
+            if *n < 0 {
+                return Err("random.binomial expects n >= 0".to_string());
+            }
+            Ok(Value::Int(binomial(*n, probability_arg(p, "random.binomial")?)?))
+        })),
+    );
+
+    random_obj.insert(
+        "poisson".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            let [lambda] = args.as_slice() else {
+                return Err("random.poisson expects one rate".to_string());
+            };
+            let lambda = number_arg(lambda, "random.poisson", "lambda")?;
+            if !(0.0..=1e9).contains(&lambda) {
+                return Err(format!("random.poisson expects lambda between 0 and 1e9, got {}", lambda));
+            }
+            Ok(Value::Int(poisson(lambda)?))
+        })),
+    );
+
+    env.declare("random".to_string(), Value::Object(random_obj), true);
+    Ok(())
+}
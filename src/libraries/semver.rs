@@ -0,0 +1,368 @@
+use crate::environment::{Environment, Value};
+use indexmap::IndexMap;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    pub pre: Vec<String>,
+    pub build: String,
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.major
+            .cmp(&other.major)
+            .then(self.minor.cmp(&other.minor))
+            .then(self.patch.cmp(&other.patch))
+            .then_with(|| compare_pre(&self.pre, &other.pre))
+    }
+}
+
+// Build metadata does not take part in precedence, so equality ignores it
+// too: `1.0.0+a == 1.0.0+b`, as `Ord` says.
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre.is_empty() {
+            write!(f, "-{}", self.pre.join("."))?;
+        }
+        if !self.build.is_empty() {
+            write!(f, "+{}", self.build)?;
+        }
+        Ok(())
+    }
+}
+
+// Pre-release precedence follows semver.org: a version without a pre-release
+// ranks higher, numeric identifiers compare numerically and rank below
+// alphanumeric ones, and a longer list wins when all shared fields are equal.
+fn compare_pre(left: &[String], right: &[String]) -> Ordering {
+    match (left.is_empty(), right.is_empty()) {
+        (true, true) => return Ordering::Equal,
+        (true, false) => return Ordering::Greater,
+        (false, true) => return Ordering::Less,
+        _ => {}
+    }
+    for (l, r) in left.iter().zip(right.iter()) {
+        let ord = match (l.parse::<u64>(), r.parse::<u64>()) {
+            (Ok(l), Ok(r)) => l.cmp(&r),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => l.cmp(r),
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    left.len().cmp(&right.len())
+}
+
+fn parse_number(part: &str, input: &str) -> Result<u64, String> {
+    if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("Invalid version '{}': '{}' is not a number", input, part));
+    }
+    if part.len() > 1 && part.starts_with('0') {
+        return Err(format!("Invalid version '{}': leading zero in '{}'", input, part));
+    }
+    part.parse::<u64>()
+        .map_err(|_| format!("Invalid version '{}': '{}' is out of range", input, part))
+}
+
+fn split_suffixes(input: &str) -> Result<(&str, Vec<String>, String), String> {
+    let (rest, build) = match input.split_once('+') {
+        Some((rest, build)) => {
+            check_identifiers(build, "build", input)?;
+            (rest, build.to_string())
+        }
+        None => (input, String::new()),
+    };
+    let (core, pre) = match rest.split_once('-') {
+        Some((core, pre)) => {
+            check_identifiers(pre, "pre-release", input)?;
+            (core, pre.split('.').map(|s| s.to_string()).collect::<Vec<_>>())
+        }
+        None => (rest, Vec::new()),
+    };
+    Ok((core, pre, build))
+}
+
+// SemVer 2.0 identifiers: non-empty runs of ASCII letters, digits and
+// hyphens, and numeric pre-release identifiers without leading zeros.
+fn check_identifiers(ids: &str, kind: &str, input: &str) -> Result<(), String> {
+    for id in ids.split('.') {
+        if id.is_empty() {
+            return Err(format!("Invalid version '{}': empty {} identifier", input, kind));
+        }
+        if !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(format!("Invalid version '{}': {} identifier '{}' may only use letters, digits and '-'", input, kind, id));
+        }
+        if kind == "pre-release" && id.len() > 1 && id.starts_with('0') && id.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("Invalid version '{}': numeric pre-release identifier '{}' has a leading zero", input, id));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a full `MAJOR.MINOR.PATCH[-pre][+build]` version. A leading `v` is accepted.
+pub fn parse_version(input: &str) -> Result<Version, String> {
+    let trimmed = input.trim();
+    let trimmed = trimmed.strip_prefix('v').unwrap_or(trimmed);
+    let (core, pre, build) = split_suffixes(trimmed)?;
+    let parts: Vec<&str> = core.split('.').collect();
+    if parts.len() != 3 {
+        return Err(format!("Invalid version '{}': expected MAJOR.MINOR.PATCH", input));
+    }
+    Ok(Version {
+        major: parse_number(parts[0], input)?,
+        minor: parse_number(parts[1], input)?,
+        patch: parse_number(parts[2], input)?,
+        pre,
+        build,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+}
+
+type Comparator = (Op, Version);
+
+fn version(major: u64, minor: u64, patch: u64) -> Version {
+    Version { major, minor, patch, pre: Vec::new(), build: String::new() }
+}
+
+// A version in a range may leave trailing parts out or use `x`/`*` wildcards
+// (`1`, `1.2`, `1.x`). Missing parts are reported as `None`.
+struct PartialVersion {
+    major: Option<u64>,
+    minor: Option<u64>,
+    patch: Option<u64>,
+    pre: Vec<String>,
+}
+
+fn parse_partial(input: &str, range: &str) -> Result<PartialVersion, String> {
+    let input = input.strip_prefix('v').unwrap_or(input);
+    let (core, pre, _) = split_suffixes(input)?;
+    let parts: Vec<&str> = core.split('.').collect();
+    if parts.is_empty() || parts.len() > 3 {
+        return Err(format!("Invalid version range '{}'", range));
+    }
+    let mut nums = [None, None, None];
+    for (idx, part) in parts.iter().enumerate() {
+        if matches!(*part, "x" | "X" | "*") {
+            break;
+        }
+        nums[idx] = Some(parse_number(part, input).map_err(|_| format!("Invalid version range '{}'", range))?);
+    }
+    Ok(PartialVersion { major: nums[0], minor: nums[1], patch: nums[2], pre })
+}
+
+fn expand_comparator(token: &str, range: &str) -> Result<Vec<Comparator>, String> {
+    if matches!(token, "*" | "x" | "X") {
+        return Ok(Vec::new());
+    }
+
+    let (op, rest) = if let Some(rest) = token.strip_prefix(">=") {
+        (">=", rest)
+    } else if let Some(rest) = token.strip_prefix("<=") {
+        ("<=", rest)
+    } else if let Some(rest) = token.strip_prefix('>') {
+        (">", rest)
+    } else if let Some(rest) = token.strip_prefix('<') {
+        ("<", rest)
+    } else if let Some(rest) = token.strip_prefix('=') {
+        ("=", rest)
+    } else if let Some(rest) = token.strip_prefix('^') {
+        ("^", rest)
+    } else if let Some(rest) = token.strip_prefix('~') {
+        ("~", rest)
+    } else {
+        ("", token)
+    };
+
+    let PartialVersion { major, minor, patch, pre } = parse_partial(rest, range)?;
+    let Some(major) = major else {
+        return Ok(Vec::new());
+    };
+    let lower = Version {
+        major,
+        minor: minor.unwrap_or(0),
+        patch: patch.unwrap_or(0),
+        pre,
+        build: String::new(),
+    };
+
+    // Bounds one above a part; a part at u64::MAX has nothing above it.
+    let next = |part: u64| {
+        part.checked_add(1)
+            .ok_or_else(|| format!("Invalid version range '{}': {} has no version above it", range, part))
+    };
+    // Exclusive upper bound for a partial version such as `1.2` (< 1.3.0).
+    let partial_upper = || -> Result<Version, String> {
+        Ok(match (minor, patch) {
+            (None, _) => version(next(major)?, 0, 0),
+            (Some(minor), None) => version(major, next(minor)?, 0),
+            (Some(minor), Some(patch)) => version(major, minor, next(patch)?),
+        })
+    };
+
+    Ok(match op {
+        "^" => {
+            let upper = match (major, minor, patch) {
+                (0, Some(0), Some(patch)) => version(0, 0, next(patch)?),
+                (0, Some(minor), _) => version(0, next(minor)?, 0),
+                _ => version(next(major)?, 0, 0),
+            };
+            vec![(Op::Gte, lower), (Op::Lt, upper)]
+        }
+        "~" => {
+            let upper = match minor {
+                Some(minor) => version(major, next(minor)?, 0),
+                None => version(next(major)?, 0, 0),
+            };
+            vec![(Op::Gte, lower), (Op::Lt, upper)]
+        }
+        ">=" => vec![(Op::Gte, lower)],
+        "<" => vec![(Op::Lt, lower)],
+        ">" if patch.is_some() => vec![(Op::Gt, lower)],
+        ">" => vec![(Op::Gte, partial_upper()?)],
+        "<=" if patch.is_some() => vec![(Op::Lte, lower)],
+        "<=" => vec![(Op::Lt, partial_upper()?)],
+        _ if patch.is_some() => vec![(Op::Eq, lower)],
+        _ => vec![(Op::Gte, lower), (Op::Lt, partial_upper()?)],
+    })
+}
+
+/// Checks a version against an npm-style range: comparators separated by
+/// whitespace must all match, and `||` separates alternative sets.
+pub fn satisfies(version: &Version, range: &str) -> Result<bool, String> {
+    if range.trim().is_empty() {
+        return Err("Invalid version range ''".to_string());
+    }
+    for alternative in range.split("||") {
+        let mut comparators = Vec::new();
+        for token in alternative.split_whitespace() {
+            comparators.extend(expand_comparator(token, range)?);
+        }
+        let matched = comparators.iter().all(|(op, bound)| {
+            let ord = version.cmp(bound);
+            match op {
+                Op::Eq => ord == Ordering::Equal,
+                Op::Lt => ord == Ordering::Less,
+                Op::Lte => ord != Ordering::Greater,
+                Op::Gt => ord == Ordering::Greater,
+                Op::Gte => ord != Ordering::Less,
+            }
+        });
+        if matched {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn expect_string(value: &Value, fn_name: &str) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        _ => Err(format!("{} expects string arguments", fn_name)),
+    }
+}
+
+fn version_to_value(version: &Version) -> Value {
+    let pre = version.pre.join(".");
+    let fields = [
+        ("major", Value::Int(version.major as i64)),
+        ("minor", Value::Int(version.minor as i64)),
+        ("patch", Value::Int(version.patch as i64)),
+        ("pre", Value::String(pre)),
+        ("build", Value::String(version.build.clone())),
+    ];
+    Value::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+}
+
+pub const SIGNATURES: super::Signatures = &[
+    ("parse", "|version: string| -> obj"),
+    ("valid", "|version: string| -> bool"),
+    ("compare", "|a: string, b: string| -> int"),
+    ("satisfies", "|version: string, range: string| -> bool"),
+];
+
+pub fn register(env: &mut Environment) -> Result<(), String> {
+    let mut semver_obj = IndexMap::new();
+
+    semver_obj.insert(
+        "parse".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            if args.len() != 1 {
+                return Err("semver.parse expects exactly one version string".to_string());
+            }
+            let raw = expect_string(&args[0], "semver.parse")?;
+            Ok(version_to_value(&parse_version(&raw)?))
+        })),
+    );
+
+    semver_obj.insert(
+        "valid".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            if args.len() != 1 {
+                return Err("semver.valid expects exactly one version string".to_string());
+            }
+            let raw = expect_string(&args[0], "semver.valid")?;
+            Ok(Value::Boolean(parse_version(&raw).is_ok()))
+        })),
+    );
+
+    semver_obj.insert(
+        "compare".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            if args.len() != 2 {
+                return Err("semver.compare expects exactly two version strings".to_string());
+            }
+            let left = parse_version(&expect_string(&args[0], "semver.compare")?)?;
+            let right = parse_version(&expect_string(&args[1], "semver.compare")?)?;
+            Ok(Value::Int(match left.cmp(&right) {
+                Ordering::Less => -1,
+                Ordering::Equal => 0,
+                Ordering::Greater => 1,
+            }))
+        })),
+    );
+
+    semver_obj.insert(
+        "satisfies".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            if args.len() != 2 {
+                return Err("semver.satisfies expects a version string and a range string".to_string());
+            }
+            let version = parse_version(&expect_string(&args[0], "semver.satisfies")?)?;
+            let range = expect_string(&args[1], "semver.satisfies")?;
+            Ok(Value::Boolean(satisfies(&version, &range)?))
+        })),
+    );
+
+    env.declare("semver".to_string(), Value::Object(semver_obj), true);
+    Ok(())
+}
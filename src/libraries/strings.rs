@@ -0,0 +1,32 @@
+use crate::environment::{Environment, Value};
+use indexmap::IndexMap;
+use std::sync::Arc;
+
+pub const SIGNATURES: super::Signatures = &[
+    ("from_char_code", "|code: int| -> string"),
+];
+
+pub fn register(env: &mut Environment) -> Result<(), String> {
+    let mut strings_obj = IndexMap::new();
+
+    strings_obj.insert(
+        "from_char_code".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            if args.len() != 1 {
+                return Err("strings.from_char_code expects exactly one code point".to_string());
+            }
+            let code = match &args[0] {
+                Value::Int(code) => *code,
+                _ => return Err("strings.from_char_code expects an int code point".to_string()),
+            };
+            u32::try_from(code)
+                .ok()
+                .and_then(char::from_u32)
+                .map(|ch| Value::String(ch.to_string()))
+                .ok_or_else(|| format!("{} is not a valid Unicode code point", code))
+        })),
+    );
+
+    env.declare("strings".to_string(), Value::Object(strings_obj), true);
+    Ok(())
+}
@@ -0,0 +1,396 @@
+//! Numbers with units of measure, checked for dimensional consistency.
+//!
+//! A quantity is a [`Quantity`] value: a number and the unit symbols it is
+//! measured in with their exponents, so `9.8 m/s^2` carries `{m: 1, s: -2}`.
+//! Scripts read its `value` and `unit` fields. Every symbol knows its size in
+//! SI base units and its dimension, which is all arithmetic and conversion
+//! need. Both evaluators route operators with a quantity operand through
+//! [`binary`].
+
+use crate::environment::{Environment, Value};
+use indexmap::IndexMap;
+use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
+
+/// Exponents of length, mass, time, current, temperature, amount of
+/// substance and luminous intensity.
+type Dimension = [i32; 7];
+
+const LENGTH: Dimension = [1, 0, 0, 0, 0, 0, 0];
+const MASS: Dimension = [0, 1, 0, 0, 0, 0, 0];
+const TIME: Dimension = [0, 0, 1, 0, 0, 0, 0];
+const CURRENT: Dimension = [0, 0, 0, 1, 0, 0, 0];
+const TEMPERATURE: Dimension = [0, 0, 0, 0, 1, 0, 0];
+const AMOUNT: Dimension = [0, 0, 0, 0, 0, 1, 0];
+const LUMINOSITY: Dimension = [0, 0, 0, 0, 0, 0, 1];
+const VOLUME: Dimension = [3, 0, 0, 0, 0, 0, 0];
+const FREQUENCY: Dimension = [0, 0, -1, 0, 0, 0, 0];
+const FORCE: Dimension = [1, 1, -2, 0, 0, 0, 0];
+const PRESSURE: Dimension = [-1, 1, -2, 0, 0, 0, 0];
+const ENERGY: Dimension = [2, 1, -2, 0, 0, 0, 0];
+const POWER: Dimension = [2, 1, -3, 0, 0, 0, 0];
+
+/// `(symbol, size in SI base units, dimension)`. Inches are `inch`, since
+/// `in` is a keyword.
+const UNITS: &[(&str, f64, Dimension)] = &[
+    ("m", 1.0, LENGTH),
+    ("km", 1000.0, LENGTH),
+    ("cm", 0.01, LENGTH),
+    ("mm", 0.001, LENGTH),
+    ("mi", 1609.344, LENGTH),
+    ("yd", 0.9144, LENGTH),
+    ("ft", 0.3048, LENGTH),
+    ("inch", 0.0254, LENGTH),
+    ("kg", 1.0, MASS),
+    ("g", 0.001, MASS),
+    ("mg", 0.000001, MASS),
+    ("t", 1000.0, MASS),
+    ("lb", 0.45359237, MASS),
+    ("oz", 0.028349523125, MASS),
+    ("s", 1.0, TIME),
+    ("ms", 0.001, TIME),
+    ("min", 60.0, TIME),
+    ("h", 3600.0, TIME),
+    ("day", 86400.0, TIME),
+    ("A", 1.0, CURRENT),
+    ("K", 1.0, TEMPERATURE),
+    ("mol", 1.0, AMOUNT),
+    ("cd", 1.0, LUMINOSITY),
+    ("L", 0.001, VOLUME),
+    ("mL", 0.000001, VOLUME),
+    ("Hz", 1.0, FREQUENCY),
+    ("N", 1.0, FORCE),
+    ("Pa", 1.0, PRESSURE),
+    ("J", 1.0, ENERGY),
+    ("kJ", 1000.0, ENERGY),
+    ("cal", 4.184, ENERGY),
+    ("kcal", 4184.0, ENERGY),
+    ("W", 1.0, POWER),
+    ("kW", 1000.0, POWER),
+];
+
+/// Unit symbols and their exponents, in the order they were written.
+type Powers = Vec<(String, i32)>;
+
+fn lookup(symbol: &str) -> Option<(f64, Dimension)> {
+    UNITS.iter().find(|(name, _, _)| *name == symbol).map(|(_, factor, dimension)| (*factor, *dimension))
+}
+
+/// Parses `kg*m/s^2`: symbols joined by `*` and `/`, each with an optional
+/// integer `^` exponent. A `/` divides by the one symbol after it.
+fn parse_unit(text: &str) -> Result<Powers, String> {
+    let mut powers = Powers::new();
+    let mut sign = 1;
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let end = rest.find(['*', '/']).unwrap_or(rest.len());
+        let (term, tail) = rest.split_at(end);
+        let (symbol, exponent) = match term.trim().split_once('^') {
+            Some((symbol, exponent)) => {
+                (symbol.trim(), exponent.trim().parse::<i32>().map_err(|_| format!("Invalid exponent in unit '{}'", text))?)
+            }
+            None => (term.trim(), 1),
+        };
+        if lookup(symbol).is_none() {
+            return Err(format!("Unknown unit '{}' in '{}'", symbol, text));
+        }
+        add_power(&mut powers, symbol, sign * exponent);
+        sign = if tail.starts_with('/') { -1 } else { 1 };
+        rest = tail.get(1..).unwrap_or("");
+        if tail.len() == 1 {
+            return Err(format!("Unit '{}' ends with an operator", text));
+        }
+    }
+    if powers.is_empty() {
+        return Err(format!("Invalid unit '{}'", text));
+    }
+    Ok(powers)
+}
+
+fn add_power(powers: &mut Powers, symbol: &str, exponent: i32) {
+    match powers.iter().position(|(name, _)| name == symbol) {
+        Some(i) => {
+            powers[i].1 += exponent;
+            if powers[i].1 == 0 {
+                powers.remove(i);
+            }
+        }
+        None if exponent != 0 => powers.push((symbol.to_string(), exponent)),
+        None => {}
+    }
+}
+
+/// `m/s^2` style text: positive powers joined by `*`, then each negative one
+/// after a `/`.
+fn format_unit(powers: &Powers) -> String {
+    let term = |(symbol, exponent): &(String, i32)| match exponent.abs() {
+        1 => symbol.clone(),
+        n => format!("{}^{}", symbol, n),
+    };
+    let numerator: Vec<String> = powers.iter().filter(|(_, e)| *e > 0).map(term).collect();
+    let mut text = if numerator.is_empty() { "1".to_string() } else { numerator.join("*") };
+    for power in powers.iter().filter(|(_, e)| *e < 0) {
+        text.push('/');
+        text.push_str(&term(power));
+    }
+    text
+}
+
+/// The size of one `powers` in SI base units, and its dimension.
+fn measure(powers: &Powers) -> (f64, Dimension) {
+    let mut factor = 1.0;
+    let mut dimension = [0; 7];
+    for (symbol, exponent) in powers {
+        let (size, base) = lookup(symbol).unwrap_or((1.0, [0; 7]));
+        factor *= size.powi(*exponent);
+        for (total, part) in dimension.iter_mut().zip(base) {
+            *total += part * exponent;
+        }
+    }
+    (factor, dimension)
+}
+
+/// A number measured in units. `exact` keeps an int an int until something
+/// like a conversion makes it fractional.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    value: f64,
+    exact: bool,
+    powers: Powers,
+}
+
+impl Quantity {
+    /// The fields scripts can read: `value` and `unit`.
+    pub fn property(&self, name: &str) -> Option<Value> {
+        match name {
+            "value" => Some(number(self.value, self.exact)),
+            "unit" => Some(Value::String(format_unit(&self.powers))),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Quantity {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} {}", number(self.value, self.exact), format_unit(&self.powers))
+    }
+}
+
+/// `value` in `powers`, or the plain number when the units cancel out.
+fn quantity(value: f64, exact: bool, powers: Powers) -> Value {
+    let (factor, dimension) = measure(&powers);
+    if dimension == [0; 7] {
+        return number(value * factor, exact && factor == 1.0);
+    }
+    Value::Quantity(Quantity { value, exact, powers })
+}
+
+fn number(value: f64, exact: bool) -> Value {
+    if exact && value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        Value::Int(value as i64)
+    } else {
+        Value::Float(value)
+    }
+}
+
+fn is_quantity(value: &Value) -> bool {
+    matches!(value, Value::Quantity(_))
+}
+
+/// A quantity's number, whether it is an int, and its units. Plain numbers
+/// are quantities without units.
+fn split(value: &Value) -> Option<(f64, bool, Powers)> {
+    match value {
+        Value::Int(i) => Some((*i as f64, true, Powers::new())),
+        Value::Float(f) => Some((*f, false, Powers::new())),
+        Value::Quantity(q) => Some((q.value, q.exact, q.powers.clone())),
+        _ => None,
+    }
+}
+
+fn describe(powers: &Powers) -> String {
+    if powers.is_empty() {
+        "a plain number".to_string()
+    } else {
+        format_unit(powers)
+    }
+}
+
+/// Operators with a quantity operand. Adding, subtracting and comparing need
+/// matching dimensions, and the result is in the left operand's unit;
+/// multiplying and dividing combine the units. `None` when neither side is
+/// a quantity, or for `+` with a string, which still concatenates.
+pub fn binary(op: &str, left: &Value, right: &Value) -> Option<Result<Value, String>> {
+    if !is_quantity(left) && !is_quantity(right) {
+        return None;
+    }
+    if op == "in" || (op == "+" && (matches!(left, Value::String(_)) || matches!(right, Value::String(_)))) {
+        return None;
+    }
+    let (Some((l, l_exact, l_units)), Some((r, r_exact, r_units))) = (split(left), split(right)) else {
+        return Some(match op {
+            "==" => Ok(Value::Boolean(false)),
+            "!=" => Ok(Value::Boolean(true)),
+            _ => Err(format!("Cannot apply '{}' to a quantity and a non-number", op)),
+        });
+    };
+    let (l_factor, l_dimension) = measure(&l_units);
+    let (r_factor, r_dimension) = measure(&r_units);
+    let same_dimension = l_dimension == r_dimension;
+    let mismatch = |verb: &str| {
+        Err(format!("Cannot {} {} and {}: their dimensions differ", verb, describe(&l_units), describe(&r_units)))
+    };
+
+    let result = match op {
+        "+" | "-" if !same_dimension => mismatch(if op == "+" { "add" } else { "subtract" }),
+        "+" | "-" => {
+            let ratio = r_factor / l_factor;
+            let r = r * ratio;
+            let value = if op == "+" { l + r } else { l - r };
+            Ok(quantity(value, l_exact && r_exact && ratio == 1.0, l_units))
+        }
+        "*" | "/" => {
+            let mut units = l_units;
+            let sign = if op == "*" { 1 } else { -1 };
+            for (symbol, exponent) in r_units {
+                add_power(&mut units, &symbol, sign * exponent);
+            }
+            if op == "*" {
+                Ok(quantity(l * r, l_exact && r_exact, units))
+            } else if r == 0.0 {
+                Err("Division by zero".to_string())
+            } else {
+                Ok(quantity(l / r, false, units))
+            }
+        }
+        "==" | "!=" if !same_dimension => Ok(Value::Boolean(op == "!=")),
+        "==" | "!=" | "<" | ">" | "<=" | ">=" if same_dimension => {
+            let (l, r) = (l * l_factor, r * r_factor);
+            Ok(Value::Boolean(match op {
+                "==" => l == r,
+                "!=" => l != r,
+                "<" => l < r,
+                ">" => l > r,
+                "<=" => l <= r,
+                _ => l >= r,
+            }))
+        }
+        "<" | ">" | "<=" | ">=" => mismatch("compare"),
+        _ => Err(format!("'{}' is not defined for quantities", op)),
+    };
+    Some(result)
+}
+
+fn expect_number(value: &Value, function: &str) -> Result<(f64, bool), String> {
+    match value {
+        Value::Int(i) => Ok((*i as f64, true)),
+        Value::Float(f) => Ok((*f, false)),
+        _ => Err(format!("{} expects an int or float", function)),
+    }
+}
+
+fn expect_unit(value: &Value, function: &str) -> Result<Powers, String> {
+    match value {
+        Value::String(text) => parse_unit(text),
+        _ => Err(format!("{} expects units as a string like \"km/h\"", function)),
+    }
+}
+
+/// `value` given in `from`, expressed in `to`.
+fn convert(value: f64, from: &Powers, to: &Powers) -> Result<f64, String> {
+    let (from_factor, from_dimension) = measure(from);
+    let (to_factor, to_dimension) = measure(to);
+    if from_dimension != to_dimension {
+        return Err(format!("Cannot convert {} to {}: their dimensions differ", describe(from), describe(to)));
+    }
+    Ok(value * from_factor / to_factor)
+}
+
+pub const SIGNATURES: super::Signatures = &[
+    ("of", "|value: numeric, unit: string| -> obj"),
+    ("convert", "|value: numeric | obj, from?: string, to: string| -> numeric | obj"),
+    ("m", "|value: numeric| -> obj"),
+    ("km", "|value: numeric| -> obj"),
+    ("cm", "|value: numeric| -> obj"),
+    ("mm", "|value: numeric| -> obj"),
+    ("mi", "|value: numeric| -> obj"),
+    ("yd", "|value: numeric| -> obj"),
+    ("ft", "|value: numeric| -> obj"),
+    ("inch", "|value: numeric| -> obj"),
+    ("kg", "|value: numeric| -> obj"),
+    ("g", "|value: numeric| -> obj"),
+    ("mg", "|value: numeric| -> obj"),
+    ("t", "|value: numeric| -> obj"),
+    ("lb", "|value: numeric| -> obj"),
+    ("oz", "|value: numeric| -> obj"),
+    ("s", "|value: numeric| -> obj"),
+    ("ms", "|value: numeric| -> obj"),
+    ("min", "|value: numeric| -> obj"),
+    ("h", "|value: numeric| -> obj"),
+    ("day", "|value: numeric| -> obj"),
+    ("A", "|value: numeric| -> obj"),
+    ("K", "|value: numeric| -> obj"),
+    ("mol", "|value: numeric| -> obj"),
+    ("cd", "|value: numeric| -> obj"),
+    ("L", "|value: numeric| -> obj"),
+    ("mL", "|value: numeric| -> obj"),
+    ("Hz", "|value: numeric| -> obj"),
+    ("N", "|value: numeric| -> obj"),
+    ("Pa", "|value: numeric| -> obj"),
+    ("J", "|value: numeric| -> obj"),
+    ("kJ", "|value: numeric| -> obj"),
+    ("cal", "|value: numeric| -> obj"),
+    ("kcal", "|value: numeric| -> obj"),
+    ("W", "|value: numeric| -> obj"),
+    ("kW", "|value: numeric| -> obj"),
+];
+
+pub fn register(env: &mut Environment) -> Result<(), String> {
+    let mut units_obj = IndexMap::new();
+
+    for (symbol, _, _) in UNITS {
+        let function = format!("units.{}", symbol);
+        units_obj.insert(
+            symbol.to_string(),
+            Value::NativeFunction(Arc::new(move |args| {
+                let [value] = args.as_slice() else {
+                    return Err(format!("{} expects exactly one number", function));
+                };
+                let (value, exact) = expect_number(value, &function)?;
+                Ok(quantity(value, exact, vec![(symbol.to_string(), 1)]))
+            })),
+        );
+    }
+
+    units_obj.insert(
+        "of".to_string(),
+        Value::NativeFunction(Arc::new(|args| {
+            let [value, unit] = args.as_slice() else {
+                return Err("units.of expects a number and a unit string".to_string());
+            };
+            let (value, exact) = expect_number(value, "units.of")?;
+            Ok(quantity(value, exact, expect_unit(unit, "units.of")?))
+        })),
+    );
+
+    units_obj.insert(
+        "convert".to_string(),
+        Value::NativeFunction(Arc::new(|args| match args.as_slice() {
+            [value, to] if is_quantity(value) => {
+                let (number, _, from) = split(value).ok_or("units.convert expects a quantity")?;
+                let to = expect_unit(to, "units.convert")?;
+                Ok(quantity(convert(number, &from, &to)?, false, to))
+            }
+            [value, from, to] => {
+                let (number, _) = expect_number(value, "units.convert")?;
+                let from = expect_unit(from, "units.convert")?;
+                let to = expect_unit(to, "units.convert")?;
+                Ok(Value::Float(convert(number, &from, &to)?))
+            }
+            _ => Err("units.convert expects a quantity and a unit, or a number, its unit and a target unit".to_string()),
+        })),
+    );
+
+    env.declare("units".to_string(), Value::Object(units_obj), true);
+    Ok(())
+}
@@ -0,0 +1,498 @@
+//! Where the `fs` library (and `os.ls`) reads and writes files.
+//!
+//! Every file operation goes through the [`FsBackend`] of the running thread.
+//! That is the real disk by default, or an in-memory [`Overlay`]: under
+//! `zekken run --sandbox` the overlay sits on top of the disk so scripts read
+//! real files but every change stays in memory, the wasm build uses an empty
+//! one as its virtual file system, and tests use one to run file-using
+//! scripts without touching the disk.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// An open file as the `fs.open` handle sees it.
+pub trait FileLike: Read + Write + Seek + Send {}
+
+impl<T: Read + Write + Seek + Send> FileLike for T {}
+
+/// What `fs.stat` reports about a path.
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    pub is_file: bool,
+    pub is_dir: bool,
+    pub len: u64,
+    pub readonly: bool,
+    pub modified_unix: i64,
+}
+
+/// How `fs.open` opens a file, from its `r`, `w`, `a`, `r+`, `w+` or `a+`
+/// mode.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenMode {
+    pub read: bool,
+    pub write: bool,
+    pub append: bool,
+    pub create: bool,
+    pub truncate: bool,
+}
+
+impl OpenMode {
+    pub fn parse(mode: &str) -> Option<OpenMode> {
+        let none = OpenMode::default();
+        Some(match mode {
+            "r" => OpenMode { read: true, ..none },
+            "w" => OpenMode { write: true, create: true, truncate: true, ..none },
+            "a" => OpenMode { append: true, create: true, ..none },
+            "r+" => OpenMode { read: true, write: true, ..none },
+            "w+" => OpenMode { read: true, write: true, create: true, truncate: true, ..none },
+            "a+" => OpenMode { read: true, append: true, create: true, ..none },
+            _ => return None,
+        })
+    }
+}
+
+pub trait FsBackend: Send + Sync {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+    /// Replaces the file's contents, or adds to them when `append` is set,
+    /// creating the file if needed.
+    fn write(&self, path: &str, data: &[u8], append: bool) -> io::Result<()>;
+    /// The paths of a directory's entries, each joined onto `path`.
+    fn list(&self, path: &str) -> io::Result<Vec<String>>;
+    fn metadata(&self, path: &str) -> io::Result<Metadata>;
+    /// Creates the directory and any missing parents.
+    fn create_dir(&self, path: &str) -> io::Result<()>;
+    fn remove_file(&self, path: &str) -> io::Result<()>;
+    /// Removes the directory and everything in it.
+    fn remove_dir(&self, path: &str) -> io::Result<()>;
+    fn rename(&self, from: &str, to: &str) -> io::Result<()>;
+    fn open(&self, path: &str, mode: OpenMode) -> io::Result<Box<dyn FileLike>>;
+
+    fn read_to_string(&self, path: &str) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"))
+    }
+
+    /// Copies a file, returning how many bytes were copied.
+    fn copy(&self, from: &str, to: &str) -> io::Result<u64> {
+        let data = self.read(from)?;
+        self.write(to, &data, false)?;
+        Ok(data.len() as u64)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.metadata(path).is_ok()
+    }
+}
+
+/// The real file system.
+pub struct Disk;
+
+impl FsBackend for Disk {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn read_to_string(&self, path: &str) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn write(&self, path: &str, data: &[u8], append: bool) -> io::Result<()> {
+        if append {
+            fs::OpenOptions::new().create(true).append(true).open(path)?.write_all(data)
+        } else {
+            fs::write(path, data)
+        }
+    }
+
+    fn list(&self, path: &str) -> io::Result<Vec<String>> {
+        Ok(fs::read_dir(path)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path().display().to_string())
+            .collect())
+    }
+
+    fn metadata(&self, path: &str) -> io::Result<Metadata> {
+        let meta = fs::metadata(path)?;
+        let modified_unix = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        Ok(Metadata {
+            is_file: meta.is_file(),
+            is_dir: meta.is_dir(),
+            len: meta.len(),
+            readonly: meta.permissions().readonly(),
+            modified_unix,
+        })
+    }
+
+    fn create_dir(&self, path: &str) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn remove_file(&self, path: &str) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn remove_dir(&self, path: &str) -> io::Result<()> {
+        fs::remove_dir_all(path)
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn open(&self, path: &str, mode: OpenMode) -> io::Result<Box<dyn FileLike>> {
+        let file = fs::OpenOptions::new()
+            .read(mode.read)
+            .write(mode.write)
+            .append(mode.append)
+            .create(mode.create)
+            .truncate(mode.truncate)
+            .open(path)?;
+        Ok(Box::new(file))
+    }
+
+    fn copy(&self, from: &str, to: &str) -> io::Result<u64> {
+        fs::copy(from, to)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    File { data: Vec<u8>, modified_unix: i64 },
+    Dir,
+    /// Removed here, even if the base still has it.
+    Removed,
+}
+
+/// Files kept in memory on top of an optional base backend. Reads fall
+/// through to the base for paths the overlay has not touched; writes,
+/// removals and new directories only ever change the overlay. Clones share
+/// the same files.
+#[derive(Clone)]
+pub struct Overlay {
+    base: Option<Arc<dyn FsBackend>>,
+    entries: Arc<Mutex<BTreeMap<PathBuf, Entry>>>,
+}
+
+impl Overlay {
+    /// An overlay with nothing below it: a purely in-memory file system.
+    pub fn empty() -> Overlay {
+        Overlay { base: None, entries: Arc::new(Mutex::new(BTreeMap::new())) }
+    }
+
+    /// An overlay whose untouched paths read from `base`.
+    pub fn over(base: Arc<dyn FsBackend>) -> Overlay {
+        Overlay { base: Some(base), ..Overlay::empty() }
+    }
+
+    /// Adds a file and any missing parent directories, for seeding an
+    /// overlay before a script runs.
+    pub fn with_file(self, path: &str, contents: &str) -> Overlay {
+        if let Some(parent) = key(path).parent() {
+            let _ = self.create_dir(&parent.display().to_string());
+        }
+        let _ = self.write(path, contents.as_bytes(), false);
+        self
+    }
+
+    /// The files the overlay holds, by path, as text. Removals are left out.
+    pub fn files(&self) -> BTreeMap<String, String> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(path, entry)| match entry {
+                Entry::File { data, .. } => {
+                    Some((path.display().to_string(), String::from_utf8_lossy(data).into_owned()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The overlay's own entry for `path` or a removed ancestor, if any.
+    fn entry(&self, key: &Path) -> Option<Entry> {
+        let entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get(key) {
+            return Some(entry.clone());
+        }
+        key.ancestors()
+            .skip(1)
+            .any(|ancestor| matches!(entries.get(ancestor), Some(Entry::Removed)))
+            .then_some(Entry::Removed)
+    }
+
+    fn insert(&self, key: PathBuf, entry: Entry) {
+        self.entries.lock().unwrap().insert(key, entry);
+    }
+
+    fn base_metadata(&self, path: &str) -> io::Result<Metadata> {
+        match &self.base {
+            Some(base) => base.metadata(path),
+            None => Err(not_found(path)),
+        }
+    }
+}
+
+/// `path` with `.` and `..` resolved lexically, as the overlay's key.
+fn key(path: &str) -> PathBuf {
+    let mut key = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !key.pop() {
+                    key.push("..");
+                }
+            }
+            other => key.push(other),
+        }
+    }
+    key
+}
+
+fn not_found(path: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("No such file or directory: '{}'", path))
+}
+
+fn now_unix() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+impl FsBackend for Overlay {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        match self.entry(&key(path)) {
+            Some(Entry::File { data, .. }) => Ok(data),
+            Some(Entry::Dir) => Err(io::Error::other(format!("'{}' is a directory", path))),
+            Some(Entry::Removed) => Err(not_found(path)),
+            None => match &self.base {
+                Some(base) => base.read(path),
+                None => Err(not_found(path)),
+            },
+        }
+    }
+
+    fn write(&self, path: &str, data: &[u8], append: bool) -> io::Result<()> {
+        let key = key(path);
+        if let Some(parent) = key.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            if !self.metadata(&parent.display().to_string()).is_ok_and(|meta| meta.is_dir) {
+                return Err(not_found(path));
+            }
+        }
+        let mut contents = if append {
+            match self.read(path) {
+                Ok(data) => data,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Err(err) => return Err(err),
+            }
+        } else if self.metadata(path).is_ok_and(|meta| meta.is_dir) {
+            return Err(io::Error::other(format!("'{}' is a directory", path)));
+        } else {
+            Vec::new()
+        };
+        contents.extend_from_slice(data);
+        self.insert(key, Entry::File { data: contents, modified_unix: now_unix() });
+        Ok(())
+    }
+
+    fn list(&self, path: &str) -> io::Result<Vec<String>> {
+        if !self.metadata(path)?.is_dir {
+            return Err(io::Error::other(format!("'{}' is not a directory", path)));
+        }
+        let dir = key(path);
+        let mut names: BTreeMap<String, bool> = BTreeMap::new();
+        if let Some(base) = &self.base {
+            // Fails, listing nothing, for a directory only the overlay has.
+            for entry in base.list(path).unwrap_or_default() {
+                if let Some(name) = Path::new(&entry).file_name() {
+                    names.insert(name.to_string_lossy().into_owned(), true);
+                }
+            }
+        }
+        for (entry_key, entry) in self.entries.lock().unwrap().iter() {
+            if entry_key.parent() == Some(dir.as_path()) {
+                if let Some(name) = entry_key.file_name() {
+                    names.insert(name.to_string_lossy().into_owned(), !matches!(entry, Entry::Removed));
+                }
+            }
+        }
+        Ok(names
+            .into_iter()
+            .filter(|(_, present)| *present)
+            .map(|(name, _)| Path::new(path).join(name).display().to_string())
+            .collect())
+    }
+
+    fn metadata(&self, path: &str) -> io::Result<Metadata> {
+        let key = key(path);
+        // The current directory always exists, even in an empty overlay.
+        if key.as_os_str().is_empty() && self.base.is_none() {
+            return Ok(Metadata { is_file: false, is_dir: true, len: 0, readonly: false, modified_unix: 0 });
+        }
+        match self.entry(&key) {
+            Some(Entry::File { data, modified_unix }) => Ok(Metadata {
+                is_file: true,
+                is_dir: false,
+                len: data.len() as u64,
+                readonly: false,
+                modified_unix,
+            }),
+            Some(Entry::Dir) => Ok(Metadata { is_file: false, is_dir: true, len: 0, readonly: false, modified_unix: 0 }),
+            Some(Entry::Removed) => Err(not_found(path)),
+            None => self.base_metadata(path),
+        }
+    }
+
+    fn create_dir(&self, path: &str) -> io::Result<()> {
+        let key = key(path);
+        let mut missing = Vec::new();
+        for ancestor in key.ancestors().filter(|ancestor| !ancestor.as_os_str().is_empty()) {
+            match self.metadata(&ancestor.display().to_string()) {
+                Ok(meta) if meta.is_dir => break,
+                Ok(_) => return Err(io::Error::other(format!("'{}' is a file", ancestor.display()))),
+                Err(_) => missing.push(ancestor.to_path_buf()),
+            }
+        }
+        for dir in missing {
+            self.insert(dir, Entry::Dir);
+        }
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &str) -> io::Result<()> {
+        if !self.metadata(path)?.is_file {
+            return Err(io::Error::other(format!("'{}' is not a file", path)));
+        }
+        self.insert(key(path), Entry::Removed);
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &str) -> io::Result<()> {
+        if !self.metadata(path)?.is_dir {
+            return Err(io::Error::other(format!("'{}' is not a directory", path)));
+        }
+        let dir = key(path);
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|entry_key, _| !entry_key.starts_with(&dir));
+        entries.insert(dir, Entry::Removed);
+        Ok(())
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        if !self.metadata(from)?.is_file {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "only files can be renamed in memory"));
+        }
+        let data = self.read(from)?;
+        self.write(to, &data, false)?;
+        self.insert(key(from), Entry::Removed);
+        Ok(())
+    }
+
+    fn open(&self, path: &str, mode: OpenMode) -> io::Result<Box<dyn FileLike>> {
+        let data = match self.read(path) {
+            Ok(_) if mode.truncate => Vec::new(),
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound && mode.create => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        let writable = mode.write || mode.append;
+        if writable {
+            self.write(path, &data, false)?;
+        }
+        Ok(Box::new(MemFile {
+            path: path.to_string(),
+            data: Cursor::new(data),
+            writable,
+            append: mode.append,
+            overlay: self.clone(),
+        }))
+    }
+}
+
+/// A file opened in an [`Overlay`]. Every write is stored back right away.
+struct MemFile {
+    path: String,
+    data: Cursor<Vec<u8>>,
+    writable: bool,
+    append: bool,
+    overlay: Overlay,
+}
+
+impl Read for MemFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(buf)
+    }
+}
+
+impl Seek for MemFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.data.seek(pos)
+    }
+}
+
+impl Write for MemFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.writable {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "file was not opened for writing"));
+        }
+        if self.append {
+            self.data.seek(SeekFrom::End(0))?;
+        }
+        let written = self.data.write(buf)?;
+        self.overlay.write(&self.path, self.data.get_ref(), false)?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+thread_local! {
+    static BACKEND: RefCell<Arc<dyn FsBackend>> = RefCell::new(default_backend());
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_backend() -> Arc<dyn FsBackend> {
+    Arc::new(Disk)
+}
+
+// The browser has no disk; scripts get a file system that lives as long as
+// the page.
+#[cfg(target_arch = "wasm32")]
+thread_local! {
+    static PAGE_FILES: Overlay = Overlay::empty();
+}
+
+#[cfg(target_arch = "wasm32")]
+fn default_backend() -> Arc<dyn FsBackend> {
+    Arc::new(page_files())
+}
+
+/// The playground's virtual file system, shared by every run on the page.
+#[cfg(target_arch = "wasm32")]
+pub fn page_files() -> Overlay {
+    PAGE_FILES.with(Overlay::clone)
+}
+
+/// The backend file operations on this thread go through.
+pub fn backend() -> Arc<dyn FsBackend> {
+    BACKEND.with(|backend| backend.borrow().clone())
+}
+
+/// Sends this thread's file operations to `backend`, returning the previous
+/// one.
+pub fn set_backend(backend: Arc<dyn FsBackend>) -> Arc<dyn FsBackend> {
+    BACKEND.with(|current| current.replace(backend))
+}
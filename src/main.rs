@@ -12,13 +12,22 @@ mod eval;
 mod errors;
 mod libraries;
 mod diagnostics;
+mod config;
+mod stats;
+mod history;
+mod coverage;
+mod bench;
+mod crash;
+mod trace;
+mod output;
 
 use parser::Parser as ZkParser;
 use eval::statement::evaluate_statement;
 use environment::{Environment, Value};
+use environment::snapshot::EnvDiff;
 use ast::Stmt;
-use errors::{extract_exit_code, push_error, print_and_clear_errors};
-use diagnostics::{run_program_collecting, ExecutionMode};
+use errors::{extract_exit_code, push_error, print_and_clear_errors, print_and_clear_warnings};
+use diagnostics::{check_program, run_main, run_program_collecting, run_program_stepping, ExecutionMode};
 
 /// Zekken Language CLI
 #[derive(Parser)]
@@ -37,14 +46,125 @@ enum Commands {
         /// Run using the register bytecode VM in src/bytecode
         #[arg(long)]
         vm: bool,
+        /// Require every checked expression to have a statically known type
+        #[arg(long)]
+        typed: bool,
+        /// Print interpreter statistics to stderr when the script finishes
+        #[arg(long)]
+        stats: bool,
+        /// Write a trace of calls, statements, assignments and errors to this file, in Chrome's trace event format
+        #[arg(long, value_name = "FILE")]
+        trace_out: Option<String>,
+        /// Report the names each include brings into scope
+        #[arg(long)]
+        trace_imports: bool,
+        /// Grow arrays instead of failing when assigning past their end
+        #[arg(long)]
+        permissive: bool,
+        /// Stop any loop that runs more than this many times (0: no limit)
+        #[arg(long, default_value_t = 0)]
+        max_iterations: u64,
+        /// Fail a call made while this many calls are already in progress (0: no limit)
+        #[arg(long, default_value_t = config::DEFAULT_MAX_CALL_DEPTH)]
+        max_call_depth: u64,
+        /// Warn before running about loops whose condition nothing in the body changes
+        #[arg(long)]
+        loop_warn: bool,
+        /// Keep file changes in memory; the script reads the disk but never writes to it
+        #[arg(long)]
+        sandbox: bool,
+        /// Give caught errors an env_eval method that evaluates an expression where the error was caught
+        #[arg(long)]
+        env_eval: bool,
+        /// Write the script's output as soon as it is printed, instead of a line at a time
+        #[arg(long)]
+        unbuffered: bool,
+        /// Print the value of the script's last top-level expression, as `eval` does
+        #[arg(long)]
+        print_result: bool,
+        /// Language edition to hold the script to, instead of Zekken.toml's
+        #[arg(long, value_parser = parse_edition)]
+        edition: Option<config::Edition>,
         /// Extra script arguments forwarded to the running Zekken program
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         script_args: Vec<String>,
     },
 
+    /// Run a snippet of code and print the value of its last expression
+    Eval {
+        /// The code to run
+        code: String,
+        /// Run using the register bytecode VM in src/bytecode
+        #[arg(long)]
+        vm: bool,
+    },
+
+    /// Report diagnostics for a script without running it
+    Check {
+        /// The script file to check
+        file: String,
+        /// Also report functions, exports and includes that are never used
+        #[arg(long)]
+        unused: bool,
+        /// Also report loops whose condition nothing in the body changes
+        #[arg(long)]
+        loop_warn: bool,
+        /// Require every checked expression to have a statically known type
+        #[arg(long)]
+        typed: bool,
+        /// Print the diagnostics as a JSON array on stdout
+        #[arg(long)]
+        json: bool,
+        /// Language edition to hold the script to, instead of Zekken.toml's
+        #[arg(long, value_parser = parse_edition)]
+        edition: Option<config::Edition>,
+    },
+
+    /// Run a test script; it passes when it finishes without errors
+    Test {
+        /// The test script to run
+        file: String,
+        /// Run using the register bytecode VM in src/bytecode
+        #[arg(long)]
+        vm: bool,
+        /// Report which statements ran and write an lcov tracefile
+        #[arg(long)]
+        coverage: bool,
+        /// Where `--coverage` writes its lcov tracefile
+        #[arg(long, default_value = "lcov.info")]
+        lcov: String,
+        /// Fail a call made while this many calls are already in progress (0: no limit)
+        #[arg(long, default_value_t = config::DEFAULT_MAX_CALL_DEPTH)]
+        max_call_depth: u64,
+        /// Keep file changes in memory; the script reads the disk but never writes to it
+        #[arg(long)]
+        sandbox: bool,
+        /// Give caught errors an env_eval method that evaluates an expression where the error was caught
+        #[arg(long)]
+        env_eval: bool,
+        /// Write the script's output as soon as it is printed, instead of a line at a time
+        #[arg(long)]
+        unbuffered: bool,
+        /// Language edition to hold the script to, instead of Zekken.toml's
+        #[arg(long, value_parser = parse_edition)]
+        edition: Option<config::Edition>,
+    },
+
     /// Start a Zekken REPL
     Repl,
 
+    /// Time the built-in micro-benchmarks under both engines
+    Bench {
+        /// Only run benchmarks whose name contains this text
+        filter: Option<String>,
+        /// Loop iterations per benchmark
+        #[arg(long, default_value_t = 200_000)]
+        iterations: u64,
+        /// Runs per engine; the fastest is reported
+        #[arg(long, default_value_t = 3)]
+        runs: u32,
+    },
+
     /// Debug helpers (lexer/AST dumps)
     Debug {
         #[command(subcommand)]
@@ -64,22 +184,81 @@ enum DebugCommands {
     Ast {
         /// The script file to parse
         file: String,
+        /// Print the AST as JSON instead of the debug tree
+        #[arg(long)]
+        json: bool,
     },
+
+    /// Run a script one top-level statement at a time, showing the bindings
+    /// each statement added, changed or removed
+    Step {
+        /// The script file to step through
+        file: String,
+        /// Run using the register bytecode VM in src/bytecode
+        #[arg(long)]
+        vm: bool,
+        /// Most changes `history` keeps per variable
+        #[arg(long, default_value_t = history::DEFAULT_CAP)]
+        history_cap: usize,
+    },
+}
+
+fn parse_edition(raw: &str) -> Result<config::Edition, String> {
+    config::Edition::parse(raw).ok_or_else(|| format!("expected one of: {}", config::Edition::NAMES.join(", ")))
+}
+
+/// Puts an in-memory overlay over the disk for `--sandbox`.
+fn sandbox_files() {
+    use libraries::vfs::{set_backend, Disk, Overlay};
+    set_backend(std::sync::Arc::new(Overlay::over(std::sync::Arc::new(Disk))));
+}
+
+/// Echoes a program's result the way the REPL does; `void` prints nothing.
+fn print_result_value(value: Option<Value>) {
+    if let Some(value) = value.filter(|value| !matches!(value, Value::Void)) {
+        let _ = output::print(&format!("{}\n", value));
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
+    let depth = match &cli.command {
+        Commands::Run { max_call_depth, .. } | Commands::Test { max_call_depth, .. } => *max_call_depth,
+        _ => config::DEFAULT_MAX_CALL_DEPTH,
+    };
+    // Zekken calls recurse on the native stack, so scripts run on a thread
+    // with room for as many calls as they are allowed to make.
+    let interpreter = std::thread::Builder::new()
+        .name("main".to_string())
+        .stack_size(config::stack_size_for(depth))
+        .spawn(move || run(cli))
+        .unwrap_or_else(|err| {
+            eprintln!("Error starting the interpreter: {}", err);
+            process::exit(1)
+        });
+    if interpreter.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn run(cli: Cli) {
+    crash::install();
 
     match &cli.command {
-        Commands::Run { file, vm, script_args: _ } => {
+        Commands::Run { file, vm, typed, stats, trace_out, trace_imports, permissive, max_iterations, max_call_depth, loop_warn, sandbox, env_eval, unbuffered, print_result, edition, script_args } => {
+            config::set_typed(*typed);
+            config::set_trace_imports(*trace_imports);
+            config::set_permissive(*permissive);
+            config::set_max_iterations(*max_iterations);
+            config::set_max_call_depth(*max_call_depth);
+            config::set_env_eval(*env_eval);
+            output::set_unbuffered(*unbuffered);
             std::env::set_var("ZEKKEN_CURRENT_FILE", file);
             let source_code = fs::read_to_string(file).unwrap_or_else(|err| {
                 eprintln!("Error reading file {}: {}", file, err);
                 process::exit(1)
             });
-
-            let mut parser = ZkParser::new();
-            let ast = parser.produce_ast(source_code);
+            crash::watch(file, &source_code);
 
             let mut env = Environment::new();
 
@@ -89,15 +268,60 @@ fn main() {
                 .to_string_lossy()
                 .to_string();
 
-            env.declare("ZEKKEN_CURRENT_DIR".to_string(), Value::String(current_dir), false);
+            env.declare("ZEKKEN_CURRENT_DIR".to_string(), Value::String(current_dir.clone()), false);
 
-            let report = run_program_collecting(
-                &ast,
-                &parser.errors,
-                &mut env,
-                if *vm { ExecutionMode::Bytecode } else { ExecutionMode::TreeWalk },
-            );
+            match config::ProjectConfig::discover(std::path::Path::new(&current_dir)) {
+                Ok(Some(project)) => config::set_active(project),
+                Ok(None) => {}
+                Err(err) => {
+                    eprintln!("Error loading {}: {}", config::MANIFEST_NAME, err);
+                    process::exit(1);
+                }
+            }
+            if let Some(edition) = edition {
+                config::set_edition(*edition);
+            }
+            if *sandbox {
+                sandbox_files();
+            }
+
+            // Parse after loading Zekken.toml, which can turn on keyword aliases.
+            let mut parser = ZkParser::new();
+            let ast = parser.produce_ast_cached(source_code);
+
+            if *loop_warn {
+                let mut errors = Vec::new();
+                eval::loops::collect_stuck_loops(&ast, &mut errors);
+                print_and_clear_warnings();
+                for error in errors {
+                    push_error(error);
+                }
+                if print_and_clear_errors() {
+                    process::exit(1);
+                }
+            }
+
+            if *stats {
+                stats::enable();
+            }
+            let started = std::time::Instant::now();
+            let mode = if *vm { ExecutionMode::Bytecode } else { ExecutionMode::TreeWalk };
+            if trace_out.is_some() {
+                trace::start();
+            }
+            let mut report = run_program_collecting(&ast, &parser.errors, &mut env, mode);
+            run_main(&ast, &mut env, mode, script_args, &mut report);
+            print_and_clear_warnings();
+            if let Some(path) = trace_out {
+                if let Err(err) = fs::write(path, trace::finish().to_string()) {
+                    eprintln!("Error writing trace {}: {}", path, err);
+                }
+            }
+            if *stats {
+                output::diagnostic(format_args!("Statistics:\n{}  {:<18}{:.2?}", stats::snapshot(), "elapsed", started.elapsed()));
+            }
             if let Some(code) = report.exit_code {
+                let _ = output::flush();
                 process::exit(code);
             }
             for error in report.errors {
@@ -109,18 +333,198 @@ fn main() {
                 std::process::exit(1);
             }
 
-            // `zekken run` does not implicitly print the last expression value.
-            // Use `@println` for output. (REPL remains expression-result oriented.)
-            let _ = report.value; // keep evaluation for side effects
-            io::stdout().flush().unwrap();
+            // Scripts print with `@println`; the last expression's value is
+            // only echoed when asked for, as the REPL and `eval` always do.
+            if *print_result {
+                print_result_value(report.value);
+            }
+            let _ = output::flush();
+            process::exit(0);
+        }
+        Commands::Eval { code, vm } => {
+            match config::ProjectConfig::discover(std::path::Path::new(".")) {
+                Ok(Some(project)) => config::set_active(project),
+                Ok(None) => {}
+                Err(err) => eprintln!("Ignoring {}: {}", config::MANIFEST_NAME, err),
+            }
+            let mut env = Environment::new();
+            let mut parser = ZkParser::new();
+            let ast = parser.produce_ast(code.clone());
+            let mode = if *vm { ExecutionMode::Bytecode } else { ExecutionMode::TreeWalk };
+            let report = run_program_collecting(&ast, &parser.errors, &mut env, mode);
+            // There is no file to quote, so point into the code itself.
+            for mut warning in errors::take_warnings() {
+                warning.error = warning.error.relocated("<eval>", code, 1);
+                output::diagnostic(warning);
+            }
+            if let Some(code) = report.exit_code {
+                let _ = output::flush();
+                process::exit(code);
+            }
+            for error in report.errors {
+                push_error(error.relocated("<eval>", code, 1));
+            }
+            if print_and_clear_errors() {
+                process::exit(1);
+            }
+            print_result_value(report.value);
+            let _ = output::flush();
+            process::exit(0);
+        }
+        Commands::Check { file, unused, loop_warn, typed, json, edition } => {
+            config::set_typed(*typed);
+            std::env::set_var("ZEKKEN_CURRENT_FILE", file);
+            let source_code = fs::read_to_string(file).unwrap_or_else(|err| {
+                eprintln!("Error reading file {}: {}", file, err);
+                process::exit(1)
+            });
+            crash::watch(file, &source_code);
+
+            let mut env = Environment::new();
+            let file_path = std::path::Path::new(file);
+            let current_dir = file_path.parent()
+                .unwrap_or_else(|| std::path::Path::new(""))
+                .to_string_lossy()
+                .to_string();
+            env.declare("ZEKKEN_CURRENT_DIR".to_string(), Value::String(current_dir.clone()), false);
+
+            match config::ProjectConfig::discover(std::path::Path::new(&current_dir)) {
+                Ok(Some(project)) => config::set_active(project),
+                Ok(None) => {}
+                Err(err) => {
+                    eprintln!("Error loading {}: {}", config::MANIFEST_NAME, err);
+                    process::exit(1);
+                }
+            }
+            if let Some(edition) = edition {
+                config::set_edition(*edition);
+            }
+
+            // Parse after loading Zekken.toml, which can turn on keyword aliases.
+            let mut parser = ZkParser::new();
+            let ast = parser.produce_ast(source_code);
+
+            let mut errors = check_program(&ast, &parser.errors, &mut env);
+            if *loop_warn {
+                eval::loops::collect_stuck_loops(&ast, &mut errors);
+            }
+            if *unused {
+                if let Err(err) = eval::unused::collect_unused(file_path, &mut errors) {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                }
+            }
+
+            if *json {
+                let mut errors = errors;
+                errors.extend(errors::take_collected_errors());
+                errors::sort_and_dedup_errors(&mut errors);
+                let diagnostics: Vec<serde_json::Value> = errors::take_warnings()
+                    .iter()
+                    .map(errors::ZekkenWarning::to_json)
+                    .chain(errors.iter().map(errors::ZekkenError::to_json))
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&diagnostics).unwrap_or_default());
+                process::exit(if errors.is_empty() { 0 } else { 1 });
+            }
+
+            print_and_clear_warnings();
+            for error in errors {
+                push_error(error);
+            }
+            if print_and_clear_errors() {
+                process::exit(1);
+            }
             process::exit(0);
         }
+        Commands::Test { file, vm, coverage, lcov, max_call_depth, sandbox, env_eval, unbuffered, edition } => {
+            config::set_max_call_depth(*max_call_depth);
+            config::set_env_eval(*env_eval);
+            output::set_unbuffered(*unbuffered);
+            std::env::set_var("ZEKKEN_CURRENT_FILE", file);
+            let source_code = fs::read_to_string(file).unwrap_or_else(|err| {
+                eprintln!("Error reading file {}: {}", file, err);
+                process::exit(1)
+            });
+            crash::watch(file, &source_code);
+
+            let mut env = Environment::new();
+            let file_path = std::path::Path::new(file);
+            let current_dir = file_path.parent()
+                .unwrap_or_else(|| std::path::Path::new(""))
+                .to_string_lossy()
+                .to_string();
+            env.declare("ZEKKEN_CURRENT_DIR".to_string(), Value::String(current_dir.clone()), false);
+
+            match config::ProjectConfig::discover(std::path::Path::new(&current_dir)) {
+                Ok(Some(project)) => config::set_active(project),
+                Ok(None) => {}
+                Err(err) => {
+                    eprintln!("Error loading {}: {}", config::MANIFEST_NAME, err);
+                    process::exit(1);
+                }
+            }
+            if let Some(edition) = edition {
+                config::set_edition(*edition);
+            }
+            if *sandbox {
+                sandbox_files();
+            }
+
+            let mut parser = ZkParser::new();
+            let ast = parser.produce_ast(source_code);
+
+            if *coverage {
+                coverage::start();
+            }
+            let mode = if *vm { ExecutionMode::Bytecode } else { ExecutionMode::TreeWalk };
+            let mut report = run_program_collecting(&ast, &parser.errors, &mut env, mode);
+            run_main(&ast, &mut env, mode, &[], &mut report);
+            let hits = coverage::stop();
+            print_and_clear_warnings();
+            let _ = output::flush();
+
+            let failed_exit = report.exit_code.filter(|code| *code != 0);
+            for error in report.errors {
+                push_error(error);
+            }
+            let passed = !print_and_clear_errors() && failed_exit.is_none();
+            match failed_exit {
+                Some(code) => println!("test {} ... FAILED (exit code {})", file, code),
+                None => println!("test {} ... {}", file, if passed { "ok" } else { "FAILED" }),
+            }
+
+            if *coverage {
+                let report = coverage::Report::new(&ast, &hits);
+                print!("{}", report);
+                if let Err(err) = fs::write(lcov, report.to_lcov(file)) {
+                    eprintln!("Error writing {}: {}", lcov, err);
+                    process::exit(1);
+                }
+                println!("  lcov written to {}", lcov);
+            }
+            process::exit(if passed { 0 } else { 1 });
+        }
         Commands::Repl => {
             // Enable REPL-friendly error formatting
             *errors::REPL_MODE.lock().unwrap() = true;
             println!("Zekken REPL (type 'exit' or Ctrl+C to quit)");
+            match config::ProjectConfig::discover(std::path::Path::new(".")) {
+                Ok(Some(project)) => config::set_active(project),
+                Ok(None) => {}
+                Err(err) => eprintln!("Ignoring {}: {}", config::MANIFEST_NAME, err),
+            }
             let mut env = Environment::new();
+            // What the most recent input changed, shown by `:diff`.
+            let mut last_diff = EnvDiff::default();
+            // Results echoed so far; the nth is also bound to `_n`.
+            let mut results = 0;
+            // Every line of code entered so far, which `:why` reports errors against.
+            let mut session: Vec<String> = Vec::new();
+            // The errors of the most recent input that failed, for `:why`.
+            let mut last_errors: Vec<errors::ZekkenError> = Vec::new();
             loop {
+                let _ = output::flush();
                 print!("> ");
                 io::stdout().flush().unwrap();
                 let mut input = String::new();
@@ -134,28 +538,74 @@ fn main() {
                 if line.is_empty() {
                     continue;
                 }
+                if let Some(command) = line.strip_prefix(':') {
+                    match command {
+                        "diff" if last_diff.is_empty() => println!("(no changes)"),
+                        "diff" => print!("{}", last_diff),
+                        "why" if last_errors.is_empty() => println!("(no error to explain)"),
+                        "why" => {
+                            for error in &last_errors {
+                                print!("{}", error.full_report());
+                            }
+                        }
+                        other => println!("Unknown REPL command ':{}' (available: :diff, :why)", other),
+                    }
+                    continue;
+                }
+                session.push(line.to_string());
+                crash::watch("<repl>", line);
+                let relocate = |errors: &[errors::ZekkenError]| -> Vec<errors::ZekkenError> {
+                    let source = session.join("\n");
+                    errors.iter().map(|error| error.relocated("<repl>", &source, session.len())).collect()
+                };
                 let mut parser = ZkParser::new();
                 let ast = parser.produce_ast(line.to_string());
                 for error in &parser.errors {
                     println!("{}", error); // Will use REPL-friendly format
                 }
                 if !parser.errors.is_empty() {
+                    last_errors = relocate(&parser.errors);
                     continue;
                 }
-                match evaluate_statement(&Stmt::Program(ast), &mut env) {
+                let before = env.snapshot();
+                crash::statement(1);
+                let result = evaluate_statement(&Stmt::Program(ast), &mut env);
+                last_diff = before.diff(&env.snapshot());
+                for warning in errors::take_warnings() {
+                    println!("{}", warning);
+                }
+                match result {
                     Ok(Some(Value::Void)) | Ok(None) => {}
-                    Ok(Some(val)) => println!("{}", val),
+                    Ok(Some(val)) => {
+                        println!("{}", val);
+                        env.bind_repl_result(&mut results, val);
+                    }
                     Err(e) => {
                         if let Some(_code) = extract_exit_code(&e.message) {
                             break;
                         }
-                        println!("{}", e)
-                    }, // Will use REPL-friendly format
+                        // Lint errors are collected globally; the returned one only says they exist.
+                        let mut failed = errors::take_collected_errors();
+                        if failed.is_empty() {
+                            failed.push(e);
+                        }
+                        for error in &failed {
+                            println!("{}", error); // Will use REPL-friendly format
+                        }
+                        last_errors = relocate(&failed);
+                    }
                 }
             }
             // Disable REPL mode after exiting
             *errors::REPL_MODE.lock().unwrap() = false;
         }
+        Commands::Bench { filter, iterations, runs } => {
+            std::env::set_var("ZEKKEN_DISABLE_PRINT", "1");
+            if let Err(err) = bench::run(filter.as_deref(), *iterations, *runs) {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
         Commands::Debug { command } => match command {
             DebugCommands::Tokens { file } => {
                 std::env::set_var("ZEKKEN_CURRENT_FILE", file);
@@ -164,16 +614,19 @@ fn main() {
                     process::exit(1)
                 });
 
-                let tokens = lexer::tokenize(source_code);
+                let (tokens, errors) = lexer::lex_with_comments(source_code);
                 for (i, t) in tokens.iter().enumerate() {
                     println!(
                         "{:04}  line={:<4} col={:<4}  kind={:?}  value={:?}",
                         i, t.line, t.column, t.kind, t.value
                     );
                 }
-                process::exit(0);
+                for error in &errors {
+                    eprintln!("{}", error);
+                }
+                process::exit(if errors.is_empty() { 0 } else { 1 });
             }
-            DebugCommands::Ast { file } => {
+            DebugCommands::Ast { file, json } => {
                 std::env::set_var("ZEKKEN_CURRENT_FILE", file);
                 let source_code = fs::read_to_string(file).unwrap_or_else(|err| {
                     eprintln!("Error reading file {}: {}", file, err);
@@ -190,7 +643,94 @@ fn main() {
                     process::exit(1);
                 }
 
-                println!("{:#?}", ast);
+                if *json {
+                    println!("{}", ZkParser::to_json(&ast));
+                } else {
+                    println!("{:#?}", ast);
+                }
+                process::exit(0);
+            }
+            DebugCommands::Step { file, vm, history_cap } => {
+                std::env::set_var("ZEKKEN_CURRENT_FILE", file);
+                let source_code = fs::read_to_string(file).unwrap_or_else(|err| {
+                    eprintln!("Error reading file {}: {}", file, err);
+                    process::exit(1)
+                });
+                let source_lines: Vec<String> = source_code.lines().map(str::to_string).collect();
+
+                let mut env = Environment::new();
+                let current_dir = std::path::Path::new(file)
+                    .parent()
+                    .unwrap_or_else(|| std::path::Path::new(""))
+                    .to_string_lossy()
+                    .to_string();
+                env.declare("ZEKKEN_CURRENT_DIR".to_string(), Value::String(current_dir.clone()), false);
+                if let Ok(Some(project)) = config::ProjectConfig::discover(std::path::Path::new(&current_dir)) {
+                    config::set_active(project);
+                }
+                let mut parser = ZkParser::new();
+                let ast = parser.produce_ast(source_code);
+
+                println!(
+                    "Stepping through {} (Enter: next statement, c: run to the end, history <name>: how a variable changed, eval <expr>: evaluate here, q: quit)",
+                    file
+                );
+                history::record_with_cap(*history_cap);
+                let stdin = io::stdin();
+                let mut running = false;
+                let report = run_program_stepping(
+                    &ast,
+                    &parser.errors,
+                    &mut env,
+                    if *vm { ExecutionMode::Bytecode } else { ExecutionMode::TreeWalk },
+                    &mut |location, diff, scope| {
+                        if running {
+                            return true;
+                        }
+                        let source = source_lines.get(location.line.saturating_sub(1)).map_or("", |l| l.trim());
+                        println!("{:>4} | {}", location.line, source);
+                        print!("{}", diff);
+                        loop {
+                            let _ = output::flush();
+                            print!("(step) ");
+                            io::stdout().flush().unwrap();
+                            let mut command = String::new();
+                            if stdin.read_line(&mut command).unwrap_or(0) == 0 {
+                                running = true;
+                                return true;
+                            }
+                            if let Some(expr) = command.trim().strip_prefix("eval ") {
+                                match eval::env_eval::eval_frozen(expr, scope) {
+                                    Ok(value) => println!("{}", value),
+                                    Err(message) => println!("error: {}", message),
+                                }
+                                continue;
+                            }
+                            match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+                                ["c" | "continue"] => {
+                                    running = true;
+                                    return true;
+                                }
+                                ["q" | "quit"] => return false,
+                                ["history", name] => match history::of(name) {
+                                    Some(changes) => print!("{}", changes),
+                                    None => println!("'{}' has not been assigned yet", name),
+                                },
+                                _ => return true,
+                            }
+                        }
+                    },
+                );
+                print_and_clear_warnings();
+                if let Some(code) = report.exit_code {
+                    process::exit(code);
+                }
+                for error in report.errors {
+                    push_error(error);
+                }
+                if print_and_clear_errors() {
+                    process::exit(1);
+                }
                 process::exit(0);
             }
         },
@@ -0,0 +1,84 @@
+//! Everything a running script prints: its own output on stdout and the
+//! diagnostics about it on stderr.
+//!
+//! Script output is line-buffered. Text from `@print` waits until its line
+//! is finished or `@flush` runs, and `--unbuffered` sends every piece
+//! straight through. Diagnostics take the same lock and send whatever output
+//! is still waiting first, so an error report always starts on a line of its
+//! own instead of landing in the middle of one.
+
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+struct Output {
+    /// Written by the script but not yet sent to stdout.
+    pending: String,
+    unbuffered: bool,
+    /// The last text sent to stdout stopped partway through a line.
+    mid_line: bool,
+}
+
+lazy_static::lazy_static! {
+    static ref OUTPUT: Mutex<Output> = Mutex::new(Output {
+        pending: String::new(),
+        unbuffered: false,
+        mid_line: false,
+    });
+}
+
+/// How much of `pending` is ready to send: all of it when unbuffered,
+/// otherwise every finished line.
+pub fn ready_len(pending: &str, unbuffered: bool) -> usize {
+    if unbuffered {
+        pending.len()
+    } else {
+        pending.rfind('\n').map_or(0, |end| end + 1)
+    }
+}
+
+impl Output {
+    fn send(&mut self, len: usize) -> io::Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&self.pending.as_bytes()[..len])?;
+        stdout.flush()?;
+        self.mid_line = !self.pending[..len].ends_with('\n');
+        self.pending.drain(..len);
+        Ok(())
+    }
+}
+
+/// `--unbuffered`: send script output as soon as it is written.
+#[allow(dead_code)]
+pub fn set_unbuffered(unbuffered: bool) {
+    OUTPUT.lock().unwrap().unbuffered = unbuffered;
+}
+
+/// Writes script output.
+pub fn print(text: &str) -> io::Result<()> {
+    let mut output = OUTPUT.lock().unwrap();
+    output.pending.push_str(text);
+    let len = ready_len(&output.pending, output.unbuffered);
+    output.send(len)
+}
+
+/// Sends any script output still waiting for the end of its line.
+pub fn flush() -> io::Result<()> {
+    let mut output = OUTPUT.lock().unwrap();
+    let len = output.pending.len();
+    output.send(len)
+}
+
+/// Writes `text` and a newline to stderr, after the script output so far.
+pub fn diagnostic(text: impl std::fmt::Display) {
+    let mut output = OUTPUT.lock().unwrap();
+    let len = output.pending.len();
+    let _ = output.send(len);
+    let mut stderr = io::stderr().lock();
+    if std::mem::take(&mut output.mid_line) {
+        let _ = writeln!(stderr);
+    }
+    let _ = writeln!(stderr, "{}", text);
+}
@@ -0,0 +1,104 @@
+//! On-disk cache of parsed programs.
+//!
+//! `zekken run` and `include` look a source file up here before lexing and
+//! parsing it. Entries are keyed by a hash of the source text, the Zekken
+//! version, the keyword-alias setting and a build-time hash of the lexer,
+//! parser and AST sources, so an edited file (or a different interpreter)
+//! simply misses the cache. Entries live in the project's
+//! `zk_modules/.cache/ast` when a `Zekken.toml` is active, otherwise in the
+//! OS cache directory. Deleting either directory is always safe.
+
+// The wasm build has no filesystem and never caches.
+#![allow(dead_code)]
+
+use super::Parser;
+use crate::ast::Program;
+use std::path::{Path, PathBuf};
+
+/// Set to `1` or `true` to always parse from source.
+pub const DISABLE_VAR: &str = "ZEKKEN_NO_CACHE";
+
+/// Directory that holds cached ASTs for the current process, or `None` when
+/// caching is unavailable or turned off.
+pub fn cache_dir() -> Option<PathBuf> {
+    if cfg!(target_arch = "wasm32") {
+        return None;
+    }
+    if matches!(std::env::var(DISABLE_VAR), Ok(v) if v == "1" || v.eq_ignore_ascii_case("true")) {
+        return None;
+    }
+    if let Some(manifest) = crate::config::manifest_path() {
+        let project = manifest.parent()?;
+        return Some(project.join("zk_modules").join(".cache").join("ast"));
+    }
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(base.join("zekken").join("ast"))
+}
+
+/// Bumped whenever the same source starts parsing to a different AST, so
+/// entries written by an older build of this version miss. Changes under
+/// `src/ast`, `src/lexer` and `src/parser` also change [`AST_SCHEMA`], so a
+/// missed bump there cannot serve stale entries.
+const AST_FORMAT: &str = "14";
+
+/// Hash of the parse-related sources, computed by `build.rs`.
+const AST_SCHEMA: &str = env!("ZEKKEN_AST_SCHEMA");
+
+/// Cache file name for `source`. FNV-1a keeps keys stable across Rust
+/// releases, unlike `DefaultHasher`.
+fn entry_name(source: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let aliases = if crate::config::aliases() { "1" } else { "0" };
+    for bytes in [env!("CARGO_PKG_VERSION").as_bytes(), AST_FORMAT.as_bytes(), AST_SCHEMA.as_bytes(), aliases.as_bytes(), source.as_bytes()] {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        // Separator so ("ab", "c") and ("a", "bc") hash differently.
+        hash ^= 0xff;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}.json", hash)
+}
+
+/// The cached program for `source`, if one was stored and still decodes.
+pub fn load(dir: &Path, source: &str) -> Option<Program> {
+    let json = std::fs::read_to_string(dir.join(entry_name(source))).ok()?;
+    Parser::from_json(&json).ok()
+}
+
+/// Stores `program` as the parse of `source`. Failures are ignored: the cache
+/// only ever saves work.
+pub fn store(dir: &Path, source: &str, program: &Program) {
+    if std::fs::create_dir_all(dir).is_err() {
+        return;
+    }
+    let path = dir.join(entry_name(source));
+    // Write then rename, so a concurrent run never reads a half-written entry.
+    let temp = path.with_extension(format!("tmp{}", std::process::id()));
+    if std::fs::write(&temp, Parser::to_json(program)).is_ok() && std::fs::rename(&temp, &path).is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+}
+
+impl Parser {
+    /// Like [`Parser::produce_ast`], but reuses a cached parse of `source` when
+    /// one exists. Only programs that parsed without errors are cached, so
+    /// syntax errors are always reported fresh.
+    pub fn produce_ast_cached(&mut self, source_code: String) -> Program {
+        let Some(dir) = cache_dir() else {
+            return self.produce_ast(source_code);
+        };
+        if let Some(program) = load(&dir, &source_code) {
+            return program;
+        }
+        let program = self.produce_ast(source_code.clone());
+        if self.errors.is_empty() {
+            store(&dir, &source_code, &program);
+        }
+        program
+    }
+}
@@ -5,11 +5,16 @@ use crate::lexer::*;
 use crate::errors::{ZekkenError};
 use std::panic::{catch_unwind, AssertUnwindSafe};
 
+pub mod cache;
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
     virtual_pipe_tokens: usize,
     pipe_expression_depth: usize,
+    // Labels of the loops enclosing the current statement (`None` when
+    // unlabelled), reset inside function bodies.
+    loop_labels: Vec<Option<String>>,
     debug_parser: bool,
     recovery_mode: bool,
     pub errors: Vec<ZekkenError>,
@@ -22,6 +27,7 @@ impl Parser {
             current: 0,
             virtual_pipe_tokens: 0,
             pipe_expression_depth: 0,
+            loop_labels: Vec::new(),
             debug_parser: matches!(
                 std::env::var("ZEKKEN_DEBUG_PARSER"),
                 Ok(v) if v == "1" || v.eq_ignore_ascii_case("true")
@@ -40,7 +46,8 @@ impl Parser {
             std::env::set_var("ZEKKEN_SOURCE_LINES", &source_code);
         }
 
-        let tokens = tokenize(source_code);
+        let (tokens, lex_errors) = lex(source_code);
+        self.errors.extend(lex_errors);
 
         #[cfg(not(target_arch = "wasm32"))]
         {
@@ -59,6 +66,7 @@ impl Parser {
         self.current = 0;
         self.virtual_pipe_tokens = 0;
         self.pipe_expression_depth = 0;
+        self.loop_labels.clear();
     
         let start_location = self.at().location();
         let mut program = Program {
@@ -67,19 +75,16 @@ impl Parser {
             location: start_location,
         };
     
-        // First pass: collect imports and comments
+        // First pass: collect imports
         while self.not_eof() {
             match self.at().kind {
-                TokenType::SingleLineComment | TokenType::MultiLineComment => {
-                    self.consume();
-                },
                 TokenType::Use | TokenType::Include => {
                     program.imports.push(self.parse_stmt_resilient());
                 },
                 TokenType::EOF => break,
                 _ => {
                     // Parse any other statements as program content
-                    program.content.push(Box::new(self.parse_stmt_resilient()));
+                    program.content.push(self.parse_stmt_resilient());
                 }
             }
         }
@@ -87,10 +92,15 @@ impl Parser {
         program
     }
 
-    fn skip_comments(&mut self) {
-        while self.not_eof() && matches!(self.at().kind, TokenType::SingleLineComment | TokenType::MultiLineComment) {
-            self.consume();
-        }
+    /// Serializes a parsed program as JSON, for external tooling and for
+    /// caching ASTs between runs.
+    pub fn to_json(program: &Program) -> String {
+        serde_json::to_string(program).expect("AST nodes always serialize")
+    }
+
+    /// Rebuilds a program from the JSON produced by [`Parser::to_json`].
+    pub fn from_json(json: &str) -> Result<Program, String> {
+        serde_json::from_str(json).map_err(|err| format!("Invalid AST JSON: {}", err))
     }
 
     fn synchronize_statement(&mut self) {
@@ -178,12 +188,15 @@ impl Parser {
                 TokenType::Identifier
                     | TokenType::Int
                     | TokenType::Float
+                    | TokenType::Decimal
                     | TokenType::String
+                    | TokenType::RawString
                     | TokenType::Boolean(_)
                     | TokenType::At
                     | TokenType::OpenParen
                     | TokenType::OpenBrace
                     | TokenType::OpenBracket
+                    | TokenType::Hash
                     | TokenType::ArithOp(ArithOp::Sub)
                     | TokenType::BinOp(BinOp::Not)
             )
@@ -225,13 +238,10 @@ impl Parser {
         } else {
             format!("{:?} ({})", token.kind, token.value)
         };
-        self.errors.push(ZekkenError::syntax(
-            err,
-            token.line,
-            token.column,
-            Some("Pipe"),
-            Some(&found),
-        ));
+        self.errors.push(
+            ZekkenError::syntax(err, token.line, token.column, Some("Pipe"), Some(&found))
+                .with_fix("add '|' before this"),
+        );
     }
 
     fn parse_pipe_expression(&mut self) -> Content {
@@ -262,43 +272,72 @@ impl Parser {
 
             let found = if token.line != prev_token.line {
                 "end of line".to_string()
-            } else if matches!(token.kind, TokenType::SingleLineComment | TokenType::MultiLineComment) {
-                "comment".to_string()
             } else if token.kind == TokenType::EOF {
                 "end of file".to_string()
             } else {
                 format!("{:?} ({})", token.kind, token.value)
             };
 
-            let error = ZekkenError::syntax(
+            let mut error = ZekkenError::syntax(
                 err,
                 prev_token.line,
                 prev_token.column + prev_token.value.len().max(1) - 1,
                 Some(&format!("{:?}", type_)),
                 Some(&found),
             );
+            if let Some((text, _)) = crate::lexer::TOKEN_CHAR.iter().find(|(_, kind)| *kind == type_) {
+                error = error.with_fix(format!("add '{}' after this", text));
+            }
             self.errors.push(error.clone());
             // Do NOT call push_error here; only main.rs should push parser.errors to the global list
             None
         }
     }
 
+    // Whether the current token ends a statement without a `;`: it starts a
+    // new line or closes the enclosing block or file.
+    fn at_statement_end(&self) -> bool {
+        let token = self.at();
+        let on_new_line = self.current > 0 && token.line > self.tokens[self.current - 1].line;
+        on_new_line
+            || matches!(
+                token.kind,
+                TokenType::Semicolon | TokenType::CloseBrace | TokenType::EOF
+            )
+    }
+
+    // Declarations and `return`/`defer`/`use`/`include`/`export` end at a `;`
+    // or, when that is left out, at the end of the line.
+    fn expect_statement_end(&mut self, err: &str) -> bool {
+        if self.at().kind == TokenType::Semicolon {
+            self.consume();
+            return true;
+        }
+        if self.at_statement_end() {
+            return true;
+        }
+        self.expect(TokenType::Semicolon, err).is_some()
+    }
+
     fn parse_stmt(&mut self) -> Content {
         match self.at().kind {
-            TokenType::SingleLineComment | TokenType::MultiLineComment => {
-                self.skip_comments();
-                return self.parse_stmt();
-            }
             TokenType::Let | TokenType::Const => self.parse_var_decl(),
             TokenType::Func => self.parse_func_decl(),
             TokenType::If => self.parse_if_stmt(),
-            TokenType::For => self.parse_for_stmt(),
-            TokenType::While => self.parse_while_stmt(),
+            TokenType::For => self.parse_for_stmt(None),
+            TokenType::While => self.parse_while_stmt(None),
+            TokenType::Loop => self.parse_loop_stmt(None),
+            TokenType::Identifier if self.at_loop_label() => self.parse_labeled_loop(),
+            TokenType::Break | TokenType::Continue => self.parse_break_stmt(),
             TokenType::Use => self.parse_use_stmt(),
             TokenType::Include => self.parse_include_stmt(),
             TokenType::Export => self.parse_export_stmt(),
             TokenType::Return => self.parse_return_stmt(),
             TokenType::Try => self.parse_try_catch_stmt(),
+            TokenType::Enum => self.parse_enum_decl(),
+            TokenType::Defer => self.parse_defer_stmt(),
+            TokenType::With => self.parse_with_stmt(),
+            TokenType::Switch => self.parse_switch_stmt(),
             _ => {
                 let expr = self.parse_expr();
                 if self.at().kind == TokenType::Semicolon {
@@ -309,14 +348,39 @@ impl Parser {
                         semicolon.column,
                         Some("end of line"),
                         Some("Semicolon (;)"),
-                    ));
+                    ).with_fix("remove this ';'"));
                     self.consume();
+                } else if !self.at_statement_end() {
+                    self.skip_tokens_after_expression();
                 }
                 expr
             }
         }
     }
 
+    // An expression statement ends with its line. Anything left on that line
+    // is skipped and reported once, rather than parsed as further statements
+    // that would each fail in their own confusing way.
+    fn skip_tokens_after_expression(&mut self) {
+        let first = self.at().clone();
+        let mut last = first.clone();
+        let mut found = Vec::new();
+        while self.not_eof() && self.at().line == first.line && self.at().kind != TokenType::CloseBrace {
+            last = self.at().clone();
+            found.push(last.value.clone());
+            self.consume();
+        }
+        let span_len = (last.column + last.length.max(1)).saturating_sub(first.column);
+        self.errors.push(ZekkenError::syntax_with_span(
+            "Unexpected tokens after expression",
+            first.line,
+            first.column,
+            span_len,
+            Some("end of line"),
+            Some(&found.join(" ")),
+        ));
+    }
+
     fn parse_var_decl(&mut self) -> Content {
         let start_location = self.at().location();
         let constant = matches!(self.at().kind, TokenType::Const);
@@ -371,15 +435,8 @@ impl Parser {
         let start_location = self.at().location();
         
         self.consume(); // Consume the ->
-        self.expect_pipe("Expected '|' after '->'");
-        let params = self.parse_params();
-        
-        self.expect_pipe("Expected '|' after parameters");
-        // Lambdas currently do not support an explicit return type annotation.
-        self.expect(TokenType::OpenBrace, "Expected '{' after parameters");
-        let body = self.parse_block_stmt();
-        self.expect(TokenType::CloseBrace, "Expected '}' after lambda body");
-        self.expect(TokenType::Semicolon, "Expected ';' after lambda declaration");
+        let (params, body) = self.parse_lambda_rest();
+        self.expect_statement_end("Expected ';' after lambda declaration");
         
         Content::Statement(Box::new(Stmt::Lambda(LambdaDecl {
             constant,
@@ -391,6 +448,29 @@ impl Parser {
         })))
     }
 
+
+    // The `|params| { body }` after a lambda's `->`.
+    fn parse_lambda_rest(&mut self) -> (Vec<Param>, Vec<Content>) {
+        self.expect_pipe("Expected '|' after '->'");
+        let params = self.parse_params();
+        
+        self.expect_pipe("Expected '|' after parameters");
+        // Lambdas currently do not support an explicit return type annotation.
+        self.expect(TokenType::OpenBrace, "Expected '{' after parameters");
+        let outer_loops = std::mem::take(&mut self.loop_labels);
+        let body = self.parse_block_stmt();
+        self.loop_labels = outer_loops;
+        self.expect(TokenType::CloseBrace, "Expected '}' after lambda body");
+        (params, body)
+    }
+
+    fn parse_lambda_expr(&mut self) -> Content {
+        let location = self.at().location();
+        self.consume(); // Consume the `fn`
+        self.expect(TokenType::ThinArrow, "Expected '->' after 'fn'");
+        let (params, body) = self.parse_lambda_rest();
+        Content::Expression(Box::new(Expr::Lambda(LambdaExpr { params, body, location })))
+    }
     fn parse_normal_var_decl(&mut self, constant: bool, ident: String, start_location: Location) -> Content {
         // First check if we have a type annotation
         if self.expect(TokenType::Colon, "Expected ':' after variable identifier").is_none() {
@@ -421,15 +501,16 @@ impl Parser {
             // Infer the type from the value
             let inferred_type = match &value {
                 Some(Content::Expression(expr)) => match &**expr {
-                    Expr::StringLit(_) => ("String", DataType::String),
+                    Expr::StringLit(_) | Expr::TemplateLit(_) => ("String", DataType::String),
                     Expr::IntLit(_) => ("Int", DataType::Int),
                     Expr::FloatLit(_) => ("Float", DataType::Float),
+                    Expr::DecimalLit(_) => ("Decimal", DataType::Decimal),
                     Expr::BoolLit(_) => ("Bool", DataType::Bool),
                     Expr::ObjectLit(_) => ("Object", DataType::Object),
                     Expr::ArrayLit(_) => ("Array", DataType::Array),
-                    _ => ("a type (int, float, string, bool, obj, arr, fn)", DataType::Any),
+                    _ => (crate::lexer::type_hint(), DataType::Any),
                 },
-                _ => ("a type (int, float, string, bool, obj, arr, fn)", DataType::Any),
+                _ => (crate::lexer::type_hint(), DataType::Any),
             };
 
             // Push error with the inferred type
@@ -441,7 +522,7 @@ impl Parser {
                 Some("AssignOp(Assign) (=)"),
             ));
 
-            self.expect(TokenType::Semicolon, "Expected ';' after variable declaration");
+            self.expect_statement_end("Expected ';' after variable declaration");
 
             return Content::Statement(Box::new(Stmt::VarDecl(VarDecl {
                 constant,
@@ -453,18 +534,19 @@ impl Parser {
         }
 
         // Otherwise, try to parse the type annotation
-        let type_token = match self.at().kind {
+        let type_token = match self.at().kind.clone() {
             TokenType::DataType(t) => {
                 self.consume();
-                if t == crate::lexer::DataType::Fn {
+                // `let f: fn -> |...| { }`; `let f: fn = value` is an ordinary declaration.
+                if t == crate::lexer::DataType::Fn && self.at().kind == TokenType::ThinArrow {
                     return self.parse_lambda_decl(constant, ident);
                 }
-                t
+                self.parse_type_args(t)
             },
             _ => {
                 let token = self.expect(
                     TokenType::DataType(crate::lexer::DataType::Any),
-                    "Expected type (int, float, string, bool, obj, arr, fn) after ':'"
+                    &format!("Expected type ({}) after ':'", crate::lexer::type_keywords()),
                 );
                 if token.is_none() {
                     // Same: consume until semicolon to avoid cascades
@@ -483,7 +565,7 @@ impl Parser {
                     })));
                 }
                 match token.unwrap().kind {
-                    TokenType::DataType(t) => t,
+                    TokenType::DataType(t) => self.parse_type_args(t),
                     _ => DataType::Any,
                 }
             }
@@ -524,19 +606,9 @@ impl Parser {
             })));
         }
 
-        // Add special handling for boolean literals
-        let value = if matches!(self.at().kind, TokenType::Boolean(_)) {
-            let bool_token = self.at().clone();
-            self.consume();
-            Some(Content::Expression(Box::new(Expr::BoolLit(BoolLit {
-                value: matches!(bool_token.kind, TokenType::Boolean(true)),
-                location: bool_token.location(),
-            }))))
-        } else {
-            Some(self.parse_expr())
-        };
+        let value = Some(self.parse_expr());
     
-        if self.expect(TokenType::Semicolon, "Expected ';' after variable declaration").is_none() {
+        if !self.expect_statement_end("Expected ';' after variable declaration") {
             return Content::Statement(Box::new(Stmt::VarDecl(VarDecl {
                 constant,
                 ident,
@@ -570,10 +642,10 @@ impl Parser {
         // Optional explicit return type: `func name |...| -> int { ... }`
         let return_type = if self.at().kind == TokenType::ThinArrow {
             self.consume(); // consume `->`
-            match self.at().kind {
+            match self.at().kind.clone() {
                 TokenType::DataType(t) => {
                     self.consume();
-                    Some(t)
+                    Some(self.parse_type_args(t))
                 }
                 _ => {
                     let token = self.at().clone();
@@ -592,12 +664,53 @@ impl Parser {
         };
 
         self.expect(TokenType::OpenBrace, "Expected '{' after parameters");
+        let outer_loops = std::mem::take(&mut self.loop_labels);
         let body = self.parse_block_stmt();
+        self.loop_labels = outer_loops;
         self.expect(TokenType::CloseBrace, "Expected '}' after parameters");
     
         Content::Statement(Box::new(Stmt::FuncDecl(FuncDecl { params, ident, return_type, body, location: start_location })))
     }
 
+    // What follows a type keyword `ty` that has just been consumed:
+    // `arr<T>` names an array whose elements are all `T`, and a trailing `?`
+    // also allows `null`.
+    fn parse_type_args(&mut self, ty: DataType) -> DataType {
+        let ty = if ty == DataType::Array && self.at().kind == TokenType::BinOp(BinOp::Less) {
+            self.parse_element_type()
+        } else {
+            ty
+        };
+        if self.at().kind == TokenType::Question {
+            self.consume();
+            return DataType::Nullable(Box::new(ty));
+        }
+        ty
+    }
+
+    // The `<T>` of `arr<T>`.
+    fn parse_element_type(&mut self) -> DataType {
+        self.consume(); // consume `<`
+        let element = match self.at().kind.clone() {
+            TokenType::DataType(t) => {
+                self.consume();
+                self.parse_type_args(t)
+            }
+            _ => {
+                let token = self.at().clone();
+                self.errors.push(ZekkenError::syntax(
+                    "Expected element type after 'arr<'",
+                    token.line,
+                    token.column,
+                    Some("DataType"),
+                    Some(&format!("{:?} ({})", token.kind, token.value)),
+                ));
+                DataType::Any
+            }
+        };
+        self.expect(TokenType::BinOp(BinOp::Greater), "Expected '>' after array element type");
+        DataType::ArrayOf(Box::new(element))
+    }
 
     fn parse_params(&mut self) -> Vec<Param> {
         let mut params = Vec::new();
@@ -624,15 +737,15 @@ impl Parser {
             self.expect(TokenType::Colon, "Expected ':' after parameter identifier");
     
             // Expect a type token
-            let type_ = match self.at().kind {
+            let type_ = match self.at().kind.clone() {
                 TokenType::DataType(t) => {
                     self.consume();
-                    t
+                    self.parse_type_args(t)
                 }
                 _ => {
                     let token = self.at().clone();
                     self.errors.push(ZekkenError::syntax(
-                        "Expected type (int, float, string, bool, obj, arr, fn, any) after ':'",
+                        &format!("Expected type ({}) after ':'", crate::lexer::type_keywords()),
                         token.line,
                         token.column,
                         Some("DataType"),
@@ -668,11 +781,11 @@ impl Parser {
         params
     }
 
-    fn parse_block_stmt(&mut self) -> Vec<Box<Content>> {
+    fn parse_block_stmt(&mut self) -> Vec<Content> {
         let mut body = Vec::new();
     
         while self.not_eof() && self.at().kind != TokenType::CloseBrace {
-            body.push(Box::new(self.parse_stmt_resilient()));
+            body.push(self.parse_stmt_resilient());
         }
         
         body
@@ -698,7 +811,7 @@ impl Parser {
         Content::Statement(Box::new(Stmt::IfStmt(IfStmt { test, body, alt, location: start_location })))
     }
 
-    fn parse_else(&mut self) -> Option<Vec<Box<Content>>> {
+    fn parse_else(&mut self) -> Option<Vec<Content>> {
         if self.at().kind == TokenType::Else {
             self.consume(); // Consume the else keyword
             
@@ -718,12 +831,12 @@ impl Parser {
                 
                 let alt = self.parse_else(); // Recursively parse the next else statement
                 
-                return Some(vec![Box::new(Content::Statement(Box::new(Stmt::IfStmt(IfStmt {
+                return Some(vec![Content::Statement(Box::new(Stmt::IfStmt(IfStmt {
                     test,
                     body,
                     alt,
                     location: self.at().location(),
-                }))))]);
+                })))]);
             } else {
                 // If it's just else, we can parse the body
                 self.expect(TokenType::OpenBrace, "Expected '{' after else"); // Expect the opening brace
@@ -732,18 +845,21 @@ impl Parser {
                 
                 self.expect(TokenType::CloseBrace, "Expected '}' after else body"); // Expect the closing brace
                 
-                return Some(vec![Box::new(Content::Statement(Box::new(Stmt::BlockStmt(BlockStmt { body, location: self.at().location() }))))]);
+                return Some(vec![Content::Statement(Box::new(Stmt::BlockStmt(BlockStmt { body, location: self.at().location() })))]);
             }
         }
         
         None
     }
 
-    fn parse_for_stmt(&mut self) -> Content {
+    fn parse_for_stmt(&mut self, label: Option<String>) -> Content {
         let start_location = self.at().location();
         self.expect(TokenType::For, "Expected 'for' keyword");
         
         self.expect_pipe("Expected '|' after 'for'");
+        if matches!(self.at().kind, TokenType::Let | TokenType::Const | TokenType::Semicolon) {
+            return self.parse_c_for_stmt(start_location, label);
+        }
         let mut idents = Vec::new();
         while !self.is_pipe_token() {
             let ident = self.expect(TokenType::Identifier, "Expected identifier").unwrap().value;
@@ -757,42 +873,174 @@ impl Parser {
         self.expect_pipe("Expected '|' after identifiers");
         self.expect(TokenType::In, "Expected 'in' after identifiers");
         
-        let collection = self.parse_expr();
-        
-        self.expect(TokenType::OpenBrace, "Expected '{' after for condition");
-        
-        let body = self.parse_block_stmt();
-        
-        self.expect(TokenType::CloseBrace, "Expected '}' after for body");
+        let iterable = match self.parse_expr() {
+            Content::Expression(expr) => expr,
+            _ => panic!("Expected expression after 'in'"),
+        };
         
-        let init = Some(Box::new(Stmt::VarDecl(VarDecl {
-            constant: false,
-            ident: idents.join(", "), // Join identifiers as a single string
-            type_: DataType::Any,
-            value: Some(collection),
-            location: start_location.clone(),
-        })));
+        let body = self.parse_loop_body("Expected '{' after for condition", "Expected '}' after for body", &label).body;
+        let else_body = self.parse_loop_else().map(|block| block.body);
         
+        Content::Statement(Box::new(Stmt::ForIn(ForInStmt {
+            idents,
+            iterable,
+            body,
+            else_body,
+            label,
+            location: start_location,
+        })))
+    }
+
+    // `for |init; test; update| { ... }`, after the opening pipe. Each clause may
+    // be left empty; a missing test loops until the body returns or fails.
+    fn parse_c_for_stmt(&mut self, start_location: Location, label: Option<String>) -> Content {
+        let init = if self.at().kind == TokenType::Semicolon {
+            self.consume();
+            None
+        } else {
+            // The declaration consumes its own `;`.
+            match self.parse_var_decl() {
+                Content::Statement(stmt) => Some(stmt),
+                Content::Expression(_) => None,
+            }
+        };
+
+        let test = if self.at().kind == TokenType::Semicolon {
+            None
+        } else {
+            match self.parse_expr() {
+                Content::Expression(expr) => Some(expr),
+                _ => panic!("Expected expression in for loop condition"),
+            }
+        };
+        self.expect(TokenType::Semicolon, "Expected ';' after for loop condition");
+
+        let update = if self.is_pipe_token() {
+            None
+        } else {
+            match self.parse_pipe_expression() {
+                Content::Expression(expr) => Some(expr),
+                _ => panic!("Expected expression in for loop update"),
+            }
+        };
+        self.expect_pipe("Expected '|' after for loop update");
+
+        let body = self.parse_loop_body("Expected '{' after for loop clauses", "Expected '}' after for body", &label).body;
+        let else_body = self.parse_loop_else().map(|block| block.body);
+
         Content::Statement(Box::new(Stmt::ForStmt(ForStmt {
             init,
-            test: None,
-            update: None,
+            test,
+            update,
             body,
+            else_body,
+            label,
             location: start_location,
         })))
     }
 
-    fn parse_while_stmt(&mut self) -> Content {
+    fn parse_while_stmt(&mut self, label: Option<String>) -> Content {
         let start_location = self.at().location();
         self.expect(TokenType::While, "Expected 'while' keyword");
         let test = match self.parse_expr() {
             Content::Expression(expr) => expr,
             _ => panic!("Expected expression"),
         };
-        self.expect(TokenType::OpenBrace, "Expected '{' after while condition");
+        let body = self.parse_loop_body("Expected '{' after while condition", "Expected '}' after while body", &label).body;
+        let else_body = self.parse_loop_else().map(|block| block.body);
+        Content::Statement(Box::new(Stmt::WhileStmt(WhileStmt { test, body, else_body, label, location: start_location })))
+    }
+
+    fn parse_loop_stmt(&mut self, label: Option<String>) -> Content {
+        let start_location = self.at().location();
+        self.expect(TokenType::Loop, "Expected 'loop' keyword");
+        let body = self.parse_loop_body("Expected '{' after 'loop'", "Expected '}' after loop body", &label).body;
+        Content::Statement(Box::new(Stmt::Loop(LoopStmt { body, label, location: start_location })))
+    }
+
+    // `name:` directly before `for`, `while` or `loop`.
+    fn at_loop_label(&self) -> bool {
+        matches!(
+            (
+                self.tokens.get(self.current + 1).map(|t| &t.kind),
+                self.tokens.get(self.current + 2).map(|t| &t.kind),
+            ),
+            (Some(TokenType::Colon), Some(TokenType::For | TokenType::While | TokenType::Loop))
+        )
+    }
+
+    fn parse_labeled_loop(&mut self) -> Content {
+        let label = Some(self.at().value.clone());
+        self.consume(); // Consume the label
+        self.consume(); // Consume ':'
+        match self.at().kind {
+            TokenType::For => self.parse_for_stmt(label),
+            TokenType::While => self.parse_while_stmt(label),
+            _ => self.parse_loop_stmt(label),
+        }
+    }
+
+    /// Parses `break` or `continue`, each with an optional loop label.
+    fn parse_break_stmt(&mut self) -> Content {
+        let location = self.at().location();
+        let keyword = if self.at().kind == TokenType::Continue { "continue" } else { "break" };
+        self.consume(); // Consume 'break' or 'continue'
+        let label = if self.at().kind == TokenType::Identifier && !self.at_statement_end() {
+            let token = self.at().clone();
+            self.consume();
+            Some(token)
+        } else {
+            None
+        };
+        if self.loop_labels.is_empty() {
+            self.errors.push(ZekkenError::syntax(
+                &format!("'{}' outside of a loop", keyword),
+                location.line,
+                location.column,
+                None,
+                None,
+            ));
+        } else if let Some(token) = label.as_ref().filter(|token| !self.loop_labels.contains(&Some(token.value.clone()))) {
+            self.errors.push(ZekkenError::syntax(
+                &format!("Unknown loop label '{}'", token.value),
+                token.line,
+                token.column,
+                Some("the label of an enclosing loop"),
+                Some(&token.value),
+            ));
+        }
+        self.expect_statement_end(&format!("Expected ';' after '{}'", keyword));
+        let label = label.map(|token| token.value);
+        if keyword == "continue" {
+            Content::Statement(Box::new(Stmt::Continue(ContinueStmt { label, location })))
+        } else {
+            Content::Statement(Box::new(Stmt::Break(BreakStmt { label, location })))
+        }
+    }
+
+    // Loop bodies track the enclosing labels so a stray `break` can be reported.
+    fn parse_loop_body(&mut self, open_err: &str, close_err: &str, label: &Option<String>) -> BlockStmt {
+        let location = self.at().location();
+        self.expect(TokenType::OpenBrace, open_err);
+        self.loop_labels.push(label.clone());
         let body = self.parse_block_stmt();
-        self.expect(TokenType::CloseBrace, "Expected '}' after while body");
-        Content::Statement(Box::new(Stmt::WhileStmt(WhileStmt { test, body, location: start_location })))
+        self.loop_labels.pop();
+        self.expect(TokenType::CloseBrace, close_err);
+        BlockStmt { body, location }
+    }
+
+    // `for`/`while` may be followed by `else { ... }`, run when the loop ends
+    // without `break`.
+    fn parse_loop_else(&mut self) -> Option<BlockStmt> {
+        if self.at().kind != TokenType::Else {
+            return None;
+        }
+        let location = self.at().location();
+        self.consume();
+        self.expect(TokenType::OpenBrace, "Expected '{' after else");
+        let body = self.parse_block_stmt();
+        self.expect(TokenType::CloseBrace, "Expected '}' after else body");
+        Some(BlockStmt { body, location })
     }
 
     fn parse_use_stmt(&mut self) -> Content {
@@ -808,7 +1056,7 @@ impl Parser {
             self.expect(TokenType::From, "Expected 'from' keyword after method list");
             
             let module = self.expect(TokenType::Identifier, "Expected module name after 'from'").unwrap().value; // Expect the module name
-            self.expect(TokenType::Semicolon, "Expected ';' after use statement");
+            self.expect_statement_end("Expected ';' after use statement");
     
             return Content::Statement(Box::new(Stmt::Use(UseStmt {
                 methods: Some(methods),
@@ -817,7 +1065,7 @@ impl Parser {
             })));
         } else {
             let module = self.expect(TokenType::Identifier, "Expected module name").unwrap().value; // Expect the module name
-            self.expect(TokenType::Semicolon, "Expected ';' after use statement");
+            self.expect_statement_end("Expected ';' after use statement");
     
             return Content::Statement(Box::new(Stmt::Use(UseStmt {
                 methods: None,
@@ -830,6 +1078,13 @@ impl Parser {
     fn parse_include_stmt(&mut self) -> Content {
         let start_location = self.at().location();
         self.expect(TokenType::Include, "Expected 'include' keyword");
+        // `lazy` is only a modifier when it is not itself the imported name.
+        let lazy = self.at().kind == TokenType::Identifier
+            && self.at().value == "lazy"
+            && self.tokens.get(self.current + 1).is_some_and(|next| next.kind != TokenType::From);
+        if lazy {
+            self.consume();
+        }
     
         let methods = if self.at().kind == TokenType::OpenBrace {
             self.consume(); // Consume '{'
@@ -840,20 +1095,22 @@ impl Parser {
             let method = self.expect(TokenType::Identifier, "Expected method name").unwrap().value;
             self.expect(TokenType::From, "Expected 'from' keyword after method name");
             let file_path = self.expect(TokenType::String, "Expected file path after 'from'").unwrap().value; // Expect the file path
-            self.expect(TokenType::Semicolon, "Expected ';' after include statement");
+            self.expect_statement_end("Expected ';' after include statement");
     
             return Content::Statement(Box::new(Stmt::Include(IncludeStmt {
                 methods: Some(vec![method]),
                 file_path,
+                lazy,
                 location: start_location,
             })));
         } else if self.at().kind == TokenType::String {
             let file_path = self.expect(TokenType::String, "Expected file path").unwrap().value;
-            self.expect(TokenType::Semicolon, "Expected ';' after include statement");
+            self.expect_statement_end("Expected ';' after include statement");
     
             return Content::Statement(Box::new(Stmt::Include(IncludeStmt {
                 methods: None,
                 file_path,
+                lazy,
                 location: start_location,
             })));
         } else {
@@ -862,11 +1119,12 @@ impl Parser {
     
         self.expect(TokenType::From, "Expected 'from' keyword after method list");
         let file_path = self.expect(TokenType::String, "Expected file path after 'from'").unwrap().value;
-        self.expect(TokenType::Semicolon, "Expected ';' after include statement");
+        self.expect_statement_end("Expected ';' after include statement");
     
         Content::Statement(Box::new(Stmt::Include(IncludeStmt {
             methods,
             file_path,
+            lazy,
             location: start_location,
         })))
     }
@@ -916,7 +1174,7 @@ impl Parser {
         }
 
         self.expect(TokenType::CloseBrace, "Expected '}' to close export block");
-        self.expect(TokenType::Semicolon, "Expected ';' after export statement");
+        self.expect_statement_end("Expected ';' after export statement");
 
         Content::Statement(Box::new(Stmt::Export(ExportStmt {
             exports,
@@ -928,7 +1186,7 @@ impl Parser {
         let start_location = self.at().location();
         self.expect(TokenType::Return, "Expected 'return' keyword");
 
-        let value = if self.at().kind != TokenType::Semicolon {
+        let value = if !self.at_statement_end() {
             match self.parse_expr() {
                 Content::Expression(expr) => Some(Box::new(Content::Expression(expr))),
                 _ => panic!("Expected expression after 'return'"),
@@ -937,11 +1195,171 @@ impl Parser {
             None
         };
 
-        self.expect(TokenType::Semicolon, "Expected ';' after return statement");
+        self.expect_statement_end("Expected ';' after return statement");
         
         Content::Statement(Box::new(Stmt::Return(ReturnStmt { value, location: start_location })))
     }
 
+    fn parse_defer_stmt(&mut self) -> Content {
+        let start_location = self.at().location();
+        self.expect(TokenType::Defer, "Expected 'defer' keyword");
+
+        let expr = match self.parse_expr() {
+            Content::Expression(expr) => expr,
+            _ => panic!("Expected expression after 'defer'"),
+        };
+
+        self.expect_statement_end("Expected ';' after defer statement");
+
+        Content::Statement(Box::new(Stmt::Defer(DeferStmt { expr, location: start_location })))
+    }
+
+    fn parse_with_stmt(&mut self) -> Content {
+        let start_location = self.at().location();
+        self.expect(TokenType::With, "Expected 'with' keyword");
+        let resource = match self.parse_expr() {
+            Content::Expression(expr) => expr,
+            _ => panic!("Expected expression after 'with'"),
+        };
+        self.expect(TokenType::As, "Expected 'as' after with resource");
+        let ident = self
+            .expect(TokenType::Identifier, "Expected identifier after 'as'")
+            .map(|t| t.value)
+            .unwrap_or_else(|| "_".to_string());
+        self.expect(TokenType::OpenBrace, "Expected '{' after with binding");
+        let body = self.parse_block_stmt();
+        self.expect(TokenType::CloseBrace, "Expected '}' after with body");
+        Content::Statement(Box::new(Stmt::With(WithStmt { resource, ident, body, location: start_location })))
+    }
+
+    // `case` and `default` only mean something inside a switch, so both stay
+    // usable as ordinary names elsewhere.
+    fn at_contextual(&self, word: &str) -> bool {
+        self.at().kind == TokenType::Identifier && self.at().value == word
+    }
+
+    fn parse_switch_stmt(&mut self) -> Content {
+        let start_location = self.at().location();
+        self.expect(TokenType::Switch, "Expected 'switch' keyword");
+        let subject = match self.parse_expr() {
+            Content::Expression(expr) => expr,
+            _ => panic!("Expected expression after 'switch'"),
+        };
+        self.expect(TokenType::OpenBrace, "Expected '{' after switch value");
+
+        let mut cases: Vec<SwitchCase> = Vec::new();
+        let mut default: Option<Vec<Content>> = None;
+        loop {
+            if !self.not_eof() || self.at().kind == TokenType::CloseBrace {
+                break;
+            }
+            let token = self.at().clone();
+            if self.at_contextual("case") {
+                self.consume();
+                if default.is_some() {
+                    self.errors.push(ZekkenError::syntax(
+                        "'default' must come after every case",
+                        token.line,
+                        token.column,
+                        None,
+                        None,
+                    ));
+                }
+                let mut values = Vec::new();
+                loop {
+                    match self.parse_expr() {
+                        Content::Expression(expr) => values.push(expr),
+                        _ => panic!("Expected case value"),
+                    }
+                    if self.at().kind != TokenType::Comma {
+                        break;
+                    }
+                    self.consume();
+                }
+                let (body, fallthrough) = self.parse_case_body();
+                cases.push(SwitchCase { values, body, fallthrough, location: token.location() });
+            } else if self.at_contextual("default") {
+                self.consume();
+                if default.is_some() {
+                    self.errors.push(ZekkenError::syntax(
+                        "A switch can only have one 'default'",
+                        token.line,
+                        token.column,
+                        None,
+                        None,
+                    ));
+                }
+                let (body, fallthrough) = self.parse_case_body();
+                if fallthrough {
+                    self.errors.push(ZekkenError::syntax(
+                        "'fallthrough' cannot be used in 'default'",
+                        token.line,
+                        token.column,
+                        None,
+                        None,
+                    ));
+                }
+                default = Some(body);
+            } else {
+                self.errors.push(ZekkenError::syntax(
+                    "Expected 'case' or 'default' in switch",
+                    token.line,
+                    token.column,
+                    Some("case or default"),
+                    Some(&token.value),
+                ));
+                self.synchronize_statement();
+            }
+        }
+        self.expect(TokenType::CloseBrace, "Expected '}' after switch cases");
+
+        if default.is_none() {
+            if let Some(last) = cases.last().filter(|case| case.fallthrough) {
+                self.errors.push(ZekkenError::syntax(
+                    "'fallthrough' needs a case or 'default' after it",
+                    last.location.line,
+                    last.location.column,
+                    None,
+                    None,
+                ));
+            }
+        }
+        Content::Statement(Box::new(Stmt::Switch(SwitchStmt { subject, cases, default, location: start_location })))
+    }
+
+    // A case body, and whether it ended with `fallthrough`.
+    fn parse_case_body(&mut self) -> (Vec<Content>, bool) {
+        self.expect(TokenType::OpenBrace, "Expected '{' before case body");
+        let mut body = Vec::new();
+        let mut fallthrough = false;
+        while self.not_eof() && self.at().kind != TokenType::CloseBrace {
+            let on_own = self.tokens.get(self.current + 1).is_some_and(|next| {
+                matches!(next.kind, TokenType::CloseBrace | TokenType::Semicolon) || next.line > self.at().line
+            });
+            if self.at_contextual("fallthrough") && on_own {
+                let token = self.at().clone();
+                self.consume();
+                if self.at().kind == TokenType::Semicolon {
+                    self.consume();
+                }
+                if self.at().kind != TokenType::CloseBrace {
+                    self.errors.push(ZekkenError::syntax(
+                        "'fallthrough' must be the last statement of a case",
+                        token.line,
+                        token.column,
+                        None,
+                        None,
+                    ));
+                }
+                fallthrough = true;
+                continue;
+            }
+            body.push(self.parse_stmt_resilient());
+        }
+        self.expect(TokenType::CloseBrace, "Expected '}' after case body");
+        (body, fallthrough)
+    }
+
     fn parse_try_catch_stmt(&mut self) -> Content {
         let start_location = self.at().location();
         self.expect(TokenType::Try, "Expected 'try' keyword");
@@ -974,6 +1392,189 @@ impl Parser {
         })))
     }
 
+    fn parse_enum_decl(&mut self) -> Content {
+        let start_location = self.at().location();
+        self.expect(TokenType::Enum, "Expected 'enum' keyword");
+        let ident = self
+            .expect(TokenType::Identifier, "Expected enum name after 'enum'")
+            .map(|t| t.value)
+            .unwrap_or_else(|| "<error>".to_string());
+        self.expect(TokenType::OpenBrace, "Expected '{' after enum name");
+
+        let mut variants: Vec<String> = Vec::new();
+        while self.not_eof() && self.at().kind != TokenType::CloseBrace {
+            let Some(token) = self.expect(TokenType::Identifier, "Expected variant name in enum") else {
+                break;
+            };
+            if variants.contains(&token.value) {
+                self.errors.push(ZekkenError::syntax(
+                    &format!("Duplicate variant '{}' in enum '{}'", token.value, ident),
+                    token.line,
+                    token.column,
+                    Some("a unique variant name"),
+                    Some(&token.value),
+                ));
+            } else {
+                variants.push(token.value);
+            }
+            if self.at().kind == TokenType::Comma {
+                self.consume();
+            } else {
+                break;
+            }
+        }
+        self.expect(TokenType::CloseBrace, "Expected '}' after enum variants");
+
+        Content::Statement(Box::new(Stmt::EnumDecl(EnumDecl {
+            ident,
+            variants,
+            location: start_location,
+        })))
+    }
+
+    // `match subject { pattern [if guard] -> { ... } _ -> expr }`. Arms are
+    // tried in order; commas between arms are optional.
+    fn parse_match_expr(&mut self) -> Content {
+        let start_location = self.at().location();
+        self.expect(TokenType::Match, "Expected 'match' keyword");
+        let subject = match self.parse_expr() {
+            Content::Expression(expr) => expr,
+            _ => panic!("Expected expression after 'match'"),
+        };
+        self.expect(TokenType::OpenBrace, "Expected '{' after match subject");
+
+        let mut arms = Vec::new();
+        while self.not_eof() && self.at().kind != TokenType::CloseBrace {
+            let location = self.at().location();
+            let pattern = self.parse_pattern();
+            let guard = if self.at().kind == TokenType::If {
+                self.consume();
+                match self.parse_expr() {
+                    Content::Expression(expr) => Some(expr),
+                    _ => panic!("Expected guard expression after 'if'"),
+                }
+            } else {
+                None
+            };
+            if self.expect(TokenType::ThinArrow, "Expected '->' after match pattern").is_none() {
+                break;
+            }
+            let body = if self.at().kind == TokenType::OpenBrace {
+                self.consume();
+                let body = self.parse_block_stmt();
+                self.expect(TokenType::CloseBrace, "Expected '}' after match arm body");
+                body
+            } else {
+                vec![self.parse_expr()]
+            };
+            arms.push(MatchArm { pattern, guard, body, location });
+            if self.at().kind == TokenType::Comma {
+                self.consume();
+            }
+        }
+        self.expect(TokenType::CloseBrace, "Expected '}' after match arms");
+
+        Content::Expression(Box::new(Expr::Match(MatchExpr {
+            subject,
+            arms,
+            location: start_location,
+        })))
+    }
+
+    // A pattern is one or more alternatives separated by `|`.
+    fn parse_pattern(&mut self) -> Pattern {
+        let first = self.parse_single_pattern();
+        if self.at().kind != TokenType::Pipe {
+            return first;
+        }
+        let mut alternatives = vec![first];
+        while self.at().kind == TokenType::Pipe {
+            self.consume();
+            alternatives.push(self.parse_single_pattern());
+        }
+        Pattern::Or(alternatives)
+    }
+
+    fn parse_single_pattern(&mut self) -> Pattern {
+        match self.at().kind.clone() {
+            TokenType::Identifier if self.at().value == "_" => {
+                self.consume();
+                Pattern::Wildcard
+            }
+            // A bare name binds; a dotted path such as `Color.Red` is a value.
+            TokenType::Identifier
+                if !matches!(
+                    self.tokens.get(self.current + 1).map(|t| &t.kind),
+                    Some(TokenType::Dot | TokenType::OpenBracket | TokenType::FatArrow)
+                ) =>
+            {
+                let name = self.at().value.clone();
+                self.consume();
+                Pattern::Binding(name)
+            }
+            TokenType::DataType(kind) => {
+                self.consume();
+                let binding = if self.at().kind == TokenType::Identifier && self.at().value != "_" {
+                    let name = self.at().value.clone();
+                    self.consume();
+                    Some(name)
+                } else {
+                    None
+                };
+                Pattern::Kind(kind, binding)
+            }
+            TokenType::OpenBracket => {
+                self.consume();
+                let mut items = Vec::new();
+                while self.not_eof() && self.at().kind != TokenType::CloseBracket {
+                    items.push(self.parse_pattern());
+                    if self.at().kind == TokenType::Comma {
+                        self.consume();
+                    } else {
+                        break;
+                    }
+                }
+                self.expect(TokenType::CloseBracket, "Expected ']' after array pattern");
+                Pattern::Array(items)
+            }
+            TokenType::OpenBrace => {
+                self.consume();
+                let mut fields = Vec::new();
+                while self.not_eof() && self.at().kind != TokenType::CloseBrace {
+                    let key = match self.at().kind {
+                        TokenType::Identifier | TokenType::String | TokenType::RawString => {
+                            let key = self.at().value.clone();
+                            self.consume();
+                            key
+                        }
+                        _ => {
+                            self.expect(TokenType::Identifier, "Expected key in object pattern");
+                            break;
+                        }
+                    };
+                    let field = if self.at().kind == TokenType::Colon {
+                        self.consume();
+                        self.parse_pattern()
+                    } else {
+                        Pattern::Binding(key.clone())
+                    };
+                    fields.push((key, field));
+                    if self.at().kind == TokenType::Comma {
+                        self.consume();
+                    } else {
+                        break;
+                    }
+                }
+                self.expect(TokenType::CloseBrace, "Expected '}' after object pattern");
+                Pattern::Object(fields)
+            }
+            _ => match self.parse_expr() {
+                Content::Expression(expr) => Pattern::Value(expr),
+                _ => panic!("Expected pattern in match arm"),
+            },
+        }
+    }
+
     fn parse_object_lit(&mut self) -> Content {
         let start_location = self.at().location();
         self.expect(TokenType::OpenBrace, "Expected '{' to start object literal");
@@ -989,25 +1590,67 @@ impl Parser {
         self.expect(TokenType::CloseBracket, "Expected ']' to end array literal");
         Content::Expression(Box::new(Expr::ArrayLit(ArrayLit { elements, location: start_location })))
     }
+
+    /// `#[1, 2, 3]` is a vector; `#[[1, 2], [3, 4]]`, whose first element is
+    /// an array literal, is a matrix with one array literal per row.
+    fn parse_vector_lit(&mut self) -> Content {
+        let location = self.at().location();
+        self.expect(TokenType::Hash, "Expected '#' to start a vector literal");
+        let elements = match self.parse_array_lit() {
+            Content::Expression(expr) => match *expr {
+                Expr::ArrayLit(array) => array.elements,
+                _ => Vec::new(),
+            },
+            Content::Statement(_) => Vec::new(),
+        };
+        if !elements.first().is_some_and(|first| matches!(**first, Expr::ArrayLit(_))) {
+            return Content::Expression(Box::new(Expr::VectorLit(VectorLit { elements, location })));
+        }
+        let mut rows = Vec::with_capacity(elements.len());
+        for element in elements {
+            match *element {
+                Expr::ArrayLit(row) => rows.push(row.elements),
+                other => {
+                    let at = Self::expr_location(&other);
+                    self.errors.push(ZekkenError::syntax(
+                        "Every row of a matrix literal must be an array",
+                        at.line,
+                        at.column,
+                        Some("[...]"),
+                        None,
+                    ));
+                }
+            }
+        }
+        Content::Expression(Box::new(Expr::MatrixLit(MatrixLit { rows, location })))
+    }
     
     
     fn parse_object_properties(&mut self) -> Vec<Property> {
         let mut properties = Vec::new();
-        let mut key_order = Vec::new();
         while self.at().kind != TokenType::CloseBrace {
             let start_location = self.at().location();
+            let mut computed_key = None;
             let key = match self.at().kind {
-                TokenType::Identifier | TokenType::String => {
+                TokenType::Identifier | TokenType::String | TokenType::RawString => {
                     let tok = self.at().clone();
                     self.consume();
                     tok.value
                 }
+                TokenType::OpenBracket => {
+                    self.consume();
+                    if let Content::Expression(expr) = self.parse_expr() {
+                        computed_key = Some(expr);
+                    }
+                    self.expect(TokenType::CloseBracket, "Expected ']' after computed property key");
+                    String::new()
+                }
                 _ => {
                     self.errors.push(ZekkenError::syntax(
                         "Expected property key",
                         start_location.line,
                         start_location.column,
-                        Some("Identifier or String"),
+                        Some("Identifier, String or [expression]"),
                         Some(&format!("{:?} ({})", self.at().kind, self.at().value)),
                     ));
 
@@ -1026,7 +1669,6 @@ impl Parser {
                     continue;
                 }
             };
-            key_order.push(key.clone());
             self.expect(TokenType::Colon, "Expected ':' after property key");
             let value = match self.parse_expr() {
                 Content::Expression(expr) => expr,
@@ -1045,25 +1687,13 @@ impl Parser {
                     }))
                 }
             };
-            properties.push(Property { key, value, location: start_location });
+            properties.push(Property { key, computed_key, value, location: start_location });
             if self.at().kind == TokenType::Comma {
                 self.consume(); // Consume the comma
             } else {
                 break;
             }
         }
-        // Add the key order as a hidden property
-        properties.push(Property {
-            key: "__keys__".to_string(),
-            value: Box::new(Expr::ArrayLit(ArrayLit {
-                elements: key_order.iter().map(|k| Box::new(Expr::StringLit(StringLit {
-                    value: k.clone(),
-                    location: Location { line: 0, column: 0 }
-                }))).collect(),
-                location: Location { line: 0, column: 0 }
-            })),
-            location: Location { line: 0, column: 0 }
-        });
         properties
     }
     
@@ -1115,7 +1745,7 @@ impl Parser {
             self.consume(); // Consume the current token
         }
 
-        Content::Expression(Box::new(Expr::StringLit(StringLit { value: string_content, location: quote_token.location() })))
+        Content::Expression(Box::new(string_or_template(string_content, quote_token.location())))
     }
 
     fn parse_expr(&mut self) -> Content {
@@ -1135,7 +1765,6 @@ impl Parser {
                     Some(t) => t,
                     None => break, // Recover: keep `left` as-is (e.g. for `b.`)
                 };
-                let is_method = self.at().kind == TokenType::FatArrow; // Check if followed by =>
                 let member_expr = Expr::Member(MemberExpr {
                     object: match left {
                         Content::Expression(expr) => expr,
@@ -1145,46 +1774,18 @@ impl Parser {
                         name: ident_token.value.clone(),
                         location: ident_token.location(),
                     })),
-                    is_method,
+                    computed: false,
                     location: ident_token.location(),
                 });
                 left = Content::Expression(Box::new(member_expr));
                 continue;
             }
-            // Handle all assignment operators
-            if matches!(self.at().kind, 
-                TokenType::AssignOp(AssignOp::Assign) | 
-                TokenType::AssignOp(AssignOp::AddAssign) |
-                TokenType::AssignOp(AssignOp::SubAssign) |
-                TokenType::AssignOp(AssignOp::MulAssign) |
-                TokenType::AssignOp(AssignOp::DivAssign) |
-                TokenType::AssignOp(AssignOp::ModAssign)) {
-                
-                let operator = match self.at().kind {
-                    TokenType::AssignOp(AssignOp::Assign) => "=",
-                    TokenType::AssignOp(AssignOp::AddAssign) => "+=",
-                    TokenType::AssignOp(AssignOp::SubAssign) => "-=",
-                    TokenType::AssignOp(AssignOp::MulAssign) => "*=",
-                    TokenType::AssignOp(AssignOp::DivAssign) => "/=",
-                    TokenType::AssignOp(AssignOp::ModAssign) => "%=",
-                    _ => unreachable!(),
-                };
-                
-                self.consume(); // consume operator
-                let right = self.parse_expression(0);
-                
-                return Content::Expression(Box::new(Expr::Assign(AssignExpr {
-                    left: match left {
-                        Content::Expression(expr) => expr,
-                        _ => panic!("Expected expression")
-                    },
-                    right: match right {
-                        Content::Expression(expr) => expr,
-                        _ => panic!("Expected expression")
-                    },
-                    operator: operator.to_string(),
-                    location: self.at().location(),
-                })));
+            // Assignment binds loosest and groups to the right, so only the
+            // outermost level takes it: `a = b + c` is `a = (b + c)`.
+            if min_prec == 0 {
+                if let Some(operator) = self.assign_operator() {
+                    return self.parse_assignment(left, operator);
+                }
             }
 
             // Process binary/infix operators
@@ -1232,6 +1833,13 @@ impl Parser {
                             lit.value = -lit.value;
                             return Content::Expression(Box::new(Expr::FloatLit(lit)));
                         }
+                        Expr::DecimalLit(mut lit) => {
+                            lit.value = match lit.value.strip_prefix('-') {
+                                Some(digits) => digits.to_string(),
+                                None => format!("-{}", lit.value),
+                            };
+                            return Content::Expression(Box::new(Expr::DecimalLit(lit)));
+                        }
                         _ => {}
                     }
                     return Content::Expression(Box::new(Expr::Binary(BinaryExpr {
@@ -1308,6 +1916,7 @@ impl Parser {
             return Content::Expression(Box::new(Expr::Call(CallExpr {
                 callee: Box::new(Expr::Identifier(ident)),
                 args,
+                arg_names: Vec::new(),
                 is_native: true,
                 location: ident_token.location(),
             })));
@@ -1328,24 +1937,33 @@ impl Parser {
             },
             TokenType::Int => {
                 let int_lit = self.expect(TokenType::Int, "Expected integer literal").unwrap();
+                // The lexer already reported literals that do not fit.
                 Content::Expression(Box::new(Expr::IntLit(IntLit {
-                    value: int_lit.value.parse().unwrap(),
+                    value: int_lit.value.parse().unwrap_or(i64::MAX),
                     location: int_lit.location(),
                 })))
             },
             TokenType::Float => {
                 let float_lit = self.expect(TokenType::Float, "Expected float literal").unwrap();
                 Content::Expression(Box::new(Expr::FloatLit(FloatLit {
-                    value: float_lit.value.parse().unwrap(),
+                    value: float_lit.value.parse().unwrap_or(f64::MAX),
                     location: float_lit.location(),
                 })))
             },
+            TokenType::Decimal => {
+                let decimal_lit = self.expect(TokenType::Decimal, "Expected decimal literal").unwrap();
+                Content::Expression(Box::new(Expr::DecimalLit(DecimalLit {
+                    value: decimal_lit.value.clone(),
+                    location: decimal_lit.location(),
+                })))
+            },
             TokenType::String => {
                 let string_token = self.expect(TokenType::String, "Expected string literal").unwrap();
-                Content::Expression(Box::new(Expr::StringLit(StringLit {
-                    value: string_token.value.clone(),
-                    location: string_token.location(),
-                })))
+                Content::Expression(Box::new(string_or_template(string_token.value.clone(), string_token.location())))
+            },
+            TokenType::RawString => {
+                let token = self.expect(TokenType::RawString, "Expected raw string literal").unwrap();
+                Content::Expression(Box::new(Expr::StringLit(StringLit { location: token.location(), value: token.value, raw: true })))
             },
             TokenType::Boolean(value) => {
                 let token = self.at().clone();
@@ -1355,6 +1973,11 @@ impl Parser {
                     location: token.location(),
                 })))
             },
+            TokenType::Null => {
+                let token = self.at().clone();
+                self.consume();
+                Content::Expression(Box::new(Expr::NullLit(NullLit { location: token.location() })))
+            },
             TokenType::OpenParen => {
                 self.consume(); // consume '('
                 let expr = self.parse_expression(0);
@@ -1363,6 +1986,9 @@ impl Parser {
             },
             TokenType::OpenBrace => self.parse_object_lit(),
             TokenType::OpenBracket => self.parse_array_lit(),
+            TokenType::Hash => self.parse_vector_lit(),
+            TokenType::Match => return self.parse_match_expr(),
+            TokenType::DataType(crate::lexer::DataType::Fn) => return self.parse_lambda_expr(),
             _ => {
                 let token = self.at().clone();
                 let error = ZekkenError::syntax(
@@ -1386,7 +2012,6 @@ impl Parser {
                     Some(t) => t,
                     None => break, // Recover: keep `expr` as-is (e.g. for `b.`)
                 };
-                let is_method = self.at().kind == TokenType::FatArrow; // Check if followed by =>
                 expr = Content::Expression(Box::new(Expr::Member(MemberExpr {
                     object: match expr {
                         Content::Expression(e) => e,
@@ -1396,7 +2021,7 @@ impl Parser {
                         name: ident_token.value.clone(),
                         location: ident_token.location(),
                     })),
-                    is_method,
+                    computed: false,
                     location: ident_token.location(),
                 })));
                 continue;
@@ -1414,7 +2039,7 @@ impl Parser {
                         Content::Expression(e) => e,
                         _ => panic!("Expected expression for index"),
                     },
-                    is_method: true,
+                    computed: true,
                     location: self.at().location(),
                 })));
                 continue;
@@ -1426,6 +2051,7 @@ impl Parser {
         if self.at().kind == TokenType::FatArrow {
             self.consume(); // consume '=>'
             let mut args = Vec::new();
+            let mut arg_names: Vec<Option<String>> = Vec::new();
             let empty_double_pipe = self.at().kind == TokenType::BinOp(BinOp::Or);
             if empty_double_pipe {
                 // Support => || as a zero-argument call shorthand.
@@ -1433,6 +2059,32 @@ impl Parser {
             } else {
                 self.expect_pipe("Expected '|' before function arguments");
                 while !self.is_pipe_token() {
+                    let name_token = self.at().clone();
+                    if name_token.kind == TokenType::Identifier
+                        && self.tokens.get(self.current + 1).map(|t| &t.kind) == Some(&TokenType::Colon)
+                    {
+                        self.consume(); // Consume the parameter name
+                        self.consume(); // Consume ':'
+                        if arg_names.iter().flatten().any(|name| *name == name_token.value) {
+                            self.errors.push(ZekkenError::syntax(
+                                &format!("Argument '{}' is named twice", name_token.value),
+                                name_token.line,
+                                name_token.column,
+                                None,
+                                None,
+                            ));
+                        }
+                        arg_names.resize(args.len(), None);
+                        arg_names.push(Some(name_token.value.clone()));
+                    } else if arg_names.iter().any(Option::is_some) {
+                        self.errors.push(ZekkenError::syntax(
+                            "Positional arguments must come before named ones",
+                            name_token.line,
+                            name_token.column,
+                            None,
+                            None,
+                        ));
+                    }
                     let arg = self.parse_pipe_expression();
                     match arg {
                         Content::Expression(e) => args.push(e),
@@ -1453,9 +2105,34 @@ impl Parser {
                 _ => panic!("Expected expression as callee"),
             };
             let call_location = Self::expr_location(&callee);
+            if !arg_names.is_empty() {
+                arg_names.resize(args.len(), None);
+            }
+            if let Expr::Member(MemberExpr { object, property, computed: false, .. }) = *callee {
+                if !arg_names.is_empty() {
+                    self.errors.push(ZekkenError::syntax(
+                        "Named arguments can only be passed to a function called by name",
+                        call_location.line,
+                        call_location.column,
+                        None,
+                        None,
+                    ));
+                }
+                if let Expr::Identifier(method) = *property {
+                    return Content::Expression(Box::new(Expr::MethodCall(MethodCallExpr {
+                        object,
+                        method_id: Method::resolve(&method.name),
+                        method: method.name,
+                        args,
+                        location: call_location,
+                    })));
+                }
+                unreachable!("dot access always names its property with an identifier");
+            }
             return Content::Expression(Box::new(Expr::Call(CallExpr {
                 callee,
                 args,
+                arg_names,
                 is_native: false,
                 location: call_location,
             })));
@@ -1464,30 +2141,68 @@ impl Parser {
         expr
     }
     
-    // Helper to retrieve the binding power of an infix operator
+    // Binding power of infix operators, loosest first. Operators on the same
+    // row group to the left, so `a - b - c` is `(a - b) - c`.
+    //
+    //   =  +=  -=  *=  /=  %=      assignment, groups to the right (see
+    //                              `parse_assignment`)
+    //   ||                         1
+    //   &&                         2
+    //   ==  !=                     3
+    //   <  >  <=  >=  in           4
+    //   +  -                       5
+    //   *  /  %                    6
+    //   unary -  !                 tighter than any infix operator
+    //   .  [ ]  => | |             tightest: member access, indexing, calls
+    //
+    // So `!done && count + 1 < limit || retry` is
+    // `((!done) && ((count + 1) < limit)) || retry`.
     fn get_infix_precedence(&self) -> Option<u8> {
         match self.at().kind {
-            TokenType::ArithOp(ref op) => {
-                let prec = match op {
-                    ArithOp::Add | ArithOp::Sub => 10,
-                    ArithOp::Mul | ArithOp::Div | ArithOp::Mod => 20,
-                };
-                Some(prec)
-            },
-            TokenType::BinOp(ref op) => {
-                let prec = match op {
-                    BinOp::And | BinOp::Or => 5,
-                    BinOp::Eq | BinOp::Neq | BinOp::Less | BinOp::Greater | BinOp::LessEq | BinOp::GreaterEq => 7,
-                    _ => 0,
-                };
-                Some(prec)
-            },
-            TokenType::In => Some(7),
-            TokenType::AssignOp(_) => Some(2),
+            TokenType::BinOp(BinOp::Or) => Some(1),
+            TokenType::BinOp(BinOp::And) => Some(2),
+            TokenType::BinOp(BinOp::Eq | BinOp::Neq) => Some(3),
+            TokenType::BinOp(BinOp::Less | BinOp::Greater | BinOp::LessEq | BinOp::GreaterEq) => Some(4),
+            TokenType::In => Some(4),
+            TokenType::DotDot => Some(5),
+            TokenType::ArithOp(ArithOp::Add | ArithOp::Sub) => Some(6),
+            TokenType::ArithOp(ArithOp::Mul | ArithOp::Div | ArithOp::Mod) => Some(7),
             _ => None,
         }
     }
-    
+
+    fn assign_operator(&self) -> Option<&'static str> {
+        match self.at().kind {
+            TokenType::AssignOp(AssignOp::Assign) => Some("="),
+            TokenType::AssignOp(AssignOp::AddAssign) => Some("+="),
+            TokenType::AssignOp(AssignOp::SubAssign) => Some("-="),
+            TokenType::AssignOp(AssignOp::MulAssign) => Some("*="),
+            TokenType::AssignOp(AssignOp::DivAssign) => Some("/="),
+            TokenType::AssignOp(AssignOp::ModAssign) => Some("%="),
+            _ => None,
+        }
+    }
+
+    // Parses the right-hand side of an assignment to `left`, with the parser
+    // sitting on `operator`.
+    fn parse_assignment(&mut self, left: Content, operator: &str) -> Content {
+        let location = self.at().location();
+        self.consume(); // consume operator
+        let right = self.parse_expression(0);
+        Content::Expression(Box::new(Expr::Assign(AssignExpr {
+            left: match left {
+                Content::Expression(expr) => expr,
+                _ => panic!("Expected expression")
+            },
+            right: match right {
+                Content::Expression(expr) => expr,
+                _ => panic!("Expected expression")
+            },
+            operator: operator.to_string(),
+            location,
+        })))
+    }
+
     // Helper to convert an operator token to its string representation
     fn operator_string_from_token(&self, token: &Token) -> String {
         match &token.kind {
@@ -1499,6 +2214,7 @@ impl Parser {
                 ArithOp::Mod => "%".to_string(),
             },
             TokenType::In => "in".to_string(),
+            TokenType::DotDot => "..".to_string(),
             TokenType::BinOp(op) => match op {
                 BinOp::And => "&&".to_string(),
                 BinOp::Or  => "||".to_string(),
@@ -1510,7 +2226,6 @@ impl Parser {
                 BinOp::GreaterEq => ">=".to_string(),
                 _ => "".to_string(),
             },
-            TokenType::AssignOp(_) => "=".to_string(),
             _ => "".to_string(),
         }
     }
@@ -1526,21 +2241,8 @@ impl Parser {
             if stop_tokens.iter().any(|t| self.at().kind == *t) {
                 break;
             }
-            if self.at().kind == TokenType::AssignOp(AssignOp::Assign) {
-                self.consume();
-                let right = self.parse_expression(0);
-                return Content::Expression(Box::new(Expr::Assign(AssignExpr {
-                    left: match expr {
-                        Content::Expression(e) => e,
-                        _ => panic!("Expected expression"),
-                    },
-                    right: match right {
-                        Content::Expression(e) => e,
-                        _ => panic!("Expected expression"),
-                    },
-                    operator: "=".to_string(),
-                    location: self.at().location(),
-                })));
+            if let Some(operator) = self.assign_operator() {
+                return self.parse_assignment(expr, operator);
             }
             if self.at().kind == TokenType::Dot {
                 self.consume();
@@ -1557,7 +2259,7 @@ impl Parser {
                         name: ident_token.value.clone(),
                         location: ident_token.location(),
                     })),
-                    is_method: true,
+                    computed: false,
                     location: ident_token.location(),
                 })));
                 continue;
@@ -1591,16 +2293,105 @@ impl Parser {
             Expr::Assign(e) => e.location.clone(),
             Expr::Member(e) => e.location.clone(),
             Expr::Call(e) => e.location.clone(),
+            Expr::MethodCall(e) => e.location.clone(),
             Expr::Unary(e) => e.location.clone(),
             Expr::Binary(e) => e.location.clone(),
             Expr::Identifier(e) => e.location.clone(),
             Expr::Property(e) => e.location.clone(),
             Expr::IntLit(e) => e.location.clone(),
             Expr::FloatLit(e) => e.location.clone(),
+            Expr::DecimalLit(e) => e.location.clone(),
             Expr::StringLit(e) => e.location.clone(),
+            Expr::TemplateLit(e) => e.location.clone(),
             Expr::BoolLit(e) => e.location.clone(),
+            Expr::NullLit(e) => e.location.clone(),
             Expr::ArrayLit(e) => e.location.clone(),
+            Expr::VectorLit(e) => e.location.clone(),
+            Expr::MatrixLit(e) => e.location.clone(),
             Expr::ObjectLit(e) => e.location.clone(),
+            Expr::Match(e) => e.location.clone(),
+            Expr::Lambda(e) => e.location.clone(),
+        }
+    }
+}
+
+/// A string literal, or a template when it embeds `{expr}` interpolations.
+/// Braces that do not hold a single valid expression (`{}`, JSON text) stay
+/// part of the text.
+fn string_or_template(value: String, location: Location) -> Expr {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut rest = value.as_str();
+    while let Some(open) = rest.find('{') {
+        text.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let Some(close) = interpolation_end(after) else {
+            rest = &rest[open..];
+            break;
+        };
+        let raw = &after[..close];
+        match parse_interpolation(raw) {
+            Some(expr) => {
+                if !text.is_empty() {
+                    parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                }
+                parts.push(TemplatePart::Expr { expr: Box::new(expr), raw: raw.to_string() });
+            }
+            // `{}` is a positional placeholder for `@println`.
+            None if raw.trim().is_empty() => text.push_str("{}"),
+            None => {
+                text.push('{');
+                text.push_str(raw);
+                text.push('}');
+            }
         }
+        rest = &after[close + 1..];
+    }
+    if parts.is_empty() {
+        return Expr::StringLit(StringLit { value, raw: false, location });
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        parts.push(TemplatePart::Text(text));
+    }
+    Expr::TemplateLit(TemplateLit { parts, location })
+}
+
+/// Index of the `}` closing an interpolation, skipping nested braces and
+/// quoted strings.
+fn interpolation_end(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') if depth == 0 => return Some(i),
+            (None, '}') => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_interpolation(raw: &str) -> Option<Expr> {
+    let source = raw.trim();
+    if source.is_empty() {
+        return None;
+    }
+    let (tokens, lex_errors) = lex(source.to_string());
+    if !lex_errors.is_empty() {
+        return None;
+    }
+    let mut parser = Parser { tokens, ..Parser::new() };
+    let expr = parser.parse_expr();
+    if !parser.errors.is_empty() || parser.not_eof() {
+        return None;
+    }
+    match expr {
+        Content::Expression(expr) => Some(*expr),
+        Content::Statement(_) => None,
     }
 }
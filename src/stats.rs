@@ -0,0 +1,241 @@
+//! Interpreter statistics for `@memstats` and `zekken run --stats`.
+//!
+//! Counters are per thread and only ever grow while a program runs. They
+//! stay off, so the hot paths pay a thread-local flag check and nothing
+//! else, until `--stats` or a program that calls `@memstats` turns them on.
+//! Values are reference counted rather than garbage collected, so there is
+//! no collector time to report; copying strings, arrays and objects is where
+//! that time goes instead, and scopes and bindings are what a pathological
+//! script piles up.
+
+use crate::ast::visit::{walk_expr, Visitor};
+use crate::ast::{Expr, Program};
+use crate::environment::Value;
+use indexmap::IndexMap;
+use std::cell::Cell;
+use std::fmt::{self, Display, Formatter};
+use std::time::Instant;
+
+#[derive(Default)]
+struct Counters {
+    enabled: Cell<bool>,
+    statements: Cell<u64>,
+    instructions: Cell<u64>,
+    calls: Cell<u64>,
+    call_depth: Cell<u64>,
+    peak_call_depth: Cell<u64>,
+    scopes: Cell<u64>,
+    peak_scope_depth: Cell<u64>,
+    bindings: Cell<u64>,
+    values_allocated: Cell<u64>,
+    clone_nanos: Cell<u64>,
+    copying: Cell<bool>,
+}
+
+thread_local! {
+    static COUNTERS: Counters = const {
+        Counters {
+            enabled: Cell::new(false),
+            statements: Cell::new(0),
+            instructions: Cell::new(0),
+            calls: Cell::new(0),
+            call_depth: Cell::new(0),
+            peak_call_depth: Cell::new(0),
+            scopes: Cell::new(0),
+            peak_scope_depth: Cell::new(0),
+            bindings: Cell::new(0),
+            values_allocated: Cell::new(0),
+            clone_nanos: Cell::new(0),
+            copying: Cell::new(false),
+        }
+    };
+}
+
+/// Starts counting on this thread.
+pub fn enable() {
+    COUNTERS.with(|c| c.enabled.set(true));
+}
+
+/// Starts counting if `program` calls `@memstats`, so the counters it reads
+/// cover the whole run.
+pub fn enable_for(program: &Program) {
+    struct FindMemstats(bool);
+    impl Visitor for FindMemstats {
+        fn visit_expr(&mut self, expr: &Expr) {
+            if let Expr::Call(call) = expr {
+                self.0 |= call.is_native && matches!(call.callee.as_ref(), Expr::Identifier(ident) if ident.name == "memstats");
+            }
+            walk_expr(self, expr);
+        }
+    }
+    let mut find = FindMemstats(false);
+    find.visit_block(&program.imports);
+    find.visit_block(&program.content);
+    if find.0 {
+        enable();
+    }
+}
+
+#[inline]
+fn bump(counter: &Cell<u64>, by: u64) {
+    counter.set(counter.get() + by);
+}
+
+#[inline]
+fn raise(peak: &Cell<u64>, value: u64) {
+    if value > peak.get() {
+        peak.set(value);
+    }
+}
+
+/// A statement run by the tree-walking evaluator or the VM's native fallback.
+#[inline]
+pub fn statement() {
+    COUNTERS.with(|c| {
+        if c.enabled.get() {
+            bump(&c.statements, 1);
+        }
+    });
+}
+
+/// Bytecode instructions executed by one `run_insts` call.
+#[inline]
+pub fn instructions(count: u64) {
+    COUNTERS.with(|c| {
+        if c.enabled.get() {
+            bump(&c.instructions, count);
+        }
+    });
+}
+
+/// A new scope `depth` environments deep, counting itself.
+#[inline]
+pub fn scope(depth: u64) {
+    COUNTERS.with(|c| {
+        if c.enabled.get() {
+            bump(&c.scopes, 1);
+            raise(&c.peak_scope_depth, depth);
+        }
+    });
+}
+
+#[inline]
+pub fn binding() {
+    COUNTERS.with(|c| {
+        if c.enabled.get() {
+            bump(&c.bindings, 1);
+        }
+    });
+}
+
+/// Copies a string, array or object with `copy`, counting the new value and
+/// timing the copy. A copy made inside another, such as an array's elements,
+/// is counted but its time is already part of the outer one's.
+#[inline]
+pub fn copy<T>(copy: impl FnOnce() -> T) -> T {
+    let outermost = COUNTERS.with(|c| {
+        if !c.enabled.get() {
+            return false;
+        }
+        bump(&c.values_allocated, 1);
+        // The web build has no clock, so it counts copies without timing them.
+        !cfg!(target_arch = "wasm32") && !c.copying.replace(true)
+    });
+    if !outermost {
+        return copy();
+    }
+    let started = Instant::now();
+    let value = copy();
+    let elapsed = started.elapsed().as_nanos().min(u64::MAX as u128) as u64;
+    COUNTERS.with(|c| {
+        c.copying.set(false);
+        bump(&c.clone_nanos, elapsed);
+    });
+    value
+}
+
+/// Marks a user function call as in progress until the guard is dropped.
+pub struct CallGuard(bool);
+
+#[inline]
+pub fn enter_call() -> CallGuard {
+    CallGuard(COUNTERS.with(|c| {
+        if c.enabled.get() {
+            bump(&c.calls, 1);
+            bump(&c.call_depth, 1);
+            raise(&c.peak_call_depth, c.call_depth.get());
+        }
+        c.enabled.get()
+    }))
+}
+
+impl Drop for CallGuard {
+    #[inline]
+    fn drop(&mut self) {
+        if self.0 {
+            COUNTERS.with(|c| c.call_depth.set(c.call_depth.get().saturating_sub(1)));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    pub statements: u64,
+    pub instructions: u64,
+    pub calls: u64,
+    pub peak_call_depth: u64,
+    pub scopes: u64,
+    pub peak_scope_depth: u64,
+    pub bindings: u64,
+    pub values_allocated: u64,
+    pub clone_micros: u64,
+}
+
+/// The counters for this thread so far.
+pub fn snapshot() -> Stats {
+    COUNTERS.with(|c| Stats {
+        statements: c.statements.get(),
+        instructions: c.instructions.get(),
+        calls: c.calls.get(),
+        peak_call_depth: c.peak_call_depth.get(),
+        scopes: c.scopes.get(),
+        peak_scope_depth: c.peak_scope_depth.get(),
+        bindings: c.bindings.get(),
+        values_allocated: c.values_allocated.get(),
+        clone_micros: c.clone_nanos.get() / 1_000,
+    })
+}
+
+impl Stats {
+    fn fields(&self) -> [(&'static str, u64); 9] {
+        [
+            ("statements", self.statements),
+            ("instructions", self.instructions),
+            ("calls", self.calls),
+            ("peak_call_depth", self.peak_call_depth),
+            ("scopes", self.scopes),
+            ("peak_scope_depth", self.peak_scope_depth),
+            ("bindings", self.bindings),
+            ("values_allocated", self.values_allocated),
+            ("clone_micros", self.clone_micros),
+        ]
+    }
+
+    /// The object `@memstats` returns.
+    pub fn to_value(self) -> Value {
+        let mut obj = IndexMap::with_capacity(9);
+        for (name, count) in self.fields() {
+            obj.insert(name.to_string(), Value::Int(count.min(i64::MAX as u64) as i64));
+        }
+        Value::Object(obj)
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (name, count) in self.fields() {
+            writeln!(f, "  {:<18}{}", name.replace('_', " "), count)?;
+        }
+        Ok(())
+    }
+}
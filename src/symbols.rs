@@ -0,0 +1,58 @@
+//! The global builtins and library members a script can name, for editor
+//! autocomplete.
+//!
+//! Names come from what the interpreter actually registers: a fresh
+//! environment's globals, and each library loaded into its own scope. Only
+//! the signature text is written by hand, next to each registration.
+
+// Only the wasm build exports this.
+#![allow(dead_code)]
+
+use crate::environment::{Environment, Value};
+use crate::libraries::{self, Signatures};
+use serde_json::{json, Value as JsonValue};
+
+fn describe(name: &str, value: &Value, signatures: Signatures) -> JsonValue {
+    let kind = match value {
+        Value::NativeFunction(_) | Value::Function(_) => "function",
+        _ => "constant",
+    };
+    let signature = signatures.iter().find(|(member, _)| *member == name).map(|(_, signature)| *signature);
+    json!({ "name": name, "kind": kind, "signature": signature })
+}
+
+fn sorted(bindings: impl Iterator<Item = (String, Value)>) -> Vec<(String, Value)> {
+    let mut bindings: Vec<(String, Value)> = bindings.collect();
+    bindings.sort_by(|a, b| a.0.cmp(&b.0));
+    bindings
+}
+
+/// `{ builtins: [...], libraries: [{ name, members: [...] }] }`, where every
+/// symbol is `{ name, kind, signature }` and `kind` is `function` or
+/// `constant`. Everything is sorted by name.
+pub fn builtin_symbols() -> JsonValue {
+    let globals = Environment::new();
+    let globals = globals.variables.iter().chain(globals.constants.iter());
+    let builtins: Vec<JsonValue> = sorted(globals.map(|(name, value)| (name.clone(), value.clone())))
+        .iter()
+        .map(|(name, value)| describe(name, value, crate::environment::BUILTIN_SIGNATURES))
+        .collect();
+
+    let libraries: Vec<JsonValue> = libraries::library_names()
+        .into_iter()
+        .map(|library| {
+            let mut env = Environment::new();
+            let loaded = libraries::load_library(library, &mut env).ok().and_then(|_| env.lookup(library));
+            let members = match loaded.as_ref().and_then(Value::as_object) {
+                Some(members) => sorted(members.clone().into_iter())
+                    .iter()
+                    .map(|(name, value)| describe(name, value, libraries::signatures(library)))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            json!({ "name": library, "members": members })
+        })
+        .collect();
+
+    json!({ "builtins": builtins, "libraries": libraries })
+}
@@ -0,0 +1,137 @@
+//! Execution traces for `zekken run --trace-out`.
+//!
+//! While recording, the interpreter notes each user function call and return,
+//! each statement run (through the same hooks as [`coverage`](crate::coverage)),
+//! each variable written (through [`history`](crate::history)'s hooks) and each
+//! error raised, with microseconds since recording started. [`finish`] turns
+//! them into the Trace Event Format that Chrome's `about:tracing`, Perfetto and
+//! speedscope read: calls become nested duration slices, everything else an
+//! instant event on the same timeline.
+
+// Only `zekken run` writes traces; the wasm build just carries the hooks.
+#![allow(dead_code)]
+
+use crate::ast::Location;
+use crate::environment::Value;
+use crate::errors::ZekkenError;
+use serde_json::{json, Value as JsonValue};
+use std::cell::{Cell, RefCell};
+use std::time::Instant;
+
+/// Events kept before the rest are only counted, so a long loop cannot
+/// exhaust memory.
+pub const MAX_EVENTS: usize = 1_000_000;
+
+/// Longest assigned value kept in an event, in characters.
+const MAX_VALUE_CHARS: usize = 200;
+
+thread_local! {
+    static RECORDING: Cell<bool> = const { Cell::new(false) };
+    static STARTED: Cell<Option<Instant>> = const { Cell::new(None) };
+    static EVENTS: RefCell<Vec<JsonValue>> = const { RefCell::new(Vec::new()) };
+    static DROPPED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Clears any earlier events and starts recording.
+pub fn start() {
+    EVENTS.with(|events| events.borrow_mut().clear());
+    DROPPED.with(|dropped| dropped.set(0));
+    STARTED.with(|started| started.set(Some(Instant::now())));
+    RECORDING.with(|r| r.set(true));
+}
+
+#[inline]
+pub fn is_recording() -> bool {
+    RECORDING.with(Cell::get)
+}
+
+fn push(phase: &str, category: &str, name: &str, args: Option<JsonValue>) {
+    let ts = STARTED.with(|started| started.get().map_or(0, |started| started.elapsed().as_micros() as u64));
+    EVENTS.with(|events| {
+        let mut events = events.borrow_mut();
+        if events.len() >= MAX_EVENTS {
+            DROPPED.with(|dropped| dropped.set(dropped.get() + 1));
+            return;
+        }
+        let mut event = json!({ "name": name, "cat": category, "ph": phase, "ts": ts, "pid": 1, "tid": 1 });
+        if phase == "i" {
+            event["s"] = json!("t");
+        }
+        if let Some(args) = args {
+            event["args"] = args;
+        }
+        events.push(event);
+    });
+}
+
+/// Marks a call to the user function `name`, made on `line`, as running
+/// until the guard is dropped.
+pub struct CallSpan(bool);
+
+#[inline]
+pub fn enter(name: &str, line: usize) -> CallSpan {
+    if !is_recording() {
+        return CallSpan(false);
+    }
+    let name = if name.is_empty() { "<anonymous>" } else { name };
+    push("B", "function", name, Some(json!({ "line": line })));
+    CallSpan(true)
+}
+
+impl Drop for CallSpan {
+    #[inline]
+    fn drop(&mut self) {
+        // A span opened before `finish` still closes in an empty trace.
+        if self.0 && is_recording() {
+            push("E", "function", "", None);
+        }
+    }
+}
+
+/// Notes one run of the statement starting on `line`.
+#[inline]
+pub fn statement(line: usize) {
+    if is_recording() {
+        push("i", "statement", &format!("line {}", line), Some(json!({ "line": line })));
+    }
+}
+
+/// Notes that `name` now holds `value`, written at `location`.
+#[inline]
+pub fn assign(name: &str, value: &Value, location: &Location) {
+    if is_recording() {
+        let mut value = value.to_string();
+        if let Some((cut, _)) = value.char_indices().nth(MAX_VALUE_CHARS) {
+            value.truncate(cut);
+            value.push_str("...");
+        }
+        push("i", "assign", name, Some(json!({ "value": value, "line": location.line, "column": location.column })));
+    }
+}
+
+/// Notes `error`, which a `catch` handled if `caught` is set and otherwise
+/// ended the statement it was raised in.
+pub fn error(error: &ZekkenError, caught: bool) {
+    if is_recording() {
+        push("i", "error", &format!("{:?} error", error.kind), Some(json!({
+            "message": error.message,
+            "line": error.context.line,
+            "column": error.context.column,
+            "caught": caught,
+        })));
+    }
+}
+
+/// Stops recording and hands back the trace as a Trace Event Format document.
+pub fn finish() -> JsonValue {
+    RECORDING.with(|r| r.set(false));
+    let events = EVENTS.with(|events| std::mem::take(&mut *events.borrow_mut()));
+    json!({
+        "traceEvents": events,
+        "displayTimeUnit": "ms",
+        "otherData": {
+            "interpreter": format!("zekken {}", env!("CARGO_PKG_VERSION")),
+            "dropped_events": DROPPED.with(Cell::get),
+        },
+    })
+}
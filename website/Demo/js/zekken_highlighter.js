@@ -73,18 +73,18 @@ CodeMirror.defineMode("zekken", function() {
         }
 
         // Keywords
-        if (/^(if|else|for|while|try|catch|return)$/.test(ident)) return "keyword-control";
-        if (/^(use|include|export|from|in|let|const)$/.test(ident)) return "keyword";
+        if (/^(if|else|for|while|try|catch|return|match|defer|with|switch)$/.test(ident)) return "keyword-control";
+        if (/^(use|include|export|from|in|let|const|enum|as)$/.test(ident)) return "keyword";
         if (ident === "func") {
           state.expectFuncName = true;
           return "keyword";
         }
 
         // Types
-        if (/^(int|float|bool|string|arr|obj|fn)$/.test(ident)) return "type";
+        if (/^(int|float|dec|bool|string|arr|obj|fn|any)$/.test(ident)) return "type";
 
         // Booleans
-        if (/^(true|false)$/.test(ident)) return "boolean";
+        if (/^(true|false|null)$/.test(ident)) return "boolean";
 
         // Variables
         return "variable";
@@ -94,6 +94,7 @@ CodeMirror.defineMode("zekken", function() {
       if (stream.match(/\b[a-zA-Z_][a-zA-Z0-9_]*\b(?=\s*=>)/)) return "function";
       
       // Numbers
+      if (stream.match(/\b\d+(\.\d+)?d\b/)) return "number";
       if (stream.match(/\b\d+\.\d+\b/)) return "number";
       if (stream.match(/\b\d+\b/)) return "number";
       
@@ -122,10 +122,22 @@
           out += `<span class="tok-comment">${escapeHtml(line.slice(i))}</span>`; break;
         }
       }
+      if (state.inBlockString || line.startsWith('"""', i)) {
+        var from = state.inBlockString ? i : i + 3;
+        var blockEnd = line.indexOf('"""', from);
+        var stop = blockEnd < 0 ? line.length : blockEnd + 3;
+        out += highlightEscapesInString(line.slice(i, stop));
+        state.inBlockString = blockEnd < 0; i = stop; state.afterDot = false; continue;
+      }
       var ch = line[i];
       var next = i + 1 < line.length ? line[i + 1] : "";
       if (ch === "/" && next === "/") { out += `<span class="tok-comment">${escapeHtml(line.slice(i))}</span>`; break; }
       if (ch === "/" && next === "*") { state.inComment = true; continue; }
+      if (ch === "r" && (next === '"' || next === "'") && !isIdent(line[i - 1] || "")) {
+        var rawEnd = line.indexOf(next, i + 2); rawEnd = rawEnd < 0 ? line.length : rawEnd + 1;
+        out += `<span class="tok-string">${escapeHtml(line.slice(i, rawEnd))}</span>`;
+        i = rawEnd; state.afterDot = false; continue;
+      }
       if (ch === '"' || ch === "'") {
         var quote = ch; var j = i + 1; var escaped = false;
         while (j < line.length) {
@@ -137,10 +149,12 @@
       }
       if (isDigit(ch)) {
         var k = i; while (k < line.length && isDigit(line[k])) k++;
-        if (k < line.length && line[k] === ".") { var m = k + 1; while (m < line.length && isDigit(line[m])) m++; k = m; }
+        if (k < line.length && line[k] === "." && isDigit(line[k + 1] || "")) { var m = k + 1; while (m < line.length && isDigit(line[m])) m++; k = m; }
+        if (line[k] === "d" && !/[A-Za-z0-9_]/.test(line[k + 1] || "")) k++;
         out += `<span class="tok-number">${escapeHtml(line.slice(i, k))}</span>`;
         i = k; state.afterDot = false; continue;
       }
+      if (ch === "." && line[i + 1] === ".") { out += '<span class="tok-operator">..</span>'; state.afterDot = false; i += 2; continue; }
       if (ch === ".") { out += '<span class="tok-operator">.</span>'; state.afterDot = true; i++; continue; }
       if (ch === "@") {
         i++; var bStart = i; while (i < line.length && isIdent(line[i])) i++;
@@ -158,10 +172,10 @@
           state.afterDot = false; 
           cls = /^\s*=>/.test(rest) ? "tok-function" : "tok-property"; 
         } 
-        else if (/^(if|else|for|while|try|catch|return)$/.test(ident)) cls = "tok-keyword-control";
+        else if (/^(if|else|for|while|try|catch|return|switch)$/.test(ident)) cls = "tok-keyword-control";
         else if (/^(use|include|export|from|in|let|const|func)$/.test(ident)) cls = "tok-keyword";
-        else if (/^(int|float|bool|string|arr|obj|fn)$/.test(ident)) cls = "tok-type";
-        else if (/^(true|false)$/.test(ident)) cls = "tok-boolean";
+        else if (/^(int|float|dec|bool|string|arr|obj|fn|any)$/.test(ident)) cls = "tok-type";
+        else if (/^(true|false|null)$/.test(ident)) cls = "tok-boolean";
         else if (/^\s*=>/.test(rest)) cls = "tok-function";
         out += `<span class="${cls}">${escapeHtml(ident)}</span>`;
         i = idEnd;
@@ -191,7 +205,7 @@
     codeEl.dataset.zkSource = source;
     var lines = source.split("\n");
     var html = [];
-    var state = { inComment: false, afterDot: false };
+    var state = { inComment: false, inBlockString: false, afterDot: false };
     for (var i = 0; i < lines.length; i++) {
       html.push(tokenizeLine(lines[i], state, isError));
     }